@@ -17,6 +17,7 @@ TARGET="${TARGET:-all}"
 BUILD_TYPE="${BUILD_TYPE:-debug}"
 BOARD="${BOARD:-}"
 TEST_MODE="${TEST_MODE:-false}"
+KTEST_MODE="${KTEST_MODE:-false}"
 INTERACTIVE_MODE="${INTERACTIVE_MODE:-false}"
 CLEAN_MODE="${CLEAN_MODE:-false}"
 VERBOSE="${VERBOSE:-false}"
@@ -41,6 +42,11 @@ parse_arguments() {
                 TEST_MODE=true
                 shift
                 ;;
+            --ktest|-k)
+                KTEST_MODE=true
+                TEST_MODE=true
+                shift
+                ;;
             --interactive|-i)
                 INTERACTIVE_MODE=true
                 shift
@@ -99,6 +105,7 @@ BUILD_TYPES:
 OPTIONS:
     -b, --board BOARD        Specify board configuration
     -t, --test               Run QEMU tests after build
+    -k, --ktest              Build the test kernel and fail unless its cases pass
     -i, --interactive        Run QEMU in interactive mode
     -c, --clean              Clean build artifacts first
     --timeout SECONDS        Set QEMU test timeout (default: 30s)
@@ -110,6 +117,7 @@ EXAMPLES:
     $0 arm                    # Build ARM debug
     $0 riscv release         # Build RISC-V release
     $0 all --test            # Build all and test
+    $0 riscv --ktest         # Run the on-target test cases under QEMU
     $0 arm --board lm3s6965  # Build ARM for specific board
     $0 riscv --interactive   # Build and run RISC-V interactively
     $0 --clean all           # Clean and build all
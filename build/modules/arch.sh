@@ -76,27 +76,13 @@ setup_riscv_environment() {
     log_debug "RISCV_BOARD=$RISCV_BOARD"
 }
 
-# Generate memory layout from template
+# Memory layout is generated by kernel/build.rs from the selected board
+# descriptor (kernel/src/board/descriptors.rs); nothing to copy here
 generate_memory_layout() {
     local target="$1"
     local board="${2:-}"
 
-    log_info "Generating memory layout for $target"
-
-    local template_file="$TEMPLATE_DIR/$(get_memory_template "$target")"
-    local output_file="$KERNEL_DIR/memory.x"
-
-    if [[ ! -f "$template_file" ]]; then
-        error "Memory template not found: $template_file"
-    fi
-
-    log_debug "Using template: $template_file"
-
-    # For now, just copy the template
-    # In a full implementation, this would substitute variables
-    cp "$template_file" "$output_file"
-
-    log_success "Memory layout generated: $output_file"
+    log_debug "memory.x for $target${board:+ ($board)} is generated by kernel/build.rs"
 }
 
 # Get build target triple
@@ -156,6 +142,16 @@ get_cargo_args() {
         args+=("--features" "$features")
     fi
 
+    # Test kernel: the on-target test cases replace the demo. ARM reports
+    # through semihosting, as it would on a board under a debug probe
+    if [[ "${KTEST_MODE:-false}" == true ]]; then
+        if [[ "$target" == "arm" ]]; then
+            args+=("--features" "ktest_semihosting")
+        else
+            args+=("--features" "ktest")
+        fi
+    fi
+
     # Add build type
     if [[ "$build_type" == "release" ]]; then
         args+=("--release")
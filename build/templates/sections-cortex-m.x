@@ -1,15 +1,6 @@
-/* Linker script for LM3S6965EVB board (Cortex-M3) */
-/* Compatible with cortex-m-rt crate requirements */
-
-MEMORY
-{
-  /* LM3S6965EVB: 256K Flash at 0x00000000, 64K SRAM at 0x20000000 */
-  FLASH : ORIGIN = 0x00000000, LENGTH = 256K
-  RAM : ORIGIN = 0x20000000, LENGTH = 64K
-}
-
-/* Stack grows downward from end of RAM */
-_stack_start = ORIGIN(RAM) + LENGTH(RAM);
+/* Section layout for Cortex-M boards (cortex-m-rt) */
+/* build.rs prepends MEMORY (FLASH, RAM), _stack_start and _heap_size */
+/* generated from the selected board descriptor */
 
 /* Entry point - cortex-m-rt will handle this */
 ENTRY(Reset);
@@ -31,8 +22,10 @@ SECTIONS
   /* Code and constants section */
   .text :
   {
+    __stext = .;
     *(.Reset);
     *(.text .text.*);
+    __etext = .;
   } > FLASH
 
   .rodata :
@@ -64,12 +57,22 @@ SECTIONS
   /* Data load address for initialization */
   __sidata = LOADADDR(.data);
 
+  /* Left alone by startup code so records survive a warm reset */
+  .noinit (NOLOAD) :
+  {
+    . = ALIGN(4);
+    __snoinit = .;
+    *(.noinit .noinit.*);
+    . = ALIGN(4);
+    __enoinit = .;
+  } > RAM
+
   /* Heap area (optional) */
   .heap (NOLOAD) :
   {
     . = ALIGN(4);
     __sheap = .;
-    . = . + 0x400; /* 1K heap */
+    . = . + _heap_size;
     . = ALIGN(4);
     __eheap = .;
   } > RAM
@@ -0,0 +1,81 @@
+/* Section layout for ESP32-C3 direct boot from flash */
+/* build.rs prepends MEMORY (FLASH, DROM, RAM), _stack_start and _heap_size */
+/* generated from the selected board descriptor */
+
+/* The ROM loader checks for the direct-boot magic at flash offset 0 and */
+/* jumps to 0x42000008 with flash mapped 1:1 on both cache buses, so the */
+/* same flash offset is visible at FLASH (instruction bus) and DROM (data). */
+
+/* Entry point for riscv-rt */
+ENTRY(_start)
+
+SECTIONS {
+    .header ORIGIN(FLASH) : {
+        LONG(0xaedb041d);
+        LONG(0xaedb041d);
+    } > FLASH
+
+    /* Must start right after the header: the ROM jumps to 0x42000008 */
+    .text.init : {
+        _stext = .;
+        KEEP(*(.init));
+        KEEP(*(.init.rust));
+    } > FLASH
+
+    .text : {
+        *(.text .text.*);
+        _etext = .;
+    } > FLASH
+
+    /* Data-bus alias of the flash bytes that follow .text */
+    .rodata ORIGIN(DROM) + ALIGN(ADDR(.text) + SIZEOF(.text) - ORIGIN(FLASH), 8) :
+        AT(ALIGN(ADDR(.text) + SIZEOF(.text), 8)) {
+        *(.srodata .srodata.*);
+        *(.rodata .rodata.*);
+        . = ALIGN(4);
+    } > DROM
+
+    /* Initialized data, copied from flash by riscv-rt */
+    .data : AT(LOADADDR(.rodata) + SIZEOF(.rodata)) {
+        . = ALIGN(4);
+        _sdata = .;
+        *(.sdata .sdata.*);
+        *(.data .data.*);
+        . = ALIGN(4);
+        _edata = .;
+    } > RAM
+
+    _sidata = LOADADDR(.data);
+
+    .bss (NOLOAD) : {
+        . = ALIGN(4);
+        _sbss = .;
+        *(.sbss .sbss.*);
+        *(.bss .bss.*);
+        *(COMMON);
+        . = ALIGN(4);
+        _ebss = .;
+    } > RAM
+
+    /* Left alone by startup code so records survive a warm reset */
+    .noinit (NOLOAD) : {
+        . = ALIGN(4);
+        _snoinit = .;
+        *(.noinit .noinit.*);
+        . = ALIGN(4);
+        _enoinit = .;
+    } > RAM
+
+    /* Heap area (optional) */
+    .heap (NOLOAD) : {
+        . = ALIGN(4);
+        _sheap = .;
+        . = . + _heap_size;
+        . = ALIGN(4);
+        _eheap = .;
+    } > RAM
+
+    /DISCARD/ : {
+        *(.eh_frame);
+    }
+}
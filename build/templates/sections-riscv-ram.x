@@ -1,22 +1,17 @@
-/* RISC-V memory layout for QEMU virt machine */
-/* Compatible with riscv-rt crate requirements */
-
-MEMORY {
-    RAM : ORIGIN = 0x80000000, LENGTH = 128M
-}
-
-/* Stack at the end of RAM */
-_stack_start = ORIGIN(RAM) + LENGTH(RAM);
-PROVIDE(_stack_start = _stack_start);
+/* Section layout for RISC-V images loaded into RAM (QEMU -kernel) */
+/* build.rs prepends MEMORY (RAM), _stack_start and _heap_size */
+/* generated from the selected board descriptor */
 
 /* Entry point for riscv-rt */
 ENTRY(_start)
 
 SECTIONS {
     .text : {
+        _stext = .;
         KEEP(*(.init));
         KEEP(*(.init.rust));
         *(.text .text.*);
+        _etext = .;
     } > RAM
 
     .rodata : {
@@ -36,11 +31,20 @@ SECTIONS {
         _ebss = .;
     } > RAM
 
+    /* Left alone by startup code so records survive a warm reset */
+    .noinit (NOLOAD) : {
+        . = ALIGN(4);
+        _snoinit = .;
+        *(.noinit .noinit.*);
+        . = ALIGN(4);
+        _enoinit = .;
+    } > RAM
+
     /* Heap area (optional) */
     .heap (NOLOAD) : {
         . = ALIGN(4);
         _sheap = .;
-        . = . + 0x1000; /* 4K heap */
+        . = . + _heap_size;
         . = ALIGN(4);
         _eheap = .;
     } > RAM
@@ -0,0 +1,67 @@
+/* Section layout for RISC-V boards executing in place from flash */
+/* build.rs prepends MEMORY (FLASH, RAM), _stack_start and _heap_size */
+/* generated from the selected board descriptor */
+
+/* Code and read-only data execute from FLASH; .data is copied to RAM. */
+
+/* Entry point for riscv-rt */
+ENTRY(_start)
+
+SECTIONS {
+    .text : {
+        _stext = .;
+        KEEP(*(.init));
+        KEEP(*(.init.rust));
+        *(.text .text.*);
+        _etext = .;
+    } > FLASH
+
+    .rodata : ALIGN(4) {
+        *(.srodata .srodata.*);
+        *(.rodata .rodata.*);
+        . = ALIGN(4);
+    } > FLASH
+
+    /* Initialized data, copied from flash by riscv-rt */
+    .data : ALIGN(4) {
+        _sdata = .;
+        *(.sdata .sdata.*);
+        *(.data .data.*);
+        . = ALIGN(4);
+        _edata = .;
+    } > RAM AT > FLASH
+
+    _sidata = LOADADDR(.data);
+
+    .bss (NOLOAD) : {
+        . = ALIGN(4);
+        _sbss = .;
+        *(.sbss .sbss.*);
+        *(.bss .bss.*);
+        *(COMMON);
+        . = ALIGN(4);
+        _ebss = .;
+    } > RAM
+
+    /* Left alone by startup code so records survive a warm reset */
+    .noinit (NOLOAD) : {
+        . = ALIGN(4);
+        _snoinit = .;
+        *(.noinit .noinit.*);
+        . = ALIGN(4);
+        _enoinit = .;
+    } > RAM
+
+    /* Heap area (optional) */
+    .heap (NOLOAD) : {
+        . = ALIGN(4);
+        _sheap = .;
+        . = . + _heap_size;
+        . = ALIGN(4);
+        _eheap = .;
+    } > RAM
+
+    /DISCARD/ : {
+        *(.eh_frame);
+    }
+}
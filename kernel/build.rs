@@ -4,11 +4,28 @@
 use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+// Board data shared with the kernel (crate::board::descriptors)
+#[path = "src/board/descriptors.rs"]
+#[allow(dead_code)]
+mod descriptors;
+
+use descriptors::{Arch, BoardDescriptor, LinkerLayout};
+
+// Password hashing shared with the shell login
+#[path = "src/shell/sha256.rs"]
+#[allow(dead_code)]
+mod sha256;
 
 fn main() {
     let target = env::var("TARGET").unwrap();
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    // Build identification for kernel::version(), needed on every target
+    emit_build_info(&target);
+    emit_boot_script(out);
+    emit_shell_login(out);
     
     // Configure linker script based on target architecture
     if target.starts_with("riscv32") {
@@ -22,56 +39,173 @@ fn main() {
         return;
     }
     
+    // defmt's string table section, on top of memory.x
+    if env::var_os("CARGO_FEATURE_LOG_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
+
     println!("cargo:rustc-link-search={}", out.display());
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/board/descriptors.rs");
+    println!("cargo:rerun-if-changed=src/shell/sha256.rs");
 }
 
-fn configure_riscv_build(out: &PathBuf) {
+/// Export git hash, profile and target triple as compile-time env vars
+fn emit_build_info(target: &str) {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|text| text.trim().to_string())
+    };
+
+    let hash = git(&["rev-parse", "--short=10", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|status| !status.is_empty());
+    let suffix = if dirty { "-dirty" } else { "" };
+    println!("cargo:rustc-env=KARATOS_GIT_HASH={}{}", hash, suffix);
+    println!("cargo:rustc-env=KARATOS_BUILD_PROFILE={}", env::var("PROFILE").unwrap_or_else(|_| "unknown".into()));
+    println!("cargo:rustc-env=KARATOS_TARGET={}", target);
+
+    // Rebuild when HEAD moves (checkout or commit on the current branch)
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, head_ref);
+        }
+    }
+}
+
+/// Copy the shell boot script named by KARATOS_BOOT_SCRIPT into OUT_DIR
+///
+/// The kernel embeds `boot_script.txt` unconditionally, so an empty file
+/// is written when no script is given. Relative paths are resolved from
+/// the kernel crate directory.
+fn emit_boot_script(out: &Path) {
+    println!("cargo:rerun-if-env-changed=KARATOS_BOOT_SCRIPT");
+    let script = match env::var("KARATOS_BOOT_SCRIPT") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={}", path);
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|error| panic!("KARATOS_BOOT_SCRIPT {}: {}", path, error))
+        }
+        _ => String::new(),
+    };
+    std::fs::write(out.join("boot_script.txt"), script).expect("write boot_script.txt");
+}
+
+/// Write the `shell_login` credentials to $OUT_DIR/shell_login.rs
+///
+/// Only the user name and SHA-256 of `user:password` go into the image.
+/// Without `shell_login` the file holds empty credentials nothing reads.
+fn emit_shell_login(out: &Path) {
+    println!("cargo:rerun-if-env-changed=KARATOS_SHELL_USER");
+    println!("cargo:rerun-if-env-changed=KARATOS_SHELL_PASSWORD");
+    let (user, hash) = if env::var_os("CARGO_FEATURE_SHELL_LOGIN").is_some() {
+        let user = env::var("KARATOS_SHELL_USER").unwrap_or_else(|_| "admin".into());
+        let password = env::var("KARATOS_SHELL_PASSWORD")
+            .ok()
+            .filter(|password| !password.is_empty())
+            .expect("shell_login needs KARATOS_SHELL_PASSWORD");
+        let mut hash = sha256::Sha256::new();
+        hash.update(format!("{}:{}", user, password).as_bytes());
+        (user, hash.finish())
+    } else {
+        (String::new(), [0; 32])
+    };
+    let code = format!("pub const USER: &str = {:?};\npub const PASSWORD_SHA256: [u8; 32] = {:?};\n", user, hash);
+    std::fs::write(out.join("shell_login.rs"), code).expect("write shell_login.rs");
+}
+
+/// Board descriptor for the enabled `board_*` feature, or the arch default
+fn selected_board(arch: Arch) -> &'static BoardDescriptor {
+    descriptors::BOARDS
+        .iter()
+        .filter(|board| board.arch == arch)
+        .find(|board| {
+            let feature = format!("CARGO_FEATURE_{}", board.feature.to_uppercase());
+            env::var_os(feature).is_some()
+        })
+        .unwrap_or(descriptors::default_board(arch))
+}
+
+fn configure_riscv_build(out: &Path) {
     // Set RISC-V specific configuration
     println!("cargo:rustc-cfg=riscv_target");
 
-    // Use RISC-V specific linker script from templates
-    let template_path = std::env::var("CARGO_MANIFEST_DIR")
-        .map(|dir| PathBuf::from(dir).join("../build/templates/memory-riscv.x"))
-        .unwrap_or_else(|_| PathBuf::from("../build/templates/memory-riscv.x"));
-
-    let riscv_linker_script = std::fs::read(&template_path)
-        .unwrap_or_else(|_| {
-            // Fallback to kernel directory if template not found
-            std::fs::read("memory-riscv.x")
-                .expect("Failed to read RISC-V linker script memory-riscv.x from kernel/ or ../build/templates/")
-        });
-
-    File::create(out.join("memory.x"))
-        .unwrap()
-        .write_all(&riscv_linker_script)
-        .unwrap();
-
-    println!("cargo:rerun-if-changed=memory-riscv.x");
-    println!("cargo:rerun-if-changed=../build/templates/memory-riscv.x");
+    let board = selected_board(Arch::Riscv);
+    check_fit(board);
+    write_linker_script(out, board);
 }
 
-fn configure_arm_build(out: &PathBuf) {
+fn configure_arm_build(out: &Path) {
     // Set ARM specific configuration
     println!("cargo:rustc-cfg=arm_target");
 
-    // Use ARM specific linker script from templates
+    let board = selected_board(Arch::Arm);
+    check_fit(board);
+    write_linker_script(out, board);
+}
+
+/// Fail early, not with a linker overflow, on a build that cannot fit
+fn check_fit(board: &BoardDescriptor) {
+    let enabled = |feature: &str| env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some();
+    if let Some(feature) = board.unsupported.iter().find(|feature| enabled(feature)) {
+        panic!("`{}` does not fit {}", feature, board.name);
+    }
+    if env::var("PROFILE").as_deref() == Ok("release") {
+        return;
+    }
+    if let Some(feature) = board.release_only.iter().find(|feature| enabled(feature)) {
+        panic!("`{}` does not fit {} flash unoptimized; build with --release", feature, board.name);
+    }
+}
+
+/// Emit memory.x: MEMORY and layout symbols from the board descriptor,
+/// followed by the shared SECTIONS template for the board's layout
+fn write_linker_script(out: &Path, board: &BoardDescriptor) {
+    let template = board.layout.sections_template();
     let template_path = std::env::var("CARGO_MANIFEST_DIR")
-        .map(|dir| PathBuf::from(dir).join("../build/templates/memory-arm.x"))
-        .unwrap_or_else(|_| PathBuf::from("../build/templates/memory-arm.x"));
-
-    let arm_linker_script = std::fs::read(&template_path)
-        .unwrap_or_else(|_| {
-            // Fallback to kernel directory if template not found
-            std::fs::read("memory-arm.x")
-                .expect("Failed to read ARM linker script memory-arm.x from kernel/ or ../build/templates/")
-        });
-
-    File::create(out.join("memory.x"))
-        .unwrap()
-        .write_all(&arm_linker_script)
-        .unwrap();
-
-    println!("cargo:rerun-if-changed=memory-arm.x");
-    println!("cargo:rerun-if-changed=../build/templates/memory-arm.x");
-}
\ No newline at end of file
+        .map(|dir| PathBuf::from(dir).join("../build/templates").join(template))
+        .unwrap_or_else(|_| PathBuf::from("../build/templates").join(template));
+
+    let sections = std::fs::read_to_string(&template_path)
+        .unwrap_or_else(|_| panic!("Failed to read section layout {} from ../build/templates/", template));
+
+    let mut script = File::create(out.join("memory.x")).unwrap();
+    script.write_all(memory_block(board).as_bytes()).unwrap();
+    script.write_all(sections.as_bytes()).unwrap();
+
+    println!("cargo:rerun-if-changed=../build/templates/{}", template);
+}
+
+/// MEMORY regions, stack top and heap size for `board`
+fn memory_block(board: &BoardDescriptor) -> String {
+    let mut memory = format!(
+        "/* Generated by build.rs from the {} board descriptor; do not edit */\n\nMEMORY\n{{\n",
+        board.name
+    );
+
+    let region = |name: &str, start: usize, size: usize| {
+        format!("  {} : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n", name, start, size)
+    };
+
+    match board.layout {
+        LinkerLayout::RiscvRam => {}
+        LinkerLayout::Esp32c3Direct { drom } => {
+            memory += &region("FLASH", board.flash.start, board.flash.size);
+            memory += &region("DROM", drom, board.flash.size);
+        }
+        _ => memory += &region("FLASH", board.flash.start, board.flash.size),
+    }
+    memory += &region("RAM", board.ram.start, board.ram.size);
+
+    memory += "}\n\n";
+    memory += "/* Stack grows downward from end of RAM */\n";
+    memory += "_stack_start = ORIGIN(RAM) + LENGTH(RAM);\n";
+    memory += "PROVIDE(_stack_start = _stack_start);\n";
+    memory += &format!("_heap_size = 0x{:X};\n\n", board.heap_size);
+    memory
+}
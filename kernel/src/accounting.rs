@@ -0,0 +1,183 @@
+//! Per-task resource accounting
+//! Who holds queue slots, buffers, timers and stack, and who was refused
+//!
+//! Kernel services charge resources to the running task (or to the
+//! kernel outside task context) and credit them back to the original
+//! owner when released, so a full queue or task table can be traced to
+//! the task holding it. Accounts are keyed by task id and survive the
+//! task being restarted under the same id.
+
+use core::cell::UnsafeCell;
+
+/// Owner id used for resources taken outside any task
+pub const KERNEL_OWNER: usize = usize::MAX;
+
+/// Owners tracked; resources of further owners go unaccounted
+pub const MAX_ACCOUNTS: usize = 16;
+
+/// Resource kinds that are accounted
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// Messages waiting in kernel queues
+    QueueSlot = 0,
+    /// Buffers handed out to the task
+    Buffer = 1,
+    /// Timers the task has registered
+    Timer = 2,
+    /// Stack and arena memory, in bytes
+    MemoryBytes = 3,
+    /// Task table slots; only refusals are recorded, the task table
+    /// itself shows the holders
+    TaskSlot = 4,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 5] =
+        [Resource::QueueSlot, Resource::Buffer, Resource::Timer, Resource::MemoryBytes, Resource::TaskSlot];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Resource::QueueSlot => "queue slots",
+            Resource::Buffer => "buffers",
+            Resource::Timer => "timers",
+            Resource::MemoryBytes => "memory bytes",
+            Resource::TaskSlot => "task slots",
+        }
+    }
+}
+
+const RESOURCES: usize = Resource::ALL.len();
+
+/// Resource usage of one owner
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Account {
+    pub owner: usize,
+    /// Currently held, indexed by `Resource`
+    pub held: [u32; RESOURCES],
+    /// Highest value of `held` seen
+    pub peak: [u32; RESOURCES],
+    /// Requests refused because the resource was exhausted
+    pub denied: [u32; RESOURCES],
+}
+
+impl Account {
+    const fn new(owner: usize) -> Self {
+        Self { owner, held: [0; RESOURCES], peak: [0; RESOURCES], denied: [0; RESOURCES] }
+    }
+
+    pub fn held(&self, resource: Resource) -> u32 {
+        self.held[resource as usize]
+    }
+}
+
+/// Most recent refused request
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct Exhaustion {
+    /// Task whose request was refused
+    pub requester: usize,
+    pub resource: Resource,
+    /// Owner holding the most of `resource` at the time
+    pub top_holder: Option<usize>,
+}
+
+struct Accounts {
+    table: heapless::Vec<Account, MAX_ACCOUNTS>,
+    last_exhaustion: Option<Exhaustion>,
+}
+
+struct AccountTable(UnsafeCell<Accounts>);
+unsafe impl Sync for AccountTable {} // Single-core assumption
+
+static ACCOUNTS: AccountTable =
+    AccountTable(UnsafeCell::new(Accounts { table: heapless::Vec::new(), last_exhaustion: None }));
+
+fn with_accounts<R>(f: impl FnOnce(&mut Accounts) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *ACCOUNTS.0.get()) })
+}
+
+impl Accounts {
+    /// Account for `owner`, created on first use; `None` once the table is full
+    fn account(&mut self, owner: usize) -> Option<&mut Account> {
+        let index = match self.table.iter().position(|account| account.owner == owner) {
+            Some(index) => index,
+            None => {
+                self.table.push(Account::new(owner)).ok()?;
+                self.table.len() - 1
+            }
+        };
+        self.table.get_mut(index)
+    }
+}
+
+/// Id of the task resources are charged to right now
+pub fn current_owner() -> usize {
+    crate::scheduler::current_priority_task().map_or(KERNEL_OWNER, |task| task.id)
+}
+
+/// Charge `amount` of `resource` to `owner`
+pub fn charge(owner: usize, resource: Resource, amount: u32) {
+    with_accounts(|accounts| {
+        if let Some(account) = accounts.account(owner) {
+            let held = &mut account.held[resource as usize];
+            *held = held.saturating_add(amount);
+            let peak = &mut account.peak[resource as usize];
+            *peak = (*peak).max(*held);
+        }
+    });
+}
+
+/// Return `amount` of `resource` previously charged to `owner`
+pub fn credit(owner: usize, resource: Resource, amount: u32) {
+    with_accounts(|accounts| {
+        if let Some(account) = accounts.account(owner) {
+            let held = &mut account.held[resource as usize];
+            *held = held.saturating_sub(amount);
+        }
+    });
+}
+
+/// Record that `requester` was refused `resource` because none was left
+pub fn deny(requester: usize, resource: Resource) {
+    with_accounts(|accounts| {
+        if let Some(account) = accounts.account(requester) {
+            account.denied[resource as usize] = account.denied[resource as usize].saturating_add(1);
+        }
+        let top_holder = accounts
+            .table
+            .iter()
+            .filter(|account| account.held(resource) > 0)
+            .max_by_key(|account| account.held(resource))
+            .map(|account| account.owner);
+        accounts.last_exhaustion = Some(Exhaustion { requester, resource, top_holder });
+    });
+}
+
+/// Usage recorded for `owner`
+#[allow(dead_code)]
+pub fn usage(owner: usize) -> Option<Account> {
+    with_accounts(|accounts| accounts.table.iter().find(|account| account.owner == owner).copied())
+}
+
+/// Visit every account, in the order owners were first charged
+#[allow(dead_code)]
+pub fn for_each(f: impl FnMut(&Account)) {
+    // Copy out so `f` may print without holding interrupts off
+    let table = with_accounts(|accounts| accounts.table.clone());
+    table.iter().for_each(f);
+}
+
+/// Most recent refused request, with the owner holding the most of it
+#[allow(dead_code)]
+pub fn last_exhaustion() -> Option<Exhaustion> {
+    with_accounts(|accounts| accounts.last_exhaustion)
+}
+
+/// Total held and total denied across all owners for `resource`
+pub fn totals(resource: Resource) -> (u32, u32) {
+    with_accounts(|accounts| {
+        accounts.table.iter().fold((0, 0), |(held, denied), account| {
+            (held + account.held[resource as usize], denied + account.denied[resource as usize])
+        })
+    })
+}
@@ -1,6 +1,6 @@
 //! ARM Cortex-M specific functionality and hardware abstraction
 
-use crate::arch::{ArchInit, MemoryLayout};
+use crate::arch::{ArchInit, CpuInfo, MemoryLayout};
 
 // Exception handlers for ARM Cortex-M
 #[cfg(target_arch = "arm")]
@@ -13,8 +13,42 @@ pub unsafe extern "C" fn __pre_init() {
 }
 
 /// Default handler for unhandled interrupts
+///
+/// Without a device crate every IRQ vector lands here, so board drivers
+/// are dispatched by the active vector number before giving up.
 #[no_mangle]
 pub unsafe extern "C" fn DefaultHandler() {
+    #[cfg(feature = "board_nrf52840")]
+    {
+        const ICSR: usize = 0xE000ED04;
+        let vector = core::ptr::read_volatile(ICSR as *const u32) & 0x1FF;
+        // External interrupts start at vector 16
+        if vector == 16 + crate::drivers::nrf_rtc::RTC1_IRQN {
+            crate::instrumentation::measure_isr(crate::drivers::nrf_rtc::on_interrupt);
+            return;
+        }
+    }
+
+    #[cfg(not(feature = "board_nrf52840"))]
+    {
+        const ICSR: usize = 0xE000ED04;
+        let vector = core::ptr::read_volatile(ICSR as *const u32) & 0x1FF;
+        if vector == 16 + CONSOLE_IRQN {
+            crate::instrumentation::measure_isr(crate::drivers::console_rx::on_interrupt);
+            return;
+        }
+    }
+
+    #[cfg(feature = "gdb_stub")]
+    if let Some(irqn) = DEBUG_UART_IRQN {
+        const ICSR: usize = 0xE000ED04;
+        let vector = core::ptr::read_volatile(ICSR as *const u32) & 0x1FF;
+        if vector == 16 + irqn {
+            crate::instrumentation::measure_isr(crate::drivers::debug_uart::on_interrupt);
+            return;
+        }
+    }
+    
     loop {
         cortex_m::asm::wfi();
     }
@@ -30,6 +64,7 @@ unsafe fn NonMaskableInt() {
 
 #[exception]
 unsafe fn MemoryManagement() {
+    crate::coverage::hit(crate::coverage::Path::CpuFault);
     loop {
         cortex_m::asm::wfi();
     }
@@ -37,6 +72,7 @@ unsafe fn MemoryManagement() {
 
 #[exception]
 unsafe fn BusFault() {
+    crate::coverage::hit(crate::coverage::Path::CpuFault);
     loop {
         cortex_m::asm::wfi();
     }
@@ -44,18 +80,13 @@ unsafe fn BusFault() {
 
 #[exception]
 unsafe fn UsageFault() {
+    crate::coverage::hit(crate::coverage::Path::CpuFault);
     loop {
         cortex_m::asm::wfi();
     }
 }
 
-#[exception]
-unsafe fn SVCall() {
-    loop {
-        cortex_m::asm::wfi();
-    }
-}
-
+#[cfg(not(feature = "gdb_stub"))]
 #[exception]
 unsafe fn DebugMonitor() {
     loop {
@@ -63,23 +94,22 @@ unsafe fn DebugMonitor() {
     }
 }
 
-#[exception]
-unsafe fn PendSV() {
-    loop {
-        cortex_m::asm::wfi();
-    }
-}
-
 #[exception]
 unsafe fn SysTick() {
-    loop {
-        cortex_m::asm::wfi();
-    }
+    #[cfg(not(feature = "board_nrf52840"))]
+    crate::instrumentation::measure_isr(crate::drivers::systick::on_interrupt);
 }
 
 // Hard fault handler
 #[exception]
 unsafe fn HardFault(ef: &cortex_m_rt::ExceptionFrame) -> ! {
+    crate::coverage::hit(crate::coverage::Path::CpuFault);
+    crate::crashdump::record_fault(format_args!("hard fault at 0x{:08x}", ef.pc()), &capture_registers());
+    crate::kernel::dump_to_console();
+
+    #[cfg(feature = "gdb_stub")]
+    debug::report_fault(ef);
+
     // Print fault information via semihosting for debugging
     use cortex_m_semihosting::hprintln;
     let _ = hprintln!("Hard Fault at 0x{:x}", ef.pc());
@@ -92,13 +122,525 @@ unsafe fn HardFault(ef: &cortex_m_rt::ExceptionFrame) -> ! {
     }
 }
 
+// -------- MSP/PSP dual-stack support --------
+//
+// The kernel boots and takes exceptions on the main stack (MSP). Before the
+// scheduler starts, thread mode is moved onto a dedicated process stack (PSP)
+// so task code can never corrupt the stack exception handlers run on.
+// PendSV can switch thread mode between process stacks. The scheduler
+// runs its tasks to completion on the one boot stack, so only the ktest
+// PendSV case switches stacks today.
+
+/// Size of the boot process stack used by the scheduler loop and tasks
+pub const PROCESS_STACK_SIZE: usize = 4096;
+
+/// EXC_RETURN value: return to thread mode using PSP, no FPU state
+///
+/// A new task starts with it; a switched-out task keeps the value it was
+/// interrupted with, which on FPU cores may say its frame holds FP state.
+pub const EXC_RETURN_THREAD_PSP: u32 = 0xFFFF_FFFD;
+
+// 8-byte aligned per AAPCS stack requirements
+static mut PROCESS_STACK: [u64; PROCESS_STACK_SIZE / 8] = [0; PROCESS_STACK_SIZE / 8];
+
+/// Location to store the outgoing task's PSP on the next PendSV
+#[no_mangle]
+static mut KARATOS_SAVE_PSP: *mut *mut u32 = core::ptr::null_mut();
+
+/// Location to load the incoming task's PSP from on the next PendSV
+#[no_mangle]
+static mut KARATOS_LOAD_PSP: *const *mut u32 = core::ptr::null();
+
+// PendSV context switch: push r4-r11 and the EXC_RETURN in lr onto the
+// outgoing process stack, save its PSP, then restore both from the incoming
+// stack and return with the incoming task's EXC_RETURN. Hardware stacks and
+// unstacks r0-r3, r12, lr, pc and xPSR (and s0-s15, FPSCR for a task that
+// used the FPU, EXC_RETURN bit 4 clear); s16-s31 are pushed here in that
+// case. A request is consumed by the switch; with none pending PendSV
+// returns to whatever it interrupted.
+#[cfg(not(target_abi = "eabihf"))]
+core::arch::global_asm!(
+    ".section .text.PendSV, \"ax\"",
+    ".global PendSV",
+    ".type PendSV, %function",
+    ".thumb_func",
+    "PendSV:",
+    "    ldr r2, =KARATOS_LOAD_PSP",
+    "    ldr r2, [r2]",
+    "    cbz r2, 2f",
+    "    ldr r1, =KARATOS_SAVE_PSP",
+    "    ldr r1, [r1]",
+    "    cbz r1, 1f",
+    "    mrs r0, psp",
+    "    stmdb r0!, {{r4-r11, lr}}",
+    "    str r0, [r1]",
+    "1:",
+    "    ldr r0, [r2]",
+    "    ldr r1, =KARATOS_LOAD_PSP",
+    "    movs r3, #0",
+    "    str r3, [r1]",
+    "    ldmia r0!, {{r4-r11, lr}}",
+    "    msr psp, r0",
+    "    isb",
+    "2:",
+    "    bx lr",
+    ".size PendSV, . - PendSV",
+);
+
+#[cfg(target_abi = "eabihf")]
+core::arch::global_asm!(
+    ".section .text.PendSV, \"ax\"",
+    ".global PendSV",
+    ".type PendSV, %function",
+    ".thumb_func",
+    "PendSV:",
+    "    ldr r2, =KARATOS_LOAD_PSP",
+    "    ldr r2, [r2]",
+    "    cbz r2, 2f",
+    "    ldr r1, =KARATOS_SAVE_PSP",
+    "    ldr r1, [r1]",
+    "    cbz r1, 1f",
+    "    mrs r0, psp",
+    "    tst lr, #0x10",
+    "    it eq",
+    "    vstmdbeq r0!, {{s16-s31}}",
+    "    stmdb r0!, {{r4-r11, lr}}",
+    "    str r0, [r1]",
+    "1:",
+    "    ldr r0, [r2]",
+    "    ldr r1, =KARATOS_LOAD_PSP",
+    "    movs r3, #0",
+    "    str r3, [r1]",
+    "    ldmia r0!, {{r4-r11, lr}}",
+    "    tst lr, #0x10",
+    "    it eq",
+    "    vldmiaeq r0!, {{s16-s31}}",
+    "    msr psp, r0",
+    "    isb",
+    "2:",
+    "    bx lr",
+    ".size PendSV, . - PendSV",
+);
+
+/// Move thread mode onto the process stack and jump to `entry`
+///
+/// Called once during boot. After this point the scheduler loop and tasks
+/// run on PSP while every exception handler uses MSP.
+pub fn enter_process_stack(entry: extern "C" fn() -> !) -> ! {
+    let stack_top = core::ptr::addr_of_mut!(PROCESS_STACK) as usize + PROCESS_STACK_SIZE;
+
+    unsafe {
+        core::arch::asm!(
+            "msr psp, r0",
+            "mrs r1, control",
+            "orr r1, r1, #2", // SPSEL = 1: thread mode uses PSP
+            "msr control, r1",
+            "isb",
+            "bx r2",
+            in("r0") stack_top,
+            in("r2") entry,
+            options(noreturn),
+        );
+    }
+}
+
+/// Check whether thread mode is currently running on PSP
+#[allow(dead_code)]
+pub fn using_process_stack() -> bool {
+    let control: u32;
+    unsafe {
+        core::arch::asm!("mrs {}, control", out(reg) control, options(nomem, nostack));
+    }
+    control & 0b10 != 0
+}
+
+/// Bounds (bottom, top) of the process stack thread mode runs on
+pub fn process_stack_bounds() -> (usize, usize) {
+    let bottom = core::ptr::addr_of!(PROCESS_STACK) as usize;
+    (bottom, bottom + PROCESS_STACK_SIZE)
+}
+
+/// Main stack kept at the top of RAM, the linker's `.stack` reserve
+const MAIN_STACK_RESERVE: usize = 0x400;
+
+/// RAM (start, end) nothing is linked or stacked in: past `.heap` and
+/// below the main stack exceptions run on
+pub fn free_ram_bounds() -> (usize, usize) {
+    extern "C" {
+        static __eheap: u8;
+        static _stack_start: u8;
+    }
+
+    let top = core::ptr::addr_of!(_stack_start) as usize;
+    (core::ptr::addr_of!(__eheap) as usize, top - MAIN_STACK_RESERVE)
+}
+
+/// Bounds (start, end) of the linked code, `.text` in flash
+pub fn code_bounds() -> (usize, usize) {
+    extern "C" {
+        static __stext: u8;
+        static __etext: u8;
+    }
+
+    (core::ptr::addr_of!(__stext) as usize, core::ptr::addr_of!(__etext) as usize)
+}
+
+/// Build an initial task frame so PendSV can start `entry` on `stack`
+///
+/// Returns the PSP value to hand to `request_context_switch`.
+#[allow(dead_code)]
+pub fn prepare_task_stack(stack: &mut [u64], entry: extern "C" fn() -> !) -> *mut u32 {
+    const XPSR_THUMB: u32 = 1 << 24;
+    // r4-r11, EXC_RETURN (software frame, PendSV's `ldmia {r4-r11, lr}`)
+    // + r0-r3, r12, lr, pc, xpsr (hardware frame); EXC_RETURN bit 4 is
+    // set, so the eabihf PendSV loads no s16-s31 and there is no FP frame
+    const SOFTWARE_WORDS: usize = 9;
+    const FRAME_WORDS: usize = SOFTWARE_WORDS + 8;
+    const _: () = assert!(EXC_RETURN_THREAD_PSP & 0x10 != 0, "new tasks start without FP state");
+
+    let top = unsafe { (stack.as_mut_ptr() as *mut u32).add(stack.len() * 2) };
+    let frame = unsafe { top.sub(FRAME_WORDS) };
+
+    unsafe {
+        for i in 0..FRAME_WORDS {
+            frame.add(i).write_volatile(0);
+        }
+        frame.add(SOFTWARE_WORDS - 1).write_volatile(EXC_RETURN_THREAD_PSP);
+        frame.add(SOFTWARE_WORDS + 5).write_volatile(task_exit as *const () as u32); // lr
+        // The Thumb state comes from xPSR; a stacked pc with bit 0 set is
+        // UNPREDICTABLE on exception return
+        frame.add(SOFTWARE_WORDS + 6).write_volatile(entry as *const () as u32 & !1); // pc
+        frame.add(SOFTWARE_WORDS + 7).write_volatile(XPSR_THUMB);                    // xpsr
+    }
+
+    frame
+}
+
+/// Landing pad if a task entry ever returns
+extern "C" fn task_exit() -> ! {
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Pend a PendSV context switch from `save` (may be null) to `load`
+///
+/// # Safety
+/// Both pointers must stay valid until PendSV has run, and `load` must
+/// point at a PSP produced by `prepare_task_stack` or a previous switch.
+#[allow(dead_code)]
+pub unsafe fn request_context_switch(save: *mut *mut u32, load: *const *mut u32) {
+    const ICSR: usize = 0xE000ED04; // Interrupt control and state register
+    const ICSR_PENDSVSET: u32 = 1 << 28;
+
+    KARATOS_SAVE_PSP = save;
+    KARATOS_LOAD_PSP = load;
+    core::ptr::write_volatile(ICSR as *mut u32, ICSR_PENDSVSET);
+    core::arch::asm!("dsb", "isb", options(nomem, nostack));
+}
+
+// -------- SVC system call entry --------
+//
+// Callers issue `svc #<number>` with arguments in r0-r3. The trampoline
+// picks whichever stack the caller was using (EXC_RETURN bit 2) and hands
+// the stacked exception frame to `karatos_svc_handler`, which decodes the
+// immediate from the SVC instruction and writes the result back to r0.
+
+core::arch::global_asm!(
+    ".section .text.SVCall, \"ax\"",
+    ".global SVCall",
+    ".type SVCall, %function",
+    ".thumb_func",
+    "SVCall:",
+    "    tst lr, #4",
+    "    ite eq",
+    "    mrseq r0, msp",
+    "    mrsne r0, psp",
+    "    b karatos_svc_handler",
+    ".size SVCall, . - SVCall",
+);
+
+/// Decode and dispatch an SVC from its stacked exception frame
+///
+/// Frame layout: r0, r1, r2, r3, r12, lr, pc, xpsr.
+#[no_mangle]
+unsafe extern "C" fn karatos_svc_handler(frame: *mut u32) {
+    // The stacked pc points past the 16-bit SVC instruction
+    let pc = frame.add(6).read_volatile();
+    let number = ((pc - 2) as *const u16).read_volatile() & 0xFF;
+
+    // Only r0-r3 are stacked; the remaining argument slots are zero
+    let args = [
+        frame.add(0).read_volatile() as usize,
+        frame.add(1).read_volatile() as usize,
+        frame.add(2).read_volatile() as usize,
+        frame.add(3).read_volatile() as usize,
+        0,
+        0,
+    ];
+
+    let result = crate::syscall::dispatch(number as usize, args);
+    frame.add(0).write_volatile(result as u32);
+}
+
+/// Issue system call `N` with up to four arguments
+#[allow(dead_code)]
+#[inline(always)]
+pub fn syscall<const N: u8>(args: [usize; 4]) -> usize {
+    let result: usize;
+    unsafe {
+        core::arch::asm!(
+            "svc {number}",
+            number = const N,
+            inlateout("r0") args[0] => result,
+            in("r1") args[1],
+            in("r2") args[2],
+            in("r3") args[3],
+        );
+    }
+    result
+}
+
+/// Debug monitor support for the GDB stub
+///
+/// With DEMCR.MON_EN set, BKPT instructions, FPB matches, single steps
+/// and halt requests raise DebugMonitor instead of escalating to
+/// HardFault. Its trampoline stacks r4-r11 beside the hardware frame, so
+/// the stub sees, and may change, every core register of the stopped code.
+#[cfg(feature = "gdb_stub")]
+pub mod debug {
+    use core::ptr::{read_volatile, write_volatile};
+    use crate::gdb::{Resume, StopRegisters};
+
+    const DEMCR: usize = 0xE000EDFC;
+    const DEMCR_MON_EN: u32 = 1 << 16;
+    const DEMCR_MON_PEND: u32 = 1 << 17;
+    const DEMCR_MON_STEP: u32 = 1 << 18;
+    const DFSR: usize = 0xE000ED30;
+    const DFSR_HALTED: u32 = 1 << 0; // Single step
+    const DFSR_BKPT: u32 = 1 << 1; // BKPT instruction or FPB match
+    const CFSR: usize = 0xE000ED28;
+    const HFSR: usize = 0xE000ED2C;
+    const HFSR_DEBUGEVT: u32 = 1 << 31;
+
+    const FP_CTRL: usize = 0xE000_2000;
+    const FP_COMP0: usize = 0xE000_2008;
+    const FP_CTRL_ENABLE_KEY: u32 = 0b11;
+    const FP_COMP_ENABLE: u32 = 1;
+    /// FPB comparators only match the code region
+    const FPB_LIMIT: usize = 0x2000_0000;
+
+    /// GDB register numbers: r0-r12, sp, lr, pc, xpsr
+    pub const REGISTER_COUNT: usize = 17;
+    const SP: usize = 13;
+    pub const PC: usize = 15;
+
+    /// DEMCR.MON_STEP stops again after one instruction
+    pub const SINGLE_STEP: bool = true;
+
+    /// GDB's M-profile register set, in the order above
+    pub const TARGET_XML: Option<&str> = Some(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd"><target>"#,
+        r#"<architecture>arm</architecture><feature name="org.gnu.gdb.arm.m-profile">"#,
+        r#"<reg name="r0" bitsize="32"/><reg name="r1" bitsize="32"/><reg name="r2" bitsize="32"/>"#,
+        r#"<reg name="r3" bitsize="32"/><reg name="r4" bitsize="32"/><reg name="r5" bitsize="32"/>"#,
+        r#"<reg name="r6" bitsize="32"/><reg name="r7" bitsize="32"/><reg name="r8" bitsize="32"/>"#,
+        r#"<reg name="r9" bitsize="32"/><reg name="r10" bitsize="32"/><reg name="r11" bitsize="32"/>"#,
+        r#"<reg name="r12" bitsize="32"/><reg name="sp" bitsize="32" type="data_ptr"/>"#,
+        r#"<reg name="lr" bitsize="32"/><reg name="pc" bitsize="32" type="code_ptr"/>"#,
+        r#"<reg name="xpsr" bitsize="32"/></feature></target>"#,
+    ));
+
+    /// BKPT #0; GDB's Thumb-2 breakpoint kind also only needs the first halfword
+    pub fn breakpoint_instruction(_kind: usize) -> Option<&'static [u8]> {
+        Some(&[0x00, 0xBE])
+    }
+
+    /// Route debug events to DebugMonitor
+    pub fn enable() {
+        unsafe {
+            write_volatile(DEMCR as *mut u32, read_volatile(DEMCR as *const u32) | DEMCR_MON_EN);
+        }
+    }
+
+    /// Stop the running code and enter the stub (thread mode only)
+    pub fn halt() {
+        unsafe {
+            write_volatile(DEMCR as *mut u32, read_volatile(DEMCR as *const u32) | DEMCR_MON_PEND);
+            core::arch::asm!("dsb", "isb", options(nomem, nostack));
+        }
+    }
+
+    /// Make patched instructions visible to the fetch path
+    pub fn sync_instructions() {
+        unsafe { core::arch::asm!("dsb", "isb", options(nomem, nostack)) };
+    }
+
+    /// Break at `addr` with a free FPB comparator
+    pub fn set_hw_breakpoint(addr: usize) -> bool {
+        if addr >= FPB_LIMIT {
+            return false;
+        }
+        let comp = fpb_comp(addr);
+        unsafe {
+            let Some(slot) = (0..fpb_count()).find(|&n| fp_comp(n).read_volatile() & FP_COMP_ENABLE == 0) else {
+                return false;
+            };
+            fp_comp(slot).write_volatile(comp);
+            write_volatile(FP_CTRL as *mut u32, FP_CTRL_ENABLE_KEY);
+        }
+        true
+    }
+
+    pub fn clear_hw_breakpoint(addr: usize) -> bool {
+        let comp = fpb_comp(addr);
+        unsafe {
+            let Some(slot) = (0..fpb_count()).find(|&n| fp_comp(n).read_volatile() == comp) else {
+                return false;
+            };
+            fp_comp(slot).write_volatile(0);
+        }
+        true
+    }
+
+    fn fpb_count() -> usize {
+        let ctrl = unsafe { read_volatile(FP_CTRL as *const u32) } as usize;
+        ((ctrl >> 4) & 0xF) | ((ctrl >> 8) & 0x70)
+    }
+
+    fn fp_comp(n: usize) -> *mut u32 {
+        (FP_COMP0 + 4 * n) as *mut u32
+    }
+
+    /// Comparator value for a breakpoint on the halfword at `addr`
+    fn fpb_comp(addr: usize) -> u32 {
+        let replace = if addr & 2 == 0 { 0b01 << 30 } else { 0b10 << 30 };
+        (addr as u32 & 0x1FFF_FFFC) | replace | FP_COMP_ENABLE
+    }
+
+    fn set_single_step(on: bool) {
+        unsafe {
+            let demcr = read_volatile(DEMCR as *const u32);
+            let demcr = if on { demcr | DEMCR_MON_STEP } else { demcr & !DEMCR_MON_STEP };
+            write_volatile(DEMCR as *mut u32, demcr);
+        }
+    }
+
+    /// sp of the stopped code: above the hardware frame and its padding
+    unsafe fn stacked_sp(frame: *const u32, exc_return: u32) -> u32 {
+        const EXC_RETURN_BASIC_FRAME: u32 = 1 << 4;
+        const XPSR_PADDED: u32 = 1 << 9;
+        let words = if exc_return & EXC_RETURN_BASIC_FRAME != 0 { 8 } else { 26 };
+        let padding = if frame.add(7).read_volatile() & XPSR_PADDED != 0 { 4 } else { 0 };
+        frame as u32 + words * 4 + padding
+    }
+
+    /// Copy the hardware frame (r0-r3, r12, lr, pc, xpsr) into GDB order
+    unsafe fn frame_registers(frame: *const u32, regs: &mut StopRegisters) {
+        for n in 0..4 {
+            regs.set(n, frame.add(n).read_volatile());
+        }
+        for (n, word) in [(12, 4), (14, 5), (15, 6), (16, 7)] {
+            regs.set(n, frame.add(word).read_volatile());
+        }
+    }
+
+    // Frame pointer in r0 as for SVCall, r4-r11 pushed with r3 as padding
+    // to keep the stack 8-byte aligned, EXC_RETURN in r2
+    core::arch::global_asm!(
+        ".section .text.DebugMonitor, \"ax\"",
+        ".global DebugMonitor",
+        ".type DebugMonitor, %function",
+        ".thumb_func",
+        "DebugMonitor:",
+        "    tst lr, #4",
+        "    ite eq",
+        "    mrseq r0, msp",
+        "    mrsne r0, psp",
+        "    mov r2, lr",
+        "    push {{r3-r11, lr}}",
+        "    add r1, sp, #4",
+        "    bl karatos_debug_monitor",
+        "    pop {{r3-r11, pc}}",
+        ".size DebugMonitor, . - DebugMonitor",
+    );
+
+    #[no_mangle]
+    unsafe extern "C" fn karatos_debug_monitor(frame: *mut u32, callee: *mut u32, exc_return: u32) {
+        let dfsr = read_volatile(DFSR as *const u32);
+        write_volatile(DFSR as *mut u32, dfsr); // Write one to clear
+        let signal = if dfsr & (DFSR_HALTED | DFSR_BKPT) != 0 { crate::gdb::SIGTRAP } else { crate::gdb::SIGINT };
+
+        let mut regs = StopRegisters::new();
+        frame_registers(frame, &mut regs);
+        for n in 0..8 {
+            regs.set(4 + n, callee.add(n).read_volatile());
+        }
+        regs.set(SP, stacked_sp(frame, exc_return));
+
+        let resume = crate::gdb::stopped(signal, &mut regs);
+
+        for n in 0..4 {
+            frame.add(n).write_volatile(regs.get(n));
+        }
+        for n in 0..8 {
+            callee.add(n).write_volatile(regs.get(4 + n));
+        }
+        for (n, word) in [(12, 4), (14, 5), (15, 6), (16, 7)] {
+            frame.add(word).write_volatile(regs.get(n));
+        }
+        set_single_step(resume == Resume::Step);
+    }
+
+    /// Hand a HardFault to GDB; r4-r11 are not saved on this path
+    pub fn report_fault(ef: &cortex_m_rt::ExceptionFrame) {
+        let frame = ef as *const cortex_m_rt::ExceptionFrame as *const u32;
+        let cfsr = unsafe { read_volatile(CFSR as *const u32) };
+        let hfsr = unsafe { read_volatile(HFSR as *const u32) };
+        let signal = if hfsr & HFSR_DEBUGEVT != 0 {
+            crate::gdb::SIGTRAP // BKPT where DebugMonitor cannot preempt, e.g. in a handler
+        } else if cfsr & (0xF << 16) != 0 {
+            crate::gdb::SIGILL // UNDEFINSTR, INVSTATE, INVPC, NOCP
+        } else if cfsr & (1 << 25) != 0 {
+            crate::gdb::SIGFPE // DIVBYZERO
+        } else if cfsr & (0xFF << 8 | 1 << 24) != 0 {
+            crate::gdb::SIGBUS // Bus fault or UNALIGNED
+        } else {
+            crate::gdb::SIGSEGV
+        };
+
+        let mut regs = StopRegisters::new();
+        unsafe {
+            frame_registers(frame, &mut regs);
+            // cortex-m-rt hands over no EXC_RETURN; assume no FP state
+            regs.set(SP, stacked_sp(frame, 1 << 4));
+        }
+        crate::gdb::fault(signal, &mut regs);
+    }
+}
+
+/// Enable the debug UART's interrupt in the NVIC
+#[allow(dead_code)]
+pub fn debug_uart_interrupt_enable() {
+    const NVIC_ISER: usize = 0xE000E100;
+    if let Some(irqn) = DEBUG_UART_IRQN {
+        unsafe {
+            let iser = (NVIC_ISER + 4 * (irqn as usize / 32)) as *mut u32;
+            iser.write_volatile(1 << (irqn % 32));
+        }
+    }
+}
+
 /// ARM architecture implementation
 pub struct ArmArch;
 
 impl ArchInit for ArmArch {
     fn init() {
-        // Initialize ARM-specific features
-        ArmArch::init_uart();
+        // Initialize ARM-specific features; other consoles are set up by
+        // their board init
+        if crate::board::BOARD.uart_kind == crate::board::UartKind::Pl011 {
+            ArmArch::init_uart();
+        }
         Self::irq_init();
         Self::setup_memory_protection();
     }
@@ -116,30 +658,32 @@ impl ArchInit for ArmArch {
 
 impl ArmArch {
     fn init_uart() {
-        // LM3S6965EVB UART0 initialization
+        // PL011 console at the base board detection settled on
         const RCGC1: usize = 0x400FE104; // Run mode clock gating control register 1
-        const UART0_BASE: usize = 0x4000C000;
-        const UARTIBRD: usize = UART0_BASE + 0x024; // Integer baud rate divisor
-        const UARTFBRD: usize = UART0_BASE + 0x028; // Fractional baud rate divisor
-        const UARTLCRH: usize = UART0_BASE + 0x02C; // Line control register
-        const UARTCTL: usize = UART0_BASE + 0x030; // Control register
+        let uart0_base = crate::board::active().uart_base;
+        let uartibrd = uart0_base + 0x024; // Integer baud rate divisor
+        let uartfbrd = uart0_base + 0x028; // Fractional baud rate divisor
+        let uartlcrh = uart0_base + 0x02C; // Line control register
+        let uartctl = uart0_base + 0x030; // Control register
         
         unsafe {
-            // Enable UART0 clock
-            let rcgc1 = core::ptr::read_volatile(RCGC1 as *const u32);
-            core::ptr::write_volatile(RCGC1 as *mut u32, rcgc1 | (1 << 0));
+            // Enable UART0 clock; only the LM3S has this clock gate
+            if uart0_base == crate::board::BOARD.uart_base {
+                let rcgc1 = core::ptr::read_volatile(RCGC1 as *const u32);
+                core::ptr::write_volatile(RCGC1 as *mut u32, rcgc1 | (1 << 0));
+            }
             
             // Configure UART for 115200 baud rate (assuming 16MHz system clock)
             // IBRD = 16MHz / (16 * 115200) = 8.6805 -> 8
             // FBRD = (0.6805 * 64) + 0.5 = 43.5 -> 44
-            core::ptr::write_volatile(UARTIBRD as *mut u32, 8);
-            core::ptr::write_volatile(UARTFBRD as *mut u32, 44);
+            core::ptr::write_volatile(uartibrd as *mut u32, 8);
+            core::ptr::write_volatile(uartfbrd as *mut u32, 44);
             
             // Configure line control: 8 bits, no parity, 1 stop bit
-            core::ptr::write_volatile(UARTLCRH as *mut u32, 0x60);
+            core::ptr::write_volatile(uartlcrh as *mut u32, 0x60);
             
             // Enable UART, TX, RX
-            core::ptr::write_volatile(UARTCTL as *mut u32, 0x301);
+            core::ptr::write_volatile(uartctl as *mut u32, 0x301);
         }
     }
 }
@@ -150,19 +694,19 @@ pub struct ArmMemoryLayout;
 
 impl MemoryLayout for ArmMemoryLayout {
     fn ram_start() -> usize {
-        0x20000000 // Standard ARM Cortex-M RAM start
+        crate::board::BOARD.ram.start
     }
 
     fn ram_size() -> usize {
-        64 * 1024 // 64KB RAM for LM3S6965
+        crate::board::BOARD.ram.size
     }
 
     fn flash_start() -> usize {
-        0x00000000 // Flash start
+        crate::board::BOARD.flash.start
     }
 
     fn flash_size() -> usize {
-        256 * 1024 // 256KB Flash for LM3S6965
+        crate::board::BOARD.flash.size
     }
 
     fn stack_top() -> usize {
@@ -191,20 +735,407 @@ pub fn enable_interrupts() {
     }
 }
 
+/// Whether PRIMASK lets interrupts through
+pub fn interrupts_enabled() -> bool {
+    let primask: u32;
+    unsafe {
+        core::arch::asm!("mrs {}, primask", out(reg) primask, options(nomem, nostack, preserves_flags));
+    }
+    primask & 1 == 0
+}
+
+/// Enable the DWT cycle counter (CYCCNT)
+#[allow(dead_code)]
+pub fn init_cycle_counter() {
+    const DEMCR: usize = 0xE000EDFC; // Debug exception and monitor control
+    const DEMCR_TRCENA: u32 = 1 << 24;
+    const DWT_CTRL: usize = 0xE0001000;
+    const DWT_CTRL_CYCCNTENA: u32 = 1 << 0;
+
+    unsafe {
+        let demcr = core::ptr::read_volatile(DEMCR as *const u32);
+        core::ptr::write_volatile(DEMCR as *mut u32, demcr | DEMCR_TRCENA);
+        let ctrl = core::ptr::read_volatile(DWT_CTRL as *const u32);
+        core::ptr::write_volatile(DWT_CTRL as *mut u32, ctrl | DWT_CTRL_CYCCNTENA);
+    }
+}
+
+/// Read the DWT cycle counter
+#[allow(dead_code)]
+pub fn cycle_counter() -> u32 {
+    cortex_m::peripheral::DWT::cycle_count()
+}
+
+/// Read a word, returning `None` instead of faulting if nothing responds
+///
+/// Runs with FAULTMASK set and CCR.BFHFNMIGN so a bus error on `addr` is
+/// only latched in the BFSR rather than escalating to HardFault.
+pub fn probe_read(addr: usize) -> Option<u32> {
+    const SCB_CCR: usize = 0xE000ED14;
+    const SCB_CFSR: usize = 0xE000ED28;
+    const CCR_BFHFNMIGN: u32 = 1 << 8;
+    const CFSR_BFSR_MASK: u32 = 0xFF << 8;
+
+    unsafe {
+        core::ptr::write_volatile(SCB_CFSR as *mut u32, CFSR_BFSR_MASK);
+        core::arch::asm!("cpsid f", options(nomem, nostack));
+        let ccr = core::ptr::read_volatile(SCB_CCR as *const u32);
+        core::ptr::write_volatile(SCB_CCR as *mut u32, ccr | CCR_BFHFNMIGN);
+        core::arch::asm!("dsb", "isb", options(nostack));
+
+        let value = core::ptr::read_volatile(addr as *const u32);
+        core::arch::asm!("dsb", options(nostack));
+
+        core::ptr::write_volatile(SCB_CCR as *mut u32, ccr);
+        core::arch::asm!("isb", "cpsie f", options(nostack));
+
+        let faulted = core::ptr::read_volatile(SCB_CFSR as *const u32) & CFSR_BFSR_MASK != 0;
+        core::ptr::write_volatile(SCB_CFSR as *mut u32, CFSR_BFSR_MASK);
+        if faulted {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// Read and clear the LM3S SYSCTL reset cause register (RESC)
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub fn read_reset_reason() -> super::ResetReason {
+    use super::ResetReason;
+    const SYSCTL_RESC: usize = 0x400FE05C;
+    const RESC_EXT: u32 = 1 << 0;
+    const RESC_POR: u32 = 1 << 1;
+    const RESC_BOR: u32 = 1 << 2;
+    const RESC_WDT: u32 = 1 << 3;
+    const RESC_SW: u32 = 1 << 4;
+
+    let resc = unsafe {
+        let resc = core::ptr::read_volatile(SYSCTL_RESC as *const u32);
+        core::ptr::write_volatile(SYSCTL_RESC as *mut u32, 0);
+        resc
+    };
+
+    if resc & RESC_WDT != 0 {
+        ResetReason::Watchdog
+    } else if resc & RESC_SW != 0 {
+        ResetReason::Software
+    } else if resc & RESC_BOR != 0 {
+        ResetReason::Brownout
+    } else if resc & RESC_POR != 0 {
+        ResetReason::PowerOn
+    } else if resc & RESC_EXT != 0 {
+        ResetReason::External
+    } else {
+        ResetReason::Unknown
+    }
+}
+
+/// Read and clear the STM32F4 RCC_CSR reset flags
+#[cfg(feature = "board_stm32f407")]
+pub fn read_reset_reason() -> super::ResetReason {
+    use super::ResetReason;
+    const RCC_CSR: usize = 0x40023874;
+    const CSR_RMVF: u32 = 1 << 24;
+    const CSR_BORRSTF: u32 = 1 << 25;
+    const CSR_PINRSTF: u32 = 1 << 26;
+    const CSR_PORRSTF: u32 = 1 << 27;
+    const CSR_SFTRSTF: u32 = 1 << 28;
+    const CSR_IWDGRSTF: u32 = 1 << 29;
+    const CSR_WWDGRSTF: u32 = 1 << 30;
+    const CSR_LPWRRSTF: u32 = 1 << 31;
+
+    let csr = unsafe {
+        let csr = core::ptr::read_volatile(RCC_CSR as *const u32);
+        core::ptr::write_volatile(RCC_CSR as *mut u32, csr | CSR_RMVF);
+        csr
+    };
+
+    // POR also sets PINRSTF and BORRSTF, so check it before either
+    if csr & (CSR_IWDGRSTF | CSR_WWDGRSTF) != 0 {
+        ResetReason::Watchdog
+    } else if csr & CSR_SFTRSTF != 0 {
+        ResetReason::Software
+    } else if csr & CSR_LPWRRSTF != 0 {
+        ResetReason::Wakeup
+    } else if csr & CSR_PORRSTF != 0 {
+        ResetReason::PowerOn
+    } else if csr & CSR_BORRSTF != 0 {
+        ResetReason::Brownout
+    } else if csr & CSR_PINRSTF != 0 {
+        ResetReason::External
+    } else {
+        ResetReason::Unknown
+    }
+}
+
+/// Read and clear the nRF52 POWER RESETREAS register
+#[cfg(feature = "board_nrf52840")]
+pub fn read_reset_reason() -> super::ResetReason {
+    use super::ResetReason;
+    const POWER_RESETREAS: usize = 0x40000400;
+    const RESETREAS_RESETPIN: u32 = 1 << 0;
+    const RESETREAS_DOG: u32 = 1 << 1;
+    const RESETREAS_SREQ: u32 = 1 << 2;
+    const RESETREAS_LOCKUP: u32 = 1 << 3;
+    // OFF, LPCOMP, DIF, NFC, VBUS: wakeups from System OFF
+    const RESETREAS_WAKE_MASK: u32 = 0x1F << 16;
+
+    let reas = unsafe {
+        let reas = core::ptr::read_volatile(POWER_RESETREAS as *const u32);
+        core::ptr::write_volatile(POWER_RESETREAS as *mut u32, reas);
+        reas
+    };
+
+    // No flag at all means power-on or brownout, which share the POR circuit
+    if reas & RESETREAS_DOG != 0 {
+        ResetReason::Watchdog
+    } else if reas & RESETREAS_LOCKUP != 0 {
+        ResetReason::Lockup
+    } else if reas & RESETREAS_SREQ != 0 {
+        ResetReason::Software
+    } else if reas & RESETREAS_WAKE_MASK != 0 {
+        ResetReason::Wakeup
+    } else if reas & RESETREAS_RESETPIN != 0 {
+        ResetReason::External
+    } else {
+        ResetReason::PowerOn
+    }
+}
+
+/// Decode the SCB CPUID register
+pub fn cpu_info() -> CpuInfo {
+    const CPUID: usize = 0xE000ED00;
+
+    let cpuid = unsafe { core::ptr::read_volatile(CPUID as *const u32) };
+    let implementer = cpuid >> 24;
+    let variant = (cpuid >> 20) & 0xF;
+    let architecture = (cpuid >> 16) & 0xF;
+    let partno = (cpuid >> 4) & 0xFFF;
+    let revision = cpuid & 0xF;
+
+    let name = match (implementer, partno) {
+        (0x41, 0xC20) => "Cortex-M0",
+        (0x41, 0xC60) => "Cortex-M0+",
+        (0x41, 0xC21) => "Cortex-M1",
+        (0x41, 0xC23) => "Cortex-M3",
+        (0x41, 0xC24) => "Cortex-M4",
+        (0x41, 0xC27) => "Cortex-M7",
+        (0x41, 0xD20) => "Cortex-M23",
+        (0x41, 0xD21) => "Cortex-M33",
+        _ => "Unknown ARM core",
+    };
+
+    CpuInfo {
+        vendor_id: implementer,
+        arch_id: partno,
+        revision: (variant << 4) | revision,
+        features: architecture,
+        name,
+    }
+}
+
 /// Early debug output for ARM
 pub fn early_println(msg: &str) {
-    // LM3S6965EVB UART0 at 0x4000C000
-    const UART_BASE: usize = 0x4000C000;
-    const UARTDR: usize = UART_BASE + 0x000; // Data register
+    for byte in msg.bytes() {
+        console_putc(byte);
+    }
+    // Add newline
+    console_putc(b'\n');
+}
+
+/// Write raw bytes to the console, no newline added
+pub fn console_write(bytes: &[u8]) {
+    for &byte in bytes {
+        console_putc(byte);
+    }
+}
+
+/// Wait until the console UART has sent everything
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub fn console_flush() {
+    const UARTFR: usize = 0x018; // Flag register offset
+    const FR_BUSY: u32 = 1 << 3;
+    let uart_base = crate::board::active().uart_base;
 
     unsafe {
-        for byte in msg.bytes() {
-            // Write byte directly to UART data register
-            // QEMU should handle the UART configuration
-            core::ptr::write_volatile(UARTDR as *mut u32, byte as u32);
+        while core::ptr::read_volatile((uart_base + UARTFR) as *const u32) & FR_BUSY != 0 {}
+    }
+}
+
+/// Wait until the console UART has sent everything
+#[cfg(feature = "board_stm32f407")]
+pub fn console_flush() {
+    const USART_SR: usize = crate::board::BOARD.uart_base; // Status register
+    const SR_TC: u32 = 1 << 6; // Transmission complete
+
+    unsafe {
+        while core::ptr::read_volatile(USART_SR as *const u32) & SR_TC == 0 {}
+    }
+}
+
+/// Each UARTE write already waits for ENDTX, so nothing is queued
+#[cfg(feature = "board_nrf52840")]
+pub fn console_flush() {}
+
+/// Write one byte to the board console UART
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+fn console_putc(byte: u8) {
+    // LM3S6965EVB UART0 (PL011)
+    const UARTDR: usize = 0x000; // Data register offset
+    let uart_base = crate::board::active().uart_base;
+
+    unsafe {
+        // Write byte directly to UART data register
+        // QEMU should handle the UART configuration
+        core::ptr::write_volatile((uart_base + UARTDR) as *mut u32, byte as u32);
+    }
+}
+
+/// Write one byte to the board console UART
+#[cfg(feature = "board_stm32f407")]
+fn console_putc(byte: u8) {
+    // STM32F407 USART2, configured by board init
+    const USART_BASE: usize = crate::board::BOARD.uart_base;
+    const USART_SR: usize = USART_BASE; // Status register
+    const USART_DR: usize = USART_BASE + 0x04; // Data register
+    const SR_TXE: u32 = 1 << 7; // Transmit data register empty
+
+    unsafe {
+        while core::ptr::read_volatile(USART_SR as *const u32) & SR_TXE == 0 {
+            // Busy wait - USART not ready
+        }
+        core::ptr::write_volatile(USART_DR as *mut u32, byte as u32);
+    }
+}
+
+/// Write one byte to the board console UART
+#[cfg(feature = "board_nrf52840")]
+fn console_putc(byte: u8) {
+    // nRF52840 UARTE0, configured by board init.
+    // UARTE transmits by EasyDMA, so the byte must live in RAM.
+    const UARTE_BASE: usize = crate::board::BOARD.uart_base;
+    const TASKS_STARTTX: usize = UARTE_BASE + 0x008;
+    const EVENTS_ENDTX: usize = UARTE_BASE + 0x120;
+    const TXD_PTR: usize = UARTE_BASE + 0x544;
+    const TXD_MAXCNT: usize = UARTE_BASE + 0x548;
+
+    static mut TX_BYTE: u8 = 0;
+
+    unsafe {
+        let buffer = core::ptr::addr_of_mut!(TX_BYTE);
+        buffer.write_volatile(byte);
+        core::ptr::write_volatile(EVENTS_ENDTX as *mut u32, 0);
+        core::ptr::write_volatile(TXD_PTR as *mut u32, buffer as u32);
+        core::ptr::write_volatile(TXD_MAXCNT as *mut u32, 1);
+        core::ptr::write_volatile(TASKS_STARTTX as *mut u32, 1);
+        while core::ptr::read_volatile(EVENTS_ENDTX as *const u32) == 0 {
+            // Busy wait - DMA transfer in progress
         }
-        // Add newline
-        core::ptr::write_volatile(UARTDR as *mut u32, b'\n' as u32);
+    }
+}
+
+/// Read one byte the console UART has received, if any
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub fn console_getc() -> Option<u8> {
+    const UARTDR: usize = 0x000;
+    const UARTFR: usize = 0x018;
+    const FR_RXFE: u32 = 1 << 4; // Receive FIFO empty
+    let uart_base = crate::board::active().uart_base;
+
+    unsafe {
+        if core::ptr::read_volatile((uart_base + UARTFR) as *const u32) & FR_RXFE != 0 {
+            return None;
+        }
+        Some(core::ptr::read_volatile((uart_base + UARTDR) as *const u32) as u8)
+    }
+}
+
+/// Read one byte the console UART has received, if any
+#[cfg(feature = "board_stm32f407")]
+pub fn console_getc() -> Option<u8> {
+    const USART_SR: usize = crate::board::BOARD.uart_base;
+    const USART_DR: usize = crate::board::BOARD.uart_base + 0x04;
+    const SR_RXNE: u32 = 1 << 5; // Read data register not empty
+    const SR_ORE: u32 = 1 << 3; // Overrun, cleared by SR then DR read
+
+    unsafe {
+        if core::ptr::read_volatile(USART_SR as *const u32) & (SR_RXNE | SR_ORE) == 0 {
+            return None;
+        }
+        Some(core::ptr::read_volatile(USART_DR as *const u32) as u8)
+    }
+}
+
+/// UARTE only receives by EasyDMA into a RAM buffer, which is not set up
+#[cfg(feature = "board_nrf52840")]
+pub fn console_getc() -> Option<u8> {
+    None
+}
+
+/// External IRQ number of the console UART
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub const CONSOLE_IRQN: u32 = 5; // LM3S6965 UART0
+#[cfg(feature = "board_stm32f407")]
+pub const CONSOLE_IRQN: u32 = 38; // USART2
+
+/// External IRQ number of the debug UART
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub const DEBUG_UART_IRQN: Option<u32> = Some(6); // LM3S6965 UART1
+#[cfg(feature = "board_stm32f407")]
+pub const DEBUG_UART_IRQN: Option<u32> = Some(39); // USART3
+#[cfg(feature = "board_nrf52840")]
+pub const DEBUG_UART_IRQN: Option<u32> = None;
+
+/// Unmask the console receive interrupt in the UART and the NVIC
+#[cfg(not(feature = "board_nrf52840"))]
+pub fn console_rx_interrupt_enable() -> bool {
+    const NVIC_ISER: usize = 0xE000E100;
+
+    unsafe {
+        #[cfg(not(feature = "board_stm32f407"))]
+        {
+            const UARTIMSC: usize = 0x038;
+            const IM_RX: u32 = 1 << 4; // FIFO level reached
+            const IM_RT: u32 = 1 << 6; // Receive timeout, for the bytes below it
+            let imsc = (crate::board::active().uart_base + UARTIMSC) as *mut u32;
+            imsc.write_volatile(imsc.read_volatile() | IM_RX | IM_RT);
+        }
+
+        #[cfg(feature = "board_stm32f407")]
+        {
+            const USART_CR1: usize = crate::board::BOARD.uart_base + 0x0C;
+            const CR1_RXNEIE: u32 = 1 << 5;
+            let cr1 = USART_CR1 as *mut u32;
+            cr1.write_volatile(cr1.read_volatile() | CR1_RXNEIE);
+        }
+
+        let iser = (NVIC_ISER + 4 * (CONSOLE_IRQN as usize / 32)) as *mut u32;
+        iser.write_volatile(1 << (CONSOLE_IRQN % 32));
+    }
+    true
+}
+
+#[cfg(feature = "board_nrf52840")]
+pub fn console_rx_interrupt_enable() -> bool {
+    false
+}
+
+/// Sleep until an interrupt is pending, optionally with SLEEPDEEP set
+///
+/// What SLEEPDEEP stops is vendor-defined: on nRF52 the HF clock (RTC and
+/// GPIOTE keep running), on STM32 it selects Stop mode with the PWR
+/// defaults. WFI returns on a pending interrupt even with PRIMASK set.
+pub fn enter_sleep(deep: bool) {
+    const SCB_SCR: usize = 0xE000ED10;
+    const SCR_SLEEPDEEP: u32 = 1 << 2;
+
+    unsafe {
+        let scr = core::ptr::read_volatile(SCB_SCR as *const u32);
+        let sleep_scr = if deep { scr | SCR_SLEEPDEEP } else { scr & !SCR_SLEEPDEEP };
+        core::ptr::write_volatile(SCB_SCR as *mut u32, sleep_scr);
+        core::arch::asm!("dsb", "wfi", "isb", options(nostack));
+        core::ptr::write_volatile(SCB_SCR as *mut u32, scr & !SCR_SLEEPDEEP);
     }
 }
 
@@ -217,6 +1148,77 @@ pub fn yield_cpu() {
     }
 }
 
+/// Registers in `RegisterDump`, in report order
+pub const REGISTER_NAMES: [&str; 6] = ["sp", "lr", "ipsr", "control", "primask", "cfsr"];
+
+/// Capture SP, LR and the exception/fault state registers
+#[inline(always)]
+pub fn capture_registers() -> super::RegisterDump {
+    const SCB_CFSR: usize = 0xE000ED28;
+
+    let (sp, lr, ipsr, control, primask): (u32, u32, u32, u32, u32);
+    unsafe {
+        core::arch::asm!(
+            "mov {sp}, sp",
+            "mov {lr}, lr",
+            "mrs {ipsr}, ipsr",
+            "mrs {control}, control",
+            "mrs {primask}, primask",
+            sp = out(reg) sp,
+            lr = out(reg) lr,
+            ipsr = out(reg) ipsr,
+            control = out(reg) control,
+            primask = out(reg) primask,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    let cfsr = unsafe { core::ptr::read_volatile(SCB_CFSR as *const u32) };
+
+    super::RegisterDump::from_values([sp, lr, ipsr, control, primask, cfsr])
+}
+
+/// Request a system reset through SCB AIRCR.SYSRESETREQ
+pub fn reset() -> ! {
+    const SCB_AIRCR: usize = 0xE000ED0C;
+    const AIRCR_VECTKEY: u32 = 0x05FA << 16;
+    const AIRCR_SYSRESETREQ: u32 = 1 << 2;
+
+    unsafe {
+        core::arch::asm!("dsb", options(nostack));
+        let aircr = core::ptr::read_volatile(SCB_AIRCR as *const u32) & 0x0700; // keep PRIGROUP
+        core::ptr::write_volatile(SCB_AIRCR as *mut u32, AIRCR_VECTKEY | aircr | AIRCR_SYSRESETREQ);
+        core::arch::asm!("dsb", options(nostack));
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Leave QEMU through semihosting SYS_EXIT
+#[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
+pub fn qemu_exit(code: u32) -> ! {
+    semihosting_exit(code)
+}
+
+/// Report `code` to the semihosting host (QEMU or a debug probe) and halt
+///
+/// Only call this with a host attached: on a bare board the BKPT locks up.
+#[allow(dead_code)]
+pub fn semihosting_exit(code: u32) -> ! {
+    use cortex_m_semihosting::debug;
+
+    // ARM semihosting only distinguishes success from failure
+    debug::exit(if code == 0 { debug::EXIT_SUCCESS } else { debug::EXIT_FAILURE });
+    // A probe that ignores SYS_EXIT resumes here
+    shutdown()
+}
+
+/// Real boards have no semihosting host: a BKPT would lock up, so halt
+#[cfg(any(feature = "board_stm32f407", feature = "board_nrf52840"))]
+pub fn qemu_exit(_code: u32) -> ! {
+    shutdown()
+}
+
 /// Shutdown system
 #[allow(dead_code)]
 pub fn shutdown() -> ! {
@@ -1,10 +1,22 @@
 //! Architecture abstraction layer for multi-platform support
 //! Provides unified interface for ARM and RISC-V architectures
 
-use core::sync::atomic::{AtomicBool, Ordering};
+/// Interrupt mask of a host build without the simulator, which has no
+/// core to read it from
+#[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+static HOST_MASKED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
 
-// Interrupt state for critical sections
-static INTERRUPTS_ENABLED: AtomicBool = AtomicBool::new(true);
+/// Atomic types used by the kernel
+///
+/// Cores without native read-modify-write atomics (RV32IMC, e.g. ESP32-C3)
+/// get them from `portable-atomic`, implemented with critical sections.
+pub mod atomic {
+    #[cfg(feature = "portable-atomic")]
+    pub use portable_atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+    
+    #[cfg(not(feature = "portable-atomic"))]
+    pub use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+}
 
 // Import architecture-specific modules
 #[cfg(any(feature = "arm", target_arch = "arm"))]
@@ -13,6 +25,12 @@ pub mod arm;
 #[cfg(any(feature = "riscv", target_arch = "riscv32"))]
 pub mod riscv;
 
+#[cfg(feature = "sim")]
+pub mod sim;
+
+#[cfg(all(feature = "sim", any(feature = "arm", feature = "riscv", feature = "gdb_stub")))]
+compile_error!("`sim` runs on the host; build it without `arm`, `riscv`, a board or `gdb_stub`");
+
 /// Memory layout trait for architecture-specific configurations
 #[allow(dead_code)]
 pub trait MemoryLayout {
@@ -40,6 +58,232 @@ pub trait Architecture {
     type Init: ArchInit;
 }
 
+/// Identification of the CPU core the kernel is running on
+///
+/// The simulator fills in only `name`.
+#[cfg_attr(feature = "sim", allow(dead_code))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CpuInfo {
+    /// ARM: CPUID implementer / RISC-V: mvendorid
+    pub vendor_id: u32,
+    /// ARM: CPUID PARTNO / RISC-V: marchid
+    pub arch_id: u32,
+    /// ARM: variant << 4 | revision / RISC-V: mimpid
+    pub revision: u32,
+    /// ARM: CPUID architecture field / RISC-V: misa extension bits
+    pub features: u32,
+    /// Human-readable core name
+    pub name: &'static str,
+}
+
+impl core::fmt::Display for CpuInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        #[cfg(feature = "riscv")]
+        {
+            write!(f, "{}", self.name)?;
+            for bit in 0..26 {
+                if self.features & (1 << bit) != 0 {
+                    write!(f, "{}", (b'A' + bit as u8) as char)?;
+                }
+            }
+            write!(
+                f,
+                " (vendor 0x{:x}, arch 0x{:x}, impl 0x{:x})",
+                self.vendor_id, self.arch_id, self.revision
+            )
+        }
+        
+        #[cfg(feature = "sim")]
+        {
+            write!(f, "{}", self.name)
+        }
+
+        #[cfg(not(any(feature = "riscv", feature = "sim")))]
+        {
+            write!(
+                f,
+                "{} r{}p{} (implementer 0x{:02x}, part 0x{:03x}, arch 0x{:x})",
+                self.name,
+                self.revision >> 4,
+                self.revision & 0xF,
+                self.vendor_id,
+                self.arch_id,
+                self.features
+            )
+        }
+    }
+}
+
+/// Read CPU identification registers
+#[allow(dead_code)]
+pub fn cpu_info() -> CpuInfo {
+    #[cfg(feature = "arm")]
+    {
+        arm::cpu_info()
+    }
+    
+    #[cfg(feature = "riscv")]
+    {
+        riscv::cpu_info()
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        sim::cpu_info()
+    }
+    
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        CpuInfo {
+            name: "Host",
+            ..CpuInfo::default()
+        }
+    }
+}
+
+/// Why the chip last came out of reset
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    /// Power applied (includes brownout where the two are indistinguishable)
+    PowerOn = 0,
+    /// External reset pin
+    External = 1,
+    /// Supply dropped below the brownout threshold
+    Brownout = 2,
+    /// Watchdog expired
+    Watchdog = 3,
+    /// Software-requested reset (SYSRESETREQ or equivalent)
+    Software = 4,
+    /// Core locked up (double fault)
+    Lockup = 5,
+    /// Woken from a power-off or deep-sleep state
+    Wakeup = 6,
+    /// Not reported by this board
+    Unknown = 7,
+}
+
+impl ResetReason {
+    pub const fn name(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "power-on",
+            ResetReason::External => "external pin",
+            ResetReason::Brownout => "brownout",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::Software => "software",
+            ResetReason::Lockup => "lockup",
+            ResetReason::Wakeup => "wakeup",
+            ResetReason::Unknown => "unknown",
+        }
+    }
+
+    const fn from_raw(raw: u32) -> Self {
+        match raw {
+            0 => ResetReason::PowerOn,
+            1 => ResetReason::External,
+            2 => ResetReason::Brownout,
+            3 => ResetReason::Watchdog,
+            4 => ResetReason::Software,
+            5 => ResetReason::Lockup,
+            6 => ResetReason::Wakeup,
+            _ => ResetReason::Unknown,
+        }
+    }
+}
+
+const RESET_REASON_UNREAD: u32 = u32::MAX;
+static RESET_REASON: atomic::AtomicU32 = atomic::AtomicU32::new(RESET_REASON_UNREAD);
+
+/// Reason for the last reset
+///
+/// The first call reads and clears the hardware flags (they accumulate
+/// across resets on most parts); later calls return the latched value.
+pub fn reset_reason() -> ResetReason {
+    let raw = RESET_REASON.load(atomic::Ordering::Relaxed);
+    if raw != RESET_REASON_UNREAD {
+        return ResetReason::from_raw(raw);
+    }
+
+    #[cfg(feature = "arm")]
+    let reason = arm::read_reset_reason();
+
+    #[cfg(feature = "riscv")]
+    let reason = riscv::read_reset_reason();
+
+    #[cfg(feature = "sim")]
+    let reason = ResetReason::PowerOn;
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    let reason = ResetReason::Unknown;
+
+    RESET_REASON.store(reason as u32, atomic::Ordering::Relaxed);
+    reason
+}
+
+/// Core registers captured for crash reports
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RegisterDump {
+    /// (name, value) pairs in report order
+    pub regs: [(&'static str, u32); 6],
+}
+
+/// Registers in `RegisterDump`, in report order
+#[cfg(feature = "arm")]
+pub use arm::REGISTER_NAMES;
+#[cfg(feature = "riscv")]
+pub use riscv::REGISTER_NAMES;
+#[cfg(feature = "sim")]
+pub use sim::REGISTER_NAMES;
+#[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+pub const REGISTER_NAMES: [&str; 6] = ["r0", "r1", "r2", "r3", "r4", "r5"];
+
+impl RegisterDump {
+    /// Pair raw values (e.g. from a saved crash record) with their names
+    pub fn from_values(values: [u32; 6]) -> Self {
+        let mut regs = [("", 0); 6];
+        for (slot, (name, value)) in regs.iter_mut().zip(REGISTER_NAMES.iter().zip(values)) {
+            *slot = (*name, value);
+        }
+        Self { regs }
+    }
+
+    /// Raw values in report order
+    pub fn values(&self) -> [u32; 6] {
+        self.regs.map(|(_, value)| value)
+    }
+}
+
+impl core::fmt::Display for RegisterDump {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, (name, value)) in self.regs.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}=0x{:08x}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Capture stack pointer, return address and fault/status registers
+#[allow(dead_code)]
+#[inline(always)]
+pub fn capture_registers() -> RegisterDump {
+    #[cfg(feature = "arm")]
+    {
+        arm::capture_registers()
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        riscv::capture_registers()
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    {
+        RegisterDump::default()
+    }
+}
+
 /// Early println for debugging (before full system init)
 #[allow(dead_code)]
 pub fn early_println(msg: &str) {
@@ -48,19 +292,20 @@ pub fn early_println(msg: &str) {
     
     #[cfg(feature = "riscv")]
     riscv::early_println(msg);
+
+    #[cfg(feature = "sim")]
+    sim::early_println(msg);
     
-    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
     {
-        // Host platform - use standard output
-        println!("{}", msg);
+        // Host build without the simulator has no console
+        let _ = msg;
     }
 }
 
 /// Disable interrupts for critical sections
 #[allow(dead_code)]
 pub fn disable_interrupts() {
-    INTERRUPTS_ENABLED.store(false, Ordering::SeqCst);
-    
     #[cfg(feature = "arm")]
     unsafe {
         core::arch::asm!("cpsid i");
@@ -70,13 +315,17 @@ pub fn disable_interrupts() {
     unsafe {
         core::arch::asm!("csrci mstatus, 8");
     }
+
+    #[cfg(feature = "sim")]
+    sim::disable_interrupts();
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    HOST_MASKED.store(true, core::sync::atomic::Ordering::SeqCst);
 }
 
 /// Enable interrupts after critical sections
 #[allow(dead_code)]
 pub fn enable_interrupts() {
-    INTERRUPTS_ENABLED.store(true, Ordering::SeqCst);
-    
     #[cfg(feature = "arm")]
     unsafe {
         core::arch::asm!("cpsie i");
@@ -86,6 +335,12 @@ pub fn enable_interrupts() {
     unsafe {
         core::arch::asm!("csrsi mstatus, 8");
     }
+
+    #[cfg(feature = "sim")]
+    sim::enable_interrupts();
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    HOST_MASKED.store(false, core::sync::atomic::Ordering::SeqCst);
 }
 
 /// Yield CPU to other tasks (cooperative multitasking)
@@ -96,6 +351,9 @@ pub fn arch_yield() {
     
     #[cfg(feature = "riscv")]
     riscv::yield_cpu();
+
+    #[cfg(feature = "sim")]
+    sim::yield_cpu();
 }
 
 /// Architecture-agnostic wait for interrupt
@@ -113,16 +371,336 @@ pub fn wait_for_interrupt() {
         core::arch::asm!("wfi");
     }
     
-    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    #[cfg(feature = "sim")]
+    sim::yield_cpu();
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
     {
         // Host platform - do nothing (for testing)
     }
 }
 
-/// Get current interrupt state
-    #[allow(dead_code)]
+/// Write raw bytes to the console UART
+///
+/// Returns once the last byte is in the transmitter; the console's
+/// suspend hook drains it before deep sleep stops the baud clock
+/// (`drivers::uart`).
+#[allow(dead_code)]
+pub fn console_write(bytes: &[u8]) {
+    #[cfg(feature = "arm")]
+    arm::console_write(bytes);
+
+    #[cfg(feature = "riscv")]
+    riscv::console_write(bytes);
+
+    #[cfg(feature = "sim")]
+    sim::console_write(bytes);
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    let _ = bytes;
+}
+
+/// Wait until the console UART has finished transmitting
+#[allow(dead_code)]
+pub fn console_flush() {
+    #[cfg(feature = "arm")]
+    arm::console_flush();
+
+    #[cfg(feature = "riscv")]
+    riscv::console_flush();
+
+    #[cfg(feature = "sim")]
+    sim::console_flush();
+}
+
+/// Read one byte the console UART has received, if any
+#[allow(dead_code)]
+pub fn console_read() -> Option<u8> {
+    #[cfg(feature = "arm")]
+    {
+        arm::console_getc()
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        riscv::console_getc()
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        sim::console_getc()
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        None
+    }
+}
+
+/// Unmask the console receive interrupt; false if the board polls instead
+#[allow(dead_code)]
+pub fn console_rx_interrupt_enable() -> bool {
+    #[cfg(feature = "arm")]
+    {
+        arm::console_rx_interrupt_enable()
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        riscv::console_rx_interrupt_enable()
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        sim::console_rx_interrupt_enable()
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        false
+    }
+}
+
+/// Unmask the debug UART's receive interrupt at the interrupt controller
+#[allow(dead_code)]
+pub fn debug_uart_interrupt_enable() {
+    #[cfg(feature = "arm")]
+    arm::debug_uart_interrupt_enable();
+
+    #[cfg(feature = "riscv")]
+    riscv::debug_uart_interrupt_enable();
+}
+
+/// Breakpoints, halting and register access for the GDB stub
+#[cfg(feature = "gdb_stub")]
+pub mod debug {
+    #[cfg(feature = "arm")]
+    pub use super::arm::debug::*;
+
+    #[cfg(feature = "riscv")]
+    pub use super::riscv::debug::*;
+}
+
+/// Bounds (start, end) of the kernel's code, where every task entry lies
+pub fn code_region() -> Option<(usize, usize)> {
+    #[cfg(feature = "arm")]
+    {
+        Some(arm::code_bounds())
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        Some(riscv::code_bounds())
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        Some(sim::code_bounds())
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        None
+    }
+}
+
+/// RAM (start, end) free to load data into: past `.heap`, below the stack
+#[allow(dead_code)]
+pub fn free_ram() -> Option<(usize, usize)> {
+    #[cfg(feature = "arm")]
+    {
+        Some(arm::free_ram_bounds())
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        Some(riscv::free_ram_bounds())
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    {
+        None
+    }
+}
+
+/// Bounds (bottom, top) of the stack the scheduler loop and tasks run on
+#[allow(dead_code)]
+pub fn thread_stack() -> Option<(usize, usize)> {
+    #[cfg(feature = "arm")]
+    {
+        Some(arm::process_stack_bounds())
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        Some(riscv::stack_bounds())
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    {
+        None
+    }
+}
+
+/// Enter a low-power mode until the next wakeup interrupt
+///
+/// Call with interrupts disabled; returns with them still disabled.
+/// `Stop` is deep sleep to the core; the board's power hooks set up what
+/// makes it deeper.
+pub fn enter_low_power(mode: crate::power::PowerMode) {
+    #[allow(unused_variables)]
+    let deep = mode >= crate::power::PowerMode::DeepSleep;
+
+    #[cfg(feature = "arm")]
+    arm::enter_sleep(deep);
+
+    #[cfg(feature = "riscv")]
+    riscv::enter_sleep(deep);
+
+    #[cfg(feature = "sim")]
+    sim::enter_sleep(deep);
+}
+
+/// Enable the free-running cycle counter used for timestamps
+#[allow(dead_code)]
+pub fn init_cycle_counter() {
+    #[cfg(feature = "arm")]
+    arm::init_cycle_counter();
+    
+    #[cfg(feature = "riscv")]
+    riscv::init_cycle_counter();
+
+    #[cfg(feature = "sim")]
+    sim::init_cycle_counter();
+}
+
+/// Read the free-running cycle counter (wraps at 32 bits)
+#[allow(dead_code)]
+pub fn cycle_counter() -> u32 {
+    #[cfg(feature = "arm")]
+    {
+        arm::cycle_counter()
+    }
+    
+    #[cfg(feature = "riscv")]
+    {
+        riscv::cycle_counter()
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        sim::cycle_counter()
+    }
+    
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        // Host platform - no cycle counter
+        0
+    }
+}
+
+/// Whether interrupts are unmasked, read from the core itself
+///
+/// False inside a RISC-V trap handler (the hart clears mstatus.MIE on
+/// entry) and in a section masked by `disable_interrupts`.
 pub fn interrupts_enabled() -> bool {
-    INTERRUPTS_ENABLED.load(Ordering::SeqCst)
+    #[cfg(feature = "arm")]
+    {
+        arm::interrupts_enabled()
+    }
+
+    #[cfg(feature = "riscv")]
+    {
+        riscv::interrupts_enabled()
+    }
+
+    #[cfg(feature = "sim")]
+    {
+        sim::interrupts_enabled()
+    }
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        !HOST_MASKED.load(core::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+/// Mask interrupts, returning whether they were on, for `restore`
+///
+/// Only the outermost section is timed by `instrumentation`, so nested
+/// sections do not restart the measured window.
+#[inline]
+pub fn mask() -> bool {
+    let enabled = interrupts_enabled();
+    disable_interrupts();
+    if enabled {
+        crate::instrumentation::critical_enter();
+    }
+    enabled
+}
+
+/// Undo the `mask` that returned `enabled`
+#[inline]
+pub fn restore(enabled: bool) {
+    if enabled {
+        crate::instrumentation::critical_exit();
+        enable_interrupts();
+    }
+}
+
+/// Run `f` with interrupts masked, leaving them as they were found
+///
+/// Nests, and works in interrupt handlers and with interrupts already
+/// off; every kernel critical section goes through here.
+#[inline]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = mask();
+    let result = f();
+    restore(enabled);
+    result
+}
+
+/// Reset the whole chip
+#[allow(dead_code)]
+pub fn arch_reset() -> ! {
+    disable_interrupts();
+
+    #[cfg(feature = "arm")]
+    arm::reset();
+
+    #[cfg(feature = "riscv")]
+    riscv::reset();
+
+    #[cfg(feature = "sim")]
+    sim::reset();
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Exit QEMU with `code` (0 = success); halts on hardware
+#[allow(dead_code)]
+pub fn qemu_exit(code: u32) -> ! {
+    disable_interrupts();
+
+    #[cfg(feature = "arm")]
+    arm::qemu_exit(code);
+
+    #[cfg(feature = "riscv")]
+    riscv::qemu_exit(code);
+
+    #[cfg(feature = "sim")]
+    sim::qemu_exit(code);
+
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
+    {
+        let _ = code;
+        loop {
+            core::hint::spin_loop();
+        }
+    }
 }
 
 /// Architecture-specific shutdown
@@ -135,8 +713,11 @@ pub fn arch_shutdown() -> ! {
     
     #[cfg(feature = "riscv")]
     riscv::shutdown();
+
+    #[cfg(feature = "sim")]
+    sim::shutdown();
     
-    #[cfg(not(any(feature = "arm", feature = "riscv")))]
+    #[cfg(not(any(feature = "arm", feature = "riscv", feature = "sim")))]
     loop {
         core::hint::spin_loop();
     }
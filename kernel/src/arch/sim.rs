@@ -0,0 +1,308 @@
+//! Host simulator
+//! The kernel as a std process, with threads for interrupts (`sim`)
+//!
+//! The scheduler loop runs on the process's main thread. Interrupts come
+//! from threads of their own: one raises the tick, another reads stdin
+//! and raises the console receive interrupt, and whichever gets there
+//! first runs the pending handlers. Masking interrupts takes `MASK`, a
+//! lock the interrupt threads also take around their handlers, so code
+//! that runs with interrupts off never sees a handler run beside it, as
+//! on a single core. A thread that already holds the mask may mask again;
+//! handlers return with it held, like the riscv trap code.
+//!
+//! Time is virtual: the cycle counter runs at `CPU_HZ` and follows the
+//! host clock while the kernel is busy. With `KARATOS_SIM_FAST` set in the
+//! environment, a core asleep in `enter_sleep` skips straight to the next
+//! tick instead of waiting for it, so idle stretches cost no host time and
+//! a scripted run goes as fast as the kernel can work.
+//!
+//! The console is stdin and stdout. The shell echoes and edits lines
+//! itself, so run it from a terminal in cbreak mode (`stty -icanon -echo`,
+//! `stty sane` after) or feed it from a pipe. Reset exits the process with
+//! `RESET_EXIT_CODE` for a wrapper script to restart it.
+//!
+//! With `trace`, `KARATOS_SIM_REPLAY` replaces the tick thread and stdin
+//! with a recorded trace (see `kernel::trace`).
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::arch::{ArchInit, CpuInfo};
+
+/// Rate of the virtual cycle counter
+pub const CPU_HZ: u32 = 100_000_000;
+
+/// Exit status of a simulated chip reset
+pub const RESET_EXIT_CODE: i32 = 3;
+
+/// Registers in `RegisterDump`; the simulator has none to capture
+pub const REGISTER_NAMES: [&str; 6] = ["r0", "r1", "r2", "r3", "r4", "r5"];
+
+const IRQ_TICK: u32 = 1 << 0;
+const IRQ_CONSOLE_RX: u32 = 1 << 1;
+
+/// Thread holding the interrupt mask, `UNMASKED` when none does
+static MASK: AtomicUsize = AtomicUsize::new(UNMASKED);
+const UNMASKED: usize = 0;
+
+/// Numbers threads for `MASK`, from 1
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    /// Running interrupt handlers
+    static IN_HANDLER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Interrupts raised and not yet handled
+static PENDING: AtomicU32 = AtomicU32::new(0);
+static CONSOLE_RX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The main thread is in `enter_sleep`
+static SLEEPING: AtomicBool = AtomicBool::new(false);
+
+/// Signalled when an interrupt is raised or the core goes to sleep
+static WAKE: (Mutex<()>, Condvar) = (Mutex::new(()), Condvar::new());
+
+/// Cycles skipped while the core slept, on top of the host clock
+static SKIPPED: AtomicU64 = AtomicU64::new(0);
+static START: OnceLock<Instant> = OnceLock::new();
+
+static RX: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+
+fn thread_id() -> usize {
+    THREAD.with(|id| *id)
+}
+
+/// Simulator architecture implementation
+pub struct SimArch;
+
+impl ArchInit for SimArch {
+    fn init() {
+        init_cycle_counter();
+        Self::irq_init();
+        Self::setup_memory_protection();
+    }
+
+    fn irq_init() {}
+
+    fn setup_memory_protection() {}
+}
+
+pub fn disable_interrupts() {
+    let me = thread_id();
+    if MASK.load(Ordering::Acquire) == me {
+        return;
+    }
+    while MASK.compare_exchange_weak(UNMASKED, me, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        std::thread::yield_now();
+    }
+}
+
+pub fn enable_interrupts() {
+    // Handlers return with the mask still held
+    if IN_HANDLER.with(Cell::get) {
+        return;
+    }
+    let me = thread_id();
+    let _ = MASK.compare_exchange(me, UNMASKED, Ordering::Release, Ordering::Relaxed);
+}
+
+pub fn interrupts_enabled() -> bool {
+    !IN_HANDLER.with(Cell::get) && MASK.load(Ordering::Acquire) != thread_id()
+}
+
+/// Mark `irq` pending, wake a sleeping core, then run the pending
+/// handlers once the core has interrupts enabled
+fn raise(irq: u32) {
+    PENDING.fetch_or(irq, Ordering::AcqRel);
+    {
+        let _guard = WAKE.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        WAKE.1.notify_all();
+    }
+
+    IN_HANDLER.with(|in_handler| in_handler.set(true));
+    let me = thread_id();
+    while MASK.compare_exchange_weak(UNMASKED, me, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        std::thread::yield_now();
+    }
+    let pending = PENDING.swap(0, Ordering::AcqRel);
+    if pending & IRQ_TICK != 0 {
+        crate::instrumentation::measure_isr(crate::kernel::time::tick);
+    }
+    if pending & IRQ_CONSOLE_RX != 0 && CONSOLE_RX_ENABLED.load(Ordering::Relaxed) {
+        crate::instrumentation::measure_isr(crate::drivers::console_rx::on_interrupt);
+    }
+    MASK.store(UNMASKED, Ordering::Release);
+    IN_HANDLER.with(|in_handler| in_handler.set(false));
+}
+
+/// Wait on `WAKE` until `done`, for at most `timeout`
+fn wait_until(done: impl Fn() -> bool, timeout: Duration) {
+    let guard = WAKE.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _ = WAKE.1.wait_timeout_while(guard, timeout, |_| !done());
+}
+
+pub fn init_cycle_counter() {
+    START.get_or_init(Instant::now);
+}
+
+fn cycles() -> u64 {
+    let host_ns = START.get_or_init(Instant::now).elapsed().as_nanos() as u64;
+    host_ns * (CPU_HZ as u64 / 1_000_000) / 1000 + SKIPPED.load(Ordering::Acquire)
+}
+
+fn cycles_to_host(cycles: u64) -> Duration {
+    Duration::from_nanos(cycles * 1000 / (CPU_HZ as u64 / 1_000_000))
+}
+
+/// Virtual cycle counter (wraps at 32 bits)
+pub fn cycle_counter() -> u32 {
+    cycles() as u32
+}
+
+/// Start raising the tick at `tick_hz` on a thread of its own
+pub fn start_tick(tick_hz: u32) {
+    // A replay announces the ticks it recorded instead
+    #[cfg(feature = "trace")]
+    if crate::kernel::trace::replaying() {
+        crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+        return;
+    }
+    let period = (CPU_HZ / tick_hz.max(1)) as u64;
+    let fast = std::env::var_os("KARATOS_SIM_FAST").is_some();
+    let spawned = std::thread::Builder::new().name("sim-tick".into()).spawn(move || {
+        let mut due = cycles() + period;
+        loop {
+            loop {
+                let now = cycles();
+                if now >= due {
+                    break;
+                }
+                if fast && SLEEPING.load(Ordering::Acquire) {
+                    SKIPPED.fetch_add(due - now, Ordering::AcqRel);
+                    break;
+                }
+                wait_until(|| fast && SLEEPING.load(Ordering::Acquire), cycles_to_host(due - now));
+            }
+            due += period;
+            raise(IRQ_TICK);
+        }
+    });
+    if spawned.is_ok() {
+        crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+    }
+}
+
+/// Sleep until an interrupt is pending; called with interrupts masked
+pub fn enter_sleep(_deep: bool) {
+    // What woke the core in the recording is fed in at the next scheduling point
+    #[cfg(feature = "trace")]
+    if crate::kernel::trace::replaying() {
+        return;
+    }
+    SLEEPING.store(true, Ordering::Release);
+    {
+        let _guard = WAKE.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        WAKE.1.notify_all();
+    }
+    while PENDING.load(Ordering::Acquire) == 0 {
+        wait_until(|| PENDING.load(Ordering::Acquire) != 0, Duration::from_millis(100));
+    }
+    SLEEPING.store(false, Ordering::Release);
+}
+
+/// Issue system call `number`; there is no trap, so it is a plain call
+/// into the dispatcher
+#[allow(dead_code)]
+pub fn syscall(number: usize, args: [usize; 6]) -> usize {
+    crate::syscall::dispatch(number, args)
+}
+
+/// Bounds (start, end) of the process's code, from the symbols the host
+/// linker defines around the text segment
+pub fn code_bounds() -> (usize, usize) {
+    extern "C" {
+        static __executable_start: u8;
+        static etext: u8;
+    }
+
+    (core::ptr::addr_of!(__executable_start) as usize, core::ptr::addr_of!(etext) as usize)
+}
+
+/// Give the interrupt threads a turn
+pub fn yield_cpu() {
+    std::thread::yield_now();
+}
+
+pub fn cpu_info() -> CpuInfo {
+    CpuInfo {
+        name: "karatOS sim",
+        ..CpuInfo::default()
+    }
+}
+
+pub fn early_println(msg: &str) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(msg.as_bytes());
+    let _ = stdout.write_all(b"\n");
+    let _ = stdout.flush();
+}
+
+pub fn console_write(bytes: &[u8]) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(bytes);
+    let _ = stdout.flush();
+}
+
+pub fn console_flush() {
+    let _ = std::io::stdout().flush();
+}
+
+pub fn console_getc() -> Option<u8> {
+    RX.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop_front()
+}
+
+/// Start reading stdin on a thread of its own, raising the receive
+/// interrupt for each read
+pub fn console_rx_interrupt_enable() -> bool {
+    if CONSOLE_RX_ENABLED.swap(true, Ordering::AcqRel) {
+        return true;
+    }
+    // Console input is not in a trace, so a replay has none
+    #[cfg(feature = "trace")]
+    if crate::kernel::trace::replaying() {
+        return true;
+    }
+    let spawned = std::thread::Builder::new().name("sim-stdin".into()).spawn(|| {
+        let mut buffer = [0u8; 64];
+        let mut stdin = std::io::stdin();
+        // Ends at end of input; the console then stays quiet
+        while let Ok(read @ 1..) = stdin.read(&mut buffer) {
+            RX.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).extend(&buffer[..read]);
+            raise(IRQ_CONSOLE_RX);
+        }
+    });
+    if spawned.is_err() {
+        CONSOLE_RX_ENABLED.store(false, Ordering::Release);
+    }
+    spawned.is_ok()
+}
+
+pub fn reset() -> ! {
+    console_flush();
+    std::process::exit(RESET_EXIT_CODE)
+}
+
+pub fn qemu_exit(code: u32) -> ! {
+    console_flush();
+    std::process::exit(code as i32)
+}
+
+pub fn shutdown() -> ! {
+    qemu_exit(0)
+}
@@ -0,0 +1,127 @@
+//! Boot banner
+//! One consistent block identifying the build, CPU, board and memory map
+//!
+//! Printed right after board bring-up on every architecture, so boot logs
+//! from different targets can be compared line by line.
+
+use core::fmt::{self, Write};
+
+use crate::board::MemoryRegion;
+
+const RULE: &str = "============================================================";
+
+/// Cargo features reported in the banner
+const FEATURES: &[(&str, bool)] = &[
+    ("arm", cfg!(feature = "arm")),
+    ("riscv", cfg!(feature = "riscv")),
+    ("board_lm3s6965evb", cfg!(feature = "board_lm3s6965evb")),
+    ("board_qemu_virt", cfg!(feature = "board_qemu_virt")),
+    ("board_stm32f407", cfg!(feature = "board_stm32f407")),
+    ("board_nrf52840", cfg!(feature = "board_nrf52840")),
+    ("board_esp32c3", cfg!(feature = "board_esp32c3")),
+    ("board_hifive1", cfg!(feature = "board_hifive1")),
+    ("portable-atomic", cfg!(feature = "portable-atomic")),
+    ("sim", cfg!(feature = "sim")),
+    ("ktest", cfg!(feature = "ktest")),
+    ("ktest_semihosting", cfg!(feature = "ktest_semihosting")),
+    ("bench", cfg!(feature = "bench")),
+    ("trace", cfg!(feature = "trace")),
+    ("coverage", cfg!(feature = "coverage")),
+    ("fault_inject", cfg!(feature = "fault_inject")),
+    ("bringup", cfg!(feature = "bringup")),
+    ("introspect", cfg!(feature = "introspect")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("instrumentation", cfg!(feature = "instrumentation")),
+    ("tasks_16", cfg!(feature = "tasks_16")),
+    ("tasks_32", cfg!(feature = "tasks_32")),
+    ("events_32", cfg!(feature = "events_32")),
+    ("events_64", cfg!(feature = "events_64")),
+    ("tick_100hz", cfg!(feature = "tick_100hz")),
+    ("tick_10khz", cfg!(feature = "tick_10khz")),
+    ("logger_small", cfg!(feature = "logger_small")),
+    ("logger_large", cfg!(feature = "logger_large")),
+    ("log_max_error", cfg!(feature = "log_max_error")),
+    ("log_max_warn", cfg!(feature = "log_max_warn")),
+    ("log_max_info", cfg!(feature = "log_max_info")),
+    ("log_max_debug", cfg!(feature = "log_max_debug")),
+    ("log_facade", cfg!(feature = "log_facade")),
+    ("log_defmt", cfg!(feature = "log_defmt")),
+    ("log_rtt", cfg!(feature = "log_rtt")),
+    ("log_drain", cfg!(feature = "log_drain")),
+    ("log_binary", cfg!(feature = "log_binary")),
+    ("embassy_time", cfg!(feature = "embassy_time")),
+    ("hal_async", cfg!(feature = "hal_async")),
+    ("async_tasks", cfg!(feature = "async_tasks")),
+    ("executor_embassy", cfg!(feature = "executor_embassy")),
+    ("critical_section_impl", cfg!(feature = "critical_section_impl")),
+    ("embedded_io", cfg!(feature = "embedded_io")),
+    ("net", cfg!(feature = "net")),
+    ("timers", cfg!(feature = "timers")),
+    ("tick_dynamic", cfg!(feature = "tick_dynamic")),
+    ("shell", cfg!(feature = "shell")),
+    ("shell_color", cfg!(feature = "shell_color")),
+    ("shell_login", cfg!(feature = "shell_login")),
+    ("gdb_stub", cfg!(feature = "gdb_stub")),
+];
+
+/// Print the boot banner on the console
+pub fn print() {
+    let board = crate::board::descriptor();
+
+    crate::arch::early_println(RULE);
+    line(format_args!(" {}", crate::kernel::version()));
+    crate::arch::early_println(RULE);
+    field("CPU", format_args!("{}", crate::arch::cpu_info()));
+    field("Board", format_args!("{} (config: {})", board.name, crate::board::source().name()));
+    field("Reset", format_args!("{}", crate::arch::reset_reason().name()));
+    field("Flash", format_args!("{}", Region(board.flash)));
+    field("RAM", format_args!("{}", Region(board.ram)));
+    field("Heap", format_args!("{} KiB", board.heap_size / 1024));
+    field("Console", format_args!("{} @ 0x{:08x}", board.uart_kind.name(), board.uart_base));
+    let mode = if crate::kernel::time::DYNAMIC_TICK { "dynamic" } else { "periodic" };
+    match board.timer_base {
+        Some(base) => field("Tick", format_args!("{} @ 0x{:08x}, {}", board.timer_kind.name(), base, mode)),
+        None => field("Tick", format_args!("{}, {}", board.timer_kind.name(), mode)),
+    }
+    field("Features", format_args!("{}", Features));
+    crate::arch::early_println(RULE);
+}
+
+fn field(name: &str, value: fmt::Arguments) {
+    line(format_args!(" {:<9}: {}", name, value));
+}
+
+fn line(args: fmt::Arguments) {
+    let mut text = heapless::String::<128>::new();
+    let _ = text.write_fmt(args);
+    crate::arch::early_println(&text);
+}
+
+/// Memory region as "start - end (size)"
+struct Region(MemoryRegion);
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.size == 0 {
+            return write!(f, "none");
+        }
+        write!(f, "0x{:08x} - 0x{:08x} ({} KiB)", self.0.start, self.0.end(), self.0.size / 1024)
+    }
+}
+
+/// Enabled entries of `FEATURES`, space separated
+struct Features;
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut enabled = FEATURES.iter().filter(|(_, on)| *on).map(|(name, _)| name);
+        match enabled.next() {
+            Some(first) => write!(f, "{}", first)?,
+            None => return write!(f, "none"),
+        }
+        for name in enabled {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
@@ -1,139 +1,573 @@
 //! Board Configuration Module
 //! Provides board-specific configurations and initialization
+//!
+//! Board data lives in `descriptors`; each board implements `Board` to pair
+//! its descriptor with bring-up code, and `CurrentBoard` is picked from the
+//! enabled `board_*` feature (QEMU machines when none is set). `detect`
+//! may then refine that descriptor at boot from what the hardware reports.
 
-use crate::config::BoardConfig;
-use crate::drivers::DeviceConfig;
+#[allow(dead_code)]
+mod descriptors;
+mod detect;
+#[cfg(any(target_arch = "riscv32", test))]
+mod fdt;
+
+#[allow(unused_imports)]
+pub use detect::{active, detect, source, ConfigSource};
+
+#[allow(unused_imports)]
+pub use descriptors::{
+    Arch, BoardDescriptor, LinkerLayout, MemoryRegion, TimerKind, UartKind, BOARDS,
+};
+
+/// Board support: static description plus initialization
+pub trait Board {
+    const DESCRIPTOR: BoardDescriptor;
+
+    /// Initialize board-specific features (clocks, pins, tick source)
+    fn init() {}
+}
+
+#[cfg(all(target_arch = "arm", feature = "board_stm32f407"))]
+type CurrentBoard = stm32f407::Stm32f407;
+
+#[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
+type CurrentBoard = nrf52840::Nrf52840;
+
+#[cfg(all(target_arch = "arm",
+          not(any(feature = "board_stm32f407", feature = "board_nrf52840"))))]
+type CurrentBoard = Lm3s6965evb;
+
+#[cfg(all(target_arch = "riscv32", feature = "board_esp32c3"))]
+type CurrentBoard = esp32c3::Esp32c3;
+
+#[cfg(all(target_arch = "riscv32", feature = "board_hifive1"))]
+type CurrentBoard = hifive1::Hifive1;
+
+#[cfg(all(target_arch = "riscv32",
+          not(any(feature = "board_esp32c3", feature = "board_hifive1"))))]
+type CurrentBoard = QemuVirt;
+
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
+type CurrentBoard = HostBoard;
+
+/// Descriptor of the board this kernel was built for
+pub const BOARD: BoardDescriptor = CurrentBoard::DESCRIPTOR;
 
 /// Initialize board-specific features (clocks, power management, etc.)
 pub fn init_board() {
-    // Board-specific initialization
-    #[cfg(all(target_arch = "arm", feature = "board_lm3s6965evb"))]
-    init_lm3s6965evb();
-    
-    #[cfg(all(target_arch = "riscv32", feature = "board_qemu_virt"))]
-    init_qemu_virt_riscv();
-    
-    // Default board initialization if no specific board is configured
-    #[cfg(not(any(feature = "board_lm3s6965evb", feature = "board_qemu_virt")))]
-    init_default_board();
+    CurrentBoard::init();
 }
 
-/// Get board-specific configuration
-pub fn get_board_config() -> BoardConfig {
-    #[cfg(all(target_arch = "arm", feature = "board_lm3s6965evb"))]
-    {
-        get_lm3s6965evb_config()
+/// Get the descriptor the kernel is running with (after detection)
+#[allow(dead_code)]
+pub fn descriptor() -> &'static BoardDescriptor {
+    active()
+}
+
+/// LM3S6965EVB board (QEMU `lm3s6965evb` machine)
+#[cfg(target_arch = "arm")]
+#[allow(dead_code)]
+pub struct Lm3s6965evb;
+
+#[cfg(target_arch = "arm")]
+impl Board for Lm3s6965evb {
+    const DESCRIPTOR: BoardDescriptor = descriptors::LM3S6965EVB;
+
+    // QEMU starts with the clocks and UART0 usable; only the tick is set up
+    #[cfg(not(feature = "board_nrf52840"))]
+    fn init() {
+        // 12 MHz main oscillator, PLL off out of reset
+        const CORE_HZ: u32 = 12_000_000;
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        crate::drivers::systick::init(CORE_HZ, tick_hz);
     }
-    
-    #[cfg(all(target_arch = "riscv32", feature = "board_qemu_virt"))]
-    {
-        get_qemu_virt_riscv_config()
+}
+
+/// STM32F4 Discovery (STM32F407VG) board configuration
+///
+/// Clocks: 8 MHz HSE -> PLL (M=8, N=336, P=2, Q=7) -> 168 MHz SYSCLK,
+/// AHB 168 MHz, APB1 42 MHz, APB2 84 MHz. Console on USART2 (PA2/PA3),
+/// GDB stub on USART3 (PB10/PB11) with `gdb_stub`.
+#[cfg(all(target_arch = "arm", feature = "board_stm32f407"))]
+mod stm32f407 {
+    pub const RCC_BASE: usize = 0x40023800;
+    pub const RCC_CR: usize = RCC_BASE;
+    pub const RCC_PLLCFGR: usize = RCC_BASE + 0x04;
+    pub const RCC_CFGR: usize = RCC_BASE + 0x08;
+    pub const RCC_AHB1ENR: usize = RCC_BASE + 0x30;
+    pub const RCC_APB1ENR: usize = RCC_BASE + 0x40;
+    pub const FLASH_ACR: usize = 0x40023C00;
+    pub const PWR_CR: usize = 0x40007000;
+
+    pub const GPIOA_BASE: usize = 0x40020000;
+    pub const GPIOB_BASE: usize = 0x40020400;
+    pub const GPIOD_BASE: usize = 0x40020C00;
+    pub const GPIO_MODER: usize = 0x00;
+    pub const GPIO_AFRL: usize = 0x20;
+    pub const GPIO_AFRH: usize = 0x24;
+
+    pub const USART_BRR: usize = 0x08;
+    pub const USART_CR1: usize = 0x0C;
+
+    pub const SYSCLK_HZ: u32 = 168_000_000;
+    pub const APB1_HZ: u32 = SYSCLK_HZ / 4;
+    pub const CONSOLE_BAUD: u32 = 115_200;
+
+    pub struct Stm32f407;
+
+    use core::ptr::{read_volatile, write_volatile};
+    use crate::power::PowerMode;
+
+    /// Start HSE and the PLL and run SYSCLK from it, at boot and after
+    /// Stop or deep sleep, which both wake on HSI with the two stopped
+    pub unsafe fn start_clocks() {
+        write_volatile(RCC_CR as *mut u32, read_volatile(RCC_CR as *const u32) | (1 << 16));
+        while read_volatile(RCC_CR as *const u32) & (1 << 17) == 0 {}
+        write_volatile(RCC_CR as *mut u32, read_volatile(RCC_CR as *const u32) | (1 << 24));
+        while read_volatile(RCC_CR as *const u32) & (1 << 25) == 0 {}
+
+        // AHB /1, APB1 /4, APB2 /2, then switch SYSCLK to PLL
+        let cfgr = (0b101 << 10) | (0b100 << 13) | 0b10;
+        write_volatile(RCC_CFGR as *mut u32, cfgr);
+        while (read_volatile(RCC_CFGR as *const u32) >> 2) & 0b11 != 0b10 {}
     }
-    
-    // Default board configuration
-    #[cfg(not(any(feature = "board_lm3s6965evb", feature = "board_qemu_virt")))]
-    {
-        get_default_board_config()
+
+    /// Stop puts the regulator (LPDS) and flash (FPDS) in low-power mode;
+    /// deep sleep keeps both on for a faster wakeup. PDDS stays clear, so
+    /// neither is Standby.
+    pub fn suspend(mode: PowerMode) {
+        const PWR_CR_LPDS: u32 = 1 << 0;
+        const PWR_CR_FPDS: u32 = 1 << 9;
+
+        unsafe {
+            let cr = read_volatile(PWR_CR as *const u32) & !(PWR_CR_LPDS | PWR_CR_FPDS);
+            let cr = if mode == PowerMode::Stop { cr | PWR_CR_LPDS | PWR_CR_FPDS } else { cr };
+            write_volatile(PWR_CR as *mut u32, cr);
+        }
+    }
+
+    pub fn resume(_mode: PowerMode) {
+        unsafe { start_clocks() };
+    }
+}
+
+#[cfg(all(target_arch = "arm", feature = "board_stm32f407"))]
+impl Board for stm32f407::Stm32f407 {
+    const DESCRIPTOR: BoardDescriptor = descriptors::STM32F407;
+
+    fn init() {
+        use stm32f407::*;
+        use core::ptr::{read_volatile, write_volatile};
+
+        unsafe {
+            // PLL: HSE (8 MHz crystal) / 8 * 336 / 2 = 168 MHz, 48 MHz USB
+            // clock with Q = 7; the PLLP field (bits 16-17) left 0 divides by 2
+            let pllcfgr = 8 | (336 << 6) | (1 << 22) | (7 << 24);
+            write_volatile(RCC_PLLCFGR as *mut u32, pllcfgr);
+
+            // 5 wait states at 168 MHz / 3.3 V, enable prefetch and caches
+            write_volatile(FLASH_ACR as *mut u32, 5 | (1 << 8) | (1 << 9) | (1 << 10));
+            start_clocks();
+
+            // GPIOA (console pins), GPIOD (LEDs), USART2 and PWR clocks
+            let ahb1enr = read_volatile(RCC_AHB1ENR as *const u32);
+            write_volatile(RCC_AHB1ENR as *mut u32, ahb1enr | (1 << 0) | (1 << 3));
+            let apb1enr = read_volatile(RCC_APB1ENR as *const u32);
+            write_volatile(RCC_APB1ENR as *mut u32, apb1enr | (1 << 17) | (1 << 28));
+
+            // PA2 = USART2_TX, PA3 = USART2_RX (alternate function 7)
+            let moder = read_volatile((GPIOA_BASE + GPIO_MODER) as *const u32);
+            let moder = (moder & !(0b1111 << 4)) | (0b1010 << 4);
+            write_volatile((GPIOA_BASE + GPIO_MODER) as *mut u32, moder);
+            let afrl = read_volatile((GPIOA_BASE + GPIO_AFRL) as *const u32);
+            let afrl = (afrl & !(0xFF << 8)) | (0x77 << 8);
+            write_volatile((GPIOA_BASE + GPIO_AFRL) as *mut u32, afrl);
+
+            // USART2: 115200 8N1 from the 42 MHz APB1 clock
+            let usart2 = Self::DESCRIPTOR.uart_base;
+            let brr = (APB1_HZ + CONSOLE_BAUD / 2) / CONSOLE_BAUD;
+            write_volatile((usart2 + USART_BRR) as *mut u32, brr);
+            write_volatile((usart2 + USART_CR1) as *mut u32, (1 << 13) | (1 << 3) | (1 << 2));
+
+            // USART3 for the GDB stub: PB10 = TX, PB11 = RX, same settings
+            if let Some(usart3) = Self::DESCRIPTOR.debug_uart.filter(|_| cfg!(feature = "gdb_stub")) {
+                let ahb1enr = read_volatile(RCC_AHB1ENR as *const u32);
+                write_volatile(RCC_AHB1ENR as *mut u32, ahb1enr | (1 << 1));
+                let apb1enr = read_volatile(RCC_APB1ENR as *const u32);
+                write_volatile(RCC_APB1ENR as *mut u32, apb1enr | (1 << 18));
+                let moder = read_volatile((GPIOB_BASE + GPIO_MODER) as *const u32);
+                let moder = (moder & !(0b1111 << 20)) | (0b1010 << 20);
+                write_volatile((GPIOB_BASE + GPIO_MODER) as *mut u32, moder);
+                let afrh = read_volatile((GPIOB_BASE + GPIO_AFRH) as *const u32);
+                let afrh = (afrh & !(0xFF << 8)) | (0x77 << 8);
+                write_volatile((GPIOB_BASE + GPIO_AFRH) as *mut u32, afrh);
+                write_volatile((usart3 + USART_BRR) as *mut u32, brr);
+                write_volatile((usart3 + USART_CR1) as *mut u32, (1 << 13) | (1 << 3) | (1 << 2));
+            }
+
+            // PD12-PD15 (user LEDs) as push-pull outputs
+            let moder = read_volatile((GPIOD_BASE + GPIO_MODER) as *const u32);
+            let moder = (moder & !(0xFF << 24)) | (0x55 << 24);
+            write_volatile((GPIOD_BASE + GPIO_MODER) as *mut u32, moder);
+        }
+
+        // Kernel tick from SysTick on the 168 MHz core clock
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        crate::drivers::systick::init(SYSCLK_HZ, tick_hz);
+
+        let clocks = crate::power::DriverPower { name: "clocks", suspend, resume };
+        if crate::power::register_driver(clocks).is_ok() {
+            crate::power::enable_stop();
+        }
+    }
+}
+
+/// nRF52840-DK board configuration
+///
+/// Clocks: 64 MHz HFXO for the UARTE baud generator, 32.768 kHz LFXO
+/// driving the RTC1 kernel tick. Console on UARTE0 (TX P0.06, RX P0.08).
+#[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
+mod nrf52840 {
+    pub const CLOCK_BASE: usize = 0x40000000;
+    pub const CLOCK_TASKS_HFCLKSTART: usize = CLOCK_BASE;
+    pub const CLOCK_EVENTS_HFCLKSTARTED: usize = CLOCK_BASE + 0x100;
+
+    pub const P0_BASE: usize = 0x50000000;
+    pub const GPIO_OUTSET: usize = 0x508;
+    pub const GPIO_DIRSET: usize = 0x518;
+
+    pub const UARTE_ENABLE: usize = 0x500;
+    pub const UARTE_PSEL_TXD: usize = 0x50C;
+    pub const UARTE_PSEL_RXD: usize = 0x514;
+    pub const UARTE_BAUDRATE: usize = 0x524;
+    pub const UARTE_CONFIG: usize = 0x56C;
+    pub const UARTE_ENABLE_ON: u32 = 8;
+    pub const UARTE_BAUD_115200: u32 = 0x01D7E000;
+
+    pub const CONSOLE_TX_PIN: u32 = 6;
+    pub const CONSOLE_RX_PIN: u32 = 8;
+    pub const LED_PINS: [u8; 4] = [13, 14, 15, 16];
+
+    pub struct Nrf52840;
+}
+
+#[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
+impl Board for nrf52840::Nrf52840 {
+    const DESCRIPTOR: BoardDescriptor = descriptors::NRF52840;
+
+    fn init() {
+        use nrf52840::*;
+        use core::ptr::{read_volatile, write_volatile};
+
+        unsafe {
+            // Crystal HFCLK for an accurate UART baud rate
+            write_volatile(CLOCK_EVENTS_HFCLKSTARTED as *mut u32, 0);
+            write_volatile(CLOCK_TASKS_HFCLKSTART as *mut u32, 1);
+            while read_volatile(CLOCK_EVENTS_HFCLKSTARTED as *const u32) == 0 {}
+
+            // UARTE0: 115200 8N1, no flow control. TX idles high.
+            let uarte0 = Self::DESCRIPTOR.uart_base;
+            write_volatile((P0_BASE + GPIO_OUTSET) as *mut u32, 1 << CONSOLE_TX_PIN);
+            write_volatile((P0_BASE + GPIO_DIRSET) as *mut u32, 1 << CONSOLE_TX_PIN);
+            write_volatile((uarte0 + UARTE_PSEL_TXD) as *mut u32, CONSOLE_TX_PIN);
+            write_volatile((uarte0 + UARTE_PSEL_RXD) as *mut u32, CONSOLE_RX_PIN);
+            write_volatile((uarte0 + UARTE_BAUDRATE) as *mut u32, UARTE_BAUD_115200);
+            write_volatile((uarte0 + UARTE_CONFIG) as *mut u32, 0);
+            write_volatile((uarte0 + UARTE_ENABLE) as *mut u32, UARTE_ENABLE_ON);
+
+            // LEDs are active low: drive high (off) before switching to output
+            for pin in LED_PINS {
+                write_volatile((P0_BASE + GPIO_OUTSET) as *mut u32, 1 << pin);
+                write_volatile((P0_BASE + GPIO_DIRSET) as *mut u32, 1 << pin);
+            }
+        }
+
+        // Low-power kernel tick from the RTC instead of SysTick
+        crate::drivers::nrf_rtc::init();
     }
 }
 
-/// LM3S6965EVB board configuration
-#[cfg(all(target_arch = "arm", feature = "board_lm3s6965evb"))]
-fn init_lm3s6965evb() {
-    // Initialize LM3S6965EVB specific features
-    // - System clock configuration
-    // - GPIO configuration
-    // - Peripheral power management
+/// QEMU RISC-V virt board
+#[cfg(target_arch = "riscv32")]
+#[allow(dead_code)]
+pub struct QemuVirt;
+
+#[cfg(target_arch = "riscv32")]
+impl Board for QemuVirt {
+    const DESCRIPTOR: BoardDescriptor = descriptors::QEMU_VIRT;
+
+    // Only the CLINT tick is set up; PLIC sources are enabled by their drivers
+    #[cfg(not(feature = "board_esp32c3"))]
+    fn init() {
+        // virt's mtime runs at a fixed 10 MHz
+        const MTIME_HZ: u32 = 10_000_000;
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        if let Some(clint) = Self::DESCRIPTOR.timer_base {
+            crate::drivers::clint::init(clint, MTIME_HZ, tick_hz);
+        }
+    }
 }
 
-#[cfg(all(target_arch = "arm", feature = "board_lm3s6965evb"))]
-fn get_lm3s6965evb_config() -> BoardConfig {
-    BoardConfig {
-        board_name: "LM3S6965EVB",
-        device_config: DeviceConfig {
-            uart_base: 0x4000C000,
-            uart_type: "PL011",
-            timer_base: 0x40030000,
-            memory_base: 0x20000000,
-            memory_size: 64 * 1024,
-        },
-        peripherals: &["UART0", "TIMER0", "GPIO", "SYSTICK"],
+/// ESP32-C3 board configuration (ESP32-C3-DevKitM-1)
+///
+/// Boots directly from flash via the ROM loader (no second-stage
+/// bootloader), so the watchdogs the ROM leaves running must be disabled
+/// here. Console on UART0 as configured by the ROM (115200 8N1).
+#[cfg(all(target_arch = "riscv32", feature = "board_esp32c3"))]
+mod esp32c3 {
+    pub const RTC_CNTL_BASE: usize = 0x6000_8000;
+    pub const RTC_CNTL_WDTCONFIG0: usize = RTC_CNTL_BASE + 0x90;
+    pub const RTC_CNTL_WDTWPROTECT: usize = RTC_CNTL_BASE + 0xA8;
+    pub const RTC_CNTL_SWD_CONF: usize = RTC_CNTL_BASE + 0xAC;
+    pub const RTC_CNTL_SWD_WPROTECT: usize = RTC_CNTL_BASE + 0xB0;
+    pub const RTC_WDT_KEY: u32 = 0x50D8_3AA1;
+    pub const SWD_KEY: u32 = 0x8F1D_312A;
+    pub const SWD_AUTO_FEED_EN: u32 = 1 << 31;
+
+    pub const TIMG0_BASE: usize = 0x6001_F000;
+    pub const TIMG1_BASE: usize = 0x6002_0000;
+    pub const TIMG_WDTCONFIG0: usize = 0x48;
+    pub const TIMG_WDTWPROTECT: usize = 0x64;
+    pub const TIMG_WDT_CONF_UPDATE_EN: u32 = 1 << 22;
+    pub const TIMG_WDT_KEY: u32 = 0x50D8_3AA1;
+
+    pub struct Esp32c3;
+}
+
+#[cfg(all(target_arch = "riscv32", feature = "board_esp32c3"))]
+impl Board for esp32c3::Esp32c3 {
+    const DESCRIPTOR: BoardDescriptor = descriptors::ESP32C3;
+
+    fn init() {
+        use esp32c3::*;
+        use core::ptr::{read_volatile, write_volatile};
+
+        unsafe {
+            // RTC watchdog
+            write_volatile(RTC_CNTL_WDTWPROTECT as *mut u32, RTC_WDT_KEY);
+            write_volatile(RTC_CNTL_WDTCONFIG0 as *mut u32, 0);
+            write_volatile(RTC_CNTL_WDTWPROTECT as *mut u32, 0);
+
+            // Super watchdog cannot be disabled, only auto-fed
+            write_volatile(RTC_CNTL_SWD_WPROTECT as *mut u32, SWD_KEY);
+            let swd = read_volatile(RTC_CNTL_SWD_CONF as *const u32);
+            write_volatile(RTC_CNTL_SWD_CONF as *mut u32, swd | SWD_AUTO_FEED_EN);
+            write_volatile(RTC_CNTL_SWD_WPROTECT as *mut u32, 0);
+
+            // Timer group watchdogs
+            for base in [TIMG0_BASE, TIMG1_BASE] {
+                write_volatile((base + TIMG_WDTWPROTECT) as *mut u32, TIMG_WDT_KEY);
+                write_volatile((base + TIMG_WDTCONFIG0) as *mut u32, TIMG_WDT_CONF_UPDATE_EN);
+                write_volatile((base + TIMG_WDTWPROTECT) as *mut u32, 0);
+            }
+        }
+
+        // Kernel tick from the 16 MHz system timer
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        crate::drivers::esp_systimer::init(tick_hz);
     }
 }
 
-/// QEMU RISC-V virt board configuration
-#[cfg(all(target_arch = "riscv32", feature = "board_qemu_virt"))]
-fn init_qemu_virt_riscv() {
-    // Initialize QEMU RISC-V virt board specific features
-    // - PLIC configuration
-    // - CLINT configuration
-    // - Platform-specific setup
+/// SiFive HiFive1 Rev B board configuration (FE310-G002)
+///
+/// Clocks: 16 MHz HFXOSC with the PLL bypassed drives hfclk; the CLINT
+/// mtime counter runs from the 32.768 kHz RTC clock. Console on UART0
+/// (TX GPIO17, RX GPIO16) routed to the J-Link USB serial port; GDB stub
+/// on UART1 (TX GPIO18, RX GPIO23) with `gdb_stub`.
+#[cfg(all(target_arch = "riscv32", feature = "board_hifive1"))]
+mod hifive1 {
+    pub const PRCI_BASE: usize = 0x1000_8000;
+    pub const PRCI_HFXOSCCFG: usize = PRCI_BASE + 0x04;
+    pub const PRCI_PLLCFG: usize = PRCI_BASE + 0x08;
+    pub const HFXOSC_ENABLE: u32 = 1 << 30;
+    pub const HFXOSC_READY: u32 = 1 << 31;
+    pub const PLL_SEL: u32 = 1 << 16;
+    pub const PLL_REFSEL: u32 = 1 << 17;
+    pub const PLL_BYPASS: u32 = 1 << 18;
+
+    pub const GPIO_BASE: usize = 0x1001_2000;
+    pub const GPIO_OUTPUT_EN: usize = 0x08;
+    pub const GPIO_OUTPUT_VAL: usize = 0x0C;
+    pub const GPIO_IOF_EN: usize = 0x38;
+    pub const GPIO_IOF_SEL: usize = 0x3C;
+
+    pub const UART_TXCTRL: usize = 0x08;
+    pub const UART_RXCTRL: usize = 0x0C;
+    pub const UART_DIV: usize = 0x18;
+    pub const UART_TXEN: u32 = 1 << 0;
+    pub const UART_RXEN: u32 = 1 << 0;
+
+    pub const HFCLK_HZ: u32 = 16_000_000;
+    pub const MTIME_HZ: u32 = 32_768;
+    pub const CONSOLE_BAUD: u32 = 115_200;
+    pub const CONSOLE_PINS: u32 = (1 << 16) | (1 << 17);
+    pub const DEBUG_UART_PINS: u32 = (1 << 18) | (1 << 23);
+    pub const LED_PINS: [u8; 3] = [22, 19, 21];
+
+    pub struct Hifive1;
 }
 
-#[cfg(all(target_arch = "riscv32", feature = "board_qemu_virt"))]
-fn get_qemu_virt_riscv_config() -> BoardConfig {
-    BoardConfig {
-        board_name: "QEMU RISC-V virt",
-        device_config: DeviceConfig {
-            uart_base: 0x10000000,
-            uart_type: "NS16550A",
-            timer_base: Some(0x02000000),
-            memory_base: 0x80000000,
-            memory_size: 128 * 1024 * 1024,
-        },
-        peripherals: &["UART16550", "CLINT", "PLIC"],
+#[cfg(all(target_arch = "riscv32", feature = "board_hifive1"))]
+impl Board for hifive1::Hifive1 {
+    const DESCRIPTOR: BoardDescriptor = descriptors::HIFIVE1;
+
+    fn init() {
+        use hifive1::*;
+        use core::ptr::{read_volatile, write_volatile};
+
+        unsafe {
+            // Run hfclk straight from the 16 MHz crystal (PLL bypassed)
+            let hfxosc = read_volatile(PRCI_HFXOSCCFG as *const u32);
+            write_volatile(PRCI_HFXOSCCFG as *mut u32, hfxosc | HFXOSC_ENABLE);
+            while read_volatile(PRCI_HFXOSCCFG as *const u32) & HFXOSC_READY == 0 {}
+            write_volatile(PRCI_PLLCFG as *mut u32, PLL_SEL | PLL_REFSEL | PLL_BYPASS);
+
+            // GPIO16/17 to IOF0 (UART0 RX/TX), GPIO18/23 too for the GDB stub
+            let debug_uart = Self::DESCRIPTOR.debug_uart.filter(|_| cfg!(feature = "gdb_stub"));
+            let pins = if debug_uart.is_some() { CONSOLE_PINS | DEBUG_UART_PINS } else { CONSOLE_PINS };
+            let iof_sel = read_volatile((GPIO_BASE + GPIO_IOF_SEL) as *const u32);
+            write_volatile((GPIO_BASE + GPIO_IOF_SEL) as *mut u32, iof_sel & !pins);
+            let iof_en = read_volatile((GPIO_BASE + GPIO_IOF_EN) as *const u32);
+            write_volatile((GPIO_BASE + GPIO_IOF_EN) as *mut u32, iof_en | pins);
+
+            // UART0 (and UART1): 115200 8N1, baud = hfclk / (div + 1)
+            let div = HFCLK_HZ / CONSOLE_BAUD - 1;
+            for uart in core::iter::once(Self::DESCRIPTOR.uart_base).chain(debug_uart) {
+                write_volatile((uart + UART_DIV) as *mut u32, div);
+                write_volatile((uart + UART_TXCTRL) as *mut u32, UART_TXEN);
+                write_volatile((uart + UART_RXCTRL) as *mut u32, UART_RXEN);
+            }
+
+            // RGB LED is active low: drive high (off) before enabling outputs
+            for pin in LED_PINS {
+                let val = read_volatile((GPIO_BASE + GPIO_OUTPUT_VAL) as *const u32);
+                write_volatile((GPIO_BASE + GPIO_OUTPUT_VAL) as *mut u32, val | (1 << pin));
+                let en = read_volatile((GPIO_BASE + GPIO_OUTPUT_EN) as *const u32);
+                write_volatile((GPIO_BASE + GPIO_OUTPUT_EN) as *mut u32, en | (1 << pin));
+            }
+        }
+
+        // Kernel tick from the CLINT machine timer; any remainder would drift
+        const _: () = assert!(MTIME_HZ.is_multiple_of(crate::config::TICK_HZ), "TICK_HZ must divide mtime");
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        if let Some(clint) = Self::DESCRIPTOR.timer_base {
+            crate::drivers::clint::init(clint, MTIME_HZ, tick_hz);
+        }
     }
 }
 
-/// Default board configuration
-fn init_default_board() {
-    // Generic board initialization
+/// Host build used for testing, and the simulator with `sim`
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
+pub struct HostBoard;
+
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
+impl Board for HostBoard {
+    const DESCRIPTOR: BoardDescriptor = descriptors::HOST;
+
+    // The simulated tick is a host thread
+    #[cfg(feature = "sim")]
+    fn init() {
+        let tick_hz = crate::config::get_runtime_config().timer_frequency;
+        crate::arch::sim::start_tick(tick_hz);
+    }
+}
+
+/// User LED wired to a GPIO pin
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct BoardLed {
+    pub name: &'static str,
+    pub gpio_base: usize,
+    pub pin: u8,
+    pub active_low: bool,
+}
+
+/// Get the user LEDs available on this board
+#[allow(dead_code)]
+pub fn board_leds() -> &'static [BoardLed] {
+    #[cfg(all(target_arch = "arm", feature = "board_stm32f407"))]
+    {
+        const LEDS: [BoardLed; 4] = [
+            BoardLed { name: "green", gpio_base: stm32f407::GPIOD_BASE, pin: 12, active_low: false },
+            BoardLed { name: "orange", gpio_base: stm32f407::GPIOD_BASE, pin: 13, active_low: false },
+            BoardLed { name: "red", gpio_base: stm32f407::GPIOD_BASE, pin: 14, active_low: false },
+            BoardLed { name: "blue", gpio_base: stm32f407::GPIOD_BASE, pin: 15, active_low: false },
+        ];
+        &LEDS
+    }
+    
+    #[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
+    {
+        const LEDS: [BoardLed; 4] = [
+            BoardLed { name: "led1", gpio_base: nrf52840::P0_BASE, pin: 13, active_low: true },
+            BoardLed { name: "led2", gpio_base: nrf52840::P0_BASE, pin: 14, active_low: true },
+            BoardLed { name: "led3", gpio_base: nrf52840::P0_BASE, pin: 15, active_low: true },
+            BoardLed { name: "led4", gpio_base: nrf52840::P0_BASE, pin: 16, active_low: true },
+        ];
+        &LEDS
+    }
+    
+    #[cfg(all(target_arch = "riscv32", feature = "board_hifive1"))]
+    {
+        const LEDS: [BoardLed; 3] = [
+            BoardLed { name: "red", gpio_base: hifive1::GPIO_BASE, pin: 22, active_low: true },
+            BoardLed { name: "green", gpio_base: hifive1::GPIO_BASE, pin: 19, active_low: true },
+            BoardLed { name: "blue", gpio_base: hifive1::GPIO_BASE, pin: 21, active_low: true },
+        ];
+        &LEDS
+    }
+    
+    #[cfg(not(any(all(target_arch = "arm",
+                      any(feature = "board_stm32f407", feature = "board_nrf52840")),
+                  all(target_arch = "riscv32", feature = "board_hifive1"))))]
+    {
+        &[]
+    }
 }
 
-fn get_default_board_config() -> BoardConfig {
-    #[cfg(target_arch = "arm")]
+/// Switch a board LED on or off (ignored if the index is out of range)
+#[allow(dead_code)]
+pub fn set_led(index: usize, on: bool) {
+    let led = match board_leds().get(index) {
+        Some(led) => *led,
+        None => return,
+    };
+    let level = on != led.active_low;
+    
+    #[cfg(all(target_arch = "arm", feature = "board_stm32f407"))]
     {
-        BoardConfig {
-            board_name: "Generic ARM Board",
-            device_config: DeviceConfig {
-                uart_base: 0x4000C000,
-                uart_type: "PL011",
-                timer_base: Some(0x40030000),
-                memory_base: 0x20000000,
-                memory_size: 64 * 1024,
-            },
-            peripherals: &["UART", "TIMER"],
+        // BSRR: low half sets, high half resets
+        const GPIO_BSRR: usize = 0x18;
+        let bit = if level { 1u32 << led.pin } else { 1u32 << (led.pin + 16) };
+        unsafe {
+            core::ptr::write_volatile((led.gpio_base + GPIO_BSRR) as *mut u32, bit);
         }
     }
     
-    #[cfg(target_arch = "riscv32")]
+    #[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
     {
-        BoardConfig {
-            board_name: "Generic RISC-V Board",
-            device_config: DeviceConfig {
-                uart_base: 0x10000000,
-                uart_type: "NS16550A",
-                timer_base: Some(0x02000000),
-                memory_base: 0x80000000,
-                memory_size: 128 * 1024 * 1024,
-            },
-            peripherals: &["UART", "TIMER"],
+        const GPIO_OUTSET: usize = 0x508;
+        const GPIO_OUTCLR: usize = 0x50C;
+        let reg = if level { GPIO_OUTSET } else { GPIO_OUTCLR };
+        unsafe {
+            core::ptr::write_volatile((led.gpio_base + reg) as *mut u32, 1u32 << led.pin);
         }
     }
     
-    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
+    #[cfg(all(target_arch = "riscv32", feature = "board_hifive1"))]
     {
-        // Default configuration for host testing
-        BoardConfig {
-            board_name: "Host Test Board",
-            device_config: DeviceConfig {
-                uart_base: 0x00000000,
-                uart_type: "HOST",
-                timer_base: None,
-                memory_base: 0x00000000,
-                memory_size: 1024 * 1024 * 1024,
-            },
-            peripherals: &["HOST"],
+        // No set/clear registers: read-modify-write output_val
+        let reg = (led.gpio_base + hifive1::GPIO_OUTPUT_VAL) as *mut u32;
+        unsafe {
+            let val = core::ptr::read_volatile(reg);
+            let val = if level { val | (1u32 << led.pin) } else { val & !(1u32 << led.pin) };
+            core::ptr::write_volatile(reg, val);
         }
     }
+    
+    #[cfg(not(any(all(target_arch = "arm",
+                      any(feature = "board_stm32f407", feature = "board_nrf52840")),
+                  all(target_arch = "riscv32", feature = "board_hifive1"))))]
+    let _ = level;
 }
@@ -0,0 +1,319 @@
+//! Board descriptors
+//! Static hardware description of every supported board
+//!
+//! This is the single source of board data: the kernel reads it through
+//! `crate::board`, and build.rs compiles this file directly (via `#[path]`)
+//! to generate the linker script. Keep it free of `crate::` paths and
+//! target-specific code.
+
+/// CPU architecture family a board belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    Arm,
+    Riscv,
+    Host,
+}
+
+/// Console UART controller family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UartKind {
+    Pl011,
+    Ns16550a,
+    Stm32Usart,
+    NrfUarte,
+    Esp32Uart,
+    SifiveUart,
+    Host,
+}
+
+impl UartKind {
+    pub const fn name(self) -> &'static str {
+        match self {
+            UartKind::Pl011 => "PL011",
+            UartKind::Ns16550a => "NS16550A",
+            UartKind::Stm32Usart => "STM32-USART",
+            UartKind::NrfUarte => "NRF-UARTE",
+            UartKind::Esp32Uart => "ESP32-UART",
+            UartKind::SifiveUart => "SIFIVE-UART",
+            UartKind::Host => "HOST",
+        }
+    }
+}
+
+/// Kernel tick timer family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerKind {
+    SysTick,
+    NrfRtc,
+    EspSystimer,
+    Clint,
+    None,
+}
+
+impl TimerKind {
+    pub const fn name(self) -> &'static str {
+        match self {
+            TimerKind::SysTick => "SYSTICK",
+            TimerKind::NrfRtc => "NRF-RTC",
+            TimerKind::EspSystimer => "ESP32-SYSTIMER",
+            TimerKind::Clint => "CLINT",
+            TimerKind::None => "NONE",
+        }
+    }
+}
+
+/// Contiguous address range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: usize,
+    pub size: usize,
+}
+
+impl MemoryRegion {
+    pub const fn end(&self) -> usize {
+        self.start + self.size
+    }
+}
+
+/// Section layout build.rs uses when generating the linker script
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkerLayout {
+    /// cortex-m-rt image in FLASH, data and stack in RAM
+    CortexM,
+    /// Whole image loaded into RAM (QEMU `-kernel`)
+    RiscvRam,
+    /// Code executes in place from FLASH, .data copied to RAM
+    RiscvXip,
+    /// ESP32-C3 direct boot: the same flash seen through FLASH (instruction
+    /// bus) and the `drom` data-bus window
+    Esp32c3Direct { drom: usize },
+    /// Host build, no linker script
+    Hosted,
+}
+
+impl LinkerLayout {
+    /// SECTIONS template under build/templates
+    pub const fn sections_template(self) -> &'static str {
+        match self {
+            LinkerLayout::CortexM => "sections-cortex-m.x",
+            LinkerLayout::RiscvRam => "sections-riscv-ram.x",
+            LinkerLayout::RiscvXip => "sections-riscv-xip.x",
+            LinkerLayout::Esp32c3Direct { .. } => "sections-esp32c3.x",
+            LinkerLayout::Hosted => "",
+        }
+    }
+}
+
+/// Everything the kernel and build script need to know about a board
+#[derive(Clone, Copy, Debug)]
+pub struct BoardDescriptor {
+    pub name: &'static str,
+    /// Cargo feature selecting this board (e.g. "board_stm32f407")
+    pub feature: &'static str,
+    pub arch: Arch,
+    /// Rust target triple the board is built for
+    pub triple: &'static str,
+    pub uart_base: usize,
+    pub uart_kind: UartKind,
+    /// Second UART of `uart_kind` for the GDB stub (`gdb_stub`)
+    pub debug_uart: Option<usize>,
+    pub timer_base: Option<usize>,
+    pub timer_kind: TimerKind,
+    /// Execute-in-place flash; zero size when the image is loaded into RAM
+    pub flash: MemoryRegion,
+    pub ram: MemoryRegion,
+    pub peripherals: &'static [&'static str],
+    /// Device register windows, for range-checking raw memory access
+    pub mmio: &'static [MemoryRegion],
+    pub layout: LinkerLayout,
+    /// Bytes reserved for the `.heap` section
+    pub heap_size: usize,
+    /// Features whose unoptimized build overflows `flash`; build.rs
+    /// refuses them outside `--release`
+    pub release_only: &'static [&'static str],
+    /// Features that do not fit the board at all; build.rs refuses them
+    pub unsupported: &'static [&'static str],
+}
+
+pub const LM3S6965EVB: BoardDescriptor = BoardDescriptor {
+    name: "LM3S6965EVB",
+    feature: "board_lm3s6965evb",
+    arch: Arch::Arm,
+    triple: "thumbv7m-none-eabi",
+    uart_base: 0x4000_C000,
+    uart_kind: UartKind::Pl011,
+    debug_uart: Some(0x4000_D000), // UART1
+    timer_base: Some(0xE000_E010),
+    timer_kind: TimerKind::SysTick,
+    flash: MemoryRegion { start: 0x0000_0000, size: 256 * 1024 },
+    ram: MemoryRegion { start: 0x2000_0000, size: 64 * 1024 },
+    peripherals: &["UART0", "TIMER0", "GPIO", "SYSTICK"],
+    mmio: &[
+        MemoryRegion { start: 0x4000_0000, size: 0x10_0000 }, // APB and AHB peripherals
+        MemoryRegion { start: 0xE000_0000, size: 0x10_0000 }, // Private peripheral bus
+    ],
+    layout: LinkerLayout::CortexM,
+    heap_size: 0x400,
+    release_only: &["shell", "net"],
+    unsupported: &[],
+};
+
+pub const STM32F407: BoardDescriptor = BoardDescriptor {
+    name: "STM32F4 Discovery",
+    feature: "board_stm32f407",
+    arch: Arch::Arm,
+    triple: "thumbv7em-none-eabihf",
+    uart_base: 0x4000_4400,
+    uart_kind: UartKind::Stm32Usart,
+    debug_uart: Some(0x4000_4800), // USART3
+    timer_base: Some(0xE000_E010),
+    timer_kind: TimerKind::SysTick,
+    flash: MemoryRegion { start: 0x0800_0000, size: 1024 * 1024 },
+    // The 64K CCM RAM at 0x1000_0000 is not DMA-capable and left unused
+    ram: MemoryRegion { start: 0x2000_0000, size: 128 * 1024 },
+    peripherals: &["USART2", "SYSTICK", "GPIO", "RCC"],
+    mmio: &[
+        MemoryRegion { start: 0x4000_0000, size: 0x2000_0000 }, // APB1/2, AHB1/2/3
+        MemoryRegion { start: 0xE000_0000, size: 0x10_0000 }, // Private peripheral bus
+    ],
+    layout: LinkerLayout::CortexM,
+    heap_size: 0x400,
+    release_only: &[],
+    unsupported: &[],
+};
+
+pub const NRF52840: BoardDescriptor = BoardDescriptor {
+    name: "nRF52840-DK",
+    feature: "board_nrf52840",
+    arch: Arch::Arm,
+    triple: "thumbv7em-none-eabihf",
+    uart_base: 0x4000_2000,
+    uart_kind: UartKind::NrfUarte,
+    debug_uart: None,
+    timer_base: Some(0x4001_1000), // RTC1
+    timer_kind: TimerKind::NrfRtc,
+    flash: MemoryRegion { start: 0x0000_0000, size: 1024 * 1024 },
+    ram: MemoryRegion { start: 0x2000_0000, size: 256 * 1024 },
+    peripherals: &["UARTE0", "RTC1", "GPIO", "CLOCK"],
+    mmio: &[
+        MemoryRegion { start: 0x4000_0000, size: 0x4_0000 }, // APB peripherals
+        MemoryRegion { start: 0x5000_0000, size: 0x1000 }, // GPIO P0/P1
+        MemoryRegion { start: 0xE000_0000, size: 0x10_0000 }, // Private peripheral bus
+    ],
+    layout: LinkerLayout::CortexM,
+    heap_size: 0x400,
+    release_only: &[],
+    unsupported: &[],
+};
+
+pub const QEMU_VIRT: BoardDescriptor = BoardDescriptor {
+    name: "QEMU RISC-V virt",
+    feature: "board_qemu_virt",
+    arch: Arch::Riscv,
+    triple: "riscv32imac-unknown-none-elf",
+    uart_base: 0x1000_0000,
+    uart_kind: UartKind::Ns16550a,
+    debug_uart: None,
+    timer_base: Some(0x0200_0000),
+    timer_kind: TimerKind::Clint,
+    flash: MemoryRegion { start: 0x2000_0000, size: 0 },
+    ram: MemoryRegion { start: 0x8000_0000, size: 128 * 1024 * 1024 },
+    peripherals: &["UART16550", "CLINT", "PLIC"],
+    mmio: &[
+        MemoryRegion { start: 0x0010_0000, size: 0x2000 }, // Test finisher, Goldfish RTC
+        MemoryRegion { start: 0x0200_0000, size: 0x1_0000 }, // CLINT
+        MemoryRegion { start: 0x0C00_0000, size: 0x60_0000 }, // PLIC
+        MemoryRegion { start: 0x1000_0000, size: 0x9000 }, // UART, virtio-mmio
+    ],
+    layout: LinkerLayout::RiscvRam,
+    heap_size: 0x1000,
+    release_only: &[],
+    unsupported: &[],
+};
+
+pub const ESP32C3: BoardDescriptor = BoardDescriptor {
+    name: "ESP32-C3",
+    feature: "board_esp32c3",
+    arch: Arch::Riscv,
+    triple: "riscv32imc-unknown-none-elf",
+    uart_base: 0x6000_0000,
+    uart_kind: UartKind::Esp32Uart,
+    debug_uart: None,
+    timer_base: Some(0x6002_3000), // SYSTIMER
+    timer_kind: TimerKind::EspSystimer,
+    flash: MemoryRegion { start: 0x4200_0000, size: 4 * 1024 * 1024 },
+    // SRAM1 data bus; the ROM keeps 0x3FCDE710.. for its own stack
+    ram: MemoryRegion { start: 0x3FC8_0000, size: 0x5E700 },
+    peripherals: &["UART0", "SYSTIMER", "INTMTX"],
+    mmio: &[MemoryRegion { start: 0x6000_0000, size: 0xD_1000 }], // APB peripherals
+    layout: LinkerLayout::Esp32c3Direct { drom: 0x3C00_0000 },
+    heap_size: 0x1000,
+    release_only: &[],
+    unsupported: &[],
+};
+
+pub const HIFIVE1: BoardDescriptor = BoardDescriptor {
+    name: "HiFive1 Rev B",
+    feature: "board_hifive1",
+    arch: Arch::Riscv,
+    triple: "riscv32imac-unknown-none-elf",
+    uart_base: 0x1001_3000,
+    uart_kind: UartKind::SifiveUart,
+    debug_uart: Some(0x1002_3000), // UART1
+    timer_base: Some(0x0200_0000),
+    timer_kind: TimerKind::Clint,
+    // The first 64K of flash holds the on-board bootloader; the small
+    // DTIM only leaves room for a 1K heap
+    flash: MemoryRegion { start: 0x2001_0000, size: 4 * 1024 * 1024 - 64 * 1024 },
+    ram: MemoryRegion { start: 0x8000_0000, size: 16 * 1024 },
+    peripherals: &["UART0", "CLINT", "GPIO", "PRCI"],
+    mmio: &[
+        MemoryRegion { start: 0x0200_0000, size: 0x1_0000 }, // CLINT
+        MemoryRegion { start: 0x0C00_0000, size: 0x400_0000 }, // PLIC
+        MemoryRegion { start: 0x1000_0000, size: 0x4_0000 }, // AON, PRCI, GPIO, UART, SPI, PWM
+    ],
+    layout: LinkerLayout::RiscvXip,
+    heap_size: 0x400,
+    release_only: &[],
+    unsupported: &["net"], // 16 KiB of RAM
+};
+
+/// Host build (unit testing); no hardware behind any of these
+pub const HOST: BoardDescriptor = BoardDescriptor {
+    name: "Host Test Board",
+    feature: "",
+    arch: Arch::Host,
+    triple: "host",
+    uart_base: 0,
+    uart_kind: UartKind::Host,
+    debug_uart: None,
+    timer_base: None,
+    timer_kind: TimerKind::None,
+    flash: MemoryRegion { start: 0, size: 0 },
+    ram: MemoryRegion { start: 0, size: 0 },
+    peripherals: &["HOST"],
+    mmio: &[],
+    layout: LinkerLayout::Hosted,
+    heap_size: 0,
+    release_only: &[],
+    unsupported: &[],
+};
+
+/// All boards selectable with a `board_*` feature
+pub const BOARDS: &[BoardDescriptor] = &[
+    LM3S6965EVB,
+    STM32F407,
+    NRF52840,
+    QEMU_VIRT,
+    ESP32C3,
+    HIFIVE1,
+];
+
+/// Board used when no `board_*` feature is enabled (the QEMU machines)
+pub const fn default_board(arch: Arch) -> &'static BoardDescriptor {
+    match arch {
+        Arch::Arm => &LM3S6965EVB,
+        Arch::Riscv => &QEMU_VIRT,
+        Arch::Host => &HOST,
+    }
+}
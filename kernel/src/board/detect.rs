@@ -0,0 +1,129 @@
+//! Runtime board detection
+//! Refines the compiled-in descriptor with what the hardware reports
+//!
+//! RISC-V: prefer the device tree handed over in a1 and fall back to the
+//! static descriptor. ARM: look for the PL011 console at the bases QEMU
+//! machines put one, by its PrimeCell ID.
+//! Runs once at boot, before interrupts or tasks, so `ACTIVE` is written
+//! exactly once and only read afterwards.
+
+use super::{BoardDescriptor, BOARD};
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+/// Where the active board configuration came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Compiled-in descriptor, nothing detected
+    Static,
+    /// Overridden from the boot device tree
+    DeviceTree,
+    /// Console confirmed by probing its ID registers
+    Probe,
+}
+
+impl ConfigSource {
+    pub const fn name(self) -> &'static str {
+        match self {
+            ConfigSource::Static => "static",
+            ConfigSource::DeviceTree => "device tree",
+            ConfigSource::Probe => "probe",
+        }
+    }
+}
+
+static mut ACTIVE: BoardDescriptor = BOARD;
+static SOURCE: AtomicU32 = AtomicU32::new(ConfigSource::Static as u32);
+
+/// Detect the board and select the active descriptor
+///
+/// `boot_arg` is the DTB address on RISC-V (a1 at entry), ignored on ARM.
+pub fn detect(boot_arg: usize) {
+    let (descriptor, source) = probe(boot_arg);
+    unsafe {
+        *core::ptr::addr_of_mut!(ACTIVE) = descriptor;
+    }
+    SOURCE.store(source as u32, Ordering::Relaxed);
+}
+
+/// Descriptor the kernel is running with
+pub fn active() -> &'static BoardDescriptor {
+    unsafe { &*core::ptr::addr_of!(ACTIVE) }
+}
+
+/// How the active descriptor was obtained
+pub fn source() -> ConfigSource {
+    match SOURCE.load(Ordering::Relaxed) {
+        s if s == ConfigSource::DeviceTree as u32 => ConfigSource::DeviceTree,
+        s if s == ConfigSource::Probe as u32 => ConfigSource::Probe,
+        _ => ConfigSource::Static,
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+fn probe(dtb: usize) -> (BoardDescriptor, ConfigSource) {
+    let mut descriptor = BOARD;
+
+    // Only trust a pointer into RAM; on real boards a1 is whatever the
+    // boot ROM left behind
+    if dtb < BOARD.ram.start || dtb >= BOARD.ram.end() {
+        return (descriptor, ConfigSource::Static);
+    }
+
+    let info = match unsafe { super::fdt::parse(dtb) } {
+        Some(info) => info,
+        None => return (descriptor, ConfigSource::Static),
+    };
+
+    if let Some(uart_base) = info.uart_base {
+        descriptor.uart_base = uart_base;
+    }
+    if let Some(timer_base) = info.timer_base {
+        descriptor.timer_base = Some(timer_base);
+    }
+    if let Some(memory) = info.memory {
+        descriptor.ram = memory;
+    }
+    (descriptor, ConfigSource::DeviceTree)
+}
+
+/// PL011 bases of QEMU ARM machines, tried after the compiled-in one
+#[cfg(target_arch = "arm")]
+const PL011_BASES: [usize; 5] = [
+    0x4000_C000, // lm3s6965evb, lm3s811evb
+    0x0900_0000, // virt
+    0x1000_9000, // realview-*, vexpress-a9
+    0x101F_1000, // versatilepb, versatileab
+    0x1C09_0000, // vexpress-a15
+];
+
+#[cfg(target_arch = "arm")]
+fn probe(_boot_arg: usize) -> (BoardDescriptor, ConfigSource) {
+    use super::UartKind;
+
+    // PrimeCell component ID, low byte of each word at 0xFF0..0xFFC
+    const PL011_CELL_ID: [u32; 4] = [0x0D, 0xF0, 0x05, 0xB1];
+
+    if BOARD.uart_kind != UartKind::Pl011 {
+        return (BOARD, ConfigSource::Static);
+    }
+
+    let is_pl011 = |base: usize| {
+        PL011_CELL_ID.iter().enumerate().all(|(i, &expected)| {
+            crate::arch::arm::probe_read(base + 0xFF0 + 4 * i).map(|value| value & 0xFF) == Some(expected)
+        })
+    };
+
+    let mut descriptor = BOARD;
+    match core::iter::once(BOARD.uart_base).chain(PL011_BASES).find(|&base| is_pl011(base)) {
+        Some(base) => {
+            descriptor.uart_base = base;
+            (descriptor, ConfigSource::Probe)
+        }
+        None => (descriptor, ConfigSource::Static),
+    }
+}
+
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
+fn probe(_boot_arg: usize) -> (BoardDescriptor, ConfigSource) {
+    (BOARD, ConfigSource::Static)
+}
@@ -0,0 +1,183 @@
+//! Flattened device tree reader
+//! Just enough of the FDT format to locate the console, timer and RAM
+//!
+//! QEMU passes a DTB pointer in a1 when booting with `-bios none`. This
+//! walks the structure block once and records the first node of each
+//! interesting kind; it never allocates and never follows phandles.
+
+use super::MemoryRegion;
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Refuse blobs larger than this; QEMU's are a few KB
+const FDT_MAX_SIZE: usize = 64 * 1024;
+const MAX_DEPTH: usize = 8;
+
+/// Devices found in the device tree
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FdtInfo {
+    pub uart_base: Option<usize>,
+    pub timer_base: Option<usize>,
+    pub memory: Option<MemoryRegion>,
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    address_cells: u32,
+    size_cells: u32,
+    uart: bool,
+    clint: bool,
+    memory: bool,
+    reg: Option<(usize, usize)>, // blob offset and length of `reg`
+}
+
+impl Node {
+    const fn new() -> Self {
+        Node { address_cells: 2, size_cells: 1, uart: false, clint: false, memory: false, reg: None }
+    }
+}
+
+/// Parse the blob at `addr`, returning `None` if it is not a valid FDT
+///
+/// # Safety
+/// `addr` must be readable for the size given in the FDT header.
+pub unsafe fn parse(addr: usize) -> Option<FdtInfo> {
+    if addr == 0 || !addr.is_multiple_of(4) {
+        return None;
+    }
+    let header = core::slice::from_raw_parts(addr as *const u8, 40);
+    if be32(header, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let total_size = be32(header, 4)? as usize;
+    if !(40..=FDT_MAX_SIZE).contains(&total_size) {
+        return None;
+    }
+
+    let blob = core::slice::from_raw_parts(addr as *const u8, total_size);
+    let structs = be32(blob, 8)? as usize;
+    let strings = be32(blob, 12)? as usize;
+    walk(blob, structs, strings)
+}
+
+fn walk(blob: &[u8], mut pos: usize, strings: usize) -> Option<FdtInfo> {
+    let mut info = FdtInfo::default();
+    // stack[0] is a virtual parent of the root carrying the spec defaults
+    let mut stack = [Node::new(); MAX_DEPTH + 1];
+    let mut depth = 0;
+
+    loop {
+        let token = be32(blob, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(blob, pos)?;
+                pos = align4(pos + name.len() + 1);
+                if depth == MAX_DEPTH {
+                    return None;
+                }
+                depth += 1;
+                stack[depth] = Node::new();
+                stack[depth].memory = name.starts_with(b"memory@") || name == b"memory";
+            }
+            FDT_END_NODE => {
+                if depth == 0 {
+                    return None;
+                }
+                record(blob, &stack[depth], &stack[depth - 1], &mut info);
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = be32(blob, pos)? as usize;
+                let name = cstr(blob, strings + be32(blob, pos + 4)? as usize)?;
+                let value_start = pos + 8;
+                let value = blob.get(value_start..value_start + len)?;
+                pos = align4(value_start + len);
+
+                let node = &mut stack[depth];
+                match name {
+                    b"compatible" => {
+                        for compat in value.split(|&b| b == 0) {
+                            match compat {
+                                b"ns16550a" | b"ns16550" => node.uart = true,
+                                b"riscv,clint0" | b"sifive,clint0" => node.clint = true,
+                                _ => {}
+                            }
+                        }
+                    }
+                    b"device_type" => node.memory |= value.starts_with(b"memory"),
+                    b"#address-cells" => node.address_cells = be32(value, 0)?,
+                    b"#size-cells" => node.size_cells = be32(value, 0)?,
+                    b"reg" => node.reg = Some((value_start, len)),
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => return Some(info),
+            _ => return None,
+        }
+    }
+}
+
+/// Store the first `reg` entry of a recognised node
+fn record(blob: &[u8], node: &Node, parent: &Node, info: &mut FdtInfo) {
+    let (offset, len) = match node.reg {
+        Some(reg) => reg,
+        None => return,
+    };
+    let reg = match blob.get(offset..offset + len) {
+        Some(reg) => reg,
+        None => return,
+    };
+    let base = match read_cells(reg, 0, parent.address_cells) {
+        Some(base) => base,
+        None => return,
+    };
+
+    if node.uart && info.uart_base.is_none() {
+        info.uart_base = Some(base);
+    } else if node.clint && info.timer_base.is_none() {
+        info.timer_base = Some(base);
+    } else if node.memory && info.memory.is_none() {
+        let size_offset = parent.address_cells as usize * 4;
+        if let Some(size) = read_cells(reg, size_offset, parent.size_cells) {
+            info.memory = Some(MemoryRegion { start: base, size });
+        }
+    }
+}
+
+/// Read a 1- or 2-cell big-endian number (upper cell must fit a usize)
+fn read_cells(data: &[u8], offset: usize, cells: u32) -> Option<usize> {
+    match cells {
+        1 => Some(be32(data, offset)? as usize),
+        2 => {
+            let high = be32(data, offset)? as u64;
+            let low = be32(data, offset + 4)? as u64;
+            usize::try_from((high << 32) | low).ok()
+        }
+        _ => None,
+    }
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn cstr(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let tail = data.get(offset..)?;
+    let end = tail.iter().position(|&b| b == 0)?;
+    Some(&tail[..end])
+}
+
+const fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+#[cfg(test)]
+mod tests;
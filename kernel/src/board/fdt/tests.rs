@@ -0,0 +1,211 @@
+//! Host tests for the device tree reader
+//! Small blobs built here in the QEMU virt shape, then cut short,
+//! misaligned or malformed
+
+use super::{parse, walk, FdtInfo, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_MAGIC, FDT_PROP, MAX_DEPTH};
+use crate::board::MemoryRegion;
+
+/// Header plus an empty memory reservation block
+const STRUCTS: usize = 56;
+
+/// Builds a blob token by token
+#[derive(Default)]
+struct Blob {
+    structs: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl Blob {
+    fn word(&mut self, value: u32) -> &mut Self {
+        self.structs.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn pad(&mut self) {
+        while !self.structs.len().is_multiple_of(4) {
+            self.structs.push(0);
+        }
+    }
+
+    fn begin(&mut self, name: &str) -> &mut Self {
+        self.word(FDT_BEGIN_NODE);
+        self.structs.extend_from_slice(name.as_bytes());
+        self.structs.push(0);
+        self.pad();
+        self
+    }
+
+    fn end(&mut self) -> &mut Self {
+        self.word(FDT_END_NODE)
+    }
+
+    fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+        let name_offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.word(FDT_PROP).word(value.len() as u32).word(name_offset);
+        self.structs.extend_from_slice(value);
+        self.pad();
+        self
+    }
+
+    fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+        let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+        self.prop(name, &value)
+    }
+
+    /// The whole blob with its header, FDT_END appended
+    fn finish(&mut self) -> Vec<u8> {
+        self.word(FDT_END);
+        let strings = STRUCTS + self.structs.len();
+        let total = strings + self.strings.len();
+        let header = [
+            FDT_MAGIC,
+            total as u32,
+            STRUCTS as u32,
+            strings as u32,
+            40, // memory reservation block
+            17,
+            16,
+            0,
+            self.strings.len() as u32,
+            self.structs.len() as u32,
+        ];
+        let mut blob: Vec<u8> = header.iter().flat_map(|word| word.to_be_bytes()).collect();
+        blob.resize(STRUCTS, 0);
+        blob.extend_from_slice(&self.structs);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+}
+
+/// Root and soc with two cells each, as QEMU virt lays them out
+fn virt() -> Vec<u8> {
+    Blob::default()
+        .begin("")
+        .cells("#address-cells", &[2])
+        .cells("#size-cells", &[2])
+        .begin("memory@80000000")
+        .prop("device_type", b"memory\0")
+        .cells("reg", &[0, 0x8000_0000, 0, 0x0800_0000])
+        .end()
+        .begin("soc")
+        .cells("#address-cells", &[2])
+        .cells("#size-cells", &[2])
+        .begin("clint@2000000")
+        .prop("compatible", b"sifive,clint0\0riscv,clint0\0")
+        .cells("reg", &[0, 0x0200_0000, 0, 0x1_0000])
+        .end()
+        .begin("serial@10000000")
+        .prop("compatible", b"ns16550a\0")
+        .cells("reg", &[0, 0x1000_0000, 0, 0x100])
+        .end()
+        .end()
+        .end()
+        .finish()
+}
+
+/// `blob` copied to a word-aligned buffer, `shift` bytes in
+fn placed(blob: &[u8], shift: usize) -> (Vec<u32>, usize) {
+    let mut words = vec![0u32; (blob.len() + shift).div_ceil(4) + 1];
+    let addr = words.as_mut_ptr() as usize + shift;
+    unsafe { core::ptr::copy_nonoverlapping(blob.as_ptr(), addr as *mut u8, blob.len()) };
+    (words, addr)
+}
+
+fn parse_blob(blob: &[u8]) -> Option<FdtInfo> {
+    let (_words, addr) = placed(blob, 0);
+    unsafe { parse(addr) }
+}
+
+fn walk_blob(blob: &[u8]) -> Option<FdtInfo> {
+    walk(blob, STRUCTS, STRUCTS + be32(blob, 36) as usize)
+}
+
+fn be32(blob: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(blob[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn finds_console_timer_and_ram() {
+    let info = parse_blob(&virt()).expect("valid blob");
+    assert_eq!(info.uart_base, Some(0x1000_0000));
+    assert_eq!(info.timer_base, Some(0x0200_0000));
+    assert_eq!(info.memory, Some(MemoryRegion { start: 0x8000_0000, size: 0x0800_0000 }));
+}
+
+#[test]
+fn one_cell_addresses_and_first_match_wins() {
+    let blob = Blob::default()
+        .begin("")
+        .cells("#address-cells", &[1])
+        .cells("#size-cells", &[1])
+        .begin("memory")
+        .cells("reg", &[0x8000_0000, 0x4000])
+        .end()
+        .begin("uart@10013000")
+        .prop("compatible", b"ns16550\0")
+        .cells("reg", &[0x1001_3000, 0x1000])
+        .end()
+        .begin("uart@10023000")
+        .prop("compatible", b"ns16550a\0")
+        .cells("reg", &[0x1002_3000, 0x1000])
+        .end()
+        .end()
+        .finish();
+    let info = parse_blob(&blob).expect("valid blob");
+    assert_eq!(info.uart_base, Some(0x1001_3000));
+    assert_eq!(info.timer_base, None);
+    assert_eq!(info.memory, Some(MemoryRegion { start: 0x8000_0000, size: 0x4000 }));
+}
+
+#[test]
+fn truncated_blobs_are_refused() {
+    let blob = virt();
+    assert!(walk_blob(&blob).is_some());
+    for len in 0..blob.len() {
+        if len >= 40 {
+            assert!(walk_blob(&blob[..len]).is_none(), "walk of {} bytes", len);
+        }
+        // A header claiming fewer bytes than the blob holds
+        let mut short = blob.clone();
+        short[4..8].copy_from_slice(&(len as u32).to_be_bytes());
+        assert!(parse_blob(&short).is_none(), "total size {}", len);
+    }
+}
+
+#[test]
+fn misaligned_or_null_address_is_refused() {
+    let blob = virt();
+    for shift in 1..4 {
+        let (_words, addr) = placed(&blob, shift);
+        assert!(unsafe { parse(addr) }.is_none(), "shifted by {}", shift);
+    }
+    assert!(unsafe { parse(0) }.is_none());
+}
+
+#[test]
+fn malformed_structure_is_refused() {
+    let mut bad_magic = virt();
+    bad_magic[0] ^= 0xFF;
+    assert!(parse_blob(&bad_magic).is_none());
+
+    // An unknown token where the first property should be
+    let mut bad_token = virt();
+    let first = STRUCTS + 8;
+    bad_token[first..first + 4].copy_from_slice(&7u32.to_be_bytes());
+    assert!(parse_blob(&bad_token).is_none());
+
+    let mut unbalanced = Blob::default();
+    unbalanced.begin("").end().end();
+    assert!(parse_blob(&unbalanced.finish()).is_none());
+
+    let mut deep = Blob::default();
+    (0..=MAX_DEPTH).for_each(|_| {
+        deep.begin("n");
+    });
+    (0..=MAX_DEPTH).for_each(|_| {
+        deep.end();
+    });
+    assert!(parse_blob(&deep.finish()).is_none());
+}
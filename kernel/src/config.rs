@@ -1,4 +1,106 @@
 //! Configuration management for the karatOS kernel
+//!
+//! Kernel sizing is chosen at compile time with cargo features and lands
+//! in the typed constants below, which the scheduler, logger and shell
+//! use directly. Where several features of one group are enabled, the
+//! largest wins.
+
+use core::fmt::{self, Write};
+
+use crate::board::BOARD;
+use crate::logger::Level;
+
+/// Task slots per priority level (`tasks_16`, `tasks_32`; default 8)
+pub const MAX_TASKS: usize = if cfg!(feature = "tasks_32") {
+    32
+} else if cfg!(feature = "tasks_16") {
+    16
+} else {
+    8
+};
+
+/// Event queue depth per priority level (`events_32`, `events_64`; default 16)
+pub const EVENT_QUEUE_DEPTH: usize = if cfg!(feature = "events_64") {
+    64
+} else if cfg!(feature = "events_32") {
+    32
+} else {
+    16
+};
+
+/// Scheduler tick rate in Hz (`tick_100hz`, `tick_10khz`; default 1 kHz)
+///
+/// The nRF52840 RTC and the HiFive1 mtime both count at 32.768 kHz, which
+/// only powers of two divide, so those boards get the nearest exact rates
+/// instead: 128, 8192 and 1024 Hz.
+pub const TICK_HZ: u32 = if cfg!(any(feature = "board_nrf52840", feature = "board_hifive1")) {
+    if cfg!(feature = "tick_10khz") {
+        8192
+    } else if cfg!(feature = "tick_100hz") {
+        128
+    } else {
+        1024
+    }
+} else if cfg!(feature = "tick_10khz") {
+    10_000
+} else if cfg!(feature = "tick_100hz") {
+    100
+} else {
+    1000
+};
+
+/// Lines kept by the in-memory logger (`logger_small`, `logger_large`)
+///
+/// Without either feature: 16 lines on boards with under 32 KiB of RAM,
+/// 100 otherwise.
+pub const LOG_LINES: usize = if cfg!(feature = "logger_large") {
+    256
+} else if cfg!(feature = "logger_small") || BOARD.ram.size < 32 * 1024 {
+    16
+} else {
+    100
+};
+
+/// Most verbose log level compiled in (`log_max_error`, `log_max_warn`,
+/// `log_max_info`, `log_max_debug`; default trace)
+///
+/// Log macros above this level compile to nothing, format strings
+/// included; the shell's `log level` filters further at run time.
+pub const LOG_MAX_LEVEL: Level = if cfg!(feature = "log_max_debug") {
+    Level::Debug
+} else if cfg!(feature = "log_max_info") {
+    Level::Info
+} else if cfg!(feature = "log_max_warn") {
+    Level::Warn
+} else if cfg!(feature = "log_max_error") {
+    Level::Error
+} else {
+    Level::Trace
+};
+
+/// Whether the UART shell is built in (`shell`)
+pub const SHELL_ENABLED: bool = cfg!(feature = "shell");
+
+/// Print the active configuration on the console (the shell's `config`)
+#[allow(dead_code)]
+pub fn print() {
+    let runtime = get_runtime_config();
+    let build = get_build_config();
+    field("tasks", format_args!("{} per priority, {} total", MAX_TASKS, MAX_TASKS * 4));
+    field("event queue", format_args!("{} per priority", EVENT_QUEUE_DEPTH));
+    field("tick", format_args!("{} Hz", runtime.timer_frequency));
+    field("log lines", format_args!("{}", LOG_LINES));
+    field("log level", format_args!("{} (built up to {})", crate::logger::level(), LOG_MAX_LEVEL));
+    field("shell", format_args!("{}", if SHELL_ENABLED { "on" } else { "off" }));
+    field("arch", format_args!("{} ({})", get_target_info().arch, BOARD.triple));
+    field("pointer", format_args!("{}-bit {}-endian", build.pointer_width, build.endianness));
+}
+
+fn field(name: &str, value: fmt::Arguments) {
+    let mut text = heapless::String::<96>::new();
+    let _ = write!(text, " {:<12}: {}", name, value);
+    crate::arch::early_println(&text);
+}
 
 /// Target platform information
 #[allow(dead_code)]
@@ -15,7 +117,7 @@ pub const fn get_target_info() -> TargetInfo {
     {
         TargetInfo {
             arch: "ARM Cortex-M",
-            platform: "thumbv7m-none-eabi",
+            platform: BOARD.triple,
             features: &["arm", "cortex-m"],
         }
     }
@@ -24,7 +126,7 @@ pub const fn get_target_info() -> TargetInfo {
     {
         TargetInfo {
             arch: "RISC-V",
-            platform: "riscv32imac-unknown-none-elf",
+            platform: BOARD.triple,
             features: &["riscv", "riscv32"],
         }
     }
@@ -54,8 +156,8 @@ pub const fn get_runtime_config() -> RuntimeConfig {
     RuntimeConfig {
         enable_scheduler_stats: true,
         enable_debug_output: true,
-        max_tasks: 8,
-        timer_frequency: 1000, // 1KHz
+        max_tasks: MAX_TASKS,
+        timer_frequency: TICK_HZ,
     }
 }
 
@@ -0,0 +1,135 @@
+//! Kernel path coverage
+//! Counters on the kernel's decision points, to see which ones a workload reaches (`coverage`)
+//!
+//! Each `Path` counts how often the kernel went that way: the branches of
+//! a scheduling pick, events queued or dropped on a full queue, hot-slot
+//! hits and misses, and the fault handlers. Host tools read and clear the
+//! counters with the host protocol's coverage request. Without the
+//! feature `hit` compiles to nothing and `count` reads zero, so call
+//! sites need no `cfg` guards.
+//!
+//! Counters are kept per path, not per task or priority level, and wrap
+//! at 32 bits. A path's index is part of the host protocol, so new paths
+//! go at the end.
+
+#[cfg(feature = "coverage")]
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+/// Paths counted
+pub const PATH_COUNT: usize = 14;
+
+/// A counted kernel path
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Path {
+    /// Pick took the task in the hot slot
+    HotSlotHit = 0,
+    /// Hot slot held a task that was no longer ready
+    HotSlotStale = 1,
+    /// Pick searched the task table round-robin
+    RescheduleSearch = 2,
+    /// Search found no ready task
+    RescheduleEmpty = 3,
+    /// Pick kept the current task without searching
+    KeepCurrent = 4,
+    /// Multi-priority executor found no level with a task to run
+    ExecutorIdle = 5,
+    /// Event queued at its priority level
+    EventQueued = 6,
+    /// Event dropped, its priority queue full
+    EventQueueFull = 7,
+    /// Wake-up displaced an earlier one from the hot slot
+    HotSlotDisplaced = 8,
+    /// Event woke no waiting task
+    EventNoWaiter = 9,
+    /// Sleeping task woken by its timer
+    TimerWake = 10,
+    /// Supervisor told of a task fault
+    TaskFault = 11,
+    /// CPU fault handler entered (HardFault and the other ARM faults, an
+    /// unhandled RISC-V trap)
+    CpuFault = 12,
+    /// Panic handler entered
+    Panic = 13,
+}
+
+impl Path {
+    /// Every path, by index
+    #[allow(dead_code)]
+    pub const ALL: [Path; PATH_COUNT] = [
+        Path::HotSlotHit,
+        Path::HotSlotStale,
+        Path::RescheduleSearch,
+        Path::RescheduleEmpty,
+        Path::KeepCurrent,
+        Path::ExecutorIdle,
+        Path::EventQueued,
+        Path::EventQueueFull,
+        Path::HotSlotDisplaced,
+        Path::EventNoWaiter,
+        Path::TimerWake,
+        Path::TaskFault,
+        Path::CpuFault,
+        Path::Panic,
+    ];
+
+    #[allow(dead_code)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Path::HotSlotHit => "hot_slot_hit",
+            Path::HotSlotStale => "hot_slot_stale",
+            Path::RescheduleSearch => "reschedule_search",
+            Path::RescheduleEmpty => "reschedule_empty",
+            Path::KeepCurrent => "keep_current",
+            Path::ExecutorIdle => "executor_idle",
+            Path::EventQueued => "event_queued",
+            Path::EventQueueFull => "event_queue_full",
+            Path::HotSlotDisplaced => "hot_slot_displaced",
+            Path::EventNoWaiter => "event_no_waiter",
+            Path::TimerWake => "timer_wake",
+            Path::TaskFault => "task_fault",
+            Path::CpuFault => "cpu_fault",
+            Path::Panic => "panic",
+        }
+    }
+}
+
+#[cfg(feature = "coverage")]
+static COUNTS: [AtomicU32; PATH_COUNT] = [const { AtomicU32::new(0) }; PATH_COUNT];
+
+/// Count a pass through `path`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn hit(_path: Path) {
+    #[cfg(feature = "coverage")]
+    COUNTS[_path as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Passes through `path` so far; zero without the feature
+#[allow(dead_code)]
+pub fn count(_path: Path) -> u32 {
+    #[cfg(feature = "coverage")]
+    {
+        COUNTS[_path as usize].load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "coverage"))]
+    {
+        0
+    }
+}
+
+/// Read the count of `path` and zero it
+#[allow(dead_code)]
+pub fn take(_path: Path) -> u32 {
+    #[cfg(feature = "coverage")]
+    {
+        COUNTS[_path as usize].swap(0, Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "coverage"))]
+    {
+        0
+    }
+}
@@ -0,0 +1,493 @@
+//! Persistent crash records
+//! Panic and fault state kept in `.noinit` RAM across a warm reset
+//!
+//! The panic handler and the fatal trap paths fill one `CrashRecord`
+//! (cause, registers, task table and log tail) before they report. The
+//! record sits in the linker's `.noinit` section, which startup code does
+//! not zero, so after a reboot `init` finds it by its magic value and
+//! checksum. It stays available through `last`/`print` until `clear`.
+//!
+//! Resets that skip the panic handler, such as a watchdog firing, leave
+//! no record. For those, every logged line is also copied into one of
+//! two `.noinit` log banks as it is written. `init_log` makes the bank
+//! the previous boot wrote the read-only `previous_log` and starts
+//! writing the other, so the lines that led up to any warm reset can
+//! be read back (the shell's `log prev`).
+//!
+//! A power cycle loses the record and the banks; only warm resets
+//! preserve RAM.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::mem::MaybeUninit;
+
+use crate::arch::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::arch::RegisterDump;
+use crate::scheduler::{Task, TaskPriority, TaskState};
+
+/// Marks a complete record ("KCRD")
+pub const CRASH_MAGIC: u32 = 0x4B43_5244;
+
+/// Task table entries kept in a record
+pub const CRASH_TASKS: usize = 16;
+
+/// Logger lines kept in a record (fewer on small-RAM boards)
+pub const CRASH_LOG_LINES: usize = if crate::board::BOARD.ram.size < 32 * 1024 { 2 } else { 8 };
+
+/// Log lines each persistent log bank keeps (fewer on small-RAM boards)
+pub const PERSISTENT_LOG_LINES: usize = if crate::board::BOARD.ram.size < 32 * 1024 { 4 } else { 16 };
+
+/// Marks a set-up log bank ("KLOG")
+const LOG_MAGIC: u32 = 0x4B4C_4F47;
+
+const MESSAGE_LEN: usize = 96;
+const FILE_LEN: usize = 48;
+/// Bytes of text each log bank line holds
+pub const LOG_LINE_LEN: usize = 64;
+
+/// What ended the previous boot
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CrashKind {
+    Panic = 1,
+    Fault = 2,
+    Unknown = 0,
+}
+
+impl CrashKind {
+    pub const fn name(self) -> &'static str {
+        match self {
+            CrashKind::Panic => "panic",
+            CrashKind::Fault => "fault",
+            CrashKind::Unknown => "unknown",
+        }
+    }
+
+    const fn from_raw(raw: u32) -> Self {
+        match raw {
+            1 => CrashKind::Panic,
+            2 => CrashKind::Fault,
+            _ => CrashKind::Unknown,
+        }
+    }
+}
+
+/// Fixed-size UTF-8 text, truncated on a character boundary
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Text<const N: usize> {
+    len: u32,
+    bytes: [u8; N],
+}
+
+impl<const N: usize> Text<N> {
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_str(&self) -> &str {
+        let len = (self.len as usize).min(N);
+        // Bytes come from RAM that survived a reset; never trust them blindly
+        core::str::from_utf8(&self.bytes[..len]).unwrap_or("<corrupt>")
+    }
+}
+
+impl<const N: usize> Write for Text<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let used = self.len as usize;
+        let mut take = s.len().min(N - used);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.bytes[used..used + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take as u32;
+        Ok(())
+    }
+}
+
+/// One task table entry at the time of the crash
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TaskRecord {
+    id: u32,
+    priority: u8,
+    /// 0 ready, 1 running, 2 waiting, 3 sleeping, 4 completed
+    state: u8,
+    _reserved: [u8; 2],
+    /// Event id for waiting tasks, wake time for sleeping ones
+    detail: u32,
+}
+
+impl TaskRecord {
+    fn from_task(task: &Task) -> Self {
+        let (state, detail) = match task.state {
+            TaskState::Ready => (0, 0),
+            TaskState::Running => (1, 0),
+            TaskState::WaitingForEvent(event_id) => (2, event_id),
+            TaskState::Sleeping(wake_time) => (3, wake_time.ticks() as u32),
+            TaskState::Completed => (4, 0),
+        };
+        Self { id: task.id as u32, priority: task.priority as u8, state, _reserved: [0; 2], detail }
+    }
+}
+
+impl fmt::Display for TaskRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let priority = match self.priority {
+            0 => "critical",
+            1 => "high",
+            2 => "normal",
+            _ => "low",
+        };
+        write!(f, "{:>3} {:<8} ", self.id, priority)?;
+        match self.state {
+            0 => write!(f, "ready"),
+            1 => write!(f, "running"),
+            2 => write!(f, "waiting 0x{:08x}", self.detail),
+            3 => write!(f, "sleeping until {}", self.detail),
+            _ => write!(f, "completed"),
+        }
+    }
+}
+
+/// Crash state saved for the next boot
+///
+/// Every field is plain integers or bytes so any RAM contents are a valid
+/// value; `magic` and `checksum` decide whether the record is real.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CrashRecord {
+    magic: u32,
+    checksum: u32,
+    kind: u32,
+    registers: [u32; 6],
+    line: u32,
+    /// Index into `tasks` of the task that was running, or `u32::MAX`
+    current: u32,
+    task_count: u32,
+    log_count: u32,
+    tasks: [TaskRecord; CRASH_TASKS],
+    message: Text<MESSAGE_LEN>,
+    file: Text<FILE_LEN>,
+    log: [Text<LOG_LINE_LEN>; CRASH_LOG_LINES],
+}
+
+impl CrashRecord {
+    pub fn kind(&self) -> CrashKind {
+        CrashKind::from_raw(self.kind)
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    /// Source location as (file, line), if one was recorded
+    pub fn location(&self) -> Option<(&str, u32)> {
+        if self.file.len == 0 {
+            None
+        } else {
+            Some((self.file.as_str(), self.line))
+        }
+    }
+
+    pub fn registers(&self) -> RegisterDump {
+        RegisterDump::from_values(self.registers)
+    }
+
+    /// Checksum over everything after the `checksum` field
+    fn compute_checksum(&self) -> u32 {
+        let words = core::mem::size_of::<Self>() / 4;
+        let base = self as *const Self as *const u32;
+        // FNV-1a over 32-bit words
+        (2..words).fold(0x811C_9DC5u32, |hash, i| {
+            let word = unsafe { core::ptr::read_volatile(base.add(i)) };
+            (hash ^ word).wrapping_mul(0x0100_0193)
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == CRASH_MAGIC && self.checksum == self.compute_checksum()
+    }
+}
+
+impl fmt::Display for CrashRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "kind: {}", self.kind().name())?;
+        writeln!(f, "message: {}", self.message())?;
+        if let Some((file, line)) = self.location() {
+            writeln!(f, "at: {}:{}", file, line)?;
+        }
+        writeln!(f, "regs: {}", self.registers())?;
+        writeln!(f, "tasks:")?;
+        let count = (self.task_count as usize).min(CRASH_TASKS);
+        for (index, task) in self.tasks[..count].iter().enumerate() {
+            let marker = if index as u32 == self.current { '*' } else { ' ' };
+            writeln!(f, " {}{}", marker, task)?;
+        }
+        writeln!(f, "log tail:")?;
+        let lines = (self.log_count as usize).min(CRASH_LOG_LINES);
+        for line in &self.log[..lines] {
+            writeln!(f, "  {}", line.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+struct CrashSlot(UnsafeCell<MaybeUninit<CrashRecord>>);
+unsafe impl Sync for CrashSlot {} // Single-core assumption
+
+#[link_section = ".noinit.crash"]
+static CRASH: CrashSlot = CrashSlot(UnsafeCell::new(MaybeUninit::uninit()));
+
+/// Set by `init` when the previous boot left a valid record
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+fn slot() -> *mut CrashRecord {
+    CRASH.0.get() as *mut CrashRecord
+}
+
+/// Look for a record from the previous boot
+///
+/// Registered as a boot init step; a record is not an init failure.
+pub fn init() -> crate::kernel::InitResult {
+    let record = unsafe { &*slot() };
+    if record.is_valid() {
+        PRESENT.store(true, Ordering::Relaxed);
+        let mut line = heapless::String::<128>::new();
+        let _ = write!(line, "[crash] previous boot ended in {}: {}", record.kind().name(), record.message());
+        crate::arch::early_println(&line);
+    }
+    Ok(())
+}
+
+/// Record left by the previous boot, until `clear` is called
+#[allow(dead_code)]
+pub fn last() -> Option<&'static CrashRecord> {
+    if PRESENT.load(Ordering::Relaxed) {
+        Some(unsafe { &*slot() })
+    } else {
+        None
+    }
+}
+
+/// Print the previous boot's record on the console
+#[allow(dead_code)]
+pub fn print() {
+    let Some(record) = last() else {
+        crate::arch::early_println("[crash] no record");
+        return;
+    };
+    let mut out = Console;
+    let _ = writeln!(out, "[crash] record from previous boot");
+    let _ = write!(out, "{}", record);
+}
+
+/// Forget the previous boot's record
+#[allow(dead_code)]
+pub fn clear() {
+    PRESENT.store(false, Ordering::Relaxed);
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!((*slot()).magic), 0);
+    }
+}
+
+/// Save a panic for the next boot; called from the panic handler
+#[allow(dead_code)]
+pub fn record_panic(info: &core::panic::PanicInfo, registers: &RegisterDump) {
+    let record = begin(CrashKind::Panic, registers);
+    let _ = write!(record.message, "{}", info.message());
+    if let Some(location) = info.location() {
+        let _ = record.file.write_str(location.file());
+        record.line = location.line();
+    }
+    finish(record);
+}
+
+/// Save a fatal trap for the next boot; called from fault handlers
+#[allow(dead_code)]
+pub fn record_fault(description: fmt::Arguments, registers: &RegisterDump) {
+    let record = begin(CrashKind::Fault, registers);
+    let _ = record.message.write_fmt(description);
+    finish(record);
+}
+
+/// Start a new record, overwriting any previous one
+///
+/// The magic is cleared first so a crash while recording never leaves a
+/// half-written record that looks valid.
+fn begin(kind: CrashKind, registers: &RegisterDump) -> &'static mut CrashRecord {
+    PRESENT.store(false, Ordering::Relaxed);
+    let record = unsafe { &mut *slot() };
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(record.magic), 0);
+    }
+    record.kind = kind as u32;
+    record.registers = registers.values();
+    record.line = 0;
+    record.message.clear();
+    record.file.clear();
+
+    let current = crate::scheduler::crash_snapshot().task;
+    record.current = u32::MAX;
+    record.task_count = 0;
+    crate::scheduler::crash_visit_tasks(|task| {
+        let index = record.task_count as usize;
+        if index < CRASH_TASKS {
+            if current.as_ref().is_some_and(|running| same_task(running, task)) {
+                record.current = index as u32;
+            }
+            record.tasks[index] = TaskRecord::from_task(task);
+            record.task_count += 1;
+        }
+    });
+
+    record.log_count = 0;
+    crate::logger::Logger::visit_last_lines(CRASH_LOG_LINES, |line| {
+        let entry = &mut record.log[record.log_count as usize];
+        entry.clear();
+        let _ = entry.write_str(line);
+        record.log_count += 1;
+    });
+    record
+}
+
+fn same_task(a: &Task, b: &Task) -> bool {
+    a.id == b.id && a.priority == b.priority
+}
+
+fn finish(record: &mut CrashRecord) {
+    record.checksum = record.compute_checksum();
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(record.magic), CRASH_MAGIC);
+    }
+}
+
+/// Log lines of one boot, kept across a warm reset
+///
+/// Like `CrashRecord`, any RAM contents are a valid value; `magic` tells
+/// a set-up bank from garbage and every line is checked when read.
+#[repr(C)]
+struct LogBank {
+    magic: u32,
+    /// Counts boots; of two set-up banks the higher one is the newer
+    generation: u32,
+    /// Lines written during that boot; line n is at `n % PERSISTENT_LOG_LINES`
+    written: u32,
+    lines: [Text<LOG_LINE_LEN>; PERSISTENT_LOG_LINES],
+}
+
+impl LogBank {
+    fn generation(&self) -> Option<u32> {
+        (self.magic == LOG_MAGIC).then_some(self.generation)
+    }
+}
+
+struct LogBanks(UnsafeCell<MaybeUninit<[LogBank; 2]>>);
+unsafe impl Sync for LogBanks {} // Single-core assumption
+
+#[link_section = ".noinit.log"]
+static LOG_BANKS: LogBanks = LogBanks(UnsafeCell::new(MaybeUninit::uninit()));
+
+const NO_BANK: usize = usize::MAX;
+
+/// Bank this boot writes; lines logged before `init_log` are not kept
+static CURRENT_BANK: AtomicUsize = AtomicUsize::new(NO_BANK);
+
+/// Bank the previous boot wrote, if one was set up
+static PREVIOUS_BANK: AtomicUsize = AtomicUsize::new(NO_BANK);
+
+fn bank(index: usize) -> &'static mut LogBank {
+    unsafe { &mut (*LOG_BANKS.0.get().cast::<[LogBank; 2]>())[index] }
+}
+
+/// Keep the previous boot's log bank and start writing the other
+///
+/// Registered as an early boot init step, so the rest of init is kept.
+pub fn init_log() -> crate::kernel::InitResult {
+    let generations = [bank(0).generation(), bank(1).generation()];
+    let previous = match generations {
+        [Some(a), Some(b)] => Some(if b > a { 1 } else { 0 }),
+        [Some(_), None] => Some(0),
+        [None, Some(_)] => Some(1),
+        [None, None] => None,
+    };
+    let current = previous.map_or(0, |index| 1 - index);
+    let generation = previous.map_or(1, |index| bank(index).generation.wrapping_add(1));
+
+    let target = bank(current);
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(target.magic), 0);
+    }
+    target.generation = generation;
+    target.written = 0;
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(target.magic), LOG_MAGIC);
+    }
+
+    if let Some(index) = previous.filter(|&index| bank(index).written > 0) {
+        PREVIOUS_BANK.store(index, Ordering::Relaxed);
+        let mut line = heapless::String::<80>::new();
+        let kept = (bank(index).written as usize).min(PERSISTENT_LOG_LINES);
+        let _ = write!(line, "[log] {} lines kept from the previous boot, see 'log prev'", kept);
+        crate::arch::early_println(&line);
+    }
+    CURRENT_BANK.store(current, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Copy a logged line into this boot's bank; called by `Logger`
+pub fn persist_line(line: &str) {
+    let index = CURRENT_BANK.load(Ordering::Relaxed);
+    if index == NO_BANK {
+        return;
+    }
+    let target = bank(index);
+    let entry = &mut target.lines[target.written as usize % PERSISTENT_LOG_LINES];
+    // Empty until complete, so a reset mid-copy leaves no torn line
+    entry.clear();
+    let mut text = Text::<LOG_LINE_LEN> { len: 0, bytes: [0; LOG_LINE_LEN] };
+    let _ = text.write_str(line);
+    entry.bytes = text.bytes;
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(entry.len), text.len);
+    }
+    target.written = target.written.wrapping_add(1);
+}
+
+/// Address of the bank this boot writes, for debuggers; null before `init_log`
+#[allow(dead_code)]
+pub fn current_log_bank() -> *const u8 {
+    match CURRENT_BANK.load(Ordering::Relaxed) {
+        NO_BANK => core::ptr::null(),
+        index => (bank(index) as *const LogBank).cast(),
+    }
+}
+
+/// Visit the lines kept from the previous boot, oldest first
+///
+/// Returns false if the previous boot left none.
+#[allow(dead_code)]
+pub fn previous_log(mut f: impl FnMut(&str)) -> bool {
+    let index = PREVIOUS_BANK.load(Ordering::Relaxed);
+    if index == NO_BANK {
+        return false;
+    }
+    let previous = bank(index);
+    let written = previous.written as usize;
+    for seq in written.saturating_sub(PERSISTENT_LOG_LINES)..written {
+        f(previous.lines[seq % PERSISTENT_LOG_LINES].as_str());
+    }
+    true
+}
+
+/// Unbuffered console writer for `print`
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::arch::console_write(s.as_bytes());
+        Ok(())
+    }
+}
+
+// Keeps TaskPriority's discriminants in step with TaskRecord's decoding
+const _: () = assert!(TaskPriority::Critical as u8 == 0 && TaskPriority::Low as u8 == 3);
@@ -0,0 +1,33 @@
+//! critical-section implementation
+//! `critical_section::with` on the kernel's interrupt mask (`critical_section_impl`)
+//!
+//! Crates that share state with interrupt handlers through the
+//! `critical-section` crate (heapless' pools and queues, portable-atomic
+//! built for it, embassy-executor on cores without atomics, most HALs)
+//! need one implementation linked in. This one is `arch::mask` and
+//! `arch::restore`, the pair under every kernel critical section
+//! (`arch::free`): `acquire` saves whether interrupts were on and masks
+//! them, `release` unmasks only if they were. So a section nests in
+//! another one, in a kernel critical section, and in an interrupt
+//! handler, and leaves the mask as it found it. As elsewhere, the
+//! interrupt-off window is measured (`instrumentation`) only by the
+//! outermost section.
+//!
+//! The targets are single-core, so masking shuts out every other
+//! context; on the simulator it takes the mask lock the interrupt
+//! threads take too. A board HAL that brings its own implementation
+//! must be built without this feature, since the linker takes only one.
+
+struct KernelCriticalSection;
+
+critical_section::set_impl!(KernelCriticalSection);
+
+unsafe impl critical_section::Impl for KernelCriticalSection {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        crate::arch::mask()
+    }
+
+    unsafe fn release(enabled: critical_section::RawRestoreState) {
+        crate::arch::restore(enabled);
+    }
+}
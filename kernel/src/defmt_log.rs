@@ -0,0 +1,95 @@
+//! defmt logger
+//! Global `defmt` logger keeping encoded frames in a RAM ring (`log_defmt`)
+//!
+//! A `defmt::info!` and friends send the index of their format string
+//! and the raw argument bytes instead of formatted text. The strings
+//! stay in the ELF on the host, so they take no flash and no console
+//! bandwidth on the target. Frames are rzCOBS-encoded and end in 0x00.
+//! A reader that starts mid-frame, or whose oldest bytes were already
+//! overwritten, picks up again at the next 0x00. Host tools fetch the
+//! ring with the host protocol's defmt request and decode it with
+//! `defmt-print -e <elf>`. With `log_rtt` the frames also go to the RTT
+//! "defmt" channel, which probe-rs decodes as they arrive.
+//!
+//! The levels kept are chosen at build time with `DEFMT_LOG` (e.g.
+//! `DEFMT_LOG=info`), apart from the kernel log filters; the kernel's
+//! own `log_*!` macros still write text lines to `logger`.
+
+use core::cell::UnsafeCell;
+
+use crate::arch::atomic::{AtomicBool, Ordering};
+
+/// Ring size; a quarter of the text log's footprint on small boards
+const RING_BYTES: usize = if crate::config::LOG_LINES <= 16 { 256 } else { 1024 };
+
+struct Ring {
+    bytes: [u8; RING_BYTES],
+    /// Bytes ever written; the newest is at `(written - 1) % RING_BYTES`
+    written: u32,
+}
+
+struct DefmtRing(UnsafeCell<Ring>);
+unsafe impl Sync for DefmtRing {} // Only touched with interrupts off
+
+static RING: DefmtRing = DefmtRing(UnsafeCell::new(Ring { bytes: [0; RING_BYTES], written: 0 }));
+
+struct FrameEncoder(UnsafeCell<defmt::Encoder>);
+unsafe impl Sync for FrameEncoder {} // Only touched between acquire and release
+
+static ENCODER: FrameEncoder = FrameEncoder(UnsafeCell::new(defmt::Encoder::new()));
+
+/// Interrupt state to restore when the frame is done
+static RESTORE_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+
+fn push(bytes: &[u8]) {
+    #[cfg(feature = "log_rtt")]
+    crate::drivers::rtt::write(crate::drivers::rtt::DEFMT, bytes);
+    let ring = unsafe { &mut *RING.0.get() };
+    for &byte in bytes {
+        ring.bytes[ring.written as usize % RING_BYTES] = byte;
+        ring.written = ring.written.wrapping_add(1);
+    }
+}
+
+defmt::timestamp!("{=u64}", crate::kernel::time::now());
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {
+        // Frames from an interrupt must not land inside a task's frame
+        RESTORE_INTERRUPTS.store(crate::arch::mask(), Ordering::Relaxed);
+        unsafe { (*ENCODER.0.get()).start_frame(push) };
+    }
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {
+        (*ENCODER.0.get()).end_frame(push);
+        crate::arch::restore(RESTORE_INTERRUPTS.load(Ordering::Relaxed));
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        (*ENCODER.0.get()).write(bytes, push);
+    }
+}
+
+/// Copy ring bytes from stream offset `first` on into `out`
+///
+/// Offsets count every byte written since boot. Returns the offset of
+/// the first byte copied, later than `first` if those bytes were
+/// overwritten, and how many were copied.
+#[allow(dead_code)]
+pub fn read_from(first: u32, out: &mut [u8]) -> (u32, usize) {
+    crate::arch::free(|| {
+        let ring = unsafe { &*RING.0.get() };
+        let oldest = ring.written.saturating_sub(RING_BYTES as u32);
+        let start = first.clamp(oldest, ring.written);
+        let count = ((ring.written - start) as usize).min(out.len());
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = ring.bytes[(start as usize + i) % RING_BYTES];
+        }
+        (start, count)
+    })
+}
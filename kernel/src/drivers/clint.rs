@@ -0,0 +1,129 @@
+//! RISC-V CLINT Tick Driver
+//! Kernel tick from the machine timer (mtime/mtimecmp)
+//!
+//! The mtime frequency is board specific (10 MHz on QEMU virt, 32.768 kHz
+//! on the HiFive1), so the caller passes it in together with the tick rate.
+//! `kernel::time` counts ticks as `TICK_HZ`, so boards pick one mtime
+//! divides exactly; the HiFive1 gets powers of two for that.
+//!
+//! With `tick_dynamic` the compare register is set for the next deadline
+//! instead of the next tick. Ticks are counted from the mtime of the last
+//! announced tick boundary, so announcing late loses no time; mtime is
+//! 64 bits, so any wait fits in one compare.
+
+#[cfg(feature = "tick_dynamic")]
+use core::cell::UnsafeCell;
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::memory::checked::{reg_read, reg_write};
+
+const MTIMECMP_OFFSET: usize = 0x4000; // hart 0
+const MTIME_OFFSET: usize = 0xBFF8;
+
+/// mcause interrupt code for the machine timer
+pub const MACHINE_TIMER_INT: usize = 7;
+
+static CLINT_BASE: AtomicU32 = AtomicU32::new(0);
+static PERIOD: AtomicU32 = AtomicU32::new(0);
+
+/// Start a periodic tick at roughly `tick_hz`, returning the actual rate
+pub fn init(clint_base: usize, mtime_hz: u32, tick_hz: u32) -> u32 {
+    let period = (mtime_hz / tick_hz.max(1)).max(1);
+    CLINT_BASE.store(clint_base as u32, Ordering::Relaxed);
+    PERIOD.store(period, Ordering::Relaxed);
+
+    #[cfg(feature = "tick_dynamic")]
+    with_announced(|announced| *announced = mtime());
+    set_compare(mtime() + period as u64);
+
+    unsafe {
+        riscv::register::mie::set_mtimer();
+    }
+
+    crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+
+    mtime_hz / period
+}
+
+/// Machine timer interrupt handler: schedule the next tick and count this one
+#[cfg(not(feature = "tick_dynamic"))]
+pub fn on_interrupt() {
+    let period = PERIOD.load(Ordering::Relaxed) as u64;
+    // Re-arm relative to now so a late interrupt does not cause a burst
+    set_compare(mtime() + period);
+    crate::kernel::time::tick();
+}
+
+/// mtime at the last announced tick boundary
+#[cfg(feature = "tick_dynamic")]
+struct AnnouncedCell(UnsafeCell<u64>);
+#[cfg(feature = "tick_dynamic")]
+unsafe impl Sync for AnnouncedCell {} // Only touched with interrupts off
+
+#[cfg(feature = "tick_dynamic")]
+static ANNOUNCED: AnnouncedCell = AnnouncedCell(UnsafeCell::new(0));
+
+/// Run `f` on the announced mtime with interrupts off, restoring their state
+#[cfg(feature = "tick_dynamic")]
+fn with_announced<R>(f: impl FnOnce(&mut u64) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *ANNOUNCED.0.get() }))
+}
+
+/// Whole ticks passed since the last announcement
+#[cfg(feature = "tick_dynamic")]
+pub fn pending_ticks() -> u32 {
+    let period = PERIOD.load(Ordering::Relaxed) as u64;
+    if period == 0 {
+        // Not started yet
+        return 0;
+    }
+    let announced = with_announced(|announced| *announced);
+    (mtime().saturating_sub(announced) / period) as u32
+}
+
+/// Interrupt `hw_ticks` ticks after the last announcement; at once if
+/// that has passed, since the compare fires while mtime is at or past it
+#[cfg(feature = "tick_dynamic")]
+pub fn program(hw_ticks: u32) {
+    let period = PERIOD.load(Ordering::Relaxed) as u64;
+    with_announced(|announced| set_compare(*announced + hw_ticks as u64 * period));
+}
+
+/// Machine timer interrupt handler: announce the ticks that passed, then
+/// program the next deadline
+#[cfg(feature = "tick_dynamic")]
+pub fn on_interrupt() {
+    let ticks = pending_ticks();
+    let period = PERIOD.load(Ordering::Relaxed) as u64;
+    with_announced(|announced| *announced += ticks as u64 * period);
+    crate::kernel::time::announce(ticks);
+    crate::kernel::time::program_next();
+}
+
+/// Read the 64-bit mtime counter
+pub fn mtime() -> u64 {
+    let base = CLINT_BASE.load(Ordering::Relaxed) as usize;
+    let (lo, hi) = (base + MTIME_OFFSET, base + MTIME_OFFSET + 4);
+
+    unsafe {
+        // Re-read if the low word wrapped between the two reads
+        loop {
+            let high = reg_read(hi);
+            let low = reg_read(lo);
+            if reg_read(hi) == high {
+                return ((high as u64) << 32) | low as u64;
+            }
+        }
+    }
+}
+
+fn set_compare(value: u64) {
+    let base = CLINT_BASE.load(Ordering::Relaxed) as usize;
+    let (lo, hi) = (base + MTIMECMP_OFFSET, base + MTIMECMP_OFFSET + 4);
+
+    unsafe {
+        // Raise the high word first so no intermediate value fires early
+        reg_write(hi, u32::MAX);
+        reg_write(lo, value as u32);
+        reg_write(hi, (value >> 32) as u32);
+    }
+}
@@ -0,0 +1,69 @@
+//! Console receive
+//! Bytes typed on the console UART, buffered for the shell
+//!
+//! Where the console UART's receive interrupt is routed (PL011, STM32
+//! USART2, NS16550A and SiFive UART through the PLIC), `on_interrupt`
+//! drains the hardware FIFO into `RX` and the pipe wakes its reader. The
+//! ESP32-C3 UART is not wired through the interrupt matrix yet, so its
+//! reader calls `poll` instead. The nRF52840 UARTE only receives by
+//! EasyDMA and has no receive path here.
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::kernel::{InitResult, Pipe};
+
+/// Received bytes buffered between the ISR and the reader
+pub const RX_BUFFER: usize = 64;
+
+/// Console input, written by the ISR (or `poll`) and read by the shell
+pub static RX: Pipe<RX_BUFFER> = Pipe::new();
+
+static INTERRUPT_DRIVEN: AtomicBool = AtomicBool::new(false);
+static OVERRUNS: AtomicU32 = AtomicU32::new(0);
+
+/// Unmask the receive interrupt where the board has one
+pub fn init() -> InitResult {
+    if cfg!(feature = "board_nrf52840") {
+        return Err("UARTE receive needs EasyDMA");
+    }
+    if crate::arch::console_rx_interrupt_enable() {
+        INTERRUPT_DRIVEN.store(true, Ordering::Relaxed);
+        crate::power::register_wakeup(crate::power::WakeupSource::UartRx);
+    }
+    Ok(())
+}
+
+/// Console UART receive interrupt: move the FIFO into `RX`
+#[allow(dead_code)]
+pub fn on_interrupt() {
+    drain();
+}
+
+/// Move received bytes into `RX` on boards without a receive interrupt
+#[allow(dead_code)]
+pub fn poll() {
+    if !interrupt_driven() {
+        drain();
+    }
+}
+
+/// Whether `RX` fills itself, so readers may block on it
+#[allow(dead_code)]
+pub fn interrupt_driven() -> bool {
+    INTERRUPT_DRIVEN.load(Ordering::Relaxed)
+}
+
+/// Bytes dropped because `RX` was full
+#[allow(dead_code)]
+pub fn overruns() -> u32 {
+    OVERRUNS.load(Ordering::Relaxed)
+}
+
+fn drain() {
+    // Always empty the FIFO so a level-triggered interrupt deasserts
+    while let Some(byte) = crate::arch::console_read() {
+        if RX.try_write(&[byte]) == 0 {
+            OVERRUNS.fetch_add(1, Ordering::Relaxed);
+            crate::metrics::counter!("uart.rx.overrun").inc();
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Debug UART
+//! Second UART of the console's kind, reserved for the GDB stub
+//!
+//! The board descriptor names it in `debug_uart`; boards set its pins
+//! and baud rate in `Board::init` when built with `gdb_stub`. Bytes that
+//! arrive while the system runs are moved into `RX` by `on_interrupt`,
+//! which wakes the stub task; once the system is stopped the stub polls
+//! the UART directly with `read` and `write`.
+
+use crate::board::UartKind;
+use crate::kernel::Pipe;
+
+/// Bytes buffered until the stub task stops the system
+pub const RX_BUFFER: usize = 32;
+
+/// Input received while the system runs
+pub static RX: Pipe<RX_BUFFER> = Pipe::new();
+
+/// Base address of the debug UART, if the board has one
+pub fn base() -> Option<usize> {
+    crate::board::active().debug_uart
+}
+
+/// Write one byte, waiting for room in the transmitter
+pub fn write(byte: u8) {
+    let Some(base) = base() else { return };
+    let reg = |offset: usize| (base + offset) as *mut u32;
+    unsafe {
+        match crate::board::active().uart_kind {
+            UartKind::Pl011 => {
+                const FR_TXFF: u32 = 1 << 5;
+                while reg(0x018).read_volatile() & FR_TXFF != 0 {}
+                reg(0x000).write_volatile(byte as u32);
+            }
+            UartKind::Stm32Usart => {
+                const SR_TXE: u32 = 1 << 7;
+                while reg(0x00).read_volatile() & SR_TXE == 0 {}
+                reg(0x04).write_volatile(byte as u32);
+            }
+            UartKind::SifiveUart => {
+                const TXDATA_FULL: u32 = 1 << 31;
+                while reg(0x00).read_volatile() & TXDATA_FULL != 0 {}
+                reg(0x00).write_volatile(byte as u32);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Read one received byte, if any
+pub fn read() -> Option<u8> {
+    let base = base()?;
+    let reg = |offset: usize| (base + offset) as *mut u32;
+    unsafe {
+        match crate::board::active().uart_kind {
+            UartKind::Pl011 => {
+                const FR_RXFE: u32 = 1 << 4;
+                (reg(0x018).read_volatile() & FR_RXFE == 0).then(|| reg(0x000).read_volatile() as u8)
+            }
+            UartKind::Stm32Usart => {
+                const SR_RXNE: u32 = 1 << 5;
+                const SR_ORE: u32 = 1 << 3;
+                (reg(0x00).read_volatile() & (SR_RXNE | SR_ORE) != 0).then(|| reg(0x04).read_volatile() as u8)
+            }
+            UartKind::SifiveUart => {
+                const RXDATA_EMPTY: u32 = 1 << 31;
+                let data = reg(0x04).read_volatile();
+                (data & RXDATA_EMPTY == 0).then_some(data as u8)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Unmask the receive interrupt; false if the board has no debug UART
+pub fn init() -> bool {
+    let Some(base) = base() else { return false };
+    let reg = |offset: usize| (base + offset) as *mut u32;
+    unsafe {
+        match crate::board::active().uart_kind {
+            UartKind::Pl011 => {
+                const IM_RX: u32 = 1 << 4;
+                const IM_RT: u32 = 1 << 6;
+                reg(0x038).write_volatile(reg(0x038).read_volatile() | IM_RX | IM_RT);
+            }
+            UartKind::Stm32Usart => {
+                const CR1_RXNEIE: u32 = 1 << 5;
+                reg(0x0C).write_volatile(reg(0x0C).read_volatile() | CR1_RXNEIE);
+            }
+            UartKind::SifiveUart => {
+                // rxwm is pending while the FIFO holds more than rxcnt (0) bytes
+                const RXCTRL_RXCNT_MASK: u32 = 0x7 << 16;
+                const IE_RXWM: u32 = 1 << 1;
+                reg(0x0C).write_volatile(reg(0x0C).read_volatile() & !RXCTRL_RXCNT_MASK);
+                reg(0x10).write_volatile(reg(0x10).read_volatile() | IE_RXWM);
+            }
+            _ => return false,
+        }
+    }
+    crate::arch::debug_uart_interrupt_enable();
+    true
+}
+
+/// Debug UART receive interrupt: move the FIFO into `RX`
+#[allow(dead_code)]
+pub fn on_interrupt() {
+    while let Some(byte) = read() {
+        // A full buffer means the stub is already on its way to stopping
+        let _ = RX.try_write(&[byte]);
+    }
+}
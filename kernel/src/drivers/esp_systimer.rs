@@ -0,0 +1,87 @@
+//! ESP32-C3 System Timer Tick Driver
+//! Kernel tick from SYSTIMER comparator 0 routed through the interrupt matrix
+//!
+//! SYSTIMER unit 0 is a 52-bit counter clocked at 16 MHz. Comparator 0 is
+//! run in period mode and its interrupt source is mapped to CPU interrupt
+//! line `TICK_CPU_INT`, which the RISC-V trap handler dispatches here.
+
+use crate::memory::checked::{reg_read, reg_write};
+
+const SYSTIMER_BASE: usize = 0x6002_3000;
+const SYSTIMER_CONF: usize = SYSTIMER_BASE;
+const SYSTIMER_UNIT0_OP: usize = SYSTIMER_BASE + 0x04;
+const SYSTIMER_TARGET0_CONF: usize = SYSTIMER_BASE + 0x34;
+const SYSTIMER_UNIT0_VALUE_HI: usize = SYSTIMER_BASE + 0x40;
+const SYSTIMER_UNIT0_VALUE_LO: usize = SYSTIMER_BASE + 0x44;
+const SYSTIMER_COMP0_LOAD: usize = SYSTIMER_BASE + 0x50;
+const SYSTIMER_INT_ENA: usize = SYSTIMER_BASE + 0x64;
+const SYSTIMER_INT_CLR: usize = SYSTIMER_BASE + 0x6C;
+
+const CONF_TARGET0_WORK_EN: u32 = 1 << 24;
+const UNIT0_OP_UPDATE: u32 = 1 << 30;
+const UNIT0_OP_VALUE_VALID: u32 = 1 << 29;
+const TARGET_CONF_PERIOD_MODE: u32 = 1 << 30;
+const TARGET_PERIOD_MAX: u32 = (1 << 26) - 1;
+const INT_TARGET0: u32 = 1 << 0;
+
+const INTERRUPT_CORE0_BASE: usize = 0x600C_2000;
+const SYSTIMER_TARGET0_INT_MAP: usize = INTERRUPT_CORE0_BASE + 0x94; // source 37
+const CPU_INT_ENABLE: usize = INTERRUPT_CORE0_BASE + 0x104;
+const CPU_INT_TYPE: usize = INTERRUPT_CORE0_BASE + 0x108;
+const CPU_INT_PRI_0: usize = INTERRUPT_CORE0_BASE + 0x114;
+const CPU_INT_THRESH: usize = INTERRUPT_CORE0_BASE + 0x194;
+
+/// SYSTIMER counter frequency
+pub const SYSTIMER_HZ: u32 = 16_000_000;
+
+/// CPU interrupt line the tick is routed to (mcause code)
+pub const TICK_CPU_INT: u32 = 1;
+
+/// Configure comparator 0 to interrupt at `tick_hz`, returning the actual rate
+pub fn init(tick_hz: u32) -> u32 {
+    let period = (SYSTIMER_HZ / tick_hz.max(1)).clamp(1, TARGET_PERIOD_MAX);
+
+    unsafe {
+        // Period mode on unit 0: period must be loaded before enabling
+        reg_write(SYSTIMER_TARGET0_CONF, TARGET_CONF_PERIOD_MODE | period);
+        reg_write(SYSTIMER_COMP0_LOAD, 1);
+        let conf = reg_read(SYSTIMER_CONF);
+        reg_write(SYSTIMER_CONF, conf | CONF_TARGET0_WORK_EN);
+        reg_write(SYSTIMER_INT_CLR, INT_TARGET0);
+        let ena = reg_read(SYSTIMER_INT_ENA);
+        reg_write(SYSTIMER_INT_ENA, ena | INT_TARGET0);
+
+        // Route the source to a level-triggered CPU line above threshold
+        reg_write(SYSTIMER_TARGET0_INT_MAP, TICK_CPU_INT);
+        let int_type = reg_read(CPU_INT_TYPE);
+        reg_write(CPU_INT_TYPE, int_type & !(1 << TICK_CPU_INT));
+        reg_write(CPU_INT_PRI_0 + 4 * TICK_CPU_INT as usize, 1);
+        reg_write(CPU_INT_THRESH, 1);
+        let enable = reg_read(CPU_INT_ENABLE);
+        reg_write(CPU_INT_ENABLE, enable | (1 << TICK_CPU_INT));
+    }
+
+    crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+
+    SYSTIMER_HZ / period
+}
+
+/// Tick interrupt handler: acknowledge comparator 0 and count the tick
+pub fn on_interrupt() {
+    unsafe {
+        reg_write(SYSTIMER_INT_CLR, INT_TARGET0);
+    }
+    crate::kernel::time::tick();
+}
+
+/// Read the 52-bit unit 0 counter (16 MHz)
+#[allow(dead_code)]
+pub fn now() -> u64 {
+    unsafe {
+        reg_write(SYSTIMER_UNIT0_OP, UNIT0_OP_UPDATE);
+        while reg_read(SYSTIMER_UNIT0_OP) & UNIT0_OP_VALUE_VALID == 0 {}
+        let hi = reg_read(SYSTIMER_UNIT0_VALUE_HI) as u64;
+        let lo = reg_read(SYSTIMER_UNIT0_VALUE_LO) as u64;
+        (hi << 32) | lo
+    }
+}
@@ -0,0 +1,29 @@
+//! Goldfish RTC driver
+//! Wall-clock source of the QEMU RISC-V virt machine
+//!
+//! QEMU starts the counter from the host's clock, so one read at boot
+//! gives the real date. The counter is nanoseconds since the Unix epoch;
+//! reading TIME_LOW latches TIME_HIGH, so the low word must come first.
+
+use crate::memory::checked::reg_read;
+
+/// MMIO base on the virt machine
+pub const GOLDFISH_RTC_BASE: usize = 0x0010_1000;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// Nanoseconds since 1970-01-01 00:00:00 UTC
+pub fn read_nanos() -> u64 {
+    unsafe {
+        let lo = reg_read(GOLDFISH_RTC_BASE + TIME_LOW) as u64;
+        let hi = reg_read(GOLDFISH_RTC_BASE + TIME_HIGH) as u64;
+        (hi << 32) | lo
+    }
+}
+
+/// Whole seconds since the Unix epoch
+#[allow(dead_code)]
+pub fn read_seconds() -> u64 {
+    read_nanos() / 1_000_000_000
+}
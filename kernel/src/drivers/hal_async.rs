@@ -0,0 +1,176 @@
+//! embedded-hal-async support
+//! Ecosystem async drivers awaited inside karatOS tasks (`hal_async`)
+//!
+//! Sensor and display crates written against `embedded-hal-async` take a
+//! delay and a bus. `Delay` implements `DelayNs` on the kernel tick: a
+//! wait of a tick or more parks the waker in the alarm queue that also
+//! backs the embassy-time driver, and the tick interrupt wakes it. Waits
+//! shorter than a tick spin, as a tick could not time them anyway.
+//!
+//! `block_on` drives such a future to completion from a task, sleeping
+//! the core between polls. Tasks run to completion on the one thread
+//! stack, so other tasks do not run while it waits; interrupt handlers,
+//! tasklets in interrupt context and the tick do. Drivers with their own
+//! executor can `.await` the same futures there instead.
+//!
+//! There are no interrupt-driven SPI or I2C controllers in the tree yet.
+//! `Blocking` lets a blocking `embedded-hal` 1.0 bus or device stand in
+//! for its async counterpart: each operation completes in its first poll,
+//! so a driver written for async runs unchanged, only without yielding
+//! during transfers. `embedded-hal-async` 1.0 has no serial trait; async
+//! byte streams are `embedded-io-async`'s.
+
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use crate::arch::atomic::{AtomicBool, Ordering};
+use crate::config::TICK_HZ;
+use crate::kernel::time::{self, Duration, Instant};
+use crate::kernel::time_driver;
+
+/// Nanoseconds in one kernel tick; shorter waits spin
+#[allow(dead_code)]
+const NS_PER_TICK: u64 = 1_000_000_000 / TICK_HZ as u64;
+
+/// `DelayNs` on the kernel tick
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Delay;
+
+#[allow(dead_code)]
+impl Delay {
+    /// Wait at least `ns` nanoseconds
+    async fn wait_ns(ns: u64) {
+        if ns < NS_PER_TICK {
+            time::delay_us(ns.div_ceil(1_000) as u32);
+            return;
+        }
+        // Plus one for the partial tick already under way
+        let ticks = ns.div_ceil(NS_PER_TICK) + 1;
+        sleep_until(Instant::now() + Duration::from_ticks(ticks)).await;
+    }
+}
+
+impl embedded_hal_async::delay::DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        Self::wait_ns(ns as u64).await;
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        Self::wait_ns(us as u64 * 1_000).await;
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        Self::wait_ns(ms as u64 * 1_000_000).await;
+    }
+}
+
+/// Complete once the tick reaches `deadline`
+#[allow(dead_code)]
+pub async fn sleep_until(deadline: Instant) {
+    poll_fn(|cx| {
+        if deadline.is_reached() {
+            return Poll::Ready(());
+        }
+        time_driver::wake_at(deadline, cx.waker());
+        Poll::Pending
+    })
+    .await
+}
+
+/// Set by `block_on`'s waker, cleared before each poll
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+fn clone_waker(_: *const ()) -> RawWaker {
+    RawWaker::new(core::ptr::null(), &VTABLE)
+}
+
+fn wake(_: *const ()) {
+    WOKEN.store(true, Ordering::Release);
+}
+
+fn drop_waker(_: *const ()) {}
+
+/// Poll `future` to completion, sleeping the core until it is woken
+///
+/// For task context only; interrupt handlers must not block. A wake that
+/// comes between a poll and the sleep stays pending and ends the sleep at
+/// once, as in `power::idle`. Nested calls share the wake flag, so one
+/// may see the other's wake; that costs a spare poll, nothing more.
+#[allow(dead_code)]
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = unsafe { Waker::from_raw(clone_waker(core::ptr::null())) };
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        WOKEN.store(false, Ordering::Relaxed);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        crate::power::sleep_unless(|| WOKEN.load(Ordering::Acquire));
+    }
+}
+
+/// A blocking `embedded-hal` bus or device used through the async traits
+///
+/// Every operation finishes inside its first poll.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Blocking<T>(pub T);
+
+impl<T: embedded_hal::spi::ErrorType> embedded_hal::spi::ErrorType for Blocking<T> {
+    type Error = T::Error;
+}
+
+impl<T, Word> embedded_hal_async::spi::SpiBus<Word> for Blocking<T>
+where
+    T: embedded_hal::spi::SpiBus<Word>,
+    Word: Copy + 'static,
+{
+    async fn read(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        self.0.read(words)
+    }
+
+    async fn write(&mut self, words: &[Word]) -> Result<(), Self::Error> {
+        self.0.write(words)
+    }
+
+    async fn transfer(&mut self, read: &mut [Word], write: &[Word]) -> Result<(), Self::Error> {
+        self.0.transfer(read, write)
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        self.0.transfer_in_place(words)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
+
+impl<T, Word> embedded_hal_async::spi::SpiDevice<Word> for Blocking<T>
+where
+    T: embedded_hal::spi::SpiDevice<Word>,
+    Word: Copy + 'static,
+{
+    async fn transaction(&mut self, operations: &mut [embedded_hal::spi::Operation<'_, Word>]) -> Result<(), Self::Error> {
+        self.0.transaction(operations)
+    }
+}
+
+impl<T: embedded_hal::i2c::ErrorType> embedded_hal::i2c::ErrorType for Blocking<T> {
+    type Error = T::Error;
+}
+
+impl<T, A> embedded_hal_async::i2c::I2c<A> for Blocking<T>
+where
+    T: embedded_hal::i2c::I2c<A>,
+    A: embedded_hal::i2c::AddressMode,
+{
+    async fn transaction(&mut self, address: A, operations: &mut [embedded_hal::i2c::Operation<'_>]) -> Result<(), Self::Error> {
+        self.0.transaction(address, operations)
+    }
+}
@@ -1,17 +1,153 @@
 //! Hardware driver modules
 //! Architecture-agnostic drivers for various hardware components
+//!
+//! Peripheral base addresses and controller types come from the board
+//! descriptor (`crate::board::BOARD`).
+
+#[cfg(all(target_arch = "arm", feature = "board_nrf52840"))]
+pub mod nrf_rtc;
+
+#[cfg(all(target_arch = "arm", not(feature = "board_nrf52840")))]
+pub mod systick;
+
+#[cfg(all(target_arch = "riscv32", feature = "board_esp32c3"))]
+pub mod esp_systimer;
+
+#[cfg(all(target_arch = "riscv32", not(feature = "board_esp32c3")))]
+pub mod clint;
+
+#[cfg(all(target_arch = "riscv32", not(feature = "board_esp32c3")))]
+pub mod plic;
+
+#[cfg(all(target_arch = "riscv32", not(any(feature = "board_esp32c3", feature = "board_hifive1"))))]
+pub mod goldfish_rtc;
+
+#[cfg(all(feature = "net", target_arch = "riscv32", not(any(feature = "board_esp32c3", feature = "board_hifive1"))))]
+pub mod virtio_net;
+
+pub mod console_rx;
+
+pub mod rng;
+
+#[cfg(feature = "gdb_stub")]
+pub mod debug_uart;
+
+#[cfg(feature = "log_rtt")]
+pub mod rtt;
+
+#[cfg(feature = "hal_async")]
+pub mod hal_async;
 
 pub mod uart {
     //! Simple UART driver for debugging output
-    
+
+    use crate::power::{DriverPower, PowerMode};
+
     /// Initialize UART driver
-    pub fn init() {
+    pub fn init() -> crate::kernel::InitResult {
         // UART initialization will be handled by architecture-specific code
         crate::arch::early_println("UART driver initialized");
+        crate::power::register_driver(DriverPower { name: "console", suspend, resume })
+            .map_err(|_| "power driver table full")
+    }
+
+    /// Send what is still in the TX FIFO before the baud clock stops
+    fn suspend(_mode: PowerMode) {
+        crate::arch::console_flush();
     }
+
+    /// The UART keeps its configuration through deep sleep on every board
+    fn resume(_mode: PowerMode) {}
     
     /// Print a string to UART
     pub fn print(msg: &str) {
         crate::arch::early_println(msg);
     }
+
+    /// The console UART, polled directly as an `embedded-io` byte stream
+    ///
+    /// Reads bypass `console_rx`, so this suits loaders and protocols
+    /// that run before its receive interrupt is enabled, or on boards
+    /// without one; otherwise the interrupt handler takes the bytes first.
+    #[cfg(feature = "embedded_io")]
+    #[allow(dead_code)]
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct Uart;
+
+    #[cfg(feature = "embedded_io")]
+    #[allow(dead_code)]
+    impl Uart {
+        /// Take what the receive FIFO holds, up to `buf.len()` bytes
+        fn drain(buf: &mut [u8]) -> usize {
+            let mut count = 0;
+            while count < buf.len() {
+                match crate::arch::console_read() {
+                    Some(byte) => buf[count] = byte,
+                    None => break,
+                }
+                count += 1;
+            }
+            count
+        }
+    }
+
+    #[cfg(feature = "embedded_io")]
+    impl embedded_io::ErrorType for Uart {
+        type Error = core::convert::Infallible;
+    }
+
+    #[cfg(feature = "embedded_io")]
+    impl embedded_io::Read for Uart {
+        /// Spin until a byte arrives, then take what else has
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                match Self::drain(buf) {
+                    0 => core::hint::spin_loop(),
+                    read => return Ok(read),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "embedded_io")]
+    impl embedded_io::Write for Uart {
+        fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+            crate::arch::console_write(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            crate::arch::console_flush();
+            Ok(())
+        }
+    }
+
+    // Nothing interrupts when bytes arrive, so a pending read polls again at once
+    #[cfg(feature = "embedded_io")]
+    impl embedded_io_async::Read for Uart {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let read = core::future::poll_fn(|cx| match Self::drain(buf) {
+                0 if !buf.is_empty() => {
+                    cx.waker().wake_by_ref();
+                    core::task::Poll::Pending
+                }
+                read => core::task::Poll::Ready(read),
+            });
+            Ok(read.await)
+        }
+    }
+
+    #[cfg(feature = "embedded_io")]
+    impl embedded_io_async::Write for Uart {
+        async fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+            embedded_io::Write::write(self, data)
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            embedded_io::Write::flush(self)
+        }
+    }
 }
@@ -0,0 +1,70 @@
+//! nRF52 RTC Tick Driver
+//! Low-power kernel tick from RTC1 running off the 32.768 kHz LFCLK
+//!
+//! Unlike SysTick, the RTC keeps counting while the CPU sleeps with the
+//! high-frequency clock stopped, so WFI between ticks costs only a few uA.
+
+use crate::config::TICK_HZ;
+use crate::memory::checked::{reg_read, reg_write};
+
+const CLOCK_BASE: usize = 0x40000000;
+const CLOCK_TASKS_LFCLKSTART: usize = CLOCK_BASE + 0x008;
+const CLOCK_EVENTS_LFCLKSTARTED: usize = CLOCK_BASE + 0x104;
+const CLOCK_LFCLKSRC: usize = CLOCK_BASE + 0x518;
+const LFCLKSRC_XTAL: u32 = 1;
+
+const RTC1_BASE: usize = 0x40011000;
+const RTC_TASKS_START: usize = RTC1_BASE;
+const RTC_TASKS_CLEAR: usize = RTC1_BASE + 0x008;
+const RTC_EVENTS_TICK: usize = RTC1_BASE + 0x100;
+const RTC_INTENSET: usize = RTC1_BASE + 0x304;
+const RTC_EVTENSET: usize = RTC1_BASE + 0x344;
+const RTC_PRESCALER: usize = RTC1_BASE + 0x508;
+const RTC_TICK_BIT: u32 = 1 << 0;
+
+const NVIC_ISER0: usize = 0xE000E100;
+
+/// RTC1 interrupt number on nRF52840
+pub const RTC1_IRQN: u32 = 17;
+
+/// LFCLK frequency feeding the RTC
+pub const LFCLK_HZ: u32 = 32_768;
+
+/// RTC prescaler giving exactly `TICK_HZ`
+const PRESCALER: u32 = LFCLK_HZ / TICK_HZ - 1;
+
+// The prescaler divides by 1 to 4096, and any remainder would drift
+const _: () = assert!(
+    LFCLK_HZ.is_multiple_of(TICK_HZ) && PRESCALER < 4096,
+    "TICK_HZ must divide the 32.768 kHz LFCLK"
+);
+
+/// Start the LFCLK and configure RTC1 to interrupt at `TICK_HZ`
+pub fn init() {
+    unsafe {
+        // Low-frequency crystal; the RTC cannot run without LFCLK
+        reg_write(CLOCK_LFCLKSRC, LFCLKSRC_XTAL);
+        reg_write(CLOCK_EVENTS_LFCLKSTARTED, 0);
+        reg_write(CLOCK_TASKS_LFCLKSTART, 1);
+        while reg_read(CLOCK_EVENTS_LFCLKSTARTED) == 0 {}
+
+        reg_write(RTC_TASKS_CLEAR, 1);
+        reg_write(RTC_PRESCALER, PRESCALER);
+        reg_write(RTC_EVTENSET, RTC_TICK_BIT);
+        reg_write(RTC_INTENSET, RTC_TICK_BIT);
+        reg_write(NVIC_ISER0, 1 << RTC1_IRQN);
+        reg_write(RTC_TASKS_START, 1);
+    }
+
+    crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+}
+
+/// RTC1 interrupt handler: acknowledge the TICK event and count it
+pub fn on_interrupt() {
+    unsafe {
+        if reg_read(RTC_EVENTS_TICK) != 0 {
+            reg_write(RTC_EVENTS_TICK, 0);
+            crate::kernel::time::tick();
+        }
+    }
+}
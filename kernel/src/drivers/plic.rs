@@ -0,0 +1,67 @@
+//! RISC-V PLIC Driver
+//! Platform-level interrupt controller for device interrupts
+//!
+//! QEMU virt and the FE310 both place the PLIC at 0x0C00_0000 with hart 0
+//! machine mode as context 0. Drivers enable their source here and the
+//! trap handler calls `dispatch` on a machine external interrupt.
+
+use crate::memory::checked::{reg_read, reg_write};
+
+const PLIC_BASE: usize = 0x0C00_0000;
+const PRIORITY: usize = PLIC_BASE; // 4 bytes per source
+const ENABLE: usize = PLIC_BASE + 0x2000; // context 0 bitmap
+const THRESHOLD: usize = PLIC_BASE + 0x20_0000;
+const CLAIM: usize = PLIC_BASE + 0x20_0004; // read claims, write completes
+
+/// mcause interrupt code for a machine external interrupt
+pub const MACHINE_EXTERNAL_INT: usize = 11;
+
+/// Route `source` to hart 0 machine mode and unmask external interrupts
+pub fn enable(source: u32) {
+    unsafe {
+        reg_write(PRIORITY + 4 * source as usize, 1);
+        let word = enable_word(source);
+        reg_write(word, reg_read(word) | 1 << (source % 32));
+        reg_write(THRESHOLD, 0);
+        riscv::register::mie::set_mext();
+    }
+}
+
+/// Claim and handle every pending source, then complete it
+pub fn dispatch() {
+    loop {
+        let source = unsafe { reg_read(CLAIM) };
+        if source == 0 {
+            return;
+        }
+        if source == crate::arch::riscv::CONSOLE_IRQ {
+            crate::drivers::console_rx::on_interrupt();
+        } else if virtio_net_interrupt(source) {
+            // Acknowledged in the device
+        } else {
+            // FE310 enable bits are not reset, so mask sources nobody asked for
+            disable(source);
+        }
+        unsafe { reg_write(CLAIM, source) };
+    }
+}
+
+#[cfg(all(feature = "net", not(feature = "board_hifive1")))]
+fn virtio_net_interrupt(source: u32) -> bool {
+    crate::drivers::virtio_net::on_interrupt(source)
+}
+
+#[cfg(not(all(feature = "net", not(feature = "board_hifive1"))))]
+fn virtio_net_interrupt(_source: u32) -> bool {
+    false
+}
+
+/// Stop routing `source` to hart 0
+pub fn disable(source: u32) {
+    let word = enable_word(source);
+    unsafe { reg_write(word, reg_read(word) & !(1 << (source % 32))) };
+}
+
+fn enable_word(source: u32) -> usize {
+    ENABLE + 4 * (source as usize / 32)
+}
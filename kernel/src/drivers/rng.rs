@@ -0,0 +1,122 @@
+//! Random Number Driver
+//! Seedable pseudo-random numbers, seeded from the board's entropy at boot
+//!
+//! Numbers come from xorshift64*: small and fast, and not fit for keys or
+//! nonces. What the kernel needs from it is a run that can be repeated:
+//! `seed` tells which seed the sequence started from, and `reseed` starts
+//! it again from any seed.
+//!
+//! The boot seed comes from the RNG peripheral on the nRF52840. Elsewhere
+//! it mixes the cycle counter, the RTC where the board has one and, on
+//! the simulator, the host clock; QEMU machines without an RTC may draw
+//! the same seed on every boot.
+
+use core::cell::UnsafeCell;
+
+use crate::kernel::InitResult;
+
+#[cfg(feature = "board_nrf52840")]
+mod nrf {
+    use crate::memory::checked::{reg_read, reg_write};
+
+    const RNG_BASE: usize = 0x4000D000;
+    const RNG_TASKS_START: usize = RNG_BASE;
+    const RNG_TASKS_STOP: usize = RNG_BASE + 0x004;
+    const RNG_EVENTS_VALRDY: usize = RNG_BASE + 0x100;
+    const RNG_CONFIG: usize = RNG_BASE + 0x504;
+    const RNG_VALUE: usize = RNG_BASE + 0x508;
+    /// Bias correction: slower, but evenly distributed bits
+    const CONFIG_DERCEN: u32 = 1 << 0;
+
+    /// Eight bytes from the RNG peripheral
+    pub fn entropy() -> u64 {
+        let mut value = 0u64;
+        unsafe {
+            reg_write(RNG_CONFIG, CONFIG_DERCEN);
+            reg_write(RNG_TASKS_START, 1);
+            for _ in 0..8 {
+                while reg_read(RNG_EVENTS_VALRDY) == 0 {}
+                reg_write(RNG_EVENTS_VALRDY, 0);
+                value = value << 8 | (reg_read(RNG_VALUE) & 0xFF) as u64;
+            }
+            reg_write(RNG_TASKS_STOP, 1);
+        }
+        value
+    }
+}
+
+struct State {
+    seed: u64,
+    state: u64,
+}
+
+struct RngCell(UnsafeCell<State>);
+unsafe impl Sync for RngCell {} // Only touched with interrupts off
+
+static RNG: RngCell = RngCell(UnsafeCell::new(State { seed: 0, state: scramble(0) }));
+
+fn with_rng<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *RNG.0.get() }))
+}
+
+/// Generator state for `seed`; never zero, which xorshift cannot leave
+const fn scramble(seed: u64) -> u64 {
+    seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1
+}
+
+/// Best entropy the board offers
+fn entropy() -> u64 {
+    #[cfg(feature = "board_nrf52840")]
+    return nrf::entropy();
+
+    #[allow(unreachable_code)]
+    {
+        let mut value = (crate::arch::cycle_counter() as u64) << 32 | crate::kernel::time::now() & 0xFFFF_FFFF;
+        if let Some(micros) = crate::kernel::time::rtc_micros() {
+            value ^= micros.rotate_left(17);
+        }
+        #[cfg(feature = "sim")]
+        if let Ok(since) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            value ^= since.as_nanos() as u64;
+        }
+        value
+    }
+}
+
+/// Draw the boot seed
+pub fn init() -> InitResult {
+    reseed(entropy());
+    Ok(())
+}
+
+/// Seed the current sequence started from
+#[allow(dead_code)]
+pub fn seed() -> u64 {
+    with_rng(|rng| rng.seed)
+}
+
+/// Start the sequence again from `seed`
+#[allow(dead_code)]
+pub fn reseed(seed: u64) {
+    with_rng(|rng| {
+        rng.seed = seed;
+        rng.state = scramble(seed);
+    });
+}
+
+/// Next 32 random bits
+#[allow(dead_code)]
+pub fn next_u32() -> u32 {
+    with_rng(|rng| {
+        rng.state ^= rng.state >> 12;
+        rng.state ^= rng.state << 25;
+        rng.state ^= rng.state >> 27;
+        (rng.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    })
+}
+
+/// Random number in `0..bound`; zero when `bound` is zero
+#[allow(dead_code)]
+pub fn below(bound: u32) -> u32 {
+    ((next_u32() as u64 * bound as u64) >> 32) as u32
+}
@@ -0,0 +1,141 @@
+//! SEGGER RTT
+//! Log output through a debug probe's memory access (`log_rtt`)
+//!
+//! The control block `_SEGGER_RTT` lists ring buffers in RAM that a
+//! J-Link or probe-rs finds by its ID or symbol and drains over SWD/JTAG
+//! while the core runs. Writing a line costs a memory copy; no UART is
+//! involved. Up channel 0, "Terminal", carries the logger's text lines;
+//! with `log_defmt`, up channel 1, "defmt", carries the defmt frames.
+//!
+//! Channels run in no-block-trim mode: when the host falls behind, what
+//! does not fit is dropped rather than stalling the caller. Writers take
+//! the channel with interrupts off, so tasks and ISRs may both log.
+
+use core::cell::UnsafeCell;
+use core::ptr;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+/// Text channel size; smaller on boards with the 16-line log
+const TERMINAL_BYTES: usize = if crate::config::LOG_LINES <= 16 { 256 } else { 1024 };
+
+#[cfg(feature = "log_defmt")]
+const DEFMT_BYTES: usize = if crate::config::LOG_LINES <= 16 { 256 } else { 512 };
+
+const UP_CHANNELS: usize = if cfg!(feature = "log_defmt") { 2 } else { 1 };
+
+/// Up channel of the logger's text lines
+pub const TERMINAL: usize = 0;
+
+/// Up channel of defmt frames
+#[cfg(feature = "log_defmt")]
+pub const DEFMT: usize = 1;
+
+/// Drop what does not fit, keeping the part that does
+const MODE_NO_BLOCK_TRIM: u32 = 1;
+
+/// Channel descriptor, laid out as the host expects
+#[repr(C)]
+struct Channel {
+    name: *const u8,
+    buffer: *mut u8,
+    size: u32,
+    /// Next byte the target writes; only the target moves it
+    write: AtomicU32,
+    /// Next byte the host reads; only the host moves it
+    read: AtomicU32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    max_up: u32,
+    max_down: u32,
+    up: [Channel; UP_CHANNELS],
+}
+
+struct Rtt(UnsafeCell<ControlBlock>);
+unsafe impl Sync for Rtt {} // Written with interrupts off; the host only moves `read`
+
+// Zeroed until `init`, so the host does not find a half-built block
+#[no_mangle]
+#[used]
+static _SEGGER_RTT: Rtt = Rtt(UnsafeCell::new(ControlBlock {
+    id: [0; 16],
+    max_up: 0,
+    max_down: 0,
+    up: [const { Channel::new(ptr::null(), ptr::null_mut(), 0) }; UP_CHANNELS],
+}));
+
+struct Buffer<const N: usize>(UnsafeCell<[u8; N]>);
+unsafe impl<const N: usize> Sync for Buffer<N> {} // Only reached through its channel
+
+static TERMINAL_BUFFER: Buffer<TERMINAL_BYTES> = Buffer(UnsafeCell::new([0; TERMINAL_BYTES]));
+
+#[cfg(feature = "log_defmt")]
+static DEFMT_BUFFER: Buffer<DEFMT_BYTES> = Buffer(UnsafeCell::new([0; DEFMT_BYTES]));
+
+fn control_block() -> &'static mut ControlBlock {
+    unsafe { &mut *_SEGGER_RTT.0.get() }
+}
+
+/// Fill in the control block, its ID last
+pub fn init() {
+    let block = control_block();
+    block.max_up = UP_CHANNELS as u32;
+    block.max_down = 0;
+    block.up[TERMINAL] = Channel::new(c"Terminal".as_ptr().cast(), TERMINAL_BUFFER.0.get().cast(), TERMINAL_BYTES);
+    #[cfg(feature = "log_defmt")]
+    {
+        block.up[DEFMT] = Channel::new(c"defmt".as_ptr().cast(), DEFMT_BUFFER.0.get().cast(), DEFMT_BYTES);
+    }
+
+    // Assembled here so the flash image holds no copy of the ID for the
+    // host to find instead, and published only once the rest is in place
+    let mut id = [0u8; 16];
+    id[..7].copy_from_slice(b"SEGGER ");
+    id[7..10].copy_from_slice(b"RTT");
+    core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    unsafe { ptr::write_volatile(&mut block.id, id) };
+}
+
+/// Write to up channel `channel`; returns the bytes that fitted
+pub fn write(channel: usize, bytes: &[u8]) -> usize {
+    crate::arch::free(|| match control_block().up.get(channel) {
+        Some(channel) if channel.size > 0 => channel.push(bytes),
+        _ => 0,
+    })
+}
+
+impl Channel {
+    const fn new(name: *const u8, buffer: *mut u8, size: usize) -> Self {
+        Self {
+            name,
+            buffer,
+            size: size as u32,
+            write: AtomicU32::new(0),
+            read: AtomicU32::new(0),
+            flags: MODE_NO_BLOCK_TRIM,
+        }
+    }
+
+    fn push(&self, bytes: &[u8]) -> usize {
+        let size = self.size as usize;
+        let read = self.read.load(Ordering::Acquire) as usize;
+        let mut write = self.write.load(Ordering::Relaxed) as usize;
+        let mut written = 0;
+        for &byte in bytes {
+            let next = (write + 1) % size;
+            // One slot stays free so a full ring differs from an empty one
+            if next == read {
+                break;
+            }
+            unsafe { self.buffer.add(write).write_volatile(byte) };
+            write = next;
+            written += 1;
+        }
+        self.write.store(write as u32, Ordering::Release);
+        written
+    }
+}
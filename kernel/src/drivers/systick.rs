@@ -0,0 +1,163 @@
+//! Cortex-M SysTick Driver
+//! Kernel tick from the core's 24-bit SysTick timer
+//!
+//! Clocked from the processor clock, so the caller passes the core
+//! frequency together with the tick rate. SysTick stops with the core
+//! clock in deep sleep; boards that need a tick there use their RTC.
+//!
+//! With `tick_dynamic` the timer runs one-shot: each interrupt counts the
+//! cycles that passed, announces the whole ticks among them and reloads
+//! for the next deadline. The counter is 24 bits, so one reload spans at
+//! most `RELOAD_MAX` cycles (about 100 ms at 168 MHz); a longer wait
+//! takes several interrupts. Reprogramming drops the few cycles between
+//! reading the counter and reloading it, so the tick loses a little time
+//! each time it is moved forward.
+
+#[cfg(feature = "tick_dynamic")]
+use core::cell::UnsafeCell;
+#[cfg(feature = "tick_dynamic")]
+use crate::arch::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "tick_dynamic")]
+use crate::memory::checked::reg_read;
+use crate::memory::checked::reg_write;
+
+const SYST_CSR: usize = 0xE000_E010;
+const SYST_RVR: usize = 0xE000_E014;
+const SYST_CVR: usize = 0xE000_E018;
+
+const CSR_ENABLE: u32 = 1 << 0;
+const CSR_TICKINT: u32 = 1 << 1;
+const CSR_CLKSOURCE_CORE: u32 = 1 << 2;
+#[cfg(feature = "tick_dynamic")]
+const CSR_COUNTFLAG: u32 = 1 << 16;
+
+/// Largest reload value (24-bit counter)
+const RELOAD_MAX: u32 = 0x00FF_FFFF;
+
+/// Shortest one-shot reload, so the interrupt cannot come due before the
+/// handler that programmed it has returned
+#[cfg(feature = "tick_dynamic")]
+const MIN_DELAY: u32 = 1024;
+
+/// Start the tick at roughly `tick_hz`, returning the actual rate
+pub fn init(core_hz: u32, tick_hz: u32) -> u32 {
+    let period = (core_hz / tick_hz.max(1)).clamp(2, RELOAD_MAX + 1);
+    #[cfg(feature = "tick_dynamic")]
+    with_one_shot(|state, _| {
+        PERIOD.store(period, Ordering::Relaxed);
+        state.load = period;
+    });
+
+    unsafe {
+        reg_write(SYST_CSR, 0);
+        reg_write(SYST_RVR, period - 1);
+        reg_write(SYST_CVR, 0);
+        reg_write(SYST_CSR, CSR_ENABLE | CSR_TICKINT | CSR_CLKSOURCE_CORE);
+    }
+
+    crate::power::register_wakeup(crate::power::WakeupSource::Tick);
+
+    core_hz / period
+}
+
+/// SysTick exception handler: count the tick (the flag clears on read)
+#[cfg(not(feature = "tick_dynamic"))]
+pub fn on_interrupt() {
+    crate::kernel::time::tick();
+}
+
+/// Core cycles per kernel tick
+#[cfg(feature = "tick_dynamic")]
+static PERIOD: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "tick_dynamic")]
+struct OneShot {
+    /// Cycles counted up to the start of the current reload
+    cycles: u64,
+    /// Cycles of the reloads that ran out since `cycles` was updated
+    overflow: u32,
+    /// Cycles per reload, RVR + 1
+    load: u32,
+    /// Cycles announced as whole ticks
+    announced: u64,
+}
+
+#[cfg(feature = "tick_dynamic")]
+struct OneShotCell(UnsafeCell<OneShot>);
+#[cfg(feature = "tick_dynamic")]
+unsafe impl Sync for OneShotCell {} // Only touched with interrupts off
+
+#[cfg(feature = "tick_dynamic")]
+static ONE_SHOT: OneShotCell = OneShotCell(UnsafeCell::new(OneShot { cycles: 0, overflow: 0, load: 0, announced: 0 }));
+
+/// Run `f` on the one-shot state with interrupts off, restoring their state
+#[cfg(feature = "tick_dynamic")]
+fn with_one_shot<R>(f: impl FnOnce(&mut OneShot, u32) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *ONE_SHOT.0.get() }, PERIOD.load(Ordering::Relaxed)))
+}
+
+/// Cycles since `state.cycles`, folding any reload that ran out into
+/// `state.overflow` (reading CSR clears COUNTFLAG)
+#[cfg(feature = "tick_dynamic")]
+fn elapsed(state: &mut OneShot) -> u32 {
+    let (before, csr, after) = unsafe {
+        (
+            reg_read(SYST_CVR),
+            reg_read(SYST_CSR),
+            reg_read(SYST_CVR),
+        )
+    };
+    // The counter counts down, so a larger second read means it reloaded
+    if csr & CSR_COUNTFLAG != 0 || before < after {
+        state.overflow += state.load;
+    }
+    state.overflow + (state.load - after)
+}
+
+/// Whole ticks passed since the last announcement
+#[cfg(feature = "tick_dynamic")]
+pub fn pending_ticks() -> u32 {
+    with_one_shot(|state, period| {
+        if period == 0 {
+            // Not started yet
+            return 0;
+        }
+        let now = state.cycles + elapsed(state) as u64;
+        ((now - state.announced) / period as u64) as u32
+    })
+}
+
+/// Interrupt `hw_ticks` ticks after the last announcement, or as soon as
+/// possible if that has passed
+#[cfg(feature = "tick_dynamic")]
+pub fn program(hw_ticks: u32) {
+    with_one_shot(|state, period| {
+        let since = elapsed(state);
+        state.cycles += since as u64;
+        state.overflow = 0;
+        let target = state.announced + hw_ticks as u64 * period as u64;
+        let delay = target.saturating_sub(state.cycles).clamp(MIN_DELAY as u64, RELOAD_MAX as u64 + 1) as u32;
+        state.load = delay;
+        unsafe {
+            reg_write(SYST_RVR, delay - 1);
+            // Clears the counter, which reloads from RVR on the next cycle
+            reg_write(SYST_CVR, 0);
+        }
+    });
+}
+
+/// SysTick exception handler: announce the ticks that passed, then
+/// program the next deadline
+#[cfg(feature = "tick_dynamic")]
+pub fn on_interrupt() {
+    let ticks = with_one_shot(|state, period| {
+        elapsed(state);
+        state.cycles += state.overflow as u64;
+        state.overflow = 0;
+        let ticks = ((state.cycles - state.announced) / period as u64) as u32;
+        state.announced += ticks as u64 * period as u64;
+        ticks
+    });
+    crate::kernel::time::announce(ticks);
+    crate::kernel::time::program_next();
+}
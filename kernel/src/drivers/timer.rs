@@ -1,7 +1,8 @@
 //! Timer Driver Module
 //! Unified timer driver for different timer hardware
 
-use super::{Driver, DeviceConfig};
+use super::Driver;
+use crate::board::{BoardDescriptor, TimerKind};
 
 /// Unified Timer driver
 pub struct TimerDriver {
@@ -75,18 +76,19 @@ impl TimerDriver {
 impl Driver for TimerDriver {
     type Error = TimerError;
     
-    fn init(config: &DeviceConfig) -> Result<Self, Self::Error> {
-        // Initialize timer hardware based on config
-        let timer_type = match config.uart_type {
-            "pl011" => "arm,generic-timer",  // ARM PL011 implies ARM platform
-            _ => "riscv,clint",              // Default to RISC-V
+    fn init(config: &BoardDescriptor) -> Result<Self, Self::Error> {
+        // Initialize timer hardware based on the board's timer kind
+        let timer_type = match config.timer_kind {
+            TimerKind::SysTick => "arm,generic-timer",
+            TimerKind::Clint => "riscv,clint",
+            _ => return Err(TimerError::UnsupportedType),
         };
         
-        let base_addr = config.timer_base.unwrap_or(0x10000000);
+        let base_addr = config.timer_base.ok_or(TimerError::InitializationFailed)?;
         TimerDriver::new(base_addr, timer_type)
     }
     
-    fn probe(config: &DeviceConfig) -> bool {
+    fn probe(config: &BoardDescriptor) -> bool {
         // Timer is always available in this simplified implementation
         config.timer_base.is_some()
     }
@@ -0,0 +1,408 @@
+//! virtio-net driver
+//! Ethernet over virtio-mmio on the QEMU RISC-V virt machine (`net`)
+//!
+//! `probe` looks through virt's eight virtio-mmio slots for a network
+//! device, as added by `-netdev user,id=n0 -device
+//! virtio-net-device,netdev=n0`. Both the legacy (version 1) and the
+//! virtio 1.0 (version 2) register sets are handled; the queues are laid
+//! out the legacy way, which also meets the 1.0 alignment rules.
+//!
+//! Each queue has `QUEUE_SIZE` descriptors with one frame buffer apiece.
+//! Every receive buffer stays posted to the device: `receive` copies a
+//! filled one out and posts it again. `transmit` copies the frame into a
+//! free transmit buffer, which is free again once the device has used
+//! it. The device's interrupt, PLIC source 1 + slot, wakes the network
+//! service through `net::rx_ready` and `net::link_changed`.
+
+use core::cell::UnsafeCell;
+use core::mem::{offset_of, size_of};
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering as Fence};
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::memory::checked::{reg_read, reg_write};
+use crate::net::{NetDevice, MAX_FRAME};
+
+/// First of virt's virtio-mmio slots, each `SLOT_STRIDE` bytes on
+const SLOT_BASE: usize = 0x1000_1000;
+const SLOT_STRIDE: usize = 0x1000;
+const SLOTS: u32 = 8;
+
+const MAGIC: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES: usize = 0x010;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028; // legacy
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03C; // legacy
+const QUEUE_PFN: usize = 0x040; // legacy
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DEVICE_LOW: usize = 0x0A0;
+const CONFIG: usize = 0x100;
+
+/// "virt", little-endian
+const MAGIC_VALUE: u32 = 0x7472_6976;
+const DEVICE_NET: u32 = 1;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+const F_MAC: u32 = 1 << 5;
+const F_STATUS: u32 = 1 << 16;
+/// Feature bit 32, in the second feature word
+const F_VERSION_1: u32 = 1 << 0;
+
+/// Link-up bit of the config space status field
+const NET_S_LINK_UP: u32 = 1;
+
+const INTERRUPT_USED: u32 = 1 << 0;
+const INTERRUPT_CONFIG: u32 = 1 << 1;
+
+const DESC_F_WRITE: u16 = 2;
+
+const RX_QUEUE: u32 = 0;
+const TX_QUEUE: u32 = 1;
+
+/// Descriptors, and frame buffers, per queue
+const QUEUE_SIZE: usize = 8;
+
+/// Legacy queue alignment; the used ring starts on the next page
+const PAGE: usize = 4096;
+
+/// `virtio_net_hdr` with `num_buffers`; legacy devices without merged
+/// receive buffers leave the last two bytes out
+const HEADER_MAX: usize = 12;
+const HEADER_LEGACY: usize = 10;
+
+const BUFFER: usize = HEADER_MAX + MAX_FRAME;
+
+/// MAC used when the device does not offer one
+const FALLBACK_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct Available {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct Used {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// One virtqueue in the legacy layout
+#[repr(C, align(4096))]
+struct Queue {
+    descriptors: [Descriptor; QUEUE_SIZE],
+    available: Available,
+    _pad: [u8; PAGE - size_of::<[Descriptor; QUEUE_SIZE]>() - size_of::<Available>()],
+    used: Used,
+}
+
+const _: () = assert!(offset_of!(Queue, used) == PAGE);
+
+impl Queue {
+    const EMPTY: Queue = Queue {
+        descriptors: [Descriptor { addr: 0, len: 0, flags: 0, next: 0 }; QUEUE_SIZE],
+        available: Available { flags: 0, idx: 0, ring: [0; QUEUE_SIZE], used_event: 0 },
+        _pad: [0; PAGE - size_of::<[Descriptor; QUEUE_SIZE]>() - size_of::<Available>()],
+        used: Used { flags: 0, idx: 0, ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE], avail_event: 0 },
+    };
+}
+
+/// Queues and buffers the device reads and writes
+struct Rings {
+    rx: Queue,
+    tx: Queue,
+    rx_buffers: [[u8; BUFFER]; QUEUE_SIZE],
+    tx_buffers: [[u8; BUFFER]; QUEUE_SIZE],
+}
+
+struct RingCell(UnsafeCell<Rings>);
+unsafe impl Sync for RingCell {} // Shared with the device only; the driver is the network task's
+
+static RINGS: RingCell = RingCell(UnsafeCell::new(Rings {
+    rx: Queue::EMPTY,
+    tx: Queue::EMPTY,
+    rx_buffers: [[0; BUFFER]; QUEUE_SIZE],
+    tx_buffers: [[0; BUFFER]; QUEUE_SIZE],
+}));
+
+struct DeviceCell(UnsafeCell<Option<VirtioNet>>);
+unsafe impl Sync for DeviceCell {} // Filled once by `probe`
+
+static DEVICE: DeviceCell = DeviceCell(UnsafeCell::new(None));
+
+/// PLIC source of the probed device; 0 until then
+static IRQ: AtomicU32 = AtomicU32::new(0);
+
+/// A virtio-net device in one of virt's virtio-mmio slots
+pub struct VirtioNet {
+    base: usize,
+    /// Bytes of `virtio_net_hdr` in front of every frame
+    header: usize,
+    mac: [u8; 6],
+    /// The device reports link state
+    link_status: bool,
+    /// Used ring entries taken, per queue
+    rx_seen: u16,
+    tx_seen: u16,
+    /// Transmit descriptors the device has not given back
+    tx_busy: u32,
+}
+
+/// Find and start the first virtio-net device
+///
+/// Called once, by `net::init`.
+pub fn probe() -> Option<&'static mut VirtioNet> {
+    let slot = (0..SLOTS).find(|&slot| {
+        let base = SLOT_BASE + slot as usize * SLOT_STRIDE;
+        unsafe { reg_read(base + MAGIC) == MAGIC_VALUE && reg_read(base + DEVICE_ID) == DEVICE_NET }
+    })?;
+    let device = VirtioNet::start(SLOT_BASE + slot as usize * SLOT_STRIDE)?;
+    // `probe` runs once, so this is the only reference made
+    let device = unsafe { (*DEVICE.0.get()).insert(device) };
+    IRQ.store(slot + 1, Ordering::Release);
+    crate::drivers::plic::enable(slot + 1);
+    Some(device)
+}
+
+/// Handle the device's interrupt; false if `source` is not the device's
+pub fn on_interrupt(source: u32) -> bool {
+    let irq = IRQ.load(Ordering::Acquire);
+    if irq == 0 || source != irq {
+        return false;
+    }
+    let base = SLOT_BASE + (irq as usize - 1) * SLOT_STRIDE;
+    let status = unsafe {
+        let status = reg_read(base + INTERRUPT_STATUS);
+        reg_write(base + INTERRUPT_ACK, status);
+        status
+    };
+    // Transmit completions wake the task too, to refill the queue
+    if status & INTERRUPT_USED != 0 {
+        crate::net::rx_ready();
+    }
+    if status & INTERRUPT_CONFIG != 0 {
+        crate::net::link_changed();
+    }
+    true
+}
+
+impl VirtioNet {
+    /// Reset the device at `base`, negotiate features and post the
+    /// receive buffers; `None` if it will not run with this driver
+    fn start(base: usize) -> Option<Self> {
+        let legacy = match unsafe { reg_read(base + VERSION) } {
+            1 => true,
+            2 => false,
+            _ => return None,
+        };
+        let write = |offset: usize, value: u32| unsafe { reg_write(base + offset, value) };
+        let read = |offset: usize| unsafe { reg_read(base + offset) };
+
+        write(STATUS, 0);
+        write(STATUS, STATUS_ACKNOWLEDGE);
+        write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        write(DEVICE_FEATURES_SEL, 0);
+        let features = read(DEVICE_FEATURES) & (F_MAC | F_STATUS);
+        write(DRIVER_FEATURES_SEL, 0);
+        write(DRIVER_FEATURES, features);
+        let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+        if !legacy {
+            write(DEVICE_FEATURES_SEL, 1);
+            if read(DEVICE_FEATURES) & F_VERSION_1 == 0 {
+                return None;
+            }
+            write(DRIVER_FEATURES_SEL, 1);
+            write(DRIVER_FEATURES, F_VERSION_1);
+            status |= STATUS_FEATURES_OK;
+            write(STATUS, status);
+            if read(STATUS) & STATUS_FEATURES_OK == 0 {
+                return None;
+            }
+        } else {
+            write(GUEST_PAGE_SIZE, PAGE as u32);
+        }
+
+        let rings = RINGS.0.get();
+        unsafe {
+            for (index, queue) in [(RX_QUEUE, addr_of!((*rings).rx)), (TX_QUEUE, addr_of!((*rings).tx))] {
+                write(QUEUE_SEL, index);
+                if (read(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+                    write(STATUS, 0);
+                    return None;
+                }
+                write(QUEUE_NUM, QUEUE_SIZE as u32);
+                // Physical addresses: virt runs without translation
+                let addr = queue as usize;
+                if legacy {
+                    write(QUEUE_ALIGN, PAGE as u32);
+                    write(QUEUE_PFN, (addr / PAGE) as u32);
+                } else {
+                    write(QUEUE_DESC_LOW, addr as u32);
+                    write(QUEUE_DRIVER_LOW, (addr + offset_of!(Queue, available)) as u32);
+                    write(QUEUE_DEVICE_LOW, (addr + offset_of!(Queue, used)) as u32);
+                    write(QUEUE_READY, 1);
+                }
+            }
+
+            let rx = addr_of_mut!((*rings).rx);
+            for id in 0..QUEUE_SIZE {
+                (*rx).descriptors[id] = Descriptor {
+                    addr: addr_of!((*rings).rx_buffers[id]) as usize as u64,
+                    len: BUFFER as u32,
+                    flags: DESC_F_WRITE,
+                    next: 0,
+                };
+                (*rx).available.ring[id] = id as u16;
+            }
+            fence(Fence::SeqCst);
+            write_volatile(addr_of_mut!((*rx).available.idx), QUEUE_SIZE as u16);
+        }
+
+        let mut mac = FALLBACK_MAC;
+        if features & F_MAC != 0 {
+            let config = (read(CONFIG) as u64) | (read(CONFIG + 4) as u64) << 32;
+            mac.copy_from_slice(&config.to_le_bytes()[..6]);
+        }
+
+        write(STATUS, status | STATUS_DRIVER_OK);
+        fence(Fence::SeqCst);
+        write(QUEUE_NOTIFY, RX_QUEUE);
+
+        Some(VirtioNet {
+            base,
+            header: if legacy { HEADER_LEGACY } else { HEADER_MAX },
+            mac,
+            link_status: features & F_STATUS != 0,
+            rx_seen: 0,
+            tx_seen: 0,
+            tx_busy: 0,
+        })
+    }
+
+    fn notify(&self, queue: u32) {
+        fence(Fence::SeqCst);
+        unsafe { reg_write(self.base + QUEUE_NOTIFY, queue) };
+    }
+
+    /// Next used entry of `queue` past `seen`, if the device wrote one
+    fn next_used(queue: *const Queue, seen: u16) -> Option<UsedElem> {
+        let idx = unsafe { read_volatile(addr_of!((*queue).used.idx)) };
+        if idx == seen {
+            return None;
+        }
+        fence(Fence::SeqCst);
+        Some(unsafe { read_volatile(addr_of!((*queue).used.ring[seen as usize % QUEUE_SIZE])) })
+    }
+
+    /// Offer descriptor `id` of `queue` to the device
+    fn offer(queue: *mut Queue, id: usize) {
+        unsafe {
+            let idx = read_volatile(addr_of!((*queue).available.idx));
+            write_volatile(addr_of_mut!((*queue).available.ring[idx as usize % QUEUE_SIZE]), id as u16);
+            fence(Fence::SeqCst);
+            write_volatile(addr_of_mut!((*queue).available.idx), idx.wrapping_add(1));
+        }
+    }
+
+    /// Free the transmit buffers the device has finished with
+    fn reclaim(&mut self) {
+        let tx = unsafe { addr_of!((*RINGS.0.get()).tx) };
+        while let Some(used) = Self::next_used(tx, self.tx_seen) {
+            self.tx_busy &= !(1 << (used.id as usize % QUEUE_SIZE));
+            self.tx_seen = self.tx_seen.wrapping_add(1);
+        }
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn link_up(&self) -> bool {
+        // The status field follows the MAC, in the second config word's upper half
+        !self.link_status || unsafe { reg_read(self.base + CONFIG + 4) } >> 16 & NET_S_LINK_UP != 0
+    }
+
+    fn receive(&mut self, frame: &mut [u8; MAX_FRAME]) -> Option<usize> {
+        let rings = RINGS.0.get();
+        let used = Self::next_used(unsafe { addr_of!((*rings).rx) }, self.rx_seen)?;
+        self.rx_seen = self.rx_seen.wrapping_add(1);
+        let id = used.id as usize % QUEUE_SIZE;
+        let len = (used.len as usize).saturating_sub(self.header).min(MAX_FRAME);
+        let buffer = unsafe { &(*rings).rx_buffers[id] };
+        frame[..len].copy_from_slice(&buffer[self.header..self.header + len]);
+        Self::offer(unsafe { addr_of_mut!((*rings).rx) }, id);
+        self.notify(RX_QUEUE);
+        Some(len)
+    }
+
+    fn can_transmit(&self) -> bool {
+        let tx = unsafe { addr_of!((*RINGS.0.get()).tx) };
+        self.tx_busy != (1 << QUEUE_SIZE) - 1 || Self::next_used(tx, self.tx_seen).is_some()
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> bool {
+        self.reclaim();
+        let Some(id) = (0..QUEUE_SIZE).find(|id| self.tx_busy & 1 << id == 0) else {
+            return false;
+        };
+        if frame.len() > MAX_FRAME {
+            return false;
+        }
+        let rings = RINGS.0.get();
+        unsafe {
+            let buffer = &mut (*rings).tx_buffers[id];
+            buffer[..self.header].fill(0);
+            buffer[self.header..self.header + frame.len()].copy_from_slice(frame);
+            (*rings).tx.descriptors[id] = Descriptor {
+                addr: buffer.as_ptr() as usize as u64,
+                len: (self.header + frame.len()) as u32,
+                flags: 0,
+                next: 0,
+            };
+            Self::offer(addr_of_mut!((*rings).tx), id);
+        }
+        self.tx_busy |= 1 << id;
+        self.notify(TX_QUEUE);
+        true
+    }
+}
@@ -0,0 +1,155 @@
+//! Event handlers
+//! Runtime table binding event ID ranges to callbacks
+//!
+//! Applications and drivers call `register_event_handler` to run code when
+//! an event in a range of IDs is processed by a scheduler; events no range
+//! claims go to the default handler, if one is set. Conventional IDs:
+//! 0x1 timer, 0x2 I/O, 0x3 user input, 0x10..=0x1F system, 0xFF shutdown.
+//!
+//! Schedulers process events with interrupts disabled, so they only queue
+//! them here; `dispatch_pending` runs the handlers once the scheduler lock
+//! is released. Handlers may therefore post events, spawn or wake tasks.
+
+use core::cell::UnsafeCell;
+use core::ops::RangeInclusive;
+
+use heapless::{Deque, Vec};
+
+use crate::scheduler::Event;
+
+/// Registered ID ranges
+pub const MAX_EVENT_HANDLERS: usize = 16;
+
+/// Events awaiting dispatch; one scheduler cycle processes at most 16
+const MAX_DEFERRED_EVENTS: usize = 16;
+
+/// Why a handler could not be registered
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventHandlerError {
+    /// Range is empty (start above end)
+    InvalidRange,
+    /// Range shares IDs with an already registered one
+    Overlap,
+    TableFull,
+}
+
+/// Event dispatch counters
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EventHandlerStats {
+    /// Events passed to a registered or default handler
+    pub dispatched: u32,
+    /// Events no handler took
+    pub unhandled: u32,
+    /// Events lost because the dispatch queue was full
+    pub dropped: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Binding {
+    first: u32,
+    last: u32,
+    handler: fn(Event),
+}
+
+struct HandlerTable {
+    bindings: Vec<Binding, MAX_EVENT_HANDLERS>,
+    default: Option<fn(Event)>,
+    deferred: Deque<Event, MAX_DEFERRED_EVENTS>,
+    stats: EventHandlerStats,
+}
+
+impl HandlerTable {
+    fn lookup(&self, id: u32) -> Option<fn(Event)> {
+        self.bindings
+            .iter()
+            .find(|binding| (binding.first..=binding.last).contains(&id))
+            .map(|binding| binding.handler)
+            .or(self.default)
+    }
+}
+
+struct EventHandlers(UnsafeCell<HandlerTable>);
+unsafe impl Sync for EventHandlers {} // Single-core assumption
+
+static HANDLERS: EventHandlers = EventHandlers(UnsafeCell::new(HandlerTable {
+    bindings: Vec::new(),
+    default: None,
+    deferred: Deque::new(),
+    stats: EventHandlerStats { dispatched: 0, unhandled: 0, dropped: 0 },
+}));
+
+fn with_handlers<R>(f: impl FnOnce(&mut HandlerTable) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *HANDLERS.0.get()) })
+}
+
+/// Run `handler` for every processed event whose ID is in `ids`
+#[allow(dead_code)]
+pub fn register_event_handler(ids: RangeInclusive<u32>, handler: fn(Event)) -> Result<(), EventHandlerError> {
+    let (first, last) = ids.into_inner();
+    if first > last {
+        return Err(EventHandlerError::InvalidRange);
+    }
+    with_handlers(|table| {
+        if table.bindings.iter().any(|binding| first <= binding.last && binding.first <= last) {
+            return Err(EventHandlerError::Overlap);
+        }
+        if crate::kernel::faults::inject(crate::kernel::faults::Fault::Alloc) {
+            return Err(EventHandlerError::TableFull);
+        }
+        table.bindings.push(Binding { first, last, handler }).map_err(|_| EventHandlerError::TableFull)
+    })
+}
+
+/// Remove the handler whose range contains `id`
+#[allow(dead_code)]
+pub fn unregister_event_handler(id: u32) -> bool {
+    with_handlers(|table| {
+        let found = table.bindings.iter().position(|binding| (binding.first..=binding.last).contains(&id));
+        found.map(|index| table.bindings.swap_remove(index)).is_some()
+    })
+}
+
+/// Set (or with `None`, clear) the handler for IDs no range claims
+#[allow(dead_code)]
+pub fn set_default_event_handler(handler: Option<fn(Event)>) {
+    with_handlers(|table| table.default = handler);
+}
+
+/// Queue a processed event for dispatch; caller has interrupts disabled
+pub fn defer_locked(event: Event) {
+    let table = unsafe { &mut *HANDLERS.0.get() };
+    if table.deferred.push_back(event).is_err() {
+        table.stats.dropped = table.stats.dropped.wrapping_add(1);
+    }
+}
+
+/// Run handlers for events the schedulers have processed
+///
+/// Called after each scheduler pass with interrupts enabled. Returns how
+/// many events were handed to a handler.
+pub fn dispatch_pending() -> u32 {
+    let mut dispatched = 0;
+    while let Some((event, handler)) = with_handlers(|table| {
+        let event = table.deferred.pop_front()?;
+        let handler = table.lookup(event.id);
+        match handler {
+            Some(_) => table.stats.dispatched = table.stats.dispatched.wrapping_add(1),
+            None => table.stats.unhandled = table.stats.unhandled.wrapping_add(1),
+        }
+        Some((event, handler))
+    }) {
+        if let Some(handler) = handler {
+            handler(event);
+            dispatched += 1;
+        }
+    }
+    dispatched
+}
+
+/// Get event dispatch counters
+#[allow(dead_code)]
+pub fn stats() -> EventHandlerStats {
+    with_handlers(|table| table.stats)
+}
@@ -0,0 +1,159 @@
+//! Async task executor
+//! Futures run as tasks, on the native scheduler or on embassy-executor (`async_tasks`)
+//!
+//! `spawn(name, priority, future)` starts a future as a task that runs
+//! until the future completes. Task code is plain async Rust awaiting
+//! `kernel::sync` primitives, `hal_async` delays and the like, and stays
+//! the same whichever `Executor` backend the build picks:
+//!
+//! - `native` (default): each future is a scheduler task of its own at
+//!   the priority given, polled through `scheduler::poll_current` when
+//!   the scheduler picks it.
+//! - `embassy` (`executor_embassy`): the futures are the tasks of one
+//!   `embassy_executor::raw::Executor`, which a single scheduler task
+//!   polls whenever the executor's pender says it has work. They all
+//!   run at that task's priority, and embassy `#[task]` functions can be
+//!   spawned next to them through `spawner()`.
+//!
+//! Futures are moved into a fixed arena of `MAX_ASYNC_TASKS` slots of
+//! `FUTURE_BYTES`, so neither a heap nor a nameable future type is
+//! needed; a future that does not fit is a compile error. Backends spawn
+//! their scheduler tasks with `run` as the entry.
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::mem::MaybeUninit;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::arch::atomic::{AtomicBool, Ordering};
+use crate::kernel::InitResult;
+use crate::scheduler::TaskPriority;
+
+#[cfg(feature = "executor_embassy")]
+mod embassy;
+#[cfg(not(feature = "executor_embassy"))]
+mod native;
+
+#[cfg(feature = "executor_embassy")]
+#[allow(unused_imports)]
+pub use embassy::{spawner, Embassy as Backend};
+#[cfg(not(feature = "executor_embassy"))]
+pub use native::Native as Backend;
+
+/// Futures spawned and not yet completed at once
+pub const MAX_ASYNC_TASKS: usize = 4;
+
+/// Bytes each arena slot holds
+pub const FUTURE_BYTES: usize = 1024;
+
+/// Alignment of each arena slot
+pub const FUTURE_ALIGN: usize = 8;
+
+/// Why a future was not started
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Every arena slot holds a future
+    NoSlot,
+    /// The backend had no room for another task
+    NoTask,
+}
+
+/// What the task layer needs from an executor backend
+pub trait Executor: Sync {
+    /// Set the backend up; called once at boot
+    fn init(&'static self) -> InitResult;
+
+    /// Start the future in arena slot `slot` as a task
+    fn start(&'static self, slot: usize, name: &'static str, priority: TaskPriority) -> Result<(), SpawnError>;
+
+    /// Run the scheduler task with `task_id` once, from the main loop
+    fn run(&'static self, task_id: usize);
+}
+
+static BACKEND: Backend = Backend::new();
+
+#[repr(C, align(8))]
+struct FutureBytes([MaybeUninit<u8>; FUTURE_BYTES]);
+
+const _: () = assert!(core::mem::align_of::<FutureBytes>() == FUTURE_ALIGN);
+
+/// One arena slot and the future in it
+struct Slot {
+    bytes: UnsafeCell<FutureBytes>,
+    /// The future in `bytes`, while the slot is in use
+    future: UnsafeCell<Option<*mut dyn Future<Output = ()>>>,
+    used: AtomicBool,
+}
+
+// A slot is filled by the spawner that claimed it, then only polled and
+// emptied by the task that runs its future
+unsafe impl Sync for Slot {}
+
+static SLOTS: [Slot; MAX_ASYNC_TASKS] = [const {
+    Slot {
+        bytes: UnsafeCell::new(FutureBytes([MaybeUninit::uninit(); FUTURE_BYTES])),
+        future: UnsafeCell::new(None),
+        used: AtomicBool::new(false),
+    }
+}; MAX_ASYNC_TASKS];
+
+/// Start `future` as a task at `priority` (the native backend's; the
+/// embassy backend runs every future at its own task's priority)
+///
+/// From task context; the future is polled on the thread stack only.
+#[allow(dead_code)]
+pub fn spawn<F: Future<Output = ()> + 'static>(name: &'static str, priority: TaskPriority, future: F) -> Result<(), SpawnError> {
+    const {
+        assert!(
+            core::mem::size_of::<F>() <= FUTURE_BYTES && core::mem::align_of::<F>() <= FUTURE_ALIGN,
+            "future does not fit an executor arena slot"
+        )
+    };
+    let slot = SLOTS.iter().position(|slot| !slot.used.swap(true, Ordering::Acquire)).ok_or(SpawnError::NoSlot)?;
+    // Claimed: nothing else touches the slot until it is started
+    unsafe {
+        let place = SLOTS[slot].bytes.get().cast::<F>();
+        place.write(future);
+        *SLOTS[slot].future.get() = Some(place as *mut dyn Future<Output = ()>);
+    }
+    BACKEND.start(slot, name, priority).inspect_err(|_| release(slot))
+}
+
+/// Run the task with `task_id` once; the entry of every backend task
+pub fn run(task_id: usize) {
+    BACKEND.run(task_id)
+}
+
+/// Set up the backend; in the kernel's init table
+pub fn init() -> InitResult {
+    BACKEND.init()
+}
+
+/// Drop the future in `slot`, if any, and free the slot
+fn release(slot: usize) {
+    if let Some(future) = unsafe { (*SLOTS[slot].future.get()).take() } {
+        unsafe { core::ptr::drop_in_place(future) };
+    }
+    SLOTS[slot].used.store(false, Ordering::Release);
+}
+
+/// Polls the future in one arena slot; what a backend runs as a task
+pub struct SlotFuture(usize);
+
+impl Future for SlotFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let slot = self.0;
+        let Some(future) = (unsafe { *SLOTS[slot].future.get() }) else {
+            return Poll::Ready(());
+        };
+        // The future never moves out of its static slot before it is dropped
+        let result = unsafe { Pin::new_unchecked(&mut *future) }.poll(cx);
+        if result.is_ready() {
+            release(slot);
+        }
+        result
+    }
+}
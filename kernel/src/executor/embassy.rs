@@ -0,0 +1,102 @@
+//! Embassy executor backend
+//! Futures as embassy tasks, polled by one scheduler task (`executor_embassy`)
+//!
+//! `init` makes an `embassy_executor::raw::Executor` and the scheduler
+//! task `TASK_ID` at `PRIORITY` that polls it. The executor's pender
+//! raises `SIGNAL_WAKE` on that task, so the task is ready exactly when
+//! an embassy task was woken, and a wake during a poll is kept for the
+//! next run just as with `scheduler::poll_current`. Spawned futures are
+//! embassy tasks of `SlotFuture`, one `TaskStorage` per arena slot;
+//! `spawner()` takes `#[embassy_executor::task]` functions as well.
+//!
+//! Every future shares the one task's priority, and embassy polls its
+//! ready tasks in turn within a run, so a long poll holds up the others.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use embassy_executor::raw::{Executor as RawExecutor, TaskStorage};
+use embassy_executor::Spawner;
+
+use super::{Executor, SlotFuture, SpawnError, MAX_ASYNC_TASKS};
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::kernel::{Handle, InitResult};
+use crate::scheduler::{self, Task, TaskPriority, SIGNAL_WAKE};
+
+/// Task ID of the task that polls the executor
+pub const TASK_ID: usize = 20;
+
+/// Priority of that task, and so of every spawned future
+pub const PRIORITY: TaskPriority = TaskPriority::Normal;
+
+/// Raw handle of the polling task; 0, never a valid handle, until `init`
+static TASK_HANDLE: AtomicU32 = AtomicU32::new(0);
+
+struct RawCell(UnsafeCell<MaybeUninit<RawExecutor>>);
+
+// Written once by `init` before the polling task exists, then only shared
+unsafe impl Sync for RawCell {}
+
+static RAW: RawCell = RawCell(UnsafeCell::new(MaybeUninit::uninit()));
+
+/// Futures as embassy tasks
+pub struct Embassy {
+    pool: [TaskStorage<SlotFuture>; MAX_ASYNC_TASKS],
+}
+
+impl Embassy {
+    pub const fn new() -> Self {
+        Self { pool: [const { TaskStorage::new() }; MAX_ASYNC_TASKS] }
+    }
+}
+
+impl Default for Embassy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The executor `init` made
+fn raw() -> &'static RawExecutor {
+    unsafe { (*RAW.0.get()).assume_init_ref() }
+}
+
+/// Spawner for embassy `#[task]` functions, once the backend is set up
+#[allow(dead_code)]
+pub fn spawner() -> Option<Spawner> {
+    (TASK_HANDLE.load(Ordering::Acquire) != 0).then(|| raw().spawner())
+}
+
+impl Executor for Embassy {
+    fn init(&'static self) -> InitResult {
+        unsafe { (*RAW.0.get()).write(RawExecutor::new(core::ptr::null_mut())) };
+        let task = Task::with_priority(TASK_ID, PRIORITY).named("embassy").entry(super::run);
+        let handle = scheduler::add_priority_task(task).map_err(|_| "no task slot")?;
+        TASK_HANDLE.store(handle.raw(), Ordering::Release);
+        Ok(())
+    }
+
+    fn start(&'static self, slot: usize, _name: &'static str, _priority: TaskPriority) -> Result<(), SpawnError> {
+        let Some(spawner) = spawner() else {
+            return Err(SpawnError::NoTask);
+        };
+        let token = self.pool[slot].spawn(|| SlotFuture(slot));
+        spawner.spawn(token).map_err(|_| SpawnError::NoTask)
+    }
+
+    fn run(&'static self, _task_id: usize) {
+        // A pend from before this poll is answered by the poll itself
+        scheduler::take_signals(SIGNAL_WAKE);
+        // Only this task polls, and never from the pender
+        unsafe { raw().poll() };
+        scheduler::wait_signals(SIGNAL_WAKE);
+    }
+}
+
+/// Called by the executor when one of its tasks is woken
+#[export_name = "__pender"]
+fn pender(_context: *mut ()) {
+    if let Some(handle) = Handle::from_raw(TASK_HANDLE.load(Ordering::Acquire)) {
+        let _ = scheduler::signal_handle(handle, SIGNAL_WAKE);
+    }
+}
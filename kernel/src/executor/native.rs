@@ -0,0 +1,55 @@
+//! Native executor backend
+//! One scheduler task per future
+//!
+//! A future spawned into slot `n` becomes task `TASK_ID_BASE + n` at the
+//! priority it was spawned with, so it is picked, preempted, signalled
+//! and listed by `ps` like any other task. Each run polls it once with
+//! the task's own waker; pending, the task is parked until woken. A task
+//! asked to exit drops its future at its next run and frees the slot.
+
+use core::pin::Pin;
+
+use super::{Executor, SlotFuture, SpawnError};
+use crate::kernel::InitResult;
+use crate::scheduler::{self, Task, TaskPriority};
+
+/// Task ID of the future in slot 0; the others follow
+pub const TASK_ID_BASE: usize = 32;
+
+/// Futures as scheduler tasks
+pub struct Native;
+
+impl Native {
+    pub const fn new() -> Self {
+        Native
+    }
+}
+
+impl Default for Native {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for Native {
+    fn init(&'static self) -> InitResult {
+        Ok(())
+    }
+
+    fn start(&'static self, slot: usize, name: &'static str, priority: TaskPriority) -> Result<(), SpawnError> {
+        let task = Task::with_priority(TASK_ID_BASE + slot, priority).named(name).entry(super::run);
+        scheduler::add_priority_task(task).map(|_| ()).map_err(|_| SpawnError::NoTask)
+    }
+
+    fn run(&'static self, task_id: usize) {
+        let slot = task_id - TASK_ID_BASE;
+        // The main loop retires the task once it returns
+        if scheduler::is_cancelled() {
+            super::release(slot);
+            return;
+        }
+        if scheduler::poll_current(Pin::new(&mut SlotFuture(slot))).is_ready() {
+            scheduler::exit_current_task();
+        }
+    }
+}
@@ -0,0 +1,619 @@
+//! GDB stub
+//! Remote serial protocol on the board's debug UART
+//!
+//! Built with `gdb_stub`, the kernel answers GDB on a second UART
+//! (`target remote /dev/ttyUSB1`). While the system runs, a low-priority
+//! task waits for the host and stops everything with `arch::debug::halt`;
+//! breakpoints, single steps and faults stop it the same way. Stopped, the
+//! stub runs in the exception handler with interrupts masked, polls the
+//! UART and serves register and memory packets until GDB resumes. With
+//! `introspect` it first refreshes the task table copy in `KARATOS_INFO`.
+//!
+//! Software breakpoints patch the architecture's breakpoint instruction
+//! into RAM; code in flash gets a hardware comparator instead (Cortex-M
+//! FPB, RISC-V triggers). Faults are reported as signals once the crash
+//! record is written, but the faulting code cannot be resumed.
+
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::arch::debug;
+use crate::drivers::debug_uart::{self, RX};
+use crate::kernel::InitResult;
+use crate::memory::{self, RegionKind};
+use crate::scheduler::{self, Task, TaskPriority};
+
+/// Task ID of the stub task
+pub const TASK_ID: usize = 17;
+
+/// Waiting for the host is never urgent
+const PRIORITY: TaskPriority = TaskPriority::Low;
+
+/// Signals reported in stop replies (GDB numbering)
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGBUS: u8 = 7;
+#[allow(dead_code)]
+pub const SIGFPE: u8 = 8;
+pub const SIGSEGV: u8 = 11;
+
+/// Largest packet payload, advertised to GDB as PacketSize
+const PACKET_SIZE: usize = 400;
+
+/// Breakpoints set at once, software and hardware together
+const MAX_BREAKPOINTS: usize = 16;
+
+/// Registers of the stopped code, in GDB numbering
+pub struct StopRegisters {
+    values: [u32; debug::REGISTER_COUNT],
+    /// Bit n set if register n was saved; the rest read as unavailable
+    known: u64,
+}
+
+impl Default for StopRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StopRegisters {
+    pub const fn new() -> Self {
+        Self { values: [0; debug::REGISTER_COUNT], known: 0 }
+    }
+
+    pub fn set(&mut self, n: usize, value: u32) {
+        self.values[n] = value;
+        self.known |= 1 << n;
+    }
+
+    pub fn get(&self, n: usize) -> u32 {
+        self.values[n]
+    }
+
+    fn is_known(&self, n: usize) -> bool {
+        self.known & (1 << n) != 0
+    }
+}
+
+/// How the stopped code continues
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Step,
+}
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    addr: usize,
+    /// Instruction bytes replaced by the breakpoint; `len` 0 for hardware
+    saved: [u8; 4],
+    len: usize,
+}
+
+/// Breakpoints GDB has inserted
+struct Breakpoints([Option<Breakpoint>; MAX_BREAKPOINTS]);
+
+struct StubState {
+    packet: [u8; PACKET_SIZE],
+    breakpoints: Breakpoints,
+}
+
+struct Stub(UnsafeCell<StubState>);
+unsafe impl Sync for Stub {} // Only touched while the system is stopped
+
+static STUB: Stub = Stub(UnsafeCell::new(StubState {
+    packet: [0; PACKET_SIZE],
+    breakpoints: Breakpoints([None; MAX_BREAKPOINTS]),
+}));
+
+/// Set while a stop is being served, so a fault inside it is not re-entered
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+/// Byte the task took from `RX` before halting, with bit 8 set
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// Set up the debug UART and spawn the stub task
+pub fn init() -> InitResult {
+    if !debug_uart::init() {
+        return Err("board has no debug UART");
+    }
+    debug::enable();
+    let task = Task::with_priority(TASK_ID, PRIORITY).named("gdb").entry(|_| run());
+    scheduler::add_priority_task(task).map(|_| ()).map_err(|_| "no task slot")
+}
+
+/// One run of the stub task: stop the system once the host sends anything
+pub fn run() {
+    let mut byte = [0u8; 1];
+    // Parks the task until the debug UART interrupt delivers input
+    if RX.read(&mut byte) == Ok(1) {
+        PENDING.store(0x100 | byte[0] as u32, Ordering::Relaxed);
+        debug::halt();
+    }
+}
+
+/// Serve GDB until it resumes the stopped code
+///
+/// Called by the architecture's debug exception with the registers of
+/// the stopped code, which it writes back afterwards.
+pub fn stopped(signal: u8, regs: &mut StopRegisters) -> Resume {
+    if SERVING.swap(true, Ordering::Acquire) {
+        return Resume::Continue;
+    }
+    crate::introspect::refresh_stopped();
+    let stub = unsafe { &mut *STUB.0.get() };
+    let resume = stub.serve(signal, regs, false);
+    SERVING.store(false, Ordering::Release);
+    resume
+}
+
+/// Report a fault and serve GDB for good; the fault cannot be resumed
+pub fn fault(signal: u8, regs: &mut StopRegisters) {
+    if SERVING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    crate::introspect::refresh_stopped();
+    let stub = unsafe { &mut *STUB.0.get() };
+    stub.serve(signal, regs, true);
+}
+
+enum Input {
+    /// Ctrl-C from the host
+    Interrupt,
+    /// A packet of this length in `packet`, checksum verified
+    Packet(usize),
+}
+
+impl StubState {
+    fn serve(&mut self, signal: u8, regs: &mut StopRegisters, fatal: bool) -> Resume {
+        // GDB waits for this after c or s; a halt for input only needs
+        // one if that input was Ctrl-C
+        if signal != SIGINT {
+            send_stop(signal);
+        }
+        loop {
+            let len = match self.receive() {
+                Input::Interrupt => {
+                    send_stop(signal);
+                    continue;
+                }
+                Input::Packet(len) => len,
+            };
+            match handle(&self.packet[..len], signal, regs, &mut self.breakpoints) {
+                Some(_) if fatal => send_stop(signal),
+                Some(resume) => return resume,
+                None => {}
+            }
+        }
+    }
+
+    /// Wait for Ctrl-C or a packet with a good checksum, acking as GDB expects
+    fn receive(&mut self) -> Input {
+        loop {
+            match next_byte() {
+                0x03 => return Input::Interrupt,
+                b'$' => {}
+                _ => continue, // Acks and line noise
+            }
+            let mut len = 0;
+            let mut sum = 0u8;
+            let mut overflow = false;
+            loop {
+                let byte = next_byte();
+                match byte {
+                    b'#' => break,
+                    b'$' => {
+                        // Start over on a retransmission
+                        len = 0;
+                        sum = 0;
+                        overflow = false;
+                    }
+                    _ => {
+                        sum = sum.wrapping_add(byte);
+                        if len < PACKET_SIZE {
+                            self.packet[len] = byte;
+                            len += 1;
+                        } else {
+                            overflow = true;
+                        }
+                    }
+                }
+            }
+            let checksum = parse_hex(&[next_byte(), next_byte()]);
+            if overflow || checksum != Some(sum as usize) {
+                debug_uart::write(b'-');
+                continue;
+            }
+            debug_uart::write(b'+');
+            return Input::Packet(len);
+        }
+    }
+}
+
+/// Answer one packet; `Some` once the stopped code should run again
+fn handle(packet: &[u8], signal: u8, regs: &mut StopRegisters, breakpoints: &mut Breakpoints) -> Option<Resume> {
+    let Some((&command, args)) = packet.split_first() else {
+        Reply::empty();
+        return None;
+    };
+    match command {
+        b'?' => send_stop(signal),
+        b'g' => {
+            let mut reply = Reply::start();
+            for n in 0..debug::REGISTER_COUNT {
+                reply.register(regs, n);
+            }
+            reply.finish();
+        }
+        b'G' => {
+            for (n, word) in args.chunks(8).take(debug::REGISTER_COUNT).enumerate() {
+                if let (true, Some(value)) = (regs.is_known(n), parse_le_word(word)) {
+                    regs.set(n, value);
+                }
+            }
+            Reply::ok();
+        }
+        b'p' => match parse_hex(args).filter(|&n| n < debug::REGISTER_COUNT) {
+            Some(n) => {
+                let mut reply = Reply::start();
+                reply.register(regs, n);
+                reply.finish();
+            }
+            None => Reply::error(0x01),
+        },
+        b'P' => {
+            let parsed = split(args, b'=').and_then(|(n, value)| Some((parse_hex(n)?, parse_le_word(value)?)));
+            match parsed {
+                Some((n, value)) if n < debug::REGISTER_COUNT => {
+                    regs.set(n, value);
+                    Reply::ok();
+                }
+                _ => Reply::error(0x01),
+            }
+        }
+        b'm' => match parse_range(args) {
+            Some((addr, len)) => read_memory(addr, len.min((PACKET_SIZE - 4) / 2)),
+            None => Reply::error(0x01),
+        },
+        b'M' => {
+            let parsed = split(args, b':').and_then(|(range, data)| Some((parse_range(range)?, data)));
+            match parsed {
+                Some(((addr, len), data)) if data.len() == 2 * len => write_memory(addr, data),
+                _ => Reply::error(0x01),
+            }
+        }
+        b'c' | b's' => {
+            if let Some(addr) = parse_hex(args) {
+                regs.set(debug::PC, addr as u32);
+            }
+            return resume(if command == b's' { Resume::Step } else { Resume::Continue });
+        }
+        b'Z' | b'z' => breakpoints.packet(command == b'Z', args),
+        b'D' => {
+            breakpoints.clear();
+            Reply::ok();
+            return Some(Resume::Continue);
+        }
+        b'k' => {
+            breakpoints.clear();
+            return Some(Resume::Continue);
+        }
+        b'H' => Reply::ok(),
+        b'q' => query(args),
+        b'v' => {
+            if args == b"Cont?" {
+                Reply::send(if debug::SINGLE_STEP { "vCont;c;C;s;S" } else { "vCont;c;C" });
+            } else if let Some(actions) = args.strip_prefix(b"Cont;") {
+                // One thread, so the first action is the one that applies
+                return match actions.first() {
+                    Some(b'c' | b'C') => resume(Resume::Continue),
+                    Some(b's' | b'S') => resume(Resume::Step),
+                    _ => {
+                        Reply::error(0x01);
+                        None
+                    }
+                };
+            } else {
+                Reply::empty();
+            }
+        }
+        _ => Reply::empty(),
+    }
+    None
+}
+
+impl Breakpoints {
+    /// `Z0`/`z0` software, `Z1`/`z1` hardware breakpoint at `addr,kind`
+    fn packet(&mut self, insert: bool, args: &[u8]) {
+        let parsed = split(args, b',').and_then(|(kind, rest)| {
+            let (addr, size) = split(rest, b',')?;
+            Some((kind, parse_hex(addr)?, parse_hex(size)?))
+        });
+        let done = match parsed {
+            Some((b"0", addr, size)) if insert => self.insert(addr, size, true),
+            Some((b"1", addr, size)) if insert => self.insert(addr, size, false),
+            Some((b"0" | b"1", addr, _)) => self.remove(addr),
+            _ => {
+                Reply::empty();
+                return;
+            }
+        };
+        if done {
+            Reply::ok();
+        } else {
+            Reply::error(0x0E);
+        }
+    }
+
+    fn insert(&mut self, addr: usize, kind: usize, software: bool) -> bool {
+        if self.0.iter().flatten().any(|bp| bp.addr == addr) {
+            return true;
+        }
+        let Some(slot) = self.0.iter_mut().find(|bp| bp.is_none()) else {
+            return false;
+        };
+        let Some(instruction) = debug::breakpoint_instruction(kind) else {
+            return false;
+        };
+
+        // Patch RAM; flash only takes hardware breakpoints
+        let len = instruction.len();
+        if software && memory::region_kind(addr, len) == Some(RegionKind::Ram) {
+            let mut saved = [0u8; 4];
+            for (i, byte) in saved[..len].iter_mut().enumerate() {
+                *byte = unsafe { core::ptr::read_volatile((addr + i) as *const u8) };
+            }
+            write_bytes(addr, instruction);
+            debug::sync_instructions();
+            *slot = Some(Breakpoint { addr, saved, len });
+            return true;
+        }
+        if debug::set_hw_breakpoint(addr) {
+            *slot = Some(Breakpoint { addr, saved: [0; 4], len: 0 });
+            return true;
+        }
+        false
+    }
+
+    fn remove(&mut self, addr: usize) -> bool {
+        let Some(slot) = self.0.iter_mut().find(|bp| bp.is_some_and(|bp| bp.addr == addr)) else {
+            return false;
+        };
+        if let Some(bp) = slot.take() {
+            restore(&bp);
+        }
+        true
+    }
+
+    fn clear(&mut self) {
+        for bp in self.0.iter_mut().filter_map(Option::take) {
+            restore(&bp);
+        }
+    }
+}
+
+
+fn send_stop(signal: u8) {
+    let mut reply = Reply::start();
+    let _ = write!(reply, "S{:02x}", signal);
+    reply.finish();
+}
+
+/// Refuse a step the architecture cannot make
+fn resume(resume: Resume) -> Option<Resume> {
+    if resume == Resume::Step && !debug::SINGLE_STEP {
+        Reply::error(0x01);
+        return None;
+    }
+    Some(resume)
+}
+
+/// Undo a breakpoint in RAM or free its comparator
+fn restore(bp: &Breakpoint) {
+    if bp.len == 0 {
+        debug::clear_hw_breakpoint(bp.addr);
+    } else {
+        write_bytes(bp.addr, &bp.saved[..bp.len]);
+        debug::sync_instructions();
+    }
+}
+
+/// `qSupported`, target description transfer and the rest of `q`
+fn query(args: &[u8]) {
+    if args.starts_with(b"Supported") {
+        let mut reply = Reply::start();
+        let _ = write!(reply, "PacketSize={:x}", PACKET_SIZE);
+        if debug::TARGET_XML.is_some() {
+            let _ = reply.write_str(";qXfer:features:read+");
+        }
+        reply.finish();
+    } else if let Some(range) = args.strip_prefix(b"Xfer:features:read:target.xml:") {
+        match (debug::TARGET_XML, parse_range(range)) {
+            (Some(xml), Some((offset, len))) => {
+                let rest = xml.as_bytes().get(offset..).unwrap_or_default();
+                let chunk = &rest[..rest.len().min(len).min(PACKET_SIZE - 1)];
+                let mut reply = Reply::start();
+                reply.byte(if chunk.len() < rest.len() { b'm' } else { b'l' });
+                chunk.iter().for_each(|&byte| reply.byte(byte));
+                reply.finish();
+            }
+            _ => Reply::error(0x00),
+        }
+    } else if args == b"Attached" {
+        Reply::send("1");
+    } else {
+        Reply::empty();
+    }
+}
+
+fn read_memory(addr: usize, len: usize) {
+    if memory::region_kind(addr, len).is_none() {
+        Reply::error(0x0E);
+        return;
+    }
+    let mut reply = Reply::start();
+    let mut offset = 0;
+    while offset < len {
+        let width = unit_width(addr + offset, len - offset);
+        let value = unsafe { memory::read_unit(addr + offset, width) };
+        for byte in &value.to_le_bytes()[..width] {
+            reply.hex(*byte);
+        }
+        offset += width;
+    }
+    reply.finish();
+}
+
+fn write_memory(addr: usize, hex: &[u8]) {
+    let len = hex.len() / 2;
+    if !matches!(memory::region_kind(addr, len), Some(RegionKind::Ram | RegionKind::Mmio)) {
+        Reply::error(0x0E);
+        return;
+    }
+    let mut bytes = [0u8; PACKET_SIZE / 2];
+    for (byte, digits) in bytes.iter_mut().zip(hex.chunks(2)) {
+        match parse_hex(digits) {
+            Some(value) => *byte = value as u8,
+            None => return Reply::error(0x01),
+        }
+    }
+    write_bytes(addr, &bytes[..len]);
+    Reply::ok();
+}
+
+/// Store `bytes` at `addr` with the widest aligned accesses, for device registers
+fn write_bytes(addr: usize, bytes: &[u8]) {
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let width = unit_width(addr + offset, bytes.len() - offset);
+        let mut unit = [0u8; 4];
+        unit[..width].copy_from_slice(&bytes[offset..offset + width]);
+        unsafe { memory::write_unit(addr + offset, width, u32::from_le_bytes(unit)) };
+        offset += width;
+    }
+}
+
+fn unit_width(addr: usize, remaining: usize) -> usize {
+    if addr.is_multiple_of(4) && remaining >= 4 {
+        4
+    } else if addr.is_multiple_of(2) && remaining >= 2 {
+        2
+    } else {
+        1
+    }
+}
+
+/// Next byte from the host: the one that woke the task, then buffered, then the UART
+fn next_byte() -> u8 {
+    let pending = PENDING.swap(0, Ordering::Relaxed);
+    if pending != 0 {
+        return pending as u8;
+    }
+    loop {
+        let mut byte = [0u8; 1];
+        if RX.try_read(&mut byte) == 1 {
+            return byte[0];
+        }
+        if let Some(byte) = debug_uart::read() {
+            return byte;
+        }
+    }
+}
+
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = bytes.iter().position(|&byte| byte == separator)?;
+    Some((&bytes[..at], &bytes[at + 1..]))
+}
+
+/// `addr,len` in hex
+fn parse_range(bytes: &[u8]) -> Option<(usize, usize)> {
+    let (addr, len) = split(bytes, b',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn parse_hex(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() || digits.len() > 2 * core::mem::size_of::<usize>() {
+        return None;
+    }
+    digits.iter().try_fold(0, |value, &digit| Some(value << 4 | (digit as char).to_digit(16)? as usize))
+}
+
+/// Register value as GDB sends it: target byte order, little-endian here
+fn parse_le_word(digits: &[u8]) -> Option<u32> {
+    if digits.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0u8; 4];
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+        *byte = parse_hex(pair)? as u8;
+    }
+    Some(u32::from_le_bytes(bytes))
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Packet sent straight to the UART, checksummed on the way
+struct Reply {
+    sum: u8,
+}
+
+impl Reply {
+    fn start() -> Self {
+        debug_uart::write(b'$');
+        Self { sum: 0 }
+    }
+
+    fn send(text: &str) {
+        let mut reply = Self::start();
+        let _ = reply.write_str(text);
+        reply.finish();
+    }
+
+    fn empty() {
+        Self::start().finish();
+    }
+
+    fn ok() {
+        Self::send("OK");
+    }
+
+    fn error(code: u8) {
+        let mut reply = Self::start();
+        let _ = write!(reply, "E{:02x}", code);
+        reply.finish();
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.sum = self.sum.wrapping_add(byte);
+        debug_uart::write(byte);
+    }
+
+    fn hex(&mut self, byte: u8) {
+        self.byte(HEX_DIGITS[(byte >> 4) as usize]);
+        self.byte(HEX_DIGITS[(byte & 0xF) as usize]);
+    }
+
+    /// Register `n` in target byte order, or `x`s if it was not saved
+    fn register(&mut self, regs: &StopRegisters, n: usize) {
+        if regs.is_known(n) {
+            regs.get(n).to_le_bytes().iter().for_each(|&byte| self.hex(byte));
+        } else {
+            (0..8).for_each(|_| self.byte(b'x'));
+        }
+    }
+
+    fn finish(self) {
+        debug_uart::write(b'#');
+        debug_uart::write(HEX_DIGITS[(self.sum >> 4) as usize]);
+        debug_uart::write(HEX_DIGITS[(self.sum & 0xF) as usize]);
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        text.bytes().for_each(|byte| self.byte(byte));
+        Ok(())
+    }
+}
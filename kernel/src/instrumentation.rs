@@ -0,0 +1,156 @@
+//! Interrupt latency and critical-section instrumentation
+//! Optional cycle-accurate measurement of ISR and interrupts-disabled windows
+//!
+//! Enabled with the `instrumentation` feature. When disabled every hook
+//! compiles to nothing and `stats()` reports zeros, so call sites never
+//! need their own `cfg` guards.
+//!
+//! Measurements use the architecture cycle counter (DWT CYCCNT on ARM,
+//! `mcycle` on RISC-V, started by the kernel arch init stage) and only
+//! track maxima, which is what matters when verifying worst-case
+//! real-time behaviour.
+//!
+//! The arch layer runs every interrupt handler it dispatches (tick,
+//! console and debug UART receive, PLIC sources) under `measure_isr`.
+//! An interrupts-off window runs from the outermost `arch::mask` to its
+//! `arch::restore`; nested sections and sections entered with
+//! interrupts already off are part of the window around them, so there
+//! is a single start time to keep.
+//!
+//! Scheduler events are also stamped when posted, so each priority level
+//! can report how long its events waited to be processed (min, average
+//! and max, in `LevelStats::latency`). Those stamps are microseconds from
+//! `time::now_us`, as an event may wait longer than the cycle counter
+//! takes to wrap.
+
+#[cfg(feature = "instrumentation")]
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "instrumentation")]
+static ISR_COUNT: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "instrumentation")]
+static MAX_ISR_CYCLES: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "instrumentation")]
+static CRITICAL_START: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "instrumentation")]
+static MAX_CRITICAL_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Snapshot of the recorded maxima
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InstrumentationStats {
+    pub isr_count: u32,
+    pub max_isr_cycles: u32,
+    pub max_critical_cycles: u32,
+}
+
+/// Record ISR entry, returning the entry timestamp for `isr_exit`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn isr_enter() -> u32 {
+    #[cfg(feature = "instrumentation")]
+    {
+        crate::arch::cycle_counter()
+    }
+
+    #[cfg(not(feature = "instrumentation"))]
+    {
+        0
+    }
+}
+
+/// Record ISR exit and update the longest observed ISR duration
+#[allow(dead_code)]
+#[inline(always)]
+pub fn isr_exit(_entry: u32) {
+    #[cfg(feature = "instrumentation")]
+    {
+        let elapsed = crate::arch::cycle_counter().wrapping_sub(_entry);
+        ISR_COUNT.fetch_add(1, Ordering::Relaxed);
+        MAX_ISR_CYCLES.fetch_max(elapsed, Ordering::Relaxed);
+    }
+}
+
+/// Run an interrupt handler body with entry/exit timestamps
+#[allow(dead_code)]
+#[inline(always)]
+pub fn measure_isr<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let entry = isr_enter();
+    let result = f();
+    isr_exit(entry);
+    result
+}
+
+/// Stamp `event` with the time it is posted
+#[inline(always)]
+pub fn event_posted(_event: &mut crate::scheduler::Event) {
+    #[cfg(feature = "instrumentation")]
+    {
+        _event.posted_us = crate::kernel::time::now_us() as u32;
+    }
+}
+
+/// Microseconds since `event` was posted, `None` when not measured
+#[inline(always)]
+pub fn event_queued_us(_event: &crate::scheduler::Event) -> Option<u32> {
+    #[cfg(feature = "instrumentation")]
+    {
+        Some((crate::kernel::time::now_us() as u32).wrapping_sub(_event.posted_us))
+    }
+
+    #[cfg(not(feature = "instrumentation"))]
+    {
+        None
+    }
+}
+
+/// Mark the start of an interrupts-disabled window; from `arch::mask`,
+/// for the outermost section only
+#[inline(always)]
+pub(crate) fn critical_enter() {
+    #[cfg(feature = "instrumentation")]
+    CRITICAL_START.store(crate::arch::cycle_counter(), Ordering::Relaxed);
+}
+
+/// Mark the end of the window `critical_enter` started
+#[inline(always)]
+pub(crate) fn critical_exit() {
+    #[cfg(feature = "instrumentation")]
+    {
+        let start = CRITICAL_START.load(Ordering::Relaxed);
+        let elapsed = crate::arch::cycle_counter().wrapping_sub(start);
+        MAX_CRITICAL_CYCLES.fetch_max(elapsed, Ordering::Relaxed);
+    }
+}
+
+/// Get recorded maxima (all zero when instrumentation is disabled)
+#[allow(dead_code)]
+pub fn stats() -> InstrumentationStats {
+    #[cfg(feature = "instrumentation")]
+    {
+        InstrumentationStats {
+            isr_count: ISR_COUNT.load(Ordering::Relaxed),
+            max_isr_cycles: MAX_ISR_CYCLES.load(Ordering::Relaxed),
+            max_critical_cycles: MAX_CRITICAL_CYCLES.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(not(feature = "instrumentation"))]
+    {
+        InstrumentationStats::default()
+    }
+}
+
+/// Reset recorded maxima (e.g. after boot noise has settled)
+#[allow(dead_code)]
+pub fn reset() {
+    #[cfg(feature = "instrumentation")]
+    {
+        ISR_COUNT.store(0, Ordering::Relaxed);
+        MAX_ISR_CYCLES.store(0, Ordering::Relaxed);
+        MAX_CRITICAL_CYCLES.store(0, Ordering::Relaxed);
+    }
+}
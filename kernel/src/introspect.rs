@@ -0,0 +1,330 @@
+//! Kernel introspection block
+//! A fixed-layout view of kernel state for debuggers and probe scripts (`introspect`)
+//!
+//! The scheduler's own tables are Rust types whose layout can change with
+//! any compiler or any edit, so a tool reading a halted target cannot
+//! decode them. `KARATOS_INFO` is a `repr(C)` block that can be decoded:
+//! build facts, a copy of the task table, scheduler counters and the
+//! address of this boot's persistent log bank (`crashdump`). Tools find
+//! it by its symbol, through the ELF or `nm`, from an OpenOCD or probe-rs
+//! script or GDB's `x` over the stub, and check `magic` and `version`
+//! before trusting the rest. `init` writes `magic` last, so a block that
+//! reads zero there is not set up yet.
+//!
+//! The copy is refreshed after every scheduler pass, and by the GDB stub
+//! when it stops the system, so a halted target shows the tasks as the
+//! last pass left them. `sequence` is odd while a refresh is under way;
+//! a tool that halts the core mid-refresh should resume and read again.
+//! Without the feature the block is not built and `refresh` compiles to
+//! nothing.
+//!
+//! Layout on the 32-bit targets (pointers and `Str` lengths are 32 bits;
+//! the host simulator has 64-bit pointers, so its offsets differ):
+//!
+//! | offset | field           | type                                       |
+//! |--------|-----------------|--------------------------------------------|
+//! | 0      | magic           | u32, "KINF" (0x4B494E46)                   |
+//! | 4      | version         | u16, `VERSION`                             |
+//! | 6      | size            | u16, bytes in the block                    |
+//! | 8      | sequence        | u32, odd during a refresh                  |
+//! | 12     | semver          | `Str`                                      |
+//! | 20     | git_hash        | `Str`                                      |
+//! | 28     | board           | `Str`                                      |
+//! | 36     | tick_hz         | u32                                        |
+//! | 40     | task_capacity   | u32, entries at `tasks`                    |
+//! | 44     | task_size       | u32, bytes per `TaskInfo`                  |
+//! | 48     | tasks           | pointer to `[TaskInfo; task_capacity]`     |
+//! | 52     | log_lines       | u32, lines per log bank                    |
+//! | 56     | log_line_len    | u32, text bytes per log bank line          |
+//! | 60     | log_bank        | pointer to the log bank, null if none      |
+//! | 64     | ticks           | u64, kernel ticks at the last refresh      |
+//! | 72     | task_count      | u32, entries of `tasks` in use             |
+//! | 76     | current         | u32, index of the running task, or MAX     |
+//! | 80     | dispatches      | u32, tasks handed out to run, all levels   |
+//! | 84     | switches        | u32, switches between tasks, all levels    |
+//! | 88     | events_dropped  | u32, events refused by a full queue        |
+//! | 92     | check_failures  | u32, failed `kensure!` checks (release)    |
+//!
+//! `Str` is a pointer and a byte length, UTF-8 without a terminator.
+//! `TaskInfo` is 28 bytes: id u32, priority u8 (0 critical to 3 low),
+//! state u8 (0 ready, 1 running, 2 waiting, 3 sleeping, 4 completed, as in
+//! crash records), two reserved bytes, detail u32 (the event a waiting
+//! task waits for, the low 32 bits of a sleeping task's wake tick), runs
+//! u32, wakes u32 and name `Str`. A log bank is magic u32 ("KLOG"),
+//! generation u32, lines written u32, then `log_lines` entries of length
+//! u32 and `log_line_len` bytes; line n is at entry `n % log_lines`.
+//!
+//! Fields are only ever added at the end, with `size` growing; a change
+//! to an existing field bumps `VERSION`.
+
+#[cfg(feature = "introspect")]
+use core::cell::UnsafeCell;
+
+#[cfg(feature = "introspect")]
+use crate::arch::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "introspect")]
+use crate::scheduler::{Task, TaskState};
+
+/// Layout version tools check before decoding
+#[allow(dead_code)]
+pub const VERSION: u16 = 1;
+
+/// Marks a set-up block ("KINF")
+#[allow(dead_code)]
+pub const MAGIC: u32 = 0x4B49_4E46;
+
+/// Text as a debugger reads it
+#[cfg(feature = "introspect")]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Str {
+    ptr: *const u8,
+    len: usize,
+}
+
+#[cfg(feature = "introspect")]
+impl Str {
+    const EMPTY: Str = Str { ptr: core::ptr::null(), len: 0 };
+
+    const fn new(text: &'static str) -> Self {
+        Str { ptr: text.as_ptr(), len: text.len() }
+    }
+}
+
+/// One task table entry
+#[cfg(feature = "introspect")]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TaskInfo {
+    id: u32,
+    priority: u8,
+    state: u8,
+    _reserved: [u8; 2],
+    detail: u32,
+    runs: u32,
+    wakes: u32,
+    name: Str,
+}
+
+#[cfg(feature = "introspect")]
+impl TaskInfo {
+    const EMPTY: TaskInfo =
+        TaskInfo { id: 0, priority: 0, state: 0, _reserved: [0; 2], detail: 0, runs: 0, wakes: 0, name: Str::EMPTY };
+
+    fn from_task(task: &Task) -> Self {
+        let (state, detail) = match task.state {
+            TaskState::Ready => (0, 0),
+            TaskState::Running => (1, 0),
+            TaskState::WaitingForEvent(event_id) => (2, event_id),
+            TaskState::Sleeping(wake_time) => (3, wake_time.ticks() as u32),
+            TaskState::Completed => (4, 0),
+        };
+        TaskInfo {
+            id: task.id as u32,
+            priority: task.priority as u8,
+            state,
+            _reserved: [0; 2],
+            detail,
+            runs: task.stats.runs,
+            wakes: task.stats.wakes,
+            name: Str::new(task.name),
+        }
+    }
+}
+
+#[cfg(feature = "introspect")]
+const TASK_CAPACITY: usize = crate::scheduler::TASK_CAPACITY;
+
+/// The block tools read; see the module docs for its layout
+#[cfg(feature = "introspect")]
+#[repr(C)]
+struct KernelInfo {
+    magic: u32,
+    version: u16,
+    size: u16,
+    sequence: AtomicU32,
+    semver: Str,
+    git_hash: Str,
+    board: Str,
+    tick_hz: u32,
+    task_capacity: u32,
+    task_size: u32,
+    tasks: *const TaskInfo,
+    log_lines: u32,
+    log_line_len: u32,
+    log_bank: *const u8,
+    ticks: u64,
+    task_count: u32,
+    current: u32,
+    dispatches: u32,
+    switches: u32,
+    events_dropped: u32,
+    check_failures: u32,
+}
+
+#[cfg(all(feature = "introspect", target_pointer_width = "32"))]
+const _: () = assert!(
+    core::mem::offset_of!(KernelInfo, ticks) == 64
+        && core::mem::size_of::<KernelInfo>() == 96
+        && core::mem::size_of::<TaskInfo>() == 28,
+    "introspection layout moved; update the table in the module docs and VERSION"
+);
+
+#[cfg(feature = "introspect")]
+struct Info(UnsafeCell<KernelInfo>);
+#[cfg(feature = "introspect")]
+unsafe impl Sync for Info {} // Written by the main loop, or the stub with all else stopped
+
+#[cfg(feature = "introspect")]
+struct Tasks(UnsafeCell<[TaskInfo; TASK_CAPACITY]>);
+#[cfg(feature = "introspect")]
+unsafe impl Sync for Tasks {} // As `Info`
+
+// Zeroed until `init`, so a tool does not trust a half-built block
+#[cfg(feature = "introspect")]
+#[no_mangle]
+#[used]
+static KARATOS_INFO: Info = Info(UnsafeCell::new(KernelInfo {
+    magic: 0,
+    version: 0,
+    size: 0,
+    sequence: AtomicU32::new(0),
+    semver: Str::EMPTY,
+    git_hash: Str::EMPTY,
+    board: Str::EMPTY,
+    tick_hz: 0,
+    task_capacity: 0,
+    task_size: 0,
+    tasks: core::ptr::null(),
+    log_lines: 0,
+    log_line_len: 0,
+    log_bank: core::ptr::null(),
+    ticks: 0,
+    task_count: 0,
+    current: u32::MAX,
+    dispatches: 0,
+    switches: 0,
+    events_dropped: 0,
+    check_failures: 0,
+}));
+
+#[cfg(feature = "introspect")]
+static TASKS: Tasks = Tasks(UnsafeCell::new([TaskInfo::EMPTY; TASK_CAPACITY]));
+
+#[cfg(feature = "introspect")]
+fn info() -> &'static mut KernelInfo {
+    unsafe { &mut *KARATOS_INFO.0.get() }
+}
+
+/// Fill in the build facts and publish the block, magic last
+///
+/// Registered as a boot init step, after the persistent log bank is
+/// chosen.
+pub fn init() -> crate::kernel::InitResult {
+    #[cfg(feature = "introspect")]
+    {
+        let block = info();
+        let version = crate::kernel::version();
+        block.version = VERSION;
+        block.size = core::mem::size_of::<KernelInfo>() as u16;
+        block.semver = Str::new(version.semver);
+        block.git_hash = Str::new(version.git_hash);
+        block.board = Str::new(crate::board::descriptor().name);
+        block.tick_hz = crate::config::TICK_HZ;
+        block.task_capacity = TASK_CAPACITY as u32;
+        block.task_size = core::mem::size_of::<TaskInfo>() as u32;
+        block.tasks = TASKS.0.get().cast();
+        block.log_lines = crate::crashdump::PERSISTENT_LOG_LINES as u32;
+        block.log_line_len = crate::crashdump::LOG_LINE_LEN as u32;
+        block.log_bank = crate::crashdump::current_log_bank();
+        refresh();
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+        unsafe { core::ptr::write_volatile(&mut block.magic, MAGIC) };
+    }
+    Ok(())
+}
+
+/// Copy the task table and counters into the block
+///
+/// Called by the scheduler after each pass, outside its lock.
+#[inline(always)]
+pub fn refresh() {
+    #[cfg(feature = "introspect")]
+    {
+        let current = crate::scheduler::current_priority_task();
+        let levels = crate::scheduler::level_stats();
+        let block = begin();
+        block.ticks = crate::kernel::time::now();
+        block.dispatches = levels.iter().fold(0u32, |sum, level| sum.wrapping_add(level.dispatches));
+        block.switches = levels.iter().fold(0u32, |sum, level| sum.wrapping_add(level.switches));
+        block.events_dropped = levels.iter().fold(0u32, |sum, level| sum.wrapping_add(level.dropped));
+        block.check_failures = crate::kassert::failures();
+        let mut copy = TaskCopy::new(current);
+        crate::scheduler::for_each_task(|task| copy.push(task));
+        copy.finish(block);
+        finish(block);
+    }
+}
+
+/// Copy the task table for a debugger that has stopped everything
+///
+/// Reads the executor without its lock, as the crash paths do: the stop
+/// may have come in the middle of a scheduler update. Counters keep
+/// their values from the last pass.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn refresh_stopped() {
+    #[cfg(feature = "introspect")]
+    {
+        let current = crate::scheduler::crash_snapshot().task;
+        let block = begin();
+        block.ticks = crate::kernel::time::now();
+        let mut copy = TaskCopy::new(current);
+        crate::scheduler::crash_visit_tasks(|task| copy.push(task));
+        copy.finish(block);
+        finish(block);
+    }
+}
+
+#[cfg(feature = "introspect")]
+fn begin() -> &'static mut KernelInfo {
+    let block = info();
+    block.sequence.fetch_add(1, Ordering::Release);
+    block
+}
+
+#[cfg(feature = "introspect")]
+fn finish(block: &mut KernelInfo) {
+    block.sequence.fetch_add(1, Ordering::Release);
+}
+
+/// Task table copy under way
+#[cfg(feature = "introspect")]
+struct TaskCopy {
+    running: Option<Task>,
+    count: usize,
+    current: u32,
+}
+
+#[cfg(feature = "introspect")]
+impl TaskCopy {
+    fn new(running: Option<Task>) -> Self {
+        Self { running, count: 0, current: u32::MAX }
+    }
+
+    fn push(&mut self, task: &Task) {
+        if self.count == TASK_CAPACITY {
+            return;
+        }
+        if self.running.as_ref().is_some_and(|running| running.id == task.id && running.priority == task.priority) {
+            self.current = self.count as u32;
+        }
+        unsafe { (*TASKS.0.get())[self.count] = TaskInfo::from_task(task) };
+        self.count += 1;
+    }
+
+    fn finish(self, block: &mut KernelInfo) {
+        block.task_count = self.count as u32;
+        block.current = self.current;
+    }
+}
@@ -0,0 +1,82 @@
+//! Kernel assertions
+//! `kassert!` and `kensure!` checks that trap in debug builds
+//!
+//! In debug builds a failed check logs the expression, location and the
+//! task that was running, then panics (so the panic handler reports it and
+//! saves a crash record). In release builds `kassert!` compiles to nothing
+//! and `kensure!` only bumps a failure counter before returning its error,
+//! so callers that ignore the result no longer hide the failure entirely.
+
+use core::fmt;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// Failed `kensure!` checks since boot (release builds only)
+#[allow(dead_code)]
+pub fn failures() -> u32 {
+    FAILURES.load(Ordering::Relaxed)
+}
+
+/// Check an invariant; debug builds panic if it does not hold
+///
+/// `kassert!(cond)` or `kassert!(cond, "format", args..)`. The condition is
+/// not evaluated in release builds.
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if cfg!(debug_assertions) && !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), None);
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if cfg!(debug_assertions) && !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), Some(format_args!($($arg)+)));
+        }
+    };
+}
+
+/// Return `$ret` from the enclosing function unless `$cond` holds
+///
+/// `kensure!(cond, ret)` or `kensure!(cond, ret, "format", args..)`. Debug
+/// builds panic instead of returning; release builds count the failure.
+#[macro_export]
+macro_rules! kensure {
+    ($cond:expr, $ret:expr $(,)?) => {
+        if !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), None);
+            return $ret;
+        }
+    };
+    ($cond:expr, $ret:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), Some(format_args!($($arg)+)));
+            return $ret;
+        }
+    };
+}
+
+/// Report a failed check; panics in debug builds
+///
+/// The running task is read without a critical section because checks
+/// can fail inside one (e.g. within a scheduler method).
+#[doc(hidden)]
+#[cold]
+#[inline(never)]
+pub fn fail(expr: &str, file: &str, line: u32, message: Option<fmt::Arguments>) {
+    if !cfg!(debug_assertions) {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let task = crate::scheduler::crash_snapshot().task.map(|task| task.id);
+    match message {
+        Some(message) => crate::log_error!("[kassert] {} failed at {}:{}: {}", expr, file, line, message),
+        None => crate::log_error!("[kassert] {} failed at {}:{}", expr, file, line),
+    }
+    match task {
+        Some(id) => panic!("kernel check `{}` failed at {}:{} in task {}", expr, file, line, id),
+        None => panic!("kernel check `{}` failed at {}:{}", expr, file, line),
+    }
+}
@@ -1,23 +1,394 @@
 //! Kernel core module
 //! Architecture-agnostic kernel initialization and management
+//!
+//! Also owns the kernel service table: every service reachable through a
+//! syscall number, with its argument count and validating handler. Kernel
+//! tasks call `call()` directly; SVC/ecall traps reach the same table
+//! through `crate::syscall::dispatch`, so both paths validate identically.
+//!
+//! Task communication primitives live in submodules (`channel`, `pipe`,
+//! and for tasks that await futures, `sync`), as do the
+//! generation-tagged object handles (`handle`), kernel time (`time`),
+//! periodic task releases and deadlines (`periodic`) and the embassy-time
+//! driver built on time (`time_driver`), whose alarm queue `hal_async`
+//! waits on too. With `ktest`, `ktest_cases` holds the test kernel's
+//! cases for these primitives; with `bench`, `bench` times them. With
+//! `trace`, `trace` records the scheduler's inputs, and replays them on
+//! the simulator. With `fault_inject`, `faults` makes spawns, events,
+//! ticks and kernel queue slots fail on purpose at configured rates.
+//! `dump` writes the post-mortem task and queue report (`dump_state`)
+//! the panic and fault handlers and the shell print.
 
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use heapless::Deque;
+
+use crate::accounting::{self, Resource};
+use crate::arch::atomic::{AtomicU32, Ordering};
 use crate::arch::ArchInit;
 use crate::drivers;
+use crate::memory::RegionKind;
+use crate::scheduler::{self, EventPriority, Task, TaskEntry, TaskPriority};
+use crate::syscall::{Syscall, SyscallError, SYSCALL_ERROR_BASE, SYSCALL_MAX_ARGS};
+
+#[cfg(feature = "bench")]
+pub mod bench;
+#[allow(dead_code)]
+mod channel;
+mod dump;
+pub mod faults;
+#[allow(dead_code)]
+mod pipe;
+#[allow(dead_code)]
+mod handle;
+#[cfg(feature = "ktest")]
+pub(crate) mod ktest_cases;
+pub mod periodic;
+pub mod time;
+#[cfg(feature = "timers")]
+pub mod timer;
+mod timer_wheel;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[allow(dead_code)]
+mod sync;
+#[cfg(any(feature = "embassy_time", feature = "hal_async"))]
+pub(crate) mod time_driver;
+
+#[allow(unused_imports)]
+pub use dump::{dump_state, dump_to_console};
+#[allow(unused_imports)]
+pub use channel::{channel, Channel, Receiver, Sender, TryRecvError};
+#[allow(unused_imports)]
+pub use pipe::{Pipe, PipeError};
+#[allow(unused_imports)]
+pub use sync::{AsyncChannel, AsyncMutex, AsyncMutexGuard, LockFuture, Notify, RecvFuture, SendFuture, WaitFuture};
+#[allow(unused_imports)]
+pub use handle::{Generations, Handle, HandleError, HandleKind, MAX_HANDLE_INDEX};
+pub use timer_wheel::TimerWheel;
+
+/// First scheduler event id handed out to kernel objects for wakeups
+pub const WAIT_EVENT_BASE: u32 = 0x1000_0000;
+
+static NEXT_WAIT_EVENT: AtomicU32 = AtomicU32::new(WAIT_EVENT_BASE);
+
+/// Allocate a scheduler event id for tasks to wait on a kernel object
+#[allow(dead_code)]
+pub(crate) fn alloc_wait_event() -> u32 {
+    NEXT_WAIT_EVENT.fetch_add(1, Ordering::Relaxed)
+}
+
+// -------- Version and build info --------
+
+/// Identification of the running kernel build
+#[derive(Copy, Clone, Debug)]
+pub struct Version {
+    /// Crate semantic version
+    pub semver: &'static str,
+    /// Short commit hash, `-dirty` if built with local changes
+    pub git_hash: &'static str,
+    /// Cargo profile (debug/release)
+    pub profile: &'static str,
+    /// Target triple
+    pub target: &'static str,
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "karatOS {} ({}, {}, {})", self.semver, self.git_hash, self.profile, self.target)
+    }
+}
+
+/// Version and build information, embedded by build.rs
+pub const fn version() -> Version {
+    Version {
+        semver: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("KARATOS_GIT_HASH"),
+        profile: env!("KARATOS_BUILD_PROFILE"),
+        target: env!("KARATOS_TARGET"),
+    }
+}
+
+// -------- Ordered init stages --------
+
+/// Boot stages, run in declaration order
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InitStage {
+    Arch = 0,
+    Memory = 1,
+    Drivers = 2,
+    Services = 3,
+    Tasks = 4,
+}
+
+impl InitStage {
+    pub const ALL: [InitStage; 5] = [
+        InitStage::Arch,
+        InitStage::Memory,
+        InitStage::Drivers,
+        InitStage::Services,
+        InitStage::Tasks,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            InitStage::Arch => "arch",
+            InitStage::Memory => "memory",
+            InitStage::Drivers => "drivers",
+            InitStage::Services => "services",
+            InitStage::Tasks => "tasks",
+        }
+    }
+}
+
+/// Init function result; the error is a short reason for the boot log
+pub type InitResult = Result<(), &'static str>;
+
+/// One registered init function
+pub struct InitEntry {
+    pub stage: InitStage,
+    /// Lower runs first within a stage
+    pub order: u8,
+    pub name: &'static str,
+    pub func: fn() -> InitResult,
+}
+
+/// Outcome of one init stage
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct StageReport {
+    pub stage: InitStage,
+    pub entries: u8,
+    pub failures: u8,
+    pub cycles: u32,
+}
+
+/// Register init functions: `Stage, order, "name" => path::to::fn;`
+macro_rules! init_table {
+    ($($stage:ident, $order:literal, $name:literal => $func:path;)*) => {
+        static INIT_TABLE: &[InitEntry] = &[
+            $(InitEntry { stage: InitStage::$stage, order: $order, name: $name, func: $func },)*
+        ];
+    };
+}
+
+init_table! {
+    Arch, 0, "cycle counter" => cycle_counter_init;
+    Arch, 10, "cpu" => arch_init;
+    Memory, 0, "memory map" => memory_init;
+    Memory, 10, "stack watermark" => crate::memory::paint_stack;
+    Memory, 20, "persistent log" => crate::crashdump::init_log;
+    Drivers, 0, "console" => console_init;
+    Drivers, 2, "rtt" => rtt_init;
+    Drivers, 5, "log facade" => log_facade_init;
+    Drivers, 10, "console rx" => drivers::console_rx::init;
+    Drivers, 20, "rng" => drivers::rng::init;
+    Services, 0, "crash record" => crate::crashdump::init;
+    Services, 10, "wall clock" => time::init;
+    Services, 20, "delay calibration" => time::calibrate_delay;
+    Services, 30, "shell" => crate::shell::init;
+    Services, 40, "gdb stub" => gdb_init;
+    Services, 50, "log drain" => log_drain_init;
+    Services, 60, "timer service" => timer_service_init;
+    Services, 70, "fault injection" => faults::init;
+    Services, 80, "introspection" => crate::introspect::init;
+    Services, 90, "executor" => executor_init;
+    Services, 100, "network" => net_init;
+}
 
-/// Initialize the kernel for the current architecture
+/// Most entries a single stage may hold
+const MAX_STAGE_ENTRIES: usize = 16;
+
+struct StageReports(UnsafeCell<[Option<StageReport>; InitStage::ALL.len()]>);
+unsafe impl Sync for StageReports {} // Written once at boot
+
+static STAGE_REPORTS: StageReports = StageReports(UnsafeCell::new([None; InitStage::ALL.len()]));
+
+/// Run every registered init function, stage by stage
+///
+/// A failing entry is reported and the rest still run, so one missing
+/// peripheral does not keep the console from coming up.
 pub fn init() {
-    // Initialize architecture-specific components
+    // RISC-V harts leave reset with MIE clear; drivers unmask their own
+    // sources as they come up
+    crate::arch::enable_interrupts();
+
+    for stage in InitStage::ALL {
+        let report = run_stage(stage);
+        unsafe {
+            (*STAGE_REPORTS.0.get())[stage as usize] = Some(report);
+        }
+        if report.entries > 0 {
+            let mut line = heapless::String::<64>::new();
+            let _ = write!(
+                line,
+                "[init] {}: {}/{} ok, {} cycles",
+                stage.name(),
+                report.entries - report.failures,
+                report.entries,
+                report.cycles
+            );
+            drivers::uart::print(&line);
+        }
+    }
+
+    drivers::uart::print("karatOS kernel initialized");
+}
+
+fn run_stage(stage: InitStage) -> StageReport {
+    let mut entries: heapless::Vec<&InitEntry, MAX_STAGE_ENTRIES> = heapless::Vec::new();
+    for entry in INIT_TABLE.iter().filter(|entry| entry.stage == stage) {
+        if entries.push(entry).is_err() {
+            drivers::uart::print("[init] too many entries in one stage");
+            break;
+        }
+    }
+    entries.sort_unstable_by_key(|entry| entry.order);
+
+    let mut failures = 0;
+    let start = crate::arch::cycle_counter();
+    for entry in entries.iter() {
+        if let Err(reason) = (entry.func)() {
+            failures += 1;
+            let mut line = heapless::String::<96>::new();
+            let _ = write!(line, "[init] {}/{} failed: {}", stage.name(), entry.name, reason);
+            drivers::uart::print(&line);
+        }
+    }
+
+    StageReport {
+        stage,
+        entries: entries.len() as u8,
+        failures,
+        cycles: crate::arch::cycle_counter().wrapping_sub(start),
+    }
+}
+
+/// Per-stage results of `init()`, `None` for stages not yet run
+#[allow(dead_code)]
+pub fn init_report() -> [Option<StageReport>; InitStage::ALL.len()] {
+    unsafe { *STAGE_REPORTS.0.get() }
+}
+
+fn arch_init() -> InitResult {
     #[cfg(feature = "arm")]
     crate::arch::arm::ArmArch::init();
-    
+
     #[cfg(feature = "riscv")]
     crate::arch::riscv::RiscvArch::init();
-    
-    // Initialize drivers
-    drivers::uart::init();
-    
-    // Print boot message
-    drivers::uart::print("karatOS kernel initialized\n");
+
+    #[cfg(feature = "sim")]
+    crate::arch::sim::SimArch::init();
+
+    Ok(())
+}
+
+/// Always on: stage timing and instrumentation both timestamp with it
+fn cycle_counter_init() -> InitResult {
+    crate::arch::init_cycle_counter();
+    Ok(())
+}
+
+fn memory_init() -> InitResult {
+    let regions = crate::memory::get_memory_regions();
+    if regions.ram_size == 0 {
+        return Err("no RAM region");
+    }
+    Ok(())
+}
+
+fn console_init() -> InitResult {
+    drivers::uart::init()
+}
+
+/// Early, so a probe sees the log lines of the remaining init
+fn rtt_init() -> InitResult {
+    #[cfg(feature = "log_rtt")]
+    drivers::rtt::init();
+    Ok(())
+}
+
+/// Before the other drivers, which may log through the `log` crate
+fn log_facade_init() -> InitResult {
+    #[cfg(feature = "log_facade")]
+    crate::logger::facade::init();
+    Ok(())
+}
+
+fn gdb_init() -> InitResult {
+    #[cfg(feature = "gdb_stub")]
+    return crate::gdb::init();
+
+    #[cfg(not(feature = "gdb_stub"))]
+    Ok(())
+}
+
+fn log_drain_init() -> InitResult {
+    #[cfg(feature = "log_drain")]
+    return crate::log_drain::init();
+
+    #[cfg(not(feature = "log_drain"))]
+    Ok(())
+}
+
+fn executor_init() -> InitResult {
+    #[cfg(feature = "async_tasks")]
+    return crate::executor::init();
+
+    #[cfg(not(feature = "async_tasks"))]
+    Ok(())
+}
+
+fn net_init() -> InitResult {
+    #[cfg(feature = "net")]
+    return crate::net::init();
+
+    #[cfg(not(feature = "net"))]
+    Ok(())
+}
+
+fn timer_service_init() -> InitResult {
+    #[cfg(feature = "timers")]
+    return timer::init();
+
+    #[cfg(not(feature = "timers"))]
+    Ok(())
+}
+
+/// Reboot the system after stopping tasks and draining the console
+#[allow(dead_code)]
+pub fn reboot() -> ! {
+    stop_system("rebooting");
+    crate::arch::arch_reset()
+}
+
+/// Shut down: exit QEMU with success, or halt on hardware
+#[allow(dead_code)]
+pub fn shutdown() -> ! {
+    stop_system("shutting down");
+    crate::arch::qemu_exit(0)
+}
+
+/// Common teardown for `reboot` and `shutdown`
+///
+/// Tasks go first (lowest priority first) so nothing queues new output,
+/// then the console is drained so the final lines are not cut off. The
+/// logger keeps its ring in RAM with no pending output to flush.
+///
+/// Every task gets `SIGNAL_STOP` before its slot is cleared, so the task
+/// calling this (and any code it runs on the way down) sees
+/// `is_cancelled()` and skips starting new work.
+fn stop_system(action: &str) {
+    scheduler::signal_all_tasks(scheduler::SIGNAL_STOP);
+    let stopped = scheduler::stop_all_tasks();
+
+    let mut line = heapless::String::<64>::new();
+    let _ = write!(line, "[kernel] {}: stopped {} tasks", action, stopped);
+    drivers::uart::print(&line);
+
+    crate::arch::disable_interrupts();
+    crate::arch::console_flush();
 }
 
 /// Main kernel loop
@@ -34,3 +405,199 @@ pub fn run() -> ! {
         unsafe { core::arch::asm!("wfi") };
     }
 }
+
+// -------- Kernel service table --------
+
+/// Number of kernel message queues addressable by queue id
+pub const KERNEL_QUEUES: usize = 4;
+
+/// Messages each kernel queue holds before sends fail
+pub const KERNEL_QUEUE_DEPTH: usize = 8;
+
+/// Longest buffer accepted by a single console write
+pub const CONSOLE_WRITE_MAX: usize = 256;
+
+type ServiceHandler = fn(&[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError>;
+
+/// One kernel service behind a syscall number
+pub struct Service {
+    pub syscall: Syscall,
+    #[allow(dead_code)]
+    pub name: &'static str,
+    /// Argument slots used; the rest must be zero
+    pub argc: usize,
+    handler: ServiceHandler,
+}
+
+static SERVICES: [Service; 7] = [
+    Service { syscall: Syscall::Spawn, name: "spawn", argc: 3, handler: service_spawn },
+    Service { syscall: Syscall::PostEvent, name: "post_event", argc: 2, handler: service_post_event },
+    Service { syscall: Syscall::Sleep, name: "sleep", argc: 1, handler: service_sleep },
+    Service { syscall: Syscall::QueueSend, name: "queue_send", argc: 2, handler: service_queue_send },
+    Service { syscall: Syscall::QueueReceive, name: "queue_receive", argc: 1, handler: service_queue_receive },
+    Service { syscall: Syscall::ConsoleWrite, name: "console_write", argc: 2, handler: service_console_write },
+    Service { syscall: Syscall::Kill, name: "kill", argc: 1, handler: service_kill },
+];
+
+/// Call a kernel service directly, with the same validation as a syscall
+pub fn call(syscall: Syscall, args: [usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let service = SERVICES
+        .iter()
+        .find(|service| service.syscall == syscall)
+        .ok_or(SyscallError::NoSuchSyscall)?;
+
+    if args[service.argc..].iter().any(|&arg| arg != 0) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    (service.handler)(&args)
+}
+
+/// All registered kernel services
+#[allow(dead_code)]
+pub fn services() -> &'static [Service] {
+    &SERVICES
+}
+
+fn service_spawn(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let priority = task_priority_from_raw(args[1])?;
+    let entry = task_entry_from_raw(args[2])?;
+    scheduler::add_priority_task(Task::with_priority(args[0], priority).entry(entry))
+        .map(|handle| handle.raw() as usize)
+        .map_err(|_| SyscallError::NoSpace)
+}
+
+fn service_kill(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let handle = u32::try_from(args[0]).ok().and_then(Handle::from_raw).ok_or(SyscallError::InvalidArgument)?;
+    scheduler::kill_handle(handle).map_err(|error| match error {
+        HandleError::Invalid => SyscallError::InvalidArgument,
+        HandleError::StaleHandle => SyscallError::StaleHandle,
+    })?;
+    Ok(0)
+}
+
+fn service_post_event(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let event_id = u32::try_from(args[0]).map_err(|_| SyscallError::InvalidArgument)?;
+    let priority = event_priority_from_raw(args[1])?;
+    Ok(scheduler::post_priority_event(event_id, priority) as usize)
+}
+
+fn service_sleep(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let duration = u32::try_from(args[0]).map_err(|_| SyscallError::InvalidArgument)?;
+    scheduler::sleep_current(time::Duration::from_ticks(duration as u64));
+    Ok(0)
+}
+
+fn service_queue_send(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let message = args[1];
+    if message >= SYSCALL_ERROR_BASE {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let owner = accounting::current_owner();
+    let result = with_queue(args[0], |queue| {
+        if faults::inject(faults::Fault::Alloc) {
+            return Err(SyscallError::NoSpace);
+        }
+        queue.push_back(QueuedWord { message, owner }).map_err(|_| SyscallError::NoSpace)
+    });
+    match result {
+        Ok(()) => accounting::charge(owner, Resource::QueueSlot, 1),
+        Err(SyscallError::NoSpace) => accounting::deny(owner, Resource::QueueSlot),
+        Err(_) => {}
+    }
+    result.map(|()| 0)
+}
+
+fn service_queue_receive(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let word = with_queue(args[0], |queue| queue.pop_front().ok_or(SyscallError::WouldBlock))?;
+    accounting::credit(word.owner, Resource::QueueSlot, 1);
+    Ok(word.message)
+}
+
+fn service_console_write(args: &[usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    let bytes = caller_buffer(args[0], args[1])?;
+    crate::arch::console_write(bytes);
+    Ok(bytes.len())
+}
+
+fn task_priority_from_raw(raw: usize) -> Result<TaskPriority, SyscallError> {
+    match raw {
+        0 => Ok(TaskPriority::Critical),
+        1 => Ok(TaskPriority::High),
+        2 => Ok(TaskPriority::Normal),
+        3 => Ok(TaskPriority::Low),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Task entry at `raw`, which must lie in the kernel's code; a task
+/// without one could never run
+fn task_entry_from_raw(raw: usize) -> Result<TaskEntry, SyscallError> {
+    let (start, end) = crate::arch::code_region().ok_or(SyscallError::InvalidArgument)?;
+    // Thumb function addresses carry the instruction set in bit 0
+    let (addr, aligned) = if cfg!(target_arch = "arm") {
+        (raw & !1, raw & 1 == 1)
+    } else if cfg!(target_arch = "riscv32") {
+        (raw, raw.is_multiple_of(2))
+    } else {
+        (raw, true)
+    };
+    if !aligned || !(start..end).contains(&addr) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    // SAFETY: `raw` is a code address of this kernel; that a `TaskEntry`
+    // starts there is the caller's word, as for any entry it hands over
+    Ok(unsafe { core::mem::transmute::<usize, TaskEntry>(raw) })
+}
+
+fn event_priority_from_raw(raw: usize) -> Result<EventPriority, SyscallError> {
+    match raw {
+        0 => Ok(EventPriority::Critical),
+        1 => Ok(EventPriority::High),
+        2 => Ok(EventPriority::Normal),
+        3 => Ok(EventPriority::Low),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Borrow a caller buffer, rejecting anything outside RAM or flash
+fn caller_buffer(addr: usize, len: usize) -> Result<&'static [u8], SyscallError> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if len > CONSOLE_WRITE_MAX {
+        return Err(SyscallError::InvalidArgument);
+    }
+    match crate::memory::region_kind(addr, len) {
+        Some(RegionKind::Ram | RegionKind::Flash) => Ok(unsafe { core::slice::from_raw_parts(addr as *const u8, len) }),
+        _ => Err(SyscallError::BadAddress),
+    }
+}
+
+/// Queued message word and the task its slot is charged to
+#[derive(Copy, Clone)]
+struct QueuedWord {
+    message: usize,
+    owner: usize,
+}
+
+struct KernelQueues(UnsafeCell<[Deque<QueuedWord, KERNEL_QUEUE_DEPTH>; KERNEL_QUEUES]>);
+unsafe impl Sync for KernelQueues {} // Single-core assumption
+
+static QUEUES: KernelQueues = KernelQueues(UnsafeCell::new([const { Deque::new() }; KERNEL_QUEUES]));
+
+fn with_queue<R>(
+    id: usize,
+    f: impl FnOnce(&mut Deque<QueuedWord, KERNEL_QUEUE_DEPTH>) -> Result<R, SyscallError>,
+) -> Result<R, SyscallError> {
+    if id >= KERNEL_QUEUES {
+        return Err(SyscallError::InvalidArgument);
+    }
+    crate::arch::free(|| unsafe { f(&mut (*QUEUES.0.get())[id]) })
+}
+
+/// Messages waiting in each kernel queue
+///
+/// Leaves interrupts off if they were, so fatal paths can call it.
+pub fn queue_depths() -> [usize; KERNEL_QUEUES] {
+    crate::arch::free(|| core::array::from_fn(|id| unsafe { (*QUEUES.0.get())[id].len() }))
+}
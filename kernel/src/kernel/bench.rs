@@ -0,0 +1,246 @@
+//! Benchmarks
+//! Cycle counts for kernel primitives, run from the shell or the test kernel
+//!
+//! Each benchmark times one operation with the architecture cycle counter
+//! over a number of samples:
+//!
+//! - `switch`: the running task blocks and the next ready one is picked
+//! - `post-wake`: an event is posted and the task waiting on it is picked
+//! - `channel`: one message sent and received through a channel
+//! - `critical`: entering and leaving an interrupts-off critical section
+//!
+//! The scheduling benchmarks drive an executor of their own rather than
+//! the live one, so no task is disturbed. Its two tasks sit at `Low`, so
+//! every pick walks all four levels, the longest path. Those samples run
+//! with interrupts off, as the kernel runs under its scheduler lock, so a
+//! tick cannot land inside one; `channel` and `critical` take the lock
+//! themselves. The cost of reading the cycle counter is measured first
+//! and taken off every sample.
+//!
+//! Results are cycles per operation: minimum, average and maximum. The
+//! minimum is the figure to compare across commits, as interrupts and
+//! cache misses only ever add to it; the maximum shows that interference.
+//! Figures compare only between builds for the same target and profile.
+//! Events posted by `post-wake` go on to the event handler table like any
+//! other; `BENCH_EVENT` is outside the conventional ID ranges.
+
+use core::cell::UnsafeCell;
+
+use super::channel::{channel, Channel, Receiver, Sender};
+use crate::arch::atomic::{AtomicBool, Ordering};
+use crate::scheduler::{Event, EventPriority, MultiPriorityExecutor, Task, TaskPriority};
+
+/// Samples per benchmark unless the caller asks for another count
+pub const DEFAULT_SAMPLES: u32 = 1000;
+
+/// Event the `post-wake` task waits on
+pub const BENCH_EVENT: u32 = 0xBE7C;
+
+/// Messages per `channel` sample; each sample fills and drains it
+const CHANNEL_DEPTH: usize = 8;
+
+/// Tasks of the benchmark executor
+const TASK_A: usize = 1;
+const TASK_B: usize = 2;
+
+/// Why a run produced no results
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BenchError {
+    /// Another run is in progress
+    Busy,
+    /// Zero samples asked for
+    NoSamples,
+    /// A primitive did not behave as the benchmark expects
+    Broken(&'static str),
+}
+
+/// One benchmark's figures, in cycles per operation
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub samples: u32,
+    pub min: u32,
+    pub avg: u32,
+    pub max: u32,
+}
+
+/// Running figures for one benchmark
+struct Samples {
+    name: &'static str,
+    /// Cycles to read the counter, taken off each sample
+    overhead: u32,
+    count: u32,
+    min: u32,
+    max: u32,
+    total: u64,
+}
+
+impl Samples {
+    fn new(name: &'static str, overhead: u32) -> Self {
+        Self { name, overhead, count: 0, min: u32::MAX, max: 0, total: 0 }
+    }
+
+    /// Record a sample of `cycles` spent on `ops` operations
+    fn add(&mut self, cycles: u32, ops: u32) {
+        let per_op = cycles.saturating_sub(self.overhead) / ops;
+        self.count += 1;
+        self.min = self.min.min(per_op);
+        self.max = self.max.max(per_op);
+        self.total += per_op as u64;
+    }
+
+    fn result(&self) -> BenchResult {
+        BenchResult {
+            name: self.name,
+            samples: self.count,
+            min: if self.count == 0 { 0 } else { self.min },
+            avg: self.total.checked_div(self.count as u64).unwrap_or(0) as u32,
+            max: self.max,
+        }
+    }
+}
+
+struct BenchState {
+    executor: MultiPriorityExecutor,
+    channel: Option<(Sender<u32, CHANNEL_DEPTH>, Receiver<u32, CHANNEL_DEPTH>)>,
+}
+
+struct BenchCell(UnsafeCell<BenchState>);
+unsafe impl Sync for BenchCell {} // Only touched by the run holding `RUNNING`
+
+static STATE: BenchCell = BenchCell(UnsafeCell::new(BenchState { executor: MultiPriorityExecutor::new(), channel: None }));
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static CHANNEL: Channel<u32, CHANNEL_DEPTH> = Channel::new();
+
+/// Run every benchmark with `samples` samples each, passing each result
+/// to `report` as it completes
+///
+/// Returns the cycle counter overhead taken off the samples.
+pub fn run(samples: u32, mut report: impl FnMut(&BenchResult)) -> Result<u32, BenchError> {
+    if samples == 0 {
+        return Err(BenchError::NoSamples);
+    }
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err(BenchError::Busy);
+    }
+    let state = unsafe { &mut *STATE.0.get() };
+    let result = run_all(state, samples, &mut report);
+    RUNNING.store(false, Ordering::Release);
+    result
+}
+
+fn run_all(state: &mut BenchState, samples: u32, report: &mut impl FnMut(&BenchResult)) -> Result<u32, BenchError> {
+    let overhead = counter_overhead(samples);
+    report(&switch(&mut state.executor, samples, overhead)?);
+    report(&post_wake(&mut state.executor, samples, overhead)?);
+    if state.channel.is_none() {
+        state.channel = channel(&CHANNEL);
+    }
+    let (sender, receiver) = state.channel.as_ref().ok_or(BenchError::Broken("channel taken"))?;
+    report(&channel_round_trip(sender, receiver, samples, overhead)?);
+    report(&critical_section(samples, overhead));
+    Ok(overhead)
+}
+
+/// Fewest cycles between two back-to-back counter reads
+fn counter_overhead(samples: u32) -> u32 {
+    (0..samples)
+        .map(|_| {
+            crate::arch::free(|| {
+                let start = crate::arch::cycle_counter();
+                crate::arch::cycle_counter().wrapping_sub(start)
+            })
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+/// Empty the executor and start it on task A, with B ready
+fn reset(executor: &mut MultiPriorityExecutor) -> Result<(), BenchError> {
+    executor.stop_all();
+    for id in [TASK_A, TASK_B] {
+        executor
+            .spawn_task(Task::with_priority(id, TaskPriority::Low).named("bench"))
+            .map_err(|_| BenchError::Broken("spawn failed"))?;
+    }
+    expect(executor.run_cycle(), TASK_A)
+}
+
+fn expect(picked: Option<Task>, id: usize) -> Result<(), BenchError> {
+    match picked {
+        Some(task) if task.id == id => Ok(()),
+        _ => Err(BenchError::Broken("wrong task picked")),
+    }
+}
+
+fn switch(executor: &mut MultiPriorityExecutor, samples: u32, overhead: u32) -> Result<BenchResult, BenchError> {
+    let mut figures = Samples::new("switch", overhead);
+    reset(executor)?;
+    for _ in 0..samples {
+        let (cycles, picked) = crate::arch::free(|| {
+            let start = crate::arch::cycle_counter();
+            executor.block_current(BENCH_EVENT);
+            let picked = executor.run_cycle();
+            (crate::arch::cycle_counter().wrapping_sub(start), picked)
+        });
+        expect(picked, TASK_B)?;
+        figures.add(cycles, 1);
+        // Back to A running, B ready
+        crate::arch::free(|| executor.wake(BENCH_EVENT));
+        expect(crate::arch::free(|| executor.run_cycle()), TASK_A)?;
+    }
+    Ok(figures.result())
+}
+
+fn post_wake(executor: &mut MultiPriorityExecutor, samples: u32, overhead: u32) -> Result<BenchResult, BenchError> {
+    let mut figures = Samples::new("post-wake", overhead);
+    reset(executor)?;
+    for _ in 0..samples {
+        crate::arch::free(|| executor.block_current(BENCH_EVENT));
+        expect(crate::arch::free(|| executor.run_cycle()), TASK_B)?;
+        let (cycles, picked) = crate::arch::free(|| {
+            let start = crate::arch::cycle_counter();
+            executor.post_event(Event::new(BENCH_EVENT, EventPriority::Low));
+            let picked = executor.run_cycle();
+            (crate::arch::cycle_counter().wrapping_sub(start), picked)
+        });
+        expect(picked, TASK_A)?;
+        figures.add(cycles, 1);
+        // Processing queued the event for its handler; pass it on now
+        crate::events::dispatch_pending();
+    }
+    Ok(figures.result())
+}
+
+fn channel_round_trip(
+    sender: &Sender<u32, CHANNEL_DEPTH>,
+    receiver: &Receiver<u32, CHANNEL_DEPTH>,
+    samples: u32,
+    overhead: u32,
+) -> Result<BenchResult, BenchError> {
+    let mut figures = Samples::new("channel", overhead);
+    while receiver.try_recv().is_ok() {}
+    for _ in 0..samples {
+        let start = crate::arch::cycle_counter();
+        for message in 0..CHANNEL_DEPTH as u32 {
+            sender.try_send(message).map_err(|_| BenchError::Broken("channel full"))?;
+        }
+        for _ in 0..CHANNEL_DEPTH {
+            receiver.try_recv().map_err(|_| BenchError::Broken("channel empty"))?;
+        }
+        figures.add(crate::arch::cycle_counter().wrapping_sub(start), CHANNEL_DEPTH as u32);
+    }
+    Ok(figures.result())
+}
+
+fn critical_section(samples: u32, overhead: u32) -> BenchResult {
+    let mut figures = Samples::new("critical", overhead);
+    for _ in 0..samples {
+        let start = crate::arch::cycle_counter();
+        crate::arch::free(|| ());
+        figures.add(crate::arch::cycle_counter().wrapping_sub(start), 1);
+    }
+    figures.result()
+}
@@ -0,0 +1,164 @@
+//! Multi-producer, single-consumer channels
+//! Typed message passing between tasks and from ISRs
+//!
+//! Storage is a caller-provided `static Channel<T, N>` (the kernel has no
+//! heap); `channel()` splits it once into a cloneable `Sender` and the
+//! only `Receiver`. Each channel owns a scheduler event id: a receiver
+//! that finds the channel empty is parked in `TaskState::WaitingForEvent`
+//! on it, and the next send wakes it.
+
+use core::cell::UnsafeCell;
+
+use heapless::Deque;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::scheduler;
+
+/// Why a receive returned no message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// Nothing queued (for `recv`, the task is now waiting on the channel)
+    Empty,
+    /// Queue drained and every `Sender` dropped
+    Disconnected,
+}
+
+impl crate::kernel::time::WouldBlock for TryRecvError {
+    fn would_block(&self) -> bool {
+        *self == TryRecvError::Empty
+    }
+}
+
+/// Backing storage for one channel of up to `N` messages
+pub struct Channel<T, const N: usize> {
+    queue: UnsafeCell<Deque<T, N>>,
+    event_id: AtomicU32,
+    senders: AtomicU32,
+    split: AtomicBool,
+    receiver_waiting: AtomicBool,
+}
+
+// Queue access is serialized by disabling interrupts (single core)
+unsafe impl<T: Send, const N: usize> Sync for Channel<T, N> {}
+
+impl<T, const N: usize> Channel<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: UnsafeCell::new(Deque::new()),
+            event_id: AtomicU32::new(0),
+            senders: AtomicU32::new(0),
+            split: AtomicBool::new(false),
+            receiver_waiting: AtomicBool::new(false),
+        }
+    }
+
+    /// Run `f` on the queue with interrupts off, restoring their state;
+    /// senders may be interrupt handlers or already in a critical section
+    fn with_queue<R>(&self, f: impl FnOnce(&mut Deque<T, N>) -> R) -> R {
+        crate::arch::free(|| unsafe { f(&mut *self.queue.get()) })
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Split `storage` into its sending and receiving halves
+///
+/// Returns `None` if the channel was already split, which keeps the
+/// receiver unique.
+pub fn channel<T, const N: usize>(storage: &'static Channel<T, N>) -> Option<(Sender<T, N>, Receiver<T, N>)> {
+    if storage.split.swap(true, Ordering::AcqRel) {
+        return None;
+    }
+    storage.event_id.store(super::alloc_wait_event(), Ordering::Relaxed);
+    storage.senders.store(1, Ordering::Relaxed);
+    Some((Sender { channel: storage }, Receiver { channel: storage }))
+}
+
+/// Sending half; clone it to add producers
+pub struct Sender<T: 'static, const N: usize> {
+    channel: &'static Channel<T, N>,
+}
+
+impl<T, const N: usize> Sender<T, N> {
+    /// Queue `value` without blocking, handing it back if the channel is full
+    ///
+    /// Safe to call from interrupt handlers.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.channel.with_queue(|queue| queue.push_back(value))?;
+        if self.channel.receiver_waiting.swap(false, Ordering::AcqRel) {
+            scheduler::wake_event(self.channel.event_id.load(Ordering::Relaxed));
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Clone for Sender<T, N> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::Relaxed);
+        Sender { channel: self.channel }
+    }
+}
+
+impl<T, const N: usize> Drop for Sender<T, N> {
+    fn drop(&mut self) {
+        // Last sender gone: wake the receiver so it sees the disconnect
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1
+            && self.channel.receiver_waiting.swap(false, Ordering::AcqRel)
+        {
+            scheduler::wake_event(self.channel.event_id.load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// Receiving half; there is exactly one per channel
+pub struct Receiver<T: 'static, const N: usize> {
+    channel: &'static Channel<T, N>,
+}
+
+impl<T, const N: usize> Receiver<T, N> {
+    /// Take the oldest message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.channel.with_queue(|queue| queue.pop_front()) {
+            Some(value) => Ok(value),
+            None if self.channel.senders.load(Ordering::Acquire) == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Take the oldest message, or park the current task until one arrives
+    ///
+    /// On `Empty` the running task has been moved to `WaitingForEvent` and
+    /// should return to the scheduler; it becomes ready on the next send
+    /// and calls `recv` again.
+    pub fn recv(&self) -> Result<T, TryRecvError> {
+        let result = self.try_recv();
+        if !matches!(result, Err(TryRecvError::Empty)) {
+            return result;
+        }
+
+        let event_id = self.channel.event_id.load(Ordering::Relaxed);
+        self.channel.receiver_waiting.store(true, Ordering::Release);
+        scheduler::block_current_priority(event_id);
+
+        // A send (or last sender drop) between try_recv and blocking found
+        // no parked task to wake; catch it here instead of sleeping forever
+        if !self.is_empty() || self.channel.senders.load(Ordering::Acquire) == 0 {
+            self.channel.receiver_waiting.store(false, Ordering::Release);
+            scheduler::wake_event(event_id);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    /// Messages currently queued
+    pub fn len(&self) -> usize {
+        self.channel.with_queue(|queue| queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
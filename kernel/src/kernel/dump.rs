@@ -0,0 +1,122 @@
+//! Kernel state dump
+//! Every task, the thread stack and the queue depths in one report
+//!
+//! `dump_state` is what the panic handler and the fault handlers print
+//! after their own lines, and what the shell's `dump` prints on a live
+//! system. One task per line: id, name, priority, state, the event a
+//! waiting task waits for (or the tick a sleeping one wakes at) and its
+//! run counts, the running task marked with `*`. Tasks run to completion
+//! on the one thread stack, so its bounds and peak use are given once
+//! for all of them. Then the scheduler's event queues per level and the
+//! kernel message queues.
+//!
+//! Nothing here allocates or blocks. On a live system each task is
+//! copied under its own short critical section, as `ps` does. A fatal
+//! path that stopped the kernel inside a scheduler update reads the
+//! tables unlocked instead, and the report says so, as their fields may
+//! then disagree.
+
+use core::fmt::{self, Write};
+
+use crate::scheduler::{self, Task, TaskPriority, TaskState};
+
+/// Write the state of every task and queue to `out`
+pub fn dump_state(out: &mut dyn Write) -> fmt::Result {
+    let stopped = scheduler::interrupted_update();
+    writeln!(out, "-- kernel state at tick {} --", super::time::now())?;
+    if stopped {
+        writeln!(out, "(stopped inside a scheduler update; entries may disagree)")?;
+    }
+
+    let (current, levels) = if stopped {
+        let snapshot = scheduler::crash_snapshot();
+        (snapshot.task, snapshot.levels)
+    } else {
+        (scheduler::current_priority_task(), scheduler::level_stats())
+    };
+    writeln!(out, "   ID NAME         PRI      STATE    EVENT/WAKE     RUNS    WAKES")?;
+    let mut result = Ok(());
+    let mut line = |task: &Task| {
+        if result.is_ok() {
+            let running = current.as_ref().is_some_and(|current| current.id == task.id && current.priority == task.priority);
+            result = write_task(out, task, running);
+        }
+    };
+    if stopped {
+        scheduler::crash_visit_tasks(&mut line);
+    } else {
+        scheduler::for_each_task(&mut line);
+    }
+    result?;
+
+    write_stack(out)?;
+
+    writeln!(out, "LEVEL     QUEUED  PEAK/DEPTH  DROPPED")?;
+    let priorities = [TaskPriority::Critical, TaskPriority::High, TaskPriority::Normal, TaskPriority::Low];
+    for (priority, level) in priorities.into_iter().zip(levels) {
+        writeln!(out, "{:<8} {:>7} {:>5}/{:<5} {:>8}", priority_name(priority), level.queued, level.peak, scheduler::MAX_EVENTS_PER_PRIORITY, level.dropped)?;
+    }
+    write!(out, "kernel queues:")?;
+    for depth in super::queue_depths() {
+        write!(out, " {}/{}", depth, super::KERNEL_QUEUE_DEPTH)?;
+    }
+    writeln!(out)
+}
+
+/// `dump_state` straight to the console UART, for the fault handlers
+#[allow(dead_code)]
+pub fn dump_to_console() {
+    let _ = dump_state(&mut Console);
+    crate::arch::console_flush();
+}
+
+/// Unbuffered console writer for `dump_to_console`
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::arch::console_write(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn write_task(out: &mut dyn Write, task: &Task, running: bool) -> fmt::Result {
+    let name = if task.name.is_empty() { "-" } else { task.name };
+    let marker = if running { '*' } else { ' ' };
+    write!(out, "{}{:>4} {:<12} {:<8} ", marker, task.id, name, priority_name(task.priority))?;
+    match task.state {
+        TaskState::Ready => write!(out, "{:<8} {:<10}", "ready", "-")?,
+        TaskState::Running => write!(out, "{:<8} {:<10}", "running", "-")?,
+        TaskState::WaitingForEvent(event_id) => write!(out, "{:<8} {:<#10x}", "waiting", event_id)?,
+        TaskState::Sleeping(wake_time) => write!(out, "{:<8} {:<10}", "sleeping", wake_time.ticks())?,
+        TaskState::Completed => write!(out, "{:<8} {:<10}", "done", "-")?,
+    }
+    writeln!(out, " {:>8} {:>8}", task.stats.runs, task.stats.wakes)
+}
+
+/// Bounds of the thread stack, its peak use and, when the dump runs on
+/// it, how deep it is now
+fn write_stack(out: &mut dyn Write) -> fmt::Result {
+    let Some((bottom, top)) = crate::arch::thread_stack() else {
+        return writeln!(out, "stack: no thread stack on this target");
+    };
+    write!(out, "stack: {:#010x}..{:#010x} ({} bytes), shared by all tasks", bottom, top, top - bottom)?;
+    if let Some(usage) = crate::memory::stack_usage() {
+        write!(out, ", peak {}", usage.peak)?;
+    }
+    let marker = 0u8;
+    let here = core::ptr::addr_of!(marker) as usize;
+    if (bottom..top).contains(&here) {
+        write!(out, ", now {}", top - here)?;
+    }
+    writeln!(out)
+}
+
+fn priority_name(priority: TaskPriority) -> &'static str {
+    match priority {
+        TaskPriority::Critical => "critical",
+        TaskPriority::High => "high",
+        TaskPriority::Normal => "normal",
+        TaskPriority::Low => "low",
+    }
+}
@@ -0,0 +1,160 @@
+//! Fault injection
+//! Kernel resources failing on purpose, at rates set at run time (`fault_inject`)
+//!
+//! Each `Fault` has a rate in per mille, zero until set with the `faults`
+//! shell command (or a boot script line). The kernel asks `inject` at the
+//! point the resource could run out, and a draw from the RNG driver
+//! decides: spawns fail as on a full task table, events are dropped as on
+//! a full queue or held back behind the next one posted, ticks come late
+//! and are announced with the one after, and kernel queue sends and table
+//! registrations fail as on exhausted storage. The application sees
+//! exactly what it would see if the resource were really gone.
+//!
+//! The RNG seed is printed at boot and whenever it is set, so a run that
+//! found a bug can be repeated with `faults seed <n>`. The same seed
+//! gives the same draws, though interrupts landing elsewhere shift them.
+//! Without the feature `inject` is always false, so call sites need no
+//! `cfg` guards.
+
+#[cfg(feature = "fault_inject")]
+use crate::arch::atomic::{AtomicU32, Ordering};
+
+/// Kinds of injected fault
+pub const FAULT_COUNT: usize = 5;
+
+/// Highest rate: every attempt fails
+#[allow(dead_code)]
+pub const RATE_MAX: u32 = 1000;
+
+/// An injectable fault
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Task spawn refused as if the task table were full
+    Spawn = 0,
+    /// Event dropped as if its queue were full
+    EventDrop = 1,
+    /// Event held back and queued after the next one
+    EventReorder = 2,
+    /// Tick held back and announced with the next one
+    TickDelay = 3,
+    /// Kernel queue slot or table entry refused as if exhausted
+    Alloc = 4,
+}
+
+impl Fault {
+    #[allow(dead_code)]
+    pub const ALL: [Fault; FAULT_COUNT] =
+        [Fault::Spawn, Fault::EventDrop, Fault::EventReorder, Fault::TickDelay, Fault::Alloc];
+
+    #[allow(dead_code)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Fault::Spawn => "spawn",
+            Fault::EventDrop => "drop",
+            Fault::EventReorder => "reorder",
+            Fault::TickDelay => "tick",
+            Fault::Alloc => "alloc",
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Fault> {
+        Fault::ALL.into_iter().find(|fault| fault.name() == name)
+    }
+}
+
+#[cfg(feature = "fault_inject")]
+static RATES: [AtomicU32; FAULT_COUNT] = [const { AtomicU32::new(0) }; FAULT_COUNT];
+#[cfg(feature = "fault_inject")]
+static INJECTED: [AtomicU32; FAULT_COUNT] = [const { AtomicU32::new(0) }; FAULT_COUNT];
+/// Ticks held back by `TickDelay`
+#[cfg(feature = "fault_inject")]
+static HELD_TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// Whether this attempt at `fault`'s resource should fail
+#[allow(dead_code)]
+#[inline(always)]
+pub fn inject(_fault: Fault) -> bool {
+    #[cfg(feature = "fault_inject")]
+    {
+        let rate = RATES[_fault as usize].load(Ordering::Relaxed);
+        if rate == 0 || crate::drivers::rng::below(RATE_MAX) >= rate {
+            return false;
+        }
+        INJECTED[_fault as usize].fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    #[cfg(not(feature = "fault_inject"))]
+    {
+        false
+    }
+}
+
+/// Ticks the tick interrupt should announce now out of `hw_ticks`: none
+/// if they are held back, or with the held-back ones added
+#[allow(dead_code)]
+#[inline(always)]
+pub fn delay_ticks(hw_ticks: u32) -> u32 {
+    #[cfg(feature = "fault_inject")]
+    {
+        if hw_ticks > 0 && inject(Fault::TickDelay) {
+            HELD_TICKS.fetch_add(hw_ticks, Ordering::Relaxed);
+            return 0;
+        }
+        hw_ticks + HELD_TICKS.swap(0, Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "fault_inject"))]
+    {
+        hw_ticks
+    }
+}
+
+/// Set `fault`'s rate in per mille, clamped to `RATE_MAX`
+#[allow(dead_code)]
+pub fn set_rate(_fault: Fault, _per_mille: u32) {
+    #[cfg(feature = "fault_inject")]
+    RATES[_fault as usize].store(_per_mille.min(RATE_MAX), Ordering::Relaxed);
+}
+
+/// `fault`'s rate in per mille
+#[allow(dead_code)]
+pub fn rate(_fault: Fault) -> u32 {
+    #[cfg(feature = "fault_inject")]
+    {
+        RATES[_fault as usize].load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "fault_inject"))]
+    {
+        0
+    }
+}
+
+/// Faults of this kind injected so far
+#[allow(dead_code)]
+pub fn injected(_fault: Fault) -> u32 {
+    #[cfg(feature = "fault_inject")]
+    {
+        INJECTED[_fault as usize].load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "fault_inject"))]
+    {
+        0
+    }
+}
+
+/// Print the seed the draws start from
+pub fn init() -> crate::kernel::InitResult {
+    #[cfg(feature = "fault_inject")]
+    {
+        use core::fmt::Write;
+        let mut line = heapless::String::<64>::new();
+        let _ = write!(line, "[faults] seed {:#x}, rates set with `faults`", crate::drivers::rng::seed());
+        crate::drivers::uart::print(&line);
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Kernel object handles
+//! Generation-tagged references that detect reused slots
+//!
+//! Kernel objects live in fixed tables, so a slot freed by one object is
+//! soon reused by another. A `Handle` carries the slot's generation at
+//! creation time; each table bumps the generation when the slot is freed,
+//! so an old handle no longer matches and is rejected with
+//! `HandleError::Stale` instead of acting on whatever moved in.
+//!
+//! Raw layout (u32): kind in bits 28..32, generation in 12..28, index in
+//! 0..12. Generations start at 1, so a valid raw handle is never 0.
+
+/// Object kinds a handle can name
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandleKind {
+    Task = 1,
+    Timer = 2,
+    Queue = 3,
+    Driver = 4,
+    Socket = 5,
+}
+
+impl HandleKind {
+    const fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(HandleKind::Task),
+            2 => Some(HandleKind::Timer),
+            3 => Some(HandleKind::Queue),
+            4 => Some(HandleKind::Driver),
+            5 => Some(HandleKind::Socket),
+            _ => None,
+        }
+    }
+}
+
+/// Why a handle was rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandleError {
+    /// Wrong kind, index out of range, or not a handle at all
+    Invalid,
+    /// The object was deleted; its slot may now hold another object
+    StaleHandle,
+}
+
+const INDEX_BITS: u32 = 12;
+const GENERATION_BITS: u32 = 16;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+const GENERATION_MASK: u32 = (1 << GENERATION_BITS) - 1;
+
+/// Largest table a handle can index
+pub const MAX_HANDLE_INDEX: usize = INDEX_MASK as usize;
+
+/// Generation-tagged reference to one slot of a kernel object table
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Handle(u32);
+
+impl Handle {
+    const fn new(kind: HandleKind, index: usize, generation: u16) -> Self {
+        Handle(
+            (kind as u32) << (INDEX_BITS + GENERATION_BITS)
+                | (generation as u32) << INDEX_BITS
+                | index as u32 & INDEX_MASK,
+        )
+    }
+
+    /// Rebuild a handle passed through a register or message word
+    pub const fn from_raw(raw: u32) -> Option<Self> {
+        match HandleKind::from_raw(raw >> (INDEX_BITS + GENERATION_BITS)) {
+            Some(_) => Some(Handle(raw)),
+            None => None,
+        }
+    }
+
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub const fn kind(self) -> Option<HandleKind> {
+        HandleKind::from_raw(self.0 >> (INDEX_BITS + GENERATION_BITS))
+    }
+
+    pub const fn index(self) -> usize {
+        (self.0 & INDEX_MASK) as usize
+    }
+
+    pub const fn generation(self) -> u16 {
+        ((self.0 >> INDEX_BITS) & GENERATION_MASK) as u16
+    }
+}
+
+impl core::fmt::Display for Handle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}#{}", self.index(), self.generation())
+    }
+}
+
+/// Generation counters for a table of `N` slots of one kind
+///
+/// Handles number slots from `base`, so several tables of one kind (e.g.
+/// the per-priority task tables) can share one handle index space.
+pub struct Generations<const N: usize> {
+    kind: HandleKind,
+    base: usize,
+    counts: [u16; N],
+}
+
+impl<const N: usize> Generations<N> {
+    pub const fn new(kind: HandleKind, base: usize) -> Self {
+        assert!(base + N <= MAX_HANDLE_INDEX + 1);
+        Self { kind, base, counts: [1; N] }
+    }
+
+    /// Handle for the object currently in slot `index`
+    pub fn handle(&self, index: usize) -> Handle {
+        Handle::new(self.kind, self.base + index, self.counts[index])
+    }
+
+    /// Slot `handle` refers to, if it names this table and is still current
+    pub fn resolve(&self, handle: Handle) -> Result<usize, HandleError> {
+        let index = handle.index().wrapping_sub(self.base);
+        if handle.kind() != Some(self.kind) || index >= N {
+            return Err(HandleError::Invalid);
+        }
+        if handle.generation() != self.counts[index] {
+            return Err(HandleError::StaleHandle);
+        }
+        Ok(index)
+    }
+
+    /// Invalidate every handle to slot `index`; call when its object is freed
+    pub fn retire(&mut self, index: usize) {
+        self.counts[index] = self.counts[index].wrapping_add(1).max(1);
+    }
+}
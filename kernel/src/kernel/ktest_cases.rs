@@ -0,0 +1,289 @@
+//! Kernel test cases
+//! Cases for the test kernel, registered in `crate::ktest`'s table
+//!
+//! They live here rather than beside the runner so they can reach the
+//! kernel's private primitives (pipes, channels, handles, the timer
+//! wheel). Each one uses its own statics, since a pipe or channel cannot
+//! be reset once used.
+
+use heapless::Vec;
+
+use super::channel::{channel, Channel, TryRecvError};
+use super::handle::{Generations, Handle, HandleError, HandleKind};
+use super::pipe::Pipe;
+use super::time::{self, Duration, Instant};
+use super::timer_wheel::TimerWheel;
+use super::{KERNEL_QUEUES, KERNEL_QUEUE_DEPTH};
+use crate::config::TICK_HZ;
+use crate::kcheck;
+use crate::ktest::TestResult;
+use crate::scheduler::{self, TaskEntry, TaskState};
+use crate::syscall::{self, Syscall, SyscallError, SYSCALL_MAX_ARGS};
+
+pub fn duration_units() -> TestResult {
+    kcheck!(Duration::from_secs(2).ticks() == 2 * TICK_HZ as u64);
+    kcheck!(Duration::from_secs(3).as_millis() == 3_000);
+    // Sub-tick durations round up, so a short wait still waits
+    kcheck!(Duration::from_micros(1).ticks() == 1);
+    kcheck!(Duration::from_millis(1_500).as_secs() == 1);
+    kcheck!(Duration::MAX.checked_add(Duration::from_ticks(1)).is_none());
+    Ok(())
+}
+
+pub fn deadline_wrap() -> TestResult {
+    kcheck!(time::deadline_reached_u32(5, u32::MAX - 5));
+    kcheck!(!time::deadline_reached_u32(u32::MAX - 5, 5));
+    kcheck!(time::deadline_reached_u32(7, 7));
+    kcheck!(time::ticks_between(10, 25) == 15);
+    Ok(())
+}
+
+pub fn tick_advances() -> TestResult {
+    let start = Instant::now();
+    time::delay_us(Duration::from_ticks(3).as_micros() as u32);
+    kcheck!(Instant::now() > start);
+    kcheck!(start.elapsed() >= Duration::from_ticks(1));
+    Ok(())
+}
+
+/// Advance `wheel` to tick `now`, returning the timers that expired
+fn expire<const N: usize>(wheel: &mut TimerWheel<N>, now: u64) -> Vec<usize, N> {
+    let mut expired = Vec::new();
+    wheel.advance(Instant::from_ticks(now), |id| {
+        let _ = expired.push(id);
+    });
+    expired
+}
+
+pub fn timer_wheel_expiry() -> TestResult {
+    let mut wheel = TimerWheel::<4>::new();
+    wheel.arm(0, Instant::from_ticks(5));
+    wheel.arm(1, Instant::from_ticks(2));
+    wheel.arm(2, Instant::from_ticks(300));
+    kcheck!(expire(&mut wheel, 1).is_empty());
+    kcheck!(expire(&mut wheel, 3) == [1]);
+    kcheck!(expire(&mut wheel, 5) == [0]);
+    kcheck!(expire(&mut wheel, 299).is_empty());
+    kcheck!(wheel.is_armed(2));
+    kcheck!(expire(&mut wheel, 300) == [2]);
+    kcheck!(!wheel.is_armed(2));
+    Ok(())
+}
+
+pub fn timer_wheel_disarm() -> TestResult {
+    let mut wheel = TimerWheel::<4>::new();
+    wheel.arm(0, Instant::from_ticks(4));
+    wheel.arm(1, Instant::from_ticks(4));
+    wheel.disarm(0);
+    kcheck!(!wheel.is_armed(0));
+    kcheck!(expire(&mut wheel, 10) == [1]);
+    // Re-arming replaces the earlier deadline
+    wheel.arm(3, Instant::from_ticks(12));
+    wheel.arm(3, Instant::from_ticks(20));
+    kcheck!(expire(&mut wheel, 15).is_empty());
+    kcheck!(expire(&mut wheel, 20) == [3]);
+    Ok(())
+}
+
+pub fn pipe_round_trip() -> TestResult {
+    static PIPE: Pipe<8> = Pipe::new();
+
+    kcheck!(PIPE.try_write(b"karatOS kernel") == 8);
+    kcheck!(PIPE.len() == 8);
+    kcheck!(PIPE.try_write(b"x") == 0);
+    let mut buf = [0u8; 5];
+    kcheck!(PIPE.try_read(&mut buf) == 5);
+    kcheck!(&buf == b"karat");
+    kcheck!(PIPE.try_write(b"abc") == 3);
+    let mut rest = [0u8; 16];
+    kcheck!(PIPE.try_read(&mut rest) == 6);
+    kcheck!(&rest[..6] == b"OS abc");
+    kcheck!(PIPE.is_empty());
+    Ok(())
+}
+
+pub fn channel_fifo() -> TestResult {
+    static CHANNEL: Channel<u32, 3> = Channel::new();
+
+    let Some((sender, receiver)) = channel(&CHANNEL) else {
+        return Err("channel already split");
+    };
+    kcheck!(channel(&CHANNEL).is_none());
+    kcheck!(receiver.try_recv() == Err(TryRecvError::Empty));
+    for value in 1..=3 {
+        kcheck!(sender.try_send(value).is_ok());
+    }
+    kcheck!(sender.try_send(4) == Err(4));
+    kcheck!(receiver.try_recv() == Ok(1));
+    kcheck!(receiver.try_recv() == Ok(2));
+    drop(sender);
+    // Messages already queued still arrive after the last sender is gone
+    kcheck!(receiver.try_recv() == Ok(3));
+    kcheck!(receiver.try_recv() == Err(TryRecvError::Disconnected));
+    Ok(())
+}
+
+pub fn handle_generations() -> TestResult {
+    let mut tasks = Generations::<4>::new(HandleKind::Task, 8);
+    let handle = tasks.handle(1);
+    kcheck!(handle.index() == 9);
+    kcheck!(tasks.resolve(handle) == Ok(1));
+    kcheck!(super::handle::Handle::from_raw(handle.raw()) == Some(handle));
+    tasks.retire(1);
+    kcheck!(tasks.resolve(handle) == Err(HandleError::StaleHandle));
+    kcheck!(tasks.resolve(tasks.handle(1)) == Ok(1));
+
+    let timers = Generations::<4>::new(HandleKind::Timer, 8);
+    kcheck!(tasks.resolve(timers.handle(1)) == Err(HandleError::Invalid));
+    kcheck!(super::handle::Handle::from_raw(0).is_none());
+    Ok(())
+}
+
+fn call(syscall: Syscall, args: [usize; SYSCALL_MAX_ARGS]) -> Result<usize, SyscallError> {
+    SyscallError::decode(syscall::dispatch(syscall as usize, args))
+}
+
+pub fn syscall_validation() -> TestResult {
+    kcheck!(SyscallError::decode(syscall::dispatch(0, [0; SYSCALL_MAX_ARGS])) == Err(SyscallError::NoSuchSyscall));
+    // Unused argument slots must be zero
+    kcheck!(call(Syscall::QueueReceive, [0, 1, 0, 0, 0, 0]) == Err(SyscallError::InvalidArgument));
+    kcheck!(call(Syscall::QueueReceive, [KERNEL_QUEUES, 0, 0, 0, 0, 0]) == Err(SyscallError::InvalidArgument));
+    kcheck!(call(Syscall::Spawn, [40, 9, 0, 0, 0, 0]) == Err(SyscallError::InvalidArgument));
+    // A task needs an entry in kernel code to ever run
+    kcheck!(call(Syscall::Spawn, [40, 2, 0, 0, 0, 0]) == Err(SyscallError::InvalidArgument));
+    let data = 0u32;
+    kcheck!(call(Syscall::Spawn, [40, 2, &data as *const u32 as usize, 0, 0, 0]) == Err(SyscallError::InvalidArgument));
+    kcheck!(call(Syscall::ConsoleWrite, [0, 0, 0, 0, 0, 0]) == Ok(0));
+    Ok(())
+}
+
+pub fn syscall_queue() -> TestResult {
+    let queue = KERNEL_QUEUES - 1;
+    while call(Syscall::QueueReceive, [queue, 0, 0, 0, 0, 0]).is_ok() {}
+
+    kcheck!(call(Syscall::QueueSend, [queue, 7, 0, 0, 0, 0]) == Ok(0));
+    kcheck!(call(Syscall::QueueSend, [queue, 9, 0, 0, 0, 0]) == Ok(0));
+    kcheck!(call(Syscall::QueueReceive, [queue, 0, 0, 0, 0, 0]) == Ok(7));
+    kcheck!(call(Syscall::QueueReceive, [queue, 0, 0, 0, 0, 0]) == Ok(9));
+    kcheck!(call(Syscall::QueueReceive, [queue, 0, 0, 0, 0, 0]) == Err(SyscallError::WouldBlock));
+
+    for message in 0..KERNEL_QUEUE_DEPTH {
+        kcheck!(call(Syscall::QueueSend, [queue, message, 0, 0, 0, 0]) == Ok(0));
+    }
+    kcheck!(call(Syscall::QueueSend, [queue, 1, 0, 0, 0, 0]) == Err(SyscallError::NoSpace));
+    while call(Syscall::QueueReceive, [queue, 0, 0, 0, 0, 0]).is_ok() {}
+    Ok(())
+}
+
+/// Entry of the tasks the sleep cases spawn; they pick them by hand, so
+/// it never runs
+fn sleeper(_id: usize) {}
+
+/// Spawn a critical task through `Spawn`, pick it, put it to sleep with
+/// `sleep` and check it is not picked again before its deadline
+fn sleep_until_deadline(id: usize, sleep: impl Fn(usize) -> Result<usize, SyscallError>) -> TestResult {
+    const TICKS: u64 = 4;
+    let entry = sleeper as TaskEntry as usize;
+    let Ok(raw) = call(Syscall::Spawn, [id, 0, entry, 0, 0, 0]) else {
+        return Err("spawn with a valid entry refused");
+    };
+    let handle = Handle::from_raw(raw as u32).ok_or("spawn returned no handle")?;
+
+    let start = Instant::now();
+    let picked = scheduler::schedule_with_priority();
+    kcheck!(picked.as_ref().map(|task| task.id) == Some(id));
+    kcheck!(picked.and_then(|task| task.entry).is_some());
+    kcheck!(sleep(TICKS as usize) == Ok(0));
+    let Ok(TaskState::Sleeping(deadline)) = scheduler::task_by_handle(handle).map(|task| task.state) else {
+        return Err("task not asleep after the call");
+    };
+    // The deadline counts from the pick, no earlier than `start`
+    kcheck!(deadline >= start + Duration::from_ticks(TICKS));
+
+    // Passed over while its deadline is ahead, picked once it is reached
+    loop {
+        let due = Instant::now() >= deadline;
+        if scheduler::schedule_with_priority().map(|task| task.id) == Some(id) {
+            kcheck!(Instant::now() >= deadline);
+            break;
+        }
+        kcheck!(!due);
+    }
+    kcheck!(scheduler::remove_handle(handle).is_ok());
+    Ok(())
+}
+
+pub fn syscall_sleep() -> TestResult {
+    sleep_until_deadline(41, |ticks| call(Syscall::Sleep, [ticks, 0, 0, 0, 0, 0]))
+}
+
+/// Issue `Sleep` the way a task does, through the architecture's trap
+fn trap_sleep(ticks: usize) -> Result<usize, SyscallError> {
+    const SLEEP: u8 = Syscall::Sleep as u8;
+    #[cfg(feature = "arm")]
+    let result = crate::arch::arm::syscall::<SLEEP>([ticks, 0, 0, 0]);
+    #[cfg(feature = "riscv")]
+    let result = crate::arch::riscv::syscall(SLEEP as usize, [ticks, 0, 0, 0, 0, 0]);
+    #[cfg(feature = "sim")]
+    let result = crate::arch::sim::syscall(SLEEP as usize, [ticks, 0, 0, 0, 0, 0]);
+    SyscallError::decode(result)
+}
+
+pub fn syscall_trap_sleep() -> TestResult {
+    sleep_until_deadline(42, trap_sleep)
+}
+
+/// Stack the PendSV case switches onto, and the two saved PSPs
+#[cfg(target_arch = "arm")]
+static mut SIDE_STACK: [u64; 64] = [0; 64];
+#[cfg(target_arch = "arm")]
+static mut MAIN_PSP: *mut u32 = core::ptr::null_mut();
+#[cfg(target_arch = "arm")]
+static mut SIDE_PSP: *mut u32 = core::ptr::null_mut();
+#[cfg(target_arch = "arm")]
+static SIDE_RUNS: crate::arch::atomic::AtomicU32 = crate::arch::atomic::AtomicU32::new(0);
+
+/// Runs on `SIDE_STACK` and switches straight back; never resumed
+#[cfg(target_arch = "arm")]
+extern "C" fn side_task() -> ! {
+    use core::ptr::{addr_of, addr_of_mut};
+    SIDE_RUNS.fetch_add(1, crate::arch::atomic::Ordering::Relaxed);
+    unsafe { crate::arch::arm::request_context_switch(addr_of_mut!(SIDE_PSP), addr_of!(MAIN_PSP)) };
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// PendSV starts a prepared stack and comes back with every register intact
+#[cfg(target_arch = "arm")]
+pub fn pendsv_round_trip() -> TestResult {
+    use core::ptr::{addr_of, addr_of_mut};
+    use crate::arch::arm::{prepare_task_stack, request_context_switch};
+    // Live across the switch, so kept in callee-saved registers or the stack
+    let (a, b) = (core::hint::black_box(0x1234_5678u32), core::hint::black_box(-1.5f32));
+    unsafe {
+        SIDE_PSP = prepare_task_stack(&mut *addr_of_mut!(SIDE_STACK), side_task);
+        request_context_switch(addr_of_mut!(MAIN_PSP), addr_of!(SIDE_PSP));
+    }
+    kcheck!(SIDE_RUNS.load(crate::arch::atomic::Ordering::Relaxed) == 1);
+    kcheck!(core::hint::black_box(a) == 0x1234_5678);
+    kcheck!(core::hint::black_box(b) * 2.0 == -3.0);
+    Ok(())
+}
+
+/// Run the benchmarks, reporting their figures as diagnostics
+#[cfg(feature = "bench")]
+pub fn bench_suite() -> TestResult {
+    use super::bench::{self, BenchError, BenchResult};
+    let report = |result: &BenchResult| {
+        crate::ktest::diag(format_args!(
+            "bench {} min {} avg {} max {} cycles",
+            result.name, result.min, result.avg, result.max
+        ));
+    };
+    match bench::run(bench::DEFAULT_SAMPLES, report) {
+        Ok(_) => Ok(()),
+        Err(BenchError::Broken(reason)) => Err(reason),
+        Err(_) => Err("benchmarks did not run"),
+    }
+}
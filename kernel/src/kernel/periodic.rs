@@ -0,0 +1,288 @@
+//! Periodic tasks
+//! Release times and deadline-miss detection for tasks run every period
+//!
+//! `set_periodic` gives a task a period and a deadline relative to each
+//! release, the period itself unless set shorter. The task ends each
+//! activation with `wait_next_period`, which parks it until the next
+//! release; the scheduler makes it ready again then. An activation
+//! starts when the scheduler first picks the task after its release, and
+//! may span several runs of a run-to-completion task.
+//!
+//! An activation that starts or finishes after its deadline is a miss.
+//! Misses are added to the task's `TaskStats::deadline_misses` (shown by
+//! `ps`), broken down by `counts`, and can post a `Critical` event so a
+//! monitor task hears of them. A task that falls behind by more than its
+//! deadline skips the releases it can no longer meet instead of running
+//! them back to back; each one skipped counts as a miss. Times are kernel
+//! ticks, so misses are seen to tick resolution.
+//!
+//! A task that can start a little late may declare a release slack, which
+//! lets a dynamic tick wake it together with other deadlines nearby. The
+//! slack comes out of the deadline: a release woken late still has to
+//! start and finish in time.
+
+use core::cell::UnsafeCell;
+
+use heapless::Vec;
+
+use super::time::{Duration, Instant};
+use crate::scheduler::{self, EventPriority, Task, TaskPriority};
+
+/// Tasks that can be periodic at once
+pub const MAX_PERIODIC: usize = 4;
+
+/// A task's period and deadline
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Periodic {
+    pub period: Duration,
+    /// After each release
+    pub deadline: Duration,
+    /// Posted at `Critical` priority on each miss
+    pub miss_event: Option<u32>,
+    /// How late a release may be woken, to share a dynamic tick's wakeup
+    pub slack: Duration,
+}
+
+#[allow(dead_code)]
+impl Periodic {
+    /// Released every `period`, due by the next release
+    pub const fn new(period: Duration) -> Self {
+        Self { period, deadline: period, miss_event: None, slack: Duration::ZERO }
+    }
+
+    /// Due `deadline` after each release instead
+    pub const fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Let each release be woken up to `slack` late
+    pub const fn slack(mut self, slack: Duration) -> Self {
+        self.slack = slack;
+        self
+    }
+
+    /// Post `event_id` at `Critical` priority on each miss
+    pub const fn post_on_miss(mut self, event_id: u32) -> Self {
+        self.miss_event = Some(event_id);
+        self
+    }
+}
+
+/// Activation and miss counts of one periodic task
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MissCounts {
+    /// Activations started
+    pub activations: u32,
+    /// Activations that started after their deadline
+    pub late_starts: u32,
+    /// Activations that started in time and finished after their deadline
+    pub late_finishes: u32,
+    /// Releases skipped because their deadline had passed
+    pub skipped: u32,
+}
+
+#[allow(dead_code)]
+impl MissCounts {
+    pub const fn misses(&self) -> u32 {
+        self.late_starts + self.late_finishes + self.skipped
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    /// Released, not picked by the scheduler yet
+    Released,
+    /// Picked, not finished with `wait_next_period`
+    Running,
+    /// Waiting for the next release
+    Parked,
+}
+
+struct Entry {
+    task_id: usize,
+    priority: TaskPriority,
+    spec: Periodic,
+    /// Wait event the task parks on
+    event: u32,
+    /// Release of the current or next activation
+    release: Instant,
+    phase: Phase,
+    counts: MissCounts,
+}
+
+impl Entry {
+    fn deadline(&self) -> Instant {
+        self.release + self.spec.deadline
+    }
+}
+
+struct PeriodicTable(UnsafeCell<Vec<Entry, MAX_PERIODIC>>);
+unsafe impl Sync for PeriodicTable {} // Only touched with interrupts off
+
+static TABLE: PeriodicTable = PeriodicTable(UnsafeCell::new(Vec::new()));
+
+/// Run `f` on the table with interrupts off, restoring their state; a
+/// dynamic tick reads it from its interrupt handler
+fn with_table<R>(f: impl FnOnce(&mut Vec<Entry, MAX_PERIODIC>) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *TABLE.0.get() }))
+}
+
+fn find(table: &mut Vec<Entry, MAX_PERIODIC>, task_id: usize, priority: TaskPriority) -> Option<&mut Entry> {
+    table.iter_mut().find(|entry| entry.task_id == task_id && entry.priority == priority)
+}
+
+/// Make task `task_id` at `priority` periodic, first released now
+///
+/// Replaces an earlier setting and its counts. Returns false for a zero
+/// period or when the table is full.
+#[allow(dead_code)]
+pub fn set_periodic(task_id: usize, priority: TaskPriority, spec: Periodic) -> bool {
+    if spec.period.is_zero() {
+        return false;
+    }
+    let now = Instant::now();
+    with_table(|table| {
+        let event = match find(table, task_id, priority) {
+            Some(entry) => entry.event,
+            None => super::alloc_wait_event(),
+        };
+        table.retain(|entry| !(entry.task_id == task_id && entry.priority == priority));
+        let entry = Entry {
+            task_id,
+            priority,
+            spec,
+            event,
+            release: now,
+            phase: Phase::Released,
+            counts: MissCounts::default(),
+        };
+        table.push(entry).is_ok()
+    })
+}
+
+/// Stop releasing task `task_id` at `priority` periodically
+///
+/// A task parked for its next release is woken.
+#[allow(dead_code)]
+pub fn clear_periodic(task_id: usize, priority: TaskPriority) {
+    let parked = with_table(|table| {
+        let parked = find(table, task_id, priority).is_some_and(|entry| entry.phase == Phase::Parked);
+        table.retain(|entry| !(entry.task_id == task_id && entry.priority == priority));
+        parked
+    });
+    if parked {
+        scheduler::wake_task(task_id, priority);
+    }
+}
+
+/// Activation and miss counts of a periodic task
+#[allow(dead_code)]
+pub fn counts(task_id: usize, priority: TaskPriority) -> Option<MissCounts> {
+    with_table(|table| find(table, task_id, priority).map(|entry| entry.counts))
+}
+
+/// End the calling task's activation and park it until the next release
+///
+/// Returns to the task, which then returns to the scheduler. If the
+/// next release is already due the task is not parked. False if the
+/// caller is not a periodic task.
+#[allow(dead_code)]
+pub fn wait_next_period() -> bool {
+    let Some(task) = scheduler::current_priority_task() else {
+        return false;
+    };
+    let now = Instant::now();
+    let outcome = with_table(|table| {
+        let entry = find(table, task.id, task.priority)?;
+        let mut misses = 0;
+        if entry.phase == Phase::Running && now > entry.deadline() {
+            entry.counts.late_finishes += 1;
+            misses += 1;
+        }
+        entry.release += entry.spec.period;
+        while now > entry.deadline() {
+            entry.release += entry.spec.period;
+            entry.counts.skipped += 1;
+            misses += 1;
+        }
+        let park = now < entry.release;
+        entry.phase = if park { Phase::Parked } else { Phase::Released };
+        let wake_by = entry.release + entry.spec.slack;
+        Some((misses, entry.spec.miss_event, park.then_some((entry.event, wake_by))))
+    });
+    let Some((misses, miss_event, park)) = outcome else {
+        return false;
+    };
+    report(&task, misses, miss_event);
+    if let Some((event, wake_by)) = park {
+        scheduler::block_current_priority(event);
+        super::time::deadline_added(wake_by);
+    }
+    true
+}
+
+/// Note the start of an activation; called by the scheduler when it
+/// picks `task`
+pub(crate) fn on_start(task: &Task) {
+    let now = Instant::now();
+    let late = with_table(|table| {
+        let entry = find(table, task.id, task.priority).filter(|entry| entry.phase == Phase::Released)?;
+        entry.phase = Phase::Running;
+        entry.counts.activations += 1;
+        if now > entry.deadline() {
+            entry.counts.late_starts += 1;
+            return Some(entry.spec.miss_event);
+        }
+        None
+    });
+    if let Some(miss_event) = late {
+        report(task, 1, miss_event);
+    }
+}
+
+fn report(task: &Task, misses: u32, miss_event: Option<u32>) {
+    if misses == 0 {
+        return;
+    }
+    scheduler::record_misses(task.id, task.priority, misses);
+    if let Some(event_id) = miss_event {
+        scheduler::post_priority_event(event_id, EventPriority::Critical);
+    }
+}
+
+/// Wake parked tasks whose release has come
+///
+/// Called by the scheduler before each pass. Entries for tasks that no
+/// longer exist are dropped.
+pub fn poll_releases() {
+    let now = Instant::now();
+    let due: Vec<(usize, TaskPriority), MAX_PERIODIC> = with_table(|table| {
+        table
+            .iter_mut()
+            .filter(|entry| entry.phase == Phase::Parked && now >= entry.release)
+            .map(|entry| {
+                entry.phase = Phase::Released;
+                (entry.task_id, entry.priority)
+            })
+            .collect()
+    });
+    for (task_id, priority) in due {
+        if !scheduler::wake_task(task_id, priority) {
+            with_table(|table| table.retain(|entry| !(entry.task_id == task_id && entry.priority == priority)));
+        }
+    }
+}
+
+/// Earliest release plus slack a parked task waits for, for a dynamic tick
+pub fn next_release() -> Option<Instant> {
+    with_table(|table| {
+        table
+            .iter()
+            .filter(|entry| entry.phase == Phase::Parked)
+            .map(|entry| entry.release + entry.spec.slack)
+            .min()
+    })
+}
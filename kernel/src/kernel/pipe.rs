@@ -0,0 +1,316 @@
+//! Byte pipes
+//! Fixed-capacity byte streams between ISRs and tasks
+//!
+//! A `Pipe<N>` lives in a `static` and is shared by reference. Readers
+//! and writers that cannot make progress are parked on the pipe's
+//! scheduler events (one for data, one for space) and woken by the other
+//! side. The `try_` variants never block and are safe in interrupt
+//! handlers.
+//!
+//! `poll_read` and `poll_write` serve futures instead: one pending
+//! reader and one pending writer keep their wakers in the pipe, and the
+//! other side wakes them. A second waker on the same side displaces the
+//! first, which is woken to poll again. With `embedded_io` the pipe is
+//! an `embedded-io` and `embedded-io-async` byte stream.
+
+use core::cell::UnsafeCell;
+use core::task::{Context, Poll, Waker};
+
+use heapless::Deque;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::scheduler;
+
+/// Why a pipe operation moved no bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PipeError {
+    /// Pipe empty (read) or full (write); the task now waits on it
+    WouldBlock,
+}
+
+impl crate::kernel::time::WouldBlock for PipeError {
+    fn would_block(&self) -> bool {
+        *self == PipeError::WouldBlock
+    }
+}
+
+/// Byte stream with `N` bytes of buffering
+pub struct Pipe<const N: usize> {
+    state: UnsafeCell<PipeState<N>>,
+    data_event: AtomicU32,
+    space_event: AtomicU32,
+}
+
+struct PipeState<const N: usize> {
+    buffer: Deque<u8, N>,
+    /// Waker of the future waiting for data
+    reader: Option<Waker>,
+    /// Waker of the future waiting for space
+    writer: Option<Waker>,
+}
+
+// Buffer access is serialized by disabling interrupts (single core)
+unsafe impl<const N: usize> Sync for Pipe<N> {}
+
+impl<const N: usize> Pipe<N> {
+    pub const fn new() -> Self {
+        Self {
+            state: UnsafeCell::new(PipeState { buffer: Deque::new(), reader: None, writer: None }),
+            data_event: AtomicU32::new(0),
+            space_event: AtomicU32::new(0),
+        }
+    }
+
+    /// Copy as much of `data` as fits, returning the count (ISR-safe)
+    pub fn try_write(&self, data: &[u8]) -> usize {
+        let (written, reader) = self.with_state(|state| {
+            let count = state.push(data);
+            (count, if count > 0 { state.reader.take() } else { None })
+        });
+        if written > 0 {
+            scheduler::wake_event(Self::event_id(&self.data_event));
+        }
+        if let Some(reader) = reader {
+            reader.wake();
+        }
+        written
+    }
+
+    /// Read up to `buf.len()` bytes, returning the count (ISR-safe)
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        let (read, writer) = self.with_state(|state| {
+            let count = state.pop(buf);
+            (count, if count > 0 { state.writer.take() } else { None })
+        });
+        if read > 0 {
+            scheduler::wake_event(Self::event_id(&self.space_event));
+        }
+        if let Some(writer) = writer {
+            writer.wake();
+        }
+        read
+    }
+
+    /// Write what fits, or leave `cx`'s waker to be woken by a reader
+    pub fn poll_write(&self, cx: &Context<'_>, data: &[u8]) -> Poll<usize> {
+        if data.is_empty() {
+            return Poll::Ready(0);
+        }
+        // The waker to wake: the other side's, or one this displaced
+        let (written, waker) = self.with_state(|state| match state.push(data) {
+            0 => (0, register(&mut state.writer, cx.waker())),
+            count => (count, state.reader.take()),
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        if written == 0 {
+            return Poll::Pending;
+        }
+        scheduler::wake_event(Self::event_id(&self.data_event));
+        Poll::Ready(written)
+    }
+
+    /// Read what is available, or leave `cx`'s waker to be woken by a writer
+    pub fn poll_read(&self, cx: &Context<'_>, buf: &mut [u8]) -> Poll<usize> {
+        if buf.is_empty() {
+            return Poll::Ready(0);
+        }
+        // The waker to wake: the other side's, or one this displaced
+        let (read, waker) = self.with_state(|state| match state.pop(buf) {
+            0 => (0, register(&mut state.reader, cx.waker())),
+            count => (count, state.writer.take()),
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        if read == 0 {
+            return Poll::Pending;
+        }
+        scheduler::wake_event(Self::event_id(&self.space_event));
+        Poll::Ready(read)
+    }
+
+    /// Write what fits; if nothing does, park the task until space frees
+    ///
+    /// A short count means the rest should be written on a later call.
+    pub fn write(&self, data: &[u8]) -> Result<usize, PipeError> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        match self.try_write(data) {
+            0 => {
+                self.park(&self.space_event, |pipe| pipe.len() < N);
+                Err(PipeError::WouldBlock)
+            }
+            written => Ok(written),
+        }
+    }
+
+    /// Read what is available; if nothing is, park the task until data arrives
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, PipeError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.try_read(buf) {
+            0 => {
+                self.park(&self.data_event, |pipe| !pipe.is_empty());
+                Err(PipeError::WouldBlock)
+            }
+            read => Ok(read),
+        }
+    }
+
+    /// Bytes currently buffered
+    pub fn len(&self) -> usize {
+        self.with_state(|state| state.buffer.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Block the running task on `event`, undoing it if `ready` already holds
+    fn park(&self, event: &AtomicU32, ready: impl Fn(&Self) -> bool) {
+        let event_id = Self::event_id(event);
+        scheduler::block_current_priority(event_id);
+        // The other side may have moved bytes before the task was parked
+        if ready(self) {
+            scheduler::wake_event(event_id);
+        }
+    }
+
+    /// Event ids are allocated on first use so `new` can stay `const`
+    fn event_id(slot: &AtomicU32) -> u32 {
+        let id = slot.load(Ordering::Relaxed);
+        if id != 0 {
+            return id;
+        }
+        let fresh = super::alloc_wait_event();
+        match slot.compare_exchange(0, fresh, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => fresh,
+            Err(existing) => existing,
+        }
+    }
+
+    /// Run `f` on the state with interrupts off, restoring their state
+    fn with_state<R>(&self, f: impl FnOnce(&mut PipeState<N>) -> R) -> R {
+        crate::arch::free(|| unsafe { f(&mut *self.state.get()) })
+    }
+}
+
+impl<const N: usize> PipeState<N> {
+    /// Append as much of `data` as fits, returning the count
+    fn push(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(N - self.buffer.len());
+        for &byte in &data[..count] {
+            let _ = self.buffer.push_back(byte);
+        }
+        count
+    }
+
+    /// Take up to `buf.len()` bytes, returning the count
+    fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < buf.len() {
+            match self.buffer.pop_front() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Keep `waker` in `slot`, returning a different waker it displaced
+fn register(slot: &mut Option<Waker>, waker: &Waker) -> Option<Waker> {
+    match slot {
+        Some(listed) if listed.will_wake(waker) => None,
+        _ => slot.replace(waker.clone()),
+    }
+}
+
+impl<const N: usize> Default for Pipe<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// -------- embedded-io --------
+
+// Blocking calls sleep the core until the other side moves bytes, so they
+// suit pipes whose other end is an interrupt handler. Between tasks, use
+// the async traits under an executor, or `read`/`write`, which park the task.
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io::ErrorType for &Pipe<N> {
+    type Error = core::convert::Infallible;
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io::Read for &Pipe<N> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.try_read(buf) {
+                0 => crate::power::sleep_unless(|| !self.is_empty()),
+                read => return Ok(read),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io::ReadReady for &Pipe<N> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_empty())
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io::Write for &Pipe<N> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.try_write(data) {
+                0 => crate::power::sleep_unless(|| self.len() < N),
+                written => return Ok(written),
+            }
+        }
+    }
+
+    /// Bytes in the pipe are already where a reader takes them
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io::WriteReady for &Pipe<N> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.len() < N)
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io_async::Read for &Pipe<N> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(core::future::poll_fn(|cx| self.poll_read(cx, buf)).await)
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl<const N: usize> embedded_io_async::Write for &Pipe<N> {
+    async fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        Ok(core::future::poll_fn(|cx| self.poll_write(cx, data)).await)
+    }
+}
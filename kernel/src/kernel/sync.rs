@@ -0,0 +1,370 @@
+//! Async synchronization primitives
+//! `AsyncMutex`, `AsyncChannel` and `Notify`, awaited instead of polled
+//!
+//! Each wait returns a future. While pending it leaves its waker in the
+//! primitive's wait list (`MAX_WAITERS` entries, one per waker), and the
+//! operation that can satisfy it wakes the list. Polled with
+//! `scheduler::poll_current` the waker is the task's own, so a task that
+//! waits for a lock or a message is parked in the scheduler instead of
+//! spinning; under `hal_async::block_on` or another executor it is that
+//! executor's.
+//!
+//! A wake goes to every waiter and the first to poll again wins; the
+//! others wait again. So a waker left behind by a dropped future never
+//! swallows a wakeup, and a task may make a fresh future on each run.
+//! With a wait list full, a new waiter is woken at once and polls until
+//! a place frees up.
+//!
+//! Wait lists and the channel queue are touched with interrupts masked,
+//! so `Notify::notify` and the channel's `try_send` and `try_recv` work
+//! in interrupt handlers. Wakers run after the mask is lifted.
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use heapless::{Deque, Vec};
+
+/// Wakers one wait list holds
+pub const MAX_WAITERS: usize = 8;
+
+/// State shared with interrupt handlers
+struct Shared<T>(UnsafeCell<T>);
+
+// Only touched with interrupts masked, and never across a call out
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    const fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    /// Run `f` on the state with interrupts off, restoring their state
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        crate::arch::free(|| f(unsafe { &mut *self.0.get() }))
+    }
+}
+
+/// Wakers of the futures waiting on one condition
+struct WaitList(Vec<Waker, MAX_WAITERS>);
+
+impl WaitList {
+    const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// List `waker` unless it already is; false if the list is full
+    fn register(&mut self, waker: &Waker) -> bool {
+        if self.0.iter().any(|listed| listed.will_wake(waker)) {
+            return true;
+        }
+        self.0.push(waker.clone()).is_ok()
+    }
+
+    /// Empty the list, for `wake_all` once the mask is lifted
+    fn take(&mut self) -> Vec<Waker, MAX_WAITERS> {
+        core::mem::take(&mut self.0)
+    }
+}
+
+fn wake_all(wakers: Vec<Waker, MAX_WAITERS>) {
+    wakers.into_iter().for_each(Waker::wake);
+}
+
+/// Ready with the value, or pending with the waker listed
+///
+/// A waker the full list turned away is woken at once, so its future
+/// polls again rather than waiting for a wake that never comes.
+fn ready_or_wait<T>(outcome: Result<T, bool>, cx: &Context<'_>) -> Poll<T> {
+    match outcome {
+        Ok(value) => Poll::Ready(value),
+        Err(listed) => {
+            if !listed {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+}
+
+// -------- Notify --------
+
+/// Wakes waiting tasks; a notification with no waiter is kept for the next
+pub struct Notify {
+    state: Shared<NotifyState>,
+}
+
+struct NotifyState {
+    notified: bool,
+    waiters: WaitList,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self { state: Shared::new(NotifyState { notified: false, waiters: WaitList::new() }) }
+    }
+
+    /// Wake the waiters; the first to poll takes the notification
+    ///
+    /// Safe to call from interrupt handlers. Notifications do not add
+    /// up: several before a wait complete one wait.
+    pub fn notify(&self) {
+        let wakers = self.state.with(|state| {
+            state.notified = true;
+            state.waiters.take()
+        });
+        wake_all(wakers);
+    }
+
+    /// Wait for a notification
+    pub fn wait(&self) -> WaitFuture<'_> {
+        WaitFuture { notify: self }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future of `Notify::wait`
+pub struct WaitFuture<'a> {
+    notify: &'a Notify,
+}
+
+impl Future for WaitFuture<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let outcome = self.notify.state.with(|state| {
+            if core::mem::take(&mut state.notified) {
+                Ok(())
+            } else {
+                Err(state.waiters.register(cx.waker()))
+            }
+        });
+        ready_or_wait(outcome, cx)
+    }
+}
+
+// -------- AsyncMutex --------
+
+/// Mutual exclusion between tasks that waits instead of spinning
+///
+/// For task context; an interrupt handler can only `try_lock`. The
+/// lock is not handed to waiters by priority, so a low-priority holder
+/// keeps higher ones waiting until it lets go.
+pub struct AsyncMutex<T> {
+    state: Shared<MutexState>,
+    value: UnsafeCell<T>,
+}
+
+struct MutexState {
+    locked: bool,
+    waiters: WaitList,
+}
+
+// `value` is only reached through the one guard
+unsafe impl<T: Send> Sync for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: Shared::new(MutexState { locked: false, waiters: WaitList::new() }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T>> {
+        let taken = self.state.with(|state| !core::mem::replace(&mut state.locked, true));
+        taken.then_some(AsyncMutexGuard { mutex: self })
+    }
+
+    /// Wait for the lock
+    pub fn lock(&self) -> LockFuture<'_, T> {
+        LockFuture { mutex: self }
+    }
+}
+
+/// Future of `AsyncMutex::lock`
+pub struct LockFuture<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<'a, T> Future for LockFuture<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        let outcome = mutex.state.with(|state| {
+            if core::mem::replace(&mut state.locked, true) {
+                Err(state.waiters.register(cx.waker()))
+            } else {
+                Ok(AsyncMutexGuard { mutex })
+            }
+        });
+        ready_or_wait(outcome, cx)
+    }
+}
+
+/// Access to an `AsyncMutex`'s value; dropping it unlocks
+pub struct AsyncMutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<T> Deref for AsyncMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let wakers = self.mutex.state.with(|state| {
+            state.locked = false;
+            state.waiters.take()
+        });
+        wake_all(wakers);
+    }
+}
+
+// -------- AsyncChannel --------
+
+/// Queue of up to `N` messages whose send waits for room and receive
+/// for a message
+///
+/// Any number of tasks send and receive; each message goes to one
+/// receiver. Storage is a `static`, as for `Channel`.
+pub struct AsyncChannel<T, const N: usize> {
+    state: Shared<ChannelState<T, N>>,
+}
+
+struct ChannelState<T, const N: usize> {
+    queue: Deque<T, N>,
+    senders: WaitList,
+    receivers: WaitList,
+}
+
+impl<T, const N: usize> AsyncChannel<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            state: Shared::new(ChannelState { queue: Deque::new(), senders: WaitList::new(), receivers: WaitList::new() }),
+        }
+    }
+
+    /// Queue `value` without waiting, handing it back if the channel is full
+    ///
+    /// Safe to call from interrupt handlers.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        let wakers = self.state.with(|state| {
+            state.queue.push_back(value)?;
+            Ok(state.receivers.take())
+        })?;
+        wake_all(wakers);
+        Ok(())
+    }
+
+    /// Take the oldest message without waiting
+    ///
+    /// Safe to call from interrupt handlers.
+    pub fn try_recv(&self) -> Option<T> {
+        let (value, wakers) = self.state.with(|state| {
+            let value = state.queue.pop_front()?;
+            Some((value, state.senders.take()))
+        })?;
+        wake_all(wakers);
+        Some(value)
+    }
+
+    /// Queue `value`, waiting for room
+    pub fn send(&self, value: T) -> SendFuture<'_, T, N> {
+        SendFuture { channel: self, value: Some(value) }
+    }
+
+    /// Take the oldest message, waiting for one
+    pub fn recv(&self) -> RecvFuture<'_, T, N> {
+        RecvFuture { channel: self }
+    }
+
+    /// Messages currently queued
+    pub fn len(&self) -> usize {
+        self.state.with(|state| state.queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Default for AsyncChannel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future of `AsyncChannel::send`; dropped before it completes, the
+/// message is dropped with it
+pub struct SendFuture<'a, T, const N: usize> {
+    channel: &'a AsyncChannel<T, N>,
+    value: Option<T>,
+}
+
+// The message is moved in and out, never pinned
+impl<T, const N: usize> Unpin for SendFuture<'_, T, N> {}
+
+impl<T, const N: usize> Future for SendFuture<'_, T, N> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let Some(value) = this.value.take() else {
+            return Poll::Ready(());
+        };
+        let outcome = this.channel.state.with(|state| match state.queue.push_back(value) {
+            Ok(()) => Ok(state.receivers.take()),
+            Err(value) => Err((value, state.senders.register(cx.waker()))),
+        });
+        match outcome {
+            Ok(wakers) => {
+                wake_all(wakers);
+                Poll::Ready(())
+            }
+            Err((value, listed)) => {
+                this.value = Some(value);
+                ready_or_wait(Err(listed), cx)
+            }
+        }
+    }
+}
+
+/// Future of `AsyncChannel::recv`
+pub struct RecvFuture<'a, T, const N: usize> {
+    channel: &'a AsyncChannel<T, N>,
+}
+
+impl<T, const N: usize> Future for RecvFuture<'_, T, N> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let outcome = self.channel.state.with(|state| match state.queue.pop_front() {
+            Some(value) => Ok((value, state.senders.take())),
+            None => Err(state.receivers.register(cx.waker())),
+        });
+        ready_or_wait(outcome, cx).map(|(value, wakers)| {
+            wake_all(wakers);
+            value
+        })
+    }
+}
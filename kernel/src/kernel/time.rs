@@ -0,0 +1,1077 @@
+//! Kernel time
+//! Monotonic tick count and calendar (wall-clock) time kept on top of it
+//!
+//! `now()` is a 64-bit tick count the board's tick interrupt advances
+//! through `tick()`, or through `announce()` when the tick is dynamic
+//! (see "Dynamic tick" below). Kernel APIs take it as an `Instant`, and spans of it
+//! as a `Duration`, both counted in ticks. Raw tick stamps are compared
+//! with `deadline_reached`, which is correct across counter wrap.
+//!
+//! The wall clock is an anchor: a Unix time in seconds and the tick count
+//! at which it was valid. Reading it adds the ticks elapsed since then, so
+//! it advances at `config::TICK_HZ` without any periodic work. It is unset
+//! until `set_wall_clock` is called or `sync_wall_clock` reads a board RTC
+//! (the goldfish RTC on QEMU virt; other boards have none wired up).
+//!
+//! A drift correction, measured against a reference clock with
+//! `calibrate_against`, makes the tick follow the reference rather than
+//! the board's crystal; see "Drift compensation" below.
+//!
+//! `with_timeout` bounds how long a task waits in a blocking call;
+//! `delay_us`/`delay_ms` busy-wait without giving up the CPU, and
+//! `now_us` reads the time in microseconds, for drivers that time
+//! sub-millisecond protocols.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+use heapless::Vec;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::config::TICK_HZ;
+use crate::scheduler::TaskPriority;
+
+/// Calendar date and time, UTC
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1..=12
+    pub month: u8,
+    /// 1..=31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Date and time `seconds` after 1970-01-01 00:00:00
+    pub const fn from_unix(seconds: u64) -> Self {
+        let days = (seconds / 86_400) as i64;
+        let rem = (seconds % 86_400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// Seconds since the Unix epoch, `None` for out-of-range fields
+    #[allow(dead_code)]
+    pub const fn unix_seconds(self) -> Option<u64> {
+        if self.year < 1970
+            || self.month < 1
+            || self.month > 12
+            || self.day < 1
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+        {
+            return None;
+        }
+        let days = days_from_civil(self.year as i64, self.month, self.day) as u64;
+        Some(days * 86_400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+const fn is_leap(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Day-count conversions after Howard Hinnant's `chrono`-compatible algorithms
+const fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+const fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Where the wall clock was last set from
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    Unset,
+    /// `set_wall_clock`, e.g. from a shell command or network time
+    Manual,
+    Rtc,
+}
+
+struct WallClock {
+    /// Unix seconds at `anchor`
+    anchor_seconds: u64,
+    anchor: Instant,
+    source: ClockSource,
+}
+
+struct WallClockCell(UnsafeCell<WallClock>);
+unsafe impl Sync for WallClockCell {} // Single-core assumption
+
+static WALL_CLOCK: WallClockCell =
+    WallClockCell(UnsafeCell::new(WallClock { anchor_seconds: 0, anchor: Instant::ZERO, source: ClockSource::Unset }));
+
+fn with_clock<R>(f: impl FnOnce(&mut WallClock) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *WALL_CLOCK.0.get()) })
+}
+
+// The tick count is 64 bits split over two words: the tick handler bumps
+// `TICKS_HIGH` when `TICKS_LOW` wraps, and readers retry if the high word
+// changed under them. Only the tick interrupt writes either word, and it
+// bumps `ANNOUNCES` after each announcement so readers that combine the
+// count with the tick driver's state can tell one came in under them.
+static TICKS_LOW: AtomicU32 = AtomicU32::new(0);
+static TICKS_HIGH: AtomicU32 = AtomicU32::new(0);
+static ANNOUNCES: AtomicU32 = AtomicU32::new(0);
+
+/// Count one kernel tick; called by the board's periodic tick interrupt
+#[cfg_attr(feature = "tick_dynamic", allow(dead_code))]
+pub fn tick() {
+    announce(1);
+}
+
+/// Count `hw_ticks` hardware ticks at once; called by the tick interrupt
+///
+/// A periodic tick announces each tick; a dynamic one the ticks that
+/// passed since its last interrupt, which may be none.
+pub fn announce(hw_ticks: u32) {
+    let hw_ticks = super::faults::delay_ticks(hw_ticks);
+    #[cfg(feature = "trace")]
+    super::trace::ticked(hw_ticks);
+    let advance = drift_adjust(hw_ticks, true);
+    if advance > 0 {
+        TICK_CYCLES.store(crate::arch::cycle_counter(), Ordering::Relaxed);
+        let (low, wrapped) = TICKS_LOW.load(Ordering::Relaxed).overflowing_add(advance);
+        if wrapped {
+            TICKS_HIGH.fetch_add(1, Ordering::Relaxed);
+        }
+        TICKS_LOW.store(low, Ordering::Release);
+    }
+    ANNOUNCES.store(ANNOUNCES.load(Ordering::Relaxed).wrapping_add(1), Ordering::Release);
+    if advance > 0 {
+        expire_deadlines();
+    }
+}
+
+/// Let the deadline queues built on the tick catch up with it
+fn expire_deadlines() {
+    #[cfg(any(feature = "embassy_time", feature = "hal_async"))]
+    super::time_driver::on_tick(Instant::now());
+    #[cfg(feature = "timers")]
+    super::timer::on_tick(Instant::now());
+}
+
+/// Ticks counted by `announce` so far
+fn announced() -> u64 {
+    loop {
+        let high = TICKS_HIGH.load(Ordering::Acquire);
+        let low = TICKS_LOW.load(Ordering::Acquire);
+        if TICKS_HIGH.load(Ordering::Acquire) == high {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// Monotonic kernel ticks since boot
+///
+/// 64 bits at `config::TICK_HZ`, so it never wraps in practice. With a
+/// dynamic tick this includes the ticks the driver has counted since its
+/// last interrupt, so it reads the same as a periodic tick would.
+pub fn now() -> u64 {
+    if !DYNAMIC_TICK {
+        return announced();
+    }
+    loop {
+        let seq = ANNOUNCES.load(Ordering::Acquire);
+        let ticks = announced() + drift_adjust(tick_device::pending_ticks(), false) as u64;
+        if ANNOUNCES.load(Ordering::Acquire) == seq {
+            return ticks;
+        }
+    }
+}
+
+/// Low 32 bits of `now()`; wraps after about 49 days at 1 kHz
+#[allow(dead_code)]
+pub fn ticks() -> u32 {
+    now() as u32
+}
+
+/// Whether `deadline` has been reached at tick `now`
+///
+/// Compares by wrapping difference, so it stays correct across a wrap as
+/// long as deadlines are less than half the counter range away.
+pub const fn deadline_reached(now: u64, deadline: u64) -> bool {
+    (now.wrapping_sub(deadline) as i64) >= 0
+}
+
+/// `deadline_reached` for 32-bit tick stamps
+pub const fn deadline_reached_u32(now: u32, deadline: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+/// Ticks from `earlier` to `later`, zero if `later` is not after it
+#[allow(dead_code)]
+pub const fn ticks_between(earlier: u64, later: u64) -> u64 {
+    if deadline_reached(later, earlier) {
+        later.wrapping_sub(earlier)
+    } else {
+        0
+    }
+}
+
+// -------- Dynamic tick --------
+//
+// A periodic tick interrupts every tick whether or not anything is due.
+// With `tick_dynamic` the driver runs one-shot instead: it interrupts at
+// the next deadline the kernel knows of and announces the ticks that
+// passed in one go, so an idle system sleeps through the ticks between.
+// `now()` adds the ticks the driver has counted since its last
+// interrupt, so time reads the same in both modes and nothing built on
+// it needs to know which one runs.
+//
+// The deadlines are those `next_deadline` collects: `with_timeout`,
+// periodic task releases, software timers and embassy alarms. Code that adds one calls
+// `deadline_added`, which moves the interrupt forward if the new one
+// comes first. Tasks sleeping on the main-loop timer count loop passes,
+// not ticks, so the tick stays periodic while any does, and the
+// interrupt comes at least every `MAX_DYNAMIC_TICKS` so work the main
+// loop polls still runs while the system idles.
+//
+// Software timers and periodic releases may declare a slack: how much
+// later than its deadline the wakeup may come. The interrupt is then
+// programmed for the earliest deadline plus its slack, and everything
+// due by that tick is handled in the same wakeup, so timers a few ticks
+// apart share one interrupt instead of taking one each. No deadline is
+// handled later than its own slack allows, and none earlier than it is
+// due. Timeouts and embassy alarms have no slack. With a periodic tick
+// every deadline is handled on the tick it falls due, slack or not.
+//
+// SysTick and the CLINT can be programmed one-shot. The nRF52840 RTC and
+// the ESP32-C3 SYSTIMER drivers cannot yet, so on those boards
+// `tick_dynamic` leaves the tick periodic.
+
+/// Whether the tick is dynamic in this build
+pub const DYNAMIC_TICK: bool = cfg!(all(
+    feature = "tick_dynamic",
+    any(
+        all(target_arch = "arm", not(feature = "board_nrf52840")),
+        all(target_arch = "riscv32", not(feature = "board_esp32c3"))
+    )
+));
+
+/// Longest a dynamic tick goes without an interrupt
+pub const MAX_DYNAMIC_TICKS: u32 = TICK_HZ;
+
+#[cfg(all(feature = "tick_dynamic", target_arch = "arm", not(feature = "board_nrf52840")))]
+use crate::drivers::systick as tick_device;
+#[cfg(all(feature = "tick_dynamic", target_arch = "riscv32", not(feature = "board_esp32c3")))]
+use crate::drivers::clint as tick_device;
+
+/// A periodic tick announces every tick, so none is ever pending
+#[cfg(not(all(
+    feature = "tick_dynamic",
+    any(
+        all(target_arch = "arm", not(feature = "board_nrf52840")),
+        all(target_arch = "riscv32", not(feature = "board_esp32c3"))
+    )
+)))]
+mod tick_device {
+    pub fn pending_ticks() -> u32 {
+        0
+    }
+
+    pub fn program(_hw_ticks: u32) {}
+}
+
+/// Low word of the tick the dynamic tick interrupt is programmed for
+static NEXT_WAKE: AtomicU32 = AtomicU32::new(0);
+
+/// Latest tick the kernel can wake at without handling any deadline later
+/// than its slack allows, `None` if there is no deadline
+pub fn next_deadline() -> Option<Instant> {
+    let timeouts = with_timeouts(|table| table.iter().map(|entry| entry.deadline).min());
+    let releases = super::periodic::next_release();
+    #[cfg(feature = "timers")]
+    let timers = super::timer::next_deadline();
+    #[cfg(not(feature = "timers"))]
+    let timers = None;
+    #[cfg(any(feature = "embassy_time", feature = "hal_async"))]
+    let alarms = super::time_driver::next_deadline();
+    #[cfg(not(any(feature = "embassy_time", feature = "hal_async")))]
+    let alarms = None;
+    [timeouts, releases, timers, alarms].into_iter().flatten().min()
+}
+
+/// Program a dynamic tick's next interrupt for the earliest deadline;
+/// called by its driver after each announcement
+#[allow(dead_code)]
+pub fn program_next() {
+    with_tick_device(|| tick_device::program(next_wakeup()));
+}
+
+/// Bring the tick interrupt forward to `deadline` if it is programmed
+/// for later (ISR-safe); a no-op with a periodic tick
+///
+/// A deadline with a slack passes the latest tick it can be handled at.
+pub fn deadline_added(deadline: Instant) {
+    if !DYNAMIC_TICK {
+        return;
+    }
+    with_tick_device(|| {
+        if !deadline_reached_u32(deadline.ticks() as u32, NEXT_WAKE.load(Ordering::Relaxed)) {
+            tick_device::program(next_wakeup());
+        }
+    });
+}
+
+/// Run `f` with interrupts off, restoring their state, so the deadline
+/// read and the one programmed stay the same
+fn with_tick_device(f: impl FnOnce()) {
+    crate::arch::free(f)
+}
+
+/// Hardware ticks after the last announcement to interrupt at
+///
+/// The span is counted in corrected ticks, so under a drift correction
+/// the interrupt may come a little early; it then announces what passed
+/// and programs the rest.
+fn next_wakeup() -> u32 {
+    let now = Instant::now();
+    let ahead = if crate::scheduler::has_sleeping_tasks_locked() {
+        1
+    } else {
+        next_deadline().map_or(MAX_DYNAMIC_TICKS as u64, |at| (at - now).ticks())
+    };
+    let ahead = ahead.clamp(1, MAX_DYNAMIC_TICKS as u64) as u32;
+    NEXT_WAKE.store((now.ticks() as u32).wrapping_add(ahead), Ordering::Relaxed);
+    tick_device::pending_ticks() + ahead
+}
+
+// -------- Instant and Duration --------
+//
+// Both count kernel ticks in a u64, which does not wrap in practice, so
+// they compare as plain numbers. Conversions from ms and us round up to
+// whole ticks, so a sleep or timeout never ends early; conversions back
+// round down.
+
+/// A point on the kernel timebase
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ticks: u64,
+}
+
+/// A span of kernel time
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration {
+    ticks: u64,
+}
+
+#[allow(dead_code)]
+impl Instant {
+    /// Boot
+    pub const ZERO: Self = Self { ticks: 0 };
+
+    pub fn now() -> Self {
+        Self { ticks: now() }
+    }
+
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    /// Ticks since boot
+    pub const fn ticks(self) -> u64 {
+        self.ticks
+    }
+
+    pub const fn checked_add(self, duration: Duration) -> Option<Self> {
+        match self.ticks.checked_add(duration.ticks) {
+            Some(ticks) => Some(Self { ticks }),
+            None => None,
+        }
+    }
+
+    pub const fn checked_sub(self, duration: Duration) -> Option<Self> {
+        match self.ticks.checked_sub(duration.ticks) {
+            Some(ticks) => Some(Self { ticks }),
+            None => None,
+        }
+    }
+
+    pub const fn wrapping_add(self, duration: Duration) -> Self {
+        Self { ticks: self.ticks.wrapping_add(duration.ticks) }
+    }
+
+    pub const fn wrapping_sub(self, duration: Duration) -> Self {
+        Self { ticks: self.ticks.wrapping_sub(duration.ticks) }
+    }
+
+    /// Time from `earlier` to this instant, `None` if `earlier` is later
+    pub const fn checked_duration_since(self, earlier: Self) -> Option<Duration> {
+        match self.ticks.checked_sub(earlier.ticks) {
+            Some(ticks) => Some(Duration { ticks }),
+            None => None,
+        }
+    }
+
+    /// Time from `earlier` to this instant, zero if `earlier` is later
+    pub const fn saturating_duration_since(self, earlier: Self) -> Duration {
+        Duration { ticks: self.ticks.saturating_sub(earlier.ticks) }
+    }
+
+    /// Time passed since this instant
+    pub fn elapsed(self) -> Duration {
+        Self::now().saturating_duration_since(self)
+    }
+
+    /// Time left until this instant, zero once it has passed
+    pub fn remaining(self) -> Duration {
+        self.saturating_duration_since(Self::now())
+    }
+
+    /// Whether this instant has come, taken as a deadline
+    pub fn is_reached(self) -> bool {
+        Self::now() >= self
+    }
+}
+
+#[allow(dead_code)]
+impl Duration {
+    pub const ZERO: Self = Self { ticks: 0 };
+    pub const MAX: Self = Self { ticks: u64::MAX };
+
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self { ticks: secs.saturating_mul(TICK_HZ as u64) }
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self::from_parts(millis, 1_000)
+    }
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self::from_parts(micros, 1_000_000)
+    }
+
+    /// `count` units of 1/`per_second` s, rounded up to whole ticks
+    const fn from_parts(count: u64, per_second: u64) -> Self {
+        Self { ticks: count.saturating_mul(TICK_HZ as u64).div_ceil(per_second) }
+    }
+
+    pub const fn ticks(self) -> u64 {
+        self.ticks
+    }
+
+    pub const fn as_secs(self) -> u64 {
+        self.ticks / TICK_HZ as u64
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        self.ticks.saturating_mul(1_000) / TICK_HZ as u64
+    }
+
+    pub const fn as_micros(self) -> u64 {
+        self.ticks.saturating_mul(1_000_000) / TICK_HZ as u64
+    }
+
+    /// Ticks as a u32, for 32-bit tick stamps; saturates
+    pub const fn ticks_u32(self) -> u32 {
+        if self.ticks > u32::MAX as u64 {
+            u32::MAX
+        } else {
+            self.ticks as u32
+        }
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.ticks == 0
+    }
+
+    pub const fn checked_add(self, other: Self) -> Option<Self> {
+        match self.ticks.checked_add(other.ticks) {
+            Some(ticks) => Some(Self { ticks }),
+            None => None,
+        }
+    }
+
+    pub const fn checked_sub(self, other: Self) -> Option<Self> {
+        match self.ticks.checked_sub(other.ticks) {
+            Some(ticks) => Some(Self { ticks }),
+            None => None,
+        }
+    }
+
+    pub const fn saturating_add(self, other: Self) -> Self {
+        Self { ticks: self.ticks.saturating_add(other.ticks) }
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self { ticks: self.ticks.saturating_sub(other.ticks) }
+    }
+
+    pub const fn checked_mul(self, factor: u32) -> Option<Self> {
+        match self.ticks.checked_mul(factor as u64) {
+            Some(ticks) => Some(Self { ticks }),
+            None => None,
+        }
+    }
+}
+
+// Instant operators wrap like the tick count, Duration ones saturate; the
+// checked methods are there where an overflow would be a bug to catch
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.wrapping_add(duration)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.wrapping_sub(duration)
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+/// Saturates at zero, like `saturating_duration_since`
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        self.saturating_add(other)
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        self.saturating_sub(other)
+    }
+}
+
+impl fmt::Display for Duration {
+    /// `1.250 s`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.as_millis();
+        write!(f, "{}.{:03} s", millis / 1000, millis % 1000)
+    }
+}
+
+// -------- Drift compensation --------
+//
+// A tick source off by N ppm gains or loses N microseconds a second,
+// which adds up over long sleeps and in the wall clock. The correction
+// is kept in ppm of the tick rate, positive when the hardware tick runs
+// fast. `announce()` accumulates it and drops one tick every 1_000_000 / N
+// hardware ticks (or counts one twice when the tick runs slow), so
+// `now()` and every deadline, timeout and wall-clock read built on it
+// follow the reference instead of the crystal.
+//
+// `calibrate_against` measures the correction from readings of a
+// trusted clock: the board RTC where there is one (`rtc_micros`), or
+// the host through the host protocol. Each reading at least
+// `MIN_CALIBRATION_US` after the previous one compares the time `now_us`
+// saw pass with the reference's and refines the correction by the
+// difference. The result is only as good as the reference's jitter
+// over that span, so longer spans between readings give a finer one.
+
+/// Largest correction applied either way, 5%
+pub const MAX_DRIFT_PPM: i32 = 50_000;
+
+/// Shortest reference span a calibration reading is measured over
+pub const MIN_CALIBRATION_US: u64 = 10_000_000;
+
+const PPM: i32 = 1_000_000;
+
+/// Tick correction in ppm, the bits of an i32
+static DRIFT_PPM: AtomicU32 = AtomicU32::new(0);
+
+/// Correction carried over between ticks, in ppm of a tick, the bits of
+/// an i32; only the tick interrupt writes it
+static DRIFT_PHASE: AtomicU32 = AtomicU32::new(0);
+
+/// Ticks to count for `hw_ticks` hardware ticks under the correction,
+/// carrying what is left over to the next call if `commit`
+///
+/// One hardware tick counts as 1, or 0 or 2 to catch up. `now()` previews
+/// the ticks a dynamic tick has not announced yet without committing, and
+/// gets the same count the announcement will.
+fn drift_adjust(hw_ticks: u32, commit: bool) -> u32 {
+    let ppm = DRIFT_PPM.load(Ordering::Relaxed) as i32;
+    if ppm == 0 || hw_ticks == 0 {
+        return hw_ticks;
+    }
+    let phase = DRIFT_PHASE.load(Ordering::Relaxed) as i32 as i64 + ppm as i64 * hw_ticks as i64;
+    // Truncates toward zero: negative when the tick runs slow
+    let dropped = phase / PPM as i64;
+    if commit {
+        DRIFT_PHASE.store((phase - dropped * PPM as i64) as i32 as u32, Ordering::Relaxed);
+    }
+    (hw_ticks as i64 - dropped).clamp(0, u32::MAX as i64) as u32
+}
+
+/// Tick correction in effect, in ppm; positive when the hardware runs fast
+pub fn drift_ppm() -> i32 {
+    DRIFT_PPM.load(Ordering::Relaxed) as i32
+}
+
+/// Apply a known correction, e.g. one measured on an earlier boot
+///
+/// Clamped to `MAX_DRIFT_PPM`. Restarts any calibration under way, since
+/// its first reading was taken at the old rate.
+pub fn set_drift_ppm(ppm: i32) {
+    DRIFT_PPM.store(ppm.clamp(-MAX_DRIFT_PPM, MAX_DRIFT_PPM) as u32, Ordering::Relaxed);
+    with_calibration(|calibration| *calibration = None);
+}
+
+/// Paired readings of `now_us` and the reference at the last calibration
+#[derive(Copy, Clone)]
+struct CalibrationPoint {
+    local_us: u64,
+    reference_us: u64,
+}
+
+struct CalibrationCell(UnsafeCell<Option<CalibrationPoint>>);
+unsafe impl Sync for CalibrationCell {} // Single-core assumption
+
+static CALIBRATION: CalibrationCell = CalibrationCell(UnsafeCell::new(None));
+
+fn with_calibration<R>(f: impl FnOnce(&mut Option<CalibrationPoint>) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *CALIBRATION.0.get()) })
+}
+
+/// Compare the tick with a reference clock reading `reference_us`
+///
+/// The first reading, or one where the reference went backwards, only
+/// starts a span. Readings less than `MIN_CALIBRATION_US` into the span
+/// are ignored. Later ones update the correction, start the next span
+/// and return the new correction in ppm.
+pub fn calibrate_against(reference_us: u64) -> Option<i32> {
+    let point = CalibrationPoint { local_us: now_us(), reference_us };
+    let (local_span, reference_span) = with_calibration(|calibration| {
+        let start = calibration.filter(|start| start.reference_us <= reference_us);
+        let spans = start.map(|start| (point.local_us - start.local_us, reference_us - start.reference_us));
+        match spans {
+            Some((_, reference_span)) if reference_span < MIN_CALIBRATION_US => None,
+            _ => {
+                *calibration = Some(point);
+                spans
+            }
+        }
+    })?;
+    // How far the corrected tick still ran fast over the span
+    let error = (local_span as i64 - reference_span as i64) * PPM as i64 / reference_span as i64;
+    let ppm = (drift_ppm() as i64 + error).clamp(-MAX_DRIFT_PPM as i64, MAX_DRIFT_PPM as i64) as i32;
+    DRIFT_PPM.store(ppm as u32, Ordering::Relaxed);
+    Some(ppm)
+}
+
+/// Board RTC reading in microseconds since the Unix epoch, `None` on
+/// boards without one
+pub fn rtc_micros() -> Option<u64> {
+    #[cfg(all(target_arch = "riscv32", not(any(feature = "board_esp32c3", feature = "board_hifive1"))))]
+    return Some(crate::drivers::goldfish_rtc::read_nanos() / 1000);
+
+    #[allow(unreachable_code)]
+    None
+}
+
+// -------- Busy-wait delays and microsecond time --------
+//
+// The cycle counter rate is measured against the tick at boot, so delays
+// and `now_us` hold at any CPU clock. Without a working cycle counter
+// (QEMU's Cortex-M has no DWT) they fall back to whole ticks.
+//
+// Accuracy by platform, on top of the CPU clock's own tolerance:
+//
+// - STM32F407, nRF52840 (DWT CYCCNT), HiFive1 (mcycle) and ESP32-C3
+//   (mpccr): a few cycles for delays; `now_us` also carries the tick
+//   interrupt's entry latency, well under a microsecond.
+// - QEMU virt: mcycle follows emulated instructions, not host time, so
+//   sub-tick readings are only roughly proportional.
+// - QEMU lm3s6965: no cycle counter; one tick (1 ms at the default rate).
+//
+// The counters stop while the core sleeps in WFI, so `now_us` read just
+// after an idle wake-up lags by up to that sleep until the next tick
+// (by at most one tick with a dynamic tick, whose driver counts the
+// ticks slept). It never goes backwards. Delays spin, so the core does not sleep
+// during them.
+
+/// Ticks the calibration measures over
+const CALIBRATION_TICKS: u32 = 10;
+
+/// Spins to wait for one tick before deciding the tick is not running
+const CALIBRATION_SPIN_LIMIT: u32 = 2_000_000;
+
+/// CPU cycles per kernel tick, 0 until calibrated
+static CYCLES_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+/// Cycle counter at the last announcement, for `now_us` between ticks
+static TICK_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Spin until the tick count moves; false if it never does
+fn wait_for_tick() -> bool {
+    let start = ticks();
+    (0..CALIBRATION_SPIN_LIMIT).any(|_| {
+        core::hint::spin_loop();
+        ticks() != start
+    })
+}
+
+/// Boot step: measure the cycle counter against the tick
+pub fn calibrate_delay() -> crate::kernel::InitResult {
+    // A replayed tick only moves at scheduling points; the simulator's rate is known
+    #[cfg(all(feature = "trace", feature = "sim"))]
+    if super::trace::replaying() {
+        CYCLES_PER_TICK.store(crate::arch::sim::CPU_HZ / TICK_HZ, Ordering::Relaxed);
+        return Ok(());
+    }
+    if !wait_for_tick() {
+        return Err("tick not running");
+    }
+    let start = crate::arch::cycle_counter();
+    for _ in 0..CALIBRATION_TICKS {
+        if !wait_for_tick() {
+            return Err("tick stopped");
+        }
+    }
+    let cycles = crate::arch::cycle_counter().wrapping_sub(start) / CALIBRATION_TICKS;
+    if cycles == 0 {
+        return Err("no cycle counter, delays use the tick");
+    }
+    CYCLES_PER_TICK.store(cycles, Ordering::Relaxed);
+    Ok(())
+}
+
+/// CPU clock measured at boot in Hz, `None` if calibration failed
+#[allow(dead_code)]
+pub fn cpu_hz() -> Option<u32> {
+    match CYCLES_PER_TICK.load(Ordering::Relaxed) {
+        0 => None,
+        cycles => Some(cycles.saturating_mul(TICK_HZ)),
+    }
+}
+
+/// Microseconds since boot
+///
+/// Whole ticks plus the cycles counted since the last one; see the
+/// accuracy notes above. Tick resolution without a cycle counter.
+#[allow(dead_code)]
+pub fn now_us() -> u64 {
+    let us_per_tick = 1_000_000 / TICK_HZ as u64;
+    let cycles_per_tick = CYCLES_PER_TICK.load(Ordering::Relaxed) as u64;
+    loop {
+        let seq = ANNOUNCES.load(Ordering::Acquire);
+        let announced = announced();
+        let ticks = now();
+        let since_tick = crate::arch::cycle_counter().wrapping_sub(TICK_CYCLES.load(Ordering::Relaxed));
+        if ANNOUNCES.load(Ordering::Acquire) != seq {
+            // A tick came in between the reads
+            continue;
+        }
+        if cycles_per_tick == 0 {
+            return ticks * us_per_tick;
+        }
+        // Ticks a dynamic tick has counted but not announced yet
+        let pending = ticks - announced;
+        // A tick held off by a critical section must not make time run
+        // past the next tick's start and then jump back
+        let within = (since_tick as u64 * us_per_tick / cycles_per_tick)
+            .clamp(pending * us_per_tick, (pending + 1) * us_per_tick - 1);
+        return announced * us_per_tick + within;
+    }
+}
+
+/// Busy-wait at least `us` microseconds
+#[allow(dead_code)]
+pub fn delay_us(us: u32) {
+    let cycles_per_tick = CYCLES_PER_TICK.load(Ordering::Relaxed) as u64;
+    if cycles_per_tick == 0 {
+        // Round up, plus one for the partial tick already under way
+        let ticks = (us as u64 * TICK_HZ as u64).div_ceil(1_000_000) + 1;
+        let deadline = now().wrapping_add(ticks);
+        while !deadline_reached(now(), deadline) {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+    let mut remaining = (us as u64 * cycles_per_tick * TICK_HZ as u64).div_ceil(1_000_000);
+    // The cycle counter is 32 bits; wait in chunks well inside its range
+    while remaining > 0 {
+        let chunk = remaining.min(1 << 30) as u32;
+        let start = crate::arch::cycle_counter();
+        while crate::arch::cycle_counter().wrapping_sub(start) < chunk {
+            core::hint::spin_loop();
+        }
+        remaining -= chunk as u64;
+    }
+}
+
+/// Busy-wait at least `ms` milliseconds
+#[allow(dead_code)]
+pub fn delay_ms(ms: u32) {
+    for _ in 0..ms {
+        delay_us(1000);
+    }
+}
+
+/// Time since boot
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Uptime {
+    pub ticks: u64,
+}
+
+impl Uptime {
+    pub const fn seconds(self) -> u64 {
+        self.ticks / TICK_HZ as u64
+    }
+
+    #[allow(dead_code)]
+    pub const fn millis(self) -> u64 {
+        self.ticks * 1000 / TICK_HZ as u64
+    }
+}
+
+impl fmt::Display for Uptime {
+    /// `3d 04:05:06` (days only once there is one)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.seconds();
+        let (days, rem) = (seconds / 86_400, seconds % 86_400);
+        if days > 0 {
+            write!(f, "{}d ", days)?;
+        }
+        write!(f, "{:02}:{:02}:{:02}", rem / 3600, rem / 60 % 60, rem % 60)
+    }
+}
+
+/// Time since boot, counted by the hardware tick
+///
+/// Independent of the scheduler timer the main loop advances.
+pub fn uptime() -> Uptime {
+    Uptime { ticks: now() }
+}
+
+/// Set the wall clock to `unix_seconds` (seconds since 1970-01-01 UTC)
+#[allow(dead_code)]
+pub fn set_wall_clock(unix_seconds: u64) {
+    set(unix_seconds, ClockSource::Manual);
+}
+
+fn set(unix_seconds: u64, source: ClockSource) {
+    let now = Instant::now();
+    with_clock(|clock| {
+        clock.anchor_seconds = unix_seconds;
+        clock.anchor = now;
+        clock.source = source;
+    });
+}
+
+/// Current Unix time in seconds, `None` until the clock has been set
+pub fn wall_clock_seconds() -> Option<u64> {
+    let now = Instant::now();
+    with_clock(|clock| match clock.source {
+        ClockSource::Unset => None,
+        _ => Some(clock.anchor_seconds + (now - clock.anchor).as_secs()),
+    })
+}
+
+/// Current calendar time, `None` until the clock has been set
+#[allow(dead_code)]
+pub fn wall_clock() -> Option<DateTime> {
+    wall_clock_seconds().map(DateTime::from_unix)
+}
+
+/// Where the wall clock was last set from
+#[allow(dead_code)]
+pub fn wall_clock_source() -> ClockSource {
+    with_clock(|clock| clock.source)
+}
+
+/// Set the wall clock from the board RTC; false if the board has none
+pub fn sync_wall_clock() -> bool {
+    match rtc_micros() {
+        Some(micros) => {
+            set(micros / 1_000_000, ClockSource::Rtc);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Boot step: take the date from the RTC where there is one
+pub fn init() -> crate::kernel::InitResult {
+    sync_wall_clock();
+    Ok(())
+}
+
+// -------- Timeouts --------
+//
+// Tasks run to completion, so a blocking call that parks the task returns
+// and is made again when the task next runs. `with_timeout` keeps the
+// deadline between those calls, keyed by task, and `poll_timeouts` wakes
+// a parked task once its deadline passes so it can observe the timeout.
+
+/// Tasks that can be inside `with_timeout` at once
+pub const MAX_TIMEOUTS: usize = 8;
+
+/// Errors that mean "not yet; the task was parked or should retry"
+pub trait WouldBlock {
+    fn would_block(&self) -> bool;
+}
+
+/// Why `with_timeout` returned no value
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timeout<E> {
+    /// The deadline passed before the operation completed
+    Elapsed,
+    /// The operation failed, or would block and the task is now parked;
+    /// in the latter case return to the scheduler and call again
+    Op(E),
+}
+
+#[derive(Copy, Clone)]
+struct PendingTimeout {
+    task_id: usize,
+    priority: TaskPriority,
+    deadline: Instant,
+}
+
+struct TimeoutTable(UnsafeCell<Vec<PendingTimeout, MAX_TIMEOUTS>>);
+unsafe impl Sync for TimeoutTable {} // Single-core assumption
+
+static TIMEOUTS: TimeoutTable = TimeoutTable(UnsafeCell::new(Vec::new()));
+
+/// Run `f` on the table with interrupts off, restoring their state; a
+/// dynamic tick reads it from its interrupt handler
+fn with_timeouts<R>(f: impl FnOnce(&mut Vec<PendingTimeout, MAX_TIMEOUTS>) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *TIMEOUTS.0.get()) })
+}
+
+/// Run a blocking operation with a deadline `duration` away
+///
+/// The first call starts the deadline; calls made after the task is woken
+/// keep it, until `op` succeeds, fails outright or the deadline passes.
+/// On `Elapsed` the task has been made ready again and carries on. Outside
+/// task context, or when the table is full, `op` runs without a deadline.
+#[allow(dead_code)]
+pub fn with_timeout<T, E: WouldBlock>(duration: Duration, op: impl FnOnce() -> Result<T, E>) -> Result<T, Timeout<E>> {
+    let Some(task) = crate::scheduler::current_priority_task() else {
+        return op().map_err(Timeout::Op);
+    };
+    let start = Instant::now();
+    let deadline = with_timeouts(|table| {
+        match table.iter().find(|entry| entry.task_id == task.id && entry.priority == task.priority) {
+            Some(entry) => Some(entry.deadline),
+            None => {
+                let deadline = start + duration;
+                let entry = PendingTimeout { task_id: task.id, priority: task.priority, deadline };
+                table.push(entry).ok().map(|_| deadline)
+            }
+        }
+    });
+
+    if let Some(deadline) = deadline {
+        deadline_added(deadline);
+    }
+
+    let result = op();
+    let blocked = matches!(&result, Err(error) if error.would_block());
+    let Some(deadline) = deadline else {
+        return result.map_err(Timeout::Op);
+    };
+    if blocked && !deadline.is_reached() {
+        return result.map_err(Timeout::Op);
+    }
+
+    with_timeouts(|table| table.retain(|entry| !(entry.task_id == task.id && entry.priority == task.priority)));
+    if blocked {
+        // `op` parked the task again; undo that so it sees the timeout
+        crate::scheduler::wake_task(task.id, task.priority);
+        return Err(Timeout::Elapsed);
+    }
+    result.map_err(Timeout::Op)
+}
+
+/// Wake tasks whose `with_timeout` deadline has passed
+///
+/// Called by the scheduler before each pass. Entries for tasks that no
+/// longer exist are dropped.
+pub fn poll_timeouts() {
+    let now = Instant::now();
+    let expired: Vec<PendingTimeout, MAX_TIMEOUTS> = with_timeouts(|table| {
+        table.iter().filter(|entry| now >= entry.deadline).copied().collect()
+    });
+    for entry in expired {
+        if !crate::scheduler::wake_task(entry.task_id, entry.priority) {
+            with_timeouts(|table| {
+                table.retain(|pending| !(pending.task_id == entry.task_id && pending.priority == entry.priority))
+            });
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! embassy-time driver
+//! `embassy_time::Timer` and friends on the kernel tick (`embassy_time`)
+//!
+//! Implements `embassy_time_driver::Driver`. Embassy counts time at its
+//! default 1 MHz; `now()` is the 64-bit kernel tick scaled up to that,
+//! so it is monotonic and never wraps, and reads zero before the tick
+//! starts. Its resolution is one kernel tick.
+//!
+//! `schedule_wake` puts the waker in a fixed queue of `MAX_ALARMS`
+//! entries, one per waker, keeping the earliest deadline asked for. The
+//! tick interrupt acts as the alarm interrupt: each tick wakes the
+//! wakers whose deadline has come; a dynamic tick is programmed for the
+//! earliest deadline queued. An executor polled from a karatOS
+//! task then gets its task woken, and the interrupt has already taken
+//! the core out of WFI. With the queue full, the waker is woken at once;
+//! the timer polls again and reschedules, so it still completes, only by
+//! spinning.
+//!
+//! The queue is also built for `hal_async`, whose `Delay` waits on it
+//! through `wake_at` in the same way.
+
+use core::cell::UnsafeCell;
+use core::task::Waker;
+
+use heapless::Vec;
+
+use super::time::Instant;
+#[cfg(feature = "embassy_time")]
+use crate::config::TICK_HZ;
+
+/// Embassy time units per kernel tick
+#[cfg(feature = "embassy_time")]
+const UNITS_PER_TICK: u64 = embassy_time_driver::TICK_HZ / TICK_HZ as u64;
+
+#[cfg(feature = "embassy_time")]
+const _: () = assert!(
+    embassy_time_driver::TICK_HZ.is_multiple_of(TICK_HZ as u64),
+    "the kernel tick rate must divide the embassy tick rate"
+);
+
+/// Wakers with a pending deadline at once
+pub const MAX_ALARMS: usize = 8;
+
+struct Alarm {
+    /// Kernel tick to wake at
+    deadline: Instant,
+    waker: Waker,
+}
+
+struct AlarmQueue(UnsafeCell<Vec<Alarm, MAX_ALARMS>>);
+unsafe impl Sync for AlarmQueue {} // Only touched with interrupts off
+
+static ALARMS: AlarmQueue = AlarmQueue(UnsafeCell::new(Vec::new()));
+
+/// Run `f` on the queue with interrupts off, restoring their state
+///
+/// Executors may schedule from interrupt handlers, and the tick handler
+/// runs with interrupts off already.
+fn with_alarms<R>(f: impl FnOnce(&mut Vec<Alarm, MAX_ALARMS>) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *ALARMS.0.get() }))
+}
+
+/// Wake `waker` once the tick reaches `deadline`
+///
+/// At once if it already has, or if the queue is full.
+#[allow(dead_code)]
+pub fn wake_at(deadline: Instant, waker: &Waker) {
+    if deadline.is_reached() {
+        waker.wake_by_ref();
+        return;
+    }
+    let queued = with_alarms(|alarms| {
+        // A task may wait on several timers; the earliest one wakes it
+        if let Some(alarm) = alarms.iter_mut().find(|alarm| alarm.waker.will_wake(waker)) {
+            alarm.deadline = alarm.deadline.min(deadline);
+            return true;
+        }
+        alarms.push(Alarm { deadline, waker: waker.clone() }).is_ok()
+    });
+    if queued {
+        super::time::deadline_added(deadline);
+    } else {
+        waker.wake_by_ref();
+    }
+}
+
+#[cfg(feature = "embassy_time")]
+struct KernelTimeDriver;
+
+#[cfg(feature = "embassy_time")]
+impl embassy_time_driver::Driver for KernelTimeDriver {
+    fn now(&self) -> u64 {
+        Instant::now().ticks() * UNITS_PER_TICK
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        wake_at(Instant::from_ticks(at.div_ceil(UNITS_PER_TICK)), waker);
+    }
+}
+
+#[cfg(feature = "embassy_time")]
+embassy_time_driver::time_driver_impl!(static DRIVER: KernelTimeDriver = KernelTimeDriver);
+
+/// Earliest deadline in the queue, for a dynamic tick
+pub fn next_deadline() -> Option<Instant> {
+    with_alarms(|alarms| alarms.iter().map(|alarm| alarm.deadline).min())
+}
+
+/// Wake the wakers whose deadline has come; called by `time::announce`
+pub fn on_tick(now: Instant) {
+    let mut due: Vec<Waker, MAX_ALARMS> = Vec::new();
+    with_alarms(|alarms| {
+        let mut index = 0;
+        while index < alarms.len() {
+            if now >= alarms[index].deadline {
+                let _ = due.push(alarms.swap_remove(index).waker);
+            } else {
+                index += 1;
+            }
+        }
+    });
+    due.into_iter().for_each(Waker::wake);
+}
@@ -0,0 +1,288 @@
+//! Software timers
+//! One-shot and periodic callbacks on the kernel tick (`timers`)
+//!
+//! A `Timer` is a `static` callback whose mode is fixed where it is
+//! declared. A `Fast` timer runs its callback in the tick interrupt,
+//! right after the tick that made it due: lowest latency, but with ISR
+//! rules. The callback must not block or wait, should take a few
+//! microseconds at most, and may only use ISR-safe calls (posting
+//! events, scheduling tasklets, logging, starting and stopping timers).
+//! A `Deferred` timer is handed to the timer service task, which runs it
+//! at `High` priority with interrupts enabled, like any task code. That
+//! costs a pass of the scheduler loop in latency.
+//!
+//! Deadlines live in a `TimerWheel` keyed by slot, so the tick handler's
+//! work does not grow with the number of timers waiting. A timer takes a
+//! slot the first time it is started and keeps it. A periodic timer's
+//! next deadline is its last one plus the period, so it does not drift.
+//! A deferred timer that comes due again before the service ran it runs
+//! once; the expiries it missed are counted in `overruns`.
+//!
+//! A timer declared `with_slack` may expire up to that much after its
+//! deadline. A dynamic tick uses the room to expire it together with
+//! other deadlines close by in one interrupt; see "Dynamic tick" in
+//! `kernel::time`. Timers without slack expire on the tick they are due.
+
+use core::cell::UnsafeCell;
+
+use heapless::Vec;
+
+use super::time::{Duration, Instant};
+use super::TimerWheel;
+use crate::arch::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use crate::kernel::InitResult;
+use crate::scheduler::{self, Task, TaskPriority};
+
+/// Timers that can be started at once
+pub const MAX_TIMERS: usize = 16;
+
+const _: () = assert!(MAX_TIMERS <= 32, "pending deferred timers are a u32 mask");
+
+/// Task ID of the timer service task
+pub const TASK_ID: usize = 19;
+
+/// Deferred callbacks come before application work
+const PRIORITY: TaskPriority = TaskPriority::High;
+
+const NO_SLOT: usize = usize::MAX;
+
+/// Where a timer's callback runs
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerMode {
+    /// In the tick interrupt
+    Fast,
+    /// In the timer service task
+    Deferred,
+}
+
+/// Software timer, declared as a `static`
+#[allow(dead_code)]
+pub struct Timer {
+    name: &'static str,
+    func: fn(),
+    mode: TimerMode,
+    /// How late an expiry may come, to share a dynamic tick's wakeup
+    slack: Duration,
+    slot: AtomicUsize,
+    /// Ticks between expiries, 0 for one-shot
+    period: AtomicU32,
+    runs: AtomicU32,
+    overruns: AtomicU32,
+}
+
+#[allow(dead_code)]
+impl Timer {
+    pub const fn new(name: &'static str, mode: TimerMode, func: fn()) -> Self {
+        Self {
+            name,
+            func,
+            mode,
+            slack: Duration::ZERO,
+            slot: AtomicUsize::new(NO_SLOT),
+            period: AtomicU32::new(0),
+            runs: AtomicU32::new(0),
+            overruns: AtomicU32::new(0),
+        }
+    }
+
+    /// Let each expiry come up to `slack` after its deadline, so a dynamic
+    /// tick can group it with other wakeups nearby
+    pub const fn with_slack(mut self, slack: Duration) -> Self {
+        self.slack = slack;
+        self
+    }
+
+    /// Run the callback once, `delay` from now (ISR-safe)
+    ///
+    /// Restarting a running timer moves its deadline. Returns false if
+    /// every slot is taken by other timers.
+    pub fn start(&'static self, delay: Duration) -> bool {
+        self.arm(delay, Duration::ZERO)
+    }
+
+    /// Run the callback every `period`, the first time one period from
+    /// now (ISR-safe)
+    ///
+    /// Periods are kept to 32 bits of ticks.
+    pub fn start_periodic(&'static self, period: Duration) -> bool {
+        self.arm(period, period)
+    }
+
+    /// Cancel the timer, including a deferred run not yet made (ISR-safe)
+    pub fn stop(&self) {
+        let slot = self.slot.load(Ordering::Relaxed);
+        if slot == NO_SLOT {
+            return;
+        }
+        with_table(|table| {
+            table.wheel.disarm(slot);
+            table.pending &= !(1 << slot);
+        });
+    }
+
+    /// Whether the timer is waiting to expire
+    pub fn is_running(&self) -> bool {
+        let slot = self.slot.load(Ordering::Relaxed);
+        slot != NO_SLOT && with_table(|table| table.wheel.is_armed(slot))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    pub fn slack(&self) -> Duration {
+        self.slack
+    }
+
+    /// Times the callback has run
+    pub fn runs(&self) -> u32 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    /// Deferred expiries folded into a run that was still pending
+    pub fn overruns(&self) -> u32 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    fn arm(&'static self, delay: Duration, period: Duration) -> bool {
+        let armed = with_table(|table| {
+            let slot = match self.slot.load(Ordering::Relaxed) {
+                NO_SLOT => {
+                    let slot = table.timers.iter().position(Option::is_none)?;
+                    table.timers[slot] = Some(self);
+                    self.slot.store(slot, Ordering::Relaxed);
+                    slot
+                }
+                slot => slot,
+            };
+            self.period.store(period.ticks_u32(), Ordering::Relaxed);
+            let deadline = Instant::now() + delay.max(Duration::from_ticks(1));
+            table.deadlines[slot] = deadline;
+            table.wheel.arm(slot, deadline);
+            Some(deadline)
+        });
+        armed.inspect(|&deadline| super::time::deadline_added(deadline + self.slack)).is_some()
+    }
+
+    fn run(&self) {
+        (self.func)();
+        self.runs.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct TimerTable {
+    timers: [Option<&'static Timer>; MAX_TIMERS],
+    /// Deadline each slot was armed for, the base of the next period
+    deadlines: [Instant; MAX_TIMERS],
+    wheel: TimerWheel<MAX_TIMERS>,
+    /// Deferred timers due and not yet run, by slot
+    pending: u32,
+}
+
+struct TimerCell(UnsafeCell<TimerTable>);
+unsafe impl Sync for TimerCell {} // Only touched with interrupts off
+
+static TABLE: TimerCell = TimerCell(UnsafeCell::new(TimerTable {
+    timers: [None; MAX_TIMERS],
+    deadlines: [Instant::ZERO; MAX_TIMERS],
+    wheel: TimerWheel::new(),
+    pending: 0,
+}));
+
+/// The service task is parked and needs a wakeup for pending timers
+static PARKED: AtomicBool = AtomicBool::new(false);
+
+/// Wait event id, allocated by `init`
+static EVENT: AtomicU32 = AtomicU32::new(0);
+
+/// Run `f` on the table with interrupts off, restoring their state
+fn with_table<R>(f: impl FnOnce(&mut TimerTable) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *TABLE.0.get() }))
+}
+
+/// Earliest deadline plus slack of the running timers, for a dynamic tick
+pub fn next_deadline() -> Option<Instant> {
+    with_table(|table| {
+        (0..MAX_TIMERS)
+            .filter(|&slot| table.wheel.is_armed(slot))
+            .filter_map(|slot| table.timers[slot].map(|timer| table.deadlines[slot] + timer.slack))
+            .min()
+    })
+}
+
+/// Expire the timers due at `now`; called by `time::announce`
+///
+/// Fast callbacks run here, after the table is released, so they may
+/// restart or stop timers themselves.
+pub fn on_tick(now: Instant) {
+    let mut fast: Vec<&'static Timer, MAX_TIMERS> = Vec::new();
+    let deferred = with_table(|table| {
+        let mut expired: Vec<usize, MAX_TIMERS> = Vec::new();
+        table.wheel.advance(now, |slot| {
+            let _ = expired.push(slot);
+        });
+        let mut deferred = false;
+        for slot in expired {
+            let Some(timer) = table.timers[slot] else {
+                continue;
+            };
+            let period = Duration::from_ticks(timer.period.load(Ordering::Relaxed) as u64);
+            if !period.is_zero() {
+                let mut next = table.deadlines[slot] + period;
+                if next <= now {
+                    // Time jumped by more than a period; skip what was missed
+                    timer.overruns.fetch_add(1, Ordering::Relaxed);
+                    next = now + period;
+                }
+                table.deadlines[slot] = next;
+                table.wheel.arm(slot, next);
+            }
+            match timer.mode {
+                TimerMode::Fast => {
+                    let _ = fast.push(timer);
+                }
+                TimerMode::Deferred if table.pending & (1 << slot) != 0 => {
+                    timer.overruns.fetch_add(1, Ordering::Relaxed);
+                }
+                TimerMode::Deferred => {
+                    table.pending |= 1 << slot;
+                    deferred = true;
+                }
+            }
+        }
+        deferred
+    });
+    fast.iter().for_each(|timer| timer.run());
+    if deferred && PARKED.swap(false, Ordering::AcqRel) {
+        scheduler::wake_event(EVENT.load(Ordering::Relaxed));
+    }
+}
+
+/// Spawn the timer service task
+pub fn init() -> InitResult {
+    EVENT.store(crate::kernel::alloc_wait_event(), Ordering::Relaxed);
+    let task = Task::with_priority(TASK_ID, PRIORITY).named("timers").entry(|_| run_service());
+    scheduler::add_priority_task(task).map(|_| ()).map_err(|_| "no task slot")
+}
+
+/// One run of the service task: the deferred callbacks due, then park
+pub fn run_service() {
+    let (pending, timers) = with_table(|table| (core::mem::take(&mut table.pending), table.timers));
+    for (slot, timer) in timers.iter().enumerate() {
+        if let (true, Some(timer)) = (pending & (1 << slot) != 0, timer) {
+            timer.run();
+        }
+    }
+
+    PARKED.store(true, Ordering::Release);
+    scheduler::block_current_priority(EVENT.load(Ordering::Relaxed));
+    // A timer may have come due between taking the mask and parking
+    if with_table(|table| table.pending != 0) && PARKED.swap(false, Ordering::AcqRel) {
+        scheduler::wake_event(EVENT.load(Ordering::Relaxed));
+    }
+}
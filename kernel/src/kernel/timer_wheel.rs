@@ -0,0 +1,198 @@
+//! Timer wheel
+//! Deadlines for many timers at O(1) cost per tick
+//!
+//! Timers are numbered `0..N` by their owner (a task slot, say) and hang
+//! in doubly linked lists threaded through a fixed entry table. A timer
+//! due within `NEAR` ticks sits in the near slot for its tick; one due
+//! within `SPAN` ticks sits in the far slot for its `NEAR`-tick block and
+//! moves down when that block starts; anything later waits in an
+//! overflow list that is sorted out once every `SPAN` ticks. Arming and
+//! disarming unlink and link one entry, and a tick visits one near slot
+//! plus, every `NEAR` ticks, one far slot, so the work done per tick is
+//! bounded by the timers that actually move or expire.
+//!
+//! `advance` steps one tick at a time; a jump of more than `SPAN` ticks
+//! (time read after a long idle) settles every timer directly instead.
+
+use super::time::{deadline_reached, Instant};
+
+const NEAR_BITS: u32 = 4;
+const FAR_BITS: u32 = 4;
+
+/// Near slots, one per tick
+const NEAR: u64 = 1 << NEAR_BITS;
+/// Far slots, one per `NEAR` ticks
+const FAR: u64 = 1 << FAR_BITS;
+/// Ticks the near and far slots cover
+const SPAN: u64 = NEAR * FAR;
+
+/// Near slots, then far slots, then the overflow list
+const LISTS: usize = (NEAR + FAR + 1) as usize;
+const OVERFLOW: u8 = (NEAR + FAR) as u8;
+
+/// No entry / on no list
+const NIL: u8 = u8::MAX;
+
+#[derive(Copy, Clone)]
+struct Entry {
+    deadline: u64,
+    prev: u8,
+    next: u8,
+    list: u8,
+}
+
+/// Deadlines for timers `0..N`
+pub struct TimerWheel<const N: usize> {
+    /// Tick the wheel has been advanced to
+    now: u64,
+    heads: [u8; LISTS],
+    entries: [Entry; N],
+}
+
+impl<const N: usize> Default for TimerWheel<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TimerWheel<N> {
+    const FITS: () = assert!(N < NIL as usize, "timer ids must fit the u8 links");
+
+    pub const fn new() -> Self {
+        let () = Self::FITS;
+        Self {
+            now: 0,
+            heads: [NIL; LISTS],
+            entries: [Entry { deadline: 0, prev: NIL, next: NIL, list: NIL }; N],
+        }
+    }
+
+    /// Expire timer `id` at `deadline`, replacing any earlier one
+    ///
+    /// A deadline already reached expires on the next `advance`.
+    pub fn arm(&mut self, id: usize, deadline: Instant) {
+        self.unlink(id);
+        self.entries[id].deadline = deadline.ticks();
+        self.place(id, self.now.wrapping_add(1));
+    }
+
+    /// Cancel timer `id`; nothing happens if it is not armed
+    pub fn disarm(&mut self, id: usize) {
+        self.unlink(id);
+    }
+
+    /// Whether timer `id` is waiting to expire
+    #[allow(dead_code)]
+    pub fn is_armed(&self, id: usize) -> bool {
+        self.entries[id].list != NIL
+    }
+
+    /// Move time on to `now`, calling `expired` with each timer that is due
+    ///
+    /// Expired timers are disarmed before `expired` sees them.
+    pub fn advance(&mut self, now: Instant, mut expired: impl FnMut(usize)) {
+        let now = now.ticks();
+        let ahead = now.wrapping_sub(self.now);
+        if ahead as i64 > SPAN as i64 || (ahead as i64) < 0 {
+            self.settle(now, &mut expired);
+            return;
+        }
+        while self.now != now {
+            self.now = self.now.wrapping_add(1);
+            self.step(&mut expired);
+        }
+    }
+
+    /// One tick: bring later timers closer, then expire this tick's slot
+    fn step(&mut self, expired: &mut impl FnMut(usize)) {
+        let tick = self.now;
+        if tick.is_multiple_of(NEAR) {
+            let block = tick / NEAR;
+            if block.is_multiple_of(FAR) {
+                self.cascade(OVERFLOW);
+            }
+            self.cascade((NEAR + block % FAR) as u8);
+        }
+        let mut id = self.heads[(tick % NEAR) as usize];
+        while id != NIL {
+            let next = self.entries[id as usize].next;
+            self.unlink(id as usize);
+            // Only timers armed in the past land here early; the rest are due now
+            expired(id as usize);
+            id = next;
+        }
+    }
+
+    /// Re-place every timer on `list` against the current tick
+    fn cascade(&mut self, list: u8) {
+        let mut id = self.heads[list as usize];
+        while id != NIL {
+            let next = self.entries[id as usize].next;
+            self.unlink(id as usize);
+            // Due this very tick: the near slot about to be expired
+            self.place(id as usize, self.now);
+            id = next;
+        }
+    }
+
+    /// Jump straight to `now`, expiring what is due and re-placing the rest
+    fn settle(&mut self, now: u64, expired: &mut impl FnMut(usize)) {
+        self.now = now;
+        for id in 0..N {
+            if self.entries[id].list == NIL {
+                continue;
+            }
+            self.unlink(id);
+            if deadline_reached(now, self.entries[id].deadline) {
+                expired(id);
+            } else {
+                self.place(id, now.wrapping_add(1));
+            }
+        }
+    }
+
+    /// Link `id` into the list for its deadline, expiring no sooner than `earliest`
+    fn place(&mut self, id: usize, earliest: u64) {
+        let deadline = self.entries[id].deadline;
+        let at = if deadline_reached(earliest, deadline) { earliest } else { deadline };
+        let delta = at.wrapping_sub(self.now);
+        let list = if delta < NEAR {
+            at % NEAR
+        } else if (at / NEAR).wrapping_sub(self.now / NEAR) < FAR {
+            NEAR + (at / NEAR) % FAR
+        } else {
+            OVERFLOW as u64
+        };
+        self.link(id, list as u8);
+    }
+
+    fn link(&mut self, id: usize, list: u8) {
+        let head = self.heads[list as usize];
+        if head != NIL {
+            self.entries[head as usize].prev = id as u8;
+        }
+        let entry = &mut self.entries[id];
+        entry.prev = NIL;
+        entry.next = head;
+        entry.list = list;
+        self.heads[list as usize] = id as u8;
+    }
+
+    fn unlink(&mut self, id: usize) {
+        let Entry { prev, next, list, .. } = self.entries[id];
+        if list == NIL {
+            return;
+        }
+        match prev {
+            NIL => self.heads[list as usize] = next,
+            prev => self.entries[prev as usize].next = next,
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        }
+        let entry = &mut self.entries[id];
+        entry.prev = NIL;
+        entry.next = NIL;
+        entry.list = NIL;
+    }
+}
@@ -0,0 +1,222 @@
+//! Event trace
+//! Record of the scheduler's inputs, and their replay on the simulator (`trace`)
+//!
+//! Every event posted to the multi-priority executor and every tick the
+//! tick interrupt announces is recorded with two timestamps: the kernel
+//! tick, and the scheduling point, the number of times
+//! `schedule_with_priority` has run. The scheduling point is the trace's
+//! virtual time. Unlike the tick or the cycle counter it depends only on
+//! what the kernel did, so a replay can find the same moment again. Ticks
+//! between two scheduling points share one record.
+//!
+//! The first `TRACE_DEPTH` records are kept in a buffer; later ones are
+//! counted but not kept. The `trace` shell command prints them, one line
+//! each in the format `Record::parse` reads back.
+//!
+//! On the simulator, `KARATOS_SIM_RECORD=<file>` also writes every record
+//! to a file as it is made, without limit, and `KARATOS_SIM_REPLAY=<file>`
+//! feeds a trace back in (see `replay`).
+
+use core::cell::UnsafeCell;
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::scheduler::EventPriority;
+
+#[cfg(feature = "sim")]
+mod replay;
+
+#[cfg(feature = "sim")]
+pub use replay::replaying;
+
+/// Records kept in the buffer
+pub const TRACE_DEPTH: usize = 256;
+
+/// One input to the scheduler
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Scheduling points passed before the input arrived
+    pub at: u32,
+    /// Kernel tick when it arrived
+    pub tick: u64,
+    pub input: Input,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// Ticks announced by the tick interrupt
+    Tick(u32),
+    /// Event posted to the multi-priority executor; not `accepted` when
+    /// its queue was full
+    Post { id: u32, priority: EventPriority, accepted: bool },
+}
+
+impl Record {
+    /// Record from a line written by its `Display`
+    #[allow(dead_code)]
+    pub fn parse(line: &str) -> Option<Record> {
+        let mut fields = line.split_whitespace();
+        let at = fields.next()?.parse().ok()?;
+        let tick = fields.next()?.parse().ok()?;
+        let input = match fields.next()? {
+            "tick" => Input::Tick(fields.next()?.parse().ok()?),
+            "post" => {
+                let id = u32::from_str_radix(fields.next()?.strip_prefix("0x")?, 16).ok()?;
+                let priority = priority_from_name(fields.next()?)?;
+                let accepted = match fields.next()? {
+                    "ok" => true,
+                    "full" => false,
+                    _ => return None,
+                };
+                Input::Post { id, priority, accepted }
+            }
+            _ => return None,
+        };
+        fields.next().is_none().then_some(Record { at, tick, input })
+    }
+
+    /// Fold `next` into this record if both are ticks at the same point
+    fn merge(&mut self, next: &Record) -> bool {
+        match (&mut self.input, next.input) {
+            (Input::Tick(count), Input::Tick(more)) if self.at == next.at => {
+                *count += more;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// `12 3040 tick 5`, `12 3045 post 0x10 critical ok`
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.at, self.tick)?;
+        match self.input {
+            Input::Tick(count) => write!(f, "tick {}", count),
+            Input::Post { id, priority, accepted } => {
+                write!(f, "post {:#x} {} {}", id, priority_name(priority), if accepted { "ok" } else { "full" })
+            }
+        }
+    }
+}
+
+const PRIORITIES: [(EventPriority, &str); 4] = [
+    (EventPriority::Critical, "critical"),
+    (EventPriority::High, "high"),
+    (EventPriority::Normal, "normal"),
+    (EventPriority::Low, "low"),
+];
+
+fn priority_name(priority: EventPriority) -> &'static str {
+    PRIORITIES.iter().find(|(p, _)| *p == priority).map_or("?", |(_, name)| name)
+}
+
+#[allow(dead_code)]
+fn priority_from_name(name: &str) -> Option<EventPriority> {
+    PRIORITIES.iter().find(|(_, n)| *n == name).map(|(priority, _)| *priority)
+}
+
+struct Trace {
+    records: Vec<Record, TRACE_DEPTH>,
+    /// Tick record still collecting ticks at the current point
+    pending: Option<Record>,
+    dropped: u32,
+}
+
+impl Trace {
+    fn keep(&mut self, record: Record) {
+        if self.records.push(record).is_err() {
+            self.dropped += 1;
+        }
+    }
+}
+
+struct TraceCell(UnsafeCell<Trace>);
+unsafe impl Sync for TraceCell {} // Only touched with interrupts off
+
+static TRACE: TraceCell = TraceCell(UnsafeCell::new(Trace { records: Vec::new(), pending: None, dropped: 0 }));
+
+/// Scheduling points passed
+static POINT: AtomicU32 = AtomicU32::new(0);
+
+fn with_trace<R>(f: impl FnOnce(&mut Trace) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *TRACE.0.get() }))
+}
+
+fn record(input: Input) {
+    let record = Record { at: POINT.load(Ordering::Relaxed), tick: super::time::now(), input };
+    let committed = with_trace(|trace| {
+        if trace.pending.as_mut().is_some_and(|pending| pending.merge(&record)) {
+            return None;
+        }
+        // Ticks are kept once their point has passed, ahead of what ended them
+        let committed = trace.pending.take();
+        if let Some(ticks) = committed {
+            trace.keep(ticks);
+        }
+        match input {
+            Input::Tick(_) => trace.pending = Some(record),
+            Input::Post { .. } => trace.keep(record),
+        }
+        committed
+    });
+    #[cfg(feature = "sim")]
+    {
+        if let Some(ticks) = committed {
+            replay::write(&ticks);
+        }
+        if let Input::Post { .. } = input {
+            replay::write(&record);
+        }
+    }
+    #[cfg(not(feature = "sim"))]
+    let _ = committed;
+}
+
+/// Record `hw_ticks` ticks; called by the tick interrupt's `announce`
+pub fn ticked(hw_ticks: u32) {
+    if hw_ticks > 0 {
+        record(Input::Tick(hw_ticks));
+    }
+}
+
+/// Record a post to the multi-priority executor, and check it against the
+/// trace when replaying
+pub fn posted(id: u32, priority: EventPriority, accepted: bool) {
+    #[cfg(feature = "sim")]
+    replay::check_post(POINT.load(Ordering::Relaxed), id, priority);
+    record(Input::Post { id, priority, accepted });
+}
+
+/// Pass a scheduling point; called by `schedule_with_priority` before it
+/// picks a task. A replay feeds in the inputs that arrived before it.
+pub fn schedule_point() {
+    let point = POINT.load(Ordering::Relaxed);
+    #[cfg(feature = "sim")]
+    replay::inject(point);
+    POINT.store(point.wrapping_add(1), Ordering::Relaxed);
+}
+
+/// Scheduling points passed so far
+#[allow(dead_code)]
+pub fn point() -> u32 {
+    POINT.load(Ordering::Relaxed)
+}
+
+/// Pass each kept record to `f`, oldest first; returns the count of
+/// records that did not fit
+///
+/// Records are read one at a time, so `f` runs with interrupts on.
+#[allow(dead_code)]
+pub fn for_each(mut f: impl FnMut(&Record)) -> u32 {
+    let mut index = 0;
+    while let Some(record) = with_trace(|trace| trace.records.get(index).copied()) {
+        f(&record);
+        index += 1;
+    }
+    let (pending, dropped) = with_trace(|trace| (trace.pending, trace.dropped));
+    pending.iter().for_each(f);
+    dropped
+}
@@ -0,0 +1,196 @@
+//! Trace files on the simulator
+//! Recording to `KARATOS_SIM_RECORD` and replay from `KARATOS_SIM_REPLAY`
+//!
+//! Recording writes each record to the file as a line as soon as it is
+//! made, so a run stopped with Ctrl-C keeps its trace, short of the ticks
+//! still collecting at the last scheduling point.
+//!
+//! A replay takes the tick and the console away from the host: the tick
+//! thread and the stdin reader are not started, and a core that goes to
+//! sleep wakes at once. Instead, at each scheduling point, every input
+//! the trace has up to that point is fed in: ticks announced as the tick
+//! interrupt would, events posted as an interrupt handler would. Posts
+//! the tasks make themselves happen again during the replay; each one is
+//! matched against the trace at its point and not fed in a second time,
+//! and one the trace does not have is reported as a divergence.
+//!
+//! Inputs land at the scheduling point after they arrived rather than
+//! part way through a task, which is as finely as the scheduler can tell
+//! them apart. Console input is not recorded, so a replay has none. Once
+//! the trace is used up the run stops: exit status 0 if it went as
+//! recorded, 1 if it diverged, 2 if a trace file could not be used.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use super::{Input, Record};
+use crate::scheduler::EventPriority;
+
+/// Exit status when a trace file cannot be read or written
+const EXIT_UNUSABLE: u32 = 2;
+
+/// Divergences reported one by one; later ones are only counted
+const REPORTED_DIVERGENCES: u32 = 10;
+
+struct Replay {
+    records: Vec<Record>,
+    /// Fed in, or matched by a post made during the replay
+    done: Vec<bool>,
+    /// First record not yet fed in
+    next: usize,
+    injected: u32,
+    matched: u32,
+    diverged: u32,
+}
+
+impl Replay {
+    /// Mark the records up to `point` done, returning those not yet matched
+    fn take_due(&mut self, point: u32) -> Vec<Input> {
+        let mut due = Vec::new();
+        while let Some(record) = self.records.get(self.next).filter(|record| record.at <= point) {
+            if !self.done[self.next] {
+                due.push(record.input);
+                self.done[self.next] = true;
+                self.injected += 1;
+            }
+            self.next += 1;
+        }
+        due
+    }
+
+    /// Whether the trace is used up once `point` has passed
+    fn finished(&self, point: u32) -> bool {
+        self.next == self.records.len() && self.records.last().is_none_or(|last| point > last.at)
+    }
+}
+
+static REPLAY: OnceLock<Option<Mutex<Replay>>> = OnceLock::new();
+static RECORDER: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+/// Set while `inject` posts, so its posts are not matched against the trace
+static INJECTING: AtomicBool = AtomicBool::new(false);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn unusable(message: std::fmt::Arguments) -> ! {
+    crate::arch::early_println(&format!("trace: {}", message));
+    crate::arch::qemu_exit(EXIT_UNUSABLE)
+}
+
+fn replay() -> Option<&'static Mutex<Replay>> {
+    REPLAY
+        .get_or_init(|| {
+            let path = std::env::var_os("KARATOS_SIM_REPLAY")?;
+            let file = File::open(&path).unwrap_or_else(|error| unusable(format_args!("{}: {}", path.to_string_lossy(), error)));
+            let mut records = Vec::new();
+            for (number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.unwrap_or_else(|error| unusable(format_args!("{}: {}", path.to_string_lossy(), error)));
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let record = Record::parse(&line)
+                    .filter(|record| records.last().is_none_or(|last: &Record| last.at <= record.at))
+                    .unwrap_or_else(|| unusable(format_args!("{}:{}: not a record in order: {}", path.to_string_lossy(), number + 1, line)));
+                records.push(record);
+            }
+            let done = vec![false; records.len()];
+            Some(Mutex::new(Replay { records, done, next: 0, injected: 0, matched: 0, diverged: 0 }))
+        })
+        .as_ref()
+}
+
+fn recorder() -> Option<&'static Mutex<File>> {
+    RECORDER
+        .get_or_init(|| {
+            let path = std::env::var_os("KARATOS_SIM_RECORD")?;
+            let file = File::create(&path).unwrap_or_else(|error| unusable(format_args!("{}: {}", path.to_string_lossy(), error)));
+            Some(Mutex::new(file))
+        })
+        .as_ref()
+}
+
+/// Whether this run replays a trace instead of taking the host's inputs
+pub fn replaying() -> bool {
+    replay().is_some()
+}
+
+/// Append `record` to the recording, if there is one
+pub fn write(record: &Record) {
+    if let Some(file) = recorder() {
+        let _ = writeln!(lock(file), "{}", record);
+    }
+}
+
+/// Feed in the trace's inputs up to `point`, and stop once it is used up
+pub fn inject(point: u32) {
+    let Some(replay) = replay() else {
+        return;
+    };
+    let due = lock(replay).take_due(point);
+
+    INJECTING.store(true, Ordering::Relaxed);
+    for input in due {
+        match input {
+            Input::Tick(count) => (0..count).for_each(|_| as_interrupt(|| crate::kernel::time::announce(1))),
+            Input::Post { id, priority, .. } => {
+                let _ = crate::scheduler::post_priority_event(id, priority);
+            }
+        }
+    }
+    INJECTING.store(false, Ordering::Relaxed);
+
+    let replay = lock(replay);
+    if replay.finished(point) {
+        crate::arch::early_println(&format!(
+            "trace: replayed {} records, {} fed in, {} posts matched, {} diverged",
+            replay.records.len(),
+            replay.injected,
+            replay.matched,
+            replay.diverged
+        ));
+        crate::arch::qemu_exit(if replay.diverged == 0 { 0 } else { 1 });
+    }
+}
+
+/// Match a post made during the replay against the trace at `point`
+pub fn check_post(point: u32, id: u32, priority: EventPriority) {
+    let Some(replay) = replay() else {
+        return;
+    };
+    if INJECTING.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut replay = lock(replay);
+    let found = (replay.next..replay.records.len())
+        .take_while(|&index| replay.records[index].at <= point)
+        .find(|&index| {
+            !replay.done[index]
+                && matches!(replay.records[index].input, Input::Post { id: recorded, priority: level, .. } if recorded == id && level == priority)
+        });
+    match found {
+        Some(index) => {
+            replay.done[index] = true;
+            replay.matched += 1;
+        }
+        None => {
+            replay.diverged += 1;
+            if replay.diverged <= REPORTED_DIVERGENCES {
+                crate::arch::early_println(&format!(
+                    "trace: diverged at point {}: post {:#x} {} is not in the trace",
+                    point,
+                    id,
+                    super::priority_name(priority)
+                ));
+            }
+        }
+    }
+}
+
+/// Run `f` with interrupts off, as the interrupt it stands in for would
+fn as_interrupt(f: impl FnOnce()) {
+    crate::arch::free(|| crate::instrumentation::measure_isr(f));
+}
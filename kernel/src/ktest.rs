@@ -0,0 +1,193 @@
+//! Test kernel
+//! On-target test cases with TAP output and a pass/fail exit (`ktest`)
+//!
+//! A `ktest` build runs the cases in `TEST_TABLE` after `kernel::init`
+//! instead of the demo tasks. Results are TAP version 13: a `1..N` plan,
+//! then `ok N - name`, or `not ok N - name` followed by a YAML block with
+//! the failed check and where it was made. The run then exits with 0 when
+//! every case passed, 1 otherwise, so it gives a verdict without anyone
+//! reading the log.
+//!
+//! Where the results go and how the run exits is up to the backend:
+//!
+//! - `Console` (default): TAP on the console, then `arch::qemu_exit`. QEMU
+//!   leaves through the SiFive test device on riscv and semihosting on
+//!   ARM, the simulator exits the process, and real boards halt.
+//! - `Semihosting` (`ktest_semihosting`, ARM only): TAP on the debug
+//!   host's console and the verdict through semihosting SYS_EXIT, on QEMU
+//!   and on real boards alike. With a probe attached (probe-rs, OpenOCD
+//!   with semihosting on) the runner gets the results and the exit code
+//!   without scraping a UART. Without one, the first line locks the board
+//!   up, so only flash such a build to a board under a debugger.
+//!
+//! Cases run one after another on the boot stack, with interrupts on and
+//! the tick running but no scheduler, so they must not block. A case that
+//! hangs never reports; the runner's timeout catches that.
+
+use core::fmt::{self, Write};
+
+#[cfg(all(feature = "ktest_semihosting", not(feature = "arm")))]
+compile_error!("`ktest_semihosting` needs an ARM target; riscv and sim report on the console");
+
+/// Outcome of one case; the error is the check that failed
+pub type TestResult = Result<(), &'static str>;
+
+/// Exit code when every case passed
+pub const EXIT_PASS: u32 = 0;
+
+/// Exit code when any case failed
+pub const EXIT_FAIL: u32 = 1;
+
+/// One registered test case
+pub struct TestCase {
+    pub name: &'static str,
+    pub func: fn() -> TestResult,
+}
+
+/// Fail the enclosing case unless `$cond` holds
+///
+/// The failure names the condition and where it was checked.
+#[macro_export]
+macro_rules! kcheck {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            return Err(concat!(file!(), ":", line!(), ": ", stringify!($cond)));
+        }
+    };
+}
+
+macro_rules! test_table {
+    ($($(#[$attr:meta])* $name:literal => $func:path;)*) => {
+        static TEST_TABLE: &[TestCase] = &[
+            $($(#[$attr])* TestCase { name: $name, func: $func },)*
+        ];
+    };
+}
+
+test_table! {
+    "time: duration units" => crate::kernel::ktest_cases::duration_units;
+    "time: deadline across wrap" => crate::kernel::ktest_cases::deadline_wrap;
+    "time: tick advances" => crate::kernel::ktest_cases::tick_advances;
+    "timer wheel: expiry order" => crate::kernel::ktest_cases::timer_wheel_expiry;
+    "timer wheel: disarm" => crate::kernel::ktest_cases::timer_wheel_disarm;
+    "pipe: bounded round trip" => crate::kernel::ktest_cases::pipe_round_trip;
+    "channel: fifo and full" => crate::kernel::ktest_cases::channel_fifo;
+    "handle: stale after retire" => crate::kernel::ktest_cases::handle_generations;
+    "syscall: argument validation" => crate::kernel::ktest_cases::syscall_validation;
+    "syscall: queue round trip" => crate::kernel::ktest_cases::syscall_queue;
+    "syscall: sleep until deadline" => crate::kernel::ktest_cases::syscall_sleep;
+    "syscall: sleep through the trap" => crate::kernel::ktest_cases::syscall_trap_sleep;
+    #[cfg(target_arch = "arm")]
+    "arm: PendSV round trip" => crate::kernel::ktest_cases::pendsv_round_trip;
+    #[cfg(feature = "bench")]
+    "bench: kernel primitives" => crate::kernel::ktest_cases::bench_suite;
+}
+
+/// Where results go and how the run ends
+trait Report {
+    /// Write one line of results
+    fn line(text: &str);
+
+    /// End the run with exit code `code`
+    fn exit(code: u32) -> !;
+}
+
+/// TAP on the console, verdict through `arch::qemu_exit`
+#[cfg(not(feature = "ktest_semihosting"))]
+struct Console;
+
+#[cfg(not(feature = "ktest_semihosting"))]
+impl Report for Console {
+    fn line(text: &str) {
+        crate::arch::early_println(text);
+    }
+
+    fn exit(code: u32) -> ! {
+        crate::arch::qemu_exit(code)
+    }
+}
+
+/// TAP on the debug host's console, verdict through semihosting SYS_EXIT
+#[cfg(feature = "ktest_semihosting")]
+struct Semihosting;
+
+#[cfg(feature = "ktest_semihosting")]
+impl Report for Semihosting {
+    fn line(text: &str) {
+        cortex_m_semihosting::hprintln!("{}", text);
+    }
+
+    fn exit(code: u32) -> ! {
+        crate::arch::disable_interrupts();
+        crate::arch::arm::semihosting_exit(code)
+    }
+}
+
+#[cfg(not(feature = "ktest_semihosting"))]
+type Backend = Console;
+#[cfg(feature = "ktest_semihosting")]
+type Backend = Semihosting;
+
+/// Run every case, report them as TAP and exit with the verdict
+pub fn run() -> ! {
+    crate::arch::enable_interrupts();
+
+    line(format_args!("TAP version 13"));
+    line(format_args!("1..{}", TEST_TABLE.len()));
+    let mut failed = 0;
+    for (index, case) in TEST_TABLE.iter().enumerate() {
+        match (case.func)() {
+            Ok(()) => line(format_args!("ok {} - {}", index + 1, case.name)),
+            Err(check) => {
+                failed += 1;
+                line(format_args!("not ok {} - {}", index + 1, case.name));
+                failure(check);
+            }
+        }
+    }
+    line(format_args!("# {} passed, {} failed", TEST_TABLE.len() - failed, failed));
+
+    Backend::exit(if failed == 0 { EXIT_PASS } else { EXIT_FAIL })
+}
+
+/// YAML block under a `not ok` line: the check, and where `kcheck!` made it
+fn failure(check: &str) {
+    line(format_args!("  ---"));
+    // `kcheck!` failures read `file:line: condition`; others are a bare reason
+    match check.split_once(": ") {
+        Some((at, message)) => {
+            line(format_args!("  message: {}", Quoted(message)));
+            line(format_args!("  at: {}", Quoted(at)));
+        }
+        None => line(format_args!("  message: {}", Quoted(check))),
+    }
+    line(format_args!("  ..."));
+}
+
+/// `text` as a YAML single-quoted scalar
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('\'')?;
+        for (index, part) in self.0.split('\'').enumerate() {
+            if index > 0 {
+                f.write_str("''")?;
+            }
+            f.write_str(part)?;
+        }
+        f.write_char('\'')
+    }
+}
+
+/// Print a TAP diagnostic (`# ...`) under the running case
+#[allow(dead_code)]
+pub fn diag(args: fmt::Arguments) {
+    line(format_args!("# {}", args));
+}
+
+fn line(args: fmt::Arguments) {
+    let mut text = heapless::String::<128>::new();
+    let _ = text.write_fmt(args);
+    Backend::line(&text);
+}
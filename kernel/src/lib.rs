@@ -1,12 +1,48 @@
 //! karatOS Kernel Library
 //! Multi-architecture RTOS kernel for ARM and RISC-V platforms
 
-#![no_std]
-#![no_main]
+#![cfg_attr(not(any(feature = "sim", test)), no_std)]
+#![cfg_attr(not(any(feature = "sim", test)), no_main)]
 
 // Core modules
+pub mod accounting;
 pub mod arch;
+pub mod banner;
+pub mod board;
 pub mod config;
+pub mod coverage;
+pub mod crashdump;
+#[cfg(feature = "critical_section_impl")]
+pub mod critical_section_impl;
+#[cfg(feature = "log_defmt")]
+pub mod defmt_log;
 pub mod drivers;
+pub mod events;
+#[cfg(feature = "async_tasks")]
+pub mod executor;
+#[cfg(feature = "gdb_stub")]
+pub mod gdb;
+pub mod instrumentation;
+pub mod introspect;
+pub mod kassert;
 pub mod kernel;
-pub mod memory;
\ No newline at end of file
+#[cfg(feature = "ktest")]
+pub mod ktest;
+#[cfg(feature = "log_binary")]
+#[allow(dead_code)]
+pub mod log_binary;
+#[cfg(feature = "log_drain")]
+pub mod log_drain;
+#[allow(dead_code)]
+pub mod logger;
+pub mod memory;
+pub mod metrics;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod panic;
+pub mod power;
+pub mod scheduler;
+pub mod shell;
+pub mod supervisor;
+pub mod syscall;
+pub mod tasklet;
\ No newline at end of file
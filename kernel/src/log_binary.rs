@@ -0,0 +1,414 @@
+//! Binary log records
+//! Compact log entries with unformatted arguments (`log_binary`)
+//!
+//! `log_record!` stores a record in a byte ring instead of a formatted
+//! 64-byte text line. The record holds the level, the target, the tick,
+//! the format string's flash address as its id, and the raw arguments.
+//! A typical record takes 15 to 25 bytes. The format string itself stays
+//! in flash, so nothing is formatted until the record is read. The
+//! shell's `log records` renders records on the target. Host tools fetch
+//! them with the host protocol's records request. They resolve the id
+//! from the ELF's `.rodata`, or read the string with peek requests.
+//!
+//! A record is laid out as follows (little-endian):
+//!
+//! - `len` u8: whole record, header included
+//! - `level` u8 and `target` u8: `logger::Level` and `logger::Target`
+//! - `ticks` u32: low half of `kernel::time::now()`
+//! - `format` u32 and `format_len` u8: address and length of the string
+//! - arguments, each a tag byte and its value: `TAG_UNSIGNED` u32,
+//!   `TAG_SIGNED` i32, `TAG_U64` u64, `TAG_I64` i64, `TAG_BOOL` u8,
+//!   `TAG_CHAR` u32, `TAG_STR` length u8 and up to `MAX_STR` bytes
+//!
+//! Arguments that do not fit in `MAX_RECORD` are dropped along with
+//! all after them; zero bytes fill the record out, and a zero tag ends
+//! it. When the ring is full, the oldest whole records make room.
+//! Without the feature, `log_record!` formats a text line like `log_at!`.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+
+use heapless::Vec;
+
+use crate::logger::{Level, Target};
+
+/// Ring size, as for the defmt ring
+const RING_BYTES: usize = if crate::config::LOG_LINES <= 16 { 256 } else { 1024 };
+
+/// Longest record; one always fits in a host protocol reply
+pub const MAX_RECORD: usize = 96;
+
+const HEADER: usize = 12;
+
+/// Longest string argument kept
+const MAX_STR: usize = 32;
+
+pub const TAG_UNSIGNED: u8 = 1;
+pub const TAG_SIGNED: u8 = 2;
+pub const TAG_U64: u8 = 3;
+pub const TAG_I64: u8 = 4;
+pub const TAG_BOOL: u8 = 5;
+pub const TAG_CHAR: u8 = 6;
+pub const TAG_STR: u8 = 7;
+
+/// Encoded arguments of one record
+pub type Args = Vec<u8, { MAX_RECORD - HEADER }>;
+
+/// An argument `log_record!` can store without formatting it
+pub trait Arg {
+    fn encode(&self, args: &mut Args);
+}
+
+/// Append one tagged value, or close `args` if it does not fit
+///
+/// A smaller argument after a dropped one must not take its place, so
+/// the rest is filled with zeros: tag 0 ends decoding, and a full `args`
+/// takes nothing more.
+fn push(args: &mut Args, tag: u8, value: &[u8]) {
+    if args.len() + 1 + value.len() <= args.capacity() {
+        let _ = args.push(tag);
+        let _ = args.extend_from_slice(value);
+    } else {
+        let _ = args.resize(args.capacity(), 0);
+    }
+}
+
+impl Arg for u64 {
+    fn encode(&self, args: &mut Args) {
+        match u32::try_from(*self) {
+            Ok(value) => push(args, TAG_UNSIGNED, &value.to_le_bytes()),
+            Err(_) => push(args, TAG_U64, &self.to_le_bytes()),
+        }
+    }
+}
+
+impl Arg for i64 {
+    fn encode(&self, args: &mut Args) {
+        match i32::try_from(*self) {
+            Ok(value) => push(args, TAG_SIGNED, &value.to_le_bytes()),
+            Err(_) => push(args, TAG_I64, &self.to_le_bytes()),
+        }
+    }
+}
+
+macro_rules! widened_arg {
+    ($wide:ty: $($narrow:ty),*) => {
+        $(
+            impl Arg for $narrow {
+                fn encode(&self, args: &mut Args) {
+                    (*self as $wide).encode(args);
+                }
+            }
+        )*
+    };
+}
+
+widened_arg!(u64: u8, u16, u32, usize);
+widened_arg!(i64: i8, i16, i32, isize);
+
+impl Arg for bool {
+    fn encode(&self, args: &mut Args) {
+        push(args, TAG_BOOL, &[*self as u8]);
+    }
+}
+
+impl Arg for char {
+    fn encode(&self, args: &mut Args) {
+        push(args, TAG_CHAR, &(*self as u32).to_le_bytes());
+    }
+}
+
+impl Arg for str {
+    fn encode(&self, args: &mut Args) {
+        let mut len = self.len().min(MAX_STR);
+        while !self.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut value = Vec::<u8, { MAX_STR + 1 }>::new();
+        let _ = value.push(len as u8);
+        let _ = value.extend_from_slice(&self.as_bytes()[..len]);
+        push(args, TAG_STR, &value);
+    }
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, args: &mut Args) {
+        (**self).encode(args);
+    }
+}
+
+struct Ring {
+    bytes: [u8; RING_BYTES],
+    /// Bytes ever written; the newest is at `(written - 1) % RING_BYTES`
+    written: u32,
+    /// Stream offset of the oldest whole record
+    oldest: u32,
+}
+
+impl Ring {
+    fn at(&self, offset: u32) -> u8 {
+        self.bytes[offset as usize % RING_BYTES]
+    }
+}
+
+struct RecordRing(UnsafeCell<Ring>);
+unsafe impl Sync for RecordRing {} // Only touched with interrupts off
+
+static RING: RecordRing = RecordRing(UnsafeCell::new(Ring { bytes: [0; RING_BYTES], written: 0, oldest: 0 }));
+
+fn with_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *RING.0.get() }))
+}
+
+/// Store one record; called by `log_record!`
+pub fn record(level: Level, target: Target, format: &'static str, args: &Args) {
+    let ticks = crate::kernel::time::now() as u32;
+    let format_len = format.len().min(u8::MAX as usize) as u8;
+    let mut header = [0u8; HEADER];
+    header[0] = (HEADER + args.len()) as u8;
+    header[1] = level as u8;
+    header[2] = target as u8;
+    header[3..7].copy_from_slice(&ticks.to_le_bytes());
+    header[7..11].copy_from_slice(&(format.as_ptr() as usize as u32).to_le_bytes());
+    header[11] = format_len;
+
+    with_ring(|ring| {
+        let len = (HEADER + args.len()) as u32;
+        // Drop the oldest records until this one fits
+        while ring.written - ring.oldest + len > RING_BYTES as u32 {
+            ring.oldest += ring.at(ring.oldest) as u32;
+        }
+        for &byte in header.iter().chain(args.iter()) {
+            ring.bytes[ring.written as usize % RING_BYTES] = byte;
+            ring.written += 1;
+        }
+    });
+}
+
+/// Visit whole records from stream offset `first` on, oldest first
+///
+/// Offsets count every byte written since boot; records older than the
+/// ring start at a later offset than asked for. `f` gets each record's
+/// offset and bytes and returns false to stop.
+#[allow(dead_code)]
+pub fn visit_from(first: u32, mut f: impl FnMut(u32, &[u8]) -> bool) {
+    let mut offset = with_ring(|ring| ring.oldest);
+    loop {
+        let mut bytes = [0u8; MAX_RECORD];
+        // Copied out one at a time, so loggers are not held off for long
+        let len = with_ring(|ring| {
+            // Overwritten while the previous record was visited
+            offset = offset.max(ring.oldest);
+            if offset >= ring.written {
+                return 0;
+            }
+            let len = ring.at(offset) as usize;
+            for (i, byte) in bytes[..len].iter_mut().enumerate() {
+                *byte = ring.at(offset + i as u32);
+            }
+            len
+        });
+        if len == 0 {
+            return;
+        }
+        if offset >= first && !f(offset, &bytes[..len]) {
+            return;
+        }
+        offset += len as u32;
+    }
+}
+
+/// Stored record, decoded for rendering
+#[allow(dead_code)]
+pub struct Record<'a> {
+    pub level: Level,
+    pub target: Target,
+    pub ticks: u32,
+    pub format: &'static str,
+    args: &'a [u8],
+}
+
+#[allow(dead_code)]
+impl<'a> Record<'a> {
+    /// Decode the bytes `visit_from` passes on
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER {
+            return None;
+        }
+        let level = Level::ALL.into_iter().find(|level| *level as u8 == bytes[1])?;
+        let target = Target::ALL.into_iter().find(|target| *target as u8 == bytes[2])?;
+        let address = u32::from_le_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]) as usize;
+        // The address came from a `&'static str` of this image
+        let format = unsafe { core::slice::from_raw_parts(address as *const u8, bytes[11] as usize) };
+        let format = match core::str::from_utf8(format) {
+            Ok(format) => format,
+            // Cut at `u8::MAX` bytes, possibly inside a character
+            Err(error) => unsafe { core::str::from_utf8_unchecked(&format[..error.valid_up_to()]) },
+        };
+        Some(Self {
+            level,
+            target,
+            ticks: u32::from_le_bytes([bytes[3], bytes[4], bytes[5], bytes[6]]),
+            format,
+            args: &bytes[HEADER..],
+        })
+    }
+}
+
+/// One decoded argument
+#[derive(Copy, Clone)]
+enum Value<'a> {
+    Unsigned(u64),
+    Signed(i64),
+    Bool(bool),
+    Char(char),
+    Str(&'a str),
+}
+
+/// Split the next argument off `args`
+fn next_value<'a>(args: &mut &'a [u8]) -> Option<Value<'a>> {
+    let (&tag, rest) = args.split_first()?;
+    let take = |rest: &'a [u8], n: usize| (rest.len() >= n).then(|| rest.split_at(n));
+    let mut wide = [0u8; 8];
+    let (value, rest) = match tag {
+        TAG_UNSIGNED | TAG_SIGNED | TAG_CHAR => {
+            let (bytes, rest) = take(rest, 4)?;
+            let raw = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let value = match tag {
+                TAG_UNSIGNED => Value::Unsigned(raw as u64),
+                TAG_SIGNED => Value::Signed(raw as i32 as i64),
+                _ => Value::Char(char::from_u32(raw).unwrap_or(char::REPLACEMENT_CHARACTER)),
+            };
+            (value, rest)
+        }
+        TAG_U64 | TAG_I64 => {
+            let (bytes, rest) = take(rest, 8)?;
+            wide.copy_from_slice(bytes);
+            let value = match tag {
+                TAG_U64 => Value::Unsigned(u64::from_le_bytes(wide)),
+                _ => Value::Signed(i64::from_le_bytes(wide)),
+            };
+            (value, rest)
+        }
+        TAG_BOOL => {
+            let (bytes, rest) = take(rest, 1)?;
+            (Value::Bool(bytes[0] != 0), rest)
+        }
+        TAG_STR => {
+            let (&len, rest) = rest.split_first()?;
+            let (bytes, rest) = take(rest, len as usize)?;
+            (Value::Str(core::str::from_utf8(bytes).ok()?), rest)
+        }
+        _ => return None,
+    };
+    *args = rest;
+    Some(value)
+}
+
+/// Format spec of one `{...}`: `#`, `0`, width and `x`, `X`, `b` or `?`
+#[derive(Default)]
+struct Spec {
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    kind: Option<char>,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Self {
+        let mut parsed = Spec::default();
+        let mut rest = spec.strip_prefix(':').unwrap_or("");
+        if let Some(stripped) = rest.strip_prefix('#') {
+            parsed.alternate = true;
+            rest = stripped;
+        }
+        if let Some(stripped) = rest.strip_prefix('0') {
+            parsed.zero = true;
+            rest = stripped;
+        }
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        parsed.width = rest[..digits].parse().unwrap_or(0);
+        parsed.kind = rest[digits..].chars().next();
+        parsed
+    }
+}
+
+/// Write `value` as `spec` asks, padded to its width
+fn write_value(out: &mut dyn Write, value: &Value, spec: &Spec) -> fmt::Result {
+    let mut text = heapless::String::<72>::new();
+    let (sign, magnitude) = match *value {
+        Value::Unsigned(value) => ("", Some(value)),
+        Value::Signed(value) if value < 0 => ("-", Some(value.unsigned_abs())),
+        Value::Signed(value) => ("", Some(value as u64)),
+        _ => ("", None),
+    };
+    let prefix = match (magnitude, spec.kind, spec.alternate) {
+        (Some(_), Some('x' | 'X'), true) => "0x",
+        (Some(_), Some('b'), true) => "0b",
+        _ => "",
+    };
+    let _ = match (magnitude, *value) {
+        (Some(magnitude), _) => match spec.kind {
+            Some('x') => write!(text, "{:x}", magnitude),
+            Some('X') => write!(text, "{:X}", magnitude),
+            Some('b') => write!(text, "{:b}", magnitude),
+            _ => write!(text, "{}", magnitude),
+        },
+        (None, Value::Bool(value)) => write!(text, "{}", value),
+        (None, Value::Char(value)) if spec.kind == Some('?') => write!(text, "{:?}", value),
+        (None, Value::Char(value)) => write!(text, "{}", value),
+        (None, Value::Str(value)) if spec.kind == Some('?') => write!(text, "{:?}", value),
+        (None, Value::Str(value)) => write!(text, "{}", value),
+        _ => Ok(()),
+    };
+
+    let padding = spec.width.saturating_sub(sign.len() + prefix.len() + text.chars().count());
+    if spec.zero && magnitude.is_some() {
+        write!(out, "{}{}", sign, prefix)?;
+        (0..padding).try_for_each(|_| out.write_char('0'))?;
+        out.write_str(&text)
+    } else if magnitude.is_some() {
+        (0..padding).try_for_each(|_| out.write_char(' '))?;
+        write!(out, "{}{}{}", sign, prefix, text)
+    } else {
+        out.write_str(&text)?;
+        (0..padding).try_for_each(|_| out.write_char(' '))
+    }
+}
+
+impl fmt::Display for Record<'_> {
+    /// The message as the text logger would have stored it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.level.prefix())?;
+        if self.target != Target::Kernel {
+            write!(f, "[{}] ", self.target)?;
+        }
+        let mut args = self.args;
+        let mut rest = self.format;
+        while let Some(index) = rest.find(['{', '}']) {
+            f.write_str(&rest[..index])?;
+            let (brace, after) = (&rest[index..index + 1], &rest[index + 1..]);
+            if after.starts_with(brace) {
+                // `{{` or `}}`
+                f.write_str(brace)?;
+                rest = &after[1..];
+                continue;
+            }
+            if brace == "}" {
+                rest = after;
+                continue;
+            }
+            let end = after.find('}').unwrap_or(after.len());
+            match next_value(&mut args) {
+                Some(value) => write_value(f, &value, &Spec::parse(&after[..end]))?,
+                // Dropped for lack of room
+                None => f.write_str("{?}")?,
+            }
+            rest = after.get(end + 1..).unwrap_or("");
+        }
+        f.write_str(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+//! Host tests for binary log records
+//! Arguments encoded and rendered back, checked against `format!`, and
+//! records stored through the ring
+//!
+//! A record keeps only the low 32 bits of its format string's address,
+//! which on a 64-bit host is not enough to find it again, so rendering
+//! builds the `Record` around the string directly.
+
+use super::{record, visit_from, Arg, Args, Record, HEADER, MAX_RECORD, MAX_STR, RING_BYTES};
+use crate::logger::{Level, Target};
+
+fn encode(values: &[&dyn Arg]) -> Args {
+    let mut args = Args::new();
+    values.iter().for_each(|value| value.encode(&mut args));
+    args
+}
+
+fn render(level: Level, target: Target, format: &'static str, args: &Args) -> String {
+    Record { level, target, ticks: 0, format, args }.to_string()
+}
+
+#[test]
+fn arguments_round_trip() {
+    let args = encode(&[&7u8, &-3i16, &u32::MAX, &i32::MIN, &(1u64 << 40), &i64::MIN, &true, &'é', &"net0"]);
+    assert_eq!(
+        render(Level::Info, Target::Kernel, "{} {} {} {} {} {} {} {} {}", &args),
+        format!("{} {} {} {} {} {} {} {} {}", 7u8, -3i16, u32::MAX, i32::MIN, 1u64 << 40, i64::MIN, true, 'é', "net0"),
+    );
+}
+
+#[test]
+fn format_specs_match_core() {
+    let args = encode(&[&0xBEEFu32, &0xBEEFu32, &5u32, &-5i32, &5u8, &'x', &"ab", &"ab"]);
+    assert_eq!(
+        render(Level::Info, Target::Kernel, "{:#010x} {:X} {:#b} {:04} {:3} {:?} {:?} {:4}|", &args),
+        format!("{:#010x} {:X} {:#b} {:04} {:3} {:?} {:?} {:4}|", 0xBEEFu32, 0xBEEFu32, 5u32, -5i32, 5u8, 'x', "ab", "ab"),
+    );
+    let none = Args::new();
+    assert_eq!(render(Level::Info, Target::Kernel, "{{}} }}", &none), "{} }");
+}
+
+#[test]
+fn level_and_target_prefix() {
+    let args = encode(&[&1u32]);
+    assert_eq!(render(Level::Warn, Target::Net, "link {}", &args), format!("warn: [{}] link 1", Target::Net));
+    assert_eq!(render(Level::Info, Target::Kernel, "link {}", &args), "link 1");
+}
+
+#[test]
+fn dropped_arguments_render_as_placeholders() {
+    // A string argument is cut at MAX_STR bytes on a character boundary
+    let long = "é".repeat(MAX_STR);
+    let args = encode(&[&long.as_str()]);
+    assert_eq!(render(Level::Info, Target::Kernel, "{}", &args), "é".repeat(MAX_STR / 2));
+
+    // Arguments past MAX_RECORD are left out whole
+    let mut args = Args::new();
+    while args.len() + 9 <= args.capacity() {
+        (u64::MAX).encode(&mut args);
+    }
+    let stored = args.len() / 9;
+    // Too big for the room left; the bool after it would fit
+    1u64.encode(&mut args);
+    true.encode(&mut args);
+    let format: &'static str = Box::leak("{} ".repeat(stored + 2).into_boxed_str());
+    let rendered = render(Level::Info, Target::Kernel, format, &args);
+    assert!(rendered.ends_with(&format!("{} {{?}} {{?}} ", u64::MAX)), "{}", rendered);
+}
+
+#[test]
+fn short_or_unknown_records_do_not_parse() {
+    let mut bytes = [0u8; HEADER];
+    bytes[1] = Level::Info as u8;
+    assert!(Record::parse(&bytes[..HEADER - 1]).is_none());
+    bytes[1] = 0;
+    assert!(Record::parse(&bytes).is_none());
+    bytes[1] = Level::Info as u8;
+    bytes[2] = 0xFF;
+    assert!(Record::parse(&bytes).is_none());
+}
+
+#[test]
+fn ring_keeps_whole_records() {
+    let mut last = 0;
+    // Enough records to wrap the ring several times
+    for i in 0..(4 * RING_BYTES / HEADER) as u32 {
+        let args = encode(&[&i, &"x".repeat(i as usize % 8).as_str()]);
+        record(Level::Debug, Target::App, "{} {}", &args);
+        last = i;
+    }
+
+    let mut seen = Vec::new();
+    let mut next = None;
+    visit_from(0, |offset, bytes| {
+        assert!(next.is_none_or(|next| next == offset), "gap before {}", offset);
+        next = Some(offset + bytes.len() as u32);
+        assert_eq!(bytes[0] as usize, bytes.len());
+        assert!(bytes.len() <= MAX_RECORD);
+        assert_eq!((bytes[1], bytes[2]), (Level::Debug as u8, Target::App as u8));
+        seen.push(u32::from_le_bytes([bytes[HEADER + 1], bytes[HEADER + 2], bytes[HEADER + 3], bytes[HEADER + 4]]));
+        true
+    });
+
+    // The newest records survive, in order and without gaps
+    assert!(!seen.is_empty());
+    assert_eq!(seen.last(), Some(&last));
+    assert!(seen.windows(2).all(|pair| pair[1] == pair[0] + 1), "{:?}", seen);
+}
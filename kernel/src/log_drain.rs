@@ -0,0 +1,158 @@
+//! Log drain
+//! Background task writing logged lines to the sinks (`log_drain`)
+//!
+//! Once the task has run, `Logger` only copies a line into its RAM
+//! buffer and wakes the task; the other sinks are written from here, at
+//! low priority and a few lines per run. A `log_debug!` in a
+//! time-critical task or an ISR then never waits for UART FIFO space.
+//! Until the first run, during boot, lines still go out as they are
+//! logged, so boot output keeps its order with direct console prints.
+//! Lines below the buffer sink's level are never stored, so they too go
+//! out as they are logged.
+//!
+//! Lines overwritten in the buffer before the task got to them are
+//! skipped and counted in `LogStats::overwritten`.
+//!
+//! A line identical to the one before it, with the same level and
+//! target, is held back and counted rather than written. The count goes
+//! out as "last message repeated N times" before the next different
+//! line, as with syslog; the buffer still holds every repeat.
+//! `log_visible!` lines are never folded.
+
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use crate::kernel::InitResult;
+use crate::logger::{LineInfo, LogLine, Logger};
+use crate::scheduler::{self, Task, TaskPriority};
+
+/// Task ID of the drain task
+pub const TASK_ID: usize = 18;
+
+/// Sinks are written when nothing else wants the CPU
+const PRIORITY: TaskPriority = TaskPriority::Low;
+
+/// Lines written per run, so a burst cannot hold the console for long
+const LINES_PER_RUN: usize = 4;
+
+/// Set by the first run; from then on loggers leave the sinks to the task
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Sequence number of the next line to write
+static NEXT_LINE: AtomicUsize = AtomicUsize::new(0);
+
+/// The task is parked and needs a wakeup for new lines
+static PARKED: AtomicBool = AtomicBool::new(false);
+
+/// Wait event id, allocated by `init`
+static EVENT: AtomicU32 = AtomicU32::new(0);
+
+/// Last line written, while lines repeating it are held back
+struct Fold {
+    line: LogLine,
+    info: Option<LineInfo>,
+    repeats: u32,
+}
+
+struct FoldCell(UnsafeCell<Fold>);
+unsafe impl Sync for FoldCell {} // Only touched by the drain task
+
+static FOLD: FoldCell = FoldCell(UnsafeCell::new(Fold { line: String::new(), info: None, repeats: 0 }));
+
+impl Fold {
+    /// Whether `line` repeats the last one and was counted instead
+    fn absorb(&mut self, line: &str, info: LineInfo) -> bool {
+        let repeats = self.info.is_some_and(|last| {
+            !info.visible && last.level == info.level && last.target == info.target && self.line == line
+        });
+        if repeats {
+            self.repeats += 1;
+            // The count is reported with the time of the latest repeat
+            self.info = Some(info);
+        }
+        repeats
+    }
+
+    /// Remember a line just written
+    fn written(&mut self, line: &str, info: LineInfo) {
+        self.line.clear();
+        let _ = self.line.push_str(line);
+        self.info = (!info.visible).then_some(info);
+    }
+
+    /// Report held-back repeats
+    fn flush(&mut self) {
+        if let (Some(info), 1..) = (self.info, self.repeats) {
+            let mut message = String::<40>::new();
+            let _ = write!(message, "last message repeated {} times", self.repeats);
+            Logger::write_sinks(&message, info);
+        }
+        self.repeats = 0;
+    }
+}
+
+/// Spawn the drain task
+pub fn init() -> InitResult {
+    EVENT.store(crate::kernel::alloc_wait_event(), Ordering::Relaxed);
+    let task = Task::with_priority(TASK_ID, PRIORITY).named("log drain").entry(|_| run());
+    scheduler::add_priority_task(task).map(|_| ()).map_err(|_| "no task slot")
+}
+
+/// Whether the task writes the sinks
+pub fn running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Sequence number of the next line the task writes
+pub fn next_line() -> usize {
+    NEXT_LINE.load(Ordering::Relaxed)
+}
+
+/// New lines are waiting (ISR-safe)
+pub fn wake() {
+    if PARKED.swap(false, Ordering::AcqRel) {
+        scheduler::wake_event(EVENT.load(Ordering::Relaxed));
+    }
+}
+
+/// One run: write a few lines, park once caught up
+pub fn run() {
+    if !RUNNING.swap(true, Ordering::Relaxed) {
+        // Everything logged so far went out directly
+        NEXT_LINE.store(Logger::get_stats().total, Ordering::Relaxed);
+    }
+
+    let total = Logger::get_stats().total;
+    // `log clear` starts the numbering again
+    let mut next = NEXT_LINE.load(Ordering::Relaxed).min(total);
+    let mut budget = LINES_PER_RUN;
+    let fold = unsafe { &mut *FOLD.0.get() };
+    Logger::visit_with_info_from(next, |seq, line, info| {
+        if budget == 0 {
+            return false;
+        }
+        next = seq + 1;
+        // Folded lines cost no output, so they do not count against the budget
+        if !fold.absorb(line, info) {
+            fold.flush();
+            Logger::write_sinks(line, info);
+            fold.written(line, info);
+            budget -= 1;
+        }
+        true
+    });
+    NEXT_LINE.store(next, Ordering::Relaxed);
+
+    if next >= total {
+        let event_id = EVENT.load(Ordering::Relaxed);
+        PARKED.store(true, Ordering::Release);
+        scheduler::block_current_priority(event_id);
+        // A line may have come in between the check and parking
+        if Logger::get_stats().total > next {
+            wake();
+        }
+    }
+}
@@ -1,116 +1,899 @@
 // Circular log buffer for capturing system debug output
 // Stores up to 100 log lines in static memory with rollover (reduced for memory constraints)
+//
+// Lines carry a level, Error (most severe) to Trace. A macro above
+// config::LOG_MAX_LEVEL compiles to nothing; below it, lines above the
+// runtime filter (`set_level`, the shell's `log level`) are dropped
+// before they are formatted. Error and warning lines are stored with an
+// `error: ` / `warn: ` prefix so readers of the buffer can tell them apart.
+//
+// A macro may name a subsystem `Target` first, as in
+// `log_warn!(target: Net, "link down")`; without one the line belongs to
+// `Target::Kernel`. Each target can override the runtime filter
+// (`set_target_level`, `log level net debug`), and lines of targets other
+// than the kernel are stored with a `[name] ` tag.
+//
+// With `log_facade`, records of the `log` crate from third-party no_std
+// crates land here too, see `facade`.
+//
+// `log_warn_ratelimited!(hz, ...)` and `log_error_ratelimited!` let a
+// callsite through at most `hz` times a second and report how many lines
+// they held back, so a flooding driver cannot push everything else out
+// of the buffer.
+//
+// Logging is safe from tasks and ISRs alike: the buffer is only touched
+// inside short critical sections, and readers copy one line out at a
+// time (`Snapshot`), so interrupts stay off only for a line's copy.
+//
+// Built with `log_binary`, `log_record!` keeps compact records in a ring
+// of their own instead of text lines here, see `log_binary`.
+//
+// Lines that pass the filters go to the sinks: the RAM buffer, the
+// console UART, the RTT terminal channel (`log_rtt`) and ARM semihosting.
+// Each sink takes lines up to its own level, so by default errors go
+// straight to the UART while everything stays in the buffer;
+// `log_visible!` lines reach the UART at any level. `set_sink_level`
+// (the shell's `log sink`) changes a sink's level or switches it off,
+// e.g. to keep log output off the console while a probe reads RTT.
+//
+// With `log_drain` a background task writes to the sinks once it runs,
+// so loggers only copy into RAM; until then, and without the feature,
+// sinks are written as each line is logged.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
 
 use heapless::{String, Vec};
 
-const MAX_LOG_LINES: usize = 100;  // Reduced from 1000
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+
+// Sized by config.rs; 16-line ring on boards with under 32 KiB of RAM
+const MAX_LOG_LINES: usize = crate::config::LOG_LINES;
 const MAX_LINE_LENGTH: usize = 64;  // Reduced from 128
-const STATUS_SNAPSHOT_LINES: usize = 50;  // Reduced from 100
 
-type LogLine = String<MAX_LINE_LENGTH>;
+pub type LogLine = String<MAX_LINE_LENGTH>;
 type LogBuffer = Vec<LogLine, MAX_LOG_LINES>;
 
+/// Log level, most severe first
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    /// Every level, most severe first
+    #[allow(dead_code)]
+    pub const ALL: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    /// Level by its `name`, ignoring case
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Level> {
+        Self::ALL.into_iter().find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+
+    const fn from_u32(value: u32) -> Level {
+        match value {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    /// Capitalized name, as terminal sinks show it
+    pub const fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    /// Color of `label` on terminal sinks
+    const fn style(self) -> Option<crate::shell::color::Style> {
+        use crate::shell::color::Style;
+        match self {
+            Level::Error => Some(Style::Error),
+            Level::Warn => Some(Style::Warning),
+            Level::Info => Some(Style::Info),
+            Level::Debug => None,
+            Level::Trace => Some(Style::Muted),
+        }
+    }
+
+    /// Stored in front of the message, so errors stand out in the buffer
+    pub(crate) const fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "error: ",
+            Level::Warn => "warn: ",
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Subsystem a log line comes from
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Kernel = 0,
+    Scheduler = 1,
+    Uart = 2,
+    Net = 3,
+    App = 4,
+}
+
+const TARGET_COUNT: usize = 5;
+
+impl Target {
+    /// Every target, in declaration order
+    #[allow(dead_code)]
+    pub const ALL: [Target; TARGET_COUNT] = [Target::Kernel, Target::Scheduler, Target::Uart, Target::Net, Target::App];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Target::Kernel => "kernel",
+            Target::Scheduler => "scheduler",
+            Target::Uart => "uart",
+            Target::Net => "net",
+            Target::App => "app",
+        }
+    }
+
+    /// Target by its `name`, ignoring case
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Target> {
+        Self::ALL.into_iter().find(|target| target.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Runtime filter: most verbose level stored; debug until changed
+static LEVEL: AtomicU32 = AtomicU32::new(Level::Debug as u32);
+
+// Per-target overrides of LEVEL; 0 follows it
+static TARGET_LEVELS: [AtomicU32; TARGET_COUNT] = [const { AtomicU32::new(0) }; TARGET_COUNT];
+
+/// Most verbose level currently stored
+pub fn level() -> Level {
+    Level::from_u32(LEVEL.load(Ordering::Relaxed)).min(crate::config::LOG_MAX_LEVEL)
+}
+
+/// Set the runtime filter; levels above `LOG_MAX_LEVEL` stay compiled out
+#[allow(dead_code)]
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u32, Ordering::Relaxed);
+}
+
+/// Runtime filter of `target`, if it overrides the global one
+#[allow(dead_code)]
+pub fn target_level(target: Target) -> Option<Level> {
+    match TARGET_LEVELS[target as usize].load(Ordering::Relaxed) {
+        0 => None,
+        value => Some(Level::from_u32(value)),
+    }
+}
+
+/// Override the runtime filter for `target`, or follow the global one again
+#[allow(dead_code)]
+pub fn set_target_level(target: Target, level: Option<Level>) {
+    TARGET_LEVELS[target as usize].store(level.map_or(0, |level| level as u32), Ordering::Relaxed);
+}
+
+/// Whether a line of `target` at `level` would be stored
+#[inline(always)]
+pub fn enabled(level: Level, target: Target) -> bool {
+    // The first test is constant, so disabled macros drop out entirely
+    if level > crate::config::LOG_MAX_LEVEL {
+        return false;
+    }
+    let filter = match TARGET_LEVELS[target as usize].load(Ordering::Relaxed) {
+        0 => LEVEL.load(Ordering::Relaxed),
+        value => value,
+    };
+    level as u32 <= filter
+}
+
+/// Where log lines go; each sink takes lines up to its own level
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sink {
+    /// RAM ring read by `log`, the host protocol and the drain task
+    Buffer = 0,
+    /// Console UART; also takes `log_visible!` lines whatever its level
+    Uart = 1,
+    /// RTT terminal channel (`log_rtt`)
+    Rtt = 2,
+    /// Debugger console through ARM semihosting (QEMU and probes only)
+    Semihosting = 3,
+}
+
+const SINK_COUNT: usize = 4;
+
+impl fmt::Display for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Sink {
+    #[allow(dead_code)]
+    pub const ALL: [Sink; SINK_COUNT] = [Sink::Buffer, Sink::Uart, Sink::Rtt, Sink::Semihosting];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Sink::Buffer => "buffer",
+            Sink::Uart => "uart",
+            Sink::Rtt => "rtt",
+            Sink::Semihosting => "semihosting",
+        }
+    }
+
+    /// Sink by its `name`, ignoring case
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Sink> {
+        Self::ALL.into_iter().find(|sink| sink.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the sink is in this build
+    pub const fn built(self) -> bool {
+        match self {
+            Sink::Buffer | Sink::Uart => true,
+            Sink::Rtt => cfg!(feature = "log_rtt"),
+            // Without a host attached the BKPT would lock a real board up
+            Sink::Semihosting => cfg!(all(feature = "arm", not(any(feature = "board_stm32f407", feature = "board_nrf52840")))),
+        }
+    }
+
+    /// Level the sink starts at and `on` restores; `None` is off
+    ///
+    /// Errors go straight to the UART and everything else stays in RAM.
+    /// Semihosting stalls the core on every line, so it is opt-in.
+    pub const fn default_level(self) -> Option<Level> {
+        match self {
+            Sink::Buffer | Sink::Rtt => Some(Level::Trace),
+            Sink::Uart => Some(Level::Error),
+            Sink::Semihosting => None,
+        }
+    }
+}
+
+// Most verbose level each sink takes, by `Sink`; 0 is off
+static SINK_LEVELS: [AtomicU32; SINK_COUNT] = {
+    let mut levels = [const { AtomicU32::new(0) }; SINK_COUNT];
+    let mut index = 0;
+    while index < SINK_COUNT {
+        if let Some(level) = Sink::ALL[index].default_level() {
+            levels[index] = AtomicU32::new(level as u32);
+        }
+        index += 1;
+    }
+    levels
+};
+
+/// Most verbose level `sink` takes; `None` if off or not built in
+pub fn sink_level(sink: Sink) -> Option<Level> {
+    match SINK_LEVELS[sink as usize].load(Ordering::Relaxed) {
+        _ if !sink.built() => None,
+        0 => None,
+        value => Some(Level::from_u32(value)),
+    }
+}
+
+/// Set the most verbose level `sink` takes, or switch it off
+#[allow(dead_code)]
+pub fn set_sink_level(sink: Sink, level: Option<Level>) {
+    SINK_LEVELS[sink as usize].store(level.map_or(0, |level| level as u32), Ordering::Relaxed);
+}
+
+/// What a line was logged with, for routing and rendering it
+#[derive(Copy, Clone, Debug)]
+pub struct LineInfo {
+    pub level: Level,
+    pub target: Target,
+    /// From `log_visible!`: the UART takes it at any level, unrendered
+    pub visible: bool,
+    /// Uptime when logged, so drained lines keep their time
+    pub millis: u32,
+}
+
+impl LineInfo {
+    fn new(level: Level, target: Target, visible: bool) -> Self {
+        Self { level, target, visible, millis: crate::kernel::time::uptime().millis() as u32 }
+    }
+
+    /// Whether `sink` takes the line
+    pub fn reaches(self, sink: Sink) -> bool {
+        match sink_level(sink) {
+            Some(max) => self.level <= max || (self.visible && sink == Sink::Uart),
+            None => false,
+        }
+    }
+}
+
+/// Circular log buffer and what each line was logged with
+struct LogRing {
+    lines: LogBuffer,
+    /// Buffer index the next line goes to
+    index: usize,
+    /// Lines stored since boot or the last `clear`
+    total: usize,
+    /// By buffer index, for the drain task and renderers
+    info: [LineInfo; MAX_LOG_LINES],
+}
+
+impl LogRing {
+    /// Buffer index of line `seq`, if it is still held
+    fn slot(&self, seq: usize) -> Option<usize> {
+        let held = self.lines.len();
+        let oldest_seq = self.total - held;
+        if seq < oldest_seq || seq >= self.total {
+            return None;
+        }
+        // Before the buffer wraps the oldest line is at 0, after at `index`
+        let oldest = if held < MAX_LOG_LINES { 0 } else { self.index };
+        Some((oldest + seq - oldest_seq) % held)
+    }
+}
+
+struct LogCell(UnsafeCell<LogRing>);
+unsafe impl Sync for LogCell {} // Only touched inside `with_buffer`
+
 // Static circular log buffer
-static mut LOG_BUFFER: LogBuffer = Vec::new();
-static mut LOG_INDEX: usize = 0;
-static mut TOTAL_LINES: usize = 0;
+static LOG_BUFFER: LogCell = LogCell(UnsafeCell::new(LogRing {
+    lines: Vec::new(),
+    index: 0,
+    total: 0,
+    info: [LineInfo { level: Level::Info, target: Target::Kernel, visible: false, millis: 0 }; MAX_LOG_LINES],
+}));
+
+/// Run `f` on the buffer with interrupts off
+///
+/// Tasks and ISRs may both log, and may do so with interrupts already
+/// off, so the previous state is restored rather than interrupts being
+/// enabled. `f` must stay short: no sink writes, no caller callbacks.
+fn with_buffer<R>(f: impl FnOnce(&mut LogRing) -> R) -> R {
+    crate::arch::free(|| f(unsafe { &mut *LOG_BUFFER.0.get() }))
+}
+
+// Lines cut to MAX_LINE_LENGTH
+static TRUNCATED: AtomicU32 = AtomicU32::new(0);
+// Lines overwritten before the drain task wrote them to the sinks
+static OVERWRITTEN: AtomicU32 = AtomicU32::new(0);
+
+/// Log buffer occupancy and loss counters
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LogStats {
+    /// Lines in the buffer, out of `config::LOG_LINES`
+    pub held: usize,
+    /// Lines logged since boot or the last `clear`
+    pub total: usize,
+    /// Buffer index the next line goes to
+    pub index: usize,
+    /// Lines cut short because they were longer than a buffer line
+    pub truncated: u32,
+    /// Lines overwritten before the drain task got to them (`log_drain`)
+    pub overwritten: u32,
+}
+
+/// A stored line as terminal sinks show it
+///
+/// The level and target tags the buffer keeps in the text are replaced
+/// by the time, the level in capitals and the target. The level is
+/// colored while the shell's colors are on (`color on|off`).
+struct Rendered<'a> {
+    line: &'a str,
+    info: LineInfo,
+}
+
+/// Longest rendered line: time, level, target and color codes on top
+const RENDERED_LENGTH: usize = MAX_LINE_LENGTH + 40;
+
+impl fmt::Display for Rendered<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LineInfo { level, target, millis, .. } = self.info;
+        let body = self.line.strip_prefix(level.prefix()).unwrap_or(self.line);
+        let mut tag = String::<16>::new();
+        let _ = write!(tag, "[{}] ", target);
+        let body = match target {
+            Target::Kernel => body,
+            _ => body.strip_prefix(tag.as_str()).unwrap_or(body),
+        };
+        write!(f, "[{}.{:03}] ", millis / 1000, millis % 1000)?;
+        let label = level.label();
+        match level.style() {
+            Some(style) => write!(f, "{}", crate::shell::color::styled(style, label))?,
+            None => f.write_str(label)?,
+        }
+        // Padded outside the color codes, which `Styled` ignores width for
+        let padding = 5 - label.len();
+        write!(f, "{:padding$} {}: {}", "", target, body)
+    }
+}
+
+/// Per-callsite state of a rate-limited log macro
+///
+/// Lets one line through per `1 / hz` seconds and counts the ones it
+/// holds back; the next line let through reports them. Contexts racing
+/// on one callsite may let an extra line through, never lose the count
+/// entirely, so no compare-and-swap is needed.
+pub struct RateLimit {
+    /// Low word of the tick the last line went through at
+    last: AtomicU32,
+    /// Lines held back since
+    suppressed: AtomicU32,
+    /// A line has gone through, so `last` is valid
+    started: AtomicBool,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self { last: AtomicU32::new(0), suppressed: AtomicU32::new(0), started: AtomicBool::new(false) }
+    }
+
+    /// `Some(held back)` if a line may go through now at `hz` lines a second
+    pub fn check(&self, hz: u32) -> Option<u32> {
+        let now = crate::kernel::time::now() as u32;
+        let interval = (crate::config::TICK_HZ / hz.max(1)).max(1);
+        let last = self.last.load(Ordering::Relaxed);
+        if self.started.load(Ordering::Relaxed) && now.wrapping_sub(last) < interval {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.last.store(now, Ordering::Relaxed);
+        self.started.store(true, Ordering::Relaxed);
+        Some(self.suppressed.swap(0, Ordering::Relaxed))
+    }
+}
+
+/// A held line, copied out of the buffer
+#[derive(Clone, Debug)]
+pub struct Line {
+    /// Position in everything logged since boot or the last `clear`
+    pub seq: usize,
+    pub text: LogLine,
+    pub info: LineInfo,
+}
+
+impl Line {
+    pub fn as_str(&self) -> &str {
+        self.text.as_str()
+    }
+}
+
+/// Iterator over a range of held lines, oldest first
+///
+/// Nothing is copied up front: each `next` copies one line out of the
+/// buffer inside a short critical section, so reading the whole log
+/// costs one line of stack and the caller may print or log between
+/// lines with interrupts on. Lines overwritten before `next` reaches
+/// them are skipped; lines logged after the snapshot was taken are left
+/// for the next one, so a reader that logs cannot keep it going.
+pub struct Snapshot {
+    next: usize,
+    end: usize,
+}
+
+impl Iterator for Snapshot {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        if self.next >= self.end {
+            return None;
+        }
+        let line = with_buffer(|ring| {
+            let seq = self.next.max(ring.total - ring.lines.len());
+            let index = ring.slot(seq).filter(|_| seq < self.end)?;
+            Some(Line { seq, text: ring.lines[index].clone(), info: ring.info[index] })
+        });
+        match &line {
+            Some(line) => self.next = line.seq + 1,
+            // Cleared meanwhile
+            None => self.next = self.end,
+        }
+        line
+    }
+}
 
 pub struct Logger;
 
 impl Logger {
     /// Add a new log line to the circular buffer
-    #[allow(static_mut_refs)]
     pub fn log(message: &str) {
-        unsafe {
-            let mut log_line = LogLine::new();
-            let _ = log_line.push_str(message);
-            
-            if LOG_BUFFER.len() < MAX_LOG_LINES {
+        Self::store(message, LineInfo::new(Level::Info, Target::Kernel, false));
+    }
+
+    /// Add a line that also goes to the console UART (`log_visible!`)
+    pub fn log_visible(message: &str) {
+        Self::store(message, LineInfo::new(Level::Info, Target::Kernel, true));
+    }
+
+    fn store(message: &str, info: LineInfo) {
+        if !info.reaches(Sink::Buffer) {
+            crate::crashdump::persist_line(message);
+            // The drain task only sees buffered lines
+            Self::write_sinks(message, info);
+            return;
+        }
+
+        let mut log_line = LogLine::new();
+        // Keep what fits; `push_str` would keep nothing
+        let mut end = message.len().min(MAX_LINE_LENGTH);
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        let _ = log_line.push_str(&message[..end]);
+        if end < message.len() {
+            Self::count_truncated();
+        }
+
+        with_buffer(|ring| {
+            if ring.lines.len() < MAX_LOG_LINES {
                 // Buffer not full yet, just push
-                let _ = LOG_BUFFER.push(log_line);
+                let _ = ring.lines.push(log_line);
             } else {
                 // Buffer is full, overwrite at current index (circular)
-                LOG_BUFFER[LOG_INDEX] = log_line;
+                #[cfg(feature = "log_drain")]
+                if crate::log_drain::running() && ring.total - MAX_LOG_LINES >= crate::log_drain::next_line() {
+                    OVERWRITTEN.fetch_add(1, Ordering::Relaxed);
+                }
+                ring.lines[ring.index] = log_line;
             }
-            
+            ring.info[ring.index] = info;
+
             // Update circular index
-            LOG_INDEX = (LOG_INDEX + 1) % MAX_LOG_LINES;
-            TOTAL_LINES += 1;
+            ring.index = (ring.index + 1) % MAX_LOG_LINES;
+            ring.total += 1;
+            // Same order as the buffer, so inside the critical section too
+            crate::crashdump::persist_line(message);
+        });
+
+        #[cfg(feature = "log_drain")]
+        if crate::log_drain::running() {
+            crate::log_drain::wake();
+            return;
+        }
+        Self::write_sinks(message, info);
+    }
+
+    /// Write one line to the sinks other than the buffer that take it
+    ///
+    /// These are all terminals, so lines go out rendered as in
+    /// `[12.345] WARN  net: link down`; `log_visible!` lines go out as
+    /// they are.
+    pub fn write_sinks(line: &str, info: LineInfo) {
+        let mut text = String::<RENDERED_LENGTH>::new();
+        let line = if info.visible {
+            line
+        } else {
+            let _ = write!(text, "{}", Rendered { line, info });
+            text.as_str()
+        };
+        if info.reaches(Sink::Uart) {
+            crate::arch::early_println(line);
+        }
+        #[cfg(feature = "log_rtt")]
+        if info.reaches(Sink::Rtt) {
+            let channel = crate::drivers::rtt::TERMINAL;
+            crate::drivers::rtt::write(channel, line.as_bytes());
+            crate::drivers::rtt::write(channel, b"\n");
+        }
+        #[cfg(all(feature = "arm", not(any(feature = "board_stm32f407", feature = "board_nrf52840"))))]
+        if info.reaches(Sink::Semihosting) {
+            cortex_m_semihosting::hprintln!("{}", line);
         }
     }
     
-    /// Get the last N lines for status command
-    #[allow(static_mut_refs)]
-    pub fn get_last_lines(count: usize) -> Vec<LogLine, STATUS_SNAPSHOT_LINES> {
-        let mut result = Vec::new();
-        
-        unsafe {
-            let buffer_size = LOG_BUFFER.len();
-            if buffer_size == 0 {
-                return result;
-            }
-            
-            let lines_to_get = count.min(buffer_size).min(STATUS_SNAPSHOT_LINES);
-            
-            if buffer_size < MAX_LOG_LINES {
-                // Buffer not full yet, get from end
-                let start_idx = buffer_size.saturating_sub(lines_to_get);
-                for i in start_idx..buffer_size {
-                    if result.push(LOG_BUFFER[i].clone()).is_err() {
-                        break;
-                    }
-                }
-            } else {
-                // Buffer is full, get from circular position
-                let start_idx = if LOG_INDEX >= lines_to_get {
-                    LOG_INDEX - lines_to_get
-                } else {
-                    MAX_LOG_LINES - (lines_to_get - LOG_INDEX)
-                };
-                
-                for i in 0..lines_to_get {
-                    let idx = (start_idx + i) % MAX_LOG_LINES;
-                    if result.push(LOG_BUFFER[idx].clone()).is_err() {
-                        break;
-                    }
-                }
+    /// Format and add a line of `target` at `level`, cut to the line length
+    pub fn log_fmt(level: Level, target: Target, args: fmt::Arguments) {
+        let mut line = LogLine::new();
+        let _ = line.push_str(level.prefix());
+        if target != Target::Kernel {
+            let _ = write!(line, "[{}] ", target);
+        }
+        // A full line ends the write early; keep what fitted
+        if line.write_fmt(args).is_err() {
+            Self::count_truncated();
+        }
+        Self::store(line.as_str(), LineInfo::new(level, target, false));
+    }
+
+    /// Count a line cut short before it reached the buffer
+    pub fn count_truncated() {
+        TRUNCATED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Held lines from sequence number `first` on, oldest first
+    ///
+    /// Lines are numbered from 0 in the order they were logged. The
+    /// snapshot ends at the newest line now held; see `Snapshot`.
+    pub fn lines_from(first: usize) -> Snapshot {
+        Snapshot { next: first, end: with_buffer(|ring| ring.total) }
+    }
+
+    /// The last `count` held lines, oldest first
+    pub fn snapshot(count: usize) -> Snapshot {
+        with_buffer(|ring| Snapshot { next: ring.total - count.min(ring.lines.len()), end: ring.total })
+    }
+
+    /// Visit the last N lines, oldest first
+    pub fn visit_last_lines(count: usize, mut f: impl FnMut(&str)) {
+        Self::snapshot(count).for_each(|line| f(line.as_str()));
+    }
+
+    /// Visit held lines from sequence number `first` on, oldest first
+    ///
+    /// `f` gets each line's number and returns false to stop.
+    pub fn visit_from(first: usize, mut f: impl FnMut(usize, &str) -> bool) {
+        Self::visit_with_info_from(first, |seq, line, _| f(seq, line));
+    }
+
+    /// `visit_from`, also passing on each line's `LineInfo`
+    pub fn visit_with_info_from(first: usize, mut f: impl FnMut(usize, &str, LineInfo) -> bool) {
+        for line in Self::lines_from(first) {
+            if !f(line.seq, line.as_str(), line.info) {
+                break;
             }
         }
-        
-        result
     }
-    
+
     /// Get statistics about the log buffer
-    #[allow(static_mut_refs)]
-    pub fn get_stats() -> (usize, usize, usize) {
-        unsafe {
-            (LOG_BUFFER.len(), TOTAL_LINES, LOG_INDEX)
-        }
+    pub fn get_stats() -> LogStats {
+        with_buffer(|ring| LogStats {
+            held: ring.lines.len(),
+            total: ring.total,
+            index: ring.index,
+            truncated: TRUNCATED.load(Ordering::Relaxed),
+            overwritten: OVERWRITTEN.load(Ordering::Relaxed),
+        })
     }
     
-    /// Clear the log buffer
-    #[allow(static_mut_refs)]
+    /// Clear the log buffer and its counters
     pub fn clear() {
+        with_buffer(|ring| {
+            ring.lines.clear();
+            ring.index = 0;
+            ring.total = 0;
+        });
+        TRUNCATED.store(0, Ordering::Relaxed);
+        OVERWRITTEN.store(0, Ordering::Relaxed);
+    }
+}
+
+/// `log` crate backend (`log_facade`)
+///
+/// Records go through the same level and target filters as the kernel's
+/// own macros. A record's target is its module path; the first segment
+/// picks the `Target` (`smoltcp` counts as `net`), and records of other
+/// crates count as `app` and keep the crate name in front.
+#[cfg(feature = "log_facade")]
+pub mod facade {
+    use super::{enabled, Level, Logger, Target};
+
+    struct Facade;
+
+    static FACADE: Facade = Facade;
+
+    /// Install the backend; until then `log` records are dropped
+    pub fn init() {
+        // Called once at boot before anything logs, and `set_logger` needs
+        // compare-and-swap, which the ESP32-C3 lacks
         unsafe {
-            LOG_BUFFER.clear();
-            LOG_INDEX = 0;
-            TOTAL_LINES = 0;
+            let _ = log::set_logger_racy(&FACADE);
+            log::set_max_level_racy(level_filter(crate::config::LOG_MAX_LEVEL));
+        }
+    }
+
+    fn level_filter(level: Level) -> log::LevelFilter {
+        match level {
+            Level::Error => log::LevelFilter::Error,
+            Level::Warn => log::LevelFilter::Warn,
+            Level::Info => log::LevelFilter::Info,
+            Level::Debug => log::LevelFilter::Debug,
+            Level::Trace => log::LevelFilter::Trace,
         }
     }
+
+    fn level(level: log::Level) -> Level {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Trace,
+        }
+    }
+
+    /// Kernel target for a `log` target, and the crate name if it is foreign
+    fn target(target: &str) -> (Target, Option<&str>) {
+        let krate = target.split("::").next().unwrap_or(target);
+        match krate {
+            "smoltcp" => (Target::Net, None),
+            // The kernel's own crate names, as binary and as library
+            "karatos_kernel" | "kernel_lib" => (Target::Kernel, None),
+            _ => match Target::from_name(krate) {
+                Some(target) => (target, None),
+                None => (Target::App, Some(krate)),
+            },
+        }
+    }
+
+    impl log::Log for Facade {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            enabled(level(metadata.level()), target(metadata.target()).0)
+        }
+
+        fn log(&self, record: &log::Record) {
+            let level = level(record.level());
+            let (target, krate) = target(record.target());
+            if !enabled(level, target) {
+                return;
+            }
+            match krate {
+                Some(krate) => Logger::log_fmt(level, target, format_args!("{}: {}", krate, record.args())),
+                None => Logger::log_fmt(level, target, *record.args()),
+            }
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+/// Log at a given `logger::Level`, if it passes both filters
+///
+/// `target: Name,` before the format names a `logger::Target`; the
+/// default is `Kernel`.
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, target: $target:ident, $($arg:tt)*) => {
+        {
+            let level: $crate::logger::Level = $level;
+            let target = $crate::logger::Target::$target;
+            if $crate::logger::enabled(level, target) {
+                $crate::logger::Logger::log_fmt(level, target, format_args!($($arg)*));
+            }
+        }
+    };
+    ($level:expr, $($arg:tt)*) => {
+        $crate::log_at!($level, target: Kernel, $($arg)*)
+    };
+}
+
+/// `log_at!` letting at most `hz` lines a second through per callsite
+///
+/// Held-back lines are counted and reported with the next line that
+/// goes through, as in `rx overrun (12 suppressed)`.
+#[macro_export]
+macro_rules! log_ratelimited {
+    ($level:expr, $hz:expr, target: $target:ident, $($arg:tt)*) => {
+        {
+            static LIMIT: $crate::logger::RateLimit = $crate::logger::RateLimit::new();
+            let level: $crate::logger::Level = $level;
+            let target = $crate::logger::Target::$target;
+            if $crate::logger::enabled(level, target) {
+                match LIMIT.check($hz) {
+                    Some(0) => $crate::logger::Logger::log_fmt(level, target, format_args!($($arg)*)),
+                    Some(suppressed) => $crate::logger::Logger::log_fmt(
+                        level,
+                        target,
+                        format_args!("{} ({} suppressed)", format_args!($($arg)*), suppressed),
+                    ),
+                    None => {}
+                }
+            }
+        }
+    };
+    ($level:expr, $hz:expr, $($arg:tt)*) => {
+        $crate::log_ratelimited!($level, $hz, target: Kernel, $($arg)*)
+    };
 }
 
-/// Macro for silent logging (replaces arch_println for debug output)
+/// `log_error!` at most `hz` times a second per callsite
+#[macro_export]
+macro_rules! log_error_ratelimited {
+    ($hz:expr, $($arg:tt)*) => { $crate::log_ratelimited!($crate::logger::Level::Error, $hz, $($arg)*) };
+}
+
+/// `log_warn!` at most `hz` times a second per callsite
+#[macro_export]
+macro_rules! log_warn_ratelimited {
+    ($hz:expr, $($arg:tt)*) => { $crate::log_ratelimited!($crate::logger::Level::Warn, $hz, $($arg)*) };
+}
+
+/// Something failed and was not recovered
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logger::Level::Error, $($arg)*) };
+}
+
+/// Something is off but the system carries on
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logger::Level::Warn, $($arg)*) };
+}
+
+/// Normal operation worth keeping in the log
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logger::Level::Info, $($arg)*) };
+}
+
+/// Detail for debugging, buffer only (replaces arch_println for debug output)
 #[macro_export]
 macro_rules! log_debug {
-    ($($arg:tt)*) => {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logger::Level::Debug, $($arg)*) };
+}
+
+/// Very detailed tracing, off unless the runtime filter asks for it
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logger::Level::Trace, $($arg)*) };
+}
+
+/// Log at a `logger::Level` as a binary record (`log_binary`)
+///
+/// Takes a literal format and arguments implementing `log_binary::Arg`:
+/// integers, `bool`, `char` and `&str`. Supported specs are `{}`, `{:?}`
+/// and `{:x}`, `{:X}`, `{:b}`, with `#`, `0` and a width. Without the
+/// feature the line is formatted and stored as text.
+#[macro_export]
+macro_rules! log_record {
+    ($level:expr, target: $target:ident, $format:literal $(, $arg:expr)* $(,)?) => {
         {
-            use heapless::String;
-            let mut msg = String::<64>::new();  // Reduced from 128
-            use core::fmt::Write;
-            let _ = write!(msg, $($arg)*);
-            crate::logger::Logger::log(msg.as_str());
+            let level: $crate::logger::Level = $level;
+            let target = $crate::logger::Target::$target;
+            if $crate::logger::enabled(level, target) {
+                #[cfg(feature = "log_binary")]
+                {
+                    // Checks the arguments against the format; never run
+                    if false {
+                        let _ = format_args!($format $(, $arg)*);
+                    }
+                    let mut args = $crate::log_binary::Args::new();
+                    $($crate::log_binary::Arg::encode(&$arg, &mut args);)*
+                    $crate::log_binary::record(level, target, $format, &args);
+                }
+                #[cfg(not(feature = "log_binary"))]
+                $crate::logger::Logger::log_fmt(level, target, format_args!($format $(, $arg)*));
+            }
         }
     };
+    ($level:expr, $format:literal $(, $arg:expr)* $(,)?) => {
+        $crate::log_record!($level, target: Kernel, $format $(, $arg)*)
+    };
 }
 
 /// Macro for visible output (still goes to terminal)
@@ -118,15 +901,14 @@ macro_rules! log_debug {
 macro_rules! log_visible {
     ($($arg:tt)*) => {
         {
-            // Also log to buffer
+            // Logged to the buffer and printed on the terminal
             use heapless::String;
             let mut msg = String::<64>::new();  // Reduced from 128
             use core::fmt::Write;
-            let _ = write!(msg, $($arg)*);
-            crate::logger::Logger::log(msg.as_str());
-            
-            // And print to terminal
-            crate::arch::arch_println(&msg);
+            if write!(msg, $($arg)*).is_err() {
+                $crate::logger::Logger::count_truncated();
+            }
+            $crate::logger::Logger::log_visible(msg.as_str());
         }
     };
 }
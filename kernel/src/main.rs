@@ -1,41 +1,70 @@
 //! karatOS - Multi-architecture RTOS kernel
 //! Unified entry point for ARM and RISC-V targets
 
-#![no_std]
-#![no_main]
-
-// ARM-specific imports and panic handler
-#[cfg(target_arch = "arm")]
-use panic_halt as _;
+#![cfg_attr(not(any(feature = "sim", test)), no_std)]
+#![cfg_attr(not(any(feature = "sim", test)), no_main)]
 
+// ARM-specific imports
 #[cfg(target_arch = "arm")]
 use cortex_m_rt::entry;
 
-#[cfg(target_arch = "arm")]
+#[cfg(all(target_arch = "arm", not(any(feature = "board_stm32f407", feature = "board_nrf52840"))))]
 use cortex_m_semihosting::hprintln;
 
-// RISC-V specific imports and panic handler
-#[cfg(target_arch = "riscv32")]
-use panic_halt as _;
-
+// RISC-V specific imports
 #[cfg(target_arch = "riscv32")]
 #[allow(unused_imports)]
 use riscv_rt::entry;
 
 // Include modules directly since this is the main binary
+mod accounting;
 mod arch;
+mod banner;
+mod board;
 mod config;
+mod coverage;
+mod crashdump;
+#[cfg(feature = "critical_section_impl")]
+mod critical_section_impl;
+#[cfg(feature = "log_defmt")]
+mod defmt_log;
 mod drivers;
+mod events;
+#[cfg(feature = "async_tasks")]
+mod executor;
+#[cfg(feature = "gdb_stub")]
+mod gdb;
+mod instrumentation;
+mod introspect;
+mod kassert;
 mod kernel;
+#[cfg(feature = "ktest")]
+mod ktest;
+#[cfg(feature = "log_binary")]
+#[allow(dead_code)]
+mod log_binary;
+#[cfg(feature = "log_drain")]
+mod log_drain;
+#[allow(dead_code)]
+mod logger;
 mod memory;
+mod metrics;
+#[cfg(feature = "net")]
+mod net;
+mod panic;
+mod power;
 #[cfg(target_arch = "riscv32")]
 mod riscv_rt_config;
 
 // Import scheduler for task management
 mod scheduler;
+mod shell;
+mod supervisor;
+mod syscall;
+mod tasklet;
 use scheduler::{Task, TaskPriority, EventPriority, post_priority_event, 
                 add_priority_task, schedule_with_priority, 
-                update_global_timer, has_ready_work, current_priority_level};
+                has_ready_work, current_priority_level};
 
 // -------- Scheduling Example Tasks --------
 
@@ -65,9 +94,23 @@ fn u32_to_str(mut num: u32) -> [u8; 10] {
 }
 
 // -------- Enhanced Scheduling Test Tasks --------
+//
+// Each demo task handles one event and then waits for the next, so the
+// services spawned next to them (shell, timers, ...) get the CPU in
+// between. The main loop posts the events below.
+
+/// Demo events, one per task; the preemption scenario posts the 0x5x ones
+const EVENT_CRITICAL: u32 = 0x10;
+const EVENT_REALTIME: u32 = 0x20;
+const EVENT_USER: u32 = 0x30;
+const EVENT_BACKGROUND: u32 = 0x40;
+const EVENT_TICK: u32 = 0x50;
+const EVENT_MESSAGE: u32 = 0x51;
+const EVENT_SCENARIO_HIGH: u32 = 0x52;
+const EVENT_SCENARIO_CRITICAL: u32 = 0x53;
 
 // Task 1: Critical priority system task
-fn task_critical_system() {
+fn task_critical_system(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -77,10 +120,12 @@ fn task_critical_system() {
         arch::early_println(counter_str);
         arch::early_println(" executing");
     }
+    arch::early_println(" [Critical task completed]");
+    scheduler::block_current_priority(EVENT_CRITICAL);
 }
 
 // Task 2: High priority real-time task
-fn task_high_realtime() {
+fn task_high_realtime(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -90,10 +135,12 @@ fn task_high_realtime() {
         arch::early_println(counter_str);
         arch::early_println(" processing");
     }
+    arch::early_println(" [High priority task completed]");
+    scheduler::block_current_priority(EVENT_REALTIME);
 }
 
 // Task 3: Normal priority application task
-fn task_normal_app() {
+fn task_normal_app(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -103,10 +150,12 @@ fn task_normal_app() {
         arch::early_println(counter_str);
         arch::early_println(" running");
     }
+    arch::early_println(" [Normal app task completed]");
+    scheduler::block_current_priority(EVENT_USER);
 }
 
 // Task 4: Low priority background task
-fn task_low_background() {
+fn task_low_background(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -116,10 +165,12 @@ fn task_low_background() {
         arch::early_println(counter_str);
         arch::early_println(" cleaning");
     }
+    arch::early_println(" [Background task completed]");
+    scheduler::block_current_priority(EVENT_BACKGROUND);
 }
 
 // Task 5: Event-driven message processing task
-fn task_message_processor() {
+fn task_message_processor(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -129,10 +180,12 @@ fn task_message_processor() {
         arch::early_println(counter_str);
         arch::early_println(" handled");
     }
+    arch::early_println(" [Message processor completed]");
+    scheduler::block_current_priority(EVENT_MESSAGE);
 }
 
 // Task 6: Timer-based periodic task
-fn task_timer_periodic() {
+fn task_timer_periodic(_id: usize) {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
@@ -142,28 +195,42 @@ fn task_timer_periodic() {
         arch::early_println(counter_str);
         arch::early_println(" tick");
     }
+    arch::early_println(" [Timer task completed]");
+    scheduler::block_current_priority(EVENT_TICK);
+}
+
+/// What runs once the kernel is up: the scheduler demo, or the test
+/// cases in a `ktest` build
+fn start() -> ! {
+    #[cfg(feature = "ktest")]
+    ktest::run();
+
+    #[cfg(not(feature = "ktest"))]
+    run_enhanced_scheduler_test()
 }
 
 // -------- Enhanced Multi-Priority Scheduler Test --------
+#[cfg_attr(feature = "ktest", allow(dead_code))]
 fn run_enhanced_scheduler_test() -> ! {
     arch::early_println("=== karatOS Enhanced Multi-Priority Scheduler Test ===");
     arch::early_println("Features: Priority preemption, message-passing optimization,");
     arch::early_println("lock-free queues, timer integration, architecture-agnostic");
+    
     arch::early_println("");
 
     // Create tasks with different priorities
-    let critical_task = Task::with_priority(1, TaskPriority::Critical);
-    let high_task = Task::with_priority(2, TaskPriority::High);
-    let normal_task1 = Task::with_priority(3, TaskPriority::Normal);
-    let normal_task2 = Task::with_priority(4, TaskPriority::Normal);
-    let low_task1 = Task::with_priority(5, TaskPriority::Low);
-    let low_task2 = Task::with_priority(6, TaskPriority::Low);
+    let critical_task = Task::with_priority(1, TaskPriority::Critical).named("critical").entry(task_critical_system);
+    let high_task = Task::with_priority(2, TaskPriority::High).named("realtime").entry(task_high_realtime);
+    let normal_task1 = Task::with_priority(3, TaskPriority::Normal).named("app").entry(task_normal_app);
+    let normal_task2 = Task::with_priority(4, TaskPriority::Normal).named("msgproc").entry(task_message_processor);
+    let low_task1 = Task::with_priority(5, TaskPriority::Low).named("background").entry(task_low_background);
+    let low_task2 = Task::with_priority(6, TaskPriority::Low).named("periodic").entry(task_timer_periodic);
 
     // Spawn tasks using multi-priority scheduler
     match add_priority_task(critical_task) {
         Ok(id) => {
             arch::early_println("✅ Spawned Critical System Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn Critical Task"),
@@ -172,7 +239,7 @@ fn run_enhanced_scheduler_test() -> ! {
     match add_priority_task(high_task) {
         Ok(id) => {
             arch::early_println("✅ Spawned High Priority Real-time Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn High Priority Task"),
@@ -181,7 +248,7 @@ fn run_enhanced_scheduler_test() -> ! {
     match add_priority_task(normal_task1) {
         Ok(id) => {
             arch::early_println("✅ Spawned Normal App Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn Normal Task 1"),
@@ -190,7 +257,7 @@ fn run_enhanced_scheduler_test() -> ! {
     match add_priority_task(normal_task2) {
         Ok(id) => {
             arch::early_println("✅ Spawned Message Processor Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn Normal Task 2"),
@@ -199,7 +266,7 @@ fn run_enhanced_scheduler_test() -> ! {
     match add_priority_task(low_task1) {
         Ok(id) => {
             arch::early_println("✅ Spawned Low Background Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn Low Task 1"),
@@ -208,7 +275,7 @@ fn run_enhanced_scheduler_test() -> ! {
     match add_priority_task(low_task2) {
         Ok(id) => {
             arch::early_println("✅ Spawned Timer Periodic Task ID: ");
-            let id_str = u32_to_str(id as u32);
+            let id_str = u32_to_str(id.index() as u32);
             arch::early_println(core::str::from_utf8(&id_str).unwrap_or("0"));
         },
         Err(_) => arch::early_println("❌ Failed to spawn Low Task 2"),
@@ -225,45 +292,39 @@ fn run_enhanced_scheduler_test() -> ! {
         cycle_counter += 1;
         timer_counter += 1;
 
-        // Update global timer (simulates timer interrupt)
-        update_global_timer(timer_counter);
+        // Restart supervised tasks that faulted, stalled or exited
+        supervisor::poll(timer_counter);
 
         // Run the enhanced multi-priority scheduler
         if let Some(current_task) = schedule_with_priority() {
             let priority_level = current_priority_level();
+
+            // Deferred ISR work at or above this task's priority goes first
+            tasklet::run_pending(current_task.priority);
             
-            // Execute task based on ID and priority
-            match (current_task.id, current_task.priority) {
-                (1, TaskPriority::Critical) => {
-                    task_critical_system();
-                    arch::early_println(" [Critical task completed]");
-                },
-                (2, TaskPriority::High) => {
-                    task_high_realtime();
-                    arch::early_println(" [High priority task completed]");
-                },
-                (3, TaskPriority::Normal) => {
-                    task_normal_app();
-                    arch::early_println(" [Normal app task completed]");
-                },
-                (4, TaskPriority::Normal) => {
-                    task_message_processor();
-                    arch::early_println(" [Message processor completed]");
-                },
-                (5, TaskPriority::Low) => {
-                    task_low_background();
-                    arch::early_println(" [Background task completed]");
-                },
-                (6, TaskPriority::Low) => {
-                    task_timer_periodic();
-                    arch::early_println(" [Timer task completed]");
-                },
-                _ => {
-                    arch::early_println("⚠️  Unknown task: ");
+            // Run the task through the entry it was spawned with
+            let started = arch::cycle_counter();
+            match current_task.entry {
+                Some(entry) => entry(current_task.id),
+                None => {
+                    // Nothing to run it with: retire it rather than pick it forever
+                    arch::early_println("⚠️  Task without entry: ");
                     let id_str = u32_to_str(current_task.id as u32);
                     arch::early_println(core::str::from_utf8(&id_str).unwrap_or("?"));
+                    scheduler::exit_current_task();
                 },
             }
+            let cycles = arch::cycle_counter().wrapping_sub(started);
+            scheduler::record_run(current_task.id, current_task.priority, cycles);
+
+            supervisor::checkin(current_task.id, timer_counter);
+
+            // A task that was killed or stopped has returned at a safe point
+            if scheduler::is_cancelled() {
+                scheduler::exit_current_task();
+                supervisor::task_exited(current_task.id);
+                arch::early_println(" [Cancelled task retired]");
+            }
 
             // Show current priority level
             let priority_str = match priority_level {
@@ -273,33 +334,34 @@ fn run_enhanced_scheduler_test() -> ! {
                 TaskPriority::Low => " 🔄 LOW",
             };
             arch::early_println(priority_str);
-        } else {
+        } else if tasklet::run_pending(TaskPriority::Low) == 0 {
             arch::early_println("💤 No ready tasks - CPU can sleep");
+            power::idle();
         }
 
         // Demonstrate event posting and priority handling
         match cycle_counter % 50 {
             5 => {
                 // Post critical event (simulates interrupt)
-                if post_priority_event(0x10, EventPriority::Critical) {
+                if post_priority_event(EVENT_CRITICAL, EventPriority::Critical) {
                     arch::early_println("🚨 Posted CRITICAL interrupt event");
                 }
             },
             15 => {
                 // Post high priority event (simulates real-time deadline)
-                if post_priority_event(0x20, EventPriority::High) {
+                if post_priority_event(EVENT_REALTIME, EventPriority::High) {
                     arch::early_println("⚡ Posted HIGH priority real-time event");
                 }
             },
             25 => {
                 // Post normal event (simulates user interaction)
-                if post_priority_event(0x30, EventPriority::Normal) {
+                if post_priority_event(EVENT_USER, EventPriority::Normal) {
                     arch::early_println("📱 Posted NORMAL user event");
                 }
             },
             35 => {
                 // Post low priority event (simulates background work)
-                if post_priority_event(0x40, EventPriority::Low) {
+                if post_priority_event(EVENT_BACKGROUND, EventPriority::Low) {
                     arch::early_println("🔄 Posted LOW background event");
                 }
             },
@@ -327,6 +389,48 @@ fn run_enhanced_scheduler_test() -> ! {
             arch::early_println(" | Timer: ");
             let timer_str = u32_to_str(timer as u32);
             arch::early_println(core::str::from_utf8(&timer_str).unwrap_or("0"));
+
+            let mut uptime = heapless::String::<32>::new();
+            let _ = core::fmt::Write::write_fmt(&mut uptime, format_args!(" | Uptime: {}", kernel::time::uptime()));
+            arch::early_println(&uptime);
+
+            if let Some(now) = kernel::time::wall_clock() {
+                let mut date = heapless::String::<24>::new();
+                let _ = core::fmt::Write::write_fmt(&mut date, format_args!(" | {}", now));
+                arch::early_println(&date);
+            }
+
+            arch::early_println(" | Restarts: ");
+            let restarts_str = u32_to_str(supervisor::stats().restarts);
+            arch::early_println(core::str::from_utf8(&restarts_str).unwrap_or("0"));
+
+            // Held/refused totals per resource, for attributing exhaustion
+            for resource in accounting::Resource::ALL {
+                let (held, denied) = accounting::totals(resource);
+                if held == 0 && denied == 0 {
+                    continue;
+                }
+                arch::early_println(" | ");
+                arch::early_println(resource.name());
+                arch::early_println(" held/denied: ");
+                let held_str = u32_to_str(held);
+                arch::early_println(core::str::from_utf8(&held_str).unwrap_or("0"));
+                let denied_str = u32_to_str(denied);
+                arch::early_println(core::str::from_utf8(&denied_str).unwrap_or("0"));
+            }
+            
+            #[cfg(feature = "instrumentation")]
+            {
+                let irq_stats = instrumentation::stats();
+                
+                arch::early_println(" | Max ISR cycles: ");
+                let isr_str = u32_to_str(irq_stats.max_isr_cycles);
+                arch::early_println(core::str::from_utf8(&isr_str).unwrap_or("0"));
+                
+                arch::early_println(" | Max IRQ-off cycles: ");
+                let crit_str = u32_to_str(irq_stats.max_critical_cycles);
+                arch::early_println(core::str::from_utf8(&crit_str).unwrap_or("0"));
+            }
             
             arch::early_println("");
             
@@ -344,20 +448,18 @@ fn run_enhanced_scheduler_test() -> ! {
             arch::early_println("Posting multiple events to test priority handling...");
             
             // Post events in reverse priority order to test preemption
-            let _ = post_priority_event(0x50, EventPriority::Low);
-            let _ = post_priority_event(0x51, EventPriority::Normal);
-            let _ = post_priority_event(0x52, EventPriority::High);
-            let _ = post_priority_event(0x53, EventPriority::Critical);
+            let _ = post_priority_event(EVENT_TICK, EventPriority::Low);
+            let _ = post_priority_event(EVENT_MESSAGE, EventPriority::Normal);
+            let _ = post_priority_event(EVENT_SCENARIO_HIGH, EventPriority::High);
+            let _ = post_priority_event(EVENT_SCENARIO_CRITICAL, EventPriority::Critical);
             
             arch::early_println("Posted: Low->Normal->High->Critical");
             arch::early_println("Expected execution order: Critical->High->Normal->Low");
             arch::early_println("");
         }
 
-        // Small delay for readability (architecture-agnostic)
-        for _ in 0..8000 {
-            scheduler::yield_now();
-        }
+        // Small delay for readability, the same at any CPU clock
+        kernel::time::delay_ms(5);
 
         // Demonstrate sleep functionality periodically
         if cycle_counter % 300 == 0 {
@@ -372,12 +474,25 @@ fn run_enhanced_scheduler_test() -> ! {
 #[cfg(target_arch = "arm")]
 #[entry]
 fn main() -> ! {
-    // Test basic semihosting
+    // Pick the console and memory map, then bring up clocks and pins
+    // before any UART output
+    board::detect(0);
+    board::init_board();
+    
+    // Test basic semihosting (QEMU only; real boards have no host attached)
+    #[cfg(not(any(feature = "board_stm32f407", feature = "board_nrf52840")))]
     hprintln!("Hello from ARM Cortex-M3!");
-    arch::early_println("ARM UART initialized");
+    banner::print();
 
-    // Run the enhanced scheduler test
-    run_enhanced_scheduler_test()
+    // Exceptions stay on MSP; the scheduler and tasks move to PSP
+    arch::arm::enter_process_stack(arm_thread_entry)
+}
+
+/// ARM thread-mode entry, running on the process stack
+#[cfg(target_arch = "arm")]
+extern "C" fn arm_thread_entry() -> ! {
+    kernel::init();
+    start()
 }
 
 /// Main entry point for the kernel
@@ -386,7 +501,17 @@ fn main() -> ! {
 pub fn kernel_main() -> ! {
     // Initialize and run the kernel with enhanced scheduler test
     kernel::init();
-    run_enhanced_scheduler_test()
+    start()
+}
+
+/// Host simulator entry point
+#[cfg(feature = "sim")]
+fn main() -> ! {
+    board::detect(0);
+    board::init_board();
+    banner::print();
+    kernel::init();
+    start()
 }
 
 // Architecture-specific entry points
@@ -394,7 +519,11 @@ pub fn kernel_main() -> ! {
 /// RISC-V specific entry point
 #[cfg(target_arch = "riscv32")]
 #[riscv_rt::entry]
-fn main() -> ! {
-    arch::early_println("RISC-V entry point reached");
-    run_enhanced_scheduler_test()
+fn main(_hartid: usize, dtb: usize) -> ! {
+    // QEMU passes the device tree in a1; real boards fall back to static
+    board::detect(dtb);
+    board::init_board();
+    banner::print();
+    kernel::init();
+    start()
 }
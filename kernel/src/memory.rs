@@ -1,40 +1,20 @@
 //! Memory layout configuration
 //! Architecture-agnostic memory layout definitions
 
+use crate::arch::atomic::{AtomicBool, Ordering};
+use crate::board::{LinkerLayout, MemoryRegion};
+
+pub mod checked;
+
 /// Get memory regions for the current target
 #[allow(dead_code)]
 pub fn get_memory_regions() -> MemoryRegions {
-    #[cfg(all(target_arch = "arm", target_os = "none"))]
-    {
-        MemoryRegions {
-            ram_start: 0x20000000,
-            ram_size: 64 * 1024,
-            flash_start: 0x00000000,
-            flash_size: 256 * 1024,
-        }
-    }
-    
-    #[cfg(all(target_arch = "riscv32", target_os = "none"))]
-    {
-        MemoryRegions {
-            ram_start: 0x80000000,
-            ram_size: 128 * 1024,
-            flash_start: 0x20000000,
-            flash_size: 512 * 1024,
-        }
-    }
-    
-    #[cfg(not(any(
-        all(target_arch = "arm", target_os = "none"),
-        all(target_arch = "riscv32", target_os = "none")
-    )))]
-    {
-        MemoryRegions {
-            ram_start: 0,
-            ram_size: 0,
-            flash_start: 0,
-            flash_size: 0,
-        }
+    let board = crate::board::descriptor();
+    MemoryRegions {
+        ram_start: board.ram.start,
+        ram_size: board.ram.size,
+        flash_start: board.flash.start,
+        flash_size: board.flash.size,
     }
 }
 
@@ -73,3 +53,136 @@ impl MemoryRegions {
         self.ram_size / 4
     }
 }
+
+// -------- Address checks --------
+
+/// Kind of memory an address range lies in
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    /// Flash, or its data-bus window on direct-boot ESP32-C3
+    Flash,
+    /// Device registers listed in the board's `mmio` table
+    Mmio,
+}
+
+/// Region all of `addr..addr + len` lies in
+///
+/// `None` for null, for ranges that leave every region and for ranges
+/// spanning two regions, even adjacent ones.
+pub fn region_kind(addr: usize, len: usize) -> Option<RegionKind> {
+    let end = addr.checked_add(len)?;
+    if addr == 0 {
+        return None;
+    }
+    let board = crate::board::descriptor();
+    let contains = |region: &MemoryRegion| addr >= region.start && end <= region.end();
+    // Direct-boot ESP32-C3 reads .rodata through the DROM window
+    let in_drom = match board.layout {
+        LinkerLayout::Esp32c3Direct { drom } => contains(&MemoryRegion { start: drom, size: board.flash.size }),
+        _ => false,
+    };
+
+    if contains(&board.ram) {
+        Some(RegionKind::Ram)
+    } else if contains(&board.flash) || in_drom {
+        Some(RegionKind::Flash)
+    } else if board.mmio.iter().any(contains) {
+        Some(RegionKind::Mmio)
+    } else {
+        None
+    }
+}
+
+/// Read one `width`-byte unit (1, 2 or 4) with a single access that wide
+///
+/// Device registers may not tolerate any other access width.
+///
+/// # Safety
+/// `addr` must be aligned and readable, e.g. checked with `checked::check`.
+pub unsafe fn read_unit(addr: usize, width: usize) -> u32 {
+    match width {
+        1 => core::ptr::read_volatile(addr as *const u8) as u32,
+        2 => core::ptr::read_volatile(addr as *const u16) as u32,
+        _ => core::ptr::read_volatile(addr as *const u32),
+    }
+}
+
+/// Write one `width`-byte unit (1, 2 or 4) with a single access that wide
+///
+/// # Safety
+/// `addr` must be aligned and writable; anything may live there.
+pub unsafe fn write_unit(addr: usize, width: usize, value: u32) {
+    match width {
+        1 => core::ptr::write_volatile(addr as *mut u8, value as u8),
+        2 => core::ptr::write_volatile(addr as *mut u16, value as u16),
+        _ => core::ptr::write_volatile(addr as *mut u32, value),
+    }
+}
+
+// -------- Stack watermark --------
+//
+// Tasks run to completion on the one thread stack, so its deepest use is
+// the worst case over all tasks and the interrupts that hit them. At boot
+// the free part is filled with a pattern; the lowest word no longer
+// holding it marks the high-water point.
+
+/// Fill word for unused stack
+const STACK_PAINT: u32 = 0x5A5A_C3C3;
+
+/// Room left unpainted below the boot frame for interrupts and spills
+const PAINT_MARGIN: usize = 512;
+
+/// Deepest part of the stack watched; QEMU virt leaves most of its 128M
+/// of RAM to the stack
+const MAX_WATCHED_STACK: usize = 64 * 1024;
+
+static STACK_PAINTED: AtomicBool = AtomicBool::new(false);
+
+/// Watched thread stack size and its deepest use so far, in bytes
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct StackUsage {
+    pub size: usize,
+    pub peak: usize,
+}
+
+/// Word-aligned (bottom, top) of the watched part of the thread stack
+fn watched_stack() -> Option<(usize, usize)> {
+    let (bottom, top) = crate::arch::thread_stack()?;
+    let bottom = bottom.max(top.saturating_sub(MAX_WATCHED_STACK));
+    Some(((bottom + 3) & !3, top))
+}
+
+/// Boot step: paint the thread stack below the current frame
+pub fn paint_stack() -> crate::kernel::InitResult {
+    let (bottom, top) = watched_stack().ok_or("no thread stack")?;
+    let marker = 0u8;
+    let here = core::ptr::addr_of!(marker) as usize;
+    if here <= bottom || here > top {
+        return Err("not running on the thread stack");
+    }
+    let end = here.saturating_sub(PAINT_MARGIN) & !3;
+    let mut addr = bottom;
+    while addr < end {
+        unsafe { core::ptr::write_volatile(addr as *mut u32, STACK_PAINT) };
+        addr += 4;
+    }
+    STACK_PAINTED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Peak thread stack use, `None` if the stack was not painted at boot
+#[allow(dead_code)]
+pub fn stack_usage() -> Option<StackUsage> {
+    if !STACK_PAINTED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let (bottom, top) = watched_stack()?;
+    let mut addr = bottom;
+    while addr < top && unsafe { core::ptr::read_volatile(addr as *const u32) } == STACK_PAINT {
+        addr += 4;
+    }
+    Some(StackUsage { size: top - bottom, peak: top - addr })
+}
@@ -0,0 +1,108 @@
+//! Checked memory access
+//! Addresses validated against the board's memory map before use
+//!
+//! A wrong address during board bring-up (a base from the wrong manual
+//! page, an offset off by a block) otherwise ends in a bus fault with
+//! little to go on. `check` looks the range up in the board descriptor
+//! (RAM, flash and the `mmio` table) first, so the shell's `peek` and
+//! `poke` and the host protocol answer with an error instead.
+//!
+//! Drivers reach their registers through `reg_read` and `reg_write`. With
+//! the `bringup` feature, debug builds check each access the same way: a
+//! register outside the device windows is logged with the driver's source
+//! location and skipped, and a skipped read returns 0. A driver polling a
+//! skipped register waits for ever, but with the log saying why. Other
+//! builds compile both to a plain volatile access. The console UART is
+//! left out, as the reports go through it.
+
+use core::panic::Location;
+
+use super::RegionKind;
+
+/// What an access does with the memory it reaches
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Driver access to a device register
+    Register,
+}
+
+/// Why an address was refused
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessError {
+    Unaligned,
+    /// Outside every region the board describes, or spanning two
+    Unmapped,
+    /// A write to flash
+    ReadOnly,
+    /// A register access outside the device windows
+    NotDevice,
+}
+
+impl AccessError {
+    pub const fn message(self) -> &'static str {
+        match self {
+            AccessError::Unaligned => "address not aligned to the access width",
+            AccessError::Unmapped => "outside RAM, flash and device registers",
+            AccessError::ReadOnly => "flash is read-only",
+            AccessError::NotDevice => "not a device register",
+        }
+    }
+}
+
+/// Check `addr..addr + len`, accessed in `width`-byte units
+pub fn check(addr: usize, len: usize, width: usize, access: Access) -> Result<RegionKind, AccessError> {
+    if !addr.is_multiple_of(width) {
+        return Err(AccessError::Unaligned);
+    }
+    let kind = super::region_kind(addr, len).ok_or(AccessError::Unmapped)?;
+    match (access, kind) {
+        (Access::Write, RegionKind::Flash) => Err(AccessError::ReadOnly),
+        (Access::Register, RegionKind::Ram | RegionKind::Flash) => Err(AccessError::NotDevice),
+        _ => Ok(kind),
+    }
+}
+
+/// Whether driver register accesses are checked in this build
+const CHECK_REGISTERS: bool = cfg!(all(feature = "bringup", debug_assertions));
+
+/// Read the 32-bit device register at `addr`
+///
+/// # Safety
+/// `addr` must be a readable register; reading one can have side effects.
+#[allow(dead_code)]
+#[inline(always)]
+#[track_caller]
+pub unsafe fn reg_read(addr: usize) -> u32 {
+    if CHECK_REGISTERS {
+        if let Err(error) = check(addr, 4, 4, Access::Register) {
+            reject("read", addr, error, Location::caller());
+            return 0;
+        }
+    }
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+/// Write the 32-bit device register at `addr`
+///
+/// # Safety
+/// `addr` must be a writable register of a device the caller drives.
+#[allow(dead_code)]
+#[inline(always)]
+#[track_caller]
+pub unsafe fn reg_write(addr: usize, value: u32) {
+    if CHECK_REGISTERS {
+        if let Err(error) = check(addr, 4, 4, Access::Register) {
+            reject("write", addr, error, Location::caller());
+            return;
+        }
+    }
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
+
+#[cold]
+#[inline(never)]
+fn reject(op: &str, addr: usize, error: AccessError, at: &Location) {
+    crate::log_error_ratelimited!(1, "[memcheck] {} {:#010x} skipped at {}:{}: {}", op, addr, at.file(), at.line(), error.message());
+}
@@ -0,0 +1,248 @@
+//! Metrics registry
+//! Named counters and gauges for driver and application health (`metrics`)
+//!
+//! `counter!("uart.rx.overrun").inc()` counts an occurrence and
+//! `gauge!("pipe.fill").set(n)` records a level. Each macro use is a
+//! static handle that looks its name up in the registry on its first
+//! update and keeps the index, so later updates are one atomic operation,
+//! and uses in several places with the same name share one value. The
+//! shell's `stats` lists the registry and host tools read it with the
+//! host protocol's metrics request, so a driver's health shows up
+//! without a print added for the occasion.
+//!
+//! The registry has `MAX_METRICS` entries and entries are never removed.
+//! A handle whose name finds it full is left out for good, and `refused`
+//! counts those handles. Values are u32: counters wrap, gauges keep the last value set.
+//! Names are dotted, subsystem first. Without the feature the updates
+//! compile to nothing and the registry reads empty, so call sites need
+//! no `cfg` guards.
+
+#[cfg(feature = "metrics")]
+use core::cell::UnsafeCell;
+
+use crate::arch::atomic::AtomicU32;
+#[cfg(feature = "metrics")]
+use crate::arch::atomic::{AtomicUsize, Ordering};
+
+#[allow(unused_imports)]
+pub use crate::{counter, gauge};
+
+/// Entries the registry holds
+pub const MAX_METRICS: usize = 32;
+
+/// What a metric's value means
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    /// Occurrences so far
+    Counter = 0,
+    /// Level last set
+    Gauge = 1,
+}
+
+impl Kind {
+    #[allow(dead_code)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        }
+    }
+}
+
+/// A registry entry as read back
+#[derive(Copy, Clone, Debug)]
+pub struct Sample {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub value: u32,
+}
+
+/// A handle's slot before its first update
+#[cfg(feature = "metrics")]
+const UNRESOLVED: u32 = 0;
+
+/// A handle's slot once the registry turned its name away
+#[cfg(feature = "metrics")]
+const LEFT_OUT: u32 = u32::MAX;
+
+/// Count of something that happens; made by `counter!`
+pub struct Counter {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    /// Registry index plus one, or `UNRESOLVED` or `LEFT_OUT`
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    slot: AtomicU32,
+}
+
+impl Counter {
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, slot: AtomicU32::new(0) }
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn add(&self, _count: u32) {
+        #[cfg(feature = "metrics")]
+        if let Some(index) = resolve(&self.slot, self.name, Kind::Counter) {
+            VALUES[index].fetch_add(_count, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Level of something that rises and falls; made by `gauge!`
+pub struct Gauge {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    /// As in `Counter`
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    slot: AtomicU32,
+}
+
+impl Gauge {
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, slot: AtomicU32::new(0) }
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn set(&self, _value: u32) {
+        #[cfg(feature = "metrics")]
+        if let Some(index) = resolve(&self.slot, self.name, Kind::Gauge) {
+            VALUES[index].store(_value, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The counter named `$name`, registered on its first update
+#[macro_export]
+macro_rules! counter {
+    ($name:expr) => {{
+        static COUNTER: $crate::metrics::Counter = $crate::metrics::Counter::new($name);
+        &COUNTER
+    }};
+}
+
+/// The gauge named `$name`, registered on its first update
+#[macro_export]
+macro_rules! gauge {
+    ($name:expr) => {{
+        static GAUGE: $crate::metrics::Gauge = $crate::metrics::Gauge::new($name);
+        &GAUGE
+    }};
+}
+
+#[cfg(feature = "metrics")]
+struct Names(UnsafeCell<[(&'static str, Kind); MAX_METRICS]>);
+#[cfg(feature = "metrics")]
+unsafe impl Sync for Names {} // Written with interrupts masked; entries below `REGISTERED` never change
+
+#[cfg(feature = "metrics")]
+static NAMES: Names = Names(UnsafeCell::new([("", Kind::Counter); MAX_METRICS]));
+#[cfg(feature = "metrics")]
+static VALUES: [AtomicU32; MAX_METRICS] = [const { AtomicU32::new(0) }; MAX_METRICS];
+/// Entries of `NAMES` in use, published after the entry is written
+#[cfg(feature = "metrics")]
+static REGISTERED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "metrics")]
+static REFUSED: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "metrics")]
+#[inline(always)]
+fn resolve(slot: &AtomicU32, name: &'static str, kind: Kind) -> Option<usize> {
+    match slot.load(Ordering::Relaxed) {
+        UNRESOLVED => register(slot, name, kind),
+        LEFT_OUT => None,
+        index => Some(index as usize - 1),
+    }
+}
+
+/// Find or add `name`, and keep the answer in the handle's `slot`
+///
+/// Updates come from interrupt handlers too, so interrupts are masked
+/// and left as they were found.
+#[cfg(feature = "metrics")]
+#[cold]
+#[inline(never)]
+fn register(slot: &AtomicU32, name: &'static str, kind: Kind) -> Option<usize> {
+    crate::arch::free(|| {
+        let names = NAMES.0.get();
+        let count = REGISTERED.load(Ordering::Relaxed);
+        // Readers only look below `count`; the new entry goes at `count`
+        let known = unsafe { &(&*names)[..count] };
+        let index = match known.iter().position(|&(entry, _)| entry == name) {
+            Some(index) => {
+                crate::kassert!(known[index].1 == kind, "metric {} is both a counter and a gauge", name);
+                (known[index].1 == kind).then_some(index)
+            }
+            None if count < MAX_METRICS => {
+                unsafe { (*names)[count] = (name, kind) };
+                REGISTERED.store(count + 1, Ordering::Release);
+                Some(count)
+            }
+            None => None,
+        };
+        if index.is_none() {
+            REFUSED.fetch_add(1, Ordering::Relaxed);
+        }
+        slot.store(index.map_or(LEFT_OUT, |index| index as u32 + 1), Ordering::Relaxed);
+        index
+    })
+}
+
+/// Entries registered so far; zero without the feature
+#[allow(dead_code)]
+pub fn len() -> usize {
+    #[cfg(feature = "metrics")]
+    {
+        REGISTERED.load(Ordering::Acquire)
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    {
+        0
+    }
+}
+
+/// Entry `index`, in registration order
+#[allow(dead_code)]
+pub fn get(_index: usize) -> Option<Sample> {
+    #[cfg(feature = "metrics")]
+    {
+        if _index >= len() {
+            return None;
+        }
+        let (name, kind) = unsafe { (*NAMES.0.get())[_index] };
+        Some(Sample { name, kind, value: VALUES[_index].load(Ordering::Relaxed) })
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    {
+        None
+    }
+}
+
+/// Handles left out because the registry was full
+#[allow(dead_code)]
+pub fn refused() -> u32 {
+    #[cfg(feature = "metrics")]
+    {
+        REFUSED.load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    {
+        0
+    }
+}
@@ -0,0 +1,620 @@
+//! Network service
+//! smoltcp TCP/IP in a task of its own, its sockets used through message queues (`net`)
+//!
+//! One task owns the interface and every socket; other tasks never touch
+//! smoltcp. They ask with `open`, `send`, `send_to` and `close`, which
+//! queue a request for the task, and hear back on an inbox channel of
+//! their own, given at `open`, as `NetReply`s. Sockets are named by
+//! generation-tagged handles (`HandleKind::Socket`), so a request for a
+//! socket that has been closed is refused rather than reaching whoever
+//! has its slot now. `Closed` is the last reply about a socket.
+//!
+//! The task runs when a request arrives, when a driver reports a frame
+//! (`rx_ready`) or a link change (`link_changed`), and when smoltcp
+//! wants to be polled next: each run starts a kernel `Timer` for
+//! smoltcp's poll delay, at most `LINK_POLL`, whose expiry wakes it. Link
+//! state changes are posted as `LINK_UP_EVENT` and `LINK_DOWN_EVENT`.
+//!
+//! Received bytes are delivered up to `CHUNK` at a time. A socket whose
+//! owner's inbox is full holds that reply and reads no further, so a TCP
+//! peer sees the window close instead of data being dropped; held
+//! replies are retried every `RETRY`. A `send` takes up to `CHUNK` bytes
+//! and is answered with `Sent` and the count the socket took; the caller
+//! sends the rest again, as after a short pipe write.
+//!
+//! `init` starts the service on the QEMU virt machine's virtio-net
+//! device, when there is one, at QEMU user networking's 10.0.2.15/24.
+//! Everywhere else it uses a `Loopback` at 127.0.0.1/8; a board with a
+//! driver of its own calls `start` with its device and addresses instead.
+
+mod device;
+mod loopback;
+
+use core::cell::UnsafeCell;
+
+use heapless::{Deque, Vec};
+use smoltcp::iface::{Config as IfaceConfig, Interface, SocketHandle, SocketSet, SocketStorage};
+use smoltcp::socket::{tcp, udp};
+use smoltcp::wire::{EthernetAddress, HardwareAddress};
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::kernel::time::Duration;
+use crate::kernel::timer::{Timer, TimerMode};
+use crate::kernel::{channel, Channel, Generations, Handle, HandleError, HandleKind, InitResult, Receiver, Sender};
+use crate::scheduler::{self, EventPriority, Task, TaskPriority, SIGNAL_WAKE};
+
+use device::Phy;
+pub use device::{NetDevice, MAX_FRAME};
+pub use loopback::Loopback;
+pub use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+
+/// Task ID of the network task
+pub const TASK_ID: usize = 21;
+
+/// Protocol work runs with application tasks
+const PRIORITY: TaskPriority = TaskPriority::Normal;
+
+/// Sockets open at once
+pub const MAX_SOCKETS: usize = 4;
+
+/// Receive and transmit buffer bytes of each socket
+pub const SOCKET_BUFFER: usize = 1024;
+
+/// Datagrams a UDP socket buffers each way
+const UDP_PACKETS: usize = 4;
+
+/// Most bytes one `Data` reply or one `send` carries
+pub const CHUNK: usize = 256;
+
+/// Requests queued for the task
+pub const REQUEST_DEPTH: usize = 8;
+
+/// Replies an owner's inbox holds
+pub const INBOX_DEPTH: usize = 4;
+
+/// Replies a socket keeps while its owner's inbox is full
+const HELD_REPLIES: usize = 2;
+
+/// Posted when the link comes up
+pub const LINK_UP_EVENT: u32 = 0x1A;
+
+/// Posted when the link goes down
+pub const LINK_DOWN_EVENT: u32 = 0x1B;
+
+/// Longest the task sleeps; also how often the link is checked
+const LINK_POLL: Duration = Duration::from_secs(1);
+
+/// How soon a reply held for a full inbox is tried again
+const RETRY: Duration = Duration::from_millis(10);
+
+/// First local port of outgoing TCP connections
+const EPHEMERAL_PORTS: u16 = 49152;
+
+/// Where the task sends a socket's replies
+pub type Inbox = Sender<NetReply, INBOX_DEPTH>;
+
+/// Interface addresses
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    pub address: IpCidr,
+    pub gateway: Option<Ipv4Address>,
+}
+
+impl Config {
+    /// 127.0.0.1/8, no gateway
+    pub fn loopback() -> Self {
+        Config { address: IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8), gateway: None }
+    }
+
+    /// 10.0.2.15/24 via 10.0.2.2, the guest side of QEMU user networking
+    #[allow(dead_code)]
+    pub fn qemu_user() -> Self {
+        Config {
+            address: IpCidr::new(IpAddress::v4(10, 0, 2, 15), 24),
+            gateway: Some(Ipv4Address::new(10, 0, 2, 2)),
+        }
+    }
+}
+
+/// What `open` makes
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SocketKind {
+    /// TCP, accepting one connection on the port
+    TcpListen(u16),
+    /// TCP, connecting to the endpoint from an ephemeral port
+    TcpConnect(IpEndpoint),
+    /// UDP, bound to the port
+    Udp(u16),
+}
+
+/// Why a request failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetError {
+    /// The service has not been started
+    NotStarted,
+    /// The request queue is full; try again later
+    Busy,
+    /// Every socket is in use
+    NoSocket,
+    /// The datagram does not fit a request
+    TooLong,
+    /// smoltcp refused the listen, connect or bind
+    Refused,
+}
+
+/// What the task tells a socket's owner
+///
+/// Sized for `Data`; with no heap there is nothing to box it into.
+#[allow(dead_code, clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum NetReply {
+    /// `open` made the socket
+    Opened(Handle),
+    /// `open` failed
+    OpenFailed(NetError),
+    /// TCP: connected, or a connection was accepted
+    Connected(Handle),
+    /// Bytes received; for UDP, with the sender
+    Data { socket: Handle, from: Option<IpEndpoint>, bytes: Vec<u8, CHUNK> },
+    /// Bytes of a `send` the socket took; 0 for a datagram dropped
+    Sent { socket: Handle, count: usize },
+    /// Closed by `close` or by the peer, or the connection failed
+    Closed(Handle),
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Request {
+    Open { kind: SocketKind, inbox: Inbox },
+    Send { socket: Handle, to: Option<IpEndpoint>, bytes: Vec<u8, CHUNK> },
+    Close { socket: Handle },
+}
+
+/// One socket and who owns it
+struct Slot {
+    socket: Option<SocketHandle>,
+    tcp: bool,
+    inbox: Option<Inbox>,
+    /// TCP: `Connected` was sent
+    connected: bool,
+    /// `Closed` was sent; the socket goes once TCP has finished
+    closing: bool,
+    /// Replies a full inbox did not take yet, oldest first
+    held: Deque<NetReply, HELD_REPLIES>,
+}
+
+impl Slot {
+    const EMPTY: Slot =
+        Slot { socket: None, tcp: false, inbox: None, connected: false, closing: false, held: Deque::new() };
+}
+
+struct Service {
+    device: &'static mut dyn NetDevice,
+    iface: Interface,
+    sockets: SocketSet<'static>,
+    slots: [Slot; MAX_SOCKETS],
+    generations: Generations<MAX_SOCKETS>,
+    requests: Receiver<Request, REQUEST_DEPTH>,
+    next_port: u16,
+}
+
+struct ServiceCell(UnsafeCell<Option<Service>>);
+unsafe impl Sync for ServiceCell {} // Written by `start` before the task exists, then only by the task
+
+static SERVICE: ServiceCell = ServiceCell(UnsafeCell::new(None));
+
+/// Memory smoltcp borrows for the life of the kernel
+struct Buffers {
+    storage: [SocketStorage<'static>; MAX_SOCKETS],
+    rx: [[u8; SOCKET_BUFFER]; MAX_SOCKETS],
+    tx: [[u8; SOCKET_BUFFER]; MAX_SOCKETS],
+    rx_meta: [[udp::PacketMetadata; UDP_PACKETS]; MAX_SOCKETS],
+    tx_meta: [[udp::PacketMetadata; UDP_PACKETS]; MAX_SOCKETS],
+    rx_frame: [u8; MAX_FRAME],
+    tx_frame: [u8; MAX_FRAME],
+}
+
+struct BufferCell(UnsafeCell<Buffers>);
+unsafe impl Sync for BufferCell {} // Only reached by `start` and the task
+
+static BUFFERS: BufferCell = BufferCell(UnsafeCell::new(Buffers {
+    storage: [SocketStorage::EMPTY; MAX_SOCKETS],
+    rx: [[0; SOCKET_BUFFER]; MAX_SOCKETS],
+    tx: [[0; SOCKET_BUFFER]; MAX_SOCKETS],
+    rx_meta: [[udp::PacketMetadata::EMPTY; UDP_PACKETS]; MAX_SOCKETS],
+    tx_meta: [[udp::PacketMetadata::EMPTY; UDP_PACKETS]; MAX_SOCKETS],
+    rx_frame: [0; MAX_FRAME],
+    tx_frame: [0; MAX_FRAME],
+}));
+
+static REQUESTS: Channel<Request, REQUEST_DEPTH> = Channel::new();
+
+struct SenderCell(UnsafeCell<Option<Sender<Request, REQUEST_DEPTH>>>);
+unsafe impl Sync for SenderCell {} // Written by `start` before `TASK_HANDLE` is published
+
+static REQUEST_SENDER: SenderCell = SenderCell(UnsafeCell::new(None));
+
+/// Raw handle of the task; 0, never a valid handle, until started
+static TASK_HANDLE: AtomicU32 = AtomicU32::new(0);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+static LINK_UP: AtomicBool = AtomicBool::new(false);
+
+/// Wakes the task when smoltcp next wants to be polled
+static POLL_TIMER: Timer = Timer::new("net poll", TimerMode::Fast, wake);
+
+struct LoopbackCell(UnsafeCell<Loopback>);
+unsafe impl Sync for LoopbackCell {} // Handed to the service once, by `init`
+
+static LOOPBACK: LoopbackCell = LoopbackCell(UnsafeCell::new(Loopback::new()));
+
+/// Start the service on the board's device, or else the loopback
+/// device; in the kernel's init table
+pub fn init() -> InitResult {
+    #[cfg(all(target_arch = "riscv32", not(any(feature = "board_esp32c3", feature = "board_hifive1"))))]
+    if let Some(device) = crate::drivers::virtio_net::probe() {
+        return start(device, Config::qemu_user());
+    }
+    // `init` runs once, so this is the only reference made
+    start(unsafe { &mut *LOOPBACK.0.get() }, Config::loopback())
+}
+
+/// Start the service on `device` with the addresses in `config`
+pub fn start(device: &'static mut dyn NetDevice, config: Config) -> InitResult {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return Err("already started");
+    }
+    let (sender, requests) = channel(&REQUESTS).ok_or("request queue taken")?;
+
+    let buffers = BUFFERS.0.get();
+    let mut iface_config = IfaceConfig::new(HardwareAddress::Ethernet(EthernetAddress(device.mac())));
+    iface_config.random_seed = crate::drivers::rng::seed();
+    let (rx, tx) = unsafe { (&mut (*buffers).rx_frame, &mut (*buffers).tx_frame) };
+    let mut phy = Phy { device: &mut *device, rx, tx };
+    let mut iface = Interface::new(iface_config, &mut phy, smoltcp_now());
+    iface.update_ip_addrs(|addresses| {
+        let _ = addresses.push(config.address);
+    });
+    if let Some(gateway) = config.gateway {
+        iface.routes_mut().add_default_ipv4_route(gateway).map_err(|_| "route table full")?;
+    }
+
+    unsafe {
+        *SERVICE.0.get() = Some(Service {
+            device,
+            iface,
+            sockets: SocketSet::new(&mut (&mut (*buffers).storage)[..]),
+            slots: [const { Slot::EMPTY }; MAX_SOCKETS],
+            generations: Generations::new(HandleKind::Socket, 0),
+            requests,
+            next_port: EPHEMERAL_PORTS,
+        });
+        *REQUEST_SENDER.0.get() = Some(sender);
+    }
+    let task = Task::with_priority(TASK_ID, PRIORITY).named("net").entry(|_| run());
+    let handle = scheduler::add_priority_task(task).map_err(|_| "no task slot")?;
+    TASK_HANDLE.store(handle.raw(), Ordering::Release);
+    Ok(())
+}
+
+/// Open a socket; the task answers on `inbox` with `Opened` or `OpenFailed`
+///
+/// `inbox` is dropped if the request cannot be queued.
+#[allow(dead_code)]
+pub fn open(kind: SocketKind, inbox: Inbox) -> Result<(), NetError> {
+    request(Request::Open { kind, inbox })
+}
+
+/// Send up to `CHUNK` bytes of `data` on a TCP socket, returning how many
+/// were queued; `Sent` tells how many the socket took
+#[allow(dead_code)]
+pub fn send(socket: Handle, data: &[u8]) -> Result<usize, NetError> {
+    let count = data.len().min(CHUNK);
+    let bytes = Vec::from_slice(&data[..count]).unwrap_or_default();
+    request(Request::Send { socket, to: None, bytes }).map(|()| count)
+}
+
+/// Send `data` as one datagram from a UDP socket to `to`
+#[allow(dead_code)]
+pub fn send_to(socket: Handle, to: IpEndpoint, data: &[u8]) -> Result<(), NetError> {
+    let bytes = Vec::from_slice(data).map_err(|_| NetError::TooLong)?;
+    request(Request::Send { socket, to: Some(to), bytes })
+}
+
+/// Close a socket; TCP connections are shut down gracefully
+#[allow(dead_code)]
+pub fn close(socket: Handle) -> Result<(), NetError> {
+    request(Request::Close { socket })
+}
+
+/// Whether the link was up when the task last looked
+#[allow(dead_code)]
+pub fn link_up() -> bool {
+    LINK_UP.load(Ordering::Relaxed)
+}
+
+/// A driver received a frame (ISR-safe)
+#[allow(dead_code)]
+pub fn rx_ready() {
+    wake();
+}
+
+/// A driver saw the link change (ISR-safe)
+#[allow(dead_code)]
+pub fn link_changed() {
+    wake();
+}
+
+fn request(request: Request) -> Result<(), NetError> {
+    if TASK_HANDLE.load(Ordering::Acquire) == 0 {
+        return Err(NetError::NotStarted);
+    }
+    // Set before the handle was published
+    let sender = unsafe { (*REQUEST_SENDER.0.get()).as_ref() }.ok_or(NetError::NotStarted)?;
+    sender.try_send(request).map_err(|_| NetError::Busy)?;
+    wake();
+    Ok(())
+}
+
+fn wake() {
+    if let Some(handle) = Handle::from_raw(TASK_HANDLE.load(Ordering::Acquire)) {
+        let _ = scheduler::signal_handle(handle, SIGNAL_WAKE);
+    }
+}
+
+fn smoltcp_now() -> smoltcp::time::Instant {
+    smoltcp::time::Instant::from_micros(crate::kernel::time::now_us() as i64)
+}
+
+/// One run of the network task
+pub fn run() {
+    let Some(service) = (unsafe { (*SERVICE.0.get()).as_mut() }) else {
+        return;
+    };
+    // A wake from before this run is answered by the run itself
+    scheduler::take_signals(SIGNAL_WAKE);
+
+    service.serve_requests();
+    // Each field is borrowed on its own, apart from the socket buffers smoltcp holds
+    let buffers = BUFFERS.0.get();
+    let (rx, tx) = unsafe { (&mut (*buffers).rx_frame, &mut (*buffers).tx_frame) };
+    let now = smoltcp_now();
+    let mut phy = Phy { device: &mut *service.device, rx, tx };
+    service.iface.poll(now, &mut phy, &mut service.sockets);
+    for index in 0..MAX_SOCKETS {
+        service.report(index);
+    }
+    service.check_link();
+
+    match service.next_poll(now) {
+        Duration::ZERO => wake(),
+        delay => {
+            POLL_TIMER.start(delay);
+        }
+    }
+    scheduler::wait_signals(SIGNAL_WAKE);
+}
+
+impl Service {
+    fn serve_requests(&mut self) {
+        while let Ok(request) = self.requests.try_recv() {
+            match request {
+                Request::Open { kind, inbox } => match self.open(kind) {
+                    Ok(index) => {
+                        let handle = self.generations.handle(index);
+                        self.slots[index].inbox = Some(inbox);
+                        self.deliver(index, NetReply::Opened(handle));
+                    }
+                    Err(error) => {
+                        let _ = inbox.try_send(NetReply::OpenFailed(error));
+                    }
+                },
+                Request::Send { socket, to, bytes } => match self.resolve(socket) {
+                    Ok(index) => {
+                        let count = self.send(index, to, &bytes);
+                        self.deliver(index, NetReply::Sent { socket, count });
+                    }
+                    Err(_) => crate::metrics::counter!("net.request.stale").inc(),
+                },
+                Request::Close { socket } => match self.resolve(socket) {
+                    Ok(index) => self.close(index),
+                    Err(_) => crate::metrics::counter!("net.request.stale").inc(),
+                },
+            }
+        }
+    }
+
+    /// Make a socket in a free slot
+    fn open(&mut self, kind: SocketKind) -> Result<usize, NetError> {
+        let index = self.slots.iter().position(|slot| slot.socket.is_none()).ok_or(NetError::NoSocket)?;
+        // The socket that last borrowed this slot's buffers has been removed
+        let buffers = BUFFERS.0.get();
+        let (rx, tx) = unsafe { (&mut (&mut (*buffers).rx[index])[..], &mut (&mut (*buffers).tx[index])[..]) };
+        let socket = match kind {
+            SocketKind::TcpListen(port) => {
+                let mut socket = tcp::Socket::new(tcp::SocketBuffer::new(rx), tcp::SocketBuffer::new(tx));
+                socket.listen(port).map_err(|_| NetError::Refused)?;
+                self.sockets.add(socket)
+            }
+            SocketKind::TcpConnect(remote) => {
+                let mut socket = tcp::Socket::new(tcp::SocketBuffer::new(rx), tcp::SocketBuffer::new(tx));
+                let local = self.ephemeral_port();
+                socket.connect(self.iface.context(), remote, local).map_err(|_| NetError::Refused)?;
+                self.sockets.add(socket)
+            }
+            SocketKind::Udp(port) => {
+                let mut socket = udp::Socket::new(
+                    udp::PacketBuffer::new(unsafe { &mut (&mut (*buffers).rx_meta[index])[..] }, rx),
+                    udp::PacketBuffer::new(unsafe { &mut (&mut (*buffers).tx_meta[index])[..] }, tx),
+                );
+                socket.bind(port).map_err(|_| NetError::Refused)?;
+                self.sockets.add(socket)
+            }
+        };
+        let tcp = !matches!(kind, SocketKind::Udp(_));
+        self.slots[index] = Slot { socket: Some(socket), tcp, ..Slot::EMPTY };
+        Ok(index)
+    }
+
+    fn resolve(&self, socket: Handle) -> Result<usize, HandleError> {
+        let index = self.generations.resolve(socket)?;
+        match self.slots[index].socket {
+            Some(_) if !self.slots[index].closing => Ok(index),
+            _ => Err(HandleError::StaleHandle),
+        }
+    }
+
+    /// Queue `bytes` on the socket, returning how many it took
+    fn send(&mut self, index: usize, to: Option<IpEndpoint>, bytes: &[u8]) -> usize {
+        let Some(handle) = self.slots[index].socket else {
+            return 0;
+        };
+        if self.slots[index].tcp {
+            return self.sockets.get_mut::<tcp::Socket>(handle).send_slice(bytes).unwrap_or(0);
+        }
+        match to {
+            Some(to) if self.sockets.get_mut::<udp::Socket>(handle).send_slice(bytes, to).is_ok() => bytes.len(),
+            _ => 0,
+        }
+    }
+
+    /// Tell the owner the socket is closed and stop serving its handle
+    fn close(&mut self, index: usize) {
+        let Some(handle) = self.slots[index].socket else {
+            return;
+        };
+        if self.slots[index].tcp {
+            self.sockets.get_mut::<tcp::Socket>(handle).close();
+        }
+        let socket = self.generations.handle(index);
+        self.deliver(index, NetReply::Closed(socket));
+        self.generations.retire(index);
+        self.slots[index].closing = true;
+        self.release(index);
+    }
+
+    /// Free a closing slot once its socket is done and its last reply sent
+    fn release(&mut self, index: usize) {
+        let slot = &mut self.slots[index];
+        let Some(handle) = slot.socket else {
+            return;
+        };
+        if !slot.held.is_empty() || (slot.tcp && self.sockets.get::<tcp::Socket>(handle).is_active()) {
+            return;
+        }
+        self.sockets.remove(handle);
+        *slot = Slot::EMPTY;
+    }
+
+    /// Pass what the last poll brought to the socket's owner
+    fn report(&mut self, index: usize) {
+        let Some(handle) = self.slots[index].socket else {
+            return;
+        };
+        if !self.retry_held(index) {
+            return;
+        }
+        if self.slots[index].closing {
+            self.release(index);
+            return;
+        }
+        let socket = self.generations.handle(index);
+        if !self.slots[index].tcp {
+            while self.slots[index].held.is_empty() {
+                let mut bytes = Vec::<u8, CHUNK>::new();
+                let _ = bytes.resize(CHUNK, 0);
+                let Ok((count, meta)) = self.sockets.get_mut::<udp::Socket>(handle).recv_slice(&mut bytes) else {
+                    break;
+                };
+                bytes.truncate(count);
+                self.deliver(index, NetReply::Data { socket, from: Some(meta.endpoint), bytes });
+            }
+            return;
+        }
+
+        let tcp = self.sockets.get_mut::<tcp::Socket>(handle);
+        if !self.slots[index].connected && tcp.may_send() {
+            self.slots[index].connected = true;
+            self.deliver(index, NetReply::Connected(socket));
+        }
+        while self.slots[index].held.is_empty() {
+            let mut bytes = Vec::<u8, CHUNK>::new();
+            let _ = bytes.resize(CHUNK, 0);
+            let tcp = self.sockets.get_mut::<tcp::Socket>(handle);
+            match tcp.recv_slice(&mut bytes) {
+                Ok(count) if count > 0 => {
+                    bytes.truncate(count);
+                    self.deliver(index, NetReply::Data { socket, from: None, bytes });
+                }
+                _ => break,
+            }
+        }
+        // The peer finished sending, reset us, or never answered
+        let tcp = self.sockets.get::<tcp::Socket>(handle);
+        let finished = (self.slots[index].connected && !tcp.may_recv()) || !tcp.is_open();
+        if finished && !tcp.can_recv() && self.slots[index].held.is_empty() {
+            self.close(index);
+        }
+    }
+
+    /// Send held replies; false if the inbox is still full
+    fn retry_held(&mut self, index: usize) -> bool {
+        let slot = &mut self.slots[index];
+        let Some(inbox) = slot.inbox.as_ref() else {
+            slot.held.clear();
+            return true;
+        };
+        while let Some(reply) = slot.held.pop_front() {
+            if let Err(reply) = inbox.try_send(reply) {
+                let _ = slot.held.push_front(reply);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Send `reply` to the owner after any held ones, holding it if the
+    /// inbox is full
+    fn deliver(&mut self, index: usize, reply: NetReply) {
+        let slot = &mut self.slots[index];
+        let Some(inbox) = slot.inbox.as_ref() else {
+            return;
+        };
+        let reply = match slot.held.is_empty() {
+            true => match inbox.try_send(reply) {
+                Ok(()) => return,
+                Err(reply) => reply,
+            },
+            false => reply,
+        };
+        if slot.held.push_back(reply).is_err() {
+            crate::metrics::counter!("net.reply.dropped").inc();
+        }
+    }
+
+    fn check_link(&mut self) {
+        let up = self.device.link_up();
+        if LINK_UP.swap(up, Ordering::Relaxed) != up {
+            let event = if up { LINK_UP_EVENT } else { LINK_DOWN_EVENT };
+            scheduler::post_priority_event(event, EventPriority::Normal);
+        }
+    }
+
+    /// How long until the task should run again
+    fn next_poll(&mut self, now: smoltcp::time::Instant) -> Duration {
+        let mut delay = match self.iface.poll_delay(now, &self.sockets) {
+            Some(delay) => Duration::from_micros(delay.total_micros()).min(LINK_POLL),
+            None => LINK_POLL,
+        };
+        if self.slots.iter().any(|slot| !slot.held.is_empty()) {
+            delay = delay.min(RETRY);
+        }
+        delay
+    }
+
+    fn ephemeral_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORTS);
+        port
+    }
+}
@@ -0,0 +1,99 @@
+//! Network devices
+//! What an Ethernet driver gives the network service, and smoltcp's view of it
+//!
+//! A driver implements `NetDevice` with whole frames in and out; it never
+//! sees smoltcp. The service wraps it in `Phy`, smoltcp's `Device`, with
+//! one receive and one transmit frame buffer: a received frame is copied
+//! into the first and handed to smoltcp, and smoltcp builds an outgoing
+//! one in the second, which then goes to `transmit`. Drivers with DMA
+//! rings copy once more between their descriptors and these buffers.
+//!
+//! A driver's receive and link-change interrupts call `net::rx_ready`
+//! and `net::link_changed`, which wake the service task.
+
+use smoltcp::phy::{Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+/// Largest Ethernet frame handled, without the frame check sequence
+pub const MAX_FRAME: usize = 1514;
+
+/// An Ethernet interface, as the network service drives it
+///
+/// Called from the service task only.
+pub trait NetDevice: Send {
+    /// The interface's MAC address
+    fn mac(&self) -> [u8; 6];
+
+    /// Whether the link is up
+    fn link_up(&self) -> bool;
+
+    /// Copy the next received frame into `frame`, returning its length
+    fn receive(&mut self, frame: &mut [u8; MAX_FRAME]) -> Option<usize>;
+
+    /// Whether `transmit` can take a frame now
+    fn can_transmit(&self) -> bool;
+
+    /// Send `frame`; false if it was dropped
+    fn transmit(&mut self, frame: &[u8]) -> bool;
+}
+
+/// A `NetDevice` with its frame buffers, as a smoltcp `Device`
+pub(super) struct Phy<'a> {
+    pub device: &'a mut dyn NetDevice,
+    pub rx: &'a mut [u8; MAX_FRAME],
+    pub tx: &'a mut [u8; MAX_FRAME],
+}
+
+impl Device for Phy<'_> {
+    type RxToken<'b>
+        = RxFrame<'b>
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = TxFrame<'b>
+    where
+        Self: 'b;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(RxFrame<'_>, TxFrame<'_>)> {
+        let len = self.device.receive(self.rx)?.min(MAX_FRAME);
+        Some((RxFrame(&self.rx[..len]), TxFrame { device: &mut *self.device, buffer: self.tx }))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<TxFrame<'_>> {
+        self.device.can_transmit().then_some(TxFrame { device: &mut *self.device, buffer: self.tx })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = MAX_FRAME;
+        caps.max_burst_size = Some(1);
+        caps
+    }
+}
+
+/// A received frame
+pub(super) struct RxFrame<'a>(&'a [u8]);
+
+impl smoltcp::phy::RxToken for RxFrame<'_> {
+    fn consume<R, F: FnOnce(&[u8]) -> R>(self, f: F) -> R {
+        f(self.0)
+    }
+}
+
+/// Room for a frame to send
+pub(super) struct TxFrame<'a> {
+    device: &'a mut dyn NetDevice,
+    buffer: &'a mut [u8; MAX_FRAME],
+}
+
+impl smoltcp::phy::TxToken for TxFrame<'_> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let frame = &mut self.buffer[..len.min(MAX_FRAME)];
+        let result = f(frame);
+        if !self.device.transmit(frame) {
+            crate::metrics::counter!("net.tx.dropped").inc();
+        }
+        result
+    }
+}
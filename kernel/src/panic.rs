@@ -0,0 +1,131 @@
+//! Kernel panic handler
+//! Reports the panic over the console, then halts, reboots or exits QEMU
+//!
+//! The report covers the message and location, core registers, the task
+//! the scheduler last picked, scheduler counters, every task and queue
+//! (`kernel::dump_state`) and the newest logger lines. It writes straight to the console UART with interrupts off, so
+//! it works even if the panic hit inside a critical section. The same
+//! state is saved to `.noinit` RAM first (see `crashdump`) so it can be
+//! read back after a reboot.
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Logger lines included in the report
+pub const PANIC_LOG_LINES: usize = 8;
+
+/// Exit status reported to QEMU for a kernel panic
+pub const PANIC_EXIT_CODE: u32 = 1;
+
+/// What to do once the report is printed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Stop with interrupts disabled (keeps state for a debugger)
+    Halt = 0,
+    /// Reset the chip
+    Reboot = 1,
+    /// Exit QEMU with `PANIC_EXIT_CODE`; halts on hardware
+    QemuExit = 2,
+}
+
+impl PanicAction {
+    pub const fn name(self) -> &'static str {
+        match self {
+            PanicAction::Halt => "halt",
+            PanicAction::Reboot => "reboot",
+            PanicAction::QemuExit => "qemu exit",
+        }
+    }
+}
+
+static ACTION: AtomicU32 = AtomicU32::new(PanicAction::Halt as u32);
+#[allow(dead_code)]
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Choose what the panic handler does after reporting
+#[allow(dead_code)]
+pub fn set_action(action: PanicAction) {
+    ACTION.store(action as u32, Ordering::Relaxed);
+}
+
+/// Currently configured panic action
+pub fn action() -> PanicAction {
+    match ACTION.load(Ordering::Relaxed) {
+        1 => PanicAction::Reboot,
+        2 => PanicAction::QemuExit,
+        _ => PanicAction::Halt,
+    }
+}
+
+/// Unbuffered console writer for the report
+struct PanicConsole;
+
+impl Write for PanicConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        crate::arch::console_write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(any(target_arch = "arm", target_arch = "riscv32"))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::arch::disable_interrupts();
+    crate::coverage::hit(crate::coverage::Path::Panic);
+    let registers = crate::arch::capture_registers();
+
+    // A panic while recording or reporting (e.g. inside the logger)
+    // skips straight to the action
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        crate::crashdump::record_panic(info, &registers);
+        report(info, &registers);
+    }
+    crate::arch::console_flush();
+    finish()
+}
+
+#[allow(dead_code)]
+fn report(info: &PanicInfo, registers: &crate::arch::RegisterDump) {
+    let mut out = PanicConsole;
+    let _ = writeln!(out, "\n!!! KERNEL PANIC !!!");
+    let _ = writeln!(out, "message: {}", info.message());
+    match info.location() {
+        Some(location) => {
+            let _ = writeln!(out, "at: {}:{}:{}", location.file(), location.line(), location.column());
+        }
+        None => {
+            let _ = writeln!(out, "at: <unknown>");
+        }
+    }
+    let _ = writeln!(out, "regs: {}", registers);
+
+    let snapshot = crate::scheduler::crash_snapshot();
+    match snapshot.task {
+        Some(task) => {
+            let _ = writeln!(out, "task: {} ({:?}, {:?})", task.id, task.priority, task.state);
+        }
+        None => {
+            let _ = writeln!(out, "task: none (level {:?})", snapshot.priority);
+        }
+    }
+    let (active_tasks, total_events, timer) = snapshot.stats;
+    let _ = writeln!(out, "scheduler: {} tasks, {} events, timer {}", active_tasks, total_events, timer);
+    let _ = crate::kernel::dump_state(&mut out);
+
+    let _ = writeln!(out, "log tail:");
+    crate::logger::Logger::visit_last_lines(PANIC_LOG_LINES, |line| {
+        let _ = writeln!(out, "  {}", line);
+    });
+    let _ = writeln!(out, "action: {}", action().name());
+}
+
+#[allow(dead_code)]
+fn finish() -> ! {
+    match action() {
+        PanicAction::Halt => crate::arch::arch_shutdown(),
+        PanicAction::Reboot => crate::arch::arch_reset(),
+        PanicAction::QemuExit => crate::arch::qemu_exit(PANIC_EXIT_CODE),
+    }
+}
@@ -0,0 +1,297 @@
+//! Power management
+//! Low-power idle modes and wakeup source tracking
+//!
+//! When the scheduler has no ready work, `idle()` picks the deepest mode
+//! every registered wakeup source can still wake the core from. With no
+//! wakeup sources registered the core never sleeps, since nothing would
+//! bring it back.
+//!
+//! Tasks and drivers limit the depth further with `request`/`release`
+//! (e.g. "no deep sleep while a DMA transfer is in flight"), and drivers
+//! register suspend/resume hooks that run around deep sleep (the console
+//! drains its TX FIFO first). All power policy goes through here rather
+//! than into individual drivers.
+//!
+//! `Stop` is deeper than deep sleep only where the board has a state for
+//! it (the STM32F4 low-power regulator); a board that does calls
+//! `enable_stop` once its hooks are registered, and elsewhere deep sleep
+//! is the deepest mode.
+
+use core::cell::UnsafeCell;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::board::{TimerKind, BOARD};
+
+/// Power modes, shallowest first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PowerMode {
+    /// Keep running (no legal sleep mode)
+    Run = 0,
+    /// Core clock gated, all peripherals running (WFI)
+    Idle = 1,
+    /// High-speed clocks stopped; low-power timers and GPIO still wake
+    DeepSleep = 2,
+    /// Deep sleep with the regulator and flash in low-power mode; only
+    /// GPIO wakes, and the board restarts its clocks
+    Stop = 3,
+}
+
+impl PowerMode {
+    const fn from_raw(raw: u32) -> Self {
+        match raw {
+            1 => PowerMode::Idle,
+            2 => PowerMode::DeepSleep,
+            3 => PowerMode::Stop,
+            _ => PowerMode::Run,
+        }
+    }
+}
+
+/// Events that can bring the core out of a low-power mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WakeupSource {
+    /// Kernel tick timer
+    Tick = 0,
+    /// Console UART receive
+    UartRx = 1,
+    /// GPIO edge or level
+    Gpio = 2,
+}
+
+impl WakeupSource {
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// Deepest mode this source still works in on the current board
+    fn deepest_mode(self) -> PowerMode {
+        match self {
+            // SysTick and most high-speed timers stop with their clock
+            WakeupSource::Tick => match BOARD.timer_kind {
+                TimerKind::NrfRtc => PowerMode::DeepSleep,
+                _ => PowerMode::Idle,
+            },
+            // The UART baud generator needs the high-speed clock
+            WakeupSource::UartRx => PowerMode::Idle,
+            WakeupSource::Gpio => PowerMode::Stop,
+        }
+    }
+}
+
+const ALL_SOURCES: [WakeupSource; 3] = [WakeupSource::Tick, WakeupSource::UartRx, WakeupSource::Gpio];
+
+/// Low-power entry counters
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PowerStats {
+    pub idle_entries: u32,
+    pub deep_sleep_entries: u32,
+    pub stop_entries: u32,
+    /// Ticks spent in any low-power mode
+    pub idle_ticks: u64,
+}
+
+/// Suspend/resume callbacks for one driver
+#[derive(Clone, Copy)]
+pub struct DriverPower {
+    #[allow(dead_code)]
+    pub name: &'static str,
+    /// Called with interrupts off before entering `DeepSleep` or `Stop`
+    pub suspend: fn(PowerMode),
+    /// Called with interrupts off after waking, before any ISR runs
+    pub resume: fn(PowerMode),
+}
+
+/// Most drivers that can register power hooks
+pub const MAX_POWER_DRIVERS: usize = 8;
+
+struct DriverTable(UnsafeCell<heapless::Vec<DriverPower, MAX_POWER_DRIVERS>>);
+unsafe impl Sync for DriverTable {} // Single-core assumption
+
+static DRIVERS: DriverTable = DriverTable(UnsafeCell::new(heapless::Vec::new()));
+
+static WAKEUP_SOURCES: AtomicU32 = AtomicU32::new(0);
+static MAX_MODE: AtomicU32 = AtomicU32::new(PowerMode::Stop as u32);
+/// Set by boards with a `Stop` state of their own
+static STOP_SUPPORTED: AtomicBool = AtomicBool::new(false);
+/// Outstanding requests capping the mode at Run, Idle and DeepSleep
+static REQUESTS: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+static IDLE_ENTRIES: AtomicU32 = AtomicU32::new(0);
+static DEEP_SLEEP_ENTRIES: AtomicU32 = AtomicU32::new(0);
+static STOP_ENTRIES: AtomicU32 = AtomicU32::new(0);
+
+struct IdleTicks(UnsafeCell<u64>);
+unsafe impl Sync for IdleTicks {} // Only touched by `idle()` and `stats()` in thread mode
+
+static IDLE_TICKS: IdleTicks = IdleTicks(UnsafeCell::new(0));
+
+/// Register a source that must be able to wake the core
+#[allow(dead_code)]
+pub fn register_wakeup(source: WakeupSource) {
+    WAKEUP_SOURCES.fetch_or(source.bit(), Ordering::Relaxed);
+}
+
+/// Remove a previously registered wakeup source
+#[allow(dead_code)]
+pub fn unregister_wakeup(source: WakeupSource) {
+    WAKEUP_SOURCES.fetch_and(!source.bit(), Ordering::Relaxed);
+}
+
+/// Bitmask of registered wakeup sources (bit n = `WakeupSource` n)
+#[allow(dead_code)]
+pub fn wakeup_sources() -> u32 {
+    WAKEUP_SOURCES.load(Ordering::Relaxed)
+}
+
+/// Cap the mode `idle()` may enter (e.g. `Idle` while debugging)
+#[allow(dead_code)]
+pub fn set_max_mode(mode: PowerMode) {
+    MAX_MODE.store(mode as u32, Ordering::Relaxed);
+}
+
+/// Let `idle()` enter `Stop`; for boards whose power hooks give it a
+/// clock and regulator state distinct from deep sleep
+#[allow(dead_code)]
+pub fn enable_stop() {
+    STOP_SUPPORTED.store(true, Ordering::Relaxed);
+}
+
+/// Keep the system at or above `limit` until a matching `release`
+///
+/// Requests nest: each one must be released once. `Stop` places no limit.
+#[allow(dead_code)]
+pub fn request(limit: PowerMode) {
+    if let Some(count) = REQUESTS.get(limit as usize) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Drop a limit taken with `request`
+#[allow(dead_code)]
+pub fn release(limit: PowerMode) {
+    if let Some(count) = REQUESTS.get(limit as usize) {
+        // Saturate so an unbalanced release cannot wrap the count
+        let _ = count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+}
+
+/// Register suspend/resume hooks, run in registration order on suspend
+/// and in reverse on resume
+pub fn register_driver(driver: DriverPower) -> Result<(), DriverPower> {
+    crate::arch::free(|| unsafe { (*DRIVERS.0.get()).push(driver) })
+}
+
+/// Deepest mode any outstanding request still allows
+fn requested_limit() -> PowerMode {
+    REQUESTS
+        .iter()
+        .position(|count| count.load(Ordering::Relaxed) > 0)
+        .map_or(PowerMode::Stop, |index| PowerMode::from_raw(index as u32))
+}
+
+/// Deepest mode the board has a state for
+fn board_limit() -> PowerMode {
+    if STOP_SUPPORTED.load(Ordering::Relaxed) {
+        PowerMode::Stop
+    } else {
+        PowerMode::DeepSleep
+    }
+}
+
+/// Deepest mode legal for the wakeup sources, requests and mode cap
+pub fn select_mode() -> PowerMode {
+    let sources = WAKEUP_SOURCES.load(Ordering::Relaxed);
+    if sources == 0 {
+        return PowerMode::Run;
+    }
+
+    let deepest = ALL_SOURCES
+        .iter()
+        .filter(|source| sources & source.bit() != 0)
+        .map(|source| source.deepest_mode())
+        .min()
+        .unwrap_or(PowerMode::Run);
+
+    deepest
+        .min(board_limit())
+        .min(requested_limit())
+        .min(PowerMode::from_raw(MAX_MODE.load(Ordering::Relaxed)))
+}
+
+/// Sleep until the next wakeup if the scheduler has nothing to run
+///
+/// Returns the mode that was entered (`Run` if the core did not sleep).
+pub fn idle() -> PowerMode {
+    let mode = select_mode();
+    if mode == PowerMode::Run {
+        return mode;
+    }
+
+    // Check for work and sleep with interrupts masked so a wakeup that
+    // arrives in between stays pending and ends the sleep immediately
+    crate::arch::disable_interrupts();
+    if crate::scheduler::has_ready_work_locked() || crate::tasklet::has_pending_locked() {
+        crate::arch::enable_interrupts();
+        return PowerMode::Run;
+    }
+
+    // Idle keeps every clock running, so only deeper modes suspend drivers
+    let suspend = mode >= PowerMode::DeepSleep;
+    let drivers = unsafe { &*DRIVERS.0.get() };
+    if suspend {
+        drivers.iter().for_each(|driver| (driver.suspend)(mode));
+    }
+
+    let asleep_at = crate::kernel::time::now();
+    crate::arch::enter_low_power(mode);
+
+    if suspend {
+        drivers.iter().rev().for_each(|driver| (driver.resume)(mode));
+    }
+
+    let counter = match mode {
+        PowerMode::Idle => &IDLE_ENTRIES,
+        PowerMode::DeepSleep => &DEEP_SLEEP_ENTRIES,
+        _ => &STOP_ENTRIES,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    // The wakeup interrupt is serviced here
+    crate::arch::enable_interrupts();
+
+    // Read after the tick ISR has caught up with the time spent asleep
+    let slept = crate::kernel::time::ticks_between(asleep_at, crate::kernel::time::now());
+    unsafe { *IDLE_TICKS.0.get() += slept };
+    mode
+}
+
+/// Sleep until the next interrupt unless `ready` holds
+///
+/// For blocking waits in task context on state an interrupt handler
+/// changes; callers check again and loop. `ready` is checked with
+/// interrupts masked, so a change that comes before the sleep ends it at
+/// once. Tasks run on the one thread stack, so no other task runs
+/// meanwhile.
+#[allow(dead_code)]
+pub fn sleep_unless(ready: impl Fn() -> bool) {
+    // Not `arch::free`: time asleep is not interrupt latency
+    let enabled = crate::arch::interrupts_enabled();
+    crate::arch::disable_interrupts();
+    if !ready() {
+        crate::arch::enter_low_power(PowerMode::Idle);
+    }
+    if enabled {
+        crate::arch::enable_interrupts();
+    }
+}
+
+/// Get low-power entry counters
+#[allow(dead_code)]
+pub fn stats() -> PowerStats {
+    PowerStats {
+        idle_entries: IDLE_ENTRIES.load(Ordering::Relaxed),
+        deep_sleep_entries: DEEP_SLEEP_ENTRIES.load(Ordering::Relaxed),
+        stop_entries: STOP_ENTRIES.load(Ordering::Relaxed),
+        idle_ticks: unsafe { *IDLE_TICKS.0.get() },
+    }
+}
@@ -20,9 +20,11 @@ pub extern "C" fn _mp_hook(hart_id: usize) -> bool {
     hart_id == 0
 }
 
-// Optional hook to set up interrupts before entering Rust main. Do nothing for now.
+// Hook to set up interrupts before entering Rust main: install the kernel trap vector.
 #[no_mangle]
-pub extern "C" fn _setup_interrupts() {}
+pub extern "C" fn _setup_interrupts() {
+    crate::arch::riscv::init_trap_vector();
+}
 
 // Optional pre-init hook called very early. Do nothing.
 #[no_mangle]
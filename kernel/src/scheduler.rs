@@ -14,14 +14,50 @@
 //! - Message-passing optimization for hot-path scheduling
 //! - Lock-free ring buffers for interrupt-safe operation
 //! - Multiple executor instances for priority-based preemption
+//!
+//! The executors' global state lives in `CriticalCell`s (`cell`), the
+//! only place the scheduler turns a static into a reference; the event
+//! queues and the hot slot are the lock-free types in `lockfree`.
 
-use core::cell::UnsafeCell;
-use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
-use core::mem::MaybeUninit;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
-// Maximum number of concurrent tasks and events
-pub const MAX_TASKS: usize = 8;
-pub const MAX_EVENTS_PER_PRIORITY: usize = 16;
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::coverage::Path;
+use crate::kernel::faults::{self, Fault};
+use crate::kernel::time::{Duration, Instant};
+use crate::kernel::{Generations, Handle, HandleError, HandleKind, TimerWheel};
+
+mod cell;
+mod lockfree;
+use cell::CriticalCell;
+use lockfree::{Handoff, LockFreeQueue};
+
+// Maximum number of concurrent tasks and events (see config.rs)
+pub const MAX_TASKS: usize = crate::config::MAX_TASKS;
+pub const MAX_EVENTS_PER_PRIORITY: usize = crate::config::EVENT_QUEUE_DEPTH;
+
+// Signal bits delivered to a task's signal word
+/// Task is being deleted: finish up, then exit
+pub const SIGNAL_KILL: u32 = 1 << 0;
+/// System is stopping (shutdown or reboot): finish up, then exit
+pub const SIGNAL_STOP: u32 = 1 << 1;
+/// A waker for the task fired: a future it awaits may be ready now
+pub const SIGNAL_WAKE: u32 = 1 << 2;
+/// Bits 8..32 carry application-defined signals, see `user_signal`
+#[allow(dead_code)]
+pub const SIGNAL_USER_MASK: u32 = 0xFFFF_FF00;
+/// Signals that mean the task should wind down
+pub const SIGNAL_CANCEL_MASK: u32 = SIGNAL_KILL | SIGNAL_STOP;
+/// Reserved event id a task blocks on while waiting only for signals
+pub const SIGNAL_WAIT_EVENT: u32 = 0x0FFF_FFFF;
+
+/// Signal bit for application-defined signal `n` (0..24)
+#[allow(dead_code)]
+pub const fn user_signal(n: u32) -> u32 {
+    1 << (8 + n)
+}
 
 /// Event priority levels for mutual exclusion and ordering
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -40,16 +76,66 @@ pub struct Event {
     pub priority: EventPriority,
     #[allow(dead_code)]
     pub data: u32,  // Optional event payload
+    /// Low 32 bits of `time::now_us()` when queued; set by `post_event`
+    /// (`instrumentation` only, to keep queued events small otherwise)
+    #[cfg(feature = "instrumentation")]
+    pub posted_us: u32,
 }
 
 impl Event {
     pub const fn new(id: u32, priority: EventPriority) -> Self {
-        Self { id, priority, data: 0 }
+        Self {
+            id,
+            priority,
+            data: 0,
+            #[cfg(feature = "instrumentation")]
+            posted_us: 0,
+        }
     }
     
     #[allow(dead_code)]
     pub const fn with_data(id: u32, priority: EventPriority, data: u32) -> Self {
-        Self { id, priority, data }
+        Self {
+            id,
+            priority,
+            data,
+            #[cfg(feature = "instrumentation")]
+            posted_us: 0,
+        }
+    }
+}
+
+/// Time events spent queued, from `post_event` until a scheduler
+/// processed them
+///
+/// Measured with `time::now_us`, so only to tick resolution on boards
+/// without a cycle counter, and only with `instrumentation`; without it
+/// every count stays zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventLatency {
+    /// Events measured
+    pub handled: u32,
+    pub min_us: u32,
+    pub max_us: u32,
+    /// Sum over all events measured, for the average
+    pub total_us: u64,
+}
+
+impl EventLatency {
+    pub const fn new() -> Self {
+        Self { handled: 0, min_us: 0, max_us: 0, total_us: 0 }
+    }
+
+    fn record(&mut self, us: u32) {
+        self.min_us = if self.handled == 0 { us } else { self.min_us.min(us) };
+        self.max_us = self.max_us.max(us);
+        self.total_us += us as u64;
+        self.handled = self.handled.wrapping_add(1);
+    }
+
+    /// Mean latency, `None` before any event was handled
+    pub fn avg_us(&self) -> Option<u32> {
+        self.total_us.checked_div(self.handled as u64).map(|avg| avg as u32)
     }
 }
 
@@ -60,7 +146,7 @@ pub enum TaskState {
     Ready,              // Ready to be polled
     Running,            // Currently executing
     WaitingForEvent(u32), // Blocked on specific event ID
-    Sleeping(u64),      // Sleeping until timestamp
+    Sleeping(Instant),  // Sleeping until then
     Completed,          // Task finished
 }
 
@@ -106,61 +192,16 @@ impl AsyncTask {
     }
 }
 
-/// Lock-free ring buffer implementation (Embassy-inspired)
-struct LockFreeEventQueue<const N: usize> {
-    buffer: [MaybeUninit<Event>; N],
-    head: AtomicUsize,
-    tail: AtomicUsize,
-}
+/// Lock-free ring buffer of one priority level's events
+type LockFreeEventQueue<const N: usize> = LockFreeQueue<Event, N>;
 
-impl<const N: usize> LockFreeEventQueue<N> {
-    const fn new() -> Self {
-        Self {
-            buffer: unsafe { MaybeUninit::uninit().assume_init() },
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
-        }
-    }
-    
-    /// Push event to queue (lock-free, ISR-safe)
-    fn push(&mut self, event: Event) -> Result<(), Event> {
-        let tail = self.tail.load(Ordering::Relaxed);
-        let head = self.head.load(Ordering::Acquire);
-        
-        if tail.wrapping_sub(head) >= N {
-            return Err(event); // Queue full
-        }
-        
-        let index = tail % N;
-        unsafe {
-            self.buffer[index].as_mut_ptr().write(event);
-        }
-        self.tail.store(tail + 1, Ordering::Release);
-        Ok(())
-    }
-    
-    /// Pop event from queue (lock-free)
-    fn pop(&self) -> Option<Event> {
-        let head = self.head.load(Ordering::Relaxed);
-        let tail = self.tail.load(Ordering::Acquire);
-        
-        if head == tail {
-            return None; // Queue empty
-        }
-        
-        let index = head % N;
-        let event = unsafe { self.buffer[index].as_ptr().read() };
-        self.head.store(head + 1, Ordering::Release);
-        Some(event)
-    }
-    
-    #[allow(dead_code)]
-    fn is_empty(&self) -> bool {
-        let head = self.head.load(Ordering::Relaxed);
-        let tail = self.tail.load(Ordering::Acquire);
-        head == tail
-    }
-}
+/// What the main loop calls each time it picks a task, with its ID
+///
+/// Tasks run to completion: the function does one step of work and
+/// returns, having parked the task (`wait_signals`, `block_current_priority`,
+/// a periodic wait) if it has nothing more to do yet. A task that stays
+/// ready is picked again at once, ahead of every lower level.
+pub type TaskEntry = fn(usize);
 
 /// Simple task representation for compatibility
 #[derive(Clone, Debug)]
@@ -169,6 +210,26 @@ pub struct Task {
     pub priority: TaskPriority,
     pub state: TaskState,
     pub waiting_event: Option<u32>,
+    /// Pending signal bits (`SIGNAL_*`), cleared by the task itself
+    pub signals: u32,
+    /// Shown by `ps`; empty if never set
+    pub name: &'static str,
+    /// Run by the main loop; a task without one is retired when picked
+    pub entry: Option<TaskEntry>,
+    pub stats: TaskStats,
+}
+
+/// Per-task run statistics
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// Times the main loop ran the task
+    pub runs: u32,
+    /// Times the task was made ready after blocking or sleeping
+    pub wakes: u32,
+    /// Cycle-counter time spent running; 0 without a cycle counter
+    pub cycles: u64,
+    /// Activations of a periodic task that missed their deadline
+    pub deadline_misses: u32,
 }
 
 impl Task {
@@ -183,12 +244,49 @@ impl Task {
             priority,
             state: TaskState::Ready,
             waiting_event: None,
+            signals: 0,
+            name: "",
+            entry: None,
+            stats: TaskStats { runs: 0, wakes: 0, cycles: 0, deadline_misses: 0 },
         }
     }
+
+    /// Name the task for `ps` and other listings
+    #[allow(dead_code)]
+    pub const fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Have the main loop run `entry` whenever the task is picked
+    pub const fn entry(mut self, entry: TaskEntry) -> Self {
+        self.entry = Some(entry);
+        self
+    }
     
     pub fn is_ready(&self) -> bool {
         matches!(self.state, TaskState::Ready)
     }
+
+    /// A kill or stop signal is pending
+    pub fn is_cancelled(&self) -> bool {
+        self.signals & SIGNAL_CANCEL_MASK != 0
+    }
+
+    /// Make a blocked or sleeping task ready again
+    fn wake(&mut self) {
+        self.state = TaskState::Ready;
+        self.waiting_event = None;
+        self.stats.wakes = self.stats.wakes.wrapping_add(1);
+    }
+}
+
+/// Why a task was not spawned
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Every slot at the task's priority is taken (or fault injection
+    /// said so)
+    NoSlot,
 }
 
 /// Multi-Priority Executor for preemptive scheduling
@@ -198,27 +296,30 @@ pub struct MultiPriorityExecutor {
     normal_scheduler: AsyncScheduler,
     low_scheduler: AsyncScheduler,
     current_priority: AtomicU32,
+    /// Level and ID of the task the last cycle returned
+    last_run: Option<(TaskPriority, usize)>,
+    /// Cycles that returned a different task than the one before, by level
+    switches: [u32; 4],
 }
 
 impl MultiPriorityExecutor {
     pub const fn new() -> Self {
         Self {
-            critical_scheduler: AsyncScheduler::new(),
-            high_scheduler: AsyncScheduler::new(),
-            normal_scheduler: AsyncScheduler::new(),
-            low_scheduler: AsyncScheduler::new(),
+            critical_scheduler: AsyncScheduler::with_handle_base(TaskPriority::Critical as usize * MAX_TASKS),
+            high_scheduler: AsyncScheduler::with_handle_base(TaskPriority::High as usize * MAX_TASKS),
+            normal_scheduler: AsyncScheduler::with_handle_base(TaskPriority::Normal as usize * MAX_TASKS),
+            low_scheduler: AsyncScheduler::with_handle_base(TaskPriority::Low as usize * MAX_TASKS),
             current_priority: AtomicU32::new(TaskPriority::Low as u32),
+            last_run: None,
+            switches: [0; 4],
         }
     }
     
     /// Add task to appropriate priority scheduler
-    pub fn spawn_task(&mut self, task: Task) -> Result<usize, ()> {
-        match task.priority {
-            TaskPriority::Critical => self.critical_scheduler.spawn_task(task),
-            TaskPriority::High => self.high_scheduler.spawn_task(task),
-            TaskPriority::Normal => self.normal_scheduler.spawn_task(task),
-            TaskPriority::Low => self.low_scheduler.spawn_task(task),
-        }
+    pub fn spawn_task(&mut self, task: Task) -> Result<Handle, SpawnError> {
+        let level = self.level_mut(task.priority);
+        let slot = level.spawn_task(task)?;
+        Ok(level.task_handle(slot))
     }
     
     /// Post event to appropriate priority queue
@@ -233,6 +334,17 @@ impl MultiPriorityExecutor {
     
     /// Run one scheduling cycle with priority-based preemption
     pub fn run_cycle(&mut self) -> Option<Task> {
+        let task = self.pick()?;
+        if self.last_run != Some((task.priority, task.id)) {
+            self.last_run = Some((task.priority, task.id));
+            let switches = &mut self.switches[task.priority as usize];
+            *switches = switches.wrapping_add(1);
+        }
+        Some(task)
+    }
+
+    /// Task to run next, from the highest level that has one
+    fn pick(&mut self) -> Option<Task> {
         // Critical tasks preempt everything
         if let Some(task) = self.critical_scheduler.schedule() {
             self.current_priority.store(TaskPriority::Critical as u32, Ordering::Release);
@@ -257,6 +369,7 @@ impl MultiPriorityExecutor {
             return Some(task.clone());
         }
         
+        crate::coverage::hit(Path::ExecutorIdle);
         None
     }
     
@@ -277,6 +390,228 @@ impl MultiPriorityExecutor {
             _ => TaskPriority::Low,
         }
     }
+
+    /// Block the task last picked at the current level until `event_id`
+    pub fn block_current(&mut self, event_id: u32) {
+        match self.current_priority() {
+            TaskPriority::Critical => self.critical_scheduler.block_current_task(event_id),
+            TaskPriority::High => self.high_scheduler.block_current_task(event_id),
+            TaskPriority::Normal => self.normal_scheduler.block_current_task(event_id),
+            TaskPriority::Low => self.low_scheduler.block_current_task(event_id),
+        }
+    }
+
+    /// Wake the first task waiting on `event_id` at each level, without
+    /// queuing an event
+    pub fn wake(&mut self, event_id: u32) {
+        self.critical_scheduler.wake_waiting_tasks(event_id);
+        self.high_scheduler.wake_waiting_tasks(event_id);
+        self.normal_scheduler.wake_waiting_tasks(event_id);
+        self.low_scheduler.wake_waiting_tasks(event_id);
+    }
+
+    /// Put the task last picked at the current level to sleep for `duration`
+    pub fn sleep_current(&mut self, duration: Duration) {
+        self.current_level_mut().sleep_current_task(duration);
+    }
+
+    /// Advance every level's timer wheel to `now`, waking the sleepers
+    /// whose deadline came up
+    pub fn update_timer(&mut self, now: Instant) {
+        for level in self.levels_mut() {
+            level.update_timer(now);
+        }
+    }
+
+    /// Whether a task sleeps at any level
+    pub fn has_sleeping_tasks(&self) -> bool {
+        self.critical_scheduler.has_sleeping_tasks() ||
+        self.high_scheduler.has_sleeping_tasks() ||
+        self.normal_scheduler.has_sleeping_tasks() ||
+        self.low_scheduler.has_sleeping_tasks()
+    }
+
+    fn levels_mut(&mut self) -> [&mut AsyncScheduler; 4] {
+        [
+            &mut self.critical_scheduler,
+            &mut self.high_scheduler,
+            &mut self.normal_scheduler,
+            &mut self.low_scheduler,
+        ]
+    }
+
+    fn level_mut(&mut self, priority: TaskPriority) -> &mut AsyncScheduler {
+        match priority {
+            TaskPriority::Critical => &mut self.critical_scheduler,
+            TaskPriority::High => &mut self.high_scheduler,
+            TaskPriority::Normal => &mut self.normal_scheduler,
+            TaskPriority::Low => &mut self.low_scheduler,
+        }
+    }
+
+    fn current_level_mut(&mut self) -> &mut AsyncScheduler {
+        self.level_mut(self.current_priority())
+    }
+
+    /// Level and slot of the live task `handle` names
+    fn resolve(&mut self, handle: Handle) -> Result<(&mut AsyncScheduler, usize), HandleError> {
+        let level = match handle.index() / MAX_TASKS {
+            0 => &mut self.critical_scheduler,
+            1 => &mut self.high_scheduler,
+            2 => &mut self.normal_scheduler,
+            3 => &mut self.low_scheduler,
+            _ => return Err(HandleError::Invalid),
+        };
+        let slot = level.resolve(handle)?;
+        Ok((level, slot))
+    }
+
+    /// Copy of the task `handle` names
+    pub fn task_by_handle(&mut self, handle: Handle) -> Result<Task, HandleError> {
+        let (level, slot) = self.resolve(handle)?;
+        level.tasks[slot].clone().ok_or(HandleError::StaleHandle)
+    }
+
+    /// Raise `bits` on the task `handle` names
+    pub fn signal_handle(&mut self, handle: Handle, bits: u32) -> Result<(), HandleError> {
+        let (level, slot) = self.resolve(handle)?;
+        level.deliver_signal(slot, bits);
+        Ok(())
+    }
+
+    /// Remove the task `handle` names
+    pub fn remove_handle(&mut self, handle: Handle) -> Result<(), HandleError> {
+        let (level, slot) = self.resolve(handle)?;
+        level.remove_slot(slot);
+        Ok(())
+    }
+
+    /// Raise `bits` on the task with `task_id`, whatever its level
+    pub fn signal(&mut self, task_id: usize, bits: u32) -> bool {
+        self.levels_mut().into_iter().any(|level| level.signal_task(task_id, bits))
+    }
+
+    /// Make task `task_id` at `priority` ready if it is waiting or sleeping
+    pub fn wake_task(&mut self, task_id: usize, priority: TaskPriority) -> bool {
+        // A signal with no bits only wakes
+        self.level_mut(priority).signal_task(task_id, 0)
+    }
+
+    /// Add one run of `cycles` to the statistics of task `task_id`
+    pub fn record_run(&mut self, task_id: usize, priority: TaskPriority, cycles: u32) -> bool {
+        let level = self.level_mut(priority);
+        match level.tasks.iter_mut().flatten().find(|task| task.id == task_id) {
+            Some(task) => {
+                task.stats.runs = task.stats.runs.wrapping_add(1);
+                task.stats.cycles = task.stats.cycles.wrapping_add(cycles as u64);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add `misses` deadline misses to the statistics of task `task_id`
+    pub fn record_misses(&mut self, task_id: usize, priority: TaskPriority, misses: u32) -> bool {
+        let level = self.level_mut(priority);
+        match level.tasks.iter_mut().flatten().find(|task| task.id == task_id) {
+            Some(task) => {
+                task.stats.deadline_misses = task.stats.deadline_misses.wrapping_add(misses);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Raise `bits` on every task, returning how many were signalled
+    pub fn signal_all(&mut self, bits: u32) -> u32 {
+        self.levels_mut().into_iter().map(|level| level.signal_all(bits)).sum()
+    }
+
+    /// Clear and return the current task's pending signals within `mask`
+    pub fn take_signals(&mut self, mask: u32) -> u32 {
+        self.current_level_mut().take_current_signals(mask)
+    }
+
+    /// Remove the current task from its level
+    pub fn exit_current(&mut self) -> bool {
+        self.current_level_mut().exit_current_task()
+    }
+
+    /// Remove the task with `task_id` from whichever level holds it
+    pub fn remove(&mut self, task_id: usize) -> bool {
+        self.levels_mut().into_iter().any(|level| level.remove_task(task_id))
+    }
+
+    /// Stop all tasks, background levels first and critical last
+    pub fn stop_all(&mut self) -> u32 {
+        self.low_scheduler.stop_all()
+            + self.normal_scheduler.stop_all()
+            + self.high_scheduler.stop_all()
+            + self.critical_scheduler.stop_all()
+    }
+
+    /// Task last picked at the current priority level
+    pub fn current_task(&self) -> Option<&Task> {
+        match self.current_priority() {
+            TaskPriority::Critical => self.critical_scheduler.current_task(),
+            TaskPriority::High => self.high_scheduler.current_task(),
+            TaskPriority::Normal => self.normal_scheduler.current_task(),
+            TaskPriority::Low => self.low_scheduler.current_task(),
+        }
+    }
+
+    /// Handle for the running task
+    pub fn current_handle(&self) -> Option<Handle> {
+        match self.current_priority() {
+            TaskPriority::Critical => self.critical_scheduler.current_handle(),
+            TaskPriority::High => self.high_scheduler.current_handle(),
+            TaskPriority::Normal => self.normal_scheduler.current_handle(),
+            TaskPriority::Low => self.low_scheduler.current_handle(),
+        }
+    }
+
+    /// Task in slot `index` counted across all levels, critical level first
+    pub fn task_at(&self, index: usize) -> Option<&Task> {
+        let level = match index / MAX_TASKS {
+            0 => &self.critical_scheduler,
+            1 => &self.high_scheduler,
+            2 => &self.normal_scheduler,
+            3 => &self.low_scheduler,
+            _ => return None,
+        };
+        level.tasks[index % MAX_TASKS].as_ref()
+    }
+
+    /// Visit every task, critical level first
+    pub fn for_each_task(&self, mut f: impl FnMut(&Task)) {
+        for level in [&self.critical_scheduler, &self.high_scheduler, &self.normal_scheduler, &self.low_scheduler] {
+            level.tasks().for_each(&mut f);
+        }
+    }
+
+    /// Occupied task slots and queued events across all levels
+    pub fn occupancy(&self) -> Occupancy {
+        [&self.critical_scheduler, &self.high_scheduler, &self.normal_scheduler, &self.low_scheduler]
+            .iter()
+            .fold(Occupancy::default(), |total, level| Occupancy {
+                tasks: total.tasks + level.tasks().count(),
+                events: total.events + level.queued_events(),
+            })
+    }
+
+    /// Queue and scheduling counters of each level, critical first
+    pub fn level_stats(&self) -> [LevelStats; 4] {
+        let levels = [&self.critical_scheduler, &self.high_scheduler, &self.normal_scheduler, &self.low_scheduler];
+        core::array::from_fn(|i| LevelStats { switches: self.switches[i], ..levels[i].level_stats() })
+    }
+
+    /// Combined statistics across priority levels (active_tasks, total_events, timer)
+    pub fn stats(&self) -> (u32, u32, u64) {
+        [&self.critical_scheduler, &self.high_scheduler, &self.normal_scheduler, &self.low_scheduler]
+            .iter()
+            .map(|sched| sched.stats())
+            .fold((0, 0, 0), |(tasks, events, timer), (t, e, now)| (tasks + t, events + e, timer.max(now)))
+    }
 }
 
 /// Enhanced Priority-based Async Event-Driven Scheduler
@@ -284,54 +619,223 @@ pub struct AsyncScheduler {
     // Task management with message-passing optimization
     tasks: [Option<Task>; MAX_TASKS],
     current_task: Option<usize>,
-    next_task: Option<usize>, // Hot slot for message-passing optimization
+    // Hot slot for message-passing optimization, and the reschedule request
+    handoff: Handoff,
     
     // Lock-free event queues by priority
     critical_events: LockFreeEventQueue<MAX_EVENTS_PER_PRIORITY>,
     high_events: LockFreeEventQueue<MAX_EVENTS_PER_PRIORITY>,
     normal_events: LockFreeEventQueue<MAX_EVENTS_PER_PRIORITY>,
     low_events: LockFreeEventQueue<MAX_EVENTS_PER_PRIORITY>,
+    // Event held back by fault injection, queued after the next post
+    held_event: Option<Event>,
     
     // Scheduling state
-    needs_reschedule: AtomicBool,
     active_tasks: AtomicU32,
     event_counter: AtomicU32,
-    timer_base: AtomicU32, // For sleep/timeout functionality (32-bit for embedded compatibility)
+    dropped_events: AtomicU32,
+    // Tasks handed out by `schedule`, and how many came from the hot slot
+    dispatches: AtomicU32,
+    hot_hits: AtomicU32,
+    // Post-to-process time of the events handled; under the scheduler lock
+    latency: EventLatency,
+    timer_base: Instant, // Current tick for sleep/timeout deadlines; only touched under the scheduler lock
+    // Wake-up deadlines of sleeping tasks, by slot
+    timers: TimerWheel<MAX_TASKS>,
+
+    // Slot generations so task handles go stale when a slot is freed
+    generations: Generations<MAX_TASKS>,
 }
 
 impl AsyncScheduler {
     pub const fn new() -> Self {
+        Self::with_handle_base(0)
+    }
+
+    /// Scheduler whose task handles are numbered from `base`
+    pub const fn with_handle_base(base: usize) -> Self {
         const NONE_TASK: Option<Task> = None;
         Self {
             tasks: [NONE_TASK; MAX_TASKS],
             current_task: None,
-            next_task: None,
+            handoff: Handoff::new(),
             critical_events: LockFreeEventQueue::new(),
             high_events: LockFreeEventQueue::new(),
             normal_events: LockFreeEventQueue::new(),
             low_events: LockFreeEventQueue::new(),
-            needs_reschedule: AtomicBool::new(false),
+            held_event: None,
             active_tasks: AtomicU32::new(0),
             event_counter: AtomicU32::new(0),
-            timer_base: AtomicU32::new(0),
+            dropped_events: AtomicU32::new(0),
+            dispatches: AtomicU32::new(0),
+            hot_hits: AtomicU32::new(0),
+            latency: EventLatency::new(),
+            timer_base: Instant::ZERO,
+            timers: TimerWheel::new(),
+            generations: Generations::new(HandleKind::Task, base),
+        }
+    }
+
+    /// Handle for the task in `slot`
+    pub fn task_handle(&self, slot: usize) -> Handle {
+        self.generations.handle(slot)
+    }
+
+    /// Slot of the live task `handle` names
+    pub fn resolve(&self, handle: Handle) -> Result<usize, HandleError> {
+        let slot = self.generations.resolve(handle)?;
+        match self.tasks[slot] {
+            Some(_) => Ok(slot),
+            None => Err(HandleError::StaleHandle),
         }
     }
     
     /// Add a new task to the scheduler
-    pub fn spawn_task(&mut self, task: Task) -> Result<usize, ()> {
+    pub fn spawn_task(&mut self, task: Task) -> Result<usize, SpawnError> {
+        if faults::inject(Fault::Spawn) {
+            return Err(SpawnError::NoSlot);
+        }
+        crate::kensure!(
+            (self.active_tasks.load(Ordering::Relaxed) as usize) < MAX_TASKS,
+            Err(SpawnError::NoSlot),
+            "no free slot for task {}",
+            task.id
+        );
         for (i, slot) in self.tasks.iter_mut().enumerate() {
             if slot.is_none() {
                 *slot = Some(task);
                 self.active_tasks.fetch_add(1, Ordering::Relaxed);
-                self.needs_reschedule.store(true, Ordering::Release);
+                self.handoff.request_reschedule();
                 return Ok(i);
             }
         }
-        Err(()) // No free slots
+        crate::kassert!(false, "active task count out of sync with slots");
+        Err(SpawnError::NoSlot)
     }
     
+    /// Remove every task, returning how many were stopped
+    pub fn stop_all(&mut self) -> u32 {
+        let mut stopped = 0;
+        for (index, slot) in self.tasks.iter_mut().enumerate() {
+            if slot.take().is_some() {
+                self.timers.disarm(index);
+                self.generations.retire(index);
+                stopped += 1;
+            }
+        }
+        self.current_task = None;
+        self.handoff.clear();
+        self.active_tasks.store(0, Ordering::Relaxed);
+        stopped
+    }
+
+    /// OR `bits` into a task's signal word, waking it if blocked or asleep
+    ///
+    /// The woken task returns from whatever it waited on and should check
+    /// its signals before waiting again.
+    pub fn signal_task(&mut self, task_id: usize, bits: u32) -> bool {
+        let slot = self.tasks.iter().position(|slot| matches!(slot, Some(task) if task.id == task_id));
+        match slot {
+            Some(index) => {
+                self.deliver_signal(index, bits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Signal every task in this scheduler
+    pub fn signal_all(&mut self, bits: u32) -> u32 {
+        let mut signalled = 0;
+        for index in 0..MAX_TASKS {
+            if self.tasks[index].is_some() {
+                self.deliver_signal(index, bits);
+                signalled += 1;
+            }
+        }
+        signalled
+    }
+
+    pub fn deliver_signal(&mut self, index: usize, bits: u32) {
+        if let Some(task) = self.tasks[index].as_mut() {
+            task.signals |= bits;
+            if matches!(task.state, TaskState::WaitingForEvent(_) | TaskState::Sleeping(_)) {
+                task.wake();
+                self.timers.disarm(index);
+                self.handoff.request_reschedule();
+            }
+        }
+    }
+
+    /// Clear and return the current task's signals within `mask`
+    pub fn take_current_signals(&mut self, mask: u32) -> u32 {
+        match self.current_task.and_then(|id| self.tasks[id].as_mut()) {
+            Some(task) => {
+                let taken = task.signals & mask;
+                task.signals &= !mask;
+                taken
+            }
+            None => 0,
+        }
+    }
+
+    /// Free the current task's slot once it has wound down
+    pub fn exit_current_task(&mut self) -> bool {
+        match self.current_task {
+            Some(current_id) => self.remove_slot(current_id),
+            None => false,
+        }
+    }
+
+    /// Free the slot of the task with `task_id` without its cooperation
+    pub fn remove_task(&mut self, task_id: usize) -> bool {
+        match self.tasks.iter().position(|slot| matches!(slot, Some(task) if task.id == task_id)) {
+            Some(index) => self.remove_slot(index),
+            None => false,
+        }
+    }
+
+    /// Free `index`, invalidating handles to the task that held it
+    pub fn remove_slot(&mut self, index: usize) -> bool {
+        if self.tasks[index].take().is_none() {
+            return false;
+        }
+        self.timers.disarm(index);
+        self.generations.retire(index);
+        if self.current_task == Some(index) {
+            self.current_task = None;
+        }
+        self.handoff.withdraw(index);
+        crate::kassert!(self.active_tasks.load(Ordering::Relaxed) > 0);
+        self.active_tasks.fetch_sub(1, Ordering::Relaxed);
+        self.handoff.request_reschedule();
+        true
+    }
+
     /// Post an event with specified priority (ISR-safe)
-    pub fn post_event(&mut self, event: Event) -> bool {
+    ///
+    /// With fault injection the event may be dropped as if its queue were
+    /// full, or held back and queued after the next post (or at the next
+    /// pick, if nothing else is posted first).
+    pub fn post_event(&mut self, mut event: Event) -> bool {
+        crate::instrumentation::event_posted(&mut event);
+        if faults::inject(Fault::EventDrop) {
+            crate::coverage::hit(Path::EventQueueFull);
+            self.dropped_events.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        if self.held_event.is_none() && faults::inject(Fault::EventReorder) {
+            self.held_event = Some(event);
+            return true;
+        }
+        let queued = self.queue_event(event);
+        if let Some(held) = self.held_event.take() {
+            self.queue_event(held);
+        }
+        queued
+    }
+
+    fn queue_event(&mut self, event: Event) -> bool {
         let result = match event.priority {
             EventPriority::Critical => self.critical_events.push(event),
             EventPriority::High => self.high_events.push(event),
@@ -340,10 +844,13 @@ impl AsyncScheduler {
         };
         
         if result.is_ok() {
+            crate::coverage::hit(Path::EventQueued);
             self.event_counter.fetch_add(1, Ordering::Relaxed);
             self.wake_waiting_tasks(event.id);
             true
         } else {
+            crate::coverage::hit(Path::EventQueueFull);
+            self.dropped_events.fetch_add(1, Ordering::Relaxed);
             false // Queue full
         }
     }
@@ -351,26 +858,30 @@ impl AsyncScheduler {
     /// Wake tasks waiting for a specific event with message-passing optimization
     fn wake_waiting_tasks(&mut self, event_id: u32) {
         let mut displaced_task_id: Option<usize> = None;
+        let mut woken = false;
         
         for (i, task_slot) in self.tasks.iter_mut().enumerate() {
             if let Some(task) = task_slot {
                 if let TaskState::WaitingForEvent(waiting_id) = task.state {
                     if waiting_id == event_id {
-                        task.state = TaskState::Ready;
-                        task.waiting_event = None;
+                        task.wake();
+                        woken = true;
                         
                         // Message-passing optimization: put in hot slot
-                        displaced_task_id = self.next_task.replace(i);
-                        
-                        self.needs_reschedule.store(true, Ordering::Release);
+                        displaced_task_id = self.handoff.offer(i);
                         break; // Only wake first matching task for fairness
                     }
                 }
             }
         }
         
+        if !woken {
+            crate::coverage::hit(Path::EventNoWaiter);
+        }
+        
         // Handle displaced task outside the iterator
         if let Some(displaced_id) = displaced_task_id {
+            crate::coverage::hit(Path::HotSlotDisplaced);
             if let Some(displaced_task) = &mut self.tasks[displaced_id] {
                 if displaced_task.state == TaskState::Running {
                     displaced_task.state = TaskState::Ready;
@@ -382,6 +893,9 @@ impl AsyncScheduler {
     /// Process events in priority order (lock-free)
     pub fn process_events(&mut self) -> u32 {
         let mut processed = 0;
+        if let Some(held) = self.held_event.take() {
+            self.queue_event(held);
+        }
         
         // Process one event per priority level for fairness
         if let Some(event) = self.critical_events.pop() {
@@ -407,17 +921,15 @@ impl AsyncScheduler {
         processed
     }
     
-    /// Handle a single event (can be extended for specific event types)
+    /// Hand a processed event to the handler table
+    ///
+    /// Handlers run later from `events::dispatch_pending`, outside the
+    /// scheduler lock.
     fn handle_event(&mut self, event: Event) {
-        // Event handling logic - can be customized per event type
-        match event.id {
-            0x1 => { /* Timer event */ },
-            0x2 => { /* I/O event */ },
-            0x3 => { /* User input */ },
-            0x10..=0x1F => { /* System events */ },
-            0xFF => { /* Shutdown event */ },
-            _ => { /* Generic event */ }
+        if let Some(queued) = crate::instrumentation::event_queued_us(&event) {
+            self.latency.record(queued);
         }
+        crate::events::defer_locked(event);
     }
     
     /// Block current task on an event
@@ -429,37 +941,41 @@ impl AsyncScheduler {
                 task.waiting_event = Some(event_id);
             }
             self.current_task = None;
-            self.needs_reschedule.store(true, Ordering::Release);
+            self.handoff.request_reschedule();
         }
     }
     
     /// Put current task to sleep for duration
     #[allow(dead_code)]
-    pub fn sleep_current_task(&mut self, duration: u32) {
+    pub fn sleep_current_task(&mut self, duration: Duration) {
         if let Some(current_id) = self.current_task {
             if let Some(task) = &mut self.tasks[current_id] {
-                let wake_time = self.timer_base.load(Ordering::Relaxed) + duration;
-                task.state = TaskState::Sleeping(wake_time as u64);
+                let wake_time = self.timer_base + duration;
+                task.state = TaskState::Sleeping(wake_time);
+                self.timers.arm(current_id, wake_time);
             }
             self.current_task = None;
-            self.needs_reschedule.store(true, Ordering::Release);
+            self.handoff.request_reschedule();
         }
     }
     
     /// Update timer and wake sleeping tasks
-    pub fn update_timer(&mut self, current_time: u32) {
-        self.timer_base.store(current_time, Ordering::Relaxed);
-        
-        for task_slot in self.tasks.iter_mut() {
-            if let Some(task) = task_slot {
-                if let TaskState::Sleeping(wake_time) = task.state {
-                    if (current_time as u64) >= wake_time {
-                        task.state = TaskState::Ready;
-                        self.needs_reschedule.store(true, Ordering::Release);
-                    }
-                }
+    ///
+    /// Only the tasks whose deadline came up are visited, however many
+    /// are asleep.
+    pub fn update_timer(&mut self, current_time: Instant) {
+        self.timer_base = current_time;
+
+        let tasks = &mut self.tasks;
+        let handoff = &self.handoff;
+        self.timers.advance(current_time, |slot| {
+            // A task woken some other way may have left its timer armed
+            if let Some(task) = tasks[slot].as_mut().filter(|task| matches!(task.state, TaskState::Sleeping(_))) {
+                task.wake();
+                handoff.request_reschedule();
+                crate::coverage::hit(Path::TimerWake);
             }
-        }
+        });
     }
     
     /// Enhanced cooperative scheduler with message-passing optimization
@@ -468,7 +984,7 @@ impl AsyncScheduler {
         self.process_events();
         
         // Check hot slot first (message-passing optimization)
-        if let Some(next_id) = self.next_task.take() {
+        if let Some(next_id) = self.handoff.take() {
             // Check if task exists and is ready
             let task_ready = self.tasks[next_id]
                 .as_ref()
@@ -493,11 +1009,17 @@ impl AsyncScheduler {
                     self.current_task = Some(next_id);
                 }
                 
+                self.dispatches.fetch_add(1, Ordering::Relaxed);
+                self.hot_hits.fetch_add(1, Ordering::Relaxed);
+                crate::coverage::hit(Path::HotSlotHit);
                 return self.tasks[next_id].as_ref();
             }
+            crate::coverage::hit(Path::HotSlotStale);
         }
         
-        if self.needs_reschedule.swap(false, Ordering::AcqRel) || self.current_task.is_none() {
+        if self.handoff.take_reschedule() || self.current_task.is_none() {
+            crate::coverage::hit(Path::RescheduleSearch);
+
             // Mark current task as ready if it's still running
             if let Some(current_id) = self.current_task {
                 if let Some(task) = self.tasks[current_id].as_mut() {
@@ -509,6 +1031,7 @@ impl AsyncScheduler {
             
             // Find next ready task (round-robin among ready tasks)
             let start_search = self.current_task.map(|id| (id + 1) % MAX_TASKS).unwrap_or(0);
+            let mut found = false;
             
             for i in 0..MAX_TASKS {
                 let task_id = (start_search + i) % MAX_TASKS;
@@ -516,13 +1039,23 @@ impl AsyncScheduler {
                     if matches!(task.state, TaskState::Ready) {
                         task.state = TaskState::Running;
                         self.current_task = Some(task_id);
+                        found = true;
                         break;
                     }
                 }
             }
+            if !found {
+                crate::coverage::hit(Path::RescheduleEmpty);
+            }
+        } else {
+            crate::coverage::hit(Path::KeepCurrent);
         }
         
-        self.current_task.and_then(|id| self.tasks[id].as_ref())
+        let task = self.current_task.and_then(|id| self.tasks[id].as_ref());
+        if task.is_some() {
+            self.dispatches.fetch_add(1, Ordering::Relaxed);
+        }
+        task
     }
     
     /// Get current running task
@@ -530,7 +1063,36 @@ impl AsyncScheduler {
     pub fn current_task(&self) -> Option<&Task> {
         self.current_task.and_then(|id| self.tasks[id].as_ref())
     }
+
+    /// Handle for the running task
+    pub fn current_handle(&self) -> Option<Handle> {
+        self.current_task.filter(|&slot| self.tasks[slot].is_some()).map(|slot| self.task_handle(slot))
+    }
     
+    /// Occupied task slots in slot order
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.tasks.iter().flatten()
+    }
+
+    /// Events waiting in this level's queues
+    pub fn queued_events(&self) -> usize {
+        self.critical_events.len() + self.high_events.len() + self.normal_events.len() + self.low_events.len()
+    }
+
+    /// Queue and scheduling counters; `switches` is kept by the executor
+    pub fn level_stats(&self) -> LevelStats {
+        let queues = [&self.critical_events, &self.high_events, &self.normal_events, &self.low_events];
+        LevelStats {
+            queued: self.queued_events(),
+            peak: queues.iter().map(|queue| queue.peak()).max().unwrap_or(0),
+            dropped: self.dropped_events.load(Ordering::Relaxed),
+            switches: 0,
+            dispatches: self.dispatches.load(Ordering::Relaxed),
+            hot_hits: self.hot_hits.load(Ordering::Relaxed),
+            latency: self.latency,
+        }
+    }
+
     /// Check if scheduler has any active tasks
     pub fn has_active_tasks(&self) -> bool {
         self.active_tasks.load(Ordering::Relaxed) > 0
@@ -547,25 +1109,24 @@ impl AsyncScheduler {
         })
     }
     
+    /// Whether any task is asleep, waiting for `update_timer`
+    pub fn has_sleeping_tasks(&self) -> bool {
+        self.tasks.iter().flatten().any(|task| matches!(task.state, TaskState::Sleeping(_)))
+    }
+
     /// Get scheduler statistics
-    pub fn stats(&self) -> (u32, u32, u32) {
+    pub fn stats(&self) -> (u32, u32, u64) {
         (
             self.active_tasks.load(Ordering::Relaxed),
             self.event_counter.load(Ordering::Relaxed),
-            self.timer_base.load(Ordering::Relaxed)
+            self.timer_base.ticks()
         )
     }
 }
 
 // -------- Global scheduler instances --------
-struct SchedulerCell(UnsafeCell<AsyncScheduler>);
-unsafe impl Sync for SchedulerCell {} // Single-core assumption
-
-struct MultiPriorityCell(UnsafeCell<MultiPriorityExecutor>);
-unsafe impl Sync for MultiPriorityCell {} // Single-core assumption
-
-static SCHEDULER: SchedulerCell = SchedulerCell(UnsafeCell::new(AsyncScheduler::new()));
-static MULTI_PRIORITY_SCHEDULER: MultiPriorityCell = MultiPriorityCell(UnsafeCell::new(MultiPriorityExecutor::new()));
+static SCHEDULER: CriticalCell<AsyncScheduler> = CriticalCell::new(AsyncScheduler::new());
+static MULTI_PRIORITY_SCHEDULER: CriticalCell<MultiPriorityExecutor> = CriticalCell::new(MultiPriorityExecutor::new());
 
 // Critical section wrapper for single-threaded safety
 #[inline(always)]
@@ -573,11 +1134,7 @@ fn with_scheduler<F, R>(f: F) -> R
 where 
     F: FnOnce(&mut AsyncScheduler) -> R 
 {
-    // Disable interrupts for atomic scheduler access
-    crate::arch::disable_interrupts();
-    let result = unsafe { f(&mut *SCHEDULER.0.get()) };
-    crate::arch::enable_interrupts();
-    result
+    SCHEDULER.with(f)
 }
 
 // Multi-priority scheduler access
@@ -586,25 +1143,27 @@ fn with_multi_scheduler<F, R>(f: F) -> R
 where 
     F: FnOnce(&mut MultiPriorityExecutor) -> R 
 {
-    // Disable interrupts for atomic scheduler access
-    crate::arch::disable_interrupts();
-    let result = unsafe { f(&mut *MULTI_PRIORITY_SCHEDULER.0.get()) };
-    crate::arch::enable_interrupts();
-    result
+    MULTI_PRIORITY_SCHEDULER.with(f)
 }
 
 // -------- Enhanced Public API --------
 
 /// Spawn a new task with default normal priority
 #[allow(dead_code)]
-pub fn add_task(task: Task) -> Result<usize, ()> {
+pub fn add_task(task: Task) -> Result<usize, SpawnError> {
     with_scheduler(|sched| sched.spawn_task(task))
 }
 
 /// Spawn a task with specific priority (uses multi-priority executor)
+///
+/// A full task table is charged to the spawning task as a refusal.
 #[allow(dead_code)]
-pub fn add_priority_task(task: Task) -> Result<usize, ()> {
-    with_multi_scheduler(|sched| sched.spawn_task(task))
+pub fn add_priority_task(task: Task) -> Result<Handle, SpawnError> {
+    let result = with_multi_scheduler(|sched| sched.spawn_task(task));
+    if result.is_err() {
+        crate::accounting::deny(crate::accounting::current_owner(), crate::accounting::Resource::TaskSlot);
+    }
+    result
 }
 
 /// Post an event to wake waiting tasks
@@ -618,7 +1177,10 @@ pub fn post_event_with_priority(id: u32, priority: EventPriority) -> bool {
 #[allow(dead_code)]
 pub fn post_priority_event(id: u32, priority: EventPriority) -> bool {
     let event = Event::new(id, priority);
-    with_multi_scheduler(|sched| sched.post_event(event))
+    let accepted = with_multi_scheduler(|sched| sched.post_event(event));
+    #[cfg(feature = "trace")]
+    crate::kernel::trace::posted(id, priority, accepted);
+    accepted
 }
 
 /// Post a normal priority event (compatibility)
@@ -633,29 +1195,40 @@ pub fn block_current(event_id: u32) {
     with_scheduler(|sched| sched.block_current_task(event_id));
 }
 
-/// Sleep current task for specified duration
-#[allow(dead_code)]
-pub fn sleep_current(duration: u32) {
-    with_scheduler(|sched| sched.sleep_current_task(duration));
+/// Put the running task (multi-priority executor) to sleep for `duration`
+pub fn sleep_current(duration: Duration) {
+    with_multi_scheduler(|sched| sched.sleep_current(duration));
 }
 
-/// Update global timer (call this periodically from timer interrupt)
-#[allow(dead_code)]
-pub fn update_global_timer(current_time: u32) {
-    with_scheduler(|sched| sched.update_timer(current_time));
+/// Advance every priority level's timer wheel to `current_time`
+pub fn update_global_timer(current_time: Instant) {
+    with_multi_scheduler(|sched| sched.update_timer(current_time));
 }
 
 /// Run scheduler and return current task
 #[allow(dead_code)]
 pub fn schedule() -> Option<Task> {
-    with_scheduler(|sched| sched.schedule().cloned())
+    let task = with_scheduler(|sched| sched.schedule().cloned());
+    crate::events::dispatch_pending();
+    task
 }
 
 /// Run multi-priority scheduler (recommended for real-time systems)
 #[allow(dead_code)]
 #[allow(dead_code)]
 pub fn schedule_with_priority() -> Option<Task> {
-    with_multi_scheduler(|sched| sched.run_cycle())
+    #[cfg(feature = "trace")]
+    crate::kernel::trace::schedule_point();
+    crate::kernel::time::poll_timeouts();
+    crate::kernel::periodic::poll_releases();
+    update_global_timer(Instant::now());
+    let task = with_multi_scheduler(|sched| sched.run_cycle());
+    if let Some(task) = &task {
+        crate::kernel::periodic::on_start(task);
+    }
+    crate::events::dispatch_pending();
+    crate::introspect::refresh();
+    task
 }
 
 /// Get current running task
@@ -678,29 +1251,320 @@ pub fn interrupt_priority_event(event_id: u32) {
 
 /// Get scheduler statistics (active_tasks, total_events, timer)
 #[allow(dead_code)]
-pub fn scheduler_stats() -> (u32, u32, u32) {
+pub fn scheduler_stats() -> (u32, u32, u64) {
     with_scheduler(|sched| sched.stats())
 }
 
+/// Task last picked by the multi-priority executor
+pub fn current_priority_task() -> Option<Task> {
+    with_multi_scheduler(|sched| sched.current_task().cloned())
+}
+
 /// Check if any scheduler has ready work
 pub fn has_ready_work() -> bool {
     with_multi_scheduler(|sched| sched.has_ready_tasks())
 }
 
+/// Check for ready work when the caller already has interrupts disabled
+pub fn has_ready_work_locked() -> bool {
+    MULTI_PRIORITY_SCHEDULER.with_locked(|sched| sched.has_ready_tasks())
+}
+
+/// Whether a task sleeps at any priority level, when the caller already
+/// has interrupts disabled
+pub fn has_sleeping_tasks_locked() -> bool {
+    MULTI_PRIORITY_SCHEDULER.with_locked(|sched| sched.has_sleeping_tasks())
+}
+
+/// Block the running task (multi-priority executor) until `event_id`
+pub fn block_current_priority(event_id: u32) {
+    with_multi_scheduler(|sched| sched.block_current(event_id))
+}
+
+/// Wake tasks waiting on `event_id` at any priority (ISR-safe)
+pub fn wake_event(event_id: u32) {
+    with_multi_scheduler(|sched| sched.wake(event_id))
+}
+
+// -------- Signals and cooperative cancellation --------
+//
+// Signals never stop a task by themselves. A task that runs long loops
+// checks `is_cancelled()` at safe points, releases what it holds and
+// returns; the main loop then retires it with `exit_current_task()`.
+
+/// Raise `bits` on the task with `task_id` (ISR-safe)
+#[allow(dead_code)]
+pub fn signal_task(task_id: usize, bits: u32) -> bool {
+    with_multi_scheduler(|sched| sched.signal(task_id, bits))
+}
+
+/// Make a waiting or sleeping task ready again without signalling it
+pub fn wake_task(task_id: usize, priority: TaskPriority) -> bool {
+    with_multi_scheduler(|sched| sched.wake_task(task_id, priority))
+}
+
+/// Charge one run taking `cycles` to a task, after the main loop ran it
+pub fn record_run(task_id: usize, priority: TaskPriority, cycles: u32) -> bool {
+    with_multi_scheduler(|sched| sched.record_run(task_id, priority, cycles))
+}
+
+/// Charge deadline misses to a periodic task
+pub fn record_misses(task_id: usize, priority: TaskPriority, misses: u32) -> bool {
+    with_multi_scheduler(|sched| sched.record_misses(task_id, priority, misses))
+}
+
+/// Visit a copy of every task, critical level first
+///
+/// Each task is copied under its own short critical section, so `f` runs
+/// with interrupts enabled and may print; tasks spawned or removed during
+/// the walk may be missed.
+#[allow(dead_code)]
+pub fn for_each_task(mut f: impl FnMut(&Task)) {
+    for index in 0..TASK_CAPACITY {
+        if let Some(task) = with_multi_scheduler(|sched| sched.task_at(index).cloned()) {
+            f(&task);
+        }
+    }
+}
+
+/// Ask a task to finish up and exit
+#[allow(dead_code)]
+pub fn kill_task(task_id: usize) -> bool {
+    signal_task(task_id, SIGNAL_KILL)
+}
+
+/// Raise `bits` on every task, returning how many were signalled
+pub fn signal_all_tasks(bits: u32) -> u32 {
+    with_multi_scheduler(|sched| sched.signal_all(bits))
+}
+
+/// Pending signals of the running task, left set
+#[allow(dead_code)]
+pub fn pending_signals() -> u32 {
+    with_multi_scheduler(|sched| sched.current_task().map_or(0, |task| task.signals))
+}
+
+/// Clear and return the running task's signals within `mask`
+#[allow(dead_code)]
+pub fn take_signals(mask: u32) -> u32 {
+    with_multi_scheduler(|sched| sched.take_signals(mask))
+}
+
+/// True once the running task has been asked to wind down
+pub fn is_cancelled() -> bool {
+    with_multi_scheduler(|sched| sched.current_task().is_some_and(|task| task.is_cancelled()))
+}
+
+/// Take pending signals within `mask`, or park the task until one arrives
+///
+/// Returns 0 after parking; the task is woken by the next signal and
+/// calls `wait_signals` again.
+#[allow(dead_code)]
+pub fn wait_signals(mask: u32) -> u32 {
+    with_multi_scheduler(|sched| {
+        let taken = sched.take_signals(mask);
+        if taken == 0 {
+            sched.block_current(SIGNAL_WAIT_EVENT);
+        }
+        taken
+    })
+}
+
+// -------- Wakers --------
+//
+// A task awaits a future by polling it from its body with `poll_current`
+// and returning while it is pending. The waker raises `SIGNAL_WAKE` on
+// the task through its handle, which makes it ready again; the signal
+// stays pending if the task is still running, so a wake that comes
+// during the poll is not lost. A waker outliving its task finds the
+// handle stale and does nothing.
+
+static TASK_WAKER: RawWakerVTable = RawWakerVTable::new(clone_task_waker, wake_task_waker, wake_task_waker, drop_task_waker);
+
+fn clone_task_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &TASK_WAKER)
+}
+
+fn wake_task_waker(data: *const ()) {
+    if let Some(handle) = Handle::from_raw(data as usize as u32) {
+        let _ = signal_handle(handle, SIGNAL_WAKE);
+    }
+}
+
+fn drop_task_waker(_: *const ()) {}
+
+/// Waker that makes the task `handle` names ready (ISR-safe)
+pub fn task_waker(handle: Handle) -> Waker {
+    let data = handle.raw() as usize as *const ();
+    // The vtable functions only read the handle out of the pointer
+    unsafe { Waker::from_raw(RawWaker::new(data, &TASK_WAKER)) }
+}
+
+/// Poll `future` once for the running task, parking it while pending
+///
+/// The task returns to the scheduler on `Pending` and polls again when
+/// woken. A future that keeps its place in a wait list should be kept
+/// between runs; the primitives in `kernel::sync` may also be made anew
+/// each run. Outside a task the future is polled with a waker that does
+/// nothing.
+#[allow(dead_code)]
+pub fn poll_current<F: Future + ?Sized>(future: Pin<&mut F>) -> Poll<F::Output> {
+    let Some(handle) = with_multi_scheduler(|sched| sched.current_handle()) else {
+        return future.poll(&mut Context::from_waker(Waker::noop()));
+    };
+    // A wake from before this poll is already accounted for by polling
+    take_signals(SIGNAL_WAKE);
+    let result = future.poll(&mut Context::from_waker(&task_waker(handle)));
+    if result.is_pending() {
+        wait_signals(SIGNAL_WAKE);
+    }
+    result
+}
+
+/// Remove the running task from the scheduler
+pub fn exit_current_task() -> bool {
+    with_multi_scheduler(|sched| sched.exit_current())
+}
+
+/// Copy of the task `handle` names (multi-priority executor)
+#[allow(dead_code)]
+pub fn task_by_handle(handle: Handle) -> Result<Task, HandleError> {
+    with_multi_scheduler(|sched| sched.task_by_handle(handle))
+}
+
+/// Raise `bits` on the task `handle` names (ISR-safe)
+#[allow(dead_code)]
+pub fn signal_handle(handle: Handle, bits: u32) -> Result<(), HandleError> {
+    with_multi_scheduler(|sched| sched.signal_handle(handle, bits))
+}
+
+/// Ask the task `handle` names to finish up and exit
+#[allow(dead_code)]
+pub fn kill_handle(handle: Handle) -> Result<(), HandleError> {
+    signal_handle(handle, SIGNAL_KILL)
+}
+
+/// Remove the task `handle` names outright
+#[allow(dead_code)]
+pub fn remove_handle(handle: Handle) -> Result<(), HandleError> {
+    with_multi_scheduler(|sched| sched.remove_handle(handle))
+}
+
+/// Remove a task outright, for tasks that can no longer wind down
+/// themselves (faulted or stalled)
+pub fn remove_task(task_id: usize) -> bool {
+    with_multi_scheduler(|sched| sched.remove(task_id))
+}
+
+/// Stop every task ahead of shutdown or reboot, returning the count
+#[allow(dead_code)]
+pub fn stop_all_tasks() -> u32 {
+    with_multi_scheduler(|sched| sched.stop_all())
+}
+
+/// Static table usage of the multi-priority executor
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Occupancy {
+    /// Occupied task slots, out of `TASK_CAPACITY`
+    pub tasks: usize,
+    /// Queued events, out of `EVENT_CAPACITY`
+    pub events: usize,
+}
+
+/// Task slots over all priority levels
+#[allow(dead_code)]
+pub const TASK_CAPACITY: usize = 4 * MAX_TASKS;
+
+/// Event queue slots over all priority levels and event priorities
+#[allow(dead_code)]
+pub const EVENT_CAPACITY: usize = 4 * 4 * MAX_EVENTS_PER_PRIORITY;
+
+/// How full the executor's task tables and event queues are
+#[allow(dead_code)]
+pub fn occupancy() -> Occupancy {
+    with_multi_scheduler(|sched| sched.occupancy())
+}
+
+/// Counters of one priority level of the multi-priority executor
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LevelStats {
+    /// Events waiting in the level's queues now
+    pub queued: usize,
+    /// Most events one of its queues has held, out of `MAX_EVENTS_PER_PRIORITY`
+    pub peak: usize,
+    /// Events refused because their queue was full
+    pub dropped: u32,
+    /// Times the executor switched to a task of this level from another task
+    pub switches: u32,
+    /// Tasks the level handed out to run
+    pub dispatches: u32,
+    /// Of those, tasks taken from the hot slot a wake-up left them in
+    pub hot_hits: u32,
+    /// Time its events waited between being posted and processed
+    pub latency: EventLatency,
+}
+
+/// Per-level queue and scheduling counters, critical level first
+#[allow(dead_code)]
+pub fn level_stats() -> [LevelStats; 4] {
+    with_multi_scheduler(|sched| sched.level_stats())
+}
+
+/// Scheduler state captured for panic and crash reports
+#[derive(Clone, Debug)]
+pub struct CrashSnapshot {
+    pub task: Option<Task>,
+    pub priority: TaskPriority,
+    /// (active_tasks, total_events, timer) summed over priority levels
+    pub stats: (u32, u32, u64),
+    /// Queue and scheduling counters of each level, critical first
+    pub levels: [LevelStats; 4],
+}
+
+/// Read scheduler state without entering a critical section
+///
+/// Only for fatal paths that already run with interrupts off and never
+/// return; the fault may have hit mid-update, so fields can be stale.
+pub fn crash_snapshot() -> CrashSnapshot {
+    let sched = unsafe { MULTI_PRIORITY_SCHEDULER.peek() };
+    CrashSnapshot {
+        task: sched.current_task().cloned(),
+        priority: sched.current_priority(),
+        stats: sched.stats(),
+        levels: sched.level_stats(),
+    }
+}
+
+/// Visit every task without entering a critical section
+///
+/// Same restrictions as `crash_snapshot`.
+pub fn crash_visit_tasks(f: impl FnMut(&Task)) {
+    let sched = unsafe { MULTI_PRIORITY_SCHEDULER.peek() };
+    sched.for_each_task(f);
+}
+
+/// Whether the kernel stopped in the middle of a scheduler update
+///
+/// A fatal path that hit inside one must read the scheduler with
+/// `crash_snapshot` and `crash_visit_tasks`; taking it would panic.
+pub fn interrupted_update() -> bool {
+    MULTI_PRIORITY_SCHEDULER.try_with(|_| ()).is_none()
+}
+
 /// Get current priority level of executing task
 pub fn current_priority_level() -> TaskPriority {
     with_multi_scheduler(|sched| sched.current_priority())
 }
 
 /// Architecture-agnostic yield point for cooperative multitasking
+#[allow(dead_code)]
 #[inline(always)]
 pub fn yield_now() {
     // This can be called from any architecture
     // The actual yield is handled by the scheduler
-    unsafe {
-        // Generic no-op that works on all architectures
-        core::arch::asm!("nop", options(nomem, nostack, preserves_flags));
-    }
+    core::hint::spin_loop();
 }
 
 /// Architecture-agnostic sleep/wait instruction
@@ -711,3 +1575,6 @@ pub fn cpu_wait_for_interrupt() {
     crate::arch::wait_for_interrupt();
 }
 
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,99 @@
+//! Scheduler state cell
+//! The executors' global state, reached only inside a critical section
+//!
+//! `CriticalCell` is the one place the scheduler turns a static into a
+//! reference. Its `Sync` rests on what `with` does before handing one
+//! out: interrupts are masked, which on the single-core targets shuts
+//! out every other context, and on the simulator takes the `MASK` lock
+//! that the interrupt threads take too. That leaves re-entry on the same
+//! core: scheduler code calling back into the scheduler, or a handler on
+//! a path that forgot to mask. A borrow flag catches both and panics
+//! rather than hand out a second `&mut`. The host build without the
+//! simulator has no interrupts and no mask, so there the flag is all
+//! that keeps two threads apart; tests that share the statics take turns.
+//!
+//! `with` saves whether interrupts were on and restores that, so it
+//! nests inside other critical sections, and the interrupt-off window is
+//! measured (`instrumentation`) only by the outermost one.
+
+use core::cell::UnsafeCell;
+
+use crate::arch::atomic::{AtomicBool, Ordering};
+
+/// `T` shared between tasks and interrupt handlers
+pub struct CriticalCell<T> {
+    value: UnsafeCell<T>,
+    borrowed: AtomicBool,
+}
+
+// Only reached with interrupts masked and the borrow flag held
+unsafe impl<T: Send> Sync for CriticalCell<T> {}
+
+impl<T> CriticalCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value: UnsafeCell::new(value), borrowed: AtomicBool::new(false) }
+    }
+
+    /// Run `f` on the value with interrupts masked
+    ///
+    /// Panics if the value is already borrowed, as `f` would then alias it.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        crate::arch::free(|| {
+            let _borrow = Borrow::claim(&self.borrowed);
+            // Masked and claimed: nothing else holds a reference
+            f(unsafe { &mut *self.value.get() })
+        })
+    }
+
+    /// As `with`, but `None` instead of a panic if the value is borrowed
+    ///
+    /// For reports on fatal paths, which may have stopped the kernel
+    /// inside a borrow.
+    pub fn try_with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        crate::arch::free(|| {
+            Borrow::try_claim(&self.borrowed).map(|_borrow| {
+                // Masked and claimed, as in `with`
+                f(unsafe { &mut *self.value.get() })
+            })
+        })
+    }
+
+    /// Run `f` on the value when the caller has already masked interrupts
+    pub fn with_locked<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        crate::kassert!(!crate::arch::interrupts_enabled(), "scheduler read unlocked");
+        let _borrow = Borrow::claim(&self.borrowed);
+        // Masked by the caller and claimed, as in `with`
+        f(unsafe { &*self.value.get() })
+    }
+
+    /// The value, without masking or claiming it
+    ///
+    /// # Safety
+    ///
+    /// Only for fatal paths that run with interrupts off and never
+    /// return. The value may be part way through an update, so its
+    /// fields can disagree; nothing may write to it while the reference
+    /// lives.
+    pub unsafe fn peek(&self) -> &T {
+        unsafe { &*self.value.get() }
+    }
+}
+
+/// Claim on a cell's borrow flag, released when dropped (also on unwind)
+struct Borrow<'a>(&'a AtomicBool);
+
+impl<'a> Borrow<'a> {
+    fn claim(flag: &'a AtomicBool) -> Self {
+        Self::try_claim(flag).unwrap_or_else(|| panic!("scheduler state re-entered while borrowed"))
+    }
+
+    fn try_claim(flag: &'a AtomicBool) -> Option<Self> {
+        (!flag.swap(true, Ordering::Acquire)).then(|| Self(flag))
+    }
+}
+
+impl Drop for Borrow<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
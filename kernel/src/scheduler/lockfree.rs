@@ -0,0 +1,206 @@
+//! Lock-free scheduler primitives
+//! The per-level event queue and the hot-slot handoff between wake and schedule
+//!
+//! `LockFreeQueue` is a single-producer single-consumer ring: the side
+//! that posts events pushes and the scheduler pops, and neither waits for
+//! the other. `Handoff` carries the slot of a task a wake-up made ready
+//! to the scheduler's next pick, together with the reschedule request.
+//!
+//! Everything here takes its atomics and cells from the top of this file
+//! and nothing from the rest of the kernel, so `tests/loom.rs` can compile
+//! it against loom and check every interleaving of the two sides. The
+//! cells use loom's closure-based access in both builds.
+
+use core::mem::MaybeUninit;
+
+#[cfg(all(loom, test))]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+#[cfg(not(all(loom, test)))]
+use self::cell::UnsafeCell;
+#[cfg(not(all(loom, test)))]
+use crate::arch::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(not(all(loom, test)))]
+mod cell {
+    /// `core::cell::UnsafeCell` with loom's closure-based access
+    pub struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+    impl<T> UnsafeCell<T> {
+        pub const fn new(value: T) -> Self {
+            Self(core::cell::UnsafeCell::new(value))
+        }
+
+        pub fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+            f(self.0.get())
+        }
+
+        pub fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+            f(self.0.get())
+        }
+    }
+}
+
+/// Bounded FIFO of `N` values for one producer and one consumer
+///
+/// `head` and `tail` count pops and pushes and wrap around; `N` must be a
+/// power of two so slot indices stay in step across the wrap.
+pub struct LockFreeQueue<T: Copy, const N: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    /// Most values queued at once; written by the producer only
+    peak: AtomicUsize,
+}
+
+// One producer and one consumer at a time; the kernel serializes each side
+unsafe impl<T: Copy + Send, const N: usize> Sync for LockFreeQueue<T, N> {}
+
+impl<T: Copy, const N: usize> LockFreeQueue<T, N> {
+    const POWER_OF_TWO: () = assert!(N.is_power_of_two(), "queue depth must be a power of two");
+
+    #[cfg(not(all(loom, test)))]
+    pub const fn new() -> Self {
+        let () = Self::POWER_OF_TWO;
+        Self {
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Loom atomics cannot be built in a `const fn`
+    #[cfg(all(loom, test))]
+    pub fn new() -> Self {
+        let () = Self::POWER_OF_TWO;
+        Self {
+            buffer: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Empty queue whose counters start at `start`, to test the wrap
+    #[cfg(test)]
+    pub fn starting_at(start: usize) -> Self {
+        let queue = Self::new();
+        queue.head.store(start, Ordering::Relaxed);
+        queue.tail.store(start, Ordering::Relaxed);
+        queue
+    }
+
+    /// Append `value`, handing it back if the queue is full (producer only)
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        let len = tail.wrapping_sub(head);
+        if len >= N {
+            return Err(value);
+        }
+
+        // The consumer is done with this slot: it released `head` past it
+        self.buffer[tail % N].with_mut(|slot| unsafe { slot.write(MaybeUninit::new(value)) });
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        if len + 1 > self.peak.load(Ordering::Relaxed) {
+            self.peak.store(len + 1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Remove the oldest value (consumer only)
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        // The producer filled this slot before releasing `tail` past it
+        let value = self.buffer[head % N].with(|slot| unsafe { (*slot).assume_init() });
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Most values queued at once since the queue was made
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+/// Hot slot and reschedule request passed from wake-ups to the scheduler
+///
+/// A wake-up `offer`s the slot of the task it made ready; the scheduler's
+/// next pick `take`s it ahead of the round-robin search. The offer
+/// publishes everything the waker wrote to the task before it, so the
+/// scheduler sees the task ready once it holds the slot.
+pub struct Handoff {
+    /// Slot to run next, `EMPTY` when none
+    next: AtomicUsize,
+    needs_reschedule: AtomicBool,
+}
+
+impl Handoff {
+    const EMPTY: usize = usize::MAX;
+
+    #[cfg(not(all(loom, test)))]
+    pub const fn new() -> Self {
+        Self { next: AtomicUsize::new(Self::EMPTY), needs_reschedule: AtomicBool::new(false) }
+    }
+
+    #[cfg(all(loom, test))]
+    pub fn new() -> Self {
+        Self { next: AtomicUsize::new(Self::EMPTY), needs_reschedule: AtomicBool::new(false) }
+    }
+
+    /// Make `slot` the next task and ask for a reschedule, returning the
+    /// slot it displaced
+    pub fn offer(&self, slot: usize) -> Option<usize> {
+        let displaced = self.next.swap(slot, Ordering::AcqRel);
+        self.needs_reschedule.store(true, Ordering::Release);
+        (displaced != Self::EMPTY).then_some(displaced)
+    }
+
+    /// Claim the offered slot, if any
+    pub fn take(&self) -> Option<usize> {
+        let slot = self.next.swap(Self::EMPTY, Ordering::AcqRel);
+        (slot != Self::EMPTY).then_some(slot)
+    }
+
+    /// Drop the offer of `slot`, if it is still the one offered
+    pub fn withdraw(&self, slot: usize) {
+        let _ = self.next.compare_exchange(slot, Self::EMPTY, Ordering::AcqRel, Ordering::Relaxed);
+    }
+
+    /// Drop any offer
+    pub fn clear(&self) {
+        self.next.store(Self::EMPTY, Ordering::Release);
+    }
+
+    /// Ask the next pick to search for a task rather than keep the current one
+    pub fn request_reschedule(&self) {
+        self.needs_reschedule.store(true, Ordering::Release);
+    }
+
+    /// Whether a reschedule was asked for, clearing the request
+    pub fn take_reschedule(&self) -> bool {
+        self.needs_reschedule.swap(false, Ordering::AcqRel)
+    }
+}
@@ -0,0 +1,289 @@
+//! Host tests for the scheduler core
+//!
+//! Each `LockFreeEventQueue` property runs over `CASES` seeded random
+//! operation sequences and checks the queue against a `VecDeque` model.
+//! A failure names its seed; set `KARATOS_TEST_SEED` to that seed to run
+//! just the failing case.
+//!
+//! The rest drive `CriticalCell` and an `AsyncScheduler` of their own.
+//! All of it runs under Miri (`cargo +nightly miri test --lib scheduler::`),
+//! with fewer cases as Miri is slow. Tests that flip the host's interrupt
+//! flag take `INTERRUPTS` first, since every test thread shares it.
+
+use std::collections::VecDeque;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+use super::cell::CriticalCell;
+use super::{AsyncScheduler, Event, EventPriority, LockFreeEventQueue, MultiPriorityExecutor, Task, TaskPriority, TaskState};
+use crate::kernel::time::{Duration, Instant};
+
+/// Random cases per property
+const CASES: u64 = if cfg!(miri) { 4 } else { 256 };
+
+/// Operations per case
+const OPS: usize = if cfg!(miri) { 64 } else { 512 };
+
+/// Held by tests that change the interrupt flag
+static INTERRUPTS: Mutex<()> = Mutex::new(());
+
+/// Take `INTERRUPTS` and start with interrupts enabled
+fn interrupts_enabled() -> MutexGuard<'static, ()> {
+    // A test that failed while holding it leaves nothing to clean up
+    let guard = INTERRUPTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    crate::arch::enable_interrupts();
+    guard
+}
+
+/// xorshift64*: small, seedable and good enough to pick operations
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /// True with probability `percent`%
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// Run `property` once per seed, or only for `KARATOS_TEST_SEED` if set
+fn for_each_seed(property: impl Fn(u64)) {
+    match std::env::var("KARATOS_TEST_SEED").ok().and_then(|seed| seed.parse().ok()) {
+        Some(seed) => property(seed),
+        None => (0..CASES).for_each(property),
+    }
+}
+
+fn event(sequence: u32) -> Event {
+    Event::with_data(0x100, EventPriority::Normal, sequence)
+}
+
+/// Random pushes and pops checked step by step against the model
+fn matches_model<const N: usize>(seed: u64, start: usize) {
+    let mut rng = Rng::new(seed);
+    let queue = LockFreeEventQueue::<N>::starting_at(start);
+    let mut model = VecDeque::new();
+    let mut peak = 0;
+    // Bias toward pushing or popping for a while, so runs reach both full and empty
+    let mut push_percent = 50;
+    for (step, sequence) in (0..OPS).zip(0u32..) {
+        if rng.chance(5) {
+            push_percent = rng.below(101);
+        }
+        if rng.chance(push_percent) {
+            let accepted = queue.push(event(sequence));
+            assert_eq!(accepted.is_ok(), model.len() < N, "seed {seed} step {step}: push accepted");
+            if let Err(rejected) = accepted {
+                assert_eq!(rejected.data, sequence, "seed {seed} step {step}: rejected event handed back");
+            } else {
+                model.push_back(sequence);
+                peak = peak.max(model.len());
+            }
+        } else {
+            let popped = queue.pop().map(|event| event.data);
+            assert_eq!(popped, model.pop_front(), "seed {seed} step {step}: pop");
+        }
+        assert_eq!(queue.len(), model.len(), "seed {seed} step {step}: len");
+        assert_eq!(queue.is_empty(), model.is_empty(), "seed {seed} step {step}: is_empty");
+        assert_eq!(queue.peak(), peak, "seed {seed} step {step}: peak");
+    }
+}
+
+#[test]
+fn fifo_order_matches_model() {
+    for_each_seed(|seed| {
+        matches_model::<1>(seed, 0);
+        matches_model::<4>(seed, 0);
+        matches_model::<16>(seed, 0);
+    });
+}
+
+#[test]
+fn counters_wrap_without_loss() {
+    for_each_seed(|seed| {
+        let before_wrap = Rng::new(seed).below(64);
+        matches_model::<4>(seed, usize::MAX - before_wrap);
+        matches_model::<16>(seed, usize::MAX - before_wrap);
+    });
+}
+
+#[test]
+fn holds_exactly_capacity() {
+    fn check<const N: usize>(seed: u64) {
+        let start = Rng::new(seed).next() as usize;
+        let queue = LockFreeEventQueue::<N>::starting_at(start);
+        for sequence in 0..N as u32 {
+            assert!(queue.push(event(sequence)).is_ok(), "seed {seed}: push {sequence} of {N}");
+        }
+        assert_eq!(queue.push(event(N as u32)).map_err(|event| event.data), Err(N as u32), "seed {seed}: push when full");
+        assert_eq!(queue.len(), N, "seed {seed}: len when full");
+        assert_eq!(queue.pop().map(|event| event.data), Some(0), "seed {seed}: pop when full");
+        assert!(queue.push(event(N as u32)).is_ok(), "seed {seed}: push after a pop");
+        assert!(queue.push(event(N as u32 + 1)).is_err(), "seed {seed}: full again");
+    }
+    for_each_seed(|seed| {
+        check::<1>(seed);
+        check::<8>(seed);
+        check::<64>(seed);
+    });
+}
+
+#[test]
+fn no_event_lost_or_duplicated() {
+    for_each_seed(|seed| {
+        let mut rng = Rng::new(seed);
+        let queue = LockFreeEventQueue::<16>::starting_at(rng.next() as usize);
+        let mut accepted = Vec::new();
+        let mut received = Vec::new();
+        let mut sequence = 0u32;
+        // Bursts of pushes and pops of random length, as a busy ISR and a slow scheduler would make
+        for _ in 0..OPS / 8 {
+            for _ in 0..rng.below(24) {
+                if queue.push(event(sequence)).is_ok() {
+                    accepted.push(sequence);
+                }
+                sequence += 1;
+            }
+            for _ in 0..rng.below(24) {
+                received.extend(queue.pop().map(|event| event.data));
+            }
+        }
+        while let Some(event) = queue.pop() {
+            received.push(event.data);
+        }
+        assert_eq!(received, accepted, "seed {seed}: every accepted event once, in order");
+        assert!(queue.is_empty(), "seed {seed}: drained");
+    });
+}
+
+#[test]
+fn critical_cell_masks_and_restores() {
+    let _guard = interrupts_enabled();
+    let cell = CriticalCell::new(0u32);
+    cell.with(|value| {
+        assert!(!crate::arch::interrupts_enabled(), "masked inside");
+        *value += 1;
+        // Nested sections leave interrupts to the outermost one
+        let other = CriticalCell::new(());
+        other.with(|_| ());
+        assert!(!crate::arch::interrupts_enabled(), "still masked after a nested section");
+    });
+    assert!(crate::arch::interrupts_enabled(), "restored");
+    assert_eq!(cell.with(|value| *value), 1);
+
+    crate::arch::disable_interrupts();
+    assert_eq!(cell.with_locked(|value| *value), 1);
+    cell.with(|value| *value += 1);
+    assert!(!crate::arch::interrupts_enabled(), "left masked as found");
+    assert_eq!(cell.with_locked(|value| *value), 2);
+    crate::arch::enable_interrupts();
+}
+
+#[test]
+fn critical_cell_rejects_reentry() {
+    let _guard = interrupts_enabled();
+    let cell = CriticalCell::new(0u32);
+    let reentered = panic::catch_unwind(AssertUnwindSafe(|| cell.with(|_| cell.with(|value| *value += 1))));
+    assert!(reentered.is_err(), "second borrow panics");
+    // The claim is released on unwind, so the cell is usable again
+    crate::arch::enable_interrupts();
+    assert_eq!(cell.with(|value| *value), 0, "inner closure never ran");
+}
+
+#[test]
+fn critical_cell_try_with_reports_borrow() {
+    let _guard = interrupts_enabled();
+    let cell = CriticalCell::new(0u32);
+    assert_eq!(cell.with(|_| cell.try_with(|value| *value)), None, "borrowed: no second reference");
+    assert!(crate::arch::interrupts_enabled(), "outer `with` restored the mask");
+    assert_eq!(cell.try_with(|value| *value + 1), Some(1));
+}
+
+#[test]
+fn hot_slot_hands_off_to_woken_task() {
+    let _guard = interrupts_enabled();
+    let mut scheduler = AsyncScheduler::new();
+    for id in 0..3 {
+        scheduler.spawn_task(Task::new(id)).expect("free slot");
+    }
+    assert_eq!(scheduler.schedule().map(|task| task.id), Some(0));
+
+    scheduler.block_current_task(0x42);
+    assert_eq!(scheduler.schedule().map(|task| task.id), Some(1), "round-robin past the blocked task");
+
+    assert!(scheduler.post_event(Event::new(0x42, EventPriority::Normal)));
+    assert_eq!(scheduler.schedule().map(|task| task.id), Some(0), "woken task runs next");
+    assert_eq!(scheduler.tasks().filter(|task| task.state == TaskState::Running).count(), 1, "one task running");
+    assert_eq!(scheduler.level_stats().hot_hits, 1);
+
+    // No waiter: the event is only counted, and nothing is handed off
+    assert!(scheduler.post_event(Event::new(0x43, EventPriority::High)));
+    assert!(scheduler.schedule().is_some());
+    assert_eq!(scheduler.stats().1, 2, "both events queued");
+    assert_eq!(scheduler.level_stats().hot_hits, 1);
+}
+
+#[test]
+fn executor_wakes_sleepers_at_their_deadline() {
+    let _guard = interrupts_enabled();
+    let mut executor = MultiPriorityExecutor::new();
+    executor.spawn_task(Task::with_priority(7, TaskPriority::High)).expect("free slot");
+    executor.spawn_task(Task::with_priority(8, TaskPriority::Low)).expect("free slot");
+    assert_eq!(executor.run_cycle().map(|task| task.id), Some(7));
+
+    executor.sleep_current(Duration::from_ticks(5));
+    assert!(executor.has_sleeping_tasks());
+    for now in 1..5 {
+        executor.update_timer(Instant::from_ticks(now));
+        assert_eq!(executor.run_cycle().map(|task| task.id), Some(8), "tick {now}: sleeper passed over");
+    }
+
+    executor.update_timer(Instant::from_ticks(5));
+    assert!(!executor.has_sleeping_tasks());
+    assert_eq!(executor.run_cycle().map(|task| task.id), Some(7), "woken at its deadline");
+}
+
+#[test]
+fn scheduler_runs_a_ready_task() {
+    let _guard = interrupts_enabled();
+    for_each_seed(|seed| {
+        let mut rng = Rng::new(seed);
+        let mut scheduler = AsyncScheduler::new();
+        for id in 0..rng.below(super::MAX_TASKS) + 1 {
+            scheduler.spawn_task(Task::new(id)).expect("free slot");
+        }
+        for step in 0..OPS {
+            // Events pending now are processed, and may wake tasks, before the pick
+            let Some(id) = scheduler.schedule().map(|task| task.id) else {
+                assert!(scheduler.tasks().all(|task| !task.is_ready()), "seed {seed} step {step}: a ready task was passed over");
+                let waiting = scheduler.tasks().find_map(|task| task.waiting_event);
+                scheduler.post_event(Event::new(waiting.expect("some task waits"), EventPriority::Normal));
+                continue;
+            };
+            let running: Vec<_> = scheduler.tasks().filter(|task| task.state == TaskState::Running).map(|task| task.id).collect();
+            assert_eq!(running, [id], "seed {seed} step {step}: only the scheduled task runs");
+            match rng.below(4) {
+                0 => scheduler.block_current_task(rng.below(4) as u32),
+                1 => {
+                    scheduler.post_event(Event::new(rng.below(4) as u32, EventPriority::Normal));
+                }
+                _ => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,235 @@
+//! Kernel shell
+//! Command-line parsing and built-in commands for the interactive console
+//!
+//! A command line is split into whitespace-separated arguments; single or
+//! double quotes keep spaces inside one argument. `Args` then hands them
+//! to a command with typed accessors (`u32`, `usize`, ...), so a command
+//! such as `peek 0x4000C000 4` reads its operands in one call each.
+//!
+//! Commands are entries of the static `COMMANDS` table; `execute` looks
+//! the first argument up there and reports any error on the same output.
+//! `UartInterface` turns received bytes into edited command lines, and
+//! with the `shell` feature `task` runs it on the console at boot, after
+//! the commands of the build's boot `script`. Host tools can talk to
+//! the same console in binary frames, see `host`, and `rx` loads data
+//! into RAM with XMODEM, see `xmodem`. With `shell_login` the console
+//! asks for credentials first, see `login`. Commands marked `paged`
+//! stop after each screenful with `--more--`, see `pager`.
+//! Errors, usage hints and the prompt are colored by `color`.
+
+use core::fmt::{self, Write};
+#[cfg(feature = "embedded_io")]
+use core::task::Poll;
+
+use color::{styled, Style};
+#[cfg(feature = "embedded_io")]
+use crate::drivers::console_rx;
+
+mod args;
+pub mod color;
+mod commands;
+mod host;
+mod interface;
+mod login;
+mod pager;
+mod script;
+mod sha256;
+mod task;
+mod xmodem;
+
+#[allow(unused_imports)]
+pub use args::{parse_i32, parse_u32, parse_usize, ArgError, Args, MAX_ARGS};
+pub use commands::COMMANDS;
+#[allow(unused_imports)]
+pub use interface::{
+    echo, enter_raw_mode, leave_raw_mode, raw_dropped, raw_mode, set_echo, UartInterface, HISTORY_DEPTH, LINE_MAX,
+    PROMPT, RAW_INPUT,
+};
+#[allow(unused_imports)]
+pub use host::{crc16, FrameReceiver, HostStats, PROTOCOL_VERSION};
+#[allow(unused_imports)]
+pub use pager::{Pager, MORE, PAGE_LINES};
+#[allow(unused_imports)]
+pub use script::{run_script, BOOT_SCRIPT};
+#[allow(unused_imports)]
+pub use task::{init, run, TASK_ID};
+
+/// Why a command failed
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    Args(ArgError),
+    /// Wrong arguments; the command's usage line is printed
+    Usage,
+    Failed(&'static str),
+    /// The output could not be written
+    Output,
+}
+
+impl From<ArgError> for CommandError {
+    fn from(error: ArgError) -> Self {
+        CommandError::Args(error)
+    }
+}
+
+impl From<fmt::Error> for CommandError {
+    fn from(_: fmt::Error) -> Self {
+        CommandError::Output
+    }
+}
+
+/// Command handler; index 0 of the arguments is the command name
+pub type CommandFn = fn(&Args, &mut dyn Write) -> Result<(), CommandError>;
+
+/// Built-in shell command
+pub struct Command {
+    pub name: &'static str,
+    /// Arguments, e.g. `<addr> [count]`
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Output can run past a screen, so the shell shows it a page at a time
+    pub paged: bool,
+    pub run: CommandFn,
+}
+
+/// Look up a command by name
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Parse and run one command line, writing its output to `out`
+///
+/// Returns false if the line could not be parsed, named no known command
+/// or the command failed; the reason has already been written to `out`.
+#[allow(dead_code)]
+pub fn execute(line: &str, out: &mut dyn Write) -> bool {
+    let args = match Args::parse(line) {
+        Ok(args) => args,
+        Err(error) => {
+            let _ = writeln!(out, "{}", styled(Style::Error, error));
+            return false;
+        }
+    };
+    if args.is_empty() {
+        return true;
+    }
+    let Some(command) = find(args.command()) else {
+        let _ = writeln!(out, "{}", styled(Style::Error, format_args!("{}: unknown command, try 'help'", args.command())));
+        return false;
+    };
+    let result = (command.run)(&args, out);
+    match result {
+        Ok(()) => true,
+        Err(CommandError::Usage) => {
+            let _ = writeln!(out, "{}", styled(Style::Warning, format_args!("usage: {} {}", command.name, command.usage)));
+            false
+        }
+        Err(CommandError::Args(error)) => {
+            let _ = writeln!(out, "{}", styled(Style::Error, format_args!("{}: {}", command.name, error)));
+            false
+        }
+        Err(CommandError::Failed(reason)) => {
+            let _ = writeln!(out, "{}", styled(Style::Error, format_args!("{}: {}", command.name, reason)));
+            false
+        }
+        Err(CommandError::Output) => false,
+    }
+}
+
+/// Unbuffered writer to the kernel console
+#[allow(dead_code)]
+pub struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::arch::console_write(s.as_bytes());
+        Ok(())
+    }
+}
+
+// With `embedded_io` the console is also a byte stream. Reads take from
+// the receive pipe the shell task reads too, so they suit code that has
+// the console to itself: built without `shell`, or in raw mode. Boards
+// without a receive interrupt are polled on every try, and async reads
+// there poll again at once.
+
+#[cfg(feature = "embedded_io")]
+impl embedded_io::ErrorType for Console {
+    type Error = core::convert::Infallible;
+}
+
+#[cfg(feature = "embedded_io")]
+impl Console {
+    /// Take what the console received, polling the UART where needed
+    fn try_read(buf: &mut [u8]) -> usize {
+        console_rx::poll();
+        console_rx::RX.try_read(buf)
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl embedded_io::Read for Console {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match Self::try_read(buf) {
+                0 => crate::power::sleep_unless(|| !console_rx::RX.is_empty()),
+                read => return Ok(read),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl embedded_io::ReadReady for Console {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        console_rx::poll();
+        Ok(!console_rx::RX.is_empty())
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl embedded_io::Write for Console {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        crate::arch::console_write(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        crate::arch::console_flush();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded_io")]
+impl embedded_io_async::Read for Console {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let read = core::future::poll_fn(|cx| {
+            if console_rx::interrupt_driven() {
+                return console_rx::RX.poll_read(cx, buf);
+            }
+            match Self::try_read(buf) {
+                0 if !buf.is_empty() => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                read => Poll::Ready(read),
+            }
+        });
+        Ok(read.await)
+    }
+}
+
+// Console output is sent before `write` returns
+#[cfg(feature = "embedded_io")]
+impl embedded_io_async::Write for Console {
+    async fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(self, data)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io::Write::flush(self)
+    }
+}
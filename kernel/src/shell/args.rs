@@ -0,0 +1,173 @@
+//! Shell argument parsing
+//! Quote-aware line splitting and numeric argument helpers
+
+use core::fmt;
+
+use heapless::Vec;
+
+/// Most arguments on one line, command name included
+pub const MAX_ARGS: usize = 8;
+
+/// Why a line or argument could not be parsed
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArgError {
+    /// A quote was opened and never closed
+    UnterminatedQuote,
+    /// A closing quote was followed by something other than whitespace
+    TrailingQuote,
+    TooManyArgs,
+    /// Argument `index` was required but not given
+    Missing(usize),
+    /// Argument `index` is not a valid number
+    NotANumber(usize),
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ArgError::TrailingQuote => write!(f, "text directly after a closing quote"),
+            ArgError::TooManyArgs => write!(f, "too many arguments (max {})", MAX_ARGS),
+            ArgError::Missing(index) => write!(f, "missing argument {}", index),
+            ArgError::NotANumber(index) => write!(f, "argument {} is not a number", index),
+        }
+    }
+}
+
+/// Arguments of one command line; index 0 is the command name
+#[derive(Debug)]
+pub struct Args<'a> {
+    args: Vec<&'a str, MAX_ARGS>,
+}
+
+#[allow(dead_code)]
+impl<'a> Args<'a> {
+    /// Split `line` at whitespace, keeping quoted text as one argument
+    ///
+    /// Quotes (`"` or `'`) are only recognised at the start of an argument
+    /// and are not part of it; there are no escapes inside them.
+    pub fn parse(line: &'a str) -> Result<Self, ArgError> {
+        let mut args = Vec::new();
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let (arg, after) = match rest.as_bytes()[0] {
+                quote @ (b'"' | b'\'') => {
+                    let body = &rest[1..];
+                    let end = body.find(quote as char).ok_or(ArgError::UnterminatedQuote)?;
+                    let after = &body[end + 1..];
+                    if after.starts_with(|c: char| !c.is_whitespace()) {
+                        return Err(ArgError::TrailingQuote);
+                    }
+                    (&body[..end], after)
+                }
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+            args.push(arg).map_err(|_| ArgError::TooManyArgs)?;
+            rest = after.trim_start();
+        }
+        Ok(Self { args })
+    }
+
+    /// Command name, empty for a blank line
+    pub fn command(&self) -> &'a str {
+        self.args.first().copied().unwrap_or("")
+    }
+
+    /// Number of arguments, command name included
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        self.args.get(index).copied()
+    }
+
+    /// Argument `index`, or `ArgError::Missing`
+    pub fn required(&self, index: usize) -> Result<&'a str, ArgError> {
+        self.get(index).ok_or(ArgError::Missing(index))
+    }
+
+    /// Arguments after the command name
+    pub fn operands(&self) -> &[&'a str] {
+        self.args.get(1..).unwrap_or(&[])
+    }
+
+    /// Argument `index` as a number (see `parse_u32`)
+    pub fn u32(&self, index: usize) -> Result<u32, ArgError> {
+        parse_u32(self.required(index)?).ok_or(ArgError::NotANumber(index))
+    }
+
+    /// Argument `index` as a number, `default` if it was not given
+    pub fn u32_or(&self, index: usize, default: u32) -> Result<u32, ArgError> {
+        match self.get(index) {
+            Some(arg) => parse_u32(arg).ok_or(ArgError::NotANumber(index)),
+            None => Ok(default),
+        }
+    }
+
+    pub fn usize(&self, index: usize) -> Result<usize, ArgError> {
+        parse_usize(self.required(index)?).ok_or(ArgError::NotANumber(index))
+    }
+
+    pub fn i32(&self, index: usize) -> Result<i32, ArgError> {
+        parse_i32(self.required(index)?).ok_or(ArgError::NotANumber(index))
+    }
+
+    pub fn u64(&self, index: usize) -> Result<u64, ArgError> {
+        parse_u64(self.required(index)?).ok_or(ArgError::NotANumber(index))
+    }
+}
+
+/// Parse a number in decimal, hex (`0x`), binary (`0b`) or octal (`0o`)
+///
+/// Underscores may separate digits (`0x4000_C000`).
+pub fn parse_u32(text: &str) -> Option<u32> {
+    u32::try_from(parse_u64(text)?).ok()
+}
+
+/// `parse_u32` for 64-bit values
+pub fn parse_u64(text: &str) -> Option<u64> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0b" | "0B") => (&text[2..], 2),
+        Some("0o" | "0O") => (&text[2..], 8),
+        _ => (text, 10),
+    };
+    if digits.is_empty() || digits.starts_with('_') {
+        return None;
+    }
+    digits.chars().filter(|&c| c != '_').try_fold(0u64, |value, c| {
+        value.checked_mul(radix as u64)?.checked_add(c.to_digit(radix)? as u64)
+    })
+}
+
+/// `parse_u32` for sizes and addresses
+pub fn parse_usize(text: &str) -> Option<usize> {
+    parse_u32(text).map(|value| value as usize)
+}
+
+/// `parse_u32` with an optional leading `-`
+pub fn parse_i32(text: &str) -> Option<i32> {
+    match text.strip_prefix('-') {
+        Some(magnitude) => {
+            let value = parse_u32(magnitude)?;
+            if value == 1 << 31 {
+                Some(i32::MIN)
+            } else {
+                i32::try_from(value).ok().map(|value| -value)
+            }
+        }
+        None => i32::try_from(parse_u32(text)?).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,80 @@
+//! Host tests for shell argument parsing
+//! Line splitting, its errors, and the number parsers
+
+use super::{parse_i32, parse_u32, parse_u64, ArgError, Args, MAX_ARGS};
+
+fn split(line: &str) -> Result<Vec<&str>, ArgError> {
+    Args::parse(line).map(|args| (0..args.len()).filter_map(|i| args.get(i)).collect())
+}
+
+#[test]
+fn splits_at_whitespace() {
+    assert_eq!(split("  peek\t0x20000000   4 "), Ok(vec!["peek", "0x20000000", "4"]));
+    assert_eq!(split(""), Ok(vec![]));
+    assert_eq!(split("   "), Ok(vec![]));
+}
+
+#[test]
+fn quotes_keep_one_argument() {
+    assert_eq!(split(r#"echo "a b"  'c "d"' e"#), Ok(vec!["echo", "a b", r#"c "d""#, "e"]));
+    assert_eq!(split(r#"echo """#), Ok(vec!["echo", ""]));
+    // Only a leading quote opens one
+    assert_eq!(split(r#"echo a"b"#), Ok(vec!["echo", r#"a"b"#]));
+}
+
+#[test]
+fn quote_errors() {
+    assert_eq!(split(r#"echo "a b"#).unwrap_err(), ArgError::UnterminatedQuote);
+    assert_eq!(split(r#"echo "a"b"#).unwrap_err(), ArgError::TrailingQuote);
+    assert_eq!(split("echo 'a''b'").unwrap_err(), ArgError::TrailingQuote);
+}
+
+#[test]
+fn too_many_args() {
+    let full = ["x"; MAX_ARGS].join(" ");
+    assert_eq!(split(&full).map(|args| args.len()), Ok(MAX_ARGS));
+    assert_eq!(split(&format!("{} x", full)).unwrap_err(), ArgError::TooManyArgs);
+}
+
+#[test]
+fn typed_accessors_name_the_argument() {
+    let args = Args::parse("cmd 12 zz").unwrap();
+    assert_eq!(args.command(), "cmd");
+    assert_eq!(args.operands(), &["12", "zz"]);
+    assert_eq!(args.u32(1), Ok(12));
+    assert_eq!(args.u32(2), Err(ArgError::NotANumber(2)));
+    assert_eq!(args.u32(3), Err(ArgError::Missing(3)));
+    assert_eq!(args.u32_or(3, 7), Ok(7));
+}
+
+#[test]
+fn radix_prefixes() {
+    assert_eq!(parse_u32("42"), Some(42));
+    assert_eq!(parse_u32("0x4000_C000"), Some(0x4000_C000));
+    assert_eq!(parse_u32("0XfF"), Some(0xFF));
+    assert_eq!(parse_u32("0b1010"), Some(10));
+    assert_eq!(parse_u32("0o17"), Some(15));
+    assert_eq!(parse_u32("0"), Some(0));
+    for bad in ["", "0x", "0x_1", "_1", "0b2", "0o8", "12a", "-1", "+1"] {
+        assert_eq!(parse_u32(bad), None, "{:?}", bad);
+    }
+}
+
+#[test]
+fn range_limits() {
+    assert_eq!(parse_u32("0xFFFF_FFFF"), Some(u32::MAX));
+    assert_eq!(parse_u32("0x1_0000_0000"), None);
+    assert_eq!(parse_u64("0xFFFF_FFFF_FFFF_FFFF"), Some(u64::MAX));
+    assert_eq!(parse_u64("18446744073709551616"), None);
+}
+
+#[test]
+fn signed() {
+    assert_eq!(parse_i32("-2147483648"), Some(i32::MIN));
+    assert_eq!(parse_i32("-0x8000_0000"), Some(i32::MIN));
+    assert_eq!(parse_i32("2147483647"), Some(i32::MAX));
+    assert_eq!(parse_i32("2147483648"), None);
+    assert_eq!(parse_i32("-2147483649"), None);
+    assert_eq!(parse_i32("-0"), Some(0));
+    assert_eq!(parse_i32("--1"), None);
+}
@@ -0,0 +1,84 @@
+//! Shell colors
+//! ANSI styling for errors, warnings, the prompt and log levels
+//!
+//! Built in with the `shell_color` feature and switched at run time with
+//! `color on|off` for terminals that show escape codes as text. Without
+//! the feature, or while switched off, styled text is written plain. The
+//! setting also covers log lines the logger writes to its terminal sinks.
+
+use core::fmt;
+
+use crate::arch::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "shell_color"));
+
+/// Kinds of shell output that are colored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Red
+    Error,
+    /// Yellow
+    Warning,
+    /// Bold
+    Prompt,
+    /// Green
+    Info,
+    /// Faint
+    Muted,
+}
+
+impl Style {
+    const fn code(self) -> &'static str {
+        match self {
+            Style::Error => "\x1b[31m",
+            Style::Warning => "\x1b[33m",
+            Style::Prompt => "\x1b[1m",
+            Style::Info => "\x1b[32m",
+            Style::Muted => "\x1b[2m",
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Turn colors on or off; false if they are not built in
+pub fn set_enabled(on: bool) -> bool {
+    if on && !cfg!(feature = "shell_color") {
+        return false;
+    }
+    ENABLED.store(on, Ordering::Relaxed);
+    true
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// `value` displayed in `style` while colors are on
+pub struct Styled<T>(Style, T);
+
+pub fn styled<T: fmt::Display>(style: Style, value: T) -> Styled<T> {
+    Styled(style, value)
+}
+
+impl<T: fmt::Display> fmt::Display for Styled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            write!(f, "{}{}{}", self.0.code(), self.1, RESET)
+        } else {
+            write!(f, "{}", self.1)
+        }
+    }
+}
+
+/// Style for a log line, judged by the markers the kernel logs with
+pub fn classify(line: &str) -> Option<Style> {
+    let starts = |prefix: &str| line.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix));
+    if line.contains('❌') || starts("error") || line.contains("panic") {
+        Some(Style::Error)
+    } else if line.contains('⚠') || starts("warn") {
+        Some(Style::Warning)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,567 @@
+//! Built-in shell commands
+//! The `COMMANDS` table and the handlers it points to
+
+use core::fmt::Write;
+
+use super::color::{self, styled};
+use super::{Args, Command, CommandError};
+use crate::logger::{Level, Sink, Target};
+use crate::memory::checked::{self, Access};
+use crate::memory::RegionKind;
+use crate::scheduler::{self, Task, TaskPriority, TaskState};
+
+/// Every built-in command, in the order `help` lists them
+pub const COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "[command]", help: "list commands or show one's usage", paged: true, run: help },
+    Command { name: "ps", usage: "", help: "list tasks with run statistics and deadline misses", paged: true, run: ps },
+    Command { name: "free", usage: "", help: "show heap, stack and static buffer usage", paged: false, run: free },
+    Command { name: "log", usage: "[count | clear | prev | records | level [target] [name] | sink [name on|off|level]]", help: "print or empty the log, show the previous boot's, or set its level and sinks", paged: true, run: log },
+    Command { name: "stats", usage: "", help: "show scheduler queue depths, drops and switches, event latency (instrumentation), log losses and metrics", paged: false, run: stats },
+    Command { name: "dump", usage: "", help: "print every task, the thread stack and queue depths, as a panic report does", paged: true, run: dump },
+    Command { name: "bench", usage: "[samples]", help: "time context switch, event wake, channel and critical section in cycles", paged: false, run: bench },
+    Command { name: "trace", usage: "", help: "print the recorded event posts and ticks, in the simulator's replay format", paged: true, run: trace },
+    Command { name: "faults", usage: "[seed <n> | off | spawn|drop|reorder|tick|alloc <per-mille>]", help: "show or set fault injection rates and the seed they draw from", paged: false, run: faults },
+    Command { name: "uptime", usage: "[drift [ppm | cal]]", help: "show time since boot, tick rate and idle share, or set or calibrate the tick's drift correction", paged: false, run: uptime },
+    Command { name: "color", usage: "[on|off]", help: "show or switch colored output", paged: false, run: color },
+    Command { name: "echo", usage: "[on|off]", help: "show or switch echo of typed input", paged: false, run: echo },
+    Command { name: "logout", usage: "", help: "end the session and ask for a login again", paged: false, run: logout },
+    Command { name: "peek", usage: "[-b|-h|-w] <addr> [count]", help: "dump memory or device registers", paged: false, run: peek },
+    Command { name: "poke", usage: "[-b|-h|-w] <addr> <value>", help: "write memory or a device register", paged: false, run: poke },
+    Command { name: "rx", usage: "<addr> [max-len]", help: "receive data into RAM with XMODEM-CRC", paged: false, run: rx },
+];
+
+fn help(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if let Some(name) = args.get(1) {
+        let command = super::find(name).ok_or(CommandError::Failed("no such command"))?;
+        writeln!(out, "usage: {} {}", command.name, command.usage)?;
+        writeln!(out, "  {}", command.help)?;
+        return Ok(());
+    }
+    for command in COMMANDS {
+        writeln!(out, "  {:<8} {}", command.name, command.help)?;
+    }
+    Ok(())
+}
+
+fn priority_name(priority: TaskPriority) -> &'static str {
+    match priority {
+        TaskPriority::Critical => "critical",
+        TaskPriority::High => "high",
+        TaskPriority::Normal => "normal",
+        TaskPriority::Low => "low",
+    }
+}
+
+fn write_state(out: &mut dyn Write, state: &TaskState) -> core::fmt::Result {
+    match state {
+        TaskState::Ready => write!(out, "{:<12}", "ready"),
+        TaskState::Running => write!(out, "{:<12}", "running"),
+        TaskState::WaitingForEvent(event_id) => write!(out, "wait {:<#7x}", event_id),
+        TaskState::Sleeping(wake_time) => write!(out, "sleep {:<6}", wake_time.ticks()),
+        TaskState::Completed => write!(out, "{:<12}", "done"),
+    }
+}
+
+/// Cycles the CPU has run since boot, if the cycle counter rate is known
+fn elapsed_cycles() -> Option<u64> {
+    let cpu_hz = crate::kernel::time::cpu_hz()? as u64;
+    Some(crate::kernel::time::now() * cpu_hz / crate::config::TICK_HZ as u64)
+}
+
+fn ps(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    let elapsed = elapsed_cycles().filter(|&cycles| cycles > 0);
+    writeln!(out, "  ID NAME         PRI      STATE            RUNS    WAKES  MISSES   CPU%")?;
+    let mut result = Ok(());
+    scheduler::for_each_task(|task: &Task| {
+        if result.is_err() {
+            return;
+        }
+        result = (|| {
+            let name = if task.name.is_empty() { "-" } else { task.name };
+            write!(out, "{:>4} {:<12} {:<8} ", task.id, name, priority_name(task.priority))?;
+            write_state(out, &task.state)?;
+            write!(out, " {:>8} {:>8} {:>7} ", task.stats.runs, task.stats.wakes, task.stats.deadline_misses)?;
+            match elapsed {
+                Some(elapsed) => {
+                    let permille = task.stats.cycles.saturating_mul(1000) / elapsed;
+                    writeln!(out, "{:>4}.{}", permille / 10, permille % 10)
+                }
+                None => writeln!(out, "{:>6}", "-"),
+            }
+        })();
+    });
+    result.map_err(CommandError::from)
+}
+
+fn free(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    writeln!(out, "                used    total")?;
+    // The `.heap` section is reserved by the linker but nothing allocates from it yet
+    let heap = crate::board::descriptor().heap_size;
+    writeln!(out, "{:<12} {:>7} {:>8}  largest free {}", "heap", 0, heap, heap)?;
+    match crate::memory::stack_usage() {
+        Some(stack) => writeln!(out, "{:<12} {:>7} {:>8}  peak, shared by all tasks", "stack", stack.peak, stack.size)?,
+        None => writeln!(out, "{:<12} {:>7} {:>8}", "stack", "-", "-")?,
+    }
+    let log = crate::logger::Logger::get_stats();
+    writeln!(out, "{:<12} {:>7} {:>8}", "log lines", log.held, crate::config::LOG_LINES)?;
+    let occupancy = scheduler::occupancy();
+    writeln!(out, "{:<12} {:>7} {:>8}", "tasks", occupancy.tasks, scheduler::TASK_CAPACITY)?;
+    writeln!(out, "{:<12} {:>7} {:>8}  scheduler queues", "events", occupancy.events, scheduler::EVENT_CAPACITY)?;
+    let (queued, _) = crate::accounting::totals(crate::accounting::Resource::QueueSlot);
+    let queue_slots = crate::kernel::KERNEL_QUEUES * crate::kernel::KERNEL_QUEUE_DEPTH;
+    writeln!(out, "{:<12} {:>7} {:>8}  kernel queues", "messages", queued, queue_slots)?;
+    Ok(())
+}
+
+fn stats(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    let depth = scheduler::MAX_EVENTS_PER_PRIORITY;
+    writeln!(out, "LEVEL     QUEUED  PEAK/DEPTH  DROPPED  SWITCHES      RUNS   HOT%")?;
+    let priorities = [TaskPriority::Critical, TaskPriority::High, TaskPriority::Normal, TaskPriority::Low];
+    for (priority, level) in priorities.into_iter().zip(scheduler::level_stats()) {
+        write!(out, "{:<8} {:>7} {:>5}/{:<5} {:>8} {:>9} {:>9} ", priority_name(priority), level.queued, level.peak, depth, level.dropped, level.switches, level.dispatches)?;
+        // Share of runs that came straight from the hot slot a wake-up filled
+        match (level.hot_hits as u64 * 1000).checked_div(level.dispatches as u64) {
+            Some(permille) => writeln!(out, "{:>4}.{}", permille / 10, permille % 10)?,
+            None => writeln!(out, "{:>6}", "-")?,
+        }
+    }
+    // Post-to-process time of the events at each priority
+    if cfg!(feature = "instrumentation") {
+        writeln!(out, "EVENTS    HANDLED   MIN us   AVG us   MAX us")?;
+        for (priority, level) in priorities.into_iter().zip(scheduler::level_stats()) {
+            let latency = level.latency;
+            write!(out, "{:<8} {:>8} ", priority_name(priority), latency.handled)?;
+            match latency.avg_us() {
+                Some(avg) => writeln!(out, "{:>8} {:>8} {:>8}", latency.min_us, avg, latency.max_us)?,
+                None => writeln!(out, "{:>8} {:>8} {:>8}", "-", "-", "-")?,
+            }
+        }
+    }
+    // Either count growing means the log buffer is too small or too slow
+    let log = crate::logger::Logger::get_stats();
+    writeln!(out, "log: {} lines, {} truncated, {} overwritten before drained", log.total, log.truncated, log.overwritten)?;
+    if cfg!(feature = "metrics") {
+        writeln!(out, "METRIC                   KIND          VALUE")?;
+        for sample in (0..crate::metrics::len()).filter_map(crate::metrics::get) {
+            writeln!(out, "{:<24} {:<8} {:>10}", sample.name, sample.kind.name(), sample.value)?;
+        }
+        let refused = crate::metrics::refused();
+        if refused > 0 {
+            writeln!(out, "{} metrics left out, registry full at {}", refused, crate::metrics::MAX_METRICS)?;
+        }
+    }
+    Ok(())
+}
+
+fn dump(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    crate::kernel::dump_state(out)?;
+    Ok(())
+}
+
+#[cfg(feature = "bench")]
+fn bench(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    use crate::kernel::bench::{self, BenchError, BenchResult};
+    if args.len() > 2 {
+        return Err(CommandError::Usage);
+    }
+    let samples = args.u32_or(1, bench::DEFAULT_SAMPLES)?;
+    let cpu_hz = crate::kernel::time::cpu_hz();
+    writeln!(out, "BENCH      SAMPLES      MIN      AVG      MAX   MIN ns")?;
+    let mut written = Ok(());
+    let run = bench::run(samples, |result: &BenchResult| {
+        if written.is_err() {
+            return;
+        }
+        written = (|| {
+            write!(out, "{:<9} {:>8} {:>8} {:>8} {:>8} ", result.name, result.samples, result.min, result.avg, result.max)?;
+            match cpu_hz {
+                Some(hz) => writeln!(out, "{:>8}", result.min as u64 * 1_000_000_000 / hz as u64),
+                None => writeln!(out, "{:>8}", "-"),
+            }
+        })();
+    });
+    let overhead = run.map_err(|error| match error {
+        BenchError::Busy => CommandError::Failed("a benchmark run is in progress"),
+        BenchError::NoSamples => CommandError::Usage,
+        BenchError::Broken(reason) => CommandError::Failed(reason),
+    })?;
+    written?;
+    writeln!(out, "cycles per operation; counter read of {} cycles taken off", overhead)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench(_args: &Args, _out: &mut dyn Write) -> Result<(), CommandError> {
+    Err(CommandError::Failed("built without the bench feature"))
+}
+
+#[cfg(feature = "trace")]
+fn trace(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    writeln!(out, "# point tick input, at scheduling point {}", crate::kernel::trace::point())?;
+    let mut written = Ok(());
+    let dropped = crate::kernel::trace::for_each(|record| {
+        if written.is_ok() {
+            written = writeln!(out, "{}", record);
+        }
+    });
+    written?;
+    if dropped > 0 {
+        writeln!(out, "# {} later records did not fit", dropped)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "trace"))]
+fn trace(_args: &Args, _out: &mut dyn Write) -> Result<(), CommandError> {
+    Err(CommandError::Failed("built without the trace feature"))
+}
+
+#[cfg(feature = "fault_inject")]
+fn faults(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    use crate::kernel::faults::{self, Fault};
+    match (args.len(), args.get(1)) {
+        (1, _) => {}
+        (2, Some("off")) => Fault::ALL.into_iter().for_each(|fault| faults::set_rate(fault, 0)),
+        (3, Some("seed")) => crate::drivers::rng::reseed(args.u64(2)?),
+        (3, Some(name)) => {
+            let fault = Fault::from_name(name).ok_or(CommandError::Usage)?;
+            faults::set_rate(fault, args.u32(2)?);
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    writeln!(out, "seed {:#x}", crate::drivers::rng::seed())?;
+    writeln!(out, "FAULT   PER MILLE  INJECTED")?;
+    for fault in Fault::ALL {
+        writeln!(out, "{:<7} {:>9} {:>9}", fault.name(), faults::rate(fault), faults::injected(fault))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fault_inject"))]
+fn faults(_args: &Args, _out: &mut dyn Write) -> Result<(), CommandError> {
+    Err(CommandError::Failed("built without the fault_inject feature"))
+}
+
+fn uptime(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.get(1) == Some("drift") {
+        return uptime_drift(args, out);
+    }
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    let uptime = crate::kernel::time::uptime();
+    let idle_ticks = crate::power::stats().idle_ticks;
+    write!(out, "up {}.{:03}, {} ticks at {} Hz", uptime, uptime.millis() % 1000, uptime.ticks, crate::config::TICK_HZ)?;
+    match idle_ticks.saturating_mul(1000).checked_div(uptime.ticks) {
+        Some(permille) => writeln!(out, ", {}.{}% idle", permille / 10, permille % 10)?,
+        None => writeln!(out)?,
+    }
+    Ok(())
+}
+
+fn uptime_drift(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    use crate::kernel::time;
+    match (args.len(), args.get(2)) {
+        (2, _) => {}
+        (3, Some("cal")) => {
+            let reference = time::rtc_micros().ok_or(CommandError::Failed("no RTC on this board"))?;
+            if time::calibrate_against(reference).is_none() {
+                let seconds = time::MIN_CALIBRATION_US / 1_000_000;
+                writeln!(out, "calibrating; run again in {} s or more, longer is finer", seconds)?;
+                return Ok(());
+            }
+        }
+        (3, _) => time::set_drift_ppm(args.i32(2)?),
+        _ => return Err(CommandError::Usage),
+    }
+    writeln!(out, "drift {:+} ppm", time::drift_ppm())?;
+    Ok(())
+}
+
+/// Log lines written between console flushes
+const LOG_PAGE_LINES: usize = 16;
+
+fn log(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    match args.get(1) {
+        Some("level") => return log_level(args, out),
+        Some("sink") => return log_sink(args, out),
+        Some("prev") if args.len() == 2 => return log_prev(out),
+        #[cfg(feature = "log_binary")]
+        Some("records") if args.len() == 2 => return log_records(out),
+        _ => {}
+    }
+    if args.len() > 2 {
+        return Err(CommandError::Usage);
+    }
+    if args.get(1) == Some("clear") {
+        crate::logger::Logger::clear();
+        return Ok(());
+    }
+    let count = args.get(1).map_or(Ok(crate::config::LOG_LINES), |_| args.usize(1))?;
+    for line in crate::logger::Logger::snapshot(count) {
+        // Number lines by their position in everything ever logged
+        let number = line.seq + 1;
+        match color::classify(line.as_str()) {
+            Some(style) => writeln!(out, "[{:>5}] {}", number, styled(style, line.as_str()))?,
+            None => writeln!(out, "[{:>5}] {}", number, line.as_str())?,
+        }
+        if number.is_multiple_of(LOG_PAGE_LINES) {
+            crate::arch::console_flush();
+        }
+    }
+    Ok(())
+}
+
+/// `log prev`: the lines the previous boot logged last
+fn log_prev(out: &mut dyn Write) -> Result<(), CommandError> {
+    let mut result = Ok(());
+    let kept = crate::crashdump::previous_log(|line| {
+        if result.is_ok() {
+            result = match color::classify(line) {
+                Some(style) => writeln!(out, "{}", styled(style, line)),
+                None => writeln!(out, "{}", line),
+            };
+        }
+    });
+    if !kept {
+        return Err(CommandError::Failed("nothing kept from the previous boot"));
+    }
+    result.map_err(CommandError::from)
+}
+
+/// `log records`: the binary log records, rendered
+#[cfg(feature = "log_binary")]
+fn log_records(out: &mut dyn Write) -> Result<(), CommandError> {
+    let mut result = Ok(());
+    crate::log_binary::visit_from(0, |_, bytes| {
+        if let Some(record) = crate::log_binary::Record::parse(bytes) {
+            result = writeln!(out, "[{:>10}] {}", record.ticks, record);
+        }
+        result.is_ok()
+    });
+    result.map_err(CommandError::from)
+}
+
+/// `log level [target] [name]`: show or set the runtime log filters
+///
+/// A target's level overrides the global one until set to `default`.
+fn log_level(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    match args.len() {
+        2 => {
+            writeln!(out, "{:<10} {}", "all", crate::logger::level())?;
+            for target in Target::ALL {
+                if let Some(level) = crate::logger::target_level(target) {
+                    writeln!(out, "{:<10} {}", target, level)?;
+                }
+            }
+            writeln!(out, "built up to {}", crate::config::LOG_MAX_LEVEL)?;
+        }
+        3 => crate::logger::set_level(parse_level(args.get(2), out)?),
+        4 => {
+            let target = args.get(2).and_then(Target::from_name);
+            let target = target.ok_or(CommandError::Failed("targets are kernel, scheduler, uart, net, app"))?;
+            let level = match args.get(3) {
+                Some("default") => None,
+                name => Some(parse_level(name, out)?),
+            };
+            crate::logger::set_target_level(target, level);
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// `log sink [name on|off|level]`: show or set where log lines go
+///
+/// A sink takes lines up to its level; `on` restores its default one.
+fn log_sink(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    match args.len() {
+        2 => {
+            for sink in Sink::ALL {
+                write!(out, "{:<12} ", sink)?;
+                match (sink.built(), crate::logger::sink_level(sink)) {
+                    (false, _) => writeln!(out, "not built")?,
+                    (true, None) => writeln!(out, "off")?,
+                    (true, Some(level)) if sink == Sink::Uart => writeln!(out, "{}, and log_visible! lines", level)?,
+                    (true, Some(level)) => writeln!(out, "{}", level)?,
+                }
+            }
+        }
+        4 => {
+            let sink = args.get(2).and_then(Sink::from_name);
+            let sink = sink.ok_or(CommandError::Failed("sinks are buffer, uart, rtt, semihosting"))?;
+            if !sink.built() {
+                return Err(CommandError::Failed("sink not built in"));
+            }
+            let level = match args.get(3) {
+                Some("off") => None,
+                Some("on") => Some(sink.default_level().unwrap_or(Level::Trace)),
+                name => Some(parse_level(name, out)?),
+            };
+            crate::logger::set_sink_level(sink, level);
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Level named on the command line, warning if the build strips it
+fn parse_level(name: Option<&str>, out: &mut dyn Write) -> Result<Level, CommandError> {
+    let level = name.and_then(Level::from_name);
+    let level = level.ok_or(CommandError::Failed("levels are error, warn, info, debug, trace"))?;
+    let max = crate::config::LOG_MAX_LEVEL;
+    if level > max {
+        writeln!(out, "{}", styled(color::Style::Warning, format_args!("built up to {}, more verbose lines are compiled out", max)))?;
+    }
+    Ok(level)
+}
+
+/// Most units one `peek` dumps
+const MAX_PEEK_UNITS: usize = 256;
+
+/// Bytes shown per `peek` line
+const PEEK_LINE_BYTES: usize = 16;
+
+/// Access width in bytes from an optional leading `-b`, `-h` or `-w`,
+/// with the index of the first operand after it; words by default
+fn width_option(args: &Args) -> Result<(usize, usize), CommandError> {
+    match args.get(1) {
+        Some("-b") => Ok((1, 2)),
+        Some("-h") => Ok((2, 2)),
+        Some("-w") => Ok((4, 2)),
+        Some(option) if option.starts_with('-') => Err(CommandError::Usage),
+        _ => Ok((4, 1)),
+    }
+}
+
+/// Check `addr..addr + len` for an access of `width`-byte units
+fn check_range(addr: usize, len: usize, width: usize, access: Access) -> Result<(), CommandError> {
+    checked::check(addr, len, width, access).map(drop).map_err(|error| CommandError::Failed(error.message()))
+}
+
+fn peek(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    let (width, first) = width_option(args)?;
+    if args.len() > first + 2 {
+        return Err(CommandError::Usage);
+    }
+    let addr = args.usize(first)?;
+    let count = args.get(first + 1).map_or(Ok(1), |_| args.usize(first + 1))?;
+    if count == 0 || count > MAX_PEEK_UNITS {
+        return Err(CommandError::Failed("count must be 1 to 256"));
+    }
+    check_range(addr, count * width, width, Access::Read)?;
+
+    for index in 0..count {
+        let unit = addr + index * width;
+        if index % (PEEK_LINE_BYTES / width) == 0 {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{:08x}:", unit)?;
+        }
+        let value = unsafe { crate::memory::read_unit(unit, width) };
+        write!(out, " {:0digits$x}", value, digits = width * 2)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn poke(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    let (width, first) = width_option(args)?;
+    if args.len() != first + 2 {
+        return Err(CommandError::Usage);
+    }
+    let addr = args.usize(first)?;
+    let value = args.u32(first + 1)?;
+    if width < 4 && value >> (width * 8) != 0 {
+        return Err(CommandError::Failed("value too wide for the access width"));
+    }
+    check_range(addr, width, width, Access::Write)?;
+
+    unsafe { crate::memory::write_unit(addr, width, value) };
+    // No read-back: reading a device register can have side effects
+    writeln!(out, "{:08x} <- {:0digits$x}", addr, value, digits = width * 2)?;
+    Ok(())
+}
+
+fn rx(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    if !(2..=3).contains(&args.len()) {
+        return Err(CommandError::Usage);
+    }
+    let Ok(addr) = args.usize(1) else {
+        return Err(CommandError::Failed("no file system; give a RAM address"));
+    };
+    if crate::memory::region_kind(addr, 1) == Some(RegionKind::Flash) {
+        return Err(CommandError::Failed("no flash write driver"));
+    }
+    // Only RAM past `.heap` and below the stack reserve: anything lower
+    // holds the kernel's own data
+    let Some((start, end)) = crate::arch::free_ram() else {
+        return Err(CommandError::Failed("no free RAM on this target"));
+    };
+    if addr < start || addr >= end {
+        writeln!(out, "rx: free RAM is {:#010x}..{:#010x}", start, end)?;
+        return Err(CommandError::Failed("not in free RAM"));
+    }
+    let limit = args.get(2).map_or(Ok(end - addr), |_| args.usize(2))?;
+    if limit > end - addr {
+        return Err(CommandError::Failed("runs into the stack reserve"));
+    }
+    if limit < super::xmodem::BLOCK {
+        return Err(CommandError::Failed("room for less than one 128-byte block"));
+    }
+
+    writeln!(out, "rx: up to {} bytes at {:#010x}, start the sender; Ctrl-X twice cancels", limit, addr)?;
+    crate::arch::console_flush();
+    super::xmodem::start(addr, limit);
+    Ok(())
+}
+
+fn color(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    let on = match (args.len(), args.get(1)) {
+        (1, _) => return Ok(writeln!(out, "color {}", if color::enabled() { "on" } else { "off" })?),
+        (2, Some("on")) => true,
+        (2, Some("off")) => false,
+        _ => return Err(CommandError::Usage),
+    };
+    if !color::set_enabled(on) {
+        return Err(CommandError::Failed("built without the shell_color feature"));
+    }
+    Ok(())
+}
+
+fn echo(args: &Args, out: &mut dyn Write) -> Result<(), CommandError> {
+    match (args.len(), args.get(1)) {
+        (1, _) => writeln!(out, "echo {}", if super::echo() { "on" } else { "off" })?,
+        (2, Some("on")) => super::set_echo(true),
+        (2, Some("off")) => super::set_echo(false),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn logout(args: &Args, _out: &mut dyn Write) -> Result<(), CommandError> {
+    if args.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    if !cfg!(feature = "shell_login") {
+        return Err(CommandError::Failed("built without the shell_login feature"));
+    }
+    super::login::logout();
+    Ok(())
+}
@@ -0,0 +1,502 @@
+//! Host protocol
+//! Framed binary requests sharing the console with the text shell
+//!
+//! Host tools wrap each request in 0x00 bytes around its COBS encoding,
+//! so no 0x00 appears inside a frame and typed commands never contain
+//! one; the shell task hands bytes from a 0x00 up to the next to
+//! `FrameReceiver` and the rest to the line editor. A frame decodes to a
+//! message followed by its CRC-16 (CCITT: polynomial 0x1021, initial
+//! 0xFFFF, little-endian). Frames failing the CRC are dropped and only
+//! counted, as are frames left unfinished for `FRAME_TIMEOUT_MS`.
+//!
+//! A message is `[kind, seq, body...]` with little-endian fields. The
+//! reply carries the same `seq` and `kind | 0x80`, or on failure is
+//! `[0xFF, seq, kind, ErrorCode]`. Requests and their reply bodies:
+//!
+//! - 0x01 ping: reply `PROTOCOL_VERSION` u8
+//! - 0x02 stats: reply as laid out in `stats_body`
+//! - 0x03 log, first sequence u32: reply the sequence of the first line
+//!   sent u32, then as many `(len u8, text)` lines as fit
+//! - 0x04 peek, addr u32, width u8, count u8: reply `count` units
+//! - 0x05 poke, addr u32, width u8, value u32: empty reply
+//! - 0x06 defmt, first offset u32 (`log_defmt` builds): reply the offset
+//!   of the first byte sent u32, then as many bytes of the defmt ring as
+//!   fit
+//! - 0x07 records, first offset u32 (`log_binary` builds): reply the
+//!   offset of the first record sent u32, then as many whole binary log
+//!   records as fit
+//! - 0x08 calibrate, host clock in microseconds u64: reply the tick's
+//!   drift correction in ppm i32, then 1 if this reading updated it or 0
+//!   if it only started a span or came too soon (see
+//!   `time::calibrate_against`)
+//! - 0x09 coverage, first path u8, clear u8 (`coverage` builds): reply
+//!   the number of paths u8, the first path sent u8, then the counts from
+//!   there on u32 each (see `coverage::Path` for the indices); a clear of
+//!   1 zeroes the counts sent
+//! - 0x0A metrics, first entry u8 (`metrics` builds): reply the number of
+//!   entries u8, the first entry sent u8, handles left out u32, then from
+//!   there on as many `(kind u8, value u32, name len u8, name)` entries as
+//!   fit (see `metrics::Kind` for the kinds)
+
+use heapless::Vec;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::memory::checked::{self, Access, AccessError};
+
+/// Bumped when a message layout changes
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Longest message, kind and seq included
+pub const MAX_MESSAGE: usize = 128;
+
+/// Encoded frame without its delimiters: message, CRC and COBS overhead
+const MAX_ENCODED: usize = MAX_MESSAGE + 2 + (MAX_MESSAGE + 2).div_ceil(254);
+
+/// Give up on a frame whose closing delimiter has not come by then
+const FRAME_TIMEOUT_MS: u64 = 500;
+
+/// Most bytes one peek reply carries
+const MAX_PEEK_BYTES: usize = 64;
+
+const DELIMITER: u8 = 0x00;
+const REPLY: u8 = 0x80;
+const ERROR_KIND: u8 = 0xFF;
+
+const PING: u8 = 0x01;
+const STATS: u8 = 0x02;
+const LOG: u8 = 0x03;
+const PEEK: u8 = 0x04;
+const POKE: u8 = 0x05;
+#[cfg(feature = "log_defmt")]
+const DEFMT: u8 = 0x06;
+#[cfg(feature = "log_binary")]
+const RECORDS: u8 = 0x07;
+const CALIBRATE: u8 = 0x08;
+#[cfg(feature = "coverage")]
+const COVERAGE: u8 = 0x09;
+#[cfg(feature = "metrics")]
+const METRICS: u8 = 0x0A;
+
+/// Why a request failed, sent as the last byte of an error reply
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ErrorCode {
+    /// Unknown kind
+    Unsupported = 1,
+    /// Body too short or too long, or a field out of range
+    Malformed = 2,
+    /// Outside RAM, flash and device registers, or misaligned
+    BadAddress = 3,
+    /// Poke into flash
+    ReadOnly = 4,
+}
+
+/// Host protocol counters
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HostStats {
+    /// Frames that passed the CRC and were answered
+    pub frames: u32,
+    /// Frames dropped for a bad CRC or encoding, overflow or timeout
+    pub bad_frames: u32,
+}
+
+static FRAMES: AtomicU32 = AtomicU32::new(0);
+static BAD_FRAMES: AtomicU32 = AtomicU32::new(0);
+
+type Message = Vec<u8, MAX_MESSAGE>;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Receive {
+    /// Between frames; bytes are shell text
+    Idle,
+    Frame,
+    /// Frame too long; skip to its closing delimiter
+    Overflow,
+}
+
+/// Picks host frames out of console input
+pub struct FrameReceiver {
+    buffer: Vec<u8, MAX_ENCODED>,
+    state: Receive,
+    /// Tick the current frame started at
+    started: u64,
+}
+
+impl Default for FrameReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReceiver {
+    pub const fn new() -> Self {
+        Self { buffer: Vec::new(), state: Receive::Idle, started: 0 }
+    }
+
+    /// Take `byte` if it belongs to a frame, answering each complete one
+    ///
+    /// False means the byte is shell input.
+    pub fn accept(&mut self, byte: u8) -> bool {
+        let timeout = FRAME_TIMEOUT_MS * crate::config::TICK_HZ as u64 / 1000;
+        if self.state != Receive::Idle && crate::kernel::time::now() - self.started > timeout {
+            // A stray 0x00 (Ctrl-@) must not swallow the shell for long
+            self.state = Receive::Idle;
+            BAD_FRAMES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        match self.state {
+            Receive::Idle if byte != DELIMITER => return false,
+            Receive::Idle => self.start(),
+            // Back-to-back delimiters open the frame again
+            Receive::Frame if byte == DELIMITER && self.buffer.is_empty() => self.start(),
+            Receive::Frame if byte == DELIMITER => {
+                self.state = Receive::Idle;
+                self.finish();
+            }
+            Receive::Frame => {
+                if self.buffer.push(byte).is_err() {
+                    self.state = Receive::Overflow;
+                }
+            }
+            Receive::Overflow => {
+                if byte == DELIMITER {
+                    self.state = Receive::Idle;
+                    BAD_FRAMES.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        true
+    }
+
+    fn start(&mut self) {
+        self.state = Receive::Frame;
+        self.buffer.clear();
+        self.started = crate::kernel::time::now();
+    }
+
+    fn finish(&mut self) {
+        let mut frame = [0u8; MAX_ENCODED];
+        let message = match cobs_decode(&self.buffer, &mut frame) {
+            Some(len) if len >= 4 => {
+                let (message, crc) = frame[..len].split_at(len - 2);
+                (crc16(message).to_le_bytes() == crc).then_some(message)
+            }
+            _ => None,
+        };
+        match message {
+            Some(message) => {
+                FRAMES.fetch_add(1, Ordering::Relaxed);
+                send(&handle(message));
+            }
+            None => {
+                BAD_FRAMES.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Get host protocol counters
+#[allow(dead_code)]
+pub fn stats() -> HostStats {
+    HostStats { frames: FRAMES.load(Ordering::Relaxed), bad_frames: BAD_FRAMES.load(Ordering::Relaxed) }
+}
+
+/// Build the reply to one message (at least kind and seq)
+fn handle(message: &[u8]) -> Message {
+    let (kind, seq, body) = (message[0], message[1], &message[2..]);
+    let mut reply = Message::new();
+    put(&mut reply, &[kind | REPLY, seq]);
+    let result = match kind {
+        PING if body.is_empty() => {
+            put(&mut reply, &[PROTOCOL_VERSION]);
+            Ok(())
+        }
+        STATS if body.is_empty() => {
+            stats_body(&mut reply);
+            Ok(())
+        }
+        LOG => log_body(body, &mut reply),
+        PEEK => peek_body(body, &mut reply),
+        POKE => poke(body),
+        #[cfg(feature = "log_defmt")]
+        DEFMT => defmt_body(body, &mut reply),
+        #[cfg(feature = "log_binary")]
+        RECORDS => records_body(body, &mut reply),
+        CALIBRATE => calibrate_body(body, &mut reply),
+        #[cfg(feature = "coverage")]
+        COVERAGE => coverage_body(body, &mut reply),
+        #[cfg(feature = "metrics")]
+        METRICS => metrics_body(body, &mut reply),
+        PING | STATS => Err(ErrorCode::Malformed),
+        _ => Err(ErrorCode::Unsupported),
+    };
+    if let Err(code) = result {
+        reply.clear();
+        put(&mut reply, &[ERROR_KIND, seq, kind, code as u8]);
+    }
+    reply
+}
+
+/// Append to a reply; fixed-size replies always fit in `MAX_MESSAGE`
+fn put(reply: &mut Message, bytes: &[u8]) {
+    let _ = reply.extend_from_slice(bytes);
+}
+
+fn u32_at(body: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([body[offset], body[offset + 1], body[offset + 2], body[offset + 3]])
+}
+
+/// Uptime ticks u64, tick rate u32, tasks and task capacity u16 each,
+/// queued events and event capacity u16 each, idle ticks u64, lines
+/// logged u32, console bytes overrun u32, frames and bad frames u32
+fn stats_body(reply: &mut Message) {
+    let occupancy = crate::scheduler::occupancy();
+    let host = stats();
+    put(reply, &crate::kernel::time::now().to_le_bytes());
+    put(reply, &crate::config::TICK_HZ.to_le_bytes());
+    put(reply, &(occupancy.tasks as u16).to_le_bytes());
+    put(reply, &(crate::scheduler::TASK_CAPACITY as u16).to_le_bytes());
+    put(reply, &(occupancy.events as u16).to_le_bytes());
+    put(reply, &(crate::scheduler::EVENT_CAPACITY as u16).to_le_bytes());
+    put(reply, &crate::power::stats().idle_ticks.to_le_bytes());
+    put(reply, &(crate::logger::Logger::get_stats().total as u32).to_le_bytes());
+    put(reply, &crate::drivers::console_rx::overruns().to_le_bytes());
+    put(reply, &host.frames.to_le_bytes());
+    put(reply, &host.bad_frames.to_le_bytes());
+}
+
+fn calibrate_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    let reference: [u8; 8] = body.try_into().map_err(|_| ErrorCode::Malformed)?;
+    let updated = crate::kernel::time::calibrate_against(u64::from_le_bytes(reference)).is_some();
+    put(reply, &crate::kernel::time::drift_ppm().to_le_bytes());
+    put(reply, &[updated as u8]);
+    Ok(())
+}
+
+fn log_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    if body.len() != 4 {
+        return Err(ErrorCode::Malformed);
+    }
+    // Lines older than the ring are gone; report where the reply starts
+    let total = crate::logger::Logger::get_stats().total;
+    let first_at = reply.len();
+    put(reply, &(total as u32).to_le_bytes());
+    let mut first_sent = None;
+    crate::logger::Logger::visit_from(u32_at(body, 0) as usize, |seq, line| {
+        if reply.len() + 1 + line.len() > MAX_MESSAGE {
+            return false;
+        }
+        first_sent.get_or_insert(seq);
+        put(reply, &[line.len() as u8]);
+        put(reply, line.as_bytes());
+        true
+    });
+    if let Some(seq) = first_sent {
+        reply[first_at..first_at + 4].copy_from_slice(&(seq as u32).to_le_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "log_defmt")]
+fn defmt_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    if body.len() != 4 {
+        return Err(ErrorCode::Malformed);
+    }
+    let mut bytes = [0u8; MAX_MESSAGE - 6];
+    let (start, count) = crate::defmt_log::read_from(u32_at(body, 0), &mut bytes);
+    put(reply, &start.to_le_bytes());
+    put(reply, &bytes[..count]);
+    Ok(())
+}
+
+#[cfg(feature = "coverage")]
+fn coverage_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    use crate::coverage::{Path, PATH_COUNT};
+
+    let &[first, clear] = body else {
+        return Err(ErrorCode::Malformed);
+    };
+    if first as usize > PATH_COUNT || clear > 1 {
+        return Err(ErrorCode::Malformed);
+    }
+    put(reply, &[PATH_COUNT as u8, first]);
+    for &path in &Path::ALL[first as usize..] {
+        if reply.len() + 4 > MAX_MESSAGE {
+            break;
+        }
+        let count = if clear == 1 { crate::coverage::take(path) } else { crate::coverage::count(path) };
+        put(reply, &count.to_le_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+fn metrics_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    let &[first] = body else {
+        return Err(ErrorCode::Malformed);
+    };
+    let count = crate::metrics::len();
+    if first as usize > count {
+        return Err(ErrorCode::Malformed);
+    }
+    put(reply, &[count as u8, first]);
+    put(reply, &crate::metrics::refused().to_le_bytes());
+    for sample in (first as usize..count).filter_map(crate::metrics::get) {
+        if reply.len() + 6 + sample.name.len() > MAX_MESSAGE {
+            break;
+        }
+        put(reply, &[sample.kind as u8]);
+        put(reply, &sample.value.to_le_bytes());
+        put(reply, &[sample.name.len() as u8]);
+        put(reply, sample.name.as_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "log_binary")]
+fn records_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    if body.len() != 4 {
+        return Err(ErrorCode::Malformed);
+    }
+    let first = u32_at(body, 0);
+    let first_at = reply.len();
+    put(reply, &first.to_le_bytes());
+    let mut first_sent = None;
+    crate::log_binary::visit_from(first, |offset, record| {
+        if reply.len() + record.len() > MAX_MESSAGE {
+            return false;
+        }
+        first_sent.get_or_insert(offset);
+        put(reply, record);
+        true
+    });
+    if let Some(offset) = first_sent {
+        reply[first_at..first_at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// `(addr, width)` of a peek or poke body, with the width checked
+fn access(body: &[u8]) -> Result<(usize, usize), ErrorCode> {
+    let (addr, width) = (u32_at(body, 0) as usize, body[4] as usize);
+    if !matches!(width, 1 | 2 | 4) {
+        return Err(ErrorCode::Malformed);
+    }
+    Ok((addr, width))
+}
+
+fn peek_body(body: &[u8], reply: &mut Message) -> Result<(), ErrorCode> {
+    if body.len() != 6 {
+        return Err(ErrorCode::Malformed);
+    }
+    let (addr, width) = access(body)?;
+    let count = body[5] as usize;
+    if count == 0 || count * width > MAX_PEEK_BYTES {
+        return Err(ErrorCode::Malformed);
+    }
+    checked::check(addr, count * width, width, Access::Read).map_err(access_error)?;
+    for index in 0..count {
+        let value = unsafe { crate::memory::read_unit(addr + index * width, width) };
+        put(reply, &value.to_le_bytes()[..width]);
+    }
+    Ok(())
+}
+
+fn poke(body: &[u8]) -> Result<(), ErrorCode> {
+    if body.len() != 9 {
+        return Err(ErrorCode::Malformed);
+    }
+    let (addr, width) = access(body)?;
+    let value = u32_at(body, 5);
+    if width < 4 && value >> (width * 8) != 0 {
+        return Err(ErrorCode::Malformed);
+    }
+    checked::check(addr, width, width, Access::Write).map_err(access_error)?;
+    unsafe { crate::memory::write_unit(addr, width, value) };
+    Ok(())
+}
+
+fn access_error(error: AccessError) -> ErrorCode {
+    match error {
+        AccessError::ReadOnly => ErrorCode::ReadOnly,
+        _ => ErrorCode::BadAddress,
+    }
+}
+
+/// Frame `message` with its CRC and write it to the console
+fn send(message: &[u8]) {
+    let mut payload: Vec<u8, { MAX_MESSAGE + 2 }> = Vec::new();
+    let _ = payload.extend_from_slice(message);
+    let _ = payload.extend_from_slice(&crc16(message).to_le_bytes());
+
+    let mut frame = [0u8; MAX_ENCODED + 2];
+    let len = cobs_encode(&payload, &mut frame[1..]);
+    frame[0] = DELIMITER;
+    frame[len + 1] = DELIMITER;
+    crate::arch::console_write(&frame[..len + 2]);
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0xFFFF, data)
+}
+
+/// Continue a CRC-16 with polynomial 0x1021, MSB first, from `crc`
+pub(super) fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// COBS-encode `data` into `out`, returning the encoded length
+///
+/// `out` needs `data.len() + data.len() / 254 + 1` bytes.
+fn cobs_encode(data: &[u8], out: &mut [u8]) -> usize {
+    let (mut code_at, mut len, mut code) = (0, 1, 1u8);
+    for &byte in data {
+        if byte != 0 {
+            out[len] = byte;
+            len += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            out[code_at] = code;
+            code_at = len;
+            len += 1;
+            code = 1;
+        }
+    }
+    out[code_at] = code;
+    len
+}
+
+/// Decode a COBS block into `out`; `None` if malformed or too long
+fn cobs_decode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let (mut read, mut len) = (0, 0);
+    while read < data.len() {
+        let code = data[read] as usize;
+        let end = read + code;
+        if code == 0 || end > data.len() {
+            return None;
+        }
+        for &byte in &data[read + 1..end] {
+            *out.get_mut(len)? = byte;
+            len += 1;
+        }
+        read = end;
+        // A full block carries no implied zero, nor does the last one
+        if code != 0xFF && read < data.len() {
+            *out.get_mut(len)? = 0;
+            len += 1;
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests;
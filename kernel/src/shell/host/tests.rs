@@ -0,0 +1,97 @@
+//! Host tests for the host protocol framing
+//! COBS round trips around the 254-byte block limit, malformed blocks,
+//! and the CRC-16 check values
+
+use super::{cobs_decode, cobs_encode, crc16, crc16_update};
+
+/// Encoded size bound documented on `cobs_encode`
+fn encoded_max(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; encoded_max(data.len())];
+    let len = cobs_encode(data, &mut out);
+    out.truncate(len);
+    out
+}
+
+fn decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![0u8; data.len()];
+    let len = cobs_decode(data, &mut out)?;
+    out.truncate(len);
+    Some(out)
+}
+
+#[test]
+fn cobs_known_encodings() {
+    let cases: [(&[u8], &[u8]); 6] = [
+        (&[], &[0x01]),
+        (&[0x00], &[0x01, 0x01]),
+        (&[0x00, 0x00], &[0x01, 0x01, 0x01]),
+        (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33]),
+        (&[0x11, 0x22, 0x33, 0x44], &[0x05, 0x11, 0x22, 0x33, 0x44]),
+        (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01]),
+    ];
+    for (data, encoded) in cases {
+        assert_eq!(encode(data), encoded, "encoding {:02x?}", data);
+        assert_eq!(decode(encoded).as_deref(), Some(data), "decoding {:02x?}", encoded);
+    }
+}
+
+#[test]
+fn cobs_full_block() {
+    // 254 non-zero bytes fill a block, which carries no implied zero
+    let data: Vec<u8> = (1..=254).collect();
+    let encoded = encode(&data);
+    assert_eq!(encoded[0], 0xFF);
+    assert_eq!(&encoded[1..255], &data[..]);
+    assert_eq!(&encoded[255..], &[0x01]);
+    assert_eq!(decode(&encoded), Some(data));
+}
+
+#[test]
+fn cobs_round_trips_around_block_boundary() {
+    for len in (0..=3).chain(250..=260).chain(505..=512) {
+        for zero_every in [0, 1, 7, 254, 255] {
+            let data: Vec<u8> = (0..len)
+                .map(|i| if zero_every != 0 && i % zero_every == zero_every - 1 { 0 } else { (i % 255 + 1) as u8 })
+                .collect();
+            let encoded = encode(&data);
+            assert!(encoded.len() <= encoded_max(len), "len {} zero_every {}", len, zero_every);
+            assert!(!encoded.contains(&0), "len {} zero_every {}", len, zero_every);
+            assert_eq!(decode(&encoded), Some(data), "len {} zero_every {}", len, zero_every);
+        }
+    }
+}
+
+#[test]
+fn cobs_rejects_malformed_blocks() {
+    // A zero code byte
+    assert_eq!(decode(&[0x00]), None);
+    assert_eq!(decode(&[0x02, 0x11, 0x00, 0x22]), None);
+    // A code running past the end
+    assert_eq!(decode(&[0x05, 0x11, 0x22]), None);
+    assert_eq!(decode(&[0x03, 0x11, 0x22, 0x02]), None);
+    // Output too small
+    let mut out = [0u8; 2];
+    assert_eq!(cobs_decode(&[0x04, 0x11, 0x22, 0x33], &mut out), None);
+    assert_eq!(cobs_decode(&[0x03, 0x11, 0x22, 0x01], &mut out), None);
+}
+
+#[test]
+fn crc16_check_values() {
+    // CRC-16/CCITT-FALSE for the frames, and with initial 0 as XMODEM uses
+    assert_eq!(crc16(b"123456789"), 0x29B1);
+    assert_eq!(crc16_update(0, b"123456789"), 0x31C3);
+    assert_eq!(crc16(b""), 0xFFFF);
+}
+
+#[test]
+fn crc16_continues_across_updates() {
+    let data = b"frame body with a \x00 byte";
+    for split in 0..=data.len() {
+        let (head, tail) = data.split_at(split);
+        assert_eq!(crc16_update(crc16(head), tail), crc16(data), "split at {}", split);
+    }
+}
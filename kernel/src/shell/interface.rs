@@ -0,0 +1,396 @@
+//! Console line editor
+//! Byte-at-a-time line editing with history recall for the shell
+//!
+//! `UartInterface` is fed one received byte at a time and echoes the
+//! edits back, so it works the same from a polling loop or an RX
+//! interrupt's queue. Besides plain typing it understands Backspace,
+//! Ctrl-A/E/U/C and the ANSI cursor keys; up and down recall earlier
+//! lines from a small history ring, and Tab completes command names.
+//!
+//! Echo can be switched off for hosts that drive the shell. In raw mode
+//! every byte goes unedited to `RAW_INPUT` for the task that asked for
+//! it (file transfer, binary protocols) until that task leaves raw mode
+//! or the user types Ctrl-] three times in a row.
+
+use core::fmt::Write;
+
+use heapless::{Deque, String};
+
+use super::color::{styled, Style};
+use crate::arch::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::kernel::Pipe;
+
+/// Longest command line, in bytes
+pub const LINE_MAX: usize = 80;
+
+/// Lines kept for recall with the arrow keys
+pub const HISTORY_DEPTH: usize = 8;
+
+/// Bytes buffered for the raw-mode consumer
+pub const RAW_BUFFER: usize = 64;
+
+/// Consecutive `RAW_ESCAPE` bytes that return from raw mode to the shell
+pub const RAW_ESCAPE_COUNT: u8 = 3;
+
+/// Ctrl-], as in telnet
+pub const RAW_ESCAPE: u8 = 0x1D;
+
+/// Shown before each command line
+pub const PROMPT: &str = "karatos> ";
+
+/// Console input while in raw mode; the consumer reads it like any pipe
+pub static RAW_INPUT: Pipe<RAW_BUFFER> = Pipe::new();
+
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+static RAW_DROPPED: AtomicU32 = AtomicU32::new(0);
+static ECHO: AtomicBool = AtomicBool::new(true);
+
+/// Send console input to `RAW_INPUT` instead of the shell
+///
+/// False if another consumer already has raw mode.
+#[allow(dead_code)]
+pub fn enter_raw_mode() -> bool {
+    !RAW_MODE.swap(true, Ordering::AcqRel)
+}
+
+/// Give console input back to the shell
+#[allow(dead_code)]
+pub fn leave_raw_mode() {
+    RAW_MODE.store(false, Ordering::Release);
+}
+
+#[allow(dead_code)]
+pub fn raw_mode() -> bool {
+    RAW_MODE.load(Ordering::Acquire)
+}
+
+/// Raw bytes lost because the consumer fell behind
+#[allow(dead_code)]
+pub fn raw_dropped() -> u32 {
+    RAW_DROPPED.load(Ordering::Relaxed)
+}
+
+/// Switch echoing of typed input
+pub fn set_echo(on: bool) {
+    ECHO.store(on, Ordering::Relaxed);
+}
+
+pub fn echo() -> bool {
+    ECHO.load(Ordering::Relaxed)
+}
+
+/// Writer that drops everything, standing in for the console with echo off
+struct Discard;
+
+impl Write for Discard {
+    fn write_str(&mut self, _: &str) -> core::fmt::Result {
+        Ok(())
+    }
+}
+
+type Line = String<LINE_MAX>;
+
+const CTRL_A: u8 = 0x01;
+const CTRL_C: u8 = 0x03;
+const CTRL_E: u8 = 0x05;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const CTRL_U: u8 = 0x15;
+const ESC: u8 = 0x1B;
+const DELETE: u8 = 0x7F;
+
+/// Progress through an ANSI escape sequence
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// ESC received
+    Started,
+    /// `ESC [` received, with the numeric parameter so far
+    Csi(u8),
+}
+
+/// Line editor state for one console
+pub struct UartInterface {
+    line: Line,
+    /// Cursor position in `line`; lines are ASCII so bytes are columns
+    cursor: usize,
+    escape: Escape,
+    /// Oldest entry first
+    history: Deque<Line, HISTORY_DEPTH>,
+    /// History entry shown, counted back from the newest; `None` while
+    /// editing a fresh line
+    recalled: Option<usize>,
+    /// Fresh line put aside while browsing history
+    draft: Line,
+    /// Last byte was CR, so a following LF is the same line ending
+    after_cr: bool,
+    /// `RAW_ESCAPE` bytes held back in raw mode
+    raw_escapes: u8,
+}
+
+impl Default for UartInterface {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code)]
+impl UartInterface {
+    pub const fn new() -> Self {
+        Self {
+            line: Line::new(),
+            cursor: 0,
+            escape: Escape::None,
+            history: Deque::new(),
+            recalled: None,
+            draft: Line::new(),
+            after_cr: false,
+            raw_escapes: 0,
+        }
+    }
+
+    /// Print the prompt for a new line
+    pub fn prompt(&self, out: &mut dyn Write) {
+        let _ = write!(out, "{}", styled(Style::Prompt, PROMPT));
+    }
+
+    /// Handle one received byte, echoing the edit to `out`
+    ///
+    /// Returns the line when Enter completes it; the caller runs it and
+    /// then calls `prompt` again.
+    pub fn process_byte(&mut self, byte: u8, out: &mut dyn Write) -> Option<Line> {
+        if raw_mode() {
+            self.raw_byte(byte, out);
+            return None;
+        }
+        let out: &mut dyn Write = if echo() { out } else { &mut Discard };
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match self.escape {
+            Escape::Started => {
+                self.escape = if byte == b'[' || byte == b'O' { Escape::Csi(0) } else { Escape::None };
+                return None;
+            }
+            Escape::Csi(param) => {
+                if byte.is_ascii_digit() {
+                    self.escape = Escape::Csi(param.saturating_mul(10).saturating_add(byte - b'0'));
+                } else {
+                    self.escape = Escape::None;
+                    self.escape_key(byte, param, out);
+                }
+                return None;
+            }
+            Escape::None => {}
+        }
+
+        match byte {
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => return Some(self.finish_line(out)),
+            ESC => self.escape = Escape::Started,
+            BACKSPACE | DELETE if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.remove_at_cursor(out);
+            }
+            TAB => self.complete(out),
+            CTRL_A => self.move_to(0, out),
+            CTRL_E => self.move_to(self.line.len(), out),
+            CTRL_U => {
+                self.replace_line("");
+                self.redraw(out);
+            }
+            CTRL_C => {
+                let _ = out.write_str("^C\r\n");
+                self.line.clear();
+                self.cursor = 0;
+                self.recalled = None;
+                self.prompt(out);
+            }
+            0x20..=0x7E => self.insert(byte as char, out),
+            _ => {}
+        }
+        None
+    }
+
+    /// Pass a raw-mode byte on, watching for the escape sequence
+    fn raw_byte(&mut self, byte: u8, out: &mut dyn Write) {
+        if byte == RAW_ESCAPE {
+            self.raw_escapes += 1;
+            if self.raw_escapes == RAW_ESCAPE_COUNT {
+                self.raw_escapes = 0;
+                leave_raw_mode();
+                let _ = out.write_str("\r\n");
+                self.prompt(out);
+            }
+            return;
+        }
+        // Escape bytes that turned out not to start the sequence are data
+        let held = core::mem::take(&mut self.raw_escapes) as usize;
+        let mut pending = [RAW_ESCAPE; RAW_ESCAPE_COUNT as usize];
+        pending[held] = byte;
+        let dropped = held + 1 - RAW_INPUT.try_write(&pending[..=held]);
+        if dropped > 0 {
+            RAW_DROPPED.fetch_add(dropped as u32, Ordering::Relaxed);
+        }
+    }
+
+    /// Act on the final byte of `ESC [ <param> <key>`
+    fn escape_key(&mut self, key: u8, param: u8, out: &mut dyn Write) {
+        match (key, param) {
+            (b'A', _) => self.recall_older(out),
+            (b'B', _) => self.recall_newer(out),
+            (b'C', _) => self.move_to((self.cursor + 1).min(self.line.len()), out),
+            (b'D', _) => self.move_to(self.cursor.saturating_sub(1), out),
+            (b'H', _) | (b'~', 1) => self.move_to(0, out),
+            (b'F', _) | (b'~', 4) => self.move_to(self.line.len(), out),
+            (b'~', 3) if self.cursor < self.line.len() => self.remove_at_cursor(out),
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, c: char, out: &mut dyn Write) {
+        if self.line.len() == LINE_MAX {
+            return;
+        }
+        if self.cursor == self.line.len() {
+            let _ = self.line.push(c);
+            self.cursor += 1;
+            let _ = out.write_char(c);
+            return;
+        }
+        let mut edited = Line::new();
+        let _ = edited.push_str(&self.line[..self.cursor]);
+        let _ = edited.push(c);
+        let _ = edited.push_str(&self.line[self.cursor..]);
+        self.line = edited;
+        self.cursor += 1;
+        self.redraw(out);
+    }
+
+    fn remove_at_cursor(&mut self, out: &mut dyn Write) {
+        let mut edited = Line::new();
+        let _ = edited.push_str(&self.line[..self.cursor]);
+        let _ = edited.push_str(&self.line[self.cursor + 1..]);
+        self.line = edited;
+        self.redraw(out);
+    }
+
+    fn move_to(&mut self, cursor: usize, out: &mut dyn Write) {
+        if cursor != self.cursor {
+            self.cursor = cursor;
+            self.redraw(out);
+        }
+    }
+
+    /// Replace the line being edited, cursor at its end
+    fn replace_line(&mut self, text: &str) {
+        self.line.clear();
+        let _ = self.line.push_str(text);
+        self.cursor = self.line.len();
+    }
+
+    /// Rewrite the prompt and line, then put the terminal cursor in place
+    fn redraw(&self, out: &mut dyn Write) {
+        let _ = write!(out, "\r{}{}\x1b[K", styled(Style::Prompt, PROMPT), self.line);
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            let _ = write!(out, "\x1b[{}D", back);
+        }
+    }
+
+    /// Complete the command name under the cursor
+    ///
+    /// A unique match is finished and followed by a space; otherwise the
+    /// longest prefix the candidates share is filled in, and if that adds
+    /// nothing the candidates are listed.
+    fn complete(&mut self, out: &mut dyn Write) {
+        let before = &self.line[..self.cursor];
+        if before.contains(' ') || self.line[self.cursor..].starts_with(|c: char| c != ' ') {
+            return;
+        }
+        let prefix_len = before.len();
+        let mut candidates = super::COMMANDS.iter().map(|command| command.name).filter(|name| name.starts_with(before));
+        let Some(first) = candidates.next() else {
+            return;
+        };
+        let mut common = first.len();
+        let mut count = 1;
+        for name in candidates {
+            common = first.bytes().zip(name.bytes()).take_while(|(a, b)| a == b).count().min(common);
+            count += 1;
+        }
+
+        if count == 1 {
+            for c in first[prefix_len..].chars().chain(core::iter::once(' ')) {
+                self.insert(c, out);
+            }
+        } else if common > prefix_len {
+            for c in first[prefix_len..common].chars() {
+                self.insert(c, out);
+            }
+        } else {
+            let _ = out.write_str("\r\n");
+            for command in super::COMMANDS.iter().filter(|command| command.name.starts_with(&self.line[..prefix_len])) {
+                let _ = write!(out, "{}  ", command.name);
+            }
+            let _ = out.write_str("\r\n");
+            self.redraw(out);
+        }
+    }
+
+    fn recall_older(&mut self, out: &mut dyn Write) {
+        let next = self.recalled.map_or(0, |index| index + 1);
+        if next >= self.history.len() {
+            return;
+        }
+        if self.recalled.is_none() {
+            self.draft = self.line.clone();
+        }
+        self.recalled = Some(next);
+        if let Some(entry) = self.history.iter().rev().nth(next).cloned() {
+            self.replace_line(&entry);
+        }
+        self.redraw(out);
+    }
+
+    fn recall_newer(&mut self, out: &mut dyn Write) {
+        match self.recalled {
+            None => return,
+            Some(0) => {
+                self.recalled = None;
+                let draft = core::mem::take(&mut self.draft);
+                self.replace_line(&draft);
+            }
+            Some(index) => {
+                self.recalled = Some(index - 1);
+                if let Some(entry) = self.history.iter().rev().nth(index - 1).cloned() {
+                    self.replace_line(&entry);
+                }
+            }
+        }
+        self.redraw(out);
+    }
+
+    /// End the line on Enter: remember it and hand it out
+    fn finish_line(&mut self, out: &mut dyn Write) -> Line {
+        let _ = out.write_str("\r\n");
+        let line = core::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.recalled = None;
+        self.draft.clear();
+
+        let text = line.trim();
+        if !text.is_empty() && self.history.back().map(|last| last.as_str()) != Some(text) {
+            if self.history.is_full() {
+                self.history.pop_front();
+            }
+            let mut entry = Line::new();
+            let _ = entry.push_str(text);
+            let _ = self.history.push_back(entry);
+        }
+        line
+    }
+
+    /// Recorded lines, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(|line| line.as_str())
+    }
+}
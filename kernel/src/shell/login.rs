@@ -0,0 +1,203 @@
+//! Shell login
+//! Credentials asked for before the shell takes commands (`shell_login`)
+//!
+//! The user name and the SHA-256 of `user:password` are fixed at build
+//! time from `KARATOS_SHELL_USER` (default `admin`) and
+//! `KARATOS_SHELL_PASSWORD`, so the image holds no plaintext password.
+//! Until a login succeeds the shell task hands input here instead of to
+//! the line editor and ignores host protocol frames. Every
+//! `MAX_ATTEMPTS` failures in a row lock the console, for
+//! `LOCKOUT_SECONDS` at first and twice as long each time after, up to
+//! `MAX_LOCKOUT_SECONDS`; input during a lockout is thrown away.
+//! `logout` locks the shell again.
+
+// Only the shell task of a `shell` build reads input through here
+#![cfg_attr(not(feature = "shell"), allow(dead_code))]
+
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use heapless::String;
+
+use super::color::{styled, Style};
+use super::sha256::Sha256;
+use crate::kernel::time;
+
+mod credentials {
+    include!(concat!(env!("OUT_DIR"), "/shell_login.rs"));
+}
+
+/// Failed logins in a row before the console is locked
+const MAX_ATTEMPTS: u32 = 3;
+
+/// First lockout; each further one doubles it
+const LOCKOUT_SECONDS: u32 = 30;
+
+const MAX_LOCKOUT_SECONDS: u32 = 8 * 60;
+
+/// Longest user name or password accepted
+const FIELD_MAX: usize = 32;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Field {
+    User,
+    Password,
+}
+
+struct LoginState {
+    logged_in: bool,
+    field: Field,
+    user: String<FIELD_MAX>,
+    input: String<FIELD_MAX>,
+    /// Input went past `FIELD_MAX`; the attempt fails whatever follows
+    overflow: bool,
+    after_cr: bool,
+    failures: u32,
+    /// Tick the lockout ends at, while one is running
+    locked_until: Option<u64>,
+}
+
+struct Login(UnsafeCell<LoginState>);
+unsafe impl Sync for Login {} // Only touched by the shell task
+
+static LOGIN: Login = Login(UnsafeCell::new(LoginState {
+    logged_in: false,
+    field: Field::User,
+    user: String::new(),
+    input: String::new(),
+    overflow: false,
+    after_cr: false,
+    failures: 0,
+    locked_until: None,
+}));
+
+fn state() -> &'static mut LoginState {
+    unsafe { &mut *LOGIN.0.get() }
+}
+
+/// Whether input must go through `accept` rather than the shell
+pub fn required() -> bool {
+    cfg!(feature = "shell_login") && !state().logged_in
+}
+
+/// End the session; the next input is a new login
+pub fn logout() {
+    let login = state();
+    login.logged_in = false;
+    login.field = Field::User;
+    login.user.clear();
+    login.input.clear();
+    login.overflow = false;
+}
+
+/// Ask for the field being entered; nothing while locked out
+pub fn prompt(out: &mut dyn Write) {
+    state().prompt(out);
+}
+
+/// Time until a lockout ends, while one is running
+pub fn time_to_unlock() -> Option<time::Duration> {
+    let until = state().locked_until?;
+    Some(time::Duration::from_ticks(time::ticks_between(time::now(), until).max(1)))
+}
+
+/// End an expired lockout and ask again
+pub fn poll(out: &mut dyn Write) {
+    let login = state();
+    if login.locked_until.is_some_and(|until| time::deadline_reached(time::now(), until)) {
+        login.locked_until = None;
+        login.prompt(out);
+    }
+}
+
+/// Take one input byte; true once the login succeeded
+pub fn accept(byte: u8, out: &mut dyn Write) -> bool {
+    let login = state();
+    if login.locked_until.is_some() {
+        return false;
+    }
+    let after_cr = core::mem::replace(&mut login.after_cr, byte == b'\r');
+    match byte {
+        b'\n' if after_cr => {}
+        b'\r' | b'\n' => {
+            let _ = write!(out, "\r\n");
+            return login.enter(out);
+        }
+        BACKSPACE | DELETE => {
+            // Passwords are not echoed, so neither is erasing them
+            let erased = login.input.pop().is_some();
+            if erased && login.field == Field::User {
+                let _ = write!(out, "\x08 \x08");
+            }
+        }
+        0x20..=0x7E => {
+            if login.input.push(byte as char).is_err() {
+                login.overflow = true;
+            } else if login.field == Field::User {
+                let _ = out.write_char(byte as char);
+            }
+        }
+        _ => {}
+    }
+    false
+}
+
+impl LoginState {
+    /// Finish the field being typed
+    fn enter(&mut self, out: &mut dyn Write) -> bool {
+        if self.field == Field::User {
+            self.user = core::mem::take(&mut self.input);
+            self.field = Field::Password;
+            self.prompt(out);
+            return false;
+        }
+
+        let accepted = !self.overflow && self.check();
+        self.field = Field::User;
+        self.user.clear();
+        self.input.clear();
+        self.overflow = false;
+        if accepted {
+            self.logged_in = true;
+            self.failures = 0;
+            return true;
+        }
+
+        self.failures += 1;
+        crate::log_warn!("[shell] failed login {}", self.failures);
+        let _ = writeln!(out, "{}", styled(Style::Error, "login incorrect"));
+        if self.failures.is_multiple_of(MAX_ATTEMPTS) {
+            let doublings = (self.failures / MAX_ATTEMPTS - 1).min(16);
+            let seconds = (LOCKOUT_SECONDS << doublings).min(MAX_LOCKOUT_SECONDS);
+            let _ = writeln!(out, "{}", styled(Style::Warning, format_args!("locked for {} s", seconds)));
+            self.locked_until = Some(time::now() + seconds as u64 * crate::config::TICK_HZ as u64);
+        } else {
+            self.prompt(out);
+        }
+        false
+    }
+
+    fn prompt(&self, out: &mut dyn Write) {
+        if self.locked_until.is_some() {
+            return;
+        }
+        let _ = match self.field {
+            Field::User => write!(out, "login: "),
+            Field::Password => write!(out, "password: "),
+        };
+    }
+
+    /// Compare against the build-time hash without an early exit
+    fn check(&self) -> bool {
+        let mut hash = Sha256::new();
+        hash.update(self.user.as_bytes());
+        hash.update(b":");
+        hash.update(self.input.as_bytes());
+        let digest = hash.finish();
+        let difference = digest.iter().zip(credentials::PASSWORD_SHA256).fold(0, |acc, (a, b)| acc | (a ^ b));
+        difference == 0 && !credentials::USER.is_empty()
+    }
+}
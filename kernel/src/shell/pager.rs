@@ -0,0 +1,55 @@
+//! Pager
+//! Page-at-a-time output for commands that print long listings
+//!
+//! Tasks run to completion, so a command cannot stop halfway and wait
+//! for a key. Instead `Pager` wraps the console for a command marked
+//! `paged`: it passes on one page of lines after skipping those already
+//! shown, then fails the next write, which ends the command. The shell
+//! task prints `MORE`, and on a keypress runs the same line again one
+//! page further on; `q` stops. Output that changes between runs (new log
+//! lines, task statistics) is shown as it is when each page is drawn.
+
+use core::fmt::{self, Write};
+
+/// Lines per page
+pub const PAGE_LINES: usize = 20;
+
+/// Shown after a full page while more output is waiting
+pub const MORE: &str = "--more--";
+
+/// Console writer that shows the lines `skip..skip + PAGE_LINES`
+pub struct Pager<'a> {
+    out: &'a mut dyn Write,
+    skip: usize,
+    line: usize,
+    stopped: bool,
+}
+
+impl<'a> Pager<'a> {
+    pub fn new(out: &'a mut dyn Write, skip: usize) -> Self {
+        Self { out, skip, line: 0, stopped: false }
+    }
+
+    /// Whether output went past the page, so there is more to show
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+impl Write for Pager<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for piece in s.split_inclusive('\n') {
+            if self.line >= self.skip + PAGE_LINES {
+                self.stopped = true;
+                return Err(fmt::Error);
+            }
+            if self.line >= self.skip {
+                self.out.write_str(piece)?;
+            }
+            if piece.ends_with('\n') {
+                self.line += 1;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+//! Boot script
+//! Shell commands run once before the interactive prompt
+//!
+//! The script is a text file named by `KARATOS_BOOT_SCRIPT` at build time
+//! and embedded in flash with the kernel: one command per line, blank
+//! lines and lines starting with `#` skipped. Each command is echoed
+//! after the prompt as if it had been typed. The script stops at the
+//! first command that fails, so later lines never run on a board that is
+//! only half configured.
+
+use core::fmt::Write;
+
+use super::color::{styled, Style};
+use super::execute;
+
+/// Script embedded at build time; empty without `KARATOS_BOOT_SCRIPT`
+pub const BOOT_SCRIPT: &str = include_str!(concat!(env!("OUT_DIR"), "/boot_script.txt"));
+
+/// Run `script`, echoing each command to `out`
+///
+/// Returns how many commands ran, or the line number of the one that
+/// failed.
+pub fn run_script(script: &str, out: &mut dyn Write) -> Result<usize, usize> {
+    let mut ran = 0;
+    for (index, line) in script.lines().enumerate() {
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        let _ = write!(out, "{}{}\r\n", styled(Style::Prompt, super::PROMPT), command);
+        if !execute(command, out) {
+            let _ = writeln!(out, "{}", styled(Style::Warning, format_args!("boot script stopped at line {}", index + 1)));
+            return Err(index + 1);
+        }
+        ran += 1;
+    }
+    Ok(ran)
+}
@@ -0,0 +1,40 @@
+//! Host tests for SHA-256
+//! FIPS 180-4 example digests, whole and fed in pieces
+
+use super::Sha256;
+
+/// The FIPS 180-4 examples: empty, one block, and two blocks whose
+/// padding starts a block of its own
+const KNOWN: [(&str, &str); 3] = [
+    ("", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    ("abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    (
+        "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+    ),
+];
+
+fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn known_answers() {
+    for (input, expected) in KNOWN {
+        let mut hash = Sha256::new();
+        hash.update(input.as_bytes());
+        assert_eq!(hex(hash.finish()), expected, "input {:?}", input);
+    }
+}
+
+#[test]
+fn split_updates_match_one_update() {
+    for (input, expected) in KNOWN {
+        for split in 0..=input.len() {
+            let mut hash = Sha256::new();
+            hash.update(&input.as_bytes()[..split]);
+            hash.update(&input.as_bytes()[split..]);
+            assert_eq!(hex(hash.finish()), expected, "input {:?} split at {}", input, split);
+        }
+    }
+}
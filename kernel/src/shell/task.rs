@@ -0,0 +1,169 @@
+//! Shell task
+//! Console input to commands, run as an ordinary scheduler task
+//!
+//! `init` spawns the task when the kernel is built with `shell`; its first
+//! run executes the boot script before showing the prompt. Every run
+//! takes what `console_rx` has received, feeds it through the line editor
+//! and executes finished lines; host protocol frames are answered on
+//! the way, and an XMODEM transfer started by `rx` takes all input until
+//! it ends. With `shell_login` nothing reaches the editor before a login.
+//! A paged command that filled a screen leaves `--more--` up; the next
+//! key runs its line again for the following page, or `q` ends it. With
+//! a receive interrupt the task parks
+//! on the RX pipe between keystrokes; on polled consoles it stays ready
+//! and checks the UART each time the scheduler gets round to it.
+
+// Only a `shell` build spawns the task
+#![cfg_attr(not(feature = "shell"), allow(dead_code))]
+
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use heapless::String;
+
+use super::color::{styled, Style};
+use super::{
+    execute, find, login, raw_mode, run_script, xmodem, Console, FrameReceiver, Pager, UartInterface, BOOT_SCRIPT,
+    LINE_MAX, MORE, PAGE_LINES,
+};
+use crate::drivers::console_rx::{self, RX};
+use crate::kernel::{time, InitResult};
+use crate::scheduler::{self, Task, TaskPriority};
+
+/// Task ID of the shell task
+pub const TASK_ID: usize = 16;
+
+/// Below the real-time tasks, level with applications
+const PRIORITY: TaskPriority = TaskPriority::Normal;
+
+/// Bytes taken from the RX pipe per run, so a paste cannot hog the CPU
+const CHUNK: usize = 16;
+
+struct ShellState {
+    editor: UartInterface,
+    host: FrameReceiver,
+    /// Paged command waiting at `--more--`
+    more: Option<More>,
+    started: bool,
+}
+
+/// Where a paged command stopped
+struct More {
+    line: String<LINE_MAX>,
+    /// Lines already shown
+    shown: usize,
+}
+
+struct Shell(UnsafeCell<ShellState>);
+unsafe impl Sync for Shell {} // Only touched by the shell task
+
+static SHELL: Shell = Shell(UnsafeCell::new(ShellState {
+    editor: UartInterface::new(),
+    host: FrameReceiver::new(),
+    more: None,
+    started: false,
+}));
+
+/// Spawn the shell task if the `shell` feature is on
+pub fn init() -> InitResult {
+    if !crate::config::SHELL_ENABLED {
+        return Ok(());
+    }
+    let task = Task::with_priority(TASK_ID, PRIORITY).named("shell").entry(|_| run());
+    scheduler::add_priority_task(task).map(|_| ()).map_err(|_| "no task slot")
+}
+
+/// One run of the shell task
+pub fn run() {
+    let shell = unsafe { &mut *SHELL.0.get() };
+    let out = &mut Console;
+    if !shell.started {
+        // Boot output has scrolled past by now; greet below it
+        shell.started = true;
+        crate::arch::console_write(b"\r\nkaratOS shell, type 'help'\r\n");
+        let _ = run_script(BOOT_SCRIPT, out);
+        shell.prompt(out);
+    }
+
+    let mut bytes = [0u8; CHUNK];
+    let count = if console_rx::interrupt_driven() {
+        // Parks the task until the ISR delivers more input, or a running
+        // transfer's next timeout or the end of a login lockout
+        match xmodem::time_to_deadline().or_else(login::time_to_unlock) {
+            Some(timeout) => time::with_timeout(timeout, || RX.read(&mut bytes)).unwrap_or(0),
+            None => RX.read(&mut bytes).unwrap_or(0),
+        }
+    } else {
+        console_rx::poll();
+        RX.try_read(&mut bytes)
+    };
+
+    for &byte in &bytes[..count] {
+        if xmodem::active() {
+            if xmodem::accept(byte, out) {
+                shell.prompt(out);
+            }
+            continue;
+        }
+        if login::required() {
+            if login::accept(byte, out) {
+                shell.editor.prompt(out);
+            }
+            continue;
+        }
+        // Raw-mode consumers get every byte, 0x00 included
+        if !raw_mode() && shell.host.accept(byte) {
+            continue;
+        }
+        if let Some(more) = shell.more.take() {
+            // Any key but 'q' shows the next page
+            let _ = write!(out, "\r{:1$}\r", "", MORE.len());
+            if byte == b'q' || byte == b'Q' {
+                shell.prompt(out);
+            } else {
+                shell.run_line(&more.line, more.shown, out);
+            }
+            continue;
+        }
+        if let Some(line) = shell.editor.process_byte(byte, out) {
+            shell.run_line(&line, 0, out);
+        }
+    }
+    if xmodem::poll(out) {
+        shell.prompt(out);
+    }
+    login::poll(out);
+}
+
+impl ShellState {
+    /// Execute a command line, showing a paged command's output from line `shown` on
+    fn run_line(&mut self, line: &str, shown: usize, out: &mut Console) {
+        let paged = line.split_whitespace().next().and_then(find).is_some_and(|command| command.paged);
+        if paged {
+            let mut pager = Pager::new(out, shown);
+            execute(line, &mut pager);
+            if pager.stopped() {
+                let _ = write!(out, "{}", styled(Style::Prompt, MORE));
+                // The line came from the editor, so it always fits
+                let line = String::try_from(line).unwrap_or_default();
+                self.more = Some(More { line, shown: shown + PAGE_LINES });
+                return;
+            }
+        } else {
+            execute(line, out);
+        }
+        // `rx` keeps the console until its transfer ends
+        if !xmodem::active() {
+            self.prompt(out);
+        }
+    }
+
+    /// Command prompt, or the login prompt after `logout`
+    fn prompt(&self, out: &mut Console) {
+        if login::required() {
+            login::prompt(out);
+        } else {
+            self.editor.prompt(out);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! XMODEM receive
+//! Loading data into RAM over the console with XMODEM-CRC
+//!
+//! `rx` arms the receiver and returns; from then on the shell task hands
+//! it every console byte instead of the line editor, and calls `poll` so
+//! it can send its 'C' start requests and NAK silent senders, until the
+//! transfer ends. Blocks are 128 bytes followed by a CRC-16 (polynomial
+//! 0x1021, initial 0, big-endian); a block is copied to its place only
+//! once the CRC checks out. The sender pads the last block with 0x1A,
+//! which lands in memory like the rest. Two CAN bytes cancel, so Ctrl-X
+//! twice on the terminal gets the shell back.
+//!
+//! There is no file system and no flash write driver, so the target is
+//! always RAM.
+
+// Only the shell task of a `shell` build feeds the receiver
+#![cfg_attr(not(feature = "shell"), allow(dead_code))]
+
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+use super::color::{styled, Style};
+use super::host::crc16_update;
+use crate::kernel::time;
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Sent instead of NAK to ask for CRC rather than checksum blocks
+const CRC_REQUEST: u8 = b'C';
+
+/// Data bytes per block
+pub const BLOCK: usize = 128;
+
+/// SOH, block number, its complement, data and CRC
+const PACKET: usize = 3 + BLOCK + 2;
+
+/// Interval between 'C's while waiting for the sender to start
+const START_INTERVAL_MS: u64 = 3000;
+
+/// 'C's sent before giving up, about half a minute
+const START_TRIES: u8 = 10;
+
+/// Silence inside a block before it is NAKed
+const BYTE_TIMEOUT_MS: u64 = 1000;
+
+/// Silence between blocks before the last ACK is repeated as a NAK
+const BLOCK_TIMEOUT_MS: u64 = 10_000;
+
+/// Bad or missing blocks in a row before the transfer is cancelled
+const MAX_ERRORS: u8 = 10;
+
+struct Receiver {
+    dest: usize,
+    limit: usize,
+    received: usize,
+    /// Number of the next block, modulo 256
+    block: u8,
+    packet: [u8; PACKET],
+    fill: usize,
+    started: bool,
+    /// 'C's sent before the first block, then errors in a row
+    tries: u8,
+    cancels: u8,
+    deadline: u64,
+}
+
+/// How a transfer ended
+enum Outcome {
+    Received(usize),
+    Failed(&'static str),
+}
+
+struct Session(UnsafeCell<Option<Receiver>>);
+unsafe impl Sync for Session {} // Only touched by the shell task
+
+static SESSION: Session = Session(UnsafeCell::new(None));
+
+fn session() -> &'static mut Option<Receiver> {
+    unsafe { &mut *SESSION.0.get() }
+}
+
+fn ticks(ms: u64) -> u64 {
+    (ms * crate::config::TICK_HZ as u64).div_ceil(1000)
+}
+
+/// Start receiving into `dest..dest + limit`, which must be RAM
+pub fn start(dest: usize, limit: usize) {
+    *session() = Some(Receiver {
+        dest,
+        limit,
+        received: 0,
+        block: 1,
+        packet: [0; PACKET],
+        fill: 0,
+        started: false,
+        tries: 1,
+        cancels: 0,
+        deadline: time::now() + ticks(START_INTERVAL_MS),
+    });
+    send(CRC_REQUEST);
+}
+
+/// Whether a transfer owns the console input
+pub fn active() -> bool {
+    session().is_some()
+}
+
+/// Time until `poll` has work to do without input, while a transfer runs
+pub fn time_to_deadline() -> Option<time::Duration> {
+    let receiver = session().as_ref()?;
+    Some(time::Duration::from_ticks(time::ticks_between(time::now(), receiver.deadline).max(1)))
+}
+
+/// Take one console byte; true when this ended the transfer
+pub fn accept(byte: u8, out: &mut dyn Write) -> bool {
+    let outcome = session().as_mut().and_then(|receiver| receiver.accept(byte));
+    finish(outcome, out)
+}
+
+/// Act on an expired timeout; true when this ended the transfer
+pub fn poll(out: &mut dyn Write) -> bool {
+    let outcome = session().as_mut().and_then(|receiver| receiver.poll());
+    finish(outcome, out)
+}
+
+fn finish(outcome: Option<Outcome>, out: &mut dyn Write) -> bool {
+    let Some(outcome) = outcome else {
+        return false;
+    };
+    let dest = session().take().map_or(0, |receiver| receiver.dest);
+    // Let the final ACK or CANs leave before the report
+    crate::arch::console_flush();
+    let _ = match outcome {
+        Outcome::Received(len) => writeln!(out, "rx: {} bytes at {:#010x}", len, dest),
+        Outcome::Failed(reason) => writeln!(out, "{}", styled(Style::Error, format_args!("rx: {}", reason))),
+    };
+    true
+}
+
+fn send(byte: u8) {
+    crate::arch::console_write(&[byte]);
+}
+
+impl Receiver {
+    fn accept(&mut self, byte: u8) -> Option<Outcome> {
+        if self.fill == 0 {
+            self.cancels = if byte == CAN { self.cancels + 1 } else { 0 };
+            match byte {
+                SOH => {}
+                EOT if self.started => {
+                    send(ACK);
+                    return Some(Outcome::Received(self.received));
+                }
+                CAN if self.cancels >= 2 => return Some(Outcome::Failed("cancelled")),
+                _ => return None, // Noise between blocks
+            }
+            if !self.started {
+                // Start requests no longer count against the error limit
+                self.started = true;
+                self.tries = 0;
+            }
+        }
+        self.packet[self.fill] = byte;
+        self.fill += 1;
+        self.deadline = time::now() + ticks(BYTE_TIMEOUT_MS);
+        if self.fill < PACKET {
+            return None;
+        }
+        self.fill = 0;
+        self.deadline = time::now() + ticks(BLOCK_TIMEOUT_MS);
+        self.check_block()
+    }
+
+    fn check_block(&mut self) -> Option<Outcome> {
+        let number = self.packet[1];
+        let (data, crc) = self.packet[3..].split_at(BLOCK);
+        if number != !self.packet[2] || crc16_update(0, data).to_be_bytes() != crc {
+            return self.error();
+        }
+        if number == self.block.wrapping_sub(1) && self.received > 0 {
+            // Our ACK was lost and the sender repeated the block
+            send(ACK);
+            return None;
+        }
+        if number != self.block {
+            return Some(self.cancel("block out of sequence"));
+        }
+        if self.received + BLOCK > self.limit {
+            return Some(self.cancel("more data than room at the address"));
+        }
+
+        unsafe {
+            let data = self.packet[3..].as_ptr();
+            core::ptr::copy_nonoverlapping(data, (self.dest + self.received) as *mut u8, BLOCK);
+        }
+        self.received += BLOCK;
+        self.block = self.block.wrapping_add(1);
+        self.tries = 0;
+        send(ACK);
+        None
+    }
+
+    fn poll(&mut self) -> Option<Outcome> {
+        if !time::deadline_reached(time::now(), self.deadline) {
+            return None;
+        }
+        if self.started {
+            self.fill = 0;
+            self.deadline = time::now() + ticks(BLOCK_TIMEOUT_MS);
+            return self.error();
+        }
+        if self.tries >= START_TRIES {
+            return Some(Outcome::Failed("no sender"));
+        }
+        self.tries += 1;
+        self.deadline = time::now() + ticks(START_INTERVAL_MS);
+        send(CRC_REQUEST);
+        None
+    }
+
+    /// NAK a bad or missing block, or give up after too many
+    fn error(&mut self) -> Option<Outcome> {
+        self.tries += 1;
+        if self.tries > MAX_ERRORS {
+            return Some(self.cancel("too many errors"));
+        }
+        send(NAK);
+        None
+    }
+
+    fn cancel(&self, reason: &'static str) -> Outcome {
+        send(CAN);
+        send(CAN);
+        Outcome::Failed(reason)
+    }
+}
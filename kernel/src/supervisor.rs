@@ -0,0 +1,269 @@
+//! Task supervision
+//! Restarts crashed or stalled tasks according to a restart policy
+//!
+//! A supervised task is spawned through `supervise` and checks in each
+//! time it runs. `poll`, called from the scheduler loop, flags tasks that
+//! stop checking in for longer than their stall limit, removes them and
+//! re-spawns them once their backoff has elapsed. Fault paths that can
+//! pin a failure on a task report it with `task_faulted`.
+//!
+//! Backoff doubles with each consecutive failure, from `base_backoff` up
+//! to `MAX_BACKOFF_TICKS`, and resets once a restarted task has stayed
+//! healthy for `MAX_BACKOFF_TICKS`.
+
+use core::cell::UnsafeCell;
+
+use crate::arch::atomic::{AtomicU32, Ordering};
+use crate::scheduler::{self, Task, TaskEntry, TaskPriority};
+
+/// Most tasks the supervisor tracks
+pub const MAX_SUPERVISED: usize = 8;
+
+/// Longest delay before a restart, in scheduler ticks
+pub const MAX_BACKOFF_TICKS: u32 = 1024;
+
+/// When a stopped task is started again
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Always, including after a normal exit
+    Permanent,
+    /// Only after a fault or stall
+    Transient,
+    /// Never; the task is only supervised for stall detection
+    Temporary,
+}
+
+/// Why a supervised task stopped
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    /// Returned or exited after cancellation
+    Normal,
+    /// Killed by a fault handler
+    Fault,
+    /// Missed its stall limit
+    Stalled,
+}
+
+/// Why a task could not be supervised
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SuperviseError {
+    /// Supervisor table full
+    TableFull,
+    /// Task id already supervised
+    Duplicate,
+    /// Scheduler had no free slot at the task's priority
+    SpawnFailed,
+}
+
+/// Restart settings for one task
+#[derive(Copy, Clone, Debug)]
+pub struct ChildSpec {
+    pub task_id: usize,
+    pub priority: TaskPriority,
+    /// Run each time the task is picked, also after a restart
+    pub entry: TaskEntry,
+    pub policy: RestartPolicy,
+    /// Ticks without a check-in before the task counts as stalled (0 = never)
+    pub stall_ticks: u32,
+    /// Delay before the first restart; doubles per consecutive failure
+    pub base_backoff: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ChildState {
+    Running { since: u32, last_checkin: u32 },
+    /// Reported stopped; `poll` records the time it is seen
+    Stopped,
+    RestartAt(u32),
+    Finished,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Child {
+    spec: ChildSpec,
+    state: ChildState,
+    restarts: u32,
+    consecutive_failures: u32,
+}
+
+/// Supervision view of one task
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct ChildInfo {
+    pub task_id: usize,
+    pub policy: RestartPolicy,
+    pub running: bool,
+    pub restarts: u32,
+}
+
+/// Supervisor counters
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SupervisorStats {
+    pub supervised: u32,
+    pub restarts: u32,
+    pub faults: u32,
+    pub stalls: u32,
+}
+
+struct ChildTable(UnsafeCell<heapless::Vec<Child, MAX_SUPERVISED>>);
+unsafe impl Sync for ChildTable {} // Single-core assumption
+
+static CHILDREN: ChildTable = ChildTable(UnsafeCell::new(heapless::Vec::new()));
+static RESTARTS: AtomicU32 = AtomicU32::new(0);
+static FAULTS: AtomicU32 = AtomicU32::new(0);
+static STALLS: AtomicU32 = AtomicU32::new(0);
+
+fn with_children<R>(f: impl FnOnce(&mut heapless::Vec<Child, MAX_SUPERVISED>) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *CHILDREN.0.get()) })
+}
+
+/// Spawn a task and supervise it from tick `now`
+#[allow(dead_code)]
+pub fn supervise(spec: ChildSpec, now: u32) -> Result<(), SuperviseError> {
+    with_children(|children| {
+        if children.iter().any(|child| child.spec.task_id == spec.task_id) {
+            return Err(SuperviseError::Duplicate);
+        }
+        if children.is_full() || crate::kernel::faults::inject(crate::kernel::faults::Fault::Alloc) {
+            return Err(SuperviseError::TableFull);
+        }
+        Ok(())
+    })?;
+
+    scheduler::add_priority_task(Task::with_priority(spec.task_id, spec.priority).entry(spec.entry))
+        .map_err(|_| SuperviseError::SpawnFailed)?;
+
+    let child = Child {
+        spec,
+        state: ChildState::Running { since: now, last_checkin: now },
+        restarts: 0,
+        consecutive_failures: 0,
+    };
+    with_children(|children| children.push(child).map_err(|_| SuperviseError::TableFull))
+}
+
+/// Record that `task_id` ran at tick `now`
+pub fn checkin(task_id: usize, now: u32) {
+    with_children(|children| {
+        let Some(child) = children.iter_mut().find(|child| child.spec.task_id == task_id) else {
+            return;
+        };
+        if let ChildState::Running { since, ref mut last_checkin } = child.state {
+            *last_checkin = now;
+            if now.wrapping_sub(since) >= MAX_BACKOFF_TICKS {
+                child.consecutive_failures = 0;
+            }
+        }
+    });
+}
+
+/// Report that a fault handler killed `task_id`
+///
+/// The task is removed from the scheduler; `poll` restarts it.
+#[allow(dead_code)]
+pub fn task_faulted(task_id: usize) {
+    scheduler::remove_task(task_id);
+    FAULTS.fetch_add(1, Ordering::Relaxed);
+    crate::coverage::hit(crate::coverage::Path::TaskFault);
+    stopped(task_id, ExitReason::Fault);
+}
+
+/// Report that `task_id` left the scheduler on its own
+pub fn task_exited(task_id: usize) {
+    stopped(task_id, ExitReason::Normal);
+}
+
+fn stopped(task_id: usize, reason: ExitReason) {
+    with_children(|children| {
+        let Some(child) = children.iter_mut().find(|child| child.spec.task_id == task_id) else {
+            return;
+        };
+        if !matches!(child.state, ChildState::Running { .. }) {
+            return;
+        }
+        let restart = match child.spec.policy {
+            RestartPolicy::Permanent => true,
+            RestartPolicy::Transient => reason != ExitReason::Normal,
+            RestartPolicy::Temporary => false,
+        };
+        child.state = if restart { ChildState::Stopped } else { ChildState::Finished };
+        if reason != ExitReason::Normal {
+            child.consecutive_failures = child.consecutive_failures.saturating_add(1);
+        }
+    });
+}
+
+/// Detect stalls and perform due restarts; call once per scheduler tick
+pub fn poll(now: u32) {
+    for index in 0..MAX_SUPERVISED {
+        let Some(child) = with_children(|children| children.get(index).copied()) else {
+            break;
+        };
+        match child.state {
+            ChildState::Running { last_checkin, .. } => {
+                let limit = child.spec.stall_ticks;
+                if limit != 0 && now.wrapping_sub(last_checkin) > limit {
+                    scheduler::remove_task(child.spec.task_id);
+                    STALLS.fetch_add(1, Ordering::Relaxed);
+                    stopped(child.spec.task_id, ExitReason::Stalled);
+                    schedule_restart(index, now);
+                }
+            }
+            ChildState::Stopped => schedule_restart(index, now),
+            ChildState::RestartAt(at) if crate::kernel::time::deadline_reached_u32(now, at) => restart(index, now),
+            _ => {}
+        }
+    }
+}
+
+fn schedule_restart(index: usize, now: u32) {
+    with_children(|children| {
+        let child = &mut children[index];
+        if child.state != ChildState::Stopped {
+            return;
+        }
+        let shift = child.consecutive_failures.saturating_sub(1).min(16);
+        let backoff = child.spec.base_backoff.saturating_mul(1 << shift).min(MAX_BACKOFF_TICKS);
+        child.state = ChildState::RestartAt(now.wrapping_add(backoff));
+    });
+}
+
+fn restart(index: usize, now: u32) {
+    let spec = with_children(|children| children[index].spec);
+    // A full priority level leaves the restart pending for the next poll
+    if scheduler::add_priority_task(Task::with_priority(spec.task_id, spec.priority).entry(spec.entry)).is_err() {
+        return;
+    }
+    with_children(|children| {
+        let child = &mut children[index];
+        child.state = ChildState::Running { since: now, last_checkin: now };
+        child.restarts = child.restarts.saturating_add(1);
+    });
+    RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Supervision state of `task_id`, if it is supervised
+#[allow(dead_code)]
+pub fn child_info(task_id: usize) -> Option<ChildInfo> {
+    with_children(|children| {
+        children.iter().find(|child| child.spec.task_id == task_id).map(|child| ChildInfo {
+            task_id,
+            policy: child.spec.policy,
+            running: matches!(child.state, ChildState::Running { .. }),
+            restarts: child.restarts,
+        })
+    })
+}
+
+/// Get supervisor counters
+#[allow(dead_code)]
+pub fn stats() -> SupervisorStats {
+    SupervisorStats {
+        supervised: with_children(|children| children.len() as u32),
+        restarts: RESTARTS.load(Ordering::Relaxed),
+        faults: FAULTS.load(Ordering::Relaxed),
+        stalls: STALLS.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,121 @@
+//! System call interface
+//! Architecture-neutral syscall numbers, error codes and trap dispatch
+//!
+//! The architecture trap code (SVC on ARM, ecall on RISC-V) decodes a
+//! syscall number and its arguments, then calls `dispatch`. The return
+//! value is written back to the caller's first argument register. The
+//! services themselves live in the kernel service table (`crate::kernel`),
+//! which kernel tasks can also call directly.
+//!
+//! ABI:
+//! - ARM: `svc #number`, arguments in r0-r3, result in r0
+//! - RISC-V: `ecall`, number in a7, arguments in a0-a5, result in a0
+//! - Simulator (`sim`): a direct call to `dispatch`, same arguments
+//! - Errors come back as small negative values (`-code`), see `SyscallError`
+//! - Argument slots a service does not use must be zero
+
+/// Number of argument slots passed to `dispatch`
+pub const SYSCALL_MAX_ARGS: usize = 6;
+
+/// Results at or above this value are error codes; services never return them
+pub const SYSCALL_ERROR_BASE: usize = 0usize.wrapping_sub(255);
+
+/// Syscall numbers (stable ABI)
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Syscall {
+    /// args: task id, task priority, entry address (a `TaskEntry` in
+    /// kernel code) -> task handle
+    Spawn = 1,
+    /// args: event id, event priority -> 1 if queued, 0 if queue full
+    PostEvent = 2,
+    /// args: duration in ticks -> 0
+    Sleep = 3,
+    /// args: queue id, message word (below `SYSCALL_ERROR_BASE`) -> 0
+    QueueSend = 4,
+    /// args: queue id -> message word
+    QueueReceive = 5,
+    /// args: buffer address, length in bytes -> bytes written
+    ConsoleWrite = 6,
+    /// args: task handle -> 0 (the task is asked to exit, see `SIGNAL_KILL`)
+    Kill = 7,
+}
+
+impl Syscall {
+    pub const fn from_raw(number: usize) -> Option<Self> {
+        match number {
+            1 => Some(Syscall::Spawn),
+            2 => Some(Syscall::PostEvent),
+            3 => Some(Syscall::Sleep),
+            4 => Some(Syscall::QueueSend),
+            5 => Some(Syscall::QueueReceive),
+            6 => Some(Syscall::ConsoleWrite),
+            7 => Some(Syscall::Kill),
+            _ => None,
+        }
+    }
+}
+
+/// Syscall failure codes (stable ABI, returned as `-code`)
+#[repr(usize)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SyscallError {
+    /// Unknown syscall number
+    NoSuchSyscall = 1,
+    /// Argument out of range, or an unused argument slot was non-zero
+    InvalidArgument = 2,
+    /// Buffer outside the memory the caller may pass to the kernel
+    BadAddress = 3,
+    /// Task table or queue full
+    NoSpace = 4,
+    /// Nothing to receive yet
+    WouldBlock = 5,
+    /// Handle names an object that no longer exists
+    StaleHandle = 6,
+}
+
+impl crate::kernel::time::WouldBlock for SyscallError {
+    fn would_block(&self) -> bool {
+        *self == SyscallError::WouldBlock
+    }
+}
+
+impl SyscallError {
+    const ALL: [SyscallError; 6] = [
+        SyscallError::NoSuchSyscall,
+        SyscallError::InvalidArgument,
+        SyscallError::BadAddress,
+        SyscallError::NoSpace,
+        SyscallError::WouldBlock,
+        SyscallError::StaleHandle,
+    ];
+
+    /// Register value for this error
+    pub const fn to_raw(self) -> usize {
+        (self as usize).wrapping_neg()
+    }
+
+    /// Decode a raw syscall result into a value or an error
+    #[allow(dead_code)]
+    pub fn decode(result: usize) -> Result<usize, SyscallError> {
+        if result < SYSCALL_ERROR_BASE {
+            return Ok(result);
+        }
+        match Self::ALL.iter().find(|error| error.to_raw() == result) {
+            Some(&error) => Err(error),
+            None => Err(SyscallError::InvalidArgument),
+        }
+    }
+}
+
+/// Dispatch a decoded syscall to the kernel service table
+pub fn dispatch(number: usize, args: [usize; SYSCALL_MAX_ARGS]) -> usize {
+    let result = Syscall::from_raw(number)
+        .ok_or(SyscallError::NoSuchSyscall)
+        .and_then(|syscall| crate::kernel::call(syscall, args));
+
+    match result {
+        Ok(value) => value,
+        Err(error) => error.to_raw(),
+    }
+}
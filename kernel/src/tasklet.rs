@@ -0,0 +1,133 @@
+//! Tasklets
+//! Deferred interrupt work run by the executor between tasks
+//!
+//! A tasklet is a `static` callback bound to one of the four task
+//! priority levels. An interrupt handler does the minimum in the ISR and
+//! calls `schedule`; the scheduler loop later runs the callback to
+//! completion with interrupts enabled, before any task of the same or
+//! lower priority. Scheduling a tasklet that is already pending only
+//! updates its data word, so a burst of interrupts runs it once.
+
+use core::cell::UnsafeCell;
+
+use heapless::Deque;
+
+use crate::arch::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use crate::scheduler::TaskPriority;
+
+/// Distinct tasklets that can be pending at one priority level
+pub const MAX_PENDING_TASKLETS: usize = 8;
+
+const LEVELS: usize = 4;
+
+/// Deferred callback, declared as a `static`
+#[allow(dead_code)]
+pub struct Tasklet {
+    name: &'static str,
+    func: fn(usize),
+    priority: TaskPriority,
+    data: AtomicUsize,
+    pending: AtomicBool,
+    runs: AtomicU32,
+}
+
+#[allow(dead_code)]
+impl Tasklet {
+    pub const fn new(name: &'static str, priority: TaskPriority, func: fn(usize)) -> Self {
+        Self {
+            name,
+            func,
+            priority,
+            data: AtomicUsize::new(0),
+            pending: AtomicBool::new(false),
+            runs: AtomicU32::new(0),
+        }
+    }
+
+    /// Queue the tasklet to run with `data` (ISR-safe)
+    ///
+    /// Returns false only if its level's pending queue is full.
+    pub fn schedule(&'static self, data: usize) -> bool {
+        self.data.store(data, Ordering::Relaxed);
+        if self.pending.swap(true, Ordering::AcqRel) {
+            return true;
+        }
+        let queued = with_queues(|queues| queues[self.priority as usize].push_back(self).is_ok());
+        if !queued {
+            self.pending.store(false, Ordering::Release);
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+        queued
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Times the callback has run
+    pub fn runs(&self) -> u32 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    fn run(&self) {
+        // Clear first so an interrupt during the callback queues it again
+        self.pending.store(false, Ordering::Release);
+        (self.func)(self.data.load(Ordering::Relaxed));
+        self.runs.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Tasklet counters
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TaskletStats {
+    pub runs: u32,
+    /// Schedules refused because a level's queue was full
+    pub dropped: u32,
+}
+
+type PendingQueues = [Deque<&'static Tasklet, MAX_PENDING_TASKLETS>; LEVELS];
+
+struct TaskletQueues(UnsafeCell<PendingQueues>);
+unsafe impl Sync for TaskletQueues {} // Single-core assumption
+
+static QUEUES: TaskletQueues = TaskletQueues(UnsafeCell::new([const { Deque::new() }; LEVELS]));
+static RUNS: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+fn with_queues<R>(f: impl FnOnce(&mut PendingQueues) -> R) -> R {
+    crate::arch::free(|| unsafe { f(&mut *QUEUES.0.get()) })
+}
+
+/// Run pending tasklets at `lowest` priority and above, highest first
+///
+/// Tasklets scheduled while this runs are picked up in the same call if
+/// their level has not been passed yet. Returns how many ran.
+pub fn run_pending(lowest: TaskPriority) -> u32 {
+    let mut ran = 0;
+    for level in 0..=lowest as usize {
+        while let Some(tasklet) = with_queues(|queues| queues[level].pop_front()) {
+            tasklet.run();
+            ran += 1;
+        }
+    }
+    RUNS.fetch_add(ran, Ordering::Relaxed);
+    ran
+}
+
+/// Whether any tasklet is waiting to run
+#[allow(dead_code)]
+pub fn has_pending() -> bool {
+    with_queues(|queues| queues.iter().any(|queue| !queue.is_empty()))
+}
+
+/// `has_pending` for callers that already have interrupts disabled
+pub fn has_pending_locked() -> bool {
+    unsafe { (*QUEUES.0.get()).iter().any(|queue| !queue.is_empty()) }
+}
+
+/// Get tasklet counters
+#[allow(dead_code)]
+pub fn stats() -> TaskletStats {
+    TaskletStats { runs: RUNS.load(Ordering::Relaxed), dropped: DROPPED.load(Ordering::Relaxed) }
+}
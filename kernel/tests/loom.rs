@@ -0,0 +1,140 @@
+//! Loom models of the scheduler's lock-free parts
+//!
+//! Each test runs its threads under every interleaving loom can reach and
+//! checks that the queue and the hot-slot handoff keep their promises in
+//! all of them. Only built with `--cfg loom`:
+//!
+//! `RUSTFLAGS="--cfg loom" cargo test --features sim --test loom --release`
+
+#![cfg(loom)]
+
+#[allow(dead_code)]
+#[path = "../src/scheduler/lockfree.rs"]
+mod lockfree;
+
+use loom::sync::atomic::{AtomicU32, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+use lockfree::{Handoff, LockFreeQueue};
+
+/// Values the producer side pushes in the queue models
+const VALUES: u32 = 3;
+
+/// Producer pushes `VALUES` values into a queue of two; consumer must see
+/// them all, once each, in order
+fn spsc(start: usize) {
+    loom::model(move || {
+        let queue = Arc::new(LockFreeQueue::<u32, 2>::starting_at(start));
+
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for value in 0..VALUES {
+                    while queue.push(value).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let mut expected = 0;
+        while expected < VALUES {
+            match queue.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+
+        producer.join().unwrap();
+        assert!(queue.pop().is_none());
+        assert!(queue.peak() <= 2);
+    });
+}
+
+#[test]
+fn queue_spsc_in_order() {
+    spsc(0);
+}
+
+#[test]
+fn queue_spsc_across_wrap() {
+    spsc(usize::MAX - 1);
+}
+
+#[test]
+fn handoff_publishes_waker_writes() {
+    loom::model(|| {
+        let handoff = Arc::new(Handoff::new());
+        let task_state = Arc::new(AtomicU32::new(0));
+
+        let waker = {
+            let handoff = handoff.clone();
+            let task_state = task_state.clone();
+            thread::spawn(move || {
+                // What a wake-up writes to the task before offering its slot
+                task_state.store(1, Ordering::Relaxed);
+                handoff.offer(7);
+            })
+        };
+
+        let took = handoff.take();
+        if took == Some(7) {
+            assert_eq!(task_state.load(Ordering::Relaxed), 1, "task taken before its wake-up was visible");
+        }
+
+        waker.join().unwrap();
+        // The scheduler's next pick finds the wake-up if this one missed it
+        assert!(took.is_some() || handoff.take() == Some(7), "wake-up lost");
+        assert!(handoff.take_reschedule());
+    });
+}
+
+#[test]
+fn handoff_hands_back_displaced_slot() {
+    loom::model(|| {
+        let handoff = Arc::new(Handoff::new());
+
+        let wakers: Vec<_> = [1, 2]
+            .into_iter()
+            .map(|slot| {
+                let handoff = handoff.clone();
+                thread::spawn(move || handoff.offer(slot))
+            })
+            .collect();
+        let taken = handoff.take();
+
+        let mut seen: Vec<usize> = wakers.into_iter().filter_map(|waker| waker.join().unwrap()).collect();
+        seen.extend(taken);
+        seen.extend(handoff.take());
+        seen.sort_unstable();
+        // Each slot is either displaced back to its waker or taken, exactly once
+        assert_eq!(seen, [1, 2]);
+    });
+}
+
+#[test]
+fn withdraw_leaves_other_offers() {
+    loom::model(|| {
+        let handoff = Arc::new(Handoff::new());
+        handoff.offer(1);
+
+        let waker = {
+            let handoff = handoff.clone();
+            thread::spawn(move || handoff.offer(2))
+        };
+        handoff.withdraw(1);
+
+        let displaced = waker.join().unwrap();
+        match displaced {
+            // Offer of 2 came first, so withdrawing 1 found 2 and left it
+            Some(1) => assert_eq!(handoff.take(), Some(2)),
+            // Withdraw came first and cleared the slot
+            None => assert_eq!(handoff.take(), Some(2)),
+            other => panic!("unexpected displaced slot {other:?}"),
+        }
+    });
+}
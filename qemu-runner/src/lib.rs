@@ -0,0 +1,273 @@
+//! QEMU test runner
+//! Run a karatOS kernel under QEMU, check its console and read its verdict
+//!
+//! `Runner` starts the QEMU program for a `Machine` with the flags the
+//! kernel expects and watches the serial console (and QEMU's stderr,
+//! where semihosting output and QEMU's own errors go). The console is
+//! checked against expectations, patterns that must show up in order,
+//! and rejections, patterns that fail the run wherever they show up.
+//!
+//! A kernel that exits QEMU, as the test kernel does, is judged by its
+//! exit status (see `Machine::decode_exit`). A kernel that never exits,
+//! as the demo does not, is stopped once every expectation has matched.
+//! After a rejected pattern the runner keeps reading for `REJECT_GRACE`
+//! so the report that follows it (a panic's message and location) lands
+//! in the output, then stops QEMU. Either way a run still going at the
+//! timeout fails.
+//!
+//! ```no_run
+//! use qemu_runner::{Machine, Runner};
+//! use std::time::Duration;
+//!
+//! let outcome = Runner::new(Machine::RiscvVirt, "target/riscv32imac-unknown-none-elf/debug/kernel")
+//!     .expect("karatOS kernel initialized")
+//!     .reject("!!! KERNEL PANIC !!!")
+//!     .timeout(Duration::from_secs(30))
+//!     .run()?;
+//! assert!(outcome.passed(), "{}", outcome);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod machine;
+mod matcher;
+#[cfg(test)]
+mod tests;
+
+pub use machine::{End, Machine};
+use matcher::Matcher;
+
+/// Timeout when none is set, as `QEMU_TIMEOUT` defaults to in the build
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reading continued after a rejected pattern shows up
+pub const REJECT_GRACE: Duration = Duration::from_millis(500);
+
+/// What the console shows when the kernel crashes, on either architecture
+pub const CRASH_PATTERNS: [&str; 2] = ["!!! KERNEL PANIC !!!", "Hard Fault at"];
+
+/// One kernel run under QEMU, set up builder-style
+#[derive(Clone, Debug)]
+pub struct Runner {
+    machine: Machine,
+    kernel: PathBuf,
+    program: Option<PathBuf>,
+    args: Vec<String>,
+    expect: Vec<String>,
+    reject: Vec<String>,
+    input: Vec<u8>,
+    timeout: Duration,
+    until_exit: bool,
+    echo: bool,
+}
+
+impl Runner {
+    pub fn new(machine: Machine, kernel: impl Into<PathBuf>) -> Self {
+        Self {
+            machine,
+            kernel: kernel.into(),
+            program: None,
+            args: Vec::new(),
+            expect: Vec::new(),
+            reject: Vec::new(),
+            input: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            until_exit: false,
+            echo: false,
+        }
+    }
+
+    /// Run this QEMU program instead of the machine's, found on `PATH`
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Pass one more flag to QEMU, after the machine's
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Require `pattern` on the console, after the previous expectation
+    pub fn expect(mut self, pattern: impl Into<String>) -> Self {
+        self.expect.push(pattern.into());
+        self
+    }
+
+    /// Fail the run if `pattern` shows up on the console
+    pub fn reject(mut self, pattern: impl Into<String>) -> Self {
+        self.reject.push(pattern.into());
+        self
+    }
+
+    /// Type `text` on the console once QEMU has started
+    pub fn input(mut self, text: impl AsRef<[u8]>) -> Self {
+        self.input.extend_from_slice(text.as_ref());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait for the kernel to exit QEMU even once every expectation matched
+    pub fn until_exit(mut self) -> Self {
+        self.until_exit = true;
+        self
+    }
+
+    /// Copy the console to stdout as it arrives
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// The QEMU command the run starts
+    pub fn command(&self) -> Command {
+        let program = self.program.clone().unwrap_or_else(|| self.machine.program().into());
+        let mut command = Command::new(program);
+        command.args(self.machine.args()).args(&self.args).arg("-kernel").arg(&self.kernel);
+        command
+    }
+
+    /// Start QEMU and watch it until the run ends
+    ///
+    /// Errors are only those starting QEMU or talking to it; a kernel
+    /// that fails is an `Outcome` that did not pass.
+    pub fn run(&self) -> io::Result<Outcome> {
+        let started = Instant::now();
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Both pipes feed one channel; it closes once QEMU has exited
+        let (sender, chunks) = mpsc::channel();
+        for mut pipe in [
+            Box::new(child.stdout.take().expect("stdout is piped")) as Box<dyn Read + Send>,
+            Box::new(child.stderr.take().expect("stderr is piped")),
+        ] {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut buffer = [0u8; 512];
+                while let Ok(count @ 1..) = pipe.read(&mut buffer) {
+                    if sender.send(buffer[..count].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Dropped after the run, so QEMU never sees the console close
+        let mut stdin = child.stdin.take();
+        if let Some(stdin) = stdin.as_mut().filter(|_| !self.input.is_empty()) {
+            stdin.write_all(&self.input)?;
+            stdin.flush()?;
+        }
+
+        let mut matcher = Matcher::new(self.expect.clone(), self.reject.clone());
+        let mut output = Vec::new();
+        let mut deadline = started + self.timeout;
+        let mut rejected = false;
+        let end = loop {
+            match chunks.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(chunk) => {
+                    if self.echo {
+                        let mut stdout = io::stdout().lock();
+                        stdout.write_all(&chunk)?;
+                        stdout.flush()?;
+                    }
+                    output.extend_from_slice(&chunk);
+                    matcher.feed(&output);
+                    if matcher.rejected().is_some() && !rejected {
+                        rejected = true;
+                        deadline = deadline.min(Instant::now() + REJECT_GRACE);
+                    }
+                    if matcher.all_matched() && !self.until_exit && !rejected {
+                        stop(&mut child)?;
+                        break End::Stopped;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    stop(&mut child)?;
+                    break if rejected { End::Stopped } else { End::TimedOut };
+                }
+                Err(RecvTimeoutError::Disconnected) => break self.machine.decode_exit(child.wait()?.code()),
+            }
+        };
+        drop(stdin);
+
+        Ok(Outcome {
+            machine: self.machine,
+            end,
+            output: String::from_utf8_lossy(&output).into_owned(),
+            missing: matcher.missing().to_vec(),
+            rejected: matcher.rejected().map(str::to_owned),
+            elapsed: started.elapsed(),
+        })
+    }
+}
+
+fn stop(child: &mut Child) -> io::Result<()> {
+    child.kill()?;
+    child.wait().map(|_| ())
+}
+
+/// How a run went
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub machine: Machine,
+    pub end: End,
+    /// Console and QEMU stderr, interleaved as they arrived
+    pub output: String,
+    /// Expectations that never matched, in order
+    pub missing: Vec<String>,
+    /// First rejected pattern that showed up
+    pub rejected: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl Outcome {
+    /// Whether the kernel passed, or was stopped with every expectation
+    /// matched, and nothing rejected showed up
+    pub fn passed(&self) -> bool {
+        matches!(self.end, End::Passed | End::Stopped) && self.missing.is_empty() && self.rejected.is_none()
+    }
+}
+
+/// One-line verdict: `passed on virt in 1.2 s (kernel exited 0)`
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.passed() { "passed" } else { "failed" };
+        write!(f, "{} on {} in {:.1} s (", verdict, self.machine, self.elapsed.as_secs_f32())?;
+        match self.end {
+            End::Passed => write!(f, "kernel exited 0")?,
+            End::Failed(code) => write!(f, "kernel exited {}", code)?,
+            End::Killed => write!(f, "QEMU killed by a signal")?,
+            End::Stopped => write!(f, "stopped by the runner")?,
+            End::TimedOut => write!(f, "timed out")?,
+        }
+        if let Some(pattern) = &self.rejected {
+            write!(f, "; rejected `{}` showed up", pattern)?;
+        }
+        if let Some(pattern) = self.missing.first() {
+            write!(f, "; `{}` never showed up", pattern)?;
+            if self.missing.len() > 1 {
+                write!(f, " (nor {} more)", self.missing.len() - 1)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
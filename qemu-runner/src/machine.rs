@@ -0,0 +1,109 @@
+//! QEMU machines
+//! The machines karatOS runs on under QEMU, their flags and exit devices
+//!
+//! Flags match `build/configs/global.toml` and `build/modules/core.sh`: no
+//! graphics, the first UART on stdio, and on ARM semihosting enabled so
+//! the kernel can exit QEMU (and the test kernel report through it).
+
+use std::fmt;
+
+/// A QEMU machine a kernel is built for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Machine {
+    /// `qemu-system-arm -M lm3s6965evb`, the default ARM build
+    Lm3s6965evb,
+    /// `qemu-system-riscv32 -machine virt`, the default RISC-V build
+    RiscvVirt,
+}
+
+impl Machine {
+    pub const ALL: [Machine; 2] = [Machine::Lm3s6965evb, Machine::RiscvVirt];
+
+    /// Machine by its name or by the build's target name (`arm`, `riscv`)
+    pub fn from_name(name: &str) -> Option<Machine> {
+        match name {
+            "arm" | "lm3s6965evb" => Some(Machine::Lm3s6965evb),
+            "riscv" | "virt" => Some(Machine::RiscvVirt),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Machine::Lm3s6965evb => "lm3s6965evb",
+            Machine::RiscvVirt => "virt",
+        }
+    }
+
+    /// Rust target the kernel for this machine is built for
+    pub const fn target(self) -> &'static str {
+        match self {
+            Machine::Lm3s6965evb => "thumbv7m-none-eabi",
+            Machine::RiscvVirt => "riscv32imac-unknown-none-elf",
+        }
+    }
+
+    /// QEMU program that emulates the machine
+    pub const fn program(self) -> &'static str {
+        match self {
+            Machine::Lm3s6965evb => "qemu-system-arm",
+            Machine::RiscvVirt => "qemu-system-riscv32",
+        }
+    }
+
+    /// Flags for the machine, ahead of `-kernel`
+    pub const fn args(self) -> &'static [&'static str] {
+        match self {
+            Machine::Lm3s6965evb => &[
+                "-M",
+                "lm3s6965evb",
+                "-nographic",
+                "-semihosting-config",
+                "enable=on,target=native",
+                "-serial",
+                "mon:stdio",
+            ],
+            Machine::RiscvVirt => &[
+                "-machine", "virt", "-cpu", "rv32", "-smp", "1", "-m", "128M", "-nographic", "-bios", "none",
+                "-serial", "mon:stdio",
+            ],
+        }
+    }
+
+    /// What QEMU's exit status says about the kernel
+    ///
+    /// On `virt` the kernel exits through the SiFive test device: a pass
+    /// is status 0, a failure carries the kernel's code. ARM semihosting
+    /// only tells success (0) from failure (1). Either way QEMU itself
+    /// also exits with 1 when it cannot start, which only its stderr
+    /// tells apart. `None` is an exit by signal.
+    pub fn decode_exit(self, status: Option<i32>) -> End {
+        match status {
+            Some(0) => End::Passed,
+            Some(code) => End::Failed(code),
+            None => End::Killed,
+        }
+    }
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a run ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum End {
+    /// The kernel exited QEMU reporting success
+    Passed,
+    /// The kernel exited QEMU with a failure code (or QEMU did not start)
+    Failed(i32),
+    /// QEMU ended by a signal the runner did not send
+    Killed,
+    /// The runner stopped QEMU: every expectation matched, or a rejected
+    /// pattern showed up
+    Stopped,
+    /// The timeout passed first
+    TimedOut,
+}
@@ -0,0 +1,82 @@
+//! qemu-runner command line
+//! Run a kernel under QEMU from scripts and CI
+//!
+//! Exit status 0 when the run passed, 1 when it failed, 2 when QEMU
+//! could not be run or the arguments were wrong, the same split the
+//! simulator's trace replay uses.
+
+use std::env;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use qemu_runner::{Machine, Runner, CRASH_PATTERNS};
+
+const USAGE: &str = "\
+usage: qemu-runner <arm|riscv|lm3s6965evb|virt> <kernel> [options]
+  --timeout <secs>   fail a run still going after this long (default 30)
+  --expect <text>    must show up on the console, after the previous --expect
+  --reject <text>    fails the run if it shows up on the console
+  --input <text>     typed on the console once QEMU has started
+  --until-exit       wait for the kernel to exit QEMU even once all --expect matched
+  --ktest            test kernel: expect the TAP plan and wait for the kernel's verdict
+  --qemu <program>   QEMU program to run instead of the machine's
+  --arg <flag>       pass one more flag to QEMU
+  --quiet            do not copy the console to stdout
+Crashes (kernel panic, hard fault) are always rejected.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match parse(args) {
+        Ok(runner) => match runner.run() {
+            Ok(outcome) => {
+                eprintln!("qemu-runner: {}", outcome);
+                if outcome.passed() {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::from(1)
+                }
+            }
+            Err(error) => {
+                eprintln!("qemu-runner: cannot run {:?}: {}", runner.command().get_program(), error);
+                ExitCode::from(2)
+            }
+        },
+        Err(message) => {
+            eprintln!("qemu-runner: {}\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn parse(args: Vec<String>) -> Result<Runner, String> {
+    let mut args = args.into_iter();
+    let machine = args.next().ok_or("no machine given")?;
+    let machine = Machine::from_name(&machine).ok_or_else(|| format!("unknown machine `{}`", machine))?;
+    let kernel = args.next().ok_or("no kernel given")?;
+
+    let mut runner = CRASH_PATTERNS.into_iter().fold(Runner::new(machine, kernel), Runner::reject).echo(true);
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", flag));
+        runner = match flag.as_str() {
+            "--timeout" => {
+                let secs = value()?;
+                let secs: u64 = secs.parse().map_err(|_| format!("bad timeout `{}`", secs))?;
+                runner.timeout(Duration::from_secs(secs))
+            }
+            "--expect" => runner.expect(value()?),
+            "--reject" => runner.reject(value()?),
+            "--input" => runner.input(value()?),
+            "--until-exit" => runner.until_exit(),
+            "--ktest" => runner.expect("1..").until_exit(),
+            "--qemu" => runner.program(value()?),
+            "--arg" => runner.arg(value()?),
+            "--quiet" => runner.echo(false),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        };
+    }
+    Ok(runner)
+}
@@ -0,0 +1,70 @@
+//! Console matching
+//! Expected patterns in order, rejected patterns anywhere
+//!
+//! Patterns are plain text, matched against the raw console bytes as
+//! they accumulate, so a pattern split across two reads still matches.
+//! Each expectation is looked for after the end of the one before it;
+//! a rejected pattern matches anywhere in the output.
+
+/// Progress of the expectations and rejections through the output
+#[derive(Debug, Default)]
+pub struct Matcher {
+    expect: Vec<String>,
+    reject: Vec<String>,
+    /// Expectations matched so far
+    matched: usize,
+    /// Where the next expectation is looked for
+    cursor: usize,
+    /// Output already searched for rejected patterns
+    scanned: usize,
+    rejected: Option<String>,
+}
+
+impl Matcher {
+    pub fn new(expect: Vec<String>, reject: Vec<String>) -> Self {
+        Self { expect, reject, ..Self::default() }
+    }
+
+    /// Look through `output`, which is everything read so far
+    pub fn feed(&mut self, output: &[u8]) {
+        while let Some(pattern) = self.expect.get(self.matched) {
+            match find(&output[self.cursor..], pattern.as_bytes()) {
+                Some(at) => {
+                    self.cursor += at + pattern.len();
+                    self.matched += 1;
+                }
+                None => break,
+            }
+        }
+
+        if self.rejected.is_none() {
+            // Back up far enough to catch a pattern split across reads
+            let longest = self.reject.iter().map(String::len).max().unwrap_or(0);
+            let from = self.scanned.saturating_sub(longest.saturating_sub(1));
+            self.rejected = self.reject.iter().find(|pattern| find(&output[from..], pattern.as_bytes()).is_some()).cloned();
+        }
+        self.scanned = output.len();
+    }
+
+    /// Whether there were expectations and all of them matched
+    pub fn all_matched(&self) -> bool {
+        !self.expect.is_empty() && self.matched == self.expect.len()
+    }
+
+    /// Expectations not matched yet, in order
+    pub fn missing(&self) -> &[String] {
+        &self.expect[self.matched..]
+    }
+
+    /// First rejected pattern seen
+    pub fn rejected(&self) -> Option<&str> {
+        self.rejected.as_deref()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
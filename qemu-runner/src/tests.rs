@@ -0,0 +1,115 @@
+//! Runner tests
+//!
+//! Matching is checked on its own; whole runs use a shell script standing
+//! in for QEMU, so they need no emulator (and only run on Unix).
+
+use super::matcher::Matcher;
+use super::*;
+
+fn matcher(expect: &[&str], reject: &[&str]) -> Matcher {
+    Matcher::new(expect.iter().map(|s| s.to_string()).collect(), reject.iter().map(|s| s.to_string()).collect())
+}
+
+#[test]
+fn expectations_match_in_order() {
+    let mut matcher = matcher(&["two", "one"], &[]);
+    matcher.feed(b"one two");
+    assert_eq!(matcher.missing(), ["one"]);
+    matcher.feed(b"one two one");
+    assert!(matcher.all_matched());
+}
+
+#[test]
+fn patterns_split_across_reads_match() {
+    let mut matcher = matcher(&["initialized"], &["PANIC"]);
+    let mut output = b"kernel initi".to_vec();
+    matcher.feed(&output);
+    output.extend_from_slice(b"alized\n!!! KERNEL PA");
+    matcher.feed(&output);
+    assert!(matcher.all_matched());
+    assert_eq!(matcher.rejected(), None);
+    output.extend_from_slice(b"NIC !!!");
+    matcher.feed(&output);
+    assert_eq!(matcher.rejected(), Some("PANIC"));
+}
+
+#[test]
+fn no_expectations_never_all_match() {
+    let mut matcher = matcher(&[], &[]);
+    matcher.feed(b"anything");
+    assert!(!matcher.all_matched());
+}
+
+#[test]
+fn machines_by_name() {
+    for machine in Machine::ALL {
+        assert_eq!(Machine::from_name(machine.name()), Some(machine));
+    }
+    assert_eq!(Machine::from_name("arm"), Some(Machine::Lm3s6965evb));
+    assert_eq!(Machine::from_name("riscv"), Some(Machine::RiscvVirt));
+    assert_eq!(Machine::from_name("x86"), None);
+}
+
+#[test]
+fn exit_status_decodes() {
+    // SiFive test device: the kernel's code comes through as the status
+    assert_eq!(Machine::RiscvVirt.decode_exit(Some(0)), End::Passed);
+    assert_eq!(Machine::RiscvVirt.decode_exit(Some(3)), End::Failed(3));
+    // Semihosting: failure is always 1
+    assert_eq!(Machine::Lm3s6965evb.decode_exit(Some(1)), End::Failed(1));
+    assert_eq!(Machine::Lm3s6965evb.decode_exit(None), End::Killed);
+}
+
+/// Runner for a fake QEMU that runs `script`, ignoring QEMU's flags
+#[cfg(unix)]
+fn fake_qemu(name: &str, script: &str) -> Runner {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("qemu-runner-{}-{}", std::process::id(), name));
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    Runner::new(Machine::RiscvVirt, "kernel").program(path).timeout(Duration::from_secs(5))
+}
+
+#[cfg(unix)]
+#[test]
+fn kernel_exit_is_the_verdict() {
+    let outcome = fake_qemu("exit", "echo '1..2'; echo 'not ok 2 - pipe'; exit 3").expect("1..").until_exit().run().unwrap();
+    assert_eq!(outcome.end, End::Failed(3));
+    assert!(!outcome.passed());
+    assert!(outcome.output.contains("not ok 2"));
+}
+
+#[cfg(unix)]
+#[test]
+fn stops_once_expectations_match() {
+    let outcome = fake_qemu("stop", "echo 'karatOS kernel initialized'; sleep 30")
+        .expect("initialized")
+        .run()
+        .unwrap();
+    assert_eq!(outcome.end, End::Stopped);
+    assert!(outcome.passed());
+    assert!(outcome.elapsed < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn rejected_pattern_keeps_the_report() {
+    let outcome = fake_qemu("reject", "echo '!!! KERNEL PANIC !!!'; echo 'message: boom'; sleep 30")
+        .expect("never printed")
+        .reject(CRASH_PATTERNS[0])
+        .run()
+        .unwrap();
+    assert_eq!(outcome.end, End::Stopped);
+    assert_eq!(outcome.rejected.as_deref(), Some(CRASH_PATTERNS[0]));
+    assert!(outcome.output.contains("message: boom"));
+    assert!(!outcome.passed());
+}
+
+#[cfg(unix)]
+#[test]
+fn silent_kernel_times_out() {
+    let outcome = fake_qemu("timeout", "sleep 30").expect("anything").timeout(Duration::from_millis(200)).run().unwrap();
+    assert_eq!(outcome.end, End::TimedOut);
+    assert_eq!(outcome.missing, ["anything"]);
+}